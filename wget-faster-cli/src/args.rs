@@ -1,13 +1,14 @@
 use clap::Parser;
 use std::path::PathBuf;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "wgetf")]
 #[command(
     version,
     about = "GNU Wget compatible downloader with high-performance parallel downloads",
     long_about = None,
-    disable_version_flag = true
+    disable_version_flag = true,
+    disable_help_flag = true
 )]
 pub struct Args {
     /// URLs to download
@@ -23,6 +24,10 @@ pub struct Args {
     #[arg(short = 'h', long, overrides_with = "help")]
     pub help: bool,
 
+    /// Print the effective configuration as JSON (secrets masked) and exit
+    #[arg(long, overrides_with = "show_config")]
+    pub show_config: bool,
+
     /// Go to background after startup
     #[arg(short = 'b', long, overrides_with = "background")]
     pub background: bool,
@@ -72,6 +77,10 @@ pub struct Args {
     #[arg(short = 'B', long, value_name = "URL")]
     pub base: Option<String>,
 
+    /// Record finished URLs in FILE and skip them when the list is run again
+    #[arg(long, value_name = "FILE", conflicts_with = "recursive")]
+    pub batch_state: Option<PathBuf>,
+
     /// Specify config file to use
     #[arg(long, value_name = "FILE")]
     pub config: Option<PathBuf>,
@@ -162,6 +171,59 @@ pub struct Args {
     #[arg(long, overrides_with = "spider")]
     pub spider: bool,
 
+    /// Print the planned action for each URL without writing anything (MODE: online, offline)
+    #[arg(long, value_name = "MODE", num_args = 0..=1, default_missing_value = "online")]
+    pub dry_run: Option<String>,
+
+    /// Check that each URL exists and print a TSV report; N probes at once (default 16)
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "16")]
+    pub check_links: Option<usize>,
+
+    /// Probe every URL and report the total size the downloads would transfer, without downloading
+    #[arg(long)]
+    pub estimate: bool,
+
+    /// Print the --estimate report as JSON
+    #[arg(long, requires = "estimate")]
+    pub json: bool,
+
+    /// Check the files under DIR against their provenance records instead of downloading
+    #[arg(long, value_name = "DIR")]
+    pub verify: Option<PathBuf>,
+
+    /// With --verify, use FILE (sha256sum format) instead of provenance records
+    #[arg(long, value_name = "FILE", requires = "verify")]
+    pub checksums: Option<PathBuf>,
+
+    /// With --verify, also ask the server whether recorded files changed
+    #[arg(long, requires = "verify")]
+    pub remote: bool,
+
+    /// Start downloading at the next local TIME of day (HH:MM or HH:MM:SS)
+    #[arg(long, value_name = "TIME")]
+    pub schedule: Option<String>,
+
+    /// Download again every INTERVAL (e.g. 24h, 10m, 1h30m) until interrupted
+    #[arg(long, value_name = "INTERVAL")]
+    pub repeat: Option<String>,
+
+    /// Delay each scheduled run by a random amount up to INTERVAL
+    #[arg(long, value_name = "INTERVAL")]
+    pub repeat_jitter: Option<String>,
+
+    /// Write a provenance record per file: FILE.provenance.json, or append JSON lines to FILE
+    #[arg(long, value_name = "FILE", num_args = 0..=1)]
+    #[allow(clippy::option_option)] // absent / flag only / flag with FILE
+    pub write_provenance: Option<Option<PathBuf>>,
+
+    /// Archive requests and responses to FILENAME.warc.gz
+    #[arg(long, value_name = "FILENAME")]
+    pub warc_file: Option<PathBuf>,
+
+    /// Ask range-capable servers for the total size when a body has no Content-Length
+    #[arg(long, overrides_with = "probe_total_size")]
+    pub probe_total_size: bool,
+
     /// Set all timeout values to SECONDS
     #[arg(short = 'T', long, value_name = "SECONDS")]
     pub timeout: Option<u64>,
@@ -198,6 +260,10 @@ pub struct Args {
     #[arg(short = 'Q', long, value_name = "NUMBER")]
     pub quota: Option<String>,
 
+    /// Abort the download in progress as soon as the quota is exceeded
+    #[arg(long)]
+    pub quota_hard: bool,
+
     /// Bind to ADDRESS (hostname or IP) on local host
     #[arg(long, value_name = "ADDRESS")]
     pub bind_address: Option<String>,
@@ -320,11 +386,12 @@ pub struct Args {
     #[arg(long, value_name = "STRING")]
     pub header: Vec<String>,
 
-    /// Choose compression type
+    /// Choose compression type: auto (every supported coding), gzip (gzip only),
+    /// both decoding the body, or none
     #[arg(long, value_name = "TYPE")]
     pub compression: Option<String>,
 
-    /// Maximum redirections allowed per page
+    /// Maximum redirections allowed per page (0 to stop at the first redirect)
     #[arg(long, value_name = "NUM")]
     pub max_redirect: Option<usize>,
 
@@ -340,6 +407,10 @@ pub struct Args {
     #[arg(long, value_name = "URL")]
     pub referer: Option<String>,
 
+    /// Referer policy: no-referrer, no-referrer-when-downgrade, same-origin, origin, unsafe-url
+    #[arg(long, value_name = "POLICY")]
+    pub referer_policy: Option<String>,
+
     /// Save the HTTP headers to file
     #[arg(long, overrides_with = "save_headers")]
     pub save_headers: bool,
@@ -457,6 +528,10 @@ pub struct Args {
     #[arg(long, value_name = "STR")]
     pub ciphers: Option<String>,
 
+    /// Refuse servers with weak TLS (keys under 2048 bits, SHA-1 signatures), warn about expiring certificates
+    #[arg(long)]
+    pub strict_tls: bool,
+
     // ===== HSTS Options =====
     /// Disable HSTS
     #[arg(long, overrides_with = "no_hsts")]
@@ -521,6 +596,10 @@ pub struct Args {
     #[arg(short = 'l', long, value_name = "NUMBER")]
     pub level: Option<String>,
 
+    /// Download the pages listed in a sitemap.xml (or sitemap index) instead of crawling
+    #[arg(long, value_name = "URL")]
+    pub sitemap: Option<String>,
+
     /// Delete files locally after downloading them
     #[arg(long, overrides_with = "delete_after")]
     pub delete_after: bool,
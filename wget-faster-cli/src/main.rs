@@ -1,15 +1,22 @@
 mod args;
 mod output;
+mod schedule;
 
 use anyhow::{anyhow, Context, Result};
 use args::Args;
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use output::WgetOutput;
-use std::path::PathBuf;
+use schedule::{SchedulePlan, StopSignal};
+use std::collections::HashSet;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use url::Url;
-use wget_faster_lib::{DownloadConfig, Downloader, ProgressInfo};
+use wget_faster_lib::{
+    content_disposition_filename, prepare_url, AddressFamily, ContentCoding, DownloadConfig,
+    DownloadEvent, Downloader, EventCallback, ProgressInfo,
+};
 
 #[tokio::main]
 async fn main() {
@@ -52,6 +59,16 @@ async fn main() {
         std::process::exit(1);
     }
 
+    // Show the settings the flags resolve to, without any network activity
+    if args.show_config {
+        std::process::exit(show_config(&args));
+    }
+
+    // Verify mode checks files already downloaded and needs no URLs
+    if let Some(ref dir) = args.verify {
+        std::process::exit(verify(&args, dir).await);
+    }
+
     // Collect URLs from args and input file
     let mut urls = args.urls.clone();
 
@@ -89,8 +106,26 @@ async fn main() {
         }
     }
 
-    // Check if no URLs provided
-    if urls.is_empty() {
+    // Encode unsafe characters, dropping URLs that can't be fetched at all
+    let mut invalid_url_exit = 0;
+    let given = urls.len();
+    let urls: Vec<String> = urls
+        .into_iter()
+        .filter_map(|url| match prepare_url(&url) {
+            Ok(prepared) => Some(prepared.to_string()),
+            Err(e) => {
+                eprintln!("wgetf: {url}: {}", e.format_wget_style());
+                invalid_url_exit = e.exit_code();
+                None
+            },
+        })
+        .collect();
+    if given > 0 && urls.is_empty() && args.sitemap.is_none() {
+        std::process::exit(invalid_url_exit);
+    }
+
+    // Check if no URLs provided (--sitemap supplies its own)
+    if urls.is_empty() && args.sitemap.is_none() {
         eprintln!("wgetf: missing URL");
         eprintln!("Usage: wgetf [OPTION]... [URL]...");
         eprintln!();
@@ -98,242 +133,451 @@ async fn main() {
         std::process::exit(1);
     }
 
+    // Scheduled or recurring downloads: repeat the whole run on the plan
+    if args.schedule.is_some() || args.repeat.is_some() {
+        let plan = match SchedulePlan::parse(
+            args.schedule.as_deref(),
+            args.repeat.as_deref(),
+            args.repeat_jitter.as_deref(),
+        ) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("wgetf: {e}");
+                std::process::exit(1);
+            },
+        };
+        let code = run_scheduled(&args, &urls, &plan).await;
+        std::process::exit(if code == 0 { invalid_url_exit } else { code });
+    }
+
+    let code = Box::pin(run_once(&args, &urls)).await;
+    std::process::exit(if code == 0 { invalid_url_exit } else { code });
+}
+
+/// Run the downloads on the `--schedule`/`--repeat` plan until it ends or Ctrl-C
+///
+/// Each cycle's outcome is logged; the exit status is that of the last failed cycle.
+async fn run_scheduled(args: &Args, urls: &[String], plan: &SchedulePlan) -> i32 {
+    let output = create_output(args);
+    let stop = StopSignal::on_ctrl_c();
+    let time_format = "%Y-%m-%d %H:%M:%S";
+
+    let first_run = plan.first_run(&chrono::Local::now());
+    if first_run > chrono::Local::now() {
+        output.print_next_run(&first_run.format(time_format).to_string());
+    }
+
+    let summary = schedule::run(
+        plan,
+        &stop,
+        || Box::pin(run_once(args, urls)),
+        |cycle, _started, exit_code, next_run| {
+            let next_run = next_run.map(|t| t.format(time_format).to_string());
+            output.print_cycle_result(cycle, exit_code, next_run.as_deref());
+        },
+    )
+    .await;
+
+    if summary.cycles > 1 {
+        output.print_info(&format!("{} cycles run, {} failed.", summary.cycles, summary.failed));
+    }
+    summary.exit_code
+}
+
+/// Download every URL once (recursively with -r) and return the exit status
+async fn run_once(args: &Args, urls: &[String]) -> i32 {
     // Build configuration from args
-    let config = match build_config(&args) {
+    let config = match build_config(args) {
         Ok(c) => c,
         Err(e) => {
             eprintln!("wgetf: {e}");
-            std::process::exit(1);
+            return 1;
         },
     };
+    // Only serialized when debug logging is on
+    tracing::debug!("Effective configuration:\n{}", effective_config(args, &config));
 
     // Extract values before moving config
     let wait_time = config.wait_time;
     let random_wait = config.random_wait;
     let quota = config.quota;
 
+    // Sitemap seeding: download the listed pages, no HTML crawling
+    if let Some(ref sitemap_url) = args.sitemap {
+        return Box::pin(run_sitemap(args, sitemap_url, config)).await;
+    }
+
     // Check if recursive mode is enabled
     if args.recursive {
-        // Recursive download mode
-        let recursive_config = build_recursive_config(&args);
-        let mut recursive_downloader =
-            match wget_faster_lib::RecursiveDownloader::new(config, recursive_config) {
-                Ok(d) => d,
-                Err(e) => {
-                    eprintln!("wgetf: failed to create recursive downloader: {e}");
-                    std::process::exit(1);
-                },
-            };
-
-        let mut exit_code = 0;
-
-        // Process each URL recursively
-        for url in &urls {
-            // Determine output directory
-            let output_dir = if let Some(ref prefix) = args.directory_prefix {
-                PathBuf::from(prefix)
-            } else {
-                PathBuf::from(".")
-            };
-
-            match recursive_downloader
-                .download_recursive(url, &output_dir)
-                .await
-            {
-                Ok(_files) => {
-                    // Check if there were broken links in spider mode
-                    if args.spider {
-                        let broken_links = recursive_downloader.broken_links();
-                        if !broken_links.is_empty() {
-                            exit_code = 8; // wget exit code for broken links
-                        }
-                    }
-                },
-                Err(e) => {
-                    eprintln!("wgetf: recursive download failed: {e}");
-                    exit_code = 1;
-                },
-            }
-        }
-
-        std::process::exit(exit_code);
+        return Box::pin(run_recursive(args, urls, config)).await;
     }
 
     // Create downloader for non-recursive mode
+    let mut config = config;
+    announce_retries(&mut config, args);
     let downloader = match Downloader::new(config) {
         Ok(d) => d,
         Err(e) => {
             eprintln!("wgetf: failed to create downloader: {e}");
-            std::process::exit(1);
+            return 1;
         },
     };
 
+    // --dry-run, --estimate and --check-links report instead of downloading
+    if let Some(code) = run_probe_mode(&downloader, urls, args).await {
+        return code;
+    }
+
+    // --batch-state: skip what an interrupted run of the same list finished
+    let state = match open_batch_state(args).await {
+        Ok(state) => state,
+        Err(code) => return code,
+    };
+
     // Download all URLs (non-recursive mode)
     let mut exit_code = 0;
-    let mut total_downloaded: u64 = 0;
-
-    for (i, url) in urls.iter().enumerate() {
-        // Check quota before download
-        if let Some(q) = quota {
-            if total_downloaded >= q {
-                eprintln!("wgetf: quota of {q} bytes exceeded");
-                break;
-            }
+    let mut started_any = false;
+
+    for url in urls {
+        if state.as_ref().is_some_and(|s| skip_completed(s, url, args)) {
+            continue;
+        }
+
+        // The downloader counts every byte received against the quota
+        if let Some(q) = quota.filter(|_| downloader.quota_exceeded()) {
+            eprintln!("wgetf: quota of {q} bytes exceeded");
+            break;
         }
 
         // Wait between downloads (except for first)
-        if i > 0 {
-            if let Some(wt) = wait_time {
-                let actual_wait = if random_wait {
-                    // Random wait between 0.5x and 1.5x wait_time
-                    use rand::Rng;
-                    let mut rng = rand::thread_rng();
-                    let multiplier = rng.gen_range(0.5..=1.5);
-                    Duration::from_secs_f64(wt.as_secs_f64() * multiplier)
-                } else {
-                    wt
-                };
-                tokio::time::sleep(actual_wait).await;
-            }
+        if let Some(wt) = wait_time.filter(|_| started_any) {
+            wait_between_downloads(wt, random_wait).await;
         }
+        started_any = true;
 
-        // Retry loop for 5xx errors and other transient failures
-        let mut attempt = 0;
-        let max_tries = downloader.get_client().config().retry.max_retries;
+        if let Err(code) = download_with_retries(&downloader, url, args, state.as_ref()).await {
+            exit_code = code;
+        }
+    }
 
-        loop {
-            attempt += 1;
-            let is_retry = attempt > 1;
+    if !compact_batch_state(state).await {
+        exit_code = 1;
+    }
 
-            match download_url(&downloader, url, &args, is_retry).await {
-                Ok(bytes) => {
-                    total_downloaded += bytes;
-                    break;
-                },
-                Err(e) => {
-                    // Check if error is retryable
-                    let should_retry =
-                        if let Some(lib_err) = e.downcast_ref::<wget_faster_lib::Error>() {
-                            // Check if this is a retryable status code
-                            if let wget_faster_lib::Error::InvalidStatus(status) = lib_err {
-                                downloader
-                                    .get_client()
-                                    .config()
-                                    .retry
-                                    .retry_on_status
-                                    .contains(status)
-                            } else {
-                                false
-                            }
-                        } else {
-                            false
-                        };
-
-                    if should_retry && attempt < max_tries {
-                        // Calculate backoff delay
-                        let retry_config = &downloader.get_client().config().retry;
-                        let delay = retry_config.initial_delay.as_secs_f64()
-                            * retry_config.backoff_multiplier.powi((attempt - 1) as i32);
-                        let delay = Duration::from_secs_f64(
-                            delay.min(retry_config.max_delay.as_secs_f64()),
-                        );
-
-                        eprintln!(
-                            "wgetf: retrying in {} seconds... (attempt {}/{})",
-                            delay.as_secs(),
-                            attempt,
-                            max_tries
-                        );
-
-                        tokio::time::sleep(delay).await;
-                        continue;
-                    }
-
-                    // Not retryable or max retries reached
-                    eprintln!("wgetf: {e}");
-
-                    // Get exit code from error - check if it's a library error first
-                    if let Some(lib_err) = e.downcast_ref::<wget_faster_lib::Error>() {
-                        // Use wget-compatible exit code from library error
-                        exit_code = lib_err.exit_code();
-                    } else {
-                        // For other errors, use generic exit code 1
-                        exit_code = 1;
-                    }
-                    break;
-                },
-            }
+    if !save_session(&downloader, args).await {
+        exit_code = 1;
+    }
+
+    exit_code
+}
+
+/// Save the session's cookies (`--save-cookies`) and known HSTS hosts
+///
+/// Returns `false` if either couldn't be written.
+async fn save_session(downloader: &Downloader, args: &Args) -> bool {
+    let mut saved = true;
+    for result in [
+        downloader.save_cookies().await,
+        downloader.save_hsts().await,
+    ] {
+        if let Err(e) = result {
+            eprintln!("wgetf: {}", output::format_error_chain(&e, args.verbose));
+            saved = false;
         }
     }
+    saved
+}
+
+/// Sleep `wait` (`--wait`), or 0.5-1.5 times it with `--random-wait`
+async fn wait_between_downloads(wait: Duration, random_wait: bool) {
+    let actual_wait = if random_wait {
+        // Random wait between 0.5x and 1.5x wait_time
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let multiplier = rng.gen_range(0.5..=1.5);
+        Duration::from_secs_f64(wait.as_secs_f64() * multiplier)
+    } else {
+        wait
+    };
+    tokio::time::sleep(actual_wait).await;
+}
+
+/// Open the `--batch-state` file, if given; `Err` holds the exit status
+async fn open_batch_state(args: &Args) -> Result<Option<wget_faster_lib::BatchState>, i32> {
+    let Some(ref path) = args.batch_state else {
+        return Ok(None);
+    };
+    wget_faster_lib::BatchState::open(path)
+        .await
+        .map(Some)
+        .map_err(|e| {
+            eprintln!(
+                "wgetf: cannot open batch state {}: {}",
+                path.display(),
+                output::format_error_chain(&e, args.verbose)
+            );
+            1
+        })
+}
 
-    std::process::exit(exit_code);
+/// Compact the `--batch-state` file at the end of a run; `false` if that failed
+async fn compact_batch_state(state: Option<wget_faster_lib::BatchState>) -> bool {
+    let Some(state) = state else {
+        return true;
+    };
+    state
+        .compact()
+        .await
+        .inspect_err(|e| eprintln!("wgetf: cannot compact batch state: {e}"))
+        .is_ok()
+}
+
+/// Whether an earlier `--batch-state` run finished `url`, saying so if it did
+fn skip_completed(state: &wget_faster_lib::BatchState, url: &str, args: &Args) -> bool {
+    let Some(entry) = state.completed(url) else {
+        return false;
+    };
+    let name = entry
+        .path
+        .as_ref()
+        .map_or_else(|| url.to_string(), |p| p.display().to_string());
+    create_output(args)
+        .print_info(&format!("'{name}' was downloaded by an earlier run -- not retrieving."));
+    true
+}
+
+/// Create the directories from -P or -x that `path` is saved in
+///
+/// Symlinks planted below -P aren't followed, and a link at a path built
+/// with -P or -x is replaced; an -O path is the user's own.
+async fn create_output_dirs(downloader: &Downloader, path: &Path, args: &Args) -> Result<()> {
+    let follow_symlinks = downloader.get_client().config().follow_output_symlinks;
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        let root = match (&args.output_document, &args.directory_prefix) {
+            (Some(_), _) => parent,
+            (None, Some(prefix)) => prefix.as_path(),
+            (None, None) => Path::new(""),
+        };
+        wget_faster_lib::create_dirs_within(root, parent, follow_symlinks)
+            .await
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    if args.output_document.is_none() && (args.directory_prefix.is_some() || args.force_directories)
+    {
+        wget_faster_lib::replace_links(path, follow_symlinks)
+            .await
+            .with_context(|| format!("Failed to replace link {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Download every URL recursively (-r) and return the exit status
+async fn run_recursive(args: &Args, urls: &[String], config: DownloadConfig) -> i32 {
+    // Recursive download mode
+    let mut recursive_config = build_recursive_config(args);
+    if args.show_progress {
+        // Single status line on stderr, rewritten after every queue item
+        let clear = if std::io::stderr().is_terminal() {
+            "\x1b[K"
+        } else {
+            ""
+        };
+        recursive_config.crawl_progress =
+            Some(wget_faster_lib::CrawlProgressCallback(Arc::new(move |progress| {
+                eprint!("\r{}{clear}", output::format_crawl_progress(&progress));
+            })));
+    }
+    let mut recursive_downloader =
+        match wget_faster_lib::RecursiveDownloader::new(config, recursive_config) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("wgetf: failed to create recursive downloader: {e}");
+                return 1;
+            },
+        };
+
+    let mut exit_code = 0;
+
+    // Process each URL recursively
+    for url in urls {
+        // Determine output directory
+        let output_dir = if let Some(ref prefix) = args.directory_prefix {
+            PathBuf::from(prefix)
+        } else {
+            PathBuf::from(".")
+        };
+
+        let result = recursive_downloader
+            .download_recursive(url, &output_dir)
+            .await;
+        if args.show_progress {
+            eprintln!();
+        }
+        match result {
+            Ok(files) => {
+                if args.verbose {
+                    print_referrers(&recursive_downloader, &files);
+                }
+                // Broken links are skipped in both spider and download mode
+                if !recursive_downloader.broken_links().is_empty() {
+                    exit_code = 8; // wget exit code for broken links
+                }
+            },
+            Err(e) => {
+                eprintln!(
+                    "wgetf: recursive download failed: {}",
+                    output::format_error_chain(&e, args.verbose)
+                );
+                exit_code = 1;
+            },
+        }
+        // The quota covers all the crawls: once it is used up, none starts
+        if let Some(wget_faster_lib::CrawlStopReason::QuotaExceeded(q)) =
+            recursive_downloader.stats().stop_reason
+        {
+            eprintln!("wgetf: quota of {q} bytes exceeded");
+            break;
+        }
+    }
+
+    if args.spider {
+        print_noindex_pages(recursive_downloader.noindex_pages());
+    }
+
+    if let Err(e) = recursive_downloader.save_cookies().await {
+        eprintln!("wgetf: {}", output::format_error_chain(&e, args.verbose));
+        exit_code = 1;
+    }
+    if let Err(e) = recursive_downloader.save_hsts().await {
+        eprintln!("wgetf: {}", output::format_error_chain(&e, args.verbose));
+        exit_code = 1;
+    }
+
+    exit_code
+}
+
+/// List the crawled pages that asked not to be indexed (spider report)
+fn print_noindex_pages(pages: &[String]) {
+    if pages.is_empty() {
+        return;
+    }
+    let noun = if pages.len() == 1 { "page" } else { "pages" };
+    eprintln!("Found {} {noun} marked noindex.", pages.len());
+    for page in pages {
+        eprintln!("{page}");
+    }
+}
+
+/// Print the page each saved file was linked from (`--verbose`)
+fn print_referrers(downloader: &wget_faster_lib::RecursiveDownloader, files: &[PathBuf]) {
+    let origins = downloader.download_origins();
+    for file in files {
+        if let Some(referrer) = origins.get(file).and_then(|o| o.referrer.as_ref()) {
+            eprintln!("{}: referred from {referrer}", file.display());
+        }
+    }
+}
+
+/// Download the pages listed in the `--sitemap` URL and return the exit status
+async fn run_sitemap(args: &Args, sitemap_url: &str, config: DownloadConfig) -> i32 {
+    let recursive_config = build_recursive_config(args);
+    let mut recursive_downloader =
+        match wget_faster_lib::RecursiveDownloader::new(config, recursive_config) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("wgetf: failed to create recursive downloader: {e}");
+                return 1;
+            },
+        };
+
+    let output_dir = args
+        .directory_prefix
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+    let result = recursive_downloader
+        .download_from_sitemap(sitemap_url, &output_dir)
+        .await;
+    if let Err(e) = recursive_downloader.save_cookies().await {
+        eprintln!("wgetf: {}", output::format_error_chain(&e, args.verbose));
+        return 1;
+    }
+    if let Err(e) = recursive_downloader.save_hsts().await {
+        eprintln!("wgetf: {}", output::format_error_chain(&e, args.verbose));
+        return 1;
+    }
+    match result {
+        Ok(files) => {
+            let unchanged = recursive_downloader.stats().sitemap_unchanged;
+            create_output(args).print_info(&format!(
+                "{} files downloaded from sitemap, {unchanged} unchanged.",
+                files.len()
+            ));
+            0
+        },
+        Err(e) => {
+            eprintln!(
+                "wgetf: sitemap download failed: {}",
+                output::format_error_chain(&e, args.verbose)
+            );
+            e.exit_code()
+        },
+    }
+}
+
+/// Download one URL; `Err` holds the exit status
+///
+/// The downloader retries transient failures itself (see `announce_retries`).
+async fn download_with_retries(
+    downloader: &Downloader,
+    url: &str,
+    args: &Args,
+    state: Option<&wget_faster_lib::BatchState>,
+) -> Result<u64, i32> {
+    download_url(downloader, url, args, state)
+        .await
+        .map_err(|e| {
+            eprintln!("wgetf: {}", output::format_error_chain(e.as_ref(), args.verbose));
+            // Use the wget-compatible exit code of a library error, 1 for others
+            e.downcast_ref::<wget_faster_lib::Error>()
+                .map_or(1, wget_faster_lib::Error::exit_code)
+        })
+}
+
+/// Print a line for each retry the downloader schedules (unless `--quiet`)
+fn announce_retries(config: &mut DownloadConfig, args: &Args) {
+    if args.quiet || config.event_callback.is_some() {
+        return;
+    }
+    let max_retries = config.retry.max_retries;
+    config.event_callback = Some(EventCallback(Arc::new(move |event| {
+        if let DownloadEvent::RetryScheduled { attempt, delay, .. } = event {
+            eprintln!(
+                "wgetf: retrying in {} seconds... (attempt {attempt}/{max_retries})",
+                delay.as_secs()
+            );
+        }
+    })));
 }
 
 async fn download_url(
     downloader: &Downloader,
     url: &str,
     args: &Args,
-    is_retry: bool,
+    state: Option<&wget_faster_lib::BatchState>,
 ) -> Result<u64> {
     // Parse URL
     let parsed_url = Url::parse(url).with_context(|| format!("Failed to parse URL: {url}"))?;
 
-    // Get metadata first if content_disposition is enabled
-    let metadata = if args.content_disposition {
-        Some(
-            downloader
-                .get_client()
-                .get_metadata(url)
-                .await
-                .with_context(|| format!("Failed to get metadata from: {url}"))?,
-        )
-    } else {
-        None
-    };
+    let metadata = naming_metadata(downloader, url, args).await?;
 
     // Determine output file name
-    let output_path = determine_output_path(&parsed_url, args, metadata.as_ref())
-        .with_context(|| "Failed to determine output file path")?;
+    let resume_path = state.and_then(|s| s.resume_path(url));
+    let output_path =
+        output_path_for(downloader, &parsed_url, args, metadata.as_ref(), resume_path)
+            .with_context(|| "Failed to determine output file path")?;
 
     // Create output formatter
-    let output = if let Some(ref log_file) = args.output_file {
-        // Use -o (truncate mode)
-        match WgetOutput::with_log_file(
-            args.quiet,
-            args.verbose || args.debug > 0,
-            args.show_progress || (!args.quiet && !args.no_verbose),
-            log_file.clone(),
-            false,
-        ) {
-            Ok(o) => o,
-            Err(e) => {
-                eprintln!("wgetf: failed to open log file '{}': {}", log_file.display(), e);
-                std::process::exit(3); // File I/O error
-            },
-        }
-    } else if let Some(ref log_file) = args.append_output {
-        // Use -a (append mode)
-        match WgetOutput::with_log_file(
-            args.quiet,
-            args.verbose || args.debug > 0,
-            args.show_progress || (!args.quiet && !args.no_verbose),
-            log_file.clone(),
-            true,
-        ) {
-            Ok(o) => o,
-            Err(e) => {
-                eprintln!("wgetf: failed to open log file '{}': {}", log_file.display(), e);
-                std::process::exit(3); // File I/O error
-            },
-        }
-    } else {
-        // Default to terminal output
-        WgetOutput::new(
-            args.quiet,
-            args.verbose || args.debug > 0,
-            args.show_progress || (!args.quiet && !args.no_verbose),
-        )
-    };
+    let output = create_output(args);
 
     // Print connection info
     let host = parsed_url.host_str().unwrap_or("unknown");
@@ -386,21 +630,13 @@ async fn download_url(
 
     // Start download
     let start_time = Instant::now();
+    record_started(state, url, output_path.as_deref()).await;
 
     // Print saving to file
     if let Some(ref path) = output_path {
         output.print_saving_to(&path.display().to_string());
 
-        // When using -O (output-document), create the file before download
-        // This matches GNU wget behavior: the file is created even if download fails
-        // Only do this for -O flag, not for other output modes
-        if args.output_document.is_some() {
-            // Create empty file (or truncate if exists)
-            if let Err(e) = std::fs::File::create(path) {
-                eprintln!("wgetf: cannot write to '{}': {}", path.display(), e);
-                std::process::exit(3); // File I/O error
-            }
-        }
+        create_output_dirs(downloader, path, args).await?;
     }
 
     // Create progress callback
@@ -411,109 +647,548 @@ async fn download_url(
         if let Ok(out) = output_clone.try_lock() {
             out.update_progress(&progress);
         }
-    });
-
-    // Download
-    let result = if let Some(path) = output_path {
-        // Initialize progress bar
-        {
-            let mut out = output_for_progress.lock().await;
-            out.init_progress(None); // Will be updated on first progress callback
+    });
+
+    // Initialize progress bar (sized on the first progress callback); the guard
+    // clears it however this function returns
+    let _progress = output_for_progress.lock().await.init_progress(None);
+
+    // Download
+    let reserved_path = output_path.clone();
+    let result = if let Some(path) = output_path {
+        downloader
+            .download_to_file_with_progress(url, path.clone(), Some(progress_callback))
+            .await
+    } else {
+        // Download to stdout
+        let bytes = downloader
+            .download_to_memory_with_progress(url, Some(progress_callback))
+            .await
+            .with_context(|| format!("Failed to download: {url}"))?;
+        output_for_progress.lock().await.finish_progress();
+
+        // Write to stdout
+        use std::io::Write;
+        std::io::stdout()
+            .write_all(&bytes)
+            .context("Failed to write to stdout")?;
+        record_written_to_stdout(state, url, bytes.len() as u64).await;
+
+        return Ok(bytes.len() as u64);
+    };
+
+    // Finish progress
+    {
+        let mut out = output_for_progress.lock().await;
+        out.finish_progress();
+    }
+
+    record_outcome(state, url, &result, reserved_path.as_deref()).await;
+
+    // The name is free for later URLs again; a file left on disk is numbered around
+    if let Some(ref path) = reserved_path {
+        downloader.name_registry().release(path);
+    }
+
+    match result {
+        Ok(download_result) => {
+            let elapsed = start_time.elapsed();
+            let out = output_for_progress.lock().await;
+            if report_redirect(&out, url, &download_result) {
+                return Ok(download_result.data.total_bytes);
+            }
+
+            // Print HTTP response
+            out.print_http_response(200, "OK");
+
+            // Print content info
+            out.print_content_info(
+                download_result
+                    .metadata
+                    .saved_length(downloader.get_client().config().decompress),
+                download_result.metadata.content_type.as_deref(),
+            );
+
+            // Print completion message
+            let filename = download_result
+                .data
+                .file_path
+                .as_ref()
+                .map_or_else(|| "unknown".to_string(), |p| p.display().to_string());
+
+            out.print_complete(&filename, download_result.data.total_bytes, elapsed);
+
+            Ok(download_result.data.total_bytes)
+        },
+        Err(e) => {
+            let out = output_for_progress.lock().await;
+            out.print_error(&format!("download failed: {e}"));
+            Err(e.into())
+        },
+    }
+}
+
+/// Print the redirect behind a download: the `Location` followed (verbose only),
+/// or the one left unfollowed with `--max-redirect 0`, which ends the download
+///
+/// Returns whether the download stopped at an unfollowed redirect.
+fn report_redirect(out: &WgetOutput, url: &str, result: &wget_faster_lib::DownloadResult) -> bool {
+    if let wget_faster_lib::DownloadOutcome::Redirect { location } = result.outcome() {
+        let status = result.metadata.status_code;
+        out.print_http_response(status, redirect_reason(status));
+        out.print_unfollowed_redirect(&location);
+        return true;
+    }
+    if let Some(final_url) = result.metadata.final_url.as_deref().filter(|u| *u != url) {
+        out.print_redirect(url, final_url);
+    }
+    false
+}
+
+/// Reason phrase of a 3xx status
+fn redirect_reason(status: u16) -> &'static str {
+    match status {
+        300 => "Multiple Choices",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        _ => "Redirect",
+    }
+}
+
+/// Record in the `--batch-state` file that `url` started downloading into `path`
+async fn record_started(
+    state: Option<&wget_faster_lib::BatchState>,
+    url: &str,
+    path: Option<&std::path::Path>,
+) {
+    if let Some(state) = state {
+        record_batch(state.record_started(url, path).await);
+    }
+}
+
+/// Record in the `--batch-state` file that `url` was written to stdout
+async fn record_written_to_stdout(
+    state: Option<&wget_faster_lib::BatchState>,
+    url: &str,
+    size: u64,
+) {
+    if let Some(state) = state {
+        record_batch(state.record_complete(url, None, size, None).await);
+    }
+}
+
+/// Record how the download of `url` ended in the `--batch-state` file
+async fn record_outcome(
+    state: Option<&wget_faster_lib::BatchState>,
+    url: &str,
+    result: &wget_faster_lib::Result<wget_faster_lib::DownloadResult>,
+    reserved_path: Option<&std::path::Path>,
+) {
+    let Some(state) = state else {
+        return;
+    };
+    let recorded = match result {
+        Ok(r) => {
+            let path = r.data.file_path.as_deref();
+            let size = r.data.total_bytes;
+            state
+                .record_complete(url, path, size, r.checksum.as_ref())
+                .await
+        },
+        Err(e) => state.record_failed(url, reserved_path, e).await,
+    };
+    record_batch(recorded);
+}
+
+/// Warn that `--batch-state` couldn't be updated; the download itself is unaffected
+fn record_batch(recorded: wget_faster_lib::Result<()>) {
+    if let Err(e) = recorded {
+        eprintln!("wgetf: cannot update batch state: {e}");
+    }
+}
+
+/// Metadata of `url` fetched up front, when the response may name the output
+/// file (`--content-disposition`, `--trust-server-names`)
+async fn naming_metadata(
+    downloader: &Downloader,
+    url: &str,
+    args: &Args,
+) -> Result<Option<wget_faster_lib::ResourceMetadata>> {
+    if !args.content_disposition && !args.trust_server_names {
+        return Ok(None);
+    }
+    let metadata = downloader
+        .get_client()
+        .get_metadata(url)
+        .await
+        .with_context(|| format!("Failed to get metadata from: {url}"))?;
+    Ok(Some(metadata))
+}
+
+/// Output file for `url`, or `None` for stdout
+///
+/// `resume_path`, the file an interrupted `--batch-state` run left, is continued.
+fn output_path_for(
+    downloader: &Downloader,
+    url: &Url,
+    args: &Args,
+    metadata: Option<&wget_faster_lib::ResourceMetadata>,
+    resume_path: Option<&std::path::Path>,
+) -> Result<Option<PathBuf>> {
+    match resume_path.filter(|_| args.output_document.is_none()) {
+        Some(path) => Ok(Some(
+            downloader
+                .name_registry()
+                .reserve(url.as_str(), path, false)?,
+        )),
+        None => determine_output_path(url, args, metadata, downloader.name_registry()),
+    }
+}
+
+/// Run the mode that only probes the URLs, if one was requested, and return its exit status
+async fn run_probe_mode(downloader: &Downloader, urls: &[String], args: &Args) -> Option<i32> {
+    // Dry run: print the plan for each URL and exit without writing anything
+    if let Some(ref mode) = args.dry_run {
+        let offline = mode == "offline";
+        return Some(dry_run(downloader, urls, args, offline, &mut std::io::stdout()).await);
+    }
+
+    // Estimate: probe every URL and print the total transfer size
+    if args.estimate {
+        return Some(estimate(downloader, urls, args).await);
+    }
+
+    // Link check: probe every URL and print a TSV report
+    if let Some(concurrency) = args.check_links {
+        return Some(check_links(downloader, urls.to_vec(), concurrency).await);
+    }
+
+    None
+}
+
+/// Check every URL and print a TSV report (`--check-links`)
+///
+/// Returns exit code 8 (server error, as in spider mode) if any link is broken.
+async fn check_links(downloader: &Downloader, urls: Vec<String>, concurrency: usize) -> i32 {
+    println!("url\tresult\tstatus\tfinal_url\tcontent_type\ttime_ms\terror");
+
+    let results = downloader.check_links(urls, concurrency).await;
+    for result in &results {
+        println!("{}", result.to_tsv());
+    }
+
+    let broken = results.iter().filter(|r| r.is_broken()).count();
+    if broken > 0 {
+        eprintln!("wgetf: {broken} of {} links are broken", results.len());
+        8
+    } else {
+        0
+    }
+}
+
+/// Check downloaded files against their records and print a summary (`--verify`)
+///
+/// Records are the provenance sidecars under `dir`, or the `--checksums`
+/// file. With `--remote`, files recorded with an `ETag` or `Last-Modified`
+/// are also revalidated against the server. Returns exit code 1 if any file
+/// is corrupted, missing, unreadable or changed.
+async fn verify(args: &Args, dir: &std::path::Path) -> i32 {
+    let records = match args.checksums {
+        Some(ref file) => wget_faster_lib::VerifyRecords::from_checksum_file(file, dir).await,
+        None => wget_faster_lib::VerifyRecords::from_sidecars(dir).await,
+    };
+    let records = match records {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!(
+                "wgetf: cannot read records: {}",
+                output::format_error_chain(&e, args.verbose)
+            );
+            return 1;
+        },
+    };
+
+    let show_progress = !args.quiet && std::io::stderr().is_terminal();
+    let progress: Option<wget_faster_lib::VerifyProgress> = show_progress.then(|| {
+        let shown = std::sync::atomic::AtomicU64::new(u64::MAX);
+        Arc::new(move |hashed: u64, total: u64| {
+            let percent = (hashed * 100).checked_div(total).unwrap_or(100);
+            if shown.swap(percent, std::sync::atomic::Ordering::Relaxed) != percent {
+                eprint!("\rVerifying: {percent:3}%");
+            }
+        }) as wget_faster_lib::VerifyProgress
+    });
+    let report = match wget_faster_lib::verify_local(dir, &records, progress).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("wgetf: {}: {}", dir.display(), output::format_error_chain(&e, args.verbose));
+            return 1;
+        },
+    };
+    if show_progress {
+        eprintln!();
+    }
+
+    for entry in report.problems() {
+        println!("{:<11} {}", entry.status.as_str(), entry.path.display());
+    }
+    println!("{report}");
+    let mut ok = report.is_ok();
+
+    if args.remote {
+        let config = match build_config(args) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("wgetf: {e}");
+                return 1;
+            },
+        };
+        let downloader = match Downloader::new(config) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("wgetf: failed to create downloader: {e}");
+                return 1;
+            },
+        };
+        // Same concurrency as --check-links
+        let remote = downloader.verify_remote(&records, 16).await;
+        for entry in &remote.entries {
+            match entry.status {
+                wget_faster_lib::RemoteStatus::Unchanged => {},
+                wget_faster_lib::RemoteStatus::Changed { ref reason } => {
+                    println!("{:<11} {} ({reason})", "CHANGED", entry.path.display());
+                },
+                wget_faster_lib::RemoteStatus::Failed { ref error } => {
+                    println!("{:<11} {} ({error})", "FAILED", entry.path.display());
+                },
+            }
+        }
+        println!("{remote}");
+        ok &= remote.is_ok();
+    }
+
+    i32::from(!ok)
+}
+
+/// Print how much the downloads would transfer (`--estimate`, `--json`)
+///
+/// Output names are resolved like a real download, so `-N`, `-nc` and `-c`
+/// decide which URLs are skipped or resumed.
+async fn estimate(downloader: &Downloader, urls: &[String], args: &Args) -> i32 {
+    let naming_args = args.clone();
+    let names = downloader.name_registry().clone();
+    let options = wget_faster_lib::EstimateOptions {
+        no_clobber: args.no_clobber,
+        target: Some(Arc::new(move |url, metadata| {
+            let url = Url::parse(url).ok()?;
+            if naming_args.no_clobber {
+                // The estimate itself counts existing files as skipped
+                return output_candidate(&url, &naming_args, Some(metadata));
+            }
+            determine_output_path(&url, &naming_args, Some(metadata), &names)
+                .ok()
+                .flatten()
+        })),
+        ..wget_faster_lib::EstimateOptions::default()
+    };
+
+    let report = downloader.estimate(urls, options).await;
+    if args.json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("wgetf: cannot encode estimate: {e}");
+                return 1;
+            },
+        }
+    } else {
+        println!("{report}");
+    }
+    0
+}
+
+/// Print a one-line plan for each URL (`--dry-run`) to `out`
+///
+/// Performs at most a HEAD probe per URL (none when `offline`), resolves the
+/// output name exactly like a real download, and writes nothing to disk.
+/// Returns exit code 3 if any target is unwritable, 0 otherwise.
+async fn dry_run(
+    downloader: &Downloader,
+    urls: &[String],
+    args: &Args,
+    offline: bool,
+    out: &mut impl Write,
+) -> i32 {
+    let mut exit_code = 0;
+
+    for url in urls {
+        let parsed_url = match Url::parse(url) {
+            Ok(u) => u,
+            Err(e) => {
+                let _ = writeln!(out, "{url}: invalid URL ({e})");
+                exit_code = exit_code.max(1);
+                continue;
+            },
+        };
+
+        let metadata = if offline {
+            None
+        } else {
+            match downloader.get_client().get_metadata(url).await {
+                Ok(m) => Some(m),
+                Err(e) => {
+                    let _ = writeln!(out, "{url}: probe failed ({e})");
+                    exit_code = exit_code.max(1);
+                    continue;
+                },
+            }
+        };
+
+        let output_path = match determine_output_path(
+            &parsed_url,
+            args,
+            metadata.as_ref(),
+            downloader.name_registry(),
+        ) {
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                let _ = writeln!(out, "{url} -> (stdout): download");
+                continue;
+            },
+            Err(e) => {
+                let _ = writeln!(out, "{url}: skip ({e})");
+                continue;
+            },
+        };
+
+        match downloader
+            .plan_with_metadata(url, &output_path, metadata)
+            .await
+        {
+            Ok(plan) => {
+                if !plan.writable {
+                    exit_code = 3;
+                }
+                let _ = writeln!(out, "{plan}");
+            },
+            Err(e) => {
+                let _ = writeln!(out, "{url}: cannot plan ({e})");
+                exit_code = 3;
+            },
+        }
+    }
+
+    exit_code
+}
+
+/// Create the output formatter for a download (terminal, -o log file, or -a log file)
+fn create_output(args: &Args) -> WgetOutput {
+    let show_progress = output::progress_enabled(
+        args.progress.as_deref(),
+        args.show_progress,
+        args.quiet || args.no_verbose,
+        std::io::stderr().is_terminal(),
+    );
+    if let Some(ref log_file) = args.output_file {
+        // Use -o (truncate mode)
+        match WgetOutput::with_log_file(
+            args.quiet,
+            args.verbose || args.debug > 0,
+            show_progress,
+            log_file.clone(),
+            false,
+        ) {
+            Ok(o) => o,
+            Err(e) => {
+                eprintln!("wgetf: failed to open log file '{}': {}", log_file.display(), e);
+                std::process::exit(3); // File I/O error
+            },
+        }
+    } else if let Some(ref log_file) = args.append_output {
+        // Use -a (append mode)
+        match WgetOutput::with_log_file(
+            args.quiet,
+            args.verbose || args.debug > 0,
+            show_progress,
+            log_file.clone(),
+            true,
+        ) {
+            Ok(o) => o,
+            Err(e) => {
+                eprintln!("wgetf: failed to open log file '{}': {}", log_file.display(), e);
+                std::process::exit(3); // File I/O error
+            },
         }
-
-        downloader
-            .download_to_file_with_progress(url, path.clone(), Some(progress_callback), is_retry)
-            .await
     } else {
-        // Download to stdout
-        let bytes = downloader
-            .download_to_memory_with_progress(url, Some(progress_callback))
-            .await
-            .with_context(|| format!("Failed to download: {url}"))?;
-
-        // Write to stdout
-        use std::io::Write;
-        std::io::stdout()
-            .write_all(&bytes)
-            .context("Failed to write to stdout")?;
+        // Default to terminal output
+        WgetOutput::new(args.quiet, args.verbose || args.debug > 0, show_progress)
+    }
+}
 
-        return Ok(bytes.len() as u64);
+fn determine_output_path(
+    url: &Url,
+    args: &Args,
+    metadata: Option<&wget_faster_lib::ResourceMetadata>,
+    names: &wget_faster_lib::NameRegistry,
+) -> Result<Option<PathBuf>> {
+    // -O is used as given
+    if args.output_document.is_some() {
+        return Ok(output_candidate(url, args, metadata));
+    }
+    let Some(path) = output_candidate(url, args, metadata) else {
+        return Ok(None);
     };
 
-    // Finish progress
-    {
-        let mut out = output_for_progress.lock().await;
-        out.finish_progress();
+    // Handle no-clobber
+    if args.no_clobber && path.exists() {
+        return Err(anyhow!("File '{}' already exists.", path.display()));
     }
 
-    match result {
-        Ok(download_result) => {
-            let elapsed = start_time.elapsed();
-            let out = output_for_progress.lock().await;
-
-            // Print HTTP response
-            out.print_http_response(200, "OK");
-
-            // Print content info
-            out.print_content_info(
-                download_result.metadata.content_length,
-                download_result.metadata.content_type.as_deref(),
-            );
-
-            // Print completion message
-            let filename = download_result
-                .data
-                .file_path
-                .as_ref()
-                .map_or_else(|| "unknown".to_string(), |p| p.display().to_string());
+    // Handle duplicate filenames by adding .1, .2, .3 suffix
+    // This matches wget behavior for Content-Disposition filenames
+    // Skip this for timestamping (-N) or continue (-c) mode where we want to use the same file
+    // EXCEPT: If --start-pos is used with --continue, we still create a numbered file
+    let number_existing = !args.no_clobber
+        && !args.timestamping
+        && (!args.continue_download || args.start_pos.is_some());
 
-            out.print_complete(&filename, download_result.data.total_bytes, elapsed);
+    // Reserve the name in the session registry so another URL in this run
+    // that suggests the same filename gets a numbered name instead of
+    // overwriting this one, even before either file exists
+    let path = names.reserve(url.as_str(), &path, number_existing)?;
 
-            Ok(download_result.data.total_bytes)
-        },
-        Err(e) => {
-            let out = output_for_progress.lock().await;
-            out.print_error(&format!("download failed: {e}"));
-            Err(e.into())
-        },
-    }
+    Ok(Some(path))
 }
 
-fn determine_output_path(
+/// Output file a URL would be saved to before no-clobber and numbering,
+/// or `None` for stdout (`-O -`)
+fn output_candidate(
     url: &Url,
     args: &Args,
     metadata: Option<&wget_faster_lib::ResourceMetadata>,
-) -> Result<Option<PathBuf>> {
+) -> Option<PathBuf> {
     // If -O is specified
     if let Some(ref output_doc) = args.output_document {
         // Special case: -O - means stdout
         if output_doc.to_str() == Some("-") {
-            return Ok(None);
+            return None;
         }
-        return Ok(Some(output_doc.clone()));
-    }
-
-    // Try to extract filename from Content-Disposition if enabled
-    let mut filename = if args.content_disposition {
-        metadata
-            .and_then(|m| m.content_disposition.as_ref())
-            .and_then(|cd| extract_filename_from_content_disposition(cd))
-            .or_else(|| {
-                // Fall back to URL if Content-Disposition not available
-                url.path_segments()
-                    .and_then(|mut segments| segments.next_back())
-                    .filter(|name| !name.is_empty())
-                    .map(std::string::ToString::to_string)
-            })
-            .unwrap_or_else(|| "index.html".to_string())
-    } else {
-        // Extract filename from URL
-        url.path_segments()
-            .and_then(|mut segments| segments.next_back())
-            .filter(|name| !name.is_empty())
-            .unwrap_or("index.html")
-            .to_string()
-    };
+        return Some(output_doc.clone());
+    }
+
+    let final_url = metadata.and_then(|m| m.final_url.as_deref());
+    let url = &naming_url(url, args.trust_server_names, final_url);
+
+    // Content-Disposition (if enabled) wins over the URL
+    let mut filename = args
+        .content_disposition
+        .then(|| metadata.and_then(|m| m.content_disposition.as_deref()))
+        .flatten()
+        .and_then(content_disposition_filename)
+        .unwrap_or_else(|| url_filename(url));
 
     // Apply filename restrictions if specified
     if let Some(ref restrict_str) = args.restrict_file_names {
@@ -530,103 +1205,37 @@ fn determine_output_path(
         filename = wget_faster_lib::apply_filename_restrictions(&filename, &restrictions);
     }
 
-    let mut path = PathBuf::new();
+    // The directory prefix always applies, with or without -nd
+    let mut path = args.directory_prefix.clone().unwrap_or_default();
 
-    // Add directory prefix if specified (unless -n/--no-directories is set)
-    if !args.no_directories {
-        if let Some(ref prefix) = args.directory_prefix {
-            path.push(prefix);
-        }
-    } else if let Some(ref prefix) = args.directory_prefix {
-        // With -n, still use directory_prefix if specified explicitly
-        path.push(prefix);
+    // -x: recreate the host/path hierarchy, laid out like a recursive download
+    if args.force_directories {
+        path = directory_layout(args).local_dir(url, &path);
     }
 
     // Add filename
     path.push(&filename);
-
-    // Handle no-clobber
-    if args.no_clobber && path.exists() {
-        return Err(anyhow!("File '{}' already exists.", path.display()));
-    }
-
-    // Handle duplicate filenames by adding .1, .2, .3 suffix
-    // This matches wget behavior for Content-Disposition filenames
-    // Skip this for timestamping (-N) or continue (-c) mode where we want to use the same file
-    // EXCEPT: If --start-pos is used with --continue, we still create a numbered file
-    let should_number_file = path.exists()
-        && !args.no_clobber
-        && !args.timestamping
-        && (!args.continue_download || args.start_pos.is_some());
-
-    if should_number_file {
-        let mut counter = 1;
-        loop {
-            let mut new_path = PathBuf::new();
-
-            // Add directory prefix
-            if !args.no_directories {
-                if let Some(ref prefix) = args.directory_prefix {
-                    new_path.push(prefix);
-                }
-            } else if let Some(ref prefix) = args.directory_prefix {
-                new_path.push(prefix);
-            }
-
-            // Add filename with counter suffix
-            new_path.push(format!("{filename}.{counter}"));
-
-            if !new_path.exists() {
-                path = new_path;
-                break;
-            }
-
-            counter += 1;
-
-            // Safety check: prevent infinite loop
-            if counter > 9999 {
-                return Err(anyhow!("Too many duplicate files"));
-            }
-        }
-    }
-
-    Ok(Some(path))
+    Some(path)
 }
 
-fn extract_filename_from_content_disposition(header: &str) -> Option<String> {
-    // Parse Content-Disposition header
-    // Format: attachment; filename="filename.jpg"
-    // or: attachment; filename*=UTF-8''filename.jpg
-
-    for part in header.split(';') {
-        let part = part.trim();
-
-        // Handle filename*= (RFC 5987)
-        if part.to_lowercase().starts_with("filename*=") {
-            if let Some(value) = part.split('=').nth(1) {
-                // Remove encoding prefix if present (e.g., "UTF-8''")
-                let value = if let Some(pos) = value.find("''") {
-                    &value[pos + 2..]
-                } else {
-                    value
-                };
-                // URL decode and remove quotes
-                let decoded = percent_encoding::percent_decode_str(value)
-                    .decode_utf8()
-                    .ok()?;
-                return Some(decoded.trim_matches('"').to_string());
-            }
-        }
-
-        // Handle filename= (RFC 2183)
-        if part.to_lowercase().starts_with("filename=") {
-            if let Some(value) = part.split('=').nth(1) {
-                return Some(value.trim_matches('"').to_string());
-            }
-        }
-    }
+/// URL an output file is named after
+///
+/// With `--trust-server-names` this is the URL the response came from after
+/// redirects, otherwise the requested one.
+fn naming_url(url: &Url, trust_server_names: bool, final_url: Option<&str>) -> Url {
+    final_url
+        .filter(|_| trust_server_names)
+        .and_then(|final_url| Url::parse(final_url).ok())
+        .unwrap_or_else(|| url.clone())
+}
 
-    None
+/// Last path segment of `url`, or `index.html` for a directory
+fn url_filename(url: &Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("index.html")
+        .to_string()
 }
 
 fn process_execute_command(args: &mut Args, command: &str) -> Result<(), String> {
@@ -661,12 +1270,44 @@ fn process_execute_command(args: &mut Args, command: &str) -> Result<(), String>
     }
 }
 
+/// Print the configuration built from `args` (`--show-config`) and return the exit status
+fn show_config(args: &Args) -> i32 {
+    match build_config(args) {
+        Ok(config) => {
+            println!("{}", effective_config(args, &config));
+            0
+        },
+        Err(e) => {
+            eprintln!("wgetf: {e}");
+            1
+        },
+    }
+}
+
+/// Settings a run uses, as `--show-config` prints them
+#[derive(serde::Serialize)]
+struct EffectiveConfig<'a> {
+    download: &'a DownloadConfig,
+    recursive: wget_faster_lib::RecursiveConfig,
+}
+
+/// The configuration built from `args` as pretty JSON, secrets masked
+///
+/// The field names are those of `DownloadConfig` and `RecursiveConfig`.
+fn effective_config(args: &Args, config: &DownloadConfig) -> String {
+    let effective = EffectiveConfig {
+        download: config,
+        recursive: build_recursive_config(args),
+    };
+    serde_json::to_string_pretty(&effective).unwrap_or_default()
+}
+
 fn build_config(args: &Args) -> Result<DownloadConfig> {
     let mut config = DownloadConfig::default();
 
     // Set timeouts
     if let Some(timeout) = args.timeout {
-        config.timeout = Duration::from_secs(timeout);
+        config.timeout = Some(Duration::from_secs(timeout));
     }
     if let Some(timeout) = args.connect_timeout {
         config.connect_timeout = Duration::from_secs(timeout);
@@ -700,9 +1341,21 @@ fn build_config(args: &Args) -> Result<DownloadConfig> {
     if let Some(ref cookie_file) = args.load_cookies {
         config.cookie_file = Some(resolve_file_path(cookie_file));
     }
+    if let Some(ref cookie_file) = args.save_cookies {
+        config.save_cookie_file = Some(resolve_file_path(cookie_file));
+    }
+    config.keep_session_cookies = args.keep_session_cookies;
+
+    // Set HSTS (wget keeps its database in ~/.wget-hsts)
+    config.enable_hsts = !args.no_hsts;
+    config.hsts_file =
+        args.hsts_file.as_ref().map(resolve_file_path).or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".wget-hsts"))
+        });
 
     // Set SSL verification
     config.verify_ssl = !args.no_check_certificate;
+    config.tls_policy = args.strict_tls.then(wget_faster_lib::TlsPolicy::default);
 
     // Set certificates
     if let Some(ref cert) = args.ca_certificate {
@@ -803,16 +1456,43 @@ fn build_config(args: &Args) -> Result<DownloadConfig> {
     if let Some(ref referer) = args.referer {
         config.referer = Some(referer.clone());
     }
+    if let Some(ref policy) = args.referer_policy {
+        config.referer_policy = policy
+            .parse::<wget_faster_lib::RefererPolicy>()
+            .map_err(|e| anyhow!("{e}"))?;
+    }
+
+    // Provenance records: sidecar per file, or one JSON-lines file
+    config.write_provenance = args.write_provenance.as_ref().map(|file| match file {
+        Some(path) => wget_faster_lib::ProvenanceConfig::single_file(path),
+        None => wget_faster_lib::ProvenanceConfig::sidecar(),
+    });
+
+    // WARC archive: wget appends the extension to the name given
+    config.warc_file = args.warc_file.as_ref().map(|name| {
+        let mut file = name.clone().into_os_string();
+        file.push(".warc.gz");
+        PathBuf::from(file)
+    });
+
+    // Range probe for the total size of bodies without Content-Length
+    config.probe_total_size = args.probe_total_size;
 
     // Set proxy configuration
-    // Check for proxy URL from environment variables (unless --no-proxy is set)
+    // Each scheme uses its own environment variable (unless --no-proxy is set);
+    // all_proxy covers schemes without one
     if !args.no_proxy {
-        if let Some(proxy_url) = std::env::var("http_proxy")
-            .ok()
-            .or_else(|| std::env::var("https_proxy").ok())
-            .or_else(|| std::env::var("HTTP_PROXY").ok())
-            .or_else(|| std::env::var("HTTPS_PROXY").ok())
-        {
+        let env_proxy = |names: [&str; 2]| {
+            names
+                .into_iter()
+                .find_map(|name| std::env::var(name).ok())
+                .filter(|value| !value.is_empty())
+        };
+        let fallback_proxy = env_proxy(["all_proxy", "ALL_PROXY"]);
+        let plain_proxy = env_proxy(["http_proxy", "HTTP_PROXY"]);
+        let tls_proxy = env_proxy(["https_proxy", "HTTPS_PROXY"]);
+
+        if fallback_proxy.is_some() || plain_proxy.is_some() || tls_proxy.is_some() {
             // Parse no_proxy environment variable
             let no_proxy_list = std::env::var("no_proxy")
                 .or_else(|_| std::env::var("NO_PROXY"))
@@ -822,7 +1502,7 @@ fn build_config(args: &Args) -> Result<DownloadConfig> {
                 .filter(|s| !s.is_empty())
                 .collect::<Vec<_>>();
 
-            // Set up proxy authentication if provided
+            // Set up proxy authentication if provided (sent when the proxy answers 407)
             let auth = if let Some(ref user) = args.proxy_user {
                 let password = args.proxy_password.clone().unwrap_or_default();
                 Some((user.clone(), password))
@@ -831,15 +1511,31 @@ fn build_config(args: &Args) -> Result<DownloadConfig> {
             };
 
             config.proxy = Some(wget_faster_lib::ProxyConfig {
-                url: proxy_url,
+                url: fallback_proxy,
+                http_url: plain_proxy,
+                https_url: tls_proxy,
                 auth,
                 no_proxy: no_proxy_list,
             });
         }
     }
 
-    // Set compression
-    config.enable_compression = !matches!(args.compression.as_deref(), Some("none"));
+    // Set compression: `auto` offers every coding, `gzip` only gzip; both also
+    // decode the bodies, like wget. `none` asks for identity.
+    match args.compression.as_deref() {
+        None => {},
+        Some("auto") => config.decompress = true,
+        Some("gzip") => {
+            config.accepted_encodings = vec![ContentCoding::Gzip];
+            config.decompress = true;
+        },
+        Some("none") => config.enable_compression = false,
+        Some(other) => {
+            return Err(anyhow!(
+                "Invalid --compression value '{other}' (expected auto, gzip or none)"
+            ))
+        },
+    }
 
     // Set HTTP keep-alive
     config.http_keep_alive = !args.no_http_keep_alive;
@@ -861,9 +1557,17 @@ fn build_config(args: &Args) -> Result<DownloadConfig> {
     if let Some(ref quota_str) = args.quota {
         config.quota = parse_quota(quota_str)?;
     }
+    config.quota_hard = args.quota_hard;
+
+    // Set address family (-4, -6, --prefer-family) and local address
+    config.address_family = address_family(args)?;
+    if let Some(ref address) = args.bind_address {
+        config.bind_address = Some(parse_bind_address(address)?);
+    }
 
     // Set timestamping
     config.timestamping = args.timestamping;
+    config.timestamping_continue = args.continue_download;
     config.if_modified_since = !args.no_if_modified_since;
     config.use_server_timestamps = !args.no_use_server_timestamps;
 
@@ -888,6 +1592,9 @@ fn build_config(args: &Args) -> Result<DownloadConfig> {
     // Set start position
     config.start_pos = args.start_pos;
 
+    // -O replaces the file once a body arrives (-c continues it instead)
+    config.overwrite_existing = args.output_document.is_some() && !args.continue_download;
+
     // Set HTTPS-only mode
     config.https_only = args.https_only;
 
@@ -917,6 +1624,43 @@ fn build_config(args: &Args) -> Result<DownloadConfig> {
     Ok(config)
 }
 
+/// Address family from `-4`, `-6` or `--prefer-family` (`-4` and `-6` win over a preference)
+fn address_family(args: &Args) -> Result<Option<AddressFamily>> {
+    if args.inet4_only && args.inet6_only {
+        return Err(anyhow!("Cannot specify both --inet4-only and --inet6-only"));
+    }
+    if args.inet4_only {
+        return Ok(Some(AddressFamily::V4Only));
+    }
+    if args.inet6_only {
+        return Ok(Some(AddressFamily::V6Only));
+    }
+    let preference = args.prefer_family.as_deref().map(str::to_ascii_lowercase);
+    match preference.as_deref() {
+        None | Some("none") => Ok(None),
+        Some("ipv4") => Ok(Some(AddressFamily::PreferV4)),
+        Some("ipv6") => Ok(Some(AddressFamily::PreferV6)),
+        Some(other) => {
+            Err(anyhow!("Invalid --prefer-family value '{other}' (expected IPv4, IPv6 or none)"))
+        },
+    }
+}
+
+/// `--bind-address`: an IP address, or a host name resolved to its first address
+fn parse_bind_address(address: &str) -> Result<std::net::IpAddr> {
+    use std::net::ToSocketAddrs;
+
+    if let Ok(ip) = address.parse() {
+        return Ok(ip);
+    }
+    (address, 0)
+        .to_socket_addrs()
+        .with_context(|| format!("Cannot resolve --bind-address {address}"))?
+        .next()
+        .map(|addr| addr.ip())
+        .ok_or_else(|| anyhow!("--bind-address {address} has no address"))
+}
+
 fn parse_quota(quota: &str) -> Result<Option<u64>> {
     let quota = quota.trim().to_lowercase();
 
@@ -1152,6 +1896,16 @@ fn resolve_file_path(path: &PathBuf) -> PathBuf {
     }
 }
 
+/// Directory layout from -nd, -nH, --protocol-directories and --cut-dirs
+fn directory_layout(args: &Args) -> wget_faster_lib::DirectoryLayout {
+    wget_faster_lib::DirectoryLayout {
+        no_directories: args.no_directories,
+        no_host_directories: args.no_host_directories,
+        protocol_directories: args.protocol_directories,
+        cut_dirs: args.cut_dirs.unwrap_or(0),
+    }
+}
+
 fn build_recursive_config(args: &Args) -> wget_faster_lib::RecursiveConfig {
     let mut config = wget_faster_lib::RecursiveConfig::default();
 
@@ -1165,6 +1919,9 @@ fn build_recursive_config(args: &Args) -> wget_faster_lib::RecursiveConfig {
     // Set spider mode
     config.spider = args.spider;
 
+    // Set delete_after (--delete-after)
+    config.delete_after = args.delete_after;
+
     // Set span_hosts (follow links to other domains)
     config.span_hosts = args.span_hosts;
 
@@ -1177,8 +1934,11 @@ fn build_recursive_config(args: &Args) -> wget_faster_lib::RecursiveConfig {
     // Set no_host_directories (don't create hostname directories)
     config.no_host_directories = args.no_host_directories;
 
-    // Set convert_links (-k flag)
-    config.convert_links = args.convert_links;
+    // Set convert_links (-k flag); --convert-file-only converts just the file names
+    config.convert_links = args.convert_links || args.convert_file_only;
+    if args.convert_file_only {
+        config.conversion_mode = wget_faster_lib::ConversionMode::FileOnly;
+    }
 
     // Set backup_converted (-K flag)
     config.backup_converted = args.backup_converted;
@@ -1192,6 +1952,12 @@ fn build_recursive_config(args: &Args) -> wget_faster_lib::RecursiveConfig {
     // Set no_directories (-nd/--no-directories)
     config.no_directories = args.no_directories;
 
+    // Set cut_dirs (--cut-dirs)
+    config.cut_dirs = args.cut_dirs.unwrap_or(0);
+
+    // Set protocol_directories (--protocol-directories)
+    config.protocol_directories = args.protocol_directories;
+
     // Set include_directories (-I flag)
     if let Some(ref include_dirs) = args.include_directories {
         config.include_directories = include_dirs
@@ -1210,41 +1976,123 @@ fn build_recursive_config(args: &Args) -> wget_faster_lib::RecursiveConfig {
             .collect();
     }
 
+    // Set URL regex filters (--accept-regex / --reject-regex)
+    config.accept_regex = args.accept_regex.clone();
+    config.reject_regex = args.reject_regex.clone();
+
     config
 }
 
-/// Pre-process command-line arguments to expand wget-style multi-character short flags
+/// Multi-character short options that wget accepts as a single flag.
+///
+/// These must be matched before the generic cluster splitting, otherwise
+/// `-nc` would be split into `-n -c` (no-directories + continue).
+const MULTI_CHAR_SHORTS: &[(&str, &str)] = &[
+    ("-nH", "--no-host-directories"),
+    ("-nc", "--no-clobber"),
+    ("-nd", "--no-directories"),
+    ("-np", "--no-parent"),
+    ("-nv", "--no-verbose"),
+];
+
+/// Short flags known to clap, split into boolean flags and value-taking options
+///
+/// Derived from the `Args` definition so the table never drifts from the parser.
+fn short_flag_table() -> (HashSet<char>, HashSet<char>) {
+    let mut boolean = HashSet::new();
+    let mut valued = HashSet::new();
+
+    for arg in Args::command().get_arguments() {
+        if let Some(short) = arg.get_short() {
+            if arg.get_action().takes_values() {
+                valued.insert(short);
+            } else {
+                boolean.insert(short);
+            }
+        }
+    }
+
+    (boolean, valued)
+}
+
+/// Expand a single `-abc` cluster into individual flags
+///
+/// Returns `None` if the cluster contains an unknown flag, in which case the
+/// argument is passed through unchanged and clap reports the error. The bool
+/// in the result is true when the last flag takes its value from the next argument.
+fn split_short_cluster(
+    arg: &str,
+    boolean: &HashSet<char>,
+    valued: &HashSet<char>,
+) -> Option<(Vec<String>, bool)> {
+    let body = &arg[1..];
+    let mut expanded = Vec::new();
+
+    for (idx, c) in body.char_indices() {
+        if boolean.contains(&c) {
+            expanded.push(format!("-{c}"));
+        } else if valued.contains(&c) {
+            expanded.push(format!("-{c}"));
+            let rest = &body[idx + c.len_utf8()..];
+            if rest.is_empty() {
+                return Some((expanded, true));
+            }
+            // -t5, -qOfile, -qO- : the remainder is the option's value
+            expanded.push(rest.to_string());
+            return Some((expanded, false));
+        } else {
+            return None;
+        }
+    }
+
+    Some((expanded, false))
+}
+
+/// Pre-process command-line arguments to expand wget-style short flags
 ///
 /// GNU wget supports multi-character short flags like:
 /// - `-nH` for `--no-host-directories`
 /// - `-np` for `--no-parent`
 ///
-/// Since clap doesn't support multi-character short flags, we expand them here.
+/// and POSIX-style clusters like `-drc` or `-qO file`. Since clap doesn't
+/// support multi-character short flags, we expand them here.
 fn preprocess_args(args: Vec<String>) -> Vec<String> {
-    let mut result = Vec::new();
+    let (boolean, valued) = short_flag_table();
+    let mut result = Vec::with_capacity(args.len());
+    let mut expecting_value = false;
+    let mut end_of_options = false;
 
     for arg in args {
-        // Check if this is a short flag starting with `-` (but not `--`)
-        if arg.starts_with('-') && !arg.starts_with("--") && arg.len() > 2 {
-            // Handle multi-character short flags
-            match arg.as_str() {
-                "-nH" => {
-                    result.push("--no-host-directories".to_string());
-                },
-                "-np" => {
-                    result.push("--no-parent".to_string());
-                },
-                "-nv" => {
-                    result.push("--no-verbose".to_string());
-                },
-                _ => {
-                    // For other combinations, try to split into individual flags
-                    // This handles cases like "-qO" -> "-q -O"
-                    result.push(arg);
-                },
-            }
-        } else {
+        // Values of options (e.g. `-O -`) and everything after `--` pass through verbatim
+        if expecting_value || end_of_options {
+            expecting_value = false;
+            result.push(arg);
+            continue;
+        }
+
+        if arg == "--" {
+            end_of_options = true;
             result.push(arg);
+            continue;
+        }
+
+        // Only consider short flags starting with `-` (but not `--` or a lone `-`)
+        if !arg.starts_with('-') || arg.starts_with("--") || arg.len() < 2 {
+            result.push(arg);
+            continue;
+        }
+
+        if let Some((_, long)) = MULTI_CHAR_SHORTS.iter().find(|(short, _)| *short == arg) {
+            result.push((*long).to_string());
+            continue;
+        }
+
+        match split_short_cluster(&arg, &boolean, &valued) {
+            Some((expanded, needs_value)) => {
+                result.extend(expanded);
+                expecting_value = needs_value;
+            },
+            None => result.push(arg),
         }
     }
 
@@ -1267,7 +2115,11 @@ fn print_version() {
     println!("  +parallel      Parallel chunk downloads");
     println!("  +adaptive      Adaptive performance tuning");
     println!("  +cookies       Cookie support (Netscape format)");
-    println!("  +compression   gzip, deflate, brotli");
+    let codings: Vec<&str> = ContentCoding::all()
+        .into_iter()
+        .map(ContentCoding::token)
+        .collect();
+    println!("  +compression   {}", codings.join(", "));
     println!("  +recursive     Recursive downloads with HTML parsing");
     println!("  +timestamping  If-Modified-Since support");
     println!("  +resume        Resume partial downloads");
@@ -1277,3 +2129,232 @@ fn print_version() {
     println!("This is free software: you are free to change and redistribute it.");
     println!("There is NO WARRANTY, to the extent permitted by law.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pre(args: &[&str]) -> Vec<String> {
+        let mut full = vec!["wgetf".to_string()];
+        full.extend(args.iter().map(|a| (*a).to_string()));
+        preprocess_args(full)[1..].to_vec()
+    }
+
+    #[test]
+    fn test_naming_url_follows_redirect_only_when_trusted() {
+        let url = Url::parse("http://example.com/download?id=7").unwrap();
+        let final_url = Some("http://cdn.example.com/files/tool-1.2.tar.gz");
+
+        assert_eq!(url_filename(&naming_url(&url, false, final_url)), "download");
+        assert_eq!(url_filename(&naming_url(&url, true, final_url)), "tool-1.2.tar.gz");
+        assert_eq!(url_filename(&naming_url(&url, true, None)), "download");
+
+        // A redirect to a directory still gets index.html
+        let directory = Some("http://cdn.example.com/files/");
+        assert_eq!(url_filename(&naming_url(&url, true, directory)), "index.html");
+    }
+
+    #[test]
+    fn test_show_config_snapshot() {
+        // Flags from every area; paths are absolute and proxies off so the
+        // environment doesn't show up in the output
+        let args = Args::parse_from(pre(&[
+            "--show-config",
+            "--no-proxy",
+            "--hsts-file",
+            "/var/lib/wget-hsts",
+            "--user-agent",
+            "snapshot/1.0",
+            "-T",
+            "10",
+            "--tries",
+            "3",
+            "--http-user",
+            "alice",
+            "--http-password",
+            "hunter2",
+            "--header",
+            "Authorization: Bearer s3cr3t",
+            "--header",
+            "Accept-Language: en",
+            "--post-data",
+            "password=hunter2",
+            "--max-redirect",
+            "0",
+            "-r",
+            "-l",
+            "3",
+            "-k",
+            "-E",
+            "-np",
+            "--quota",
+            "5000000",
+        ]));
+        let config = build_config(&args).unwrap();
+        let shown = effective_config(&args, &config);
+        assert!(!shown.contains("hunter2") && !shown.contains("s3cr3t"));
+
+        // Compared without what depends on the enabled features: the pack and
+        // archive settings, and the codings this build decodes
+        let mut shown: serde_json::Value = serde_json::from_str(&shown).unwrap();
+        if let Some(recursive) = shown["recursive"].as_object_mut() {
+            for key in ["small_file_threshold", "archive_output", "archive_path"] {
+                recursive.remove(key);
+            }
+        }
+        let mut expected: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/show-config.json")).unwrap();
+        expected["download"]["accepted_encodings"] =
+            serde_json::to_value(DownloadConfig::default().accepted_encodings).unwrap();
+        assert_eq!(shown, expected);
+    }
+
+    #[test]
+    fn test_address_family_flags() {
+        let config = |flags: &[&str]| {
+            let mut full = vec!["wgetf".to_string()];
+            full.extend(flags.iter().map(|flag| (*flag).to_string()));
+            build_config(&Args::parse_from(preprocess_args(full)))
+        };
+        let family = |flags: &[&str]| config(flags).map(|config| config.address_family);
+        assert_eq!(family(&[]).unwrap(), None);
+        assert_eq!(family(&["-4"]).unwrap(), Some(AddressFamily::V4Only));
+        assert_eq!(family(&["--inet6-only"]).unwrap(), Some(AddressFamily::V6Only));
+        assert_eq!(family(&["--prefer-family=IPv6"]).unwrap(), Some(AddressFamily::PreferV6));
+        assert_eq!(family(&["-4", "--prefer-family=IPv6"]).unwrap(), Some(AddressFamily::V4Only));
+        assert!(family(&["-4", "-6"]).is_err());
+        assert!(family(&["--prefer-family=ipx"]).is_err());
+
+        let bind_address = config(&["--bind-address", "127.0.0.1"])
+            .unwrap()
+            .bind_address;
+        assert_eq!(bind_address, Some(std::net::Ipv4Addr::LOCALHOST.into()));
+    }
+
+    #[test]
+    fn test_compression_flag() {
+        let config = |value: &str| {
+            let full = vec!["wgetf".to_string(), format!("--compression={value}")];
+            build_config(&Args::parse_from(preprocess_args(full)))
+        };
+        let auto = config("auto").unwrap();
+        assert_eq!(auto.accepted_encodings, ContentCoding::all());
+        assert!(auto.enable_compression && auto.decompress);
+        let gzip = config("gzip").unwrap();
+        assert_eq!(gzip.accepted_encodings, [ContentCoding::Gzip]);
+        assert!(gzip.decompress);
+        let none = config("none").unwrap();
+        assert!(!none.enable_compression && !none.decompress);
+        assert!(config("lzma").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_dry_run_offline_plan() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("wgetf-dry-run-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let locked = dir.join("locked.iso");
+        std::fs::write(&locked, "old").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o444)).unwrap();
+
+        let run = |flags: Vec<String>, urls: &'static [&'static str]| async move {
+            let mut full = vec!["wgetf".to_string(), "--dry-run=offline".to_string()];
+            full.extend(flags);
+            let args = Args::parse_from(preprocess_args(full));
+            let downloader = Downloader::new(build_config(&args).unwrap()).unwrap();
+            let urls: Vec<String> = urls.iter().map(|url| (*url).to_string()).collect();
+            let mut out = Vec::new();
+            let code = dry_run(&downloader, &urls, &args, true, &mut out).await;
+            (code, String::from_utf8(out).unwrap())
+        };
+
+        // Offline, so the unresolvable host is never contacted
+        let prefix = dir.display().to_string();
+        let (code, out) = run(
+            vec!["-P".to_string(), prefix.clone()],
+            &["http://wgetf.invalid/pub/file.iso", "not a url"],
+        )
+        .await;
+        assert_eq!(code, 1);
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                format!("http://wgetf.invalid/pub/file.iso -> {prefix}/file.iso: download [GET]"),
+                "not a url: invalid URL (relative URL without a base)".to_string(),
+            ]
+        );
+
+        let (code, out) = run(
+            vec!["-O".to_string(), locked.display().to_string()],
+            &["http://wgetf.invalid/file.iso"],
+        )
+        .await;
+        assert_eq!(code, 3);
+        assert_eq!(
+            out.trim_end(),
+            format!(
+                "http://wgetf.invalid/file.iso -> {}: download [GET] (not writable)",
+                locked.display()
+            )
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_warc_file_flag() {
+        let full = vec!["wgetf".to_string(), "--warc-file=crawl/site".to_string()];
+        let config = build_config(&Args::parse_from(preprocess_args(full))).unwrap();
+        assert_eq!(config.warc_file, Some(PathBuf::from("crawl/site.warc.gz")));
+        let full = vec!["wgetf".to_string()];
+        let config = build_config(&Args::parse_from(preprocess_args(full))).unwrap();
+        assert_eq!(config.warc_file, None);
+    }
+
+    #[test]
+    fn test_multi_char_aliases() {
+        assert_eq!(pre(&["-nH"]), vec!["--no-host-directories"]);
+        assert_eq!(pre(&["-nc"]), vec!["--no-clobber"]);
+        assert_eq!(pre(&["-nd"]), vec!["--no-directories"]);
+        assert_eq!(pre(&["-np"]), vec!["--no-parent"]);
+        assert_eq!(pre(&["-nv"]), vec!["--no-verbose"]);
+    }
+
+    #[test]
+    fn test_boolean_cluster() {
+        assert_eq!(pre(&["-drc", "http://x/"]), vec!["-d", "-r", "-c", "http://x/"]);
+        assert_eq!(pre(&["-rkp"]), vec!["-r", "-k", "-p"]);
+    }
+
+    #[test]
+    fn test_attached_value() {
+        assert_eq!(pre(&["-t5"]), vec!["-t", "5"]);
+        assert_eq!(pre(&["-qOfile.html"]), vec!["-q", "-O", "file.html"]);
+        assert_eq!(pre(&["-qO-", "http://x/"]), vec!["-q", "-O", "-", "http://x/"]);
+    }
+
+    #[test]
+    fn test_value_in_next_arg() {
+        assert_eq!(
+            pre(&["-qO", "out.html", "http://x/"]),
+            vec!["-q", "-O", "out.html", "http://x/"]
+        );
+        // The value of -O is never split, even if it looks like a cluster
+        assert_eq!(pre(&["-O", "-nv"]), vec!["-O", "-nv"]);
+        assert_eq!(pre(&["-qO", "-"]), vec!["-q", "-O", "-"]);
+    }
+
+    #[test]
+    fn test_passthrough() {
+        assert_eq!(pre(&["--", "-drc"]), vec!["--", "-drc"]);
+        assert_eq!(pre(&["-"]), vec!["-"]);
+        assert_eq!(pre(&["--output-document=x"]), vec!["--output-document=x"]);
+        // Negative numbers are not flag clusters
+        assert_eq!(pre(&["-5"]), vec!["-5"]);
+        assert_eq!(pre(&["-t", "-1"]), vec!["-t", "-1"]);
+        // Unknown flags are left for clap to report
+        assert_eq!(pre(&["-qZ"]), vec!["-qZ"]);
+    }
+}
@@ -1,27 +1,87 @@
-use chrono::Local;
-use indicatif::{ProgressBar, ProgressStyle};
-use std::fs::{File, OpenOptions};
+use chrono::{DateTime, Local};
+use console::Term;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use wget_faster_lib::{format_bytes, format_bytes_per_sec, ProgressInfo};
+use wget_faster_lib::{format_bytes, format_bytes_per_sec, CrawlProgress, ProgressInfo};
 
-/// Output destination for log messages
+/// Stream messages are written to, shared across threads
 #[derive(Clone)]
-enum LogDestination {
-    /// Write to terminal (default)
-    Terminal,
-    /// Write to file (shared across threads)
-    File(Arc<Mutex<File>>),
+struct Sink {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    /// Whether this is the terminal the progress bar is drawn on
+    terminal: bool,
 }
 
+impl Sink {
+    /// stderr, like wget, so stdout stays clean for `-O -`
+    fn stderr() -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(Box::new(std::io::stderr()))),
+            terminal: true,
+        }
+    }
+
+    fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+            terminal: false,
+        }
+    }
+}
+
+/// Time used in message timestamps
+pub type Clock = Box<dyn Fn() -> DateTime<Local> + Send + Sync>;
+
 pub struct WgetOutput {
     quiet: bool,
     verbose: bool,
+    /// Draw a progress bar (see [`progress_enabled`])
     show_progress: bool,
     progress_bar: Option<ProgressBar>,
-    log_dest: LogDestination,
+    /// Progress and status messages
+    log: Sink,
+    /// Error messages, written even with `-q`
+    error: Sink,
+    clock: Clock,
+}
+
+/// Whether to draw the progress bar
+///
+/// Like wget: `-q` and `-nv` turn the bar off unless `--show-progress` is given,
+/// and the bar is only drawn when stderr is a terminal, so redirected output
+/// never collects control characters. `--progress=bar:force` draws it anyway.
+pub fn progress_enabled(
+    progress: Option<&str>,
+    show_progress: bool,
+    quiet: bool,
+    stderr_is_tty: bool,
+) -> bool {
+    let forced = progress.is_some_and(|p| {
+        let mut parts = p.split(':');
+        parts.next() == Some("bar") && parts.any(|option| option == "force")
+    });
+    (show_progress || !quiet) && (stderr_is_tty || forced)
+}
+
+/// Clears the progress bar when dropped
+///
+/// Returned by [`WgetOutput::init_progress`]: an early return or `?` in the
+/// middle of a transfer then can't leave a half-drawn bar behind the error.
+#[must_use = "the progress bar is cleared when the guard is dropped"]
+pub struct ProgressGuard(Option<ProgressBar>);
+
+impl Drop for ProgressGuard {
+    fn drop(&mut self) {
+        if let Some(pb) = self.0.take() {
+            if !pb.is_finished() {
+                pb.finish_and_clear();
+            }
+        }
+    }
 }
 
 impl WgetOutput {
@@ -31,10 +91,28 @@ impl WgetOutput {
             verbose,
             show_progress,
             progress_bar: None,
-            log_dest: LogDestination::Terminal,
+            log: Sink::stderr(),
+            error: Sink::stderr(),
+            clock: Box::new(Local::now),
         }
     }
 
+    /// Write log messages to `log` and error messages to `error` instead of stderr
+    ///
+    /// The progress bar isn't drawn for these sinks.
+    pub fn with_sinks(mut self, log: Box<dyn Write + Send>, error: Box<dyn Write + Send>) -> Self {
+        self.log = Sink::new(log);
+        self.error = Sink::new(error);
+        self
+    }
+
+    /// Take message timestamps from `clock` instead of the system time
+    #[cfg(test)]
+    fn with_clock(mut self, clock: impl Fn() -> DateTime<Local> + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
     /// Create a new `WgetOutput` with file logging
     pub fn with_log_file(
         quiet: bool,
@@ -56,47 +134,60 @@ impl WgetOutput {
                 .open(log_file)?
         };
 
-        Ok(Self {
-            quiet,
-            verbose,
-            show_progress,
-            progress_bar: None,
-            log_dest: LogDestination::File(Arc::new(Mutex::new(file))),
-        })
+        // Errors go to the log file too, through the same open file
+        let error = file.try_clone()?;
+        Ok(Self::new(quiet, verbose, show_progress).with_sinks(Box::new(file), Box::new(error)))
     }
 
-    /// Write a log message to the appropriate destination
-    fn write_log(&self, message: &str) {
-        match &self.log_dest {
-            LogDestination::Terminal => {
-                println!("{message}");
-            },
-            LogDestination::File(file) => {
-                if let Ok(mut f) = file.lock() {
-                    let _ = writeln!(f, "{message}");
-                }
-            },
+    /// Create a new `WgetOutput` writing log and error messages to `writer`
+    #[cfg(test)]
+    fn with_writer(
+        quiet: bool,
+        verbose: bool,
+        show_progress: bool,
+        writer: impl Write + Send + 'static,
+    ) -> Self {
+        let sink = Sink::new(Box::new(writer));
+        Self {
+            log: sink.clone(),
+            error: sink,
+            ..Self::new(quiet, verbose, show_progress)
         }
     }
 
-    /// Write a log message without newline
-    fn write_log_no_newline(&self, message: &str) {
-        match &self.log_dest {
-            LogDestination::Terminal => {
-                print!("{message}");
-            },
-            LogDestination::File(file) => {
-                if let Ok(mut f) = file.lock() {
-                    let _ = write!(f, "{message}");
-                }
-            },
+    /// Write one line to `sink`
+    ///
+    /// On the terminal the progress bar is suspended around the message, so the
+    /// message gets its own line instead of tearing through the bar.
+    fn write_line(&self, sink: &Sink, message: &str) {
+        let write = || {
+            if let Ok(mut w) = sink.writer.lock() {
+                let _ = writeln!(w, "{message}");
+            }
+        };
+        match &self.progress_bar {
+            Some(pb) if sink.terminal => pb.suspend(write),
+            _ => write(),
         }
     }
 
+    fn write_log(&self, message: &str) {
+        self.write_line(&self.log, message);
+    }
+
+    fn write_error(&self, message: &str) {
+        self.write_line(&self.error, message);
+    }
+
+    /// Current time as wget prints it in messages
+    fn timestamp(&self) -> String {
+        (self.clock)().format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
     /// Print wget-style connection message
     pub fn print_connecting(&self, url: &str, host: &str, port: u16) {
         if !self.quiet {
-            let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+            let timestamp = self.timestamp();
             self.write_log(&format!("--{timestamp}--  {url}"));
             self.write_log(&format!("Resolving {host}... "));
             self.write_log(&format!("Connecting to {host}:{port}... connected."));
@@ -113,11 +204,7 @@ impl WgetOutput {
     /// Print HTTP response status
     pub fn print_http_response(&self, status: u16, status_text: &str) {
         if !self.quiet {
-            if status == 200 {
-                self.write_log(&format!("{status} {status_text}"));
-            } else {
-                self.write_log(&format!("{status} {status_text}"));
-            }
+            self.write_log(&format!("{status} {status_text}"));
         }
     }
 
@@ -147,36 +234,44 @@ impl WgetOutput {
     }
 
     /// Initialize progress bar for download
-    pub fn init_progress(&mut self, total_size: Option<u64>) {
-        if self.quiet {
-            return;
+    ///
+    /// The bar is drawn on stderr; when logging to a file (`-o`/`-a`) it is
+    /// kept but hidden.
+    pub fn init_progress(&mut self, total_size: Option<u64>) -> ProgressGuard {
+        if !self.show_progress {
+            return ProgressGuard(None);
         }
 
-        let pb = if let Some(size) = total_size {
-            ProgressBar::new(size)
+        // `show_progress` already accounts for TTY detection and `bar:force`,
+        // so draw to stderr even when it isn't a terminal
+        let target = if self.log.terminal {
+            ProgressDrawTarget::term_like_with_hz(Box::new(Term::stderr()), 20)
         } else {
-            ProgressBar::new_spinner()
+            ProgressDrawTarget::hidden()
         };
+        let pb = ProgressBar::with_draw_target(total_size, target);
 
         // wget-style progress format
-        let style = if total_size.is_some() {
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{bar:40.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} eta {eta}")
-                .unwrap()
-                .progress_chars("=>-")
+        pb.set_style(if total_size.is_some() {
+            bar_style()
         } else {
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} {bytes} {bytes_per_sec}")
-                .unwrap()
-        };
-
-        pb.set_style(style);
-        self.progress_bar = Some(pb);
+            spinner_style()
+        });
+        self.progress_bar = Some(pb.clone());
+        ProgressGuard(Some(pb))
     }
 
     /// Update progress during download
     pub fn update_progress(&self, progress: &ProgressInfo) {
         if let Some(pb) = &self.progress_bar {
+            // The total may only become known mid-transfer: turn the spinner into a bar
+            if let Some(total) = progress.total_size {
+                if pb.length() != Some(total) {
+                    pb.set_length(total);
+                    pb.set_style(bar_style());
+                }
+            }
+
             pb.set_position(progress.downloaded);
 
             // Update message with current stats
@@ -185,11 +280,11 @@ impl WgetOutput {
                 pb.set_message(format!(
                     "{:.1}% {} eta {}",
                     percentage,
-                    format_bytes_per_sec(progress.speed),
+                    format_bytes_per_sec(progress.speed_average),
                     progress.format_eta().unwrap_or_else(|| "--:--".to_string())
                 ));
             } else {
-                pb.set_message(format_bytes_per_sec(progress.speed).clone());
+                pb.set_message(format_bytes_per_sec(progress.speed_average).clone());
             }
         }
     }
@@ -214,7 +309,7 @@ impl WgetOutput {
             self.write_log("");
             self.write_log(&format!(
                 "{} - '{}' saved [{}]",
-                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                self.timestamp(),
                 filename,
                 downloaded
             ));
@@ -224,13 +319,13 @@ impl WgetOutput {
 
     /// Print error message
     pub fn print_error(&self, error: &str) {
-        eprintln!("wget-faster: {error}");
+        self.write_error(&format!("wget-faster: {error}"));
     }
 
     /// Print warning message
     pub fn print_warning(&self, warning: &str) {
         if !self.quiet {
-            eprintln!("wget-faster: {warning}");
+            self.write_log(&format!("wget-faster: {warning}"));
         }
     }
 
@@ -253,25 +348,15 @@ impl WgetOutput {
     /// Print spider mode message (for --spider)
     pub fn print_spider_result(&self, url: &str, status: u16, exists: bool) {
         if !self.quiet {
-            if exists {
-                self.write_log("Spider mode enabled. Check if remote file exists.");
-                self.write_log(&format!(
-                    "--{}--  {}",
-                    Local::now().format("%Y-%m-%d %H:%M:%S"),
-                    url
-                ));
-                self.write_log(&format!("  HTTP {status} OK"));
-                self.write_log("Remote file exists.");
+            let (status_text, result) = if exists {
+                ("OK", "Remote file exists.")
             } else {
-                self.write_log("Spider mode enabled. Check if remote file exists.");
-                self.write_log(&format!(
-                    "--{}--  {}",
-                    Local::now().format("%Y-%m-%d %H:%M:%S"),
-                    url
-                ));
-                self.write_log(&format!("  HTTP {status} Not Found"));
-                self.write_log("Remote file does not exist -- broken link!!!");
-            }
+                ("Not Found", "Remote file does not exist -- broken link!!!")
+            };
+            self.write_log("Spider mode enabled. Check if remote file exists.");
+            self.write_log(&format!("--{}--  {}", self.timestamp(), url));
+            self.write_log(&format!("  HTTP {status} {status_text}"));
+            self.write_log(result);
         }
     }
 
@@ -312,7 +397,30 @@ impl WgetOutput {
     /// Print quota exceeded message
     pub fn print_quota_exceeded(&self, quota: u64) {
         // Always show quota exceeded errors (even in quiet mode)
-        eprintln!("Download quota of {quota} bytes EXCEEDED!");
+        self.write_error(&format!("Download quota of {quota} bytes EXCEEDED!"));
+    }
+
+    /// Print the outcome of a scheduled download cycle (--schedule/--repeat)
+    pub fn print_cycle_result(&self, cycle: u64, exit_code: i32, next_run: Option<&str>) {
+        if self.quiet {
+            return;
+        }
+        let status = if exit_code == 0 {
+            "succeeded".to_string()
+        } else {
+            format!("failed (exit status {exit_code})")
+        };
+        self.write_log(&format!("{} - Cycle {cycle} {status}.", self.timestamp()));
+        if let Some(next_run) = next_run {
+            self.write_log(&format!("Next run at {next_run}."));
+        }
+    }
+
+    /// Print the next planned run of a scheduled download
+    pub fn print_next_run(&self, next_run: &str) {
+        if !self.quiet {
+            self.write_log(&format!("{} - Next run at {next_run}.", self.timestamp()));
+        }
     }
 
     /// Print redirected message
@@ -321,6 +429,13 @@ impl WgetOutput {
             self.write_log(&format!("Location: {to} [following]"));
         }
     }
+
+    /// Print the target of a redirect that was not followed (`--max-redirect 0`)
+    pub fn print_unfollowed_redirect(&self, to: &str) {
+        if !self.quiet {
+            self.write_log(&format!("Location: {to}"));
+        }
+    }
 }
 
 /// Format duration in wget style (e.g., "2m 30s", "1h 15m 20s")
@@ -350,9 +465,63 @@ pub fn format_duration_wget(duration: Duration) -> String {
     parts.join(" ")
 }
 
+/// Error message for the terminal, with a "caused by:" line per source when `verbose`
+///
+/// A source whose message is already part of the previous line is skipped, so
+/// errors that repeat their cause in their own message aren't printed twice.
+pub fn format_error_chain(error: &(dyn std::error::Error + 'static), verbose: bool) -> String {
+    let mut message = error.to_string();
+    if !verbose {
+        return message;
+    }
+    let mut previous = message.clone();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let cause_message = cause.to_string();
+        if !previous.contains(&cause_message) {
+            message.push_str(&format!("\n  caused by: {cause_message}"));
+        }
+        previous = cause_message;
+        source = cause.source();
+    }
+    message
+}
+
+/// One-line recursive crawl status, e.g. `12 visited, 30 queued, 1.50 MB, depths 1/11 [4s] URL`
+pub fn format_crawl_progress(progress: &CrawlProgress) -> String {
+    let depths: Vec<String> = progress.depths.iter().map(ToString::to_string).collect();
+    format!(
+        "{} visited, {} queued, {}, depths {} [{}] {}",
+        progress.visited,
+        progress.queued,
+        format_bytes(progress.bytes_downloaded),
+        depths.join("/"),
+        format_duration_wget(progress.elapsed),
+        progress.current_url
+    )
+}
+
+/// Progress bar style once the total size is known
+fn bar_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template(
+            "{spinner:.green} [{bar:40.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} eta {eta}",
+        )
+        .unwrap()
+        .progress_chars("=>-")
+}
+
+/// Spinner style while the total size is unknown
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::default_spinner()
+        .template("{spinner:.green} {bytes} {bytes_per_sec}")
+        .unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
 
     #[test]
     fn test_format_duration_wget() {
@@ -361,4 +530,198 @@ mod tests {
         assert_eq!(format_duration_wget(Duration::from_secs(90)), "1m 30s");
         assert_eq!(format_duration_wget(Duration::from_secs(3661)), "1h 1m 1s");
     }
+
+    #[test]
+    fn test_format_error_chain() {
+        #[derive(Debug)]
+        struct Refused;
+        impl std::fmt::Display for Refused {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("connection refused")
+            }
+        }
+        impl std::error::Error for Refused {}
+
+        let inner = wget_faster_lib::Error::IoError(std::io::Error::other(Refused))
+            .with_context("while writing chunk 3 of http://example.com/f");
+        let error = anyhow::Error::new(inner).context("download of http://example.com/f failed");
+
+        assert_eq!(
+            format_error_chain(error.as_ref(), false),
+            "download of http://example.com/f failed"
+        );
+        assert_eq!(
+            format_error_chain(error.as_ref(), true),
+            "download of http://example.com/f failed\n  \
+             caused by: IO error: connection refused (while writing chunk 3 of http://example.com/f)"
+        );
+    }
+
+    #[test]
+    fn test_format_crawl_progress() {
+        let progress = CrawlProgress {
+            visited: 12,
+            queued: 30,
+            bytes_downloaded: 2048,
+            tracked_bytes: 0,
+            depths: vec![1, 11],
+            current_url: "http://example.com/a.html".to_string(),
+            elapsed: Duration::from_secs(4),
+        };
+        assert_eq!(
+            format_crawl_progress(&progress),
+            format!(
+                "12 visited, 30 queued, {}, depths 1/11 [4s] http://example.com/a.html",
+                format_bytes(2048)
+            )
+        );
+    }
+
+    #[test]
+    fn test_progress_bar_gains_length_mid_transfer() {
+        let mut out = WgetOutput::with_writer(false, false, true, Captured::default());
+        let _progress = out.init_progress(None);
+        let mut progress = ProgressInfo::new("http://example.com/export".to_string());
+        progress.downloaded = 10;
+        out.update_progress(&progress);
+        assert_eq!(out.progress_bar.as_ref().unwrap().length(), None);
+
+        progress.total_size = Some(100);
+        out.update_progress(&progress);
+        assert_eq!(out.progress_bar.as_ref().unwrap().length(), Some(100));
+        out.finish_progress();
+    }
+
+    /// Non-TTY writer that keeps everything written to it
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_progress_enabled() {
+        assert!(progress_enabled(None, false, false, true));
+        assert!(!progress_enabled(None, false, false, false));
+        assert!(progress_enabled(Some("bar:force"), false, false, false));
+        assert!(progress_enabled(Some("bar:force:noscroll"), false, false, false));
+        assert!(!progress_enabled(Some("bar:noscroll"), false, false, false));
+        assert!(!progress_enabled(Some("dot:force"), false, false, false));
+        assert!(!progress_enabled(None, false, true, true));
+        assert!(progress_enabled(None, true, true, true));
+    }
+
+    #[test]
+    fn test_non_tty_output_has_no_escapes() {
+        let captured = Captured::default();
+        let show_progress = progress_enabled(None, false, false, false);
+        let mut out = WgetOutput::with_writer(false, true, show_progress, captured.clone());
+
+        out.print_connecting("http://example.com/f", "example.com", 80);
+        out.print_saving_to("f");
+        let progress_guard = out.init_progress(Some(100));
+        let mut progress = ProgressInfo::new("http://example.com/f".to_string());
+        progress.downloaded = 40;
+        progress.total_size = Some(100);
+        out.update_progress(&progress);
+        drop(progress_guard);
+        out.print_error("download failed: connection reset");
+
+        let text = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(text.contains("Connecting to example.com:80... connected."));
+        assert!(text.contains("Saving to: 'f'"));
+        assert!(text.ends_with("wget-faster: download failed: connection reset\n"));
+        assert!(!text.contains('\x1b') && !text.contains('\r'), "{text:?}");
+    }
+
+    /// Run `print` against an output with separate log and error sinks and a
+    /// fixed clock, returning what each sink received
+    fn snapshot(quiet: bool, print: impl FnOnce(&WgetOutput)) -> (String, String) {
+        let (log, error) = (Captured::default(), Captured::default());
+        let out = WgetOutput::new(quiet, false, false)
+            .with_sinks(Box::new(log.clone()), Box::new(error.clone()))
+            .with_clock(|| Local.with_ymd_and_hms(2024, 3, 9, 14, 5, 7).unwrap());
+        print(&out);
+        let text = |c: Captured| String::from_utf8(c.0.lock().unwrap().clone()).unwrap();
+        (text(log), text(error))
+    }
+
+    #[test]
+    fn test_download_messages_snapshot() {
+        let (log, error) = snapshot(false, |out| {
+            out.print_connecting("http://example.com/f.tar", "example.com", 80);
+            out.print_http_request();
+            out.print_http_response(200, "OK");
+            out.print_content_info(Some(2048), Some("application/x-tar"));
+            out.print_saving_to("f.tar");
+            out.print_complete("f.tar", 2048, Duration::from_secs(1));
+        });
+        assert_eq!(
+            log,
+            "--2024-03-09 14:05:07--  http://example.com/f.tar\n\
+             Resolving example.com... \n\
+             Connecting to example.com:80... connected.\n\
+             200 OK\n\
+             Length: 2048 (2.00KB) [application/x-tar]\n\
+             Saving to: 'f.tar'\n\
+             \n\
+             \n\
+             2024-03-09 14:05:07 - 'f.tar' saved [2048]\n\
+             \n"
+        );
+        assert_eq!(error, "");
+    }
+
+    #[test]
+    fn test_spider_result_snapshot() {
+        let (log, _) = snapshot(false, |out| {
+            out.print_spider_result("http://example.com/a", 200, true);
+            out.print_spider_result("http://example.com/b", 404, false);
+        });
+        assert_eq!(
+            log,
+            "Spider mode enabled. Check if remote file exists.\n\
+             --2024-03-09 14:05:07--  http://example.com/a\n  \
+             HTTP 200 OK\n\
+             Remote file exists.\n\
+             Spider mode enabled. Check if remote file exists.\n\
+             --2024-03-09 14:05:07--  http://example.com/b\n  \
+             HTTP 404 Not Found\n\
+             Remote file does not exist -- broken link!!!\n"
+        );
+    }
+
+    #[test]
+    fn test_errors_go_to_error_sink_even_when_quiet() {
+        let (log, error) = snapshot(true, |out| {
+            out.print_connecting("http://example.com/f", "example.com", 80);
+            out.print_error("download failed: connection reset");
+            out.print_quota_exceeded(1024);
+        });
+        assert_eq!(log, "");
+        assert_eq!(
+            error,
+            "wget-faster: download failed: connection reset\n\
+             Download quota of 1024 bytes EXCEEDED!\n"
+        );
+    }
+
+    #[test]
+    fn test_progress_guard_clears_bar() {
+        let mut out = WgetOutput::with_writer(false, false, true, Captured::default());
+        let guard = out.init_progress(Some(100));
+        let pb = out.progress_bar.clone().unwrap();
+        assert!(!pb.is_finished());
+
+        drop(guard);
+        assert!(pb.is_finished());
+    }
 }
@@ -0,0 +1,385 @@
+use chrono::{DateTime, Days, LocalResult, NaiveDate, NaiveTime, TimeZone};
+use rand::Rng;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+const DAY: Duration = Duration::from_hours(24);
+
+/// When to run scheduled downloads (`--schedule`, `--repeat`, `--repeat-jitter`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulePlan {
+    /// Local time of day of the first run (`None` = start right away)
+    pub at: Option<NaiveTime>,
+
+    /// Interval between runs (`None` = run once)
+    pub repeat: Option<Duration>,
+
+    /// Upper bound of the random delay added to each run
+    pub jitter: Duration,
+}
+
+impl SchedulePlan {
+    /// Build a plan from the raw CLI values
+    pub fn parse(
+        schedule: Option<&str>,
+        repeat: Option<&str>,
+        jitter: Option<&str>,
+    ) -> Result<Self, String> {
+        let repeat = repeat.map(parse_interval).transpose()?;
+        if repeat == Some(Duration::ZERO) {
+            return Err("--repeat interval must be greater than zero".to_string());
+        }
+
+        Ok(Self {
+            at: schedule.map(parse_time_of_day).transpose()?,
+            repeat,
+            jitter: jitter.map(parse_interval).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    /// Planned time of the first run
+    pub fn first_run<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> DateTime<Tz> {
+        match self.at {
+            Some(at) => next_occurrence(now, at),
+            None => now.clone(),
+        }
+    }
+
+    /// Planned time of the run after the one planned for `previous`, or `None` if not repeating
+    ///
+    /// Slots that already passed while a run was in progress are skipped.
+    /// Whole-day intervals anchored at a time of day keep that wall-clock time
+    /// across DST changes; other intervals are exact durations.
+    pub fn next_run<Tz: TimeZone>(
+        &self,
+        previous: &DateTime<Tz>,
+        now: &DateTime<Tz>,
+    ) -> Option<DateTime<Tz>> {
+        let repeat = self.repeat?;
+        let step = chrono::Duration::from_std(repeat).ok()?;
+        let whole_days = repeat.as_secs() % DAY.as_secs() == 0 && repeat.subsec_nanos() == 0;
+
+        let mut next = previous.clone();
+        loop {
+            next = match self.at {
+                Some(at) if whole_days => {
+                    let days = Days::new(repeat.as_secs() / DAY.as_secs());
+                    let date = next.with_timezone(&now.timezone()).date_naive() + days;
+                    at_local(&now.timezone(), date, at)
+                },
+                _ => next + step,
+            };
+            if next > *now {
+                return Some(next);
+            }
+        }
+    }
+
+    /// Random delay to add to a run
+    pub fn jitter_delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        rand::thread_rng().gen_range(Duration::ZERO..=self.jitter)
+    }
+}
+
+/// Parse a local time of day: `HH:MM` or `HH:MM:SS`
+pub fn parse_time_of_day(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .map_err(|_| format!("invalid time '{value}' (expected HH:MM or HH:MM:SS)"))
+}
+
+/// Parse an interval such as `24h`, `10m`, `1h30m` or `500ms`
+///
+/// Units: `ms`, `s`, `m`, `h`, `d`. A bare number is seconds.
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid interval '{value}' (e.g. 24h, 10m, 1h30m, 500ms)");
+
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let amount: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_len] {
+            "ms" => Duration::from_millis(1),
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_mins(1),
+            "h" => Duration::from_hours(1),
+            "d" => DAY,
+            _ => return Err(invalid()),
+        };
+        let amount = u32::try_from(amount).map_err(|_| invalid())?;
+        total += unit.checked_mul(amount).ok_or_else(invalid)?;
+        rest = &rest[unit_len..];
+    }
+    Ok(total)
+}
+
+/// First time strictly after `now` at which the local clock shows `at`
+pub fn next_occurrence<Tz: TimeZone>(now: &DateTime<Tz>, at: NaiveTime) -> DateTime<Tz> {
+    let tz = now.timezone();
+    let mut date = now.date_naive();
+    loop {
+        let candidate = at_local(&tz, date, at);
+        if candidate > *now {
+            return candidate;
+        }
+        date = date + Days::new(1);
+    }
+}
+
+/// `date` at local time `at`
+///
+/// An ambiguous time (clocks going back) resolves to its first occurrence;
+/// a time skipped by clocks going forward moves to the first valid minute after it.
+fn at_local<Tz: TimeZone>(tz: &Tz, date: NaiveDate, at: NaiveTime) -> DateTime<Tz> {
+    let mut local = date.and_time(at);
+    loop {
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => return time,
+            LocalResult::None => local += chrono::Duration::minutes(1),
+        }
+    }
+}
+
+/// Stop request shared with the Ctrl-C handler
+///
+/// Ctrl-C doesn't interrupt a running transfer: the scheduler stops
+/// before the next cycle or wakes up from its wait.
+#[derive(Debug, Default, Clone)]
+pub struct StopSignal {
+    stopped: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl StopSignal {
+    /// Install a Ctrl-C handler that requests a stop
+    pub fn on_ctrl_c() -> Self {
+        let signal = Self::default();
+        let handler = signal.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                eprintln!("wgetf: interrupt received, stopping after the current cycle");
+                handler.stop();
+            }
+        });
+        signal
+    }
+
+    /// Request a stop
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Whether a stop was requested
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Sleep for `duration`; returns false if a stop was requested meanwhile
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        let notified = self.notify.notified();
+        if self.is_stopped() {
+            return false;
+        }
+        tokio::select! {
+            () = tokio::time::sleep(duration) => !self.is_stopped(),
+            () = notified => false,
+        }
+    }
+}
+
+/// Outcome of the scheduled cycles
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleSummary {
+    /// Cycles that ran
+    pub cycles: u64,
+
+    /// Cycles that ended with a non-zero exit status
+    pub failed: u64,
+
+    /// Exit status of the last failed cycle (0 if none failed)
+    pub exit_code: i32,
+}
+
+/// Run `cycle` according to `plan` until it stops repeating or `stop` is requested
+///
+/// `on_cycle` sees each cycle's number, start time and exit status, and the
+/// next planned run.
+pub async fn run<C, F, L>(
+    plan: &SchedulePlan,
+    stop: &StopSignal,
+    mut cycle: C,
+    mut on_cycle: L,
+) -> ScheduleSummary
+where
+    C: FnMut() -> F,
+    F: Future<Output = i32>,
+    L: FnMut(u64, DateTime<chrono::Local>, i32, Option<DateTime<chrono::Local>>),
+{
+    let mut summary = ScheduleSummary::default();
+    let mut planned = Some(plan.first_run(&chrono::Local::now()));
+
+    while let Some(run_at) = planned {
+        let wait =
+            (run_at - chrono::Local::now()).to_std().unwrap_or_default() + plan.jitter_delay();
+        if !stop.sleep(wait).await {
+            break;
+        }
+
+        let started = chrono::Local::now();
+        let exit_code = cycle().await;
+        summary.cycles += 1;
+        if exit_code != 0 {
+            summary.failed += 1;
+            summary.exit_code = exit_code;
+        }
+
+        planned = plan.next_run(&run_at, &chrono::Local::now());
+        on_cycle(summary.cycles, started, exit_code, planned);
+        if stop.is_stopped() {
+            break;
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn local(date: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(date).unwrap()
+    }
+
+    #[test]
+    fn test_parse_time_of_day() {
+        assert_eq!(parse_time_of_day("03:00"), Ok(time(3, 0)));
+        assert_eq!(parse_time_of_day("23:59:30"), Ok(NaiveTime::from_hms_opt(23, 59, 30).unwrap()));
+        assert!(parse_time_of_day("3am").is_err());
+        assert!(parse_time_of_day("25:00").is_err());
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("24h"), Ok(Duration::from_secs(86_400)));
+        assert_eq!(parse_interval("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_interval("1h30m"), Ok(Duration::from_secs(5_400)));
+        assert_eq!(parse_interval("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_interval("2d"), Ok(Duration::from_secs(172_800)));
+        assert_eq!(parse_interval("90"), Ok(Duration::from_secs(90)));
+        assert!(parse_interval("").is_err());
+        assert!(parse_interval("h").is_err());
+        assert!(parse_interval("10 minutes").is_err());
+        assert!(SchedulePlan::parse(None, Some("0s"), None).is_err());
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        // Later today
+        assert_eq!(
+            next_occurrence(&local("2026-03-10T01:15:00+01:00"), time(3, 0)),
+            local("2026-03-10T03:00:00+01:00")
+        );
+        // Already passed (or exactly now): tomorrow
+        assert_eq!(
+            next_occurrence(&local("2026-03-10T03:00:00+01:00"), time(3, 0)),
+            local("2026-03-11T03:00:00+01:00")
+        );
+        assert_eq!(
+            next_occurrence(&local("2026-12-31T22:00:00-05:00"), time(3, 0)),
+            local("2027-01-01T03:00:00-05:00")
+        );
+    }
+
+    #[test]
+    fn test_next_run() {
+        let now = local("2026-03-10T03:00:05+01:00");
+        let daily = SchedulePlan::parse(Some("03:00"), Some("24h"), None).unwrap();
+        assert_eq!(
+            daily.next_run(&local("2026-03-10T03:00:00+01:00"), &now),
+            Some(local("2026-03-11T03:00:00+01:00"))
+        );
+
+        // Slots missed while a long run was in progress are skipped
+        let every_10m = SchedulePlan::parse(None, Some("10m"), None).unwrap();
+        assert_eq!(
+            every_10m
+                .next_run(&local("2026-03-10T02:30:00+01:00"), &local("2026-03-10T02:55:00+01:00")),
+            Some(local("2026-03-10T03:00:00+01:00"))
+        );
+
+        // No --repeat: a single run
+        let once = SchedulePlan::parse(Some("03:00"), None, None).unwrap();
+        assert_eq!(once.next_run(&now, &now), None);
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let plan = SchedulePlan::parse(None, Some("1h"), Some("10m")).unwrap();
+        for _ in 0..100 {
+            assert!(plan.jitter_delay() <= Duration::from_secs(600));
+        }
+        let plain = SchedulePlan::parse(None, Some("1h"), None).unwrap();
+        assert_eq!(plain.jitter_delay(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_run_repeats_until_stopped() {
+        let plan = SchedulePlan::parse(None, Some("50ms"), None).unwrap();
+        let stop = StopSignal::default();
+        let mut calls = 0;
+        let mut logged = Vec::new();
+
+        let summary = run(
+            &plan,
+            &stop,
+            || {
+                calls += 1;
+                let exit_code = if calls == 2 { 8 } else { 0 };
+                if calls == 3 {
+                    stop.stop();
+                }
+                async move { exit_code }
+            },
+            |n, _, exit_code, _| logged.push((n, exit_code)),
+        )
+        .await;
+
+        assert_eq!(logged, vec![(1, 0), (2, 8), (3, 0)]);
+        assert_eq!(
+            summary,
+            ScheduleSummary {
+                cycles: 3,
+                failed: 1,
+                exit_code: 8
+            }
+        );
+    }
+}
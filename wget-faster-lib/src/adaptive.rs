@@ -21,10 +21,9 @@ struct ChunkStats {
 struct ChunkDownloadParams {
     url: String,
     chunks: Vec<(u64, u64)>,
-    downloaded: Arc<Mutex<u64>>,
+    progress: Arc<Mutex<ProgressInfo>>,
     stats: Arc<Mutex<Vec<ChunkStats>>>,
     start_time: Instant,
-    total_size: u64,
     progress_callback: Option<ProgressCallback>,
 }
 
@@ -79,7 +78,9 @@ impl AdaptiveDownloader {
         let mut chunk_size = self.calculate_chunk_size(total_size, chunk_count);
 
         let start_time = Instant::now();
-        let downloaded = Arc::new(Mutex::new(0u64));
+        let mut progress = ProgressInfo::new(url.to_string());
+        progress.total_size = Some(total_size);
+        let progress = Arc::new(Mutex::new(progress));
         let stats = Arc::new(Mutex::new(Vec::new()));
 
         // Download first batch of chunks
@@ -101,10 +102,9 @@ impl AdaptiveDownloader {
                 .download_chunks(ChunkDownloadParams {
                     url: url.to_string(),
                     chunks: batch_chunks,
-                    downloaded: Arc::clone(&downloaded),
+                    progress: Arc::clone(&progress),
                     stats: Arc::clone(&stats),
                     start_time,
-                    total_size,
                     progress_callback: progress_callback.clone(),
                 })
                 .await?;
@@ -226,11 +226,10 @@ impl AdaptiveDownloader {
         for (start, end) in params.chunks {
             let client = self.client.clone();
             let url = params.url.clone();
-            let downloaded = Arc::clone(&params.downloaded);
+            let progress = Arc::clone(&params.progress);
             let stats = Arc::clone(&params.stats);
             let progress_callback = params.progress_callback.clone();
             let start_time = params.start_time;
-            let total_size = params.total_size;
 
             let task = tokio::spawn(async move {
                 let chunk_start = Instant::now();
@@ -239,10 +238,12 @@ impl AdaptiveDownloader {
                 // Download chunk
                 let range_header = format!("bytes={start}-{end}");
                 let response = client
-                    .client()
-                    .get(&url)
-                    .header(reqwest::header::RANGE, range_header)
-                    .send()
+                    .send(
+                        client
+                            .client()
+                            .get(&url)
+                            .header(reqwest::header::RANGE, range_header),
+                    )
                     .await?;
 
                 if !response.status().is_success() && response.status().as_u16() != 206 {
@@ -250,6 +251,7 @@ impl AdaptiveDownloader {
                 }
 
                 let chunk_data = response.bytes().await?;
+                client.quota().add(chunk_data.len() as u64)?;
                 let chunk_duration = chunk_start.elapsed();
 
                 // Record stats
@@ -264,15 +266,9 @@ impl AdaptiveDownloader {
 
                 // Update progress
                 if let Some(callback) = progress_callback {
-                    let mut downloaded_guard = downloaded.lock().await;
-                    *downloaded_guard += chunk_data.len() as u64;
-
-                    let mut progress = ProgressInfo::new(url);
-                    progress.total_size = Some(total_size);
+                    let mut progress = progress.lock().await;
                     progress.update(chunk_data.len() as u64, start_time);
-                    progress.downloaded = *downloaded_guard;
-
-                    callback(progress);
+                    callback(progress.clone());
                 }
 
                 Ok::<_, Error>((start, chunk_data))
@@ -283,11 +279,17 @@ impl AdaptiveDownloader {
 
         // Wait for all chunks
         let mut results = Vec::new();
-        for task in tasks {
+        for (index, task) in tasks.into_iter().enumerate() {
             let result = task
                 .await
                 .map_err(|e| Error::ChunkError(format!("Task join error: {e}")))?
-                .map_err(|e| Error::ChunkError(format!("Chunk download failed: {e}")))?;
+                .map_err(|e| {
+                    e.with_context(crate::parallel::chunk_context(
+                        "downloading",
+                        index,
+                        &params.url,
+                    ))
+                })?;
             results.push(result);
         }
 
@@ -314,3 +316,201 @@ impl AdaptiveDownloader {
         variance.sqrt()
     }
 }
+
+/// Completed chunks measured at one request count before deciding on the next
+const SAMPLES_PER_DECISION: usize = 3;
+
+/// Throughput ratio that counts as a real difference between two request counts
+const SIGNIFICANT_GAIN: f64 = 1.1;
+
+/// Direction [`ChunkConcurrency`] is exploring in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Probe {
+    Up,
+    Down,
+    Settled,
+}
+
+/// Picks how many range requests a parallel download keeps in flight
+///
+/// Fed the size and duration of every completed chunk, it climbs: the count
+/// doubles while the total throughput (per-connection throughput times
+/// connections) grows by at least 10%, as on a high-latency link. If the first
+/// step up doesn't pay off the link is saturated, so it halves the count for as
+/// long as that costs less than 10%. A step that doesn't pay off is undone and
+/// the count stays there. Nothing here does I/O, so the policy can be tested
+/// with synthetic samples.
+#[derive(Debug)]
+pub(crate) struct ChunkConcurrency {
+    initial: usize,
+    current: usize,
+    max: usize,
+    probe: Probe,
+    /// Per-connection throughput of chunks started at `current`, bytes/second
+    samples: Vec<f64>,
+    /// Count with the best measured total throughput, and that throughput
+    best: Option<(usize, f64)>,
+}
+
+impl ChunkConcurrency {
+    /// Start at `initial` requests, never exceeding `max`
+    ///
+    /// With `max <= initial` the count stays at `initial`.
+    pub(crate) fn new(initial: usize, max: usize) -> Self {
+        let initial = initial.max(1);
+        Self {
+            initial,
+            current: initial,
+            max: max.max(initial),
+            probe: if max > initial {
+                Probe::Up
+            } else {
+                Probe::Settled
+            },
+            samples: Vec::new(),
+            best: None,
+        }
+    }
+
+    /// Requests to keep in flight now
+    pub(crate) fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Record a chunk of `bytes` fetched in `elapsed` by a request started
+    /// while `started_at` requests were allowed
+    ///
+    /// Returns the new count when it changes. Chunks started under an
+    /// earlier count are ignored.
+    pub(crate) fn record(
+        &mut self,
+        bytes: u64,
+        elapsed: Duration,
+        started_at: usize,
+    ) -> Option<usize> {
+        if self.probe == Probe::Settled || started_at != self.current || elapsed.is_zero() {
+            return None;
+        }
+        // Note: Precision loss acceptable for performance metrics
+        #[allow(clippy::cast_precision_loss)]
+        self.samples.push(bytes as f64 / elapsed.as_secs_f64());
+        if self.samples.len() < SAMPLES_PER_DECISION {
+            return None;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let total =
+            self.samples.iter().sum::<f64>() / (self.samples.len() as f64) * (self.current as f64);
+        self.samples.clear();
+
+        let previous = self.current;
+        let next = match self.best {
+            Some((count, best)) if count != self.current => {
+                let paid_off = if self.current > count {
+                    total >= best * SIGNIFICANT_GAIN
+                } else {
+                    total * SIGNIFICANT_GAIN >= best
+                };
+                if paid_off {
+                    self.best = Some((self.current, total));
+                    self.step()
+                } else if self.probe == Probe::Up && count == self.initial {
+                    // The very first step up didn't help: try fewer instead
+                    self.probe = Probe::Down;
+                    self.current = count;
+                    self.step()
+                } else {
+                    self.probe = Probe::Settled;
+                    count
+                }
+            },
+            _ => {
+                self.best = Some((self.current, total));
+                self.step()
+            },
+        };
+
+        self.current = next;
+        (next != previous).then_some(next)
+    }
+
+    /// Next count in the probing direction, settling at the bounds
+    fn step(&mut self) -> usize {
+        let next = match self.probe {
+            Probe::Up => (self.current * 2).min(self.max),
+            Probe::Down => (self.current / 2).max(1),
+            Probe::Settled => self.current,
+        };
+        if next == self.current {
+            self.probe = Probe::Settled;
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `count` chunks of 1 MB, each taking `secs`, started at the current count
+    fn feed(concurrency: &mut ChunkConcurrency, count: usize, secs: f64) -> Option<usize> {
+        let mut changed = None;
+        for _ in 0..count {
+            let current = concurrency.current();
+            changed = concurrency
+                .record(1_000_000, Duration::from_secs_f64(secs), current)
+                .or(changed);
+        }
+        changed
+    }
+
+    #[test]
+    fn test_grows_while_throughput_scales() {
+        // High latency: every connection gets 1 MB/s however many there are
+        let mut concurrency = ChunkConcurrency::new(8, 32);
+        assert_eq!(feed(&mut concurrency, 3, 1.0), Some(16));
+        assert_eq!(feed(&mut concurrency, 3, 1.0), Some(32));
+        assert_eq!(feed(&mut concurrency, 3, 1.0), None);
+        assert_eq!(concurrency.current(), 32);
+    }
+
+    #[test]
+    fn test_shrinks_on_saturated_link() {
+        // 8 MB/s in total, shared by all connections
+        let mut concurrency = ChunkConcurrency::new(8, 32);
+        assert_eq!(feed(&mut concurrency, 3, 1.0), Some(16));
+        // Twice the connections, each half as fast: back down below 8
+        assert_eq!(feed(&mut concurrency, 3, 2.0), Some(4));
+        assert_eq!(feed(&mut concurrency, 3, 0.5), Some(2));
+        assert_eq!(feed(&mut concurrency, 3, 0.25), Some(1));
+        assert_eq!(concurrency.current(), 1);
+    }
+
+    #[test]
+    fn test_undoes_step_that_costs_throughput() {
+        let mut concurrency = ChunkConcurrency::new(8, 32);
+        assert_eq!(feed(&mut concurrency, 3, 1.0), Some(16));
+        assert_eq!(feed(&mut concurrency, 3, 1.0), Some(32));
+        // 32 connections are no faster in total than 16: settle at 16
+        assert_eq!(feed(&mut concurrency, 3, 2.0), Some(16));
+        assert_eq!(feed(&mut concurrency, 6, 0.1), None);
+        assert_eq!(concurrency.current(), 16);
+    }
+
+    #[test]
+    fn test_ignores_chunks_from_earlier_counts() {
+        let mut concurrency = ChunkConcurrency::new(4, 8);
+        assert_eq!(feed(&mut concurrency, 3, 1.0), Some(8));
+        for _ in 0..10 {
+            assert_eq!(concurrency.record(1, Duration::from_secs(100), 4), None);
+        }
+        assert_eq!(concurrency.current(), 8);
+    }
+
+    #[test]
+    fn test_fixed_when_max_not_above_initial() {
+        let mut concurrency = ChunkConcurrency::new(8, 8);
+        assert_eq!(feed(&mut concurrency, 9, 1.0), None);
+        assert_eq!(concurrency.current(), 8);
+    }
+}
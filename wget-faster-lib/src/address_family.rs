@@ -0,0 +1,176 @@
+/// IP address family selection (`DownloadConfig::address_family`)
+///
+/// With a family set, host names are resolved by [`FamilyResolver`], which
+/// drops addresses of the other family (`V4Only`, `V6Only`) or lists the
+/// preferred family first (`PreferV4`, `PreferV6`), so connections try it
+/// first. A URL whose host is an IP literal of an excluded family, or a name
+/// with no address of the required family, fails with `Error::ConfigError`
+/// before any connection is attempted.
+use crate::{Error, Result};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Serialize;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+/// Which IP address family connections use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFamily {
+    /// Only IPv4 addresses (wget `-4`)
+    V4Only,
+
+    /// Only IPv6 addresses (wget `-6`)
+    V6Only,
+
+    /// IPv4 addresses first, then IPv6 (`--prefer-family=IPv4`)
+    PreferV4,
+
+    /// IPv6 addresses first, then IPv4 (`--prefer-family=IPv6`)
+    PreferV6,
+}
+
+impl AddressFamily {
+    /// Whether connections may use `ip`
+    pub fn allows(self, ip: IpAddr) -> bool {
+        match self {
+            Self::V4Only => ip.is_ipv4(),
+            Self::V6Only => ip.is_ipv6(),
+            Self::PreferV4 | Self::PreferV6 => true,
+        }
+    }
+
+    /// Resolved addresses filtered and ordered for this family
+    ///
+    /// The order among addresses of one family is kept.
+    pub(crate) fn arrange(self, addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+        let mut addrs: Vec<SocketAddr> = addrs
+            .into_iter()
+            .filter(|addr| self.allows(addr.ip()))
+            .collect();
+        match self {
+            Self::PreferV4 => addrs.sort_by_key(|addr| !addr.is_ipv4()),
+            Self::PreferV6 => addrs.sort_by_key(|addr| !addr.is_ipv6()),
+            Self::V4Only | Self::V6Only => {},
+        }
+        addrs
+    }
+
+    /// Name of the family addresses must have, for errors
+    fn required(self) -> &'static str {
+        match self {
+            Self::V4Only | Self::PreferV4 => "IPv4",
+            Self::V6Only | Self::PreferV6 => "IPv6",
+        }
+    }
+}
+
+/// Fail for a URL whose host is an IP literal `family` excludes
+///
+/// Such hosts are never resolved, so [`FamilyResolver`] doesn't see them.
+pub(crate) fn check_url(family: Option<AddressFamily>, url: &url::Url) -> Result<()> {
+    let (Some(family), Some(host)) = (family, url.host()) else {
+        return Ok(());
+    };
+    let ip = match host {
+        url::Host::Ipv4(ip) => IpAddr::V4(ip),
+        url::Host::Ipv6(ip) => IpAddr::V6(ip),
+        url::Host::Domain(_) => return Ok(()),
+    };
+    if family.allows(ip) {
+        return Ok(());
+    }
+    Err(Error::ConfigError(format!(
+        "{ip} is not an {} address (address family {family:?})",
+        family.required()
+    )))
+}
+
+/// `ConfigError` for a request that failed because its host has no address of the family
+pub(crate) fn no_address_error(error: &reqwest::Error) -> Option<Error> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if let Some(no_address) = error.downcast_ref::<NoAddressOfFamily>() {
+            return Some(Error::ConfigError(no_address.to_string()));
+        }
+        source = error.source();
+    }
+    None
+}
+
+/// DNS resolver keeping the addresses of one family, or listing them first
+#[derive(Debug)]
+pub(crate) struct FamilyResolver(pub(crate) AddressFamily);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let resolved = tokio::net::lookup_host((host.as_str(), 0)).await?;
+            let addrs = family.arrange(resolved);
+            if addrs.is_empty() {
+                return Err(Box::new(NoAddressOfFamily { host, family }) as _);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// A host name resolved, but to no address of the required family
+#[derive(Debug)]
+struct NoAddressOfFamily {
+    host: String,
+    family: AddressFamily,
+}
+
+impl fmt::Display for NoAddressOfFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} has no {} address (address family {:?})",
+            self.host,
+            self.family.required(),
+            self.family
+        )
+    }
+}
+
+impl std::error::Error for NoAddressOfFamily {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs() -> Vec<SocketAddr> {
+        ["[::1]:0", "127.0.0.1:0", "[::2]:0", "127.0.0.2:0"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_arrange() {
+        let hosts = |family: AddressFamily| -> Vec<String> {
+            family
+                .arrange(addrs())
+                .iter()
+                .map(|addr| addr.ip().to_string())
+                .collect()
+        };
+        assert_eq!(hosts(AddressFamily::V4Only), ["127.0.0.1", "127.0.0.2"]);
+        assert_eq!(hosts(AddressFamily::V6Only), ["::1", "::2"]);
+        assert_eq!(hosts(AddressFamily::PreferV4), ["127.0.0.1", "127.0.0.2", "::1", "::2"]);
+        assert_eq!(hosts(AddressFamily::PreferV6), ["::1", "::2", "127.0.0.1", "127.0.0.2"]);
+    }
+
+    #[test]
+    fn test_check_url() {
+        let url = |s: &str| url::Url::parse(s).unwrap();
+        let v6_only = Some(AddressFamily::V6Only);
+        assert!(check_url(v6_only, &url("http://127.0.0.1/")).is_err());
+        assert!(check_url(v6_only, &url("http://[::1]/")).is_ok());
+        assert!(check_url(v6_only, &url("http://example.com/")).is_ok());
+        assert!(check_url(Some(AddressFamily::PreferV6), &url("http://127.0.0.1/")).is_ok());
+        assert!(check_url(None, &url("http://127.0.0.1/")).is_ok());
+    }
+}
@@ -0,0 +1,419 @@
+/// Archive written by a crawl instead of a directory tree (`RecursiveConfig::archive_output`)
+///
+/// Files are staged in a temporary directory while they are fetched and
+/// streamed into the archive right after, so the staging directory never
+/// holds more than the files still waiting for link conversion. Tar entries
+/// are POSIX ustar (GNU long-name records for names over 100 bytes); zip
+/// entries are deflated and have no ZIP64 extensions.
+use crate::{Error, Result};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::{Compression, Crc};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+
+/// Size of a tar header and of the blocks tar content is padded to
+const BLOCK: usize = 512;
+
+/// Size of the reads from staged files, and of the compressed output kept before it's written
+const CHUNK: usize = 64 * 1024;
+
+/// Zip version needed to extract (2.0: deflate)
+const ZIP_VERSION: u16 = 20;
+
+/// Zip flags: sizes and CRC follow the data (bit 3), UTF-8 names (bit 11)
+const ZIP_FLAGS: u16 = 0x0808;
+
+/// Zip compression method: deflate
+const ZIP_DEFLATE: u16 = 8;
+
+/// Format of the archive a crawl writes with `RecursiveConfig::archive_output`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    /// Uncompressed tar
+    Tar,
+
+    /// Gzip-compressed tar
+    TarGz,
+
+    /// Zip with deflated entries (at most 65535 entries, each and the whole
+    /// archive under 2^32 bytes)
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Name of the archive in the output directory when no path is given
+    pub fn default_file_name(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "site.tar",
+            ArchiveFormat::TarGz => "site.tar.gz",
+            ArchiveFormat::Zip => "site.zip",
+        }
+    }
+}
+
+/// Central directory record of a zip entry
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    compressed: u32,
+    size: u32,
+    offset: u32,
+    modified: (u16, u16),
+}
+
+/// Writes the archive of a crawl from the files staged under `root`
+pub(crate) struct ArchiveWriter {
+    format: ArchiveFormat,
+    file: BufWriter<File>,
+    gzip: Option<GzEncoder<Vec<u8>>>, // Gzip stream of a `TarGz` archive, drained into `file`
+    root: PathBuf,                    // Staging directory; entry names are relative to it
+    offset: u64,                      // Bytes of the uncompressed archive so far
+    zip_entries: Vec<ZipEntry>,
+}
+
+impl ArchiveWriter {
+    /// Start a new archive at `path`, replacing any earlier one
+    pub(crate) async fn create(path: &Path, format: ArchiveFormat, root: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let file = File::create(path).await?;
+        Ok(Self {
+            format,
+            file: BufWriter::new(file),
+            gzip: (format == ArchiveFormat::TarGz)
+                .then(|| GzEncoder::new(Vec::new(), Compression::default())),
+            root: root.to_path_buf(),
+            offset: 0,
+            zip_entries: Vec::new(),
+        })
+    }
+
+    /// Entry name of the staged file at `path`
+    pub(crate) fn entry_path(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.root).unwrap_or(path).to_path_buf()
+    }
+
+    /// Add the staged file at `path`, dated by its modification time, and remove it
+    pub(crate) async fn append_file(&mut self, path: &Path) -> Result<()> {
+        let name = self
+            .entry_path(path)
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let metadata = tokio::fs::metadata(path).await?;
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut file = File::open(path).await?;
+        match self.format {
+            ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+                self.append_tar(&name, metadata.len(), modified, &mut file)
+                    .await?;
+            },
+            ArchiveFormat::Zip => self.append_zip(name, modified, &mut file).await?,
+        }
+        drop(file);
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    async fn append_tar(
+        &mut self,
+        name: &str,
+        size: u64,
+        modified: SystemTime,
+        file: &mut File,
+    ) -> Result<()> {
+        if name.len() > 100 {
+            // GNU long name: the full name is the content of a record before the entry
+            let mut long_name = name.as_bytes().to_vec();
+            long_name.push(0);
+            let len = long_name.len() as u64;
+            self.write(&tar_header("././@LongLink", len, SystemTime::UNIX_EPOCH, b'L'))
+                .await?;
+            self.write(&long_name).await?;
+            self.pad(len).await?;
+        }
+        self.write(&tar_header(name, size, modified, b'0')).await?;
+
+        let mut buf = vec![0; CHUNK];
+        let mut copied = 0u64;
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            copied += read as u64;
+            self.write(&buf[..read]).await?;
+        }
+        if copied != size {
+            return Err(Error::WriteError(format!("{name} changed while it was archived")));
+        }
+        self.pad(size).await
+    }
+
+    async fn append_zip(
+        &mut self,
+        name: String,
+        modified: SystemTime,
+        file: &mut File,
+    ) -> Result<()> {
+        let offset = zip32(self.offset, &name)?;
+        let modified = dos_time(modified);
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| Error::WriteError(format!("zip entry name too long: {name}")))?;
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        put32(&mut header, 0x0403_4b50);
+        for field in [ZIP_VERSION, ZIP_FLAGS, ZIP_DEFLATE, modified.0, modified.1] {
+            put16(&mut header, field);
+        }
+        // CRC and sizes are in the data descriptor
+        header.extend_from_slice(&[0; 12]);
+        put16(&mut header, name_len);
+        put16(&mut header, 0);
+        header.extend_from_slice(name.as_bytes());
+        self.write(&header).await?;
+
+        let mut crc = Crc::new();
+        let mut deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+        let mut buf = vec![0; CHUNK];
+        let (mut size, mut compressed) = (0u64, 0u64);
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            size += read as u64;
+            crc.update(&buf[..read]);
+            deflate.write_all(&buf[..read])?;
+            if deflate.get_ref().len() >= CHUNK {
+                let out = std::mem::take(deflate.get_mut());
+                compressed += out.len() as u64;
+                self.write(&out).await?;
+            }
+        }
+        let out = deflate.finish()?;
+        compressed += out.len() as u64;
+        self.write(&out).await?;
+
+        let entry = ZipEntry {
+            crc: crc.sum(),
+            compressed: zip32(compressed, &name)?,
+            size: zip32(size, &name)?,
+            offset,
+            modified,
+            name,
+        };
+        let mut descriptor = Vec::with_capacity(16);
+        for field in [0x0807_4b50, entry.crc, entry.compressed, entry.size] {
+            put32(&mut descriptor, field);
+        }
+        self.write(&descriptor).await?;
+        self.zip_entries.push(entry);
+        Ok(())
+    }
+
+    /// Add the files still staged (in path order) and write the end of the archive
+    pub(crate) async fn finish(mut self) -> Result<()> {
+        let mut staged = Vec::new();
+        let mut dirs = vec![self.root.clone()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    dirs.push(entry.path());
+                } else {
+                    staged.push(entry.path());
+                }
+            }
+        }
+        staged.sort();
+        for path in staged {
+            self.append_file(&path).await?;
+        }
+
+        match self.format {
+            ArchiveFormat::Tar | ArchiveFormat::TarGz => self.write(&[0; 2 * BLOCK]).await?,
+            ArchiveFormat::Zip => self.write_zip_directory().await?,
+        }
+        if let Some(gzip) = self.gzip.take() {
+            let rest = gzip.finish()?;
+            self.file.write_all(&rest).await?;
+        }
+        self.file.flush().await?;
+        tracing::info!(format = ?self.format, bytes = self.offset, "Wrote crawl archive");
+        Ok(())
+    }
+
+    async fn write_zip_directory(&mut self) -> Result<()> {
+        let start = zip32(self.offset, "central directory")?;
+        let count = u16::try_from(self.zip_entries.len()).map_err(|_| {
+            Error::WriteError("zip archives of over 65535 files need ZIP64".to_string())
+        })?;
+        let mut directory = Vec::new();
+        for entry in &self.zip_entries {
+            put32(&mut directory, 0x0201_4b50);
+            // Made by Unix (for the permissions in the external attributes)
+            put16(&mut directory, (3 << 8) | ZIP_VERSION);
+            for field in [
+                ZIP_VERSION,
+                ZIP_FLAGS,
+                ZIP_DEFLATE,
+                entry.modified.0,
+                entry.modified.1,
+            ] {
+                put16(&mut directory, field);
+            }
+            for field in [entry.crc, entry.compressed, entry.size] {
+                put32(&mut directory, field);
+            }
+            // Name, extra field, comment, disk number and internal attributes
+            put16(&mut directory, entry.name.len() as u16);
+            directory.extend_from_slice(&[0; 8]);
+            put32(&mut directory, 0o100_644 << 16);
+            put32(&mut directory, entry.offset);
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        self.write(&directory).await?;
+
+        let mut end = Vec::with_capacity(22);
+        put32(&mut end, 0x0605_4b50);
+        for field in [0, 0, count, count] {
+            put16(&mut end, field);
+        }
+        put32(&mut end, zip32(directory.len() as u64, "central directory")?);
+        put32(&mut end, start);
+        put16(&mut end, 0);
+        self.write(&end).await
+    }
+
+    /// Append to the archive stream (compressed for `TarGz`)
+    async fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.offset += data.len() as u64;
+        if let Some(gzip) = self.gzip.as_mut() {
+            gzip.write_all(data)?;
+            if gzip.get_ref().len() >= CHUNK {
+                let compressed = std::mem::take(gzip.get_mut());
+                self.file.write_all(&compressed).await?;
+            }
+            return Ok(());
+        }
+        self.file.write_all(data).await?;
+        Ok(())
+    }
+
+    /// Pad tar content of `len` bytes to a whole block
+    async fn pad(&mut self, len: u64) -> Result<()> {
+        let rest = (len % BLOCK as u64) as usize;
+        if rest == 0 {
+            return Ok(());
+        }
+        self.write(&[0; BLOCK][rest..]).await
+    }
+}
+
+/// Ustar header of a regular file (`kind` `b'0'`) or GNU long name (`b'L'`)
+fn tar_header(name: &str, size: u64, modified: SystemTime, kind: u8) -> [u8; BLOCK] {
+    let mut header = [0; BLOCK];
+    let name = &name.as_bytes()[..name.len().min(100)];
+    header[..name.len()].copy_from_slice(name);
+    tar_number(&mut header[100..108], 0o644);
+    tar_number(&mut header[108..116], 0);
+    tar_number(&mut header[116..124], 0);
+    tar_number(&mut header[124..136], size);
+    let mtime = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    tar_number(&mut header[136..148], mtime);
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field as spaces
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    header
+}
+
+/// Write `value` as NUL-terminated octal, or base-256 when it doesn't fit (sizes from 2^33 bytes)
+fn tar_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if digits * 3 >= 64 || value < 1 << (digits * 3) {
+        field[..digits].copy_from_slice(format!("{value:0digits$o}").as_bytes());
+    } else {
+        field.fill(0);
+        field[0] = 0x80;
+        let at = field.len() - 8;
+        field[at..].copy_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// MS-DOS (time, date) of `time` in UTC, clamped to the years 1980 to 2107
+fn dos_time(time: SystemTime) -> (u16, u16) {
+    let time: DateTime<Utc> = time.into();
+    if time.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let year = (time.year() - 1980).min(127) as u16;
+    let date = (year << 9) | ((time.month() as u16) << 5) | time.day() as u16;
+    let clock =
+        ((time.hour() as u16) << 11) | ((time.minute() as u16) << 5) | (time.second() / 2) as u16;
+    (clock, date)
+}
+
+/// `value` as a zip size or offset, which without ZIP64 must fit in 32 bits
+fn zip32(value: u64, what: &str) -> Result<u32> {
+    u32::try_from(value)
+        .map_err(|_| Error::WriteError(format!("zip archive too large at {what} (no ZIP64)")))
+}
+
+fn put16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_tar_header_fields() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_483_228_800);
+        let header = tar_header("a/b.txt", 12, modified, b'0');
+
+        assert_eq!(&header[..8], b"a/b.txt\0");
+        assert_eq!(&header[124..136], b"00000000014\0");
+        assert_eq!(&header[136..148], b"13032043200\0");
+        let stored = std::str::from_utf8(&header[148..154]).unwrap();
+        let mut blank = header;
+        blank[148..156].fill(b' ');
+        let sum: u32 = blank.iter().map(|&byte| u32::from(byte)).sum();
+        assert_eq!(u32::from_str_radix(stored, 8).unwrap(), sum);
+
+        // 8 GiB no longer fits in 11 octal digits
+        let mut field = [0; 12];
+        tar_number(&mut field, 8 << 30);
+        assert_eq!(field[0], 0x80);
+        assert_eq!(&field[4..], &(8u64 << 30).to_be_bytes());
+    }
+
+    #[test]
+    fn test_dos_time() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_483_228_800 + 3661);
+        // 2017-01-01 01:01:01
+        assert_eq!(dos_time(modified), ((1 << 11) | (1 << 5), (37 << 9) | (1 << 5) | 1));
+        assert_eq!(dos_time(SystemTime::UNIX_EPOCH), (0, (1 << 5) | 1));
+    }
+}
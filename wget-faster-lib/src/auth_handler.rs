@@ -4,7 +4,83 @@
 /// - Credential resolution (configured auth + .netrc fallback)
 /// - Authentication challenge handling (401/407)
 /// - Retry logic with credentials
+/// - Remembering which credentials worked per host (preemptive auth)
 use crate::{AuthConfig, DownloadConfig};
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Most hosts whose working credentials are remembered for preemptive auth
+pub const MAX_AUTHENTICATED_HOSTS: usize = 1024;
+
+/// Async function returning credentials for a URL that answered 401
+///
+/// Called again when previously accepted credentials are rejected, so it can
+/// hand out rotated credentials.
+pub type CredentialProviderFn =
+    Arc<dyn Fn(&str) -> BoxFuture<'static, Option<AuthConfig>> + Send + Sync>;
+
+/// Supplies credentials for 401 challenges (see `DownloadConfig::credential_provider`)
+#[derive(Clone)]
+pub struct CredentialProvider(pub CredentialProviderFn);
+
+impl fmt::Debug for CredentialProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CredentialProvider(..)")
+    }
+}
+
+/// Credentials that were accepted, per host, evicting the least recently used
+#[derive(Debug)]
+pub(crate) struct AuthenticatedHosts {
+    capacity: usize,
+    /// Host -> (credentials, last use)
+    entries: HashMap<String, (AuthConfig, u64)>,
+    clock: u64,
+}
+
+impl AuthenticatedHosts {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub(crate) fn contains(&self, host: &str) -> bool {
+        self.entries.contains_key(host)
+    }
+
+    /// Credentials remembered for `host`, marking it as recently used
+    pub(crate) fn get(&mut self, host: &str) -> Option<AuthConfig> {
+        self.clock += 1;
+        let (auth, last_used) = self.entries.get_mut(host)?;
+        *last_used = self.clock;
+        Some(auth.clone())
+    }
+
+    pub(crate) fn insert(&mut self, host: String, auth: AuthConfig) {
+        self.clock += 1;
+        if !self.entries.contains_key(&host) && self.entries.len() >= self.capacity {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(host, _)| host.clone())
+            {
+                tracing::debug!(host = %oldest, "Forgetting least recently used authenticated host");
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(host, (auth, self.clock));
+    }
+
+    pub(crate) fn remove(&mut self, host: &str) -> bool {
+        self.entries.remove(host).is_some()
+    }
+}
 
 /// Get authentication credentials for a URL
 ///
@@ -51,6 +127,46 @@ pub fn get_credentials(url: &str, config: &DownloadConfig) -> Option<AuthConfig>
     None
 }
 
+/// Get credentials to answer a 401 challenge, fetched anew on every call
+///
+/// Asks the configured `credential_provider` first, then falls back to
+/// [`get_credentials`] (configured auth, then a fresh read of .netrc).
+pub(crate) async fn challenge_credentials(
+    url: &str,
+    config: &DownloadConfig,
+) -> Option<AuthConfig> {
+    if let Some(CredentialProvider(ref provider)) = config.credential_provider {
+        if let Some(auth) = provider(url).await {
+            tracing::debug!(username = %auth.username, "Using credentials from credential provider");
+            return Some(auth);
+        }
+    }
+    get_credentials(url, config)
+}
+
+/// Get credentials for a proxy that answered 407
+///
+/// Tries the configured proxy auth first, then the .netrc entry for the
+/// proxy host.
+pub fn get_proxy_credentials(proxy_url: &str, config: &DownloadConfig) -> Option<(String, String)> {
+    if let Some(auth) = config.proxy.as_ref().and_then(|p| p.auth.clone()) {
+        return Some(auth);
+    }
+
+    let host = url::Url::parse(proxy_url).ok()?.host_str()?.to_string();
+    match crate::netrc::Netrc::from_default_location() {
+        Ok(Some(netrc)) => netrc.get(&host).map(|entry| {
+            tracing::debug!(host = %host, username = %entry.username, "Found .netrc entry for proxy");
+            (entry.username, entry.password)
+        }),
+        Ok(None) => None,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to read .netrc file");
+            None
+        },
+    }
+}
+
 /// Check if a status code indicates an authentication challenge
 ///
 /// # Arguments
@@ -131,6 +247,57 @@ mod tests {
         assert_eq!(creds.unwrap().username, "testuser");
     }
 
+    fn auth(username: &str) -> AuthConfig {
+        AuthConfig {
+            username: username.to_string(),
+            password: "secret".to_string(),
+            auth_type: crate::AuthType::Basic,
+        }
+    }
+
+    #[test]
+    fn test_authenticated_hosts_lru_eviction() {
+        let mut hosts = AuthenticatedHosts::new(2);
+        hosts.insert("a.example".to_string(), auth("a"));
+        hosts.insert("b.example".to_string(), auth("b"));
+
+        // Using a.example makes b.example the least recently used
+        assert_eq!(hosts.get("a.example").unwrap().username, "a");
+        hosts.insert("c.example".to_string(), auth("c"));
+        assert!(hosts.contains("a.example"));
+        assert!(!hosts.contains("b.example"));
+        assert!(hosts.contains("c.example"));
+
+        // Replacing the credentials of a known host evicts nothing
+        hosts.insert("a.example".to_string(), auth("a2"));
+        assert_eq!(hosts.get("a.example").unwrap().username, "a2");
+        assert!(hosts.contains("c.example"));
+
+        assert!(hosts.remove("c.example"));
+        assert!(!hosts.remove("c.example"));
+    }
+
+    #[tokio::test]
+    async fn test_challenge_credentials_prefers_provider() {
+        let mut config = DownloadConfig::default();
+        config.auth = Some(auth("configured"));
+        config.credential_provider = Some(CredentialProvider(Arc::new(|url: &str| {
+            let username = if url.contains("rotated") {
+                Some(auth("provided"))
+            } else {
+                None
+            };
+            Box::pin(async move { username })
+        })));
+
+        let creds = challenge_credentials("https://rotated.example/", &config).await;
+        assert_eq!(creds.unwrap().username, "provided");
+
+        // Provider has nothing: fall back to the configured credentials
+        let creds = challenge_credentials("https://other.example/", &config).await;
+        assert_eq!(creds.unwrap().username, "configured");
+    }
+
     #[test]
     fn test_get_credentials_without_auth() {
         let config = DownloadConfig::default();
@@ -0,0 +1,194 @@
+/// Downloading a list of URLs concurrently
+///
+/// Downloads share the per-host limits of link checks (`MAX_CHECKS_PER_HOST`
+/// at once and `wait_time` between starts on one host), and all of them
+/// count against the one `quota`. With a [`BatchState`], URLs an earlier run
+/// completed are skipped and every start, completion and failure is recorded.
+///
+/// File outputs are reserved in the downloader's [`NameRegistry`] while they
+/// download, so two requests for the same file (or, with
+/// `content_disposition`, two servers suggesting the same name) don't write
+/// over each other: the later one gets a `.1`, `.2`, ... suffix.
+use crate::link_check::for_each_per_host;
+use crate::{
+    BatchState, DownloadResult, Downloader, NameRegistry, Output, ProgressCallback, Result,
+};
+use std::path::{Path, PathBuf};
+
+/// One URL of the batch and where it goes
+struct Request {
+    url: String,
+    output: Output,
+}
+
+impl AsRef<str> for Request {
+    fn as_ref(&self) -> &str {
+        &self.url
+    }
+}
+
+pub(crate) async fn download_many(
+    downloader: &Downloader,
+    requests: Vec<(String, Output)>,
+    concurrency: usize,
+    progress: Option<ProgressCallback>,
+) -> Vec<Result<DownloadResult>> {
+    run(downloader, requests, concurrency, progress, None).await
+}
+
+/// Like [`download_many`], skipping the URLs `state` has as completed (`Ok(None)`)
+pub(crate) async fn download_many_with_state(
+    downloader: &Downloader,
+    requests: Vec<(String, Output)>,
+    concurrency: usize,
+    progress: Option<ProgressCallback>,
+    state: &BatchState,
+) -> Vec<Result<Option<DownloadResult>>> {
+    // Completed URLs are left out before scheduling, so they don't wait for a turn
+    let mut results: Vec<Option<Result<Option<DownloadResult>>>> = requests
+        .iter()
+        .map(|(url, _)| state.completed(url).map(|_| Ok(None)))
+        .collect();
+    let (indices, to_download): (Vec<_>, Vec<_>) = requests
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| results[*i].is_none())
+        .unzip();
+
+    let finished = run(downloader, to_download, concurrency, progress, Some(state)).await;
+    for (i, result) in indices.into_iter().zip(finished) {
+        results[i] = Some(result.map(Some));
+    }
+    results.into_iter().flatten().collect()
+}
+
+async fn run(
+    downloader: &Downloader,
+    requests: Vec<(String, Output)>,
+    concurrency: usize,
+    progress: Option<ProgressCallback>,
+    state: Option<&BatchState>,
+) -> Vec<Result<DownloadResult>> {
+    let config = downloader.get_client().config();
+    let requests = requests
+        .into_iter()
+        .map(|(url, output)| Request { url, output })
+        .collect();
+
+    let results = for_each_per_host(
+        requests,
+        concurrency,
+        config.wait_time,
+        config.random_wait,
+        None,
+        |request| download_one(downloader, request, progress.clone(), state),
+    )
+    .await;
+    results.into_iter().flatten().collect()
+}
+
+/// Download one request of the batch into its reserved output
+async fn download_one(
+    downloader: &Downloader,
+    request: Request,
+    progress: Option<ProgressCallback>,
+    state: Option<&BatchState>,
+) -> Result<DownloadResult> {
+    // Neither probed nor recorded as started (or failed) once the quota is used up
+    downloader.get_client().quota().check()?;
+
+    let resume_path = state.and_then(|s| s.resume_path(&request.url));
+    let output = reserve_output(downloader, &request.url, request.output, resume_path).await?;
+    let path = match output {
+        Output::File(ref path) => Some(path.clone()),
+        _ => None,
+    };
+    let _reservation = Reservation {
+        names: downloader.name_registry(),
+        path: path.as_deref(),
+    };
+
+    let Some(state) = state else {
+        return downloader.download(&request.url, output, progress).await;
+    };
+    record(state.record_started(&request.url, path.as_deref()).await);
+    let result = downloader.download(&request.url, output, progress).await;
+    record_outcome(state, &request.url, path.clone(), &result).await;
+    result
+}
+
+/// Claim the file `output` is saved to in the session's name registry
+///
+/// A file an interrupted run left (`resume_path`) is continued. Otherwise,
+/// with `content_disposition`, the name the server suggests replaces the
+/// requested file name, keeping its directory, and an existing file of that
+/// name is numbered around like GNU wget does.
+async fn reserve_output(
+    downloader: &Downloader,
+    url: &str,
+    output: Output,
+    resume_path: Option<&Path>,
+) -> Result<Output> {
+    let Output::File(path) = output else {
+        return Ok(output);
+    };
+    let names = downloader.name_registry();
+    if let Some(resume_path) = resume_path {
+        return Ok(Output::File(names.reserve(url, resume_path, false)?));
+    }
+
+    let client = downloader.get_client();
+    if client.config().content_disposition {
+        let metadata = client.get_metadata(url).await?;
+        if let Some(name) = metadata
+            .content_disposition
+            .as_deref()
+            .and_then(crate::naming::content_disposition_filename)
+        {
+            return Ok(Output::File(names.reserve(url, &path.with_file_name(name), true)?));
+        }
+    }
+    Ok(Output::File(names.reserve(url, &path, false)?))
+}
+
+/// Releases a reserved output name once its download is over, however it
+/// ended (even when the batch is dropped mid-download)
+struct Reservation<'a> {
+    names: &'a NameRegistry,
+    path: Option<&'a Path>,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Some(path) = self.path {
+            self.names.release(path);
+        }
+    }
+}
+
+/// Record how the download of `url` into `path` ended
+async fn record_outcome(
+    state: &BatchState,
+    url: &str,
+    path: Option<PathBuf>,
+    result: &Result<DownloadResult>,
+) {
+    let recorded = match result {
+        Ok(result) => {
+            let path = result.data.file_path.clone().or(path);
+            let size = result.data.total_bytes;
+            state
+                .record_complete(url, path.as_deref(), size, result.checksum.as_ref())
+                .await
+        },
+        Err(e) => state.record_failed(url, path.as_deref(), e).await,
+    };
+    record(recorded);
+}
+
+/// A state file that can't be written only costs work on the next run
+fn record(recorded: Result<()>) {
+    if let Err(e) = recorded {
+        tracing::warn!(error = %e, "Cannot update batch state");
+    }
+}
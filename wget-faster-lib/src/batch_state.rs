@@ -0,0 +1,293 @@
+/// Progress of a multi-URL download kept across runs (`--batch-state`)
+///
+/// The state file has one JSON object per line, appended as each URL starts,
+/// completes or fails, so a crash loses at most the line being written (a
+/// torn final line is dropped when the file is reopened). The last line for
+/// a URL is its state: a completed URL whose file is still there is skipped
+/// without any request, and a started or failed one is downloaded again into
+/// the path it was given, which resumes a partial file.
+/// [`BatchState::compact`] rewrites the file with only those last lines.
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// How far a URL of the batch got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    /// Download started but didn't finish
+    Started,
+
+    /// Downloaded completely
+    Complete,
+
+    /// Download failed
+    Failed,
+}
+
+/// One line of the state file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchEntry {
+    /// Requested URL
+    pub url: String,
+
+    /// How far the download got
+    pub status: BatchStatus,
+
+    /// File the URL is saved to (`None` when it isn't saved to a file)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+
+    /// Size of the file in bytes, for complete downloads
+    #[serde(default)]
+    pub size: u64,
+
+    /// Digest of the file as `algorithm:hex`, for complete downloads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+
+    /// Why the download failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchEntry {
+    fn new(url: &str, status: BatchStatus, path: Option<&Path>) -> Self {
+        Self {
+            url: url.to_string(),
+            status,
+            path: path.map(Path::to_path_buf),
+            size: 0,
+            checksum: None,
+            error: None,
+        }
+    }
+}
+
+/// State file of a batch download, opened for appending
+#[derive(Debug)]
+pub struct BatchState {
+    path: PathBuf,
+
+    /// Last entry of each URL when the file was opened
+    previous: HashMap<String, BatchEntry>,
+
+    file: tokio::sync::Mutex<tokio::fs::File>,
+}
+
+impl BatchState {
+    /// Open (or create) the state file at `path`
+    ///
+    /// A final line without a newline, left by a crash mid-write, is dropped.
+    /// Any other line that isn't a valid entry is an error.
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let (entries, valid_len) = parse_entries(&bytes, &path)?;
+
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        if valid_len < bytes.len() {
+            file.set_len(valid_len as u64).await?;
+        }
+
+        let previous = entries.into_iter().map(|e| (e.url.clone(), e)).collect();
+        Ok(Self {
+            path,
+            previous,
+            file: tokio::sync::Mutex::new(file),
+        })
+    }
+
+    /// Last entry of `url` from earlier runs
+    pub fn entry(&self, url: &str) -> Option<&BatchEntry> {
+        self.previous.get(url)
+    }
+
+    /// Entry of `url` if an earlier run completed it and its file still has the recorded size
+    pub fn completed(&self, url: &str) -> Option<&BatchEntry> {
+        self.entry(url)
+            .filter(|e| e.status == BatchStatus::Complete)
+            .filter(|e| {
+                e.path
+                    .as_ref()
+                    .is_none_or(|path| std::fs::metadata(path).is_ok_and(|m| m.len() == e.size))
+            })
+    }
+
+    /// File an earlier, unfinished download of `url` was saved to
+    ///
+    /// Downloading into it again continues the partial file.
+    pub fn resume_path(&self, url: &str) -> Option<&Path> {
+        if self.completed(url).is_some() {
+            return None;
+        }
+        self.entry(url)?.path.as_deref()
+    }
+
+    /// Record that `url` started downloading into `path`
+    pub async fn record_started(&self, url: &str, path: Option<&Path>) -> Result<()> {
+        self.append(&BatchEntry::new(url, BatchStatus::Started, path))
+            .await
+    }
+
+    /// Record that `url` was downloaded completely into `path`
+    ///
+    /// The size is that of the file on disk, so a resumed download records the whole file.
+    pub async fn record_complete(
+        &self,
+        url: &str,
+        path: Option<&Path>,
+        size: u64,
+        checksum: Option<&crate::Checksum>,
+    ) -> Result<()> {
+        let size = match path {
+            Some(path) => tokio::fs::metadata(path).await?.len(),
+            None => size,
+        };
+        self.append(&BatchEntry {
+            size,
+            checksum: checksum.map(ToString::to_string),
+            ..BatchEntry::new(url, BatchStatus::Complete, path)
+        })
+        .await
+    }
+
+    /// Record that downloading `url` into `path` failed
+    pub async fn record_failed(
+        &self,
+        url: &str,
+        path: Option<&Path>,
+        error: &dyn std::fmt::Display,
+    ) -> Result<()> {
+        self.append(&BatchEntry {
+            error: Some(error.to_string()),
+            ..BatchEntry::new(url, BatchStatus::Failed, path)
+        })
+        .await
+    }
+
+    /// Rewrite the file with only the last entry of each URL
+    ///
+    /// Meant for a clean exit. URLs keep the order they were first recorded in.
+    pub async fn compact(&self) -> Result<()> {
+        let mut file = self.file.lock().await;
+        let bytes = tokio::fs::read(&self.path).await?;
+        let (entries, _) = parse_entries(&bytes, &self.path)?;
+
+        let mut order = Vec::new();
+        let mut last = HashMap::new();
+        for entry in entries {
+            if !last.contains_key(&entry.url) {
+                order.push(entry.url.clone());
+            }
+            last.insert(entry.url.clone(), entry);
+        }
+        let mut compacted = Vec::new();
+        for url in order {
+            compacted.extend(entry_line(&last[&url])?);
+        }
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        tokio::fs::write(&temp, compacted).await?;
+        tokio::fs::rename(&temp, &self.path).await?;
+        *file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .await?;
+        Ok(())
+    }
+
+    async fn append(&self, entry: &BatchEntry) -> Result<()> {
+        let line = entry_line(entry)?;
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// `entry` as a line of the state file
+fn entry_line(entry: &BatchEntry) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(entry)
+        .map_err(|e| Error::WriteError(format!("cannot encode batch state entry: {e}")))?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Parse the complete lines of a state file, returning them and their length in bytes
+fn parse_entries(bytes: &[u8], path: &Path) -> Result<(Vec<BatchEntry>, usize)> {
+    let mut entries = Vec::new();
+    let mut valid_len = 0;
+    for (number, line) in bytes.split_inclusive(|b| *b == b'\n').enumerate() {
+        if !line.ends_with(b"\n") {
+            tracing::warn!(path = %path.display(), line = number + 1, "Dropping incomplete last line of batch state");
+            break;
+        }
+        if !line.trim_ascii().is_empty() {
+            let entry = serde_json::from_slice(line).map_err(|e| {
+                Error::ConfigError(format!(
+                    "{}:{}: invalid batch state entry: {e}",
+                    path.display(),
+                    number + 1
+                ))
+            })?;
+            entries.push(entry);
+        }
+        valid_len += line.len();
+    }
+    Ok((entries, valid_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_torn_last_line_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.jsonl");
+        std::fs::write(
+            &path,
+            "{\"url\":\"http://a/1\",\"status\":\"complete\",\"size\":3}\n\
+             {\"url\":\"http://a/2\",\"status\":\"started\"}\n\
+             {\"url\":\"http://a/2\",\"sta",
+        )
+        .unwrap();
+
+        let state = BatchState::open(&path).await.unwrap();
+        assert!(state.completed("http://a/1").is_some());
+        assert_eq!(state.entry("http://a/2").unwrap().status, BatchStatus::Started);
+
+        // The torn line is cut off, so new entries start on their own line
+        state
+            .record_complete("http://a/2", None, 5, None)
+            .await
+            .unwrap();
+        state.compact().await.unwrap();
+        let reopened = BatchState::open(&path).await.unwrap();
+        assert!(reopened.completed("http://a/2").is_some());
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_line_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.jsonl");
+        std::fs::write(&path, "not json\n{\"url\":\"http://a/1\",\"status\":\"failed\"}\n")
+            .unwrap();
+
+        let error = BatchState::open(&path).await.unwrap_err();
+        assert!(error.to_string().contains("state.jsonl:1"), "{error}");
+    }
+}
@@ -0,0 +1,200 @@
+/// Enforcement of the declared Content-Length and the time limit on response bodies
+///
+/// Some buggy backends send more bytes than their Content-Length declares.
+/// By default the excess is discarded (and counted) so files match the
+/// declared size; `DownloadConfig::allow_excess_body` keeps everything for
+/// servers known to understate the length.
+use crate::content_coding::ContentCoding;
+use crate::{DownloadConfig, Error, Result};
+use bytes::Bytes;
+use std::future::Future;
+use std::time::Duration;
+
+/// Declared body length from the Content-Length header
+///
+/// Read from the header itself rather than the body size hint, which is
+/// unknown when the body is framed differently (e.g. chunked).
+pub(crate) fn declared_length(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Tracks how much of the declared length is left while streaming a body
+#[derive(Debug)]
+pub(crate) struct BodyLimit {
+    /// Bytes still expected (`None` if unbounded)
+    remaining: Option<u64>,
+
+    /// Bytes received beyond the declared length and dropped
+    discarded: u64,
+
+    /// When the body must have arrived
+    deadline: BodyDeadline,
+}
+
+impl BodyLimit {
+    /// Create a limit for a body of `declared` bytes
+    ///
+    /// With `allow_excess` (or no declared length) nothing is ever trimmed.
+    pub(crate) fn new(declared: Option<u64>, allow_excess: bool) -> Self {
+        Self {
+            remaining: declared.filter(|_| !allow_excess),
+            discarded: 0,
+            deadline: BodyDeadline::start(None),
+        }
+    }
+
+    /// Limits for the body of `response`, with the time limit starting now
+    ///
+    /// A body decoded with `decompress` is not trimmed: its declared length
+    /// counts the coded bytes.
+    pub(crate) fn for_response(response: &reqwest::Response, config: &DownloadConfig) -> Self {
+        let declared = declared_length(response)
+            .filter(|_| ContentCoding::of_response(response, config).is_none());
+        Self {
+            deadline: BodyDeadline::start(config.max_body_duration),
+            ..Self::new(declared, config.allow_excess_body)
+        }
+    }
+
+    /// Run `future` (the read of the next chunk) within the body's time limit
+    pub(crate) async fn within_time<F: Future>(&self, future: F) -> Result<F::Output> {
+        self.deadline.run(future).await
+    }
+
+    /// Trim a chunk to the declared length, returning the part to keep
+    pub(crate) fn clamp(&mut self, mut chunk: Bytes) -> Bytes {
+        let Some(remaining) = self.remaining.as_mut() else {
+            return chunk;
+        };
+
+        let len = chunk.len() as u64;
+        if len > *remaining {
+            let keep = usize::try_from(*remaining).unwrap_or(usize::MAX);
+            self.discarded += len - *remaining;
+            chunk.truncate(keep);
+        }
+        *remaining -= chunk.len() as u64;
+        chunk
+    }
+
+    /// Bytes discarded so far
+    pub(crate) fn discarded(&self) -> u64 {
+        self.discarded
+    }
+
+    /// Log a warning if any bytes were discarded
+    pub(crate) fn warn_if_discarded(&self, url: &str) {
+        if self.discarded > 0 {
+            tracing::warn!(
+                url = %url,
+                discarded = self.discarded,
+                "Server sent more data than its Content-Length - excess discarded"
+            );
+        }
+    }
+}
+
+/// End of the time allowed for a body (`DownloadConfig::max_body_duration`)
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BodyDeadline {
+    limit: Option<(Duration, tokio::time::Instant)>,
+}
+
+impl BodyDeadline {
+    /// Deadline `limit` from now (`None` never expires)
+    pub(crate) fn start(limit: Option<Duration>) -> Self {
+        Self {
+            limit: limit.map(|limit| (limit, tokio::time::Instant::now() + limit)),
+        }
+    }
+
+    /// Run `future`, failing with `Error::BodyDurationExceeded` once the deadline passes
+    pub(crate) async fn run<F: Future>(&self, future: F) -> Result<F::Output> {
+        match self.limit {
+            Some((limit, at)) => tokio::time::timeout_at(at, future)
+                .await
+                .map_err(|_| Error::BodyDurationExceeded(limit)),
+            None => Ok(future.await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DownloadConfig, Downloader};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_clamp() {
+        let mut limit = BodyLimit::new(Some(5), false);
+        assert_eq!(limit.clamp(Bytes::from_static(b"abc")), Bytes::from_static(b"abc"));
+        assert_eq!(limit.clamp(Bytes::from_static(b"defgh")), Bytes::from_static(b"de"));
+        assert_eq!(limit.clamp(Bytes::from_static(b"ijk")), Bytes::new());
+        assert_eq!(limit.discarded(), 6);
+
+        let mut unbounded = BodyLimit::new(Some(2), true);
+        assert_eq!(unbounded.clamp(Bytes::from_static(b"abcd")).len(), 4);
+        assert_eq!(unbounded.discarded(), 0);
+
+        let mut unknown = BodyLimit::new(None, false);
+        assert_eq!(unknown.clamp(Bytes::from_static(b"abcd")).len(), 4);
+    }
+
+    /// Raw HTTP server that declares 5 bytes but sends a 10 byte chunked body
+    async fn overlong_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\
+                              Connection: close\r\n\r\na\r\nHelloWorld\r\n0\r\n\r\n",
+                        )
+                        .await;
+                });
+            }
+        });
+        format!("http://{addr}/file.txt")
+    }
+
+    async fn download(url: &str, allow_excess_body: bool) -> (Vec<u8>, u64) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        let config = DownloadConfig {
+            allow_excess_body,
+            ..Default::default()
+        };
+        let result = Downloader::new(config)
+            .unwrap()
+            .download_to_file(url, path.clone())
+            .await
+            .unwrap();
+        (std::fs::read(&path).unwrap(), result.stats.excess_bytes_discarded)
+    }
+
+    #[tokio::test]
+    async fn test_excess_body_discarded_by_default() {
+        let url = overlong_server().await;
+        let (content, discarded) = download(&url, false).await;
+        assert_eq!(content, b"Hello");
+        assert_eq!(discarded, 5);
+    }
+
+    #[tokio::test]
+    async fn test_excess_body_kept_when_allowed() {
+        let url = overlong_server().await;
+        let (content, discarded) = download(&url, true).await;
+        assert_eq!(content, b"HelloWorld");
+        assert_eq!(discarded, 0);
+    }
+}
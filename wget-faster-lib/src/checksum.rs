@@ -0,0 +1,378 @@
+/// Digests of downloaded content, computed while it is written and checked against `expected_checksum`
+use crate::{Error, Result};
+use ring::digest;
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncWrite};
+
+/// A content digest as hex, e.g. a published SHA-256 of a release artifact
+///
+/// Hex digits compare case-insensitively.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Checksum {
+    /// SHA-256 digest (64 hex digits)
+    Sha256(String),
+
+    /// SHA-512 digest (128 hex digits)
+    Sha512(String),
+
+    /// MD5 digest (32 hex digits), for mirrors that only publish MD5 sums
+    Md5(String),
+}
+
+impl Checksum {
+    /// Digest as hex
+    pub fn hex(&self) -> &str {
+        match self {
+            Self::Sha256(hex) | Self::Sha512(hex) | Self::Md5(hex) => hex,
+        }
+    }
+
+    /// Name of the algorithm, as used in `sha256sum`-style tools
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            Self::Sha256(_) => "sha256",
+            Self::Sha512(_) => "sha512",
+            Self::Md5(_) => "md5",
+        }
+    }
+
+    /// Whether `other` is the same digest with the same algorithm
+    pub fn matches(&self, other: &Checksum) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.hex().eq_ignore_ascii_case(other.hex())
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm(), self.hex())
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+/// Incremental digest with the algorithm of an expected checksum (SHA-256 without one)
+#[derive(Clone)]
+pub(crate) enum Hasher {
+    Sha256(digest::Context),
+    Sha512(digest::Context),
+    Md5(Md5),
+}
+
+impl Hasher {
+    pub(crate) fn new(expected: Option<&Checksum>) -> Self {
+        match expected {
+            None | Some(Checksum::Sha256(_)) => Self::Sha256(digest::Context::new(&digest::SHA256)),
+            Some(Checksum::Sha512(_)) => Self::Sha512(digest::Context::new(&digest::SHA512)),
+            Some(Checksum::Md5(_)) => Self::Md5(Md5::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(context) | Self::Sha512(context) => context.update(data),
+            Self::Md5(md5) => md5.update(data),
+        }
+    }
+
+    /// Hash the contents of the file at `path`
+    pub(crate) async fn update_from_file(&mut self, path: &Path) -> Result<()> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.update(&buffer[..read]);
+        }
+    }
+
+    pub(crate) fn finish(self) -> Checksum {
+        match self {
+            Self::Sha256(context) => Checksum::Sha256(to_hex(context.finish().as_ref())),
+            Self::Sha512(context) => Checksum::Sha512(to_hex(context.finish().as_ref())),
+            Self::Md5(md5) => Checksum::Md5(to_hex(&md5.finish())),
+        }
+    }
+}
+
+/// `Err(ChecksumMismatch)` unless `actual` matches `expected` (or nothing is expected)
+pub(crate) fn verify(expected: Option<&Checksum>, actual: &Checksum) -> Result<()> {
+    match expected {
+        Some(expected) if !expected.matches(actual) => Err(Error::ChecksumMismatch {
+            expected: expected.clone(),
+            actual: actual.clone(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Writer that hashes everything written through it, in order
+pub(crate) struct HashingWriter<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W> HashingWriter<W> {
+    pub(crate) fn new(inner: W, hasher: Hasher) -> Self {
+        Self { inner, hasher }
+    }
+
+    /// Writer the bytes are passed on to
+    pub(crate) fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writer the bytes are passed on to, for bytes that are not to be hashed
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Digest of the bytes written so far
+    pub(crate) fn checksum(&self) -> Checksum {
+        self.hasher.clone().finish()
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for HashingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.hasher.update(&buf[..written]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// MD5 (RFC 1321), which `ring` doesn't provide
+#[derive(Clone)]
+pub(crate) struct Md5 {
+    state: [u32; 4],
+    pending: Vec<u8>,
+    length: u64,
+}
+
+const MD5_SHIFTS: [[u32; 4]; 4] = [
+    [7, 12, 17, 22],
+    [5, 9, 14, 20],
+    [4, 11, 16, 23],
+    [6, 10, 15, 21],
+];
+
+const MD5_CONSTANTS: [u32; 64] = [
+    0xd76a_a478,
+    0xe8c7_b756,
+    0x2420_70db,
+    0xc1bd_ceee,
+    0xf57c_0faf,
+    0x4787_c62a,
+    0xa830_4613,
+    0xfd46_9501,
+    0x6980_98d8,
+    0x8b44_f7af,
+    0xffff_5bb1,
+    0x895c_d7be,
+    0x6b90_1122,
+    0xfd98_7193,
+    0xa679_438e,
+    0x49b4_0821,
+    0xf61e_2562,
+    0xc040_b340,
+    0x265e_5a51,
+    0xe9b6_c7aa,
+    0xd62f_105d,
+    0x0244_1453,
+    0xd8a1_e681,
+    0xe7d3_fbc8,
+    0x21e1_cde6,
+    0xc337_07d6,
+    0xf4d5_0d87,
+    0x455a_14ed,
+    0xa9e3_e905,
+    0xfcef_a3f8,
+    0x676f_02d9,
+    0x8d2a_4c8a,
+    0xfffa_3942,
+    0x8771_f681,
+    0x6d9d_6122,
+    0xfde5_380c,
+    0xa4be_ea44,
+    0x4bde_cfa9,
+    0xf6bb_4b60,
+    0xbebf_bc70,
+    0x289b_7ec6,
+    0xeaa1_27fa,
+    0xd4ef_3085,
+    0x0488_1d05,
+    0xd9d4_d039,
+    0xe6db_99e5,
+    0x1fa2_7cf8,
+    0xc4ac_5665,
+    0xf429_2244,
+    0x432a_ff97,
+    0xab94_23a7,
+    0xfc93_a039,
+    0x655b_59c3,
+    0x8f0c_cc92,
+    0xffef_f47d,
+    0x8584_5dd1,
+    0x6fa8_7e4f,
+    0xfe2c_e6e0,
+    0xa301_4314,
+    0x4e08_11a1,
+    0xf753_7e82,
+    0xbd3a_f235,
+    0x2ad7_d2bb,
+    0xeb86_d391,
+];
+
+impl Md5 {
+    fn new() -> Self {
+        Self {
+            state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476],
+            pending: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+        while !data.is_empty() {
+            let take = (64 - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.pending.len() == 64 {
+                let block = std::mem::take(&mut self.pending);
+                self.compress(&block);
+                self.pending = block;
+                self.pending.clear();
+            }
+        }
+    }
+
+    // a, b, c, d, f and g as named in RFC 1321
+    #[allow(clippy::many_single_char_names)]
+    fn compress(&mut self, block: &[u8]) {
+        let words: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f
+                .wrapping_add(a)
+                .wrapping_add(MD5_CONSTANTS[i])
+                .wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(MD5_SHIFTS[i / 16][i % 4]));
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        let bits = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        padding.resize(1 + (119 - self.pending.len()) % 64, 0);
+        padding.extend_from_slice(&bits.to_le_bytes());
+        let length = self.length;
+        self.update(&padding);
+        self.length = length;
+
+        let mut digest = [0; 16];
+        for (out, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            out.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(expected: Option<&Checksum>, data: &[u8]) -> String {
+        let mut hasher = Hasher::new(expected);
+        hasher.update(data);
+        hasher.finish().hex().to_string()
+    }
+
+    #[test]
+    fn test_known_digests() {
+        let md5 = Checksum::Md5(String::new());
+        // RFC 1321 test suite
+        for (input, expected) in [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ] {
+            assert_eq!(digest(Some(&md5), input.as_bytes()), expected, "{input:?}");
+        }
+        assert_eq!(
+            digest(None, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(digest(Some(&Checksum::Sha512(String::new())), b"abc").starts_with("ddaf35a1"));
+    }
+
+    #[test]
+    fn test_split_updates_match_one_update() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let md5 = Checksum::Md5(String::new());
+        for split in [0, 1, 55, 56, 63, 64, 65, 999] {
+            let mut hasher = Hasher::new(Some(&md5));
+            hasher.update(&data[..split]);
+            hasher.update(&data[split..]);
+            assert_eq!(hasher.finish().hex(), digest(Some(&md5), &data), "split at {split}");
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let actual = Checksum::Md5("900150983CD24FB0D6963F7D28E17F72".to_string());
+        assert!(verify(None, &actual).is_ok());
+        assert!(verify(Some(&Checksum::Md5("900150983cd24fb0d6963f7d28e17f72".into())), &actual)
+            .is_ok());
+        let sha = Checksum::Sha256("900150983cd24fb0d6963f7d28e17f72".to_string());
+        assert!(matches!(verify(Some(&sha), &actual), Err(Error::ChecksumMismatch { .. })));
+    }
+}
@@ -1,11 +1,166 @@
-use crate::{DownloadConfig, Error, Result};
+use crate::auth_handler::AuthenticatedHosts;
+use crate::request_hints::RequestHints;
+use crate::{AuthConfig, CacheControl, DownloadConfig, Error, LinkRelation, RefererPolicy, Result};
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, USER_AGENT},
     Client, ClientBuilder,
 };
-use std::collections::HashSet;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Proxy URL -> (username, password) sent to it
+type ProxyCredentials = Arc<Mutex<HashMap<String, (String, String)>>>;
+
+/// The client requests are executed on, replaced to start over with fresh connections
+struct Connections {
+    client: Client,
+    created: Instant,
+    /// Incremented on every replacement
+    generation: u64,
+}
+
+impl Connections {
+    fn new(client: Client) -> Self {
+        Self {
+            client,
+            created: Instant::now(),
+            generation: 0,
+        }
+    }
+}
+
+/// Session cookie store, also recording received cookies when they are to be saved
+struct SessionCookies {
+    jar: reqwest::cookie::Jar,
+    /// Cookies loaded from `cookie_file`, sent unless the session set one of the same name
+    #[cfg(feature = "cookies-file")]
+    loaded: Option<crate::CookieJar>,
+    /// Every `Set-Cookie` received, kept only if `save_cookie_file` is set
+    #[cfg(feature = "cookies-file")]
+    received: Option<Mutex<crate::CookieJar>>,
+}
+
+impl SessionCookies {
+    fn new(config: &DownloadConfig) -> Result<Self> {
+        #[cfg(not(feature = "cookies-file"))]
+        if config.cookie_file.is_some() {
+            tracing::warn!("cookie_file is ignored without the cookies-file feature");
+        }
+        #[cfg(feature = "cookies-file")]
+        let loaded = match &config.cookie_file {
+            Some(path) if config.enable_cookies => {
+                let content = std::fs::read_to_string(path).map_err(|e| {
+                    Error::from(e)
+                        .with_context(format!("while loading cookie file {}", path.display()))
+                })?;
+                Some(crate::CookieJar::parse_netscape(&content))
+            },
+            _ => None,
+        };
+        Ok(Self {
+            jar: reqwest::cookie::Jar::default(),
+            #[cfg(feature = "cookies-file")]
+            loaded,
+            #[cfg(feature = "cookies-file")]
+            received: config
+                .save_cookie_file
+                .as_ref()
+                .map(|_| Mutex::new(crate::CookieJar::new())),
+        })
+    }
+
+    /// `Cookie` header value of the loaded cookies matching `url`, without those
+    /// named in `session` (the session's own `Cookie` header)
+    #[cfg(feature = "cookies-file")]
+    fn loaded_cookies(&self, url: &url::Url, session: Option<&str>) -> Option<String> {
+        let header = self.loaded.as_ref()?.to_cookie_header(
+            url.host_str()?,
+            url.path(),
+            url.scheme() == "https",
+        )?;
+        let session_names: Vec<&str> = session
+            .into_iter()
+            .flat_map(|s| s.split("; "))
+            .filter_map(|pair| pair.split('=').next())
+            .collect();
+        let cookies: Vec<&str> = header
+            .split("; ")
+            .filter(|pair| !session_names.contains(&pair.split('=').next().unwrap_or_default()))
+            .collect();
+        (!cookies.is_empty()).then(|| cookies.join("; "))
+    }
+}
+
+impl reqwest::cookie::CookieStore for SessionCookies {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &url::Url) {
+        #[cfg(feature = "cookies-file")]
+        if let (Some(received), Some(host)) = (&self.received, url.host_str()) {
+            let headers: Vec<&HeaderValue> = cookie_headers.collect();
+            let mut received = received.lock().unwrap_or_else(PoisonError::into_inner);
+            for value in headers.iter().filter_map(|v| v.to_str().ok()) {
+                received.add_from_set_cookie(host, value);
+            }
+            drop(received);
+            self.jar.set_cookies(&mut headers.into_iter(), url);
+            return;
+        }
+        self.jar.set_cookies(cookie_headers, url);
+    }
+
+    fn cookies(&self, url: &url::Url) -> Option<HeaderValue> {
+        let session = self.jar.cookies(url);
+        #[cfg(feature = "cookies-file")]
+        {
+            let session_str = session.as_ref().and_then(|v| v.to_str().ok());
+            if let Some(loaded) = self.loaded_cookies(url, session_str) {
+                let merged = match session_str {
+                    Some(session) => format!("{session}; {loaded}"),
+                    None => loaded,
+                };
+                return HeaderValue::from_str(&merged).ok().or(session);
+            }
+        }
+        session
+    }
+}
+
+/// The HSTS store `config` asks for: read from `hsts_file` if it exists, or empty
+fn load_hsts(config: &DownloadConfig) -> Result<Option<crate::HstsStore>> {
+    if !config.enable_hsts {
+        return Ok(None);
+    }
+    let Some(path) = &config.hsts_file else {
+        return Ok(Some(crate::HstsStore::new()));
+    };
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(crate::HstsStore::parse(&content))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Some(crate::HstsStore::new())),
+        Err(e) => {
+            Err(Error::from(e)
+                .with_context(format!("while loading HSTS database {}", path.display())))
+        },
+    }
+}
+
+/// Headers sent with every request: User-Agent, Accept-Encoding and `config.headers`
+fn default_headers(config: &DownloadConfig) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+
+    // Set user agent
+    headers.insert(USER_AGENT, HeaderValue::from_str(&config.user_agent)?);
+
+    // Advertise the accepted content-codings (`identity` without compression)
+    headers.insert(ACCEPT_ENCODING, crate::content_coding::accept_encoding(config));
+
+    // Add custom headers
+    for (key, value) in &config.headers {
+        let header_name = HeaderName::from_bytes(key.as_bytes())?;
+        let header_value = HeaderValue::from_str(value)?;
+        headers.insert(header_name, header_value);
+    }
+    Ok(headers)
+}
 
 /// HTTP client wrapper for download operations
 ///
@@ -15,7 +170,7 @@ use std::time::Duration;
 /// - Proxy support with `no_proxy` filtering
 /// - Cookie management
 /// - SSL/TLS configuration
-/// - Compression (gzip, deflate, brotli)
+/// - Compression (gzip, deflate, brotli, zstd with the `zstd` feature)
 /// - Redirects with configurable limits
 ///
 /// The client is clonable and thread-safe, designed for use in parallel downloads.
@@ -23,9 +178,28 @@ use std::time::Duration;
 pub struct HttpClient {
     client: Client,
     config: DownloadConfig,
-    /// Hosts that have been successfully authenticated (for preemptive auth on subsequent requests)
+    /// Hosts that have been successfully authenticated, with the credentials that worked
+    /// (for preemptive auth on subsequent requests)
     /// This implements GNU wget's behavior of remembering successful auth and not waiting for challenge
-    authenticated_hosts: Arc<Mutex<HashSet<String>>>,
+    authenticated_hosts: Arc<Mutex<AuthenticatedHosts>>,
+    /// Session cookie store shared by all requests made through this client
+    cookie_jar: Arc<SessionCookies>,
+    /// Known HSTS hosts, if `enable_hsts` is set
+    hsts: Option<Arc<Mutex<crate::HstsStore>>>,
+    /// Body bytes received against `quota`, shared by every clone
+    quota: Arc<crate::quota::Quota>,
+    /// What the `tls_policy` verifier learned about servers, if a policy is set
+    tls: Option<Arc<crate::tls::TlsState>>,
+    /// Presigned URLs replaced by `url_refresher` (original URL -> fresh URL)
+    refreshed_urls: Arc<Mutex<HashMap<String, String>>>,
+    /// Proxies that challenged with 407, and the credentials now sent to them
+    authenticated_proxies: ProxyCredentials,
+    /// Client whose connection pool requests currently use (see `connection_max_lifetime`)
+    connections: Arc<Mutex<Connections>>,
+    /// What the requests currently made are for (set by a crawl for each URL)
+    request_hints: Option<RequestHints>,
+    /// Archive of requests and responses, if `warc_file` is set
+    warc: Option<Arc<crate::WarcWriter>>,
 }
 
 impl HttpClient {
@@ -50,65 +224,122 @@ impl HttpClient {
     /// # Ok::<(), wget_faster_lib::Error>(())
     /// ```
     pub fn new(config: DownloadConfig) -> Result<Self> {
-        let mut headers = HeaderMap::new();
-
-        // Set user agent
-        headers.insert(USER_AGENT, HeaderValue::from_str(&config.user_agent)?);
-
-        // Set compression if enabled
-        if config.enable_compression {
-            headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate, br"));
-        }
+        let cookie_jar = Arc::new(SessionCookies::new(&config)?);
+        let hsts = load_hsts(&config)?.map(|store| Arc::new(Mutex::new(store)));
+        let authenticated_proxies = ProxyCredentials::default();
+        let quota = Arc::new(crate::quota::Quota::new(&config));
+        let tls = config.tls_policy.as_ref().map(|_| Arc::default());
+        let warc = match &config.warc_file {
+            Some(path) => Some(Arc::new(crate::WarcWriter::create(path)?)),
+            None => None,
+        };
+        let client =
+            Self::build_client(&config, &cookie_jar, &authenticated_proxies, tls.as_ref())?;
 
-        // Add custom headers
-        for (key, value) in &config.headers {
-            let header_name = HeaderName::from_bytes(key.as_bytes())?;
-            let header_value = HeaderValue::from_str(value)?;
-            headers.insert(header_name, header_value);
-        }
+        Ok(Self {
+            connections: Arc::new(Mutex::new(Connections::new(client.clone()))),
+            client,
+            config,
+            authenticated_hosts: Arc::new(Mutex::new(AuthenticatedHosts::new(
+                crate::auth_handler::MAX_AUTHENTICATED_HOSTS,
+            ))),
+            cookie_jar,
+            hsts,
+            quota,
+            tls,
+            refreshed_urls: Arc::new(Mutex::new(HashMap::new())),
+            authenticated_proxies,
+            request_hints: None,
+            warc,
+        })
+    }
 
+    /// Build the `reqwest::Client` for `config`
+    ///
+    /// Called again whenever the connection pool is replaced; the cookie jar,
+    /// proxy credentials and TLS policy state are shared by every client built.
+    fn build_client(
+        config: &DownloadConfig,
+        cookie_jar: &Arc<SessionCookies>,
+        authenticated_proxies: &ProxyCredentials,
+        tls: Option<&Arc<crate::tls::TlsState>>,
+    ) -> Result<Client> {
         let mut builder = ClientBuilder::new()
-            .default_headers(headers)
-            .timeout(config.timeout)
+            .default_headers(default_headers(config)?)
             .connect_timeout(config.connect_timeout)
+            .read_timeout(config.read_timeout)
             .tcp_keepalive(Some(Duration::from_secs(30)))
-            .pool_max_idle_per_host(config.parallel_chunks)
-            .cookie_store(true); // Enable automatic cookie storage
+            .pool_max_idle_per_host(
+                config
+                    .pool_max_idle_per_host
+                    .unwrap_or(config.parallel_chunks),
+            )
+            .cookie_provider(cookie_jar.clone()); // Automatic cookie storage, inspectable via cookie_jar
+
+        if let Some(timeout) = config.request_timeout() {
+            builder = builder.timeout(timeout);
+        }
 
-        // Configure redirects
-        if config.follow_redirects {
+        // Passing None would disable reqwest's default idle timeout altogether
+        if let Some(idle_timeout) = config.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(idle_timeout);
+        }
+
+        // Configure redirects (a limit of 0 returns the 3xx response itself)
+        if config.follow_redirects && config.max_redirects > 0 {
             builder = builder.redirect(reqwest::redirect::Policy::limited(config.max_redirects));
         } else {
             builder = builder.redirect(reqwest::redirect::Policy::none());
         }
 
+        // reqwest's automatic redirect Referer is full-URL minus downgrades; only keep it
+        // for policies at least that permissive
+        builder = builder.referer(matches!(
+            config.referer_policy,
+            RefererPolicy::NoReferrerWhenDowngrade | RefererPolicy::UnsafeUrl
+        ));
+
+        // Resolve with the configured address family and connect from bind_address
+        if let Some(family) = config.address_family {
+            let resolver = crate::address_family::FamilyResolver(family);
+            builder = builder.dns_resolver(Arc::new(resolver));
+        }
+        builder = builder.local_address(config.bind_address);
+
         // Configure SSL/TLS
         builder = builder.danger_accept_invalid_certs(!config.verify_ssl);
 
         // Configure proxy
         if let Some(proxy_config) = &config.proxy {
-            // Clone proxy_config for use in the closure
-            let proxy_config_clone = proxy_config.clone();
+            let proxy_config = proxy_config.clone();
+            let preemptive_auth = if config.auth_no_challenge {
+                proxy_config.auth.clone()
+            } else {
+                None
+            };
+            let authenticated = authenticated_proxies.clone();
 
             // Use custom proxy predicate to implement wget-compatible no_proxy logic
             // This ensures ".domain.com" matches ONLY subdomains, NOT the bare domain
             let proxy = reqwest::Proxy::custom(move |url| {
-                // Check if this URL should bypass the proxy
-                if proxy_config_clone.should_bypass(url.as_str()) {
-                    return None; // No proxy for this URL
+                let proxy_url = proxy_config.proxy_for(url.as_str())?;
+                let mut proxy = url::Url::parse(proxy_url).ok()?;
+
+                // Credentials in the proxy URL become Proxy-Authorization
+                // (also on CONNECT for https tunnels)
+                let credentials = authenticated
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .get(proxy_url)
+                    .cloned()
+                    .or_else(|| preemptive_auth.clone());
+                if let Some((username, password)) = credentials {
+                    proxy.set_username(&username).ok()?;
+                    proxy.set_password(Some(&password)).ok()?;
                 }
-
-                // URL doesn't match no_proxy patterns - use the configured proxy URL
-                Some(proxy_config_clone.url.clone())
+                Some(proxy)
             });
 
-            // Add proxy authentication if configured
-            let proxy = if let Some((username, password)) = &proxy_config.auth {
-                proxy.basic_auth(username, password)
-            } else {
-                proxy
-            };
-
             builder = builder.proxy(proxy);
         }
 
@@ -116,10 +347,29 @@ impl HttpClient {
         // Note: Basic auth will be added per-request
         // Digest auth is handled automatically by reqwest
 
-        // Cookies are now handled by reqwest's built-in cookie_store(true)
-        // Note: cookie_file loading/saving will need to be re-implemented later if needed
+        // Cookies, including those loaded from cookie_file, are handled by the
+        // cookie store (see cookie_jar); received cookies are saved by
+        // Downloader::save_cookies
+
+        builder = Self::configure_certificates(builder, config, tls)?;
+
+        builder
+            .build()
+            .map_err(|e| Error::ConfigError(format!("Failed to build HTTP client: {e}")))
+    }
+
+    /// Add `ca_cert` and `client_cert` to `builder`, or hand TLS over to the
+    /// `tls_policy` verifier, which handles both
+    fn configure_certificates(
+        mut builder: ClientBuilder,
+        config: &DownloadConfig,
+        tls: Option<&Arc<crate::tls::TlsState>>,
+    ) -> Result<ClientBuilder> {
+        if let (Some(policy), Some(state)) = (&config.tls_policy, tls) {
+            let tls = crate::tls::client_config(config, policy, Arc::clone(state))?;
+            return Ok(builder.use_preconfigured_tls(tls).tls_info(true));
+        }
 
-        // Configure certificates
         if let Some(ca_cert_path) = &config.ca_cert {
             let cert = std::fs::read(ca_cert_path)?;
             let cert = reqwest::Certificate::from_pem(&cert)
@@ -133,16 +383,301 @@ impl HttpClient {
                 .map_err(|e| Error::ConfigError(format!("Invalid client certificate: {e}")))?;
             builder = builder.identity(identity);
         }
+        Ok(builder)
+    }
 
-        let client = builder
-            .build()
-            .map_err(|e| Error::ConfigError(format!("Failed to build HTTP client: {e}")))?;
+    /// Send a request built from this client
+    ///
+    /// All requests should go through here: an `http://` URL to a known HSTS
+    /// host is rewritten to `https://`, a URL previously replaced by
+    /// `url_refresher` is swapped for its fresh version, `request_signer` signs
+    /// the request last, and a 403 for an expired presigned URL triggers one
+    /// refresh and re-signed retry.
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let mut request = request.build()?;
+        self.hsts_upgrade(request.url_mut());
+        let url = request.url().clone();
+        crate::request_hints::apply(
+            &self.config,
+            self.request_hints.as_ref(),
+            &url,
+            request.headers_mut(),
+        );
+        let original_url = request.url().to_string();
+        let refreshed = self
+            .refreshed_urls
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(&original_url)
+            .cloned();
+        if let Some(fresh) = refreshed {
+            *request.url_mut() = url::Url::parse(&fresh)?;
+        }
 
-        Ok(Self {
-            client,
-            config,
-            authenticated_hosts: Arc::new(Mutex::new(HashSet::new())),
-        })
+        // Keep an unsigned copy in case the URL needs refreshing
+        let retry = self
+            .config
+            .url_refresher
+            .as_ref()
+            .and_then(|_| request.try_clone());
+
+        self.sign(&mut request).await?;
+        let response = self.execute_timed(request).await?;
+
+        if response.status() == reqwest::StatusCode::FORBIDDEN {
+            if let (Some(refresher), Some(mut retry)) = (&self.config.url_refresher, retry) {
+                if crate::signing::signature_expired(retry.url(), std::time::SystemTime::now()) {
+                    let fresh = (refresher.0)(retry.url().as_str()).await?;
+                    tracing::info!(
+                        url = %crate::redact_url(&original_url),
+                        "Presigned URL expired - retrying with refreshed URL"
+                    );
+                    *retry.url_mut() = url::Url::parse(&fresh)?;
+                    self.refreshed_urls
+                        .lock()
+                        .unwrap_or_else(std::sync::PoisonError::into_inner)
+                        .insert(original_url, fresh);
+
+                    self.sign(&mut retry).await?;
+                    return self.execute_timed(retry).await;
+                }
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// [`execute`](Self::execute) within `response_header_timeout`
+    ///
+    /// The response is returned as soon as its headers arrive, so the body
+    /// isn't covered by the limit.
+    async fn execute_timed(&self, request: reqwest::Request) -> Result<reqwest::Response> {
+        let host = self
+            .tls
+            .as_ref()
+            .and(request.url().host_str())
+            .map(str::to_string);
+        let sent = match &self.warc {
+            Some(_) => Some(self.sent_request(&request)?),
+            None => None,
+        };
+        let outcome = match self.config.response_header_timeout {
+            None => self.execute(request).await,
+            Some(limit) => {
+                let url = crate::redact_url(request.url().as_str());
+                tokio::time::timeout(limit, self.execute(request))
+                    .await
+                    .unwrap_or_else(|_| {
+                        tracing::warn!(url = %url, limit = ?limit, "No response headers in time");
+                        Err(Error::Timeout)
+                    })
+            },
+        };
+        match outcome {
+            Ok(mut response) => {
+                self.note_hsts(&response);
+                self.attach_tls_info(&mut response);
+                match (&self.warc, sent) {
+                    (Some(warc), Some(sent)) => Ok(warc.capture(sent, response)),
+                    _ => Ok(response),
+                }
+            },
+            // A handshake the TLS policy refused fails with the policy's reason
+            Err(e) => Err(host
+                .and_then(|host| self.tls.as_ref()?.take_violation(&host))
+                .map_or(e, Error::TlsError)),
+        }
+    }
+
+    /// `request` as the client will send it, for its WARC record
+    fn sent_request(&self, request: &reqwest::Request) -> Result<crate::warc::SentRequest> {
+        use reqwest::cookie::CookieStore;
+        let cookie = self.cookie_jar.cookies(request.url());
+        Ok(crate::warc::SentRequest::new(request, &default_headers(&self.config)?, cookie))
+    }
+
+    /// Make the TLS details of the server `response` came from available to
+    /// [`extract_metadata_from_response`](Self::extract_metadata_from_response)
+    fn attach_tls_info(&self, response: &mut reqwest::Response) {
+        let Some(tls) = &self.tls else {
+            return;
+        };
+        let info = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(reqwest::tls::TlsInfo::peer_certificate)
+            .and_then(|certificate| tls.info(certificate));
+        if let Some(info) = info {
+            response.extensions_mut().insert(info);
+        }
+    }
+
+    /// Record the `Strict-Transport-Security` header of `response`
+    ///
+    /// Only honored over https with certificate verification on, as RFC 6797
+    /// requires a secure transport without errors.
+    fn note_hsts(&self, response: &reqwest::Response) {
+        let (Some(hsts), Some(value)) = (
+            &self.hsts,
+            response
+                .headers()
+                .get(reqwest::header::STRICT_TRANSPORT_SECURITY),
+        ) else {
+            return;
+        };
+        let url = response.url();
+        if url.scheme() != "https" || !self.config.verify_ssl {
+            return;
+        }
+        if let (Some(host), Ok(value)) = (url.host_str(), value.to_str()) {
+            hsts.lock().unwrap_or_else(PoisonError::into_inner).record(
+                host,
+                url.port_or_known_default().unwrap_or(443),
+                value,
+            );
+        }
+    }
+
+    /// Execute a request, answering a 407 from the proxy with credentials once
+    ///
+    /// A 407 surfaces either as a response (plain HTTP through the proxy) or
+    /// as a failed CONNECT (HTTPS tunnel). A proxy that still refuses the
+    /// credentials yields `Error::InvalidStatus(407)`.
+    async fn execute(&self, request: reqwest::Request) -> Result<reqwest::Response> {
+        let retry = self.config.proxy.as_ref().and_then(|_| request.try_clone());
+        let target = request.url().to_string();
+        crate::address_family::check_url(self.config.address_family, request.url())?;
+
+        let outcome = self.execute_pooled(request).await;
+        let challenged = match &outcome {
+            Ok(response) => response.status() == reqwest::StatusCode::PROXY_AUTHENTICATION_REQUIRED,
+            Err(e) => is_proxy_auth_error(e),
+        };
+
+        let outcome = match retry {
+            Some(retry) if challenged && self.authenticate_proxy(&target) => {
+                self.execute_pooled(retry).await
+            },
+            _ => outcome,
+        };
+
+        match outcome {
+            Err(e) if is_proxy_auth_error(&e) => Err(Error::InvalidStatus(407)),
+            // No address of the configured family: retrying won't find one
+            Err(e) => Err(crate::address_family::no_address_error(&e).unwrap_or_else(|| e.into())),
+            outcome => Ok(outcome?),
+        }
+    }
+
+    /// Execute a request on a pooled connection, replaying it on a fresh one if
+    /// the server had already closed that connection
+    ///
+    /// Load balancers drop idle keep-alive connections on their own schedule,
+    /// and a request sent just then fails with "connection closed before message
+    /// completed" or a broken pipe. An idempotent request is replayed once,
+    /// straight away, without counting as one of the configured retries.
+    async fn execute_pooled(
+        &self,
+        request: reqwest::Request,
+    ) -> reqwest::Result<reqwest::Response> {
+        let replay = if request.method().is_idempotent() {
+            request.try_clone()
+        } else {
+            None
+        };
+
+        let (client, generation) = self.pooled_client();
+        match (client.execute(request).await, replay) {
+            (Err(e), Some(replay)) if is_stale_connection(&e) => {
+                tracing::debug!(error = %e, "Pooled connection was closed - retrying on a fresh connection");
+                self.fresh_client(generation).execute(replay).await
+            },
+            (outcome, _) => outcome,
+        }
+    }
+
+    /// Client to execute the next request on, replaced first if past `connection_max_lifetime`
+    fn pooled_client(&self) -> (Client, u64) {
+        let mut connections = self.connections();
+        if let Some(lifetime) = self.config.connection_max_lifetime {
+            if connections.created.elapsed() >= lifetime {
+                tracing::debug!(
+                    lifetime = ?lifetime,
+                    "Connections reached their maximum lifetime - starting a new pool"
+                );
+                self.replace_connections(&mut connections);
+            }
+        }
+        (connections.client.clone(), connections.generation)
+    }
+
+    /// Client without the connections of `generation`, whose pool holds a closed connection
+    ///
+    /// Idle connections to the same server were most likely dropped at the same
+    /// time, so the whole pool is replaced. Concurrent requests that failed on
+    /// the same generation share a single replacement.
+    fn fresh_client(&self, generation: u64) -> Client {
+        let mut connections = self.connections();
+        if connections.generation == generation {
+            self.replace_connections(&mut connections);
+        }
+        connections.client.clone()
+    }
+
+    fn replace_connections(&self, connections: &mut Connections) {
+        match Self::build_client(
+            &self.config,
+            &self.cookie_jar,
+            &self.authenticated_proxies,
+            self.tls.as_ref(),
+        ) {
+            Ok(client) => connections.client = client,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to rebuild HTTP client - keeping its connections");
+            },
+        }
+        connections.created = Instant::now();
+        connections.generation += 1;
+    }
+
+    fn connections(&self) -> std::sync::MutexGuard<'_, Connections> {
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Remember credentials for the proxy serving `target` after a 407
+    ///
+    /// Returns false if there are none, or if they were already sent.
+    fn authenticate_proxy(&self, target: &str) -> bool {
+        let Some(proxy_url) = self.config.proxy.as_ref().and_then(|p| p.proxy_for(target)) else {
+            return false;
+        };
+
+        let mut authenticated = self
+            .authenticated_proxies
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if authenticated.contains_key(proxy_url) || self.config.auth_no_challenge {
+            return false;
+        }
+
+        match crate::auth_handler::get_proxy_credentials(proxy_url, &self.config) {
+            Some(credentials) => {
+                tracing::debug!(proxy = %crate::redact_url(proxy_url), "Proxy requested authentication - retrying with credentials");
+                authenticated.insert(proxy_url.to_string(), credentials);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Apply the configured `request_signer`, if any
+    async fn sign(&self, request: &mut reqwest::Request) -> Result<()> {
+        if let Some(signer) = &self.config.request_signer {
+            signer.sign(request).await?;
+        }
+        Ok(())
     }
 
     /// Get a reference to the underlying `reqwest::Client`
@@ -159,25 +694,130 @@ impl HttpClient {
         &self.config
     }
 
+    /// Send an event to `config.event_callback`, building it only if a callback is set
+    pub(crate) fn emit(&self, event: impl FnOnce() -> crate::DownloadEvent) {
+        if let Some(callback) = &self.config.event_callback {
+            (callback.0)(event());
+        }
+    }
+
+    /// Download quota shared by every download made through this client
+    pub(crate) fn quota(&self) -> &crate::quota::Quota {
+        &self.quota
+    }
+
+    /// What the requests currently made are for, if a crawl said
+    pub(crate) fn request_hints(&self) -> Option<&RequestHints> {
+        self.request_hints.as_ref()
+    }
+
+    /// Describe what the following requests are for (`None` when not crawling)
+    #[cfg(feature = "recursive")]
+    pub(crate) fn set_request_hints(&mut self, hints: Option<RequestHints>) {
+        self.request_hints = hints;
+    }
+
+    /// Rewrite `url` to `https://` if its host is a known HSTS host
+    ///
+    /// Returns whether it was rewritten; never does without `enable_hsts`.
+    pub fn hsts_upgrade(&self, url: &mut url::Url) -> bool {
+        let Some(hsts) = &self.hsts else {
+            return false;
+        };
+        let upgraded = hsts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .upgrade(url);
+        if upgraded {
+            tracing::debug!(url = %crate::redact_url(url.as_str()), "HSTS: upgraded to https");
+        }
+        upgraded
+    }
+
+    /// The known HSTS hosts, including those learned during the session
+    ///
+    /// `None` without `enable_hsts`.
+    pub fn hsts_store(&self) -> Option<crate::HstsStore> {
+        self.hsts
+            .as_ref()
+            .map(|hsts| hsts.lock().unwrap_or_else(PoisonError::into_inner).clone())
+    }
+
+    /// Get the `Cookie` header value the session would send to `url`
+    ///
+    /// Returns `None` if no stored cookie matches the URL.
+    pub fn session_cookies(&self, url: &url::Url) -> Option<String> {
+        use reqwest::cookie::CookieStore;
+
+        self.cookie_jar
+            .cookies(url)
+            .and_then(|value| value.to_str().ok().map(str::to_string))
+    }
+
+    /// Cookies received during the session that `save_cookie_file` should hold
+    ///
+    /// Expired cookies are left out, and so are session cookies unless
+    /// `keep_session_cookies` is set. Empty if `save_cookie_file` isn't set.
+    #[cfg(feature = "cookies-file")]
+    pub fn received_cookies(&self) -> crate::CookieJar {
+        let Some(received) = &self.cookie_jar.received else {
+            return crate::CookieJar::new();
+        };
+        let mut jar = received
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let keep_session_cookies = self.config.keep_session_cookies;
+        jar.retain(|cookie| match cookie.expiration {
+            Some(_) => !cookie.is_expired(),
+            None => keep_session_cookies,
+        });
+        jar
+    }
+
     /// Check if a host has been successfully authenticated
     ///
     /// This is used to implement GNU wget's behavior of remembering successful
     /// auth and not waiting for challenge on subsequent requests to the same host.
     pub fn authenticated_hosts_contains(&self, host: &str) -> bool {
-        self.authenticated_hosts.lock().unwrap().contains(host)
+        self.authenticated_hosts().contains(host)
+    }
+
+    /// Mark a host as successfully authenticated with `auth`
+    ///
+    /// This enables preemptive auth with the same credentials for subsequent
+    /// requests to the host, matching GNU wget's behavior. At most
+    /// `MAX_AUTHENTICATED_HOSTS` hosts are remembered; the least recently
+    /// used one is forgotten first.
+    pub fn mark_host_authenticated(&self, host: String, auth: AuthConfig) {
+        self.authenticated_hosts().insert(host, auth);
     }
 
-    /// Mark a host as successfully authenticated
+    /// Forget the credentials remembered for a host
     ///
-    /// This enables preemptive auth for subsequent requests to the same host,
-    /// matching GNU wget's behavior.
-    pub fn mark_host_authenticated(&self, host: String) {
-        self.authenticated_hosts.lock().unwrap().insert(host);
+    /// Called when they are rejected, so the next request waits for a
+    /// challenge and fetches fresh credentials.
+    pub fn forget_authenticated_host(&self, host: &str) {
+        if self.authenticated_hosts().remove(host) {
+            tracing::info!(host = %host, "Remembered credentials rejected - forgetting host");
+        }
+    }
+
+    /// Credentials remembered for `host`, for preemptive auth
+    pub(crate) fn authenticated_credentials(&self, host: &str) -> Option<AuthConfig> {
+        self.authenticated_hosts().get(host)
+    }
+
+    /// Lock the authenticated hosts; a panic elsewhere never makes them unusable
+    fn authenticated_hosts(&self) -> std::sync::MutexGuard<'_, AuthenticatedHosts> {
+        self.authenticated_hosts
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
     }
 
     /// Check if server supports range requests
     pub async fn supports_range(&self, url: &str) -> Result<bool> {
-        let response = self.client.head(url).send().await?;
+        let response = self.send(self.client.head(url)).await?;
 
         Ok(response
             .headers()
@@ -188,7 +828,7 @@ impl HttpClient {
 
     /// Get content length from HEAD request
     pub async fn get_content_length(&self, url: &str) -> Result<Option<u64>> {
-        let response = self.client.head(url).send().await?;
+        let response = self.send(self.client.head(url)).await?;
 
         Ok(response
             .headers()
@@ -209,6 +849,9 @@ impl HttpClient {
 
     /// Get metadata about the resource with optional If-Modified-Since header
     ///
+    /// When the server rejects HEAD with 400, 403 or 405, the metadata comes
+    /// from a one-byte ranged GET instead (see [`get_metadata_ranged`](Self::get_metadata_ranged)).
+    ///
     /// # Arguments
     ///
     /// * `url` - The URL to fetch metadata for
@@ -217,6 +860,49 @@ impl HttpClient {
         &self,
         url: &str,
         if_modified_since: Option<std::time::SystemTime>,
+    ) -> Result<ResourceMetadata> {
+        let metadata = match self.fetch_metadata(url, if_modified_since).await {
+            Ok(metadata) if head_rejected(metadata.status_code) => {
+                tracing::debug!(url = %url, status = metadata.status_code, "HEAD rejected, probing with ranged GET");
+                self.fetch_metadata_ranged(url, if_modified_since).await
+            },
+            result => result,
+        };
+        metadata.map_err(|e| e.with_context(format!("while fetching metadata for {url}")))
+    }
+
+    /// Ask for the body without a content-coding, with `resume_safe_encoding`
+    ///
+    /// Used for file downloads, whose byte offsets (resume, parallel chunks)
+    /// must refer to the bytes as saved.
+    pub(crate) fn identity_encoding(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::RequestBuilder {
+        if self.config.resume_safe_encoding {
+            request.header(ACCEPT_ENCODING, "identity")
+        } else {
+            request
+        }
+    }
+
+    /// Credentials to send before any challenge: those that already worked for
+    /// `host`, or the configured ones with `auth_no_challenge`
+    fn preemptive_credentials(&self, host: Option<&str>) -> Option<AuthConfig> {
+        let remembered_auth = host.and_then(|h| self.authenticated_credentials(h));
+        remembered_auth.or_else(|| {
+            self.config
+                .auth
+                .clone()
+                .filter(|_| self.config.auth_no_challenge)
+        })
+    }
+
+    /// Send the HEAD request for `get_metadata_conditional`, handling auth challenges
+    async fn fetch_metadata(
+        &self,
+        url: &str,
+        if_modified_since: Option<std::time::SystemTime>,
     ) -> Result<ResourceMetadata> {
         tracing::debug!(url = %url, has_if_modified_since = if_modified_since.is_some(), "Sending HEAD request for metadata");
 
@@ -226,7 +912,8 @@ impl HttpClient {
             .and_then(|u| u.host_str().map(|h| h.to_string()));
 
         // Build HEAD request with optional If-Modified-Since header
-        let mut request = self.client.head(url);
+        // (Content-Length is that of the body a file download will ask for)
+        let mut request = self.identity_encoding(self.client.head(url));
 
         // Add If-Modified-Since header if provided
         if let Some(time) = if_modified_since {
@@ -235,34 +922,15 @@ impl HttpClient {
             request = request.header(reqwest::header::IF_MODIFIED_SINCE, http_date);
         }
 
-        // Add authentication if configured and either:
-        // 1. auth_no_challenge is set (preemptive auth), OR
-        // 2. We've previously authenticated successfully to this host
-        let host_previously_authenticated = host
-            .as_ref()
-            .map_or(false, |h| self.authenticated_hosts.lock().unwrap().contains(h));
-
-        let should_send_preemptive_auth =
-            self.config.auth_no_challenge || host_previously_authenticated;
-
-        if should_send_preemptive_auth {
-            // Get credentials - either from config.auth or from .netrc
-            let auth_creds = if let Some(ref auth) = self.config.auth {
-                Some(auth.clone())
-            } else if host_previously_authenticated {
-                // If we've authenticated before but don't have config.auth, try .netrc
-                crate::auth_handler::get_credentials(url, &self.config)
-            } else {
-                None
-            };
-
-            if let Some(auth) = auth_creds {
-                tracing::debug!(username = %auth.username, "Adding preemptive auth to HEAD request");
-                request = request.basic_auth(&auth.username, Some(&auth.password));
-            }
+        // Add authentication if either:
+        // 1. We've previously authenticated successfully to this host (reuse those credentials), OR
+        // 2. auth_no_challenge is set (preemptive auth with the configured credentials)
+        if let Some(auth) = self.preemptive_credentials(host.as_deref()) {
+            tracing::debug!(username = %auth.username, "Adding preemptive auth to HEAD request");
+            request = request.basic_auth(&auth.username, Some(&auth.password));
         }
 
-        let response = request.send().await?;
+        let response = self.send(request).await?;
         let status_code = response.status().as_u16();
         tracing::debug!(status_code, "Received HEAD response");
 
@@ -279,23 +947,36 @@ impl HttpClient {
                 last_modified: None,
                 etag: None,
                 content_type: None,
+                content_encoding: None,
                 content_disposition: None,
                 status_code,
                 headers: response.headers().clone(),
                 auth_succeeded: false,
+                final_url: Some(response.url().to_string()),
+                allow: None,
+                accept_ranges_unit: None,
+                cache_control: None,
+                age: None,
+                content_language: None,
+                links: Vec::new(),
+                tls_info: None,
             });
         }
 
         // Handle authentication challenges (401/407)
-        // If we have credentials but didn't send them preemptively, retry with auth
+        // Retry once with fresh credentials; remembered ones were just rejected
         if crate::auth_handler::should_retry_auth(status_code, &self.config) {
-            // Get credentials (configured auth or .netrc)
-            if let Some(auth) = crate::auth_handler::get_credentials(url, &self.config) {
+            if let Some(ref h) = host {
+                self.forget_authenticated_host(h);
+            }
+
+            // Get credentials (credential provider, configured auth or .netrc)
+            if let Some(auth) = crate::auth_handler::challenge_credentials(url, &self.config).await
+            {
                 tracing::debug!(username = %auth.username, "HEAD request auth challenge - retrying with credentials");
                 // Retry HEAD request with authentication
                 let mut retry_request = self
-                    .client
-                    .head(url)
+                    .identity_encoding(self.client.head(url))
                     .basic_auth(&auth.username, Some(&auth.password));
 
                 // Re-add If-Modified-Since header if it was present
@@ -305,7 +986,7 @@ impl HttpClient {
                         retry_request.header(reqwest::header::IF_MODIFIED_SINCE, http_date);
                 }
 
-                let retry_response = retry_request.send().await?;
+                let retry_response = self.send(retry_request).await?;
                 let retry_status = retry_response.status().as_u16();
 
                 // Handle 5xx server errors on retry - return minimal metadata to allow GET fallback
@@ -320,10 +1001,19 @@ impl HttpClient {
                         last_modified: None,
                         etag: None,
                         content_type: None,
+                        content_encoding: None,
                         content_disposition: None,
                         status_code: retry_status,
                         headers: retry_response.headers().clone(),
                         auth_succeeded: false,
+                        final_url: Some(retry_response.url().to_string()),
+                        allow: None,
+                        accept_ranges_unit: None,
+                        cache_control: None,
+                        age: None,
+                        content_language: None,
+                        links: Vec::new(),
+                        tls_info: None,
                     });
                 }
 
@@ -335,7 +1025,7 @@ impl HttpClient {
 
                     // Remember this host for future preemptive auth
                     if let Some(h) = host {
-                        self.authenticated_hosts.lock().unwrap().insert(h);
+                        self.mark_host_authenticated(h, auth);
                     }
                 } else {
                     tracing::warn!(retry_status, "HEAD request authentication failed");
@@ -381,6 +1071,13 @@ impl HttpClient {
             .and_then(|v| v.to_str().ok())
             .map(std::string::ToString::to_string);
 
+        let content_encoding = response
+            .headers()
+            .get(reqwest::header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .filter(|coding| !coding.eq_ignore_ascii_case("identity"))
+            .map(std::string::ToString::to_string);
+
         let content_disposition = response
             .headers()
             .get(reqwest::header::CONTENT_DISPOSITION)
@@ -396,13 +1093,73 @@ impl HttpClient {
             last_modified,
             etag,
             content_type,
+            content_encoding,
             content_disposition,
             status_code,
+            allow: crate::headers::parse_allow(&headers),
+            accept_ranges_unit: crate::headers::parse_accept_ranges(&headers),
+            cache_control: crate::headers::parse_cache_control(&headers),
+            age: crate::headers::parse_age(&headers),
+            content_language: crate::headers::parse_content_language(&headers),
+            links: crate::headers::parse_link(&headers, response.url()),
+            tls_info: response.extensions().get::<crate::TlsInfo>().cloned(),
             headers,
             auth_succeeded: false,
+            final_url: Some(response.url().to_string()),
         }
     }
 
+    /// Get metadata with a one-byte ranged GET, for servers that reject HEAD
+    ///
+    /// A 206 answer is reported as 200, with the full size taken from Content-Range.
+    pub async fn get_metadata_ranged(&self, url: &str) -> Result<ResourceMetadata> {
+        self.fetch_metadata_ranged(url, None)
+            .await
+            .map_err(|e| e.with_context(format!("while fetching metadata for {url}")))
+    }
+
+    /// Send the ranged GET for `get_metadata_ranged`
+    ///
+    /// Only the headers are read: a server ignoring the range would send the
+    /// whole file, so the response is dropped without reading its body.
+    async fn fetch_metadata_ranged(
+        &self,
+        url: &str,
+        if_modified_since: Option<std::time::SystemTime>,
+    ) -> Result<ResourceMetadata> {
+        tracing::debug!(url = %url, "Sending ranged GET for metadata");
+        let mut request = self.identity_encoding(
+            self.client
+                .get(url)
+                .header(reqwest::header::RANGE, "bytes=0-0"),
+        );
+        if let Some(time) = if_modified_since {
+            request =
+                request.header(reqwest::header::IF_MODIFIED_SINCE, httpdate::fmt_http_date(time));
+        }
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string));
+        if let Some(auth) = self.preemptive_credentials(host.as_deref()) {
+            request = request.basic_auth(&auth.username, Some(&auth.password));
+        }
+        let response = self.send(request).await?;
+
+        let mut metadata = Self::extract_metadata_from_response(&response);
+        if metadata.status_code == 206 {
+            metadata.status_code = 200;
+            metadata.supports_range = true;
+            metadata.content_length = response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(crate::response_handler::parse_content_range)
+                .and_then(|(_, _, total)| total);
+        }
+        drop(response);
+        Ok(metadata)
+    }
+
     /// Extract metadata from a HEAD response
     async fn extract_metadata(response: reqwest::Response) -> Result<ResourceMetadata> {
         Ok(Self::extract_metadata_from_response(&response))
@@ -430,6 +1187,9 @@ pub struct ResourceMetadata {
     /// Content-Type header value
     pub content_type: Option<String>,
 
+    /// Content-Encoding header value (`None` for `identity`)
+    pub content_encoding: Option<String>,
+
     /// Content-Disposition header value
     pub content_disposition: Option<String>,
 
@@ -442,12 +1202,70 @@ pub struct ResourceMetadata {
     /// Whether authentication was used and succeeded for this request
     /// Used to enable preemptive auth for subsequent requests to the same host
     pub auth_succeeded: bool,
+
+    /// URL the response was actually served from, after following redirects
+    /// (`None` when no request has been made yet)
+    pub final_url: Option<String>,
+
+    /// Methods the server supports, from the Allow header
+    pub allow: Option<Vec<reqwest::Method>>,
+
+    /// Range unit from Accept-Ranges, lowercased (`bytes`, `none`, or a custom unit)
+    pub accept_ranges_unit: Option<String>,
+
+    /// Caching directives from Cache-Control
+    pub cache_control: Option<CacheControl>,
+
+    /// Time the response has spent in caches, from the Age header
+    pub age: Option<Duration>,
+
+    /// Language tags from Content-Language
+    pub content_language: Option<Vec<String>>,
+
+    /// Relations from Link headers (`rel="next"` pagination, preloads, ...)
+    pub links: Vec<LinkRelation>,
+
+    /// TLS version and certificate chain of the server, when
+    /// `DownloadConfig::tls_policy` is set and the response came over https
+    pub tls_info: Option<crate::TlsInfo>,
 }
 
 impl ResourceMetadata {
+    /// Target of a redirect that was not followed (`max_redirects` of 0 or
+    /// `follow_redirects` off), resolved against the URL of the response
+    ///
+    /// `None` unless the status is a 3xx other than 304 with a Location header.
+    pub fn redirect_location(&self) -> Option<String> {
+        if !(300..400).contains(&self.status_code) || self.status_code == 304 {
+            return None;
+        }
+        let location = self.headers.get(reqwest::header::LOCATION)?.to_str().ok()?;
+        let resolved = self
+            .final_url
+            .as_deref()
+            .and_then(|base| url::Url::parse(base).ok())
+            .and_then(|base| base.join(location).ok());
+        Some(resolved.map_or_else(|| location.to_string(), String::from))
+    }
+
+    /// Size of the body as it will be saved, if known
+    ///
+    /// `content_length` counts the bytes on the wire, so it no longer tells
+    /// the size once a content-coded body is decoded (`decompress`).
+    pub fn saved_length(&self, decompress: bool) -> Option<u64> {
+        let decoded = decompress
+            && self
+                .content_encoding
+                .as_deref()
+                .and_then(crate::content_coding::ContentCoding::parse)
+                .is_some();
+        self.content_length.filter(|_| !decoded)
+    }
+
     /// Format headers for display (wget --server-response style)
     ///
-    /// Returns a string with all HTTP headers formatted as "Header-Name: value"
+    /// Returns a string with all HTTP headers formatted as "Header-Name: value",
+    /// followed by the TLS details if `tls_info` is known
     pub fn format_headers(&self) -> String {
         let mut output =
             format!("HTTP/1.1 {} {}\n", self.status_code, status_text(self.status_code));
@@ -457,28 +1275,254 @@ impl ResourceMetadata {
                 output.push_str(&format!("  {name}: {value_str}\n"));
             }
         }
+        for line in self.tls_info.iter().flat_map(crate::TlsInfo::format_lines) {
+            output.push_str(&format!("  {line}\n"));
+        }
 
         output
     }
 }
 
+/// Whether a HEAD status means the server refuses HEAD but may answer GET
+///
+/// Unlike [`needs_get_fallback`](crate::link_check::needs_get_fallback), 501
+/// is left out: downloads already go on to GET after any 5xx from HEAD.
+fn head_rejected(status: u16) -> bool {
+    status != 501 && crate::link_check::needs_get_fallback(status)
+}
+
+/// Whether `err` is a proxy refusing to open a CONNECT tunnel without credentials
+fn is_proxy_auth_error(err: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(e) = source {
+        if e.to_string().contains("proxy authorization required") {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Whether `err` means the server closed the connection before answering
+///
+/// Typical of a pooled keep-alive connection that a load balancer dropped
+/// while it was idle.
+fn is_stale_connection(err: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(e) = source {
+        if let Some(hyper_err) = e.downcast_ref::<hyper::Error>() {
+            if hyper_err.is_incomplete_message() {
+                return true;
+            }
+        }
+        if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io_err.kind(),
+                std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+            ) {
+                return true;
+            }
+        }
+        source = e.source();
+    }
+    false
+}
+
 /// Get status text for HTTP status code
 fn status_text(code: u16) -> &'static str {
     match code {
         200 => "OK",
         201 => "Created",
+        202 => "Accepted",
+        203 => "Non-Authoritative Information",
         204 => "No Content",
+        205 => "Reset Content",
         206 => "Partial Content",
+        207 => "Multi-Status",
+        208 => "Already Reported",
+        226 => "IM Used",
+        300 => "Multiple Choices",
         301 => "Moved Permanently",
         302 => "Found",
+        303 => "See Other",
         304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
         400 => "Bad Request",
         401 => "Unauthorized",
         403 => "Forbidden",
         404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        407 => "Proxy Authentication Required",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        429 => "Too Many Requests",
         500 => "Internal Server Error",
+        501 => "Not Implemented",
         502 => "Bad Gateway",
         503 => "Service Unavailable",
+        504 => "Gateway Timeout",
         _ => "Unknown",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProxyConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Forward proxy requiring `Proxy-Authorization: Basic dXNlcjpzZWNyZXQ=` (user:secret)
+    ///
+    /// Records whether each request carried credentials.
+    async fn auth_proxy() -> (String, Arc<Mutex<Vec<bool>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let seen: Arc<Mutex<Vec<bool>>> = Arc::default();
+        let log = seen.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let log = log.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let head = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                    let authorized = head.contains("proxy-authorization: basic dxnlcjpzzwnyzxq=");
+                    log.lock()
+                        .unwrap()
+                        .push(head.contains("proxy-authorization"));
+
+                    let response: &[u8] = if authorized {
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\nproxied"
+                    } else {
+                        b"HTTP/1.1 407 Proxy Authentication Required\r\n\
+                          Proxy-Authenticate: Basic realm=\"proxy\"\r\n\
+                          Content-Length: 0\r\nConnection: close\r\n\r\n"
+                    };
+                    let _ = socket.write_all(response).await;
+                });
+            }
+        });
+        (format!("http://{addr}"), seen)
+    }
+
+    fn proxied_client(proxy_url: &str, password: &str) -> HttpClient {
+        let config = DownloadConfig {
+            proxy: Some(ProxyConfig {
+                http_url: Some(proxy_url.to_string()),
+                auth: Some(("user".to_string(), password.to_string())),
+                ..ProxyConfig::default()
+            }),
+            ..DownloadConfig::default()
+        };
+        HttpClient::new(config).unwrap()
+    }
+
+    #[test]
+    fn test_proxy_for_scheme() {
+        let proxy = ProxyConfig {
+            http_url: Some("http://cache:3128".to_string()),
+            no_proxy: vec!["internal.example".to_string()],
+            ..ProxyConfig::default()
+        };
+        assert_eq!(proxy.proxy_for("http://example.com/"), Some("http://cache:3128"));
+        assert_eq!(proxy.proxy_for("https://example.com/"), None);
+        assert_eq!(proxy.proxy_for("http://internal.example/"), None);
+
+        let proxy = ProxyConfig {
+            https_url: Some("http://tls-proxy:8080".to_string()),
+            ..ProxyConfig::new("http://fallback:3128")
+        };
+        assert_eq!(proxy.proxy_for("https://example.com/"), Some("http://tls-proxy:8080"));
+        assert_eq!(proxy.proxy_for("http://example.com/"), Some("http://fallback:3128"));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_407_retried_with_credentials() {
+        let (proxy_url, seen) = auth_proxy().await;
+        let client = proxied_client(&proxy_url, "secret");
+
+        let url = "http://origin.invalid/file.txt";
+        let response = client.send(client.client().get(url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "proxied");
+
+        // The proxy is remembered: the next request sends credentials up front
+        let response = client.send(client.client().get(url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(*seen.lock().unwrap(), vec![false, true, true]);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_407_with_wrong_credentials() {
+        let (proxy_url, seen) = auth_proxy().await;
+        let client = proxied_client(&proxy_url, "wrong");
+
+        let response = client
+            .send(client.client().get("http://origin.invalid/file.txt"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 407);
+        assert_eq!(*seen.lock().unwrap(), vec![false, true]);
+
+        // Downloads report the persistent 407 as an authentication failure
+        let downloader = crate::Downloader::new(DownloadConfig {
+            proxy: client.config().proxy.clone(),
+            ..DownloadConfig::default()
+        })
+        .unwrap();
+        let err = downloader
+            .download_to_memory("http://origin.invalid/file.txt")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidStatus(407)), "{err:?}");
+        assert_eq!(err.exit_code(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_connect_407_is_auth_failure() {
+        let (proxy_url, _) = auth_proxy().await;
+        let config = DownloadConfig {
+            proxy: Some(ProxyConfig::new(proxy_url)),
+            ..DownloadConfig::default()
+        };
+        let client = HttpClient::new(config).unwrap();
+
+        // No credentials anywhere: the CONNECT refusal is reported as 407
+        let err = client
+            .send(client.client().get("https://origin.invalid/file.txt"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidStatus(407)), "{err:?}");
+    }
+
+    #[test]
+    fn test_connections_replaced_after_max_lifetime() {
+        let config = DownloadConfig {
+            connection_max_lifetime: Some(Duration::from_millis(20)),
+            ..DownloadConfig::default()
+        };
+        let client = HttpClient::new(config).unwrap();
+        assert_eq!(client.pooled_client().1, 0);
+        assert_eq!(client.pooled_client().1, 0);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(client.pooled_client().1, 1);
+
+        // Only the first request failing on a generation replaces it
+        client.fresh_client(1);
+        client.fresh_client(1);
+        assert_eq!(client.pooled_client().1, 2);
+    }
+}
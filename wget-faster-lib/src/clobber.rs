@@ -0,0 +1,163 @@
+/// Replacing an existing destination file (`overwrite_existing`, the CLI's `-O`)
+use std::collections::HashSet;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{ready, Context, Poll};
+use tokio::fs::File;
+use tokio::io::AsyncWrite;
+
+type CreateFuture = Pin<Box<dyn Future<Output = io::Result<File>> + Send>>;
+
+enum State {
+    /// Not created yet; whatever is at the path is untouched
+    Pending(PathBuf),
+    Creating(CreateFuture),
+    Open(File),
+}
+
+/// Destination file that is created (or truncated) by the first write
+///
+/// A request that fails before its body is accepted never writes, so it
+/// leaves a previous copy of the file as it was.
+pub(crate) struct LazyFile {
+    state: State,
+}
+
+impl LazyFile {
+    /// File at `path`, created on the first write
+    pub(crate) fn pending(path: PathBuf) -> Self {
+        Self {
+            state: State::Pending(path),
+        }
+    }
+
+    /// Whether the file has been created
+    pub(crate) fn is_open(&self) -> bool {
+        matches!(self.state, State::Open(_))
+    }
+}
+
+/// Destination a file download writes its body to
+pub(crate) trait OutputFile: AsyncWrite + Unpin + Send {
+    /// Whether the file has been created
+    fn is_open(&self) -> bool;
+}
+
+impl OutputFile for LazyFile {
+    fn is_open(&self) -> bool {
+        LazyFile::is_open(self)
+    }
+}
+
+impl From<File> for LazyFile {
+    fn from(file: File) -> Self {
+        Self {
+            state: State::Open(file),
+        }
+    }
+}
+
+impl AsyncWrite for LazyFile {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            match self.state {
+                State::Pending(ref path) => {
+                    self.state = State::Creating(Box::pin(File::create(path.clone())));
+                },
+                State::Creating(ref mut create) => {
+                    let file = ready!(create.as_mut().poll(cx))?;
+                    self.state = State::Open(file);
+                },
+                State::Open(ref mut file) => return Pin::new(file).poll_write(cx, buf),
+            }
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.state {
+            State::Open(ref mut file) => Pin::new(file).poll_flush(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.state {
+            State::Open(ref mut file) => Pin::new(file).poll_shutdown(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// Destinations truncated by a failed attempt, by URL
+///
+/// A retry of the same URL continues such a file instead of truncating it
+/// again; once the URL completes, the next download to the path replaces it.
+#[derive(Debug, Default)]
+pub(crate) struct ReplacedFiles {
+    files: Mutex<HashSet<(String, PathBuf)>>,
+}
+
+impl ReplacedFiles {
+    /// Whether an earlier attempt at `url` already truncated `path`
+    pub(crate) fn is_replacing(&self, url: &str, path: &Path) -> bool {
+        self.lock().contains(&(url.to_string(), path.to_path_buf()))
+    }
+
+    /// Remember that an attempt at `url` truncated `path` and then failed
+    pub(crate) fn record(&self, url: &str, path: &Path) {
+        self.lock().insert((url.to_string(), path.to_path_buf()));
+    }
+
+    /// Forget `path` once `url` has been downloaded into it
+    pub(crate) fn finish(&self, url: &str, path: &Path) {
+        self.lock().remove(&(url.to_string(), path.to_path_buf()));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashSet<(String, PathBuf)>> {
+        self.files
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_lazy_file_truncates_on_first_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        std::fs::write(&path, "previous copy").unwrap();
+
+        let mut file = LazyFile::pending(path.clone());
+        file.flush().await.unwrap();
+        assert!(!file.is_open());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous copy");
+
+        file.write_all(b"new").await.unwrap();
+        file.flush().await.unwrap();
+        assert!(file.is_open());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_replaced_files_by_url() {
+        let replaced = ReplacedFiles::default();
+        let path = Path::new("out.txt");
+        replaced.record("http://a.com/x", path);
+
+        assert!(replaced.is_replacing("http://a.com/x", path));
+        assert!(!replaced.is_replacing("http://a.com/y", path));
+        replaced.finish("http://a.com/x", path);
+        assert!(!replaced.is_replacing("http://a.com/x", path));
+    }
+}
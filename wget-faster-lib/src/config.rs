@@ -1,25 +1,96 @@
+use crate::{
+    AddressFamily, CacheConfig, Checksum, ContentCoding, CredentialProvider, EventCallback,
+    HeaderPreset, ProgressSink, ProvenanceConfig, RefererPolicy, RequestSigner, ResponseFilter,
+    SizeCheck, UrlRefresher,
+};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Configuration for the downloader
-#[derive(Debug, Clone)]
+///
+/// Build one with [`DownloadConfig::builder`], which checks that the settings
+/// fit together. The fields stay public for code that fills them in directly.
+///
+/// Serializes under the field names (kept stable) to show the effective
+/// settings, e.g. `wgetf --show-config`: durations are in seconds, enum values
+/// `snake_case`, credentials masked and callbacks shown as `"<callback>"`.
+#[derive(Debug, Clone, Serialize)]
 pub struct DownloadConfig {
     /// Number of parallel connections for range requests
     pub parallel_chunks: usize,
 
+    /// Most range requests a parallel download may keep in flight
+    ///
+    /// Above `parallel_chunks`, the count adapts to the measured throughput:
+    /// it grows while more connections raise the total speed and shrinks when
+    /// they don't. Set it to `parallel_chunks` for a fixed count.
+    pub max_parallel_chunks: usize,
+
     /// Size of each chunk in bytes (None for auto)
     pub chunk_size: Option<u64>,
 
-    /// Timeout for each request
-    pub timeout: Duration,
+    /// Timeout for each request, body included
+    ///
+    /// `None` by default, so long downloads aren't cut off: `response_header_timeout`
+    /// catches servers that never answer and `read_timeout` bodies that stall.
+    /// Without `response_header_timeout`, `None` means 120 seconds.
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
+    pub timeout: Option<Duration>,
 
     /// Connect timeout
+    #[serde(serialize_with = "crate::config_serde::duration")]
     pub connect_timeout: Duration,
 
-    /// Read timeout
+    /// Longest wait for the next bytes of a response
+    #[serde(serialize_with = "crate::config_serde::duration")]
     pub read_timeout: Duration,
 
+    /// Longest wait from sending a request to receiving its response headers
+    ///
+    /// Applies to every request (HEAD, GET, parallel chunks, robots.txt) and
+    /// fails with the retryable [`Error::Timeout`](crate::Error::Timeout).
+    /// The body isn't covered, however long it takes. `None` for no limit.
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
+    pub response_header_timeout: Option<Duration>,
+
+    /// Wall-clock limit on each response body, however steadily it arrives
+    ///
+    /// `read_timeout` only fires when no bytes come in; this ends bodies that
+    /// never finish (event streams, live logs) with
+    /// [`Error::BodyDurationExceeded`](crate::Error::BodyDurationExceeded).
+    /// `None` for no limit.
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
+    pub max_body_duration: Option<Duration>,
+
+    /// Close pooled keep-alive connections idle for longer than this
+    ///
+    /// Set it below the idle timeout of load balancers in the path, so a
+    /// connection they already dropped is not reused. `None` keeps reqwest's
+    /// default (90 seconds).
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
+    pub pool_idle_timeout: Option<Duration>,
+
+    /// Idle keep-alive connections kept per host (None for `parallel_chunks`)
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// Stop reusing connections once they are this old
+    ///
+    /// reqwest has no per-connection limit, so the connection pool is replaced
+    /// as a whole once it reaches this age; requests still in flight finish on
+    /// their old connections.
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
+    pub connection_max_lifetime: Option<Duration>,
+
+    /// IP address family to connect with (`None`: any, in the order resolved)
+    pub address_family: Option<AddressFamily>,
+
+    /// Local address connections are made from
+    pub bind_address: Option<IpAddr>,
+
     /// User agent string
     pub user_agent: String,
 
@@ -32,24 +103,83 @@ pub struct DownloadConfig {
     /// Authentication configuration
     pub auth: Option<AuthConfig>,
 
+    /// Asked for credentials when a server answers 401, before `auth` and .netrc
+    ///
+    /// Asked again when remembered credentials stop working (e.g. a password
+    /// rotated mid-crawl), so it can return the new ones.
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub credential_provider: Option<CredentialProvider>,
+
     /// Custom headers
+    #[serde(serialize_with = "crate::config_serde::headers")]
     pub headers: HashMap<String, String>,
 
+    /// `Accept` sent with every request, unless set in `headers`
+    ///
+    /// Replaces the `Accept` of `header_preset`.
+    pub default_accept: Option<String>,
+
+    /// Browser-like or minimal header set added to every request
+    ///
+    /// Headers set in `headers` are never replaced.
+    pub header_preset: Option<HeaderPreset>,
+
     /// Follow redirects
     pub follow_redirects: bool,
 
     /// Maximum number of redirects
+    ///
+    /// 0 follows none, like `follow_redirects` off: a 3xx response is the
+    /// result of the download (see `DownloadResult::outcome`).
     pub max_redirects: usize,
 
     /// Enable cookies
     pub enable_cookies: bool,
 
-    /// Cookie file path
+    /// Netscape cookie file whose cookies are sent with every matching request
+    /// (`--load-cookies`); read when the client is created
     pub cookie_file: Option<PathBuf>,
 
+    /// Netscape cookie file that `Downloader::save_cookies` writes the cookies
+    /// received during the session to (`--save-cookies`)
+    pub save_cookie_file: Option<PathBuf>,
+
+    /// Also save session cookies, which have no expiry (`--keep-session-cookies`)
+    pub keep_session_cookies: bool,
+
+    /// Honor `Strict-Transport-Security`: `http://` URLs to hosts that sent it
+    /// over https are requested over https (on unless `--no-hsts`)
+    pub enable_hsts: bool,
+
+    /// HSTS database in GNU wget's format (`--hsts-file`), read when the client
+    /// is created and written by `Downloader::save_hsts`
+    pub hsts_file: Option<PathBuf>,
+
     /// Enable compression
     pub enable_compression: bool,
 
+    /// Content-codings offered in `Accept-Encoding` when `enable_compression`
+    /// is set, most preferred first (every one this build decodes by default)
+    ///
+    /// With none, or without `enable_compression`, `identity` is sent.
+    pub accepted_encodings: Vec<ContentCoding>,
+
+    /// Decode gzip, deflate, brotli and (with the `zstd` feature) zstd bodies
+    /// before saving them
+    ///
+    /// Off by default, like wget: content-coded bodies are saved as received.
+    /// A decoded body's Content-Length is its coded size, so file and memory
+    /// downloads report progress without a total for it.
+    pub decompress: bool,
+
+    /// Send `Accept-Encoding: identity` when downloading to a file, so resume
+    /// offsets and parallel chunks refer to the bytes as saved
+    ///
+    /// Bodies are saved as received, so a content-coded partial file can't be
+    /// resumed from a byte offset: without this, such a file is downloaded again
+    /// from the start.
+    pub resume_safe_encoding: bool,
+
     /// Verify SSL certificates
     pub verify_ssl: bool,
 
@@ -59,6 +189,12 @@ pub struct DownloadConfig {
     /// CA certificate path
     pub ca_cert: Option<PathBuf>,
 
+    /// Refuse servers whose TLS falls below this policy (None to accept any valid certificate)
+    ///
+    /// Checked after the usual certificate verification, which must stay on
+    /// (`verify_ssl`). Responses then carry `ResourceMetadata::tls_info`.
+    pub tls_policy: Option<crate::TlsPolicy>,
+
     /// Download speed limit (bytes per second, None for unlimited)
     pub speed_limit: Option<u64>,
 
@@ -69,6 +205,7 @@ pub struct DownloadConfig {
     pub method: HttpMethod,
 
     /// POST/PUT data
+    #[serde(serialize_with = "crate::config_serde::body")]
     pub body_data: Option<Vec<u8>>,
 
     /// Referer URL
@@ -81,17 +218,29 @@ pub struct DownloadConfig {
     pub http_keep_alive: bool,
 
     /// Wait time between requests (seconds)
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
     pub wait_time: Option<Duration>,
 
     /// Random wait range multiplier (0.5-1.5x `wait_time`)
     pub random_wait: bool,
 
     /// Wait time between retries (seconds)
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
     pub wait_retry: Option<Duration>,
 
     /// Download quota (bytes, None for unlimited)
+    ///
+    /// Counts the body bytes received by all downloads of one `Downloader`.
+    /// Once it is used up no new download starts (`Error::QuotaExceeded`);
+    /// downloads already in progress finish unless `quota_hard` is set.
     pub quota: Option<u64>,
 
+    /// Abort downloads in progress as soon as `quota` is passed
+    ///
+    /// The download fails with `Error::QuotaExceeded`; a file keeps what was
+    /// written, so it can be resumed.
+    pub quota_hard: bool,
+
     /// Enable timestamping (only download if remote is newer)
     pub timestamping: bool,
 
@@ -105,6 +254,12 @@ pub struct DownloadConfig {
     pub content_disposition: bool,
 
     /// Save HTTP headers to output
+    ///
+    /// Files and memory downloads start with the GET response's status line
+    /// and headers, as GNU wget writes them; [`crate::saved_headers::split`]
+    /// finds where the body starts. The byte counts of downloads and progress
+    /// include them. Such downloads are never parallel, and packed files hold
+    /// the body only.
     pub save_headers: bool,
 
     /// Print server response headers to stderr (wget -S style)
@@ -114,6 +269,10 @@ pub struct DownloadConfig {
     pub auth_no_challenge: bool,
 
     /// Save error page content even on HTTP errors (4xx/5xx)
+    ///
+    /// Files and memory downloads alike keep the body instead of failing with
+    /// `Error::InvalidStatus`; the status is in the `DownloadResult` metadata
+    /// (see `Downloader::download_to_memory_result`).
     pub content_on_error: bool,
 
     /// Minimum file size threshold for parallel downloads (bytes)
@@ -125,6 +284,14 @@ pub struct DownloadConfig {
     /// Filename restriction modes (lowercase, uppercase, nocontrol, ascii, unix, windows)
     pub restrict_file_names: Vec<FilenameRestriction>,
 
+    /// Replace an existing destination file instead of resuming it (`-O`)
+    ///
+    /// The file is only truncated once a response body is accepted (2xx, or an
+    /// error page with `content_on_error`), so a failed request leaves the
+    /// previous copy intact. Retrying the same URL continues the file an earlier
+    /// attempt already truncated. Ignored with `timestamping` or `start_pos`.
+    pub overwrite_existing: bool,
+
     /// Start downloading from this byte offset (--start-pos option)
     /// If set, overrides resume functionality from --continue
     pub start_pos: Option<u64>,
@@ -134,12 +301,144 @@ pub struct DownloadConfig {
 
     /// GNU wget compatibility mode (disable HEAD requests, sequential-only)
     pub gnu_wget_compat: bool,
+
+    /// Permission bits applied to downloaded files (e.g. `0o600`), unix only
+    ///
+    /// When `None`, files keep the default mode from the process umask.
+    pub file_mode: Option<u32>,
+
+    /// Content types for which downloaded files are made executable (unix only)
+    ///
+    /// Matching is case-insensitive on the media type, ignoring parameters
+    /// (e.g. `application/x-executable`). Execute bits are added for every
+    /// class that has read permission, like `chmod +x`.
+    pub executable_if_content_type: Vec<String>,
+
+    /// Check free disk space against Content-Length before starting a file download
+    ///
+    /// Fails fast with `Error::DiskFull` instead of running out of space mid-transfer.
+    pub check_free_space: bool,
+
+    /// How much of `referer` is revealed, depending on the request target
+    pub referer_policy: RefererPolicy,
+
+    /// How sizes are compared in timestamping mode when timestamps are equal
+    pub timestamping_size_check: SizeCheck,
+
+    /// Keep the `ETag` of each downloaded file in a `<file>.etag` sidecar
+    ///
+    /// With `timestamping`, the saved `ETag` is sent as `If-None-Match` next
+    /// to `If-Modified-Since`, for servers whose `Last-Modified` is missing or
+    /// unreliable. A 304 keeps the file; a 200 with a different `ETag`
+    /// replaces it whatever the timestamps say.
+    pub use_etag: bool,
+
+    /// With `timestamping`, probe an existing local file first and resume it
+    /// if it is only partial (`-N -c`)
+    ///
+    /// Like wget: a remote file newer than the local one is downloaded again
+    /// from the start, a local file at least as long as a remote one that
+    /// isn't newer is kept without a transfer, and a shorter one is continued
+    /// with a Range request.
+    pub timestamping_continue: bool,
+
+    /// Keep bytes sent beyond the declared Content-Length instead of discarding them
+    pub allow_excess_body: bool,
+
+    /// Write a provenance record (URL, headers, SHA-256) for each downloaded file
+    pub write_provenance: Option<ProvenanceConfig>,
+
+    /// Download files into this local directory and move them to their destination when done
+    ///
+    /// For destinations on slow or network filesystems (NFS, SMB, FUSE). The
+    /// staging file is named after the destination, so an interrupted download
+    /// resumes from it; moves across filesystems fall back to copy, fsync and a
+    /// rename inside the destination directory. Recursive downloads stage every file.
+    pub staging_dir: Option<PathBuf>,
+
+    /// Follow symlinks already in the output tree
+    ///
+    /// By default a symlinked directory below the output directory is not
+    /// traversed (the file is skipped with an error) and a symlink or hardlink
+    /// where a file is saved is replaced rather than written through, so links
+    /// planted in a mirror can't make downloads overwrite files elsewhere.
+    /// This applies to the paths a crawl builds; a path passed to
+    /// `Downloader::download_to_file` is written as given.
+    pub follow_output_symlinks: bool,
+
+    /// Archive every request and response to this WARC file (wget's `--warc-file`)
+    ///
+    /// Records are gzip-compressed and appended, so a crawl, or several runs,
+    /// share one file. See [`WarcWriter`](crate::WarcWriter).
+    pub warc_file: Option<PathBuf>,
+
+    /// Limit on bytes buffered in memory across concurrent memory downloads
+    ///
+    /// Memory-destined transfers reserve budget as their buffers grow and give
+    /// it back when they complete. A transfer that needs room before it holds
+    /// any waits; one that already holds some spills its body to a temporary
+    /// file instead, so transfers never wait on each other. File downloads
+    /// stream to disk and are not counted. `None` means unlimited.
+    pub max_buffered_bytes: Option<u64>,
+
+    /// On-disk cache for plain GET downloads, honoring Cache-Control (`None` disables it)
+    ///
+    /// Memory downloads are served from fresh entries without any request,
+    /// revalidate stale ones, and store cacheable responses. File downloads
+    /// copy fresh entries into a new file but don't add entries.
+    pub http_cache: Option<CacheConfig>,
+
+    /// Re-request while the server answers 202 Accepted (e.g. artifacts still being built)
+    ///
+    /// Waits `Retry-After` (or `retry.initial_delay`) between attempts, up to
+    /// `retry.max_retries` times. When false, a 202 body is saved like a 200.
+    pub retry_on_202: bool,
+
+    /// Signs every request right before it is sent (HEAD, GET, ranges, retries)
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub request_signer: Option<Arc<dyn RequestSigner>>,
+
+    /// Provides a fresh URL when a presigned URL is rejected with 403 after expiring
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub url_refresher: Option<UrlRefresher>,
+
+    /// Checked for file downloads once response headers arrive, before the body is transferred
+    ///
+    /// A rejection aborts the download with `Error::ResponseRejected` and
+    /// leaves no file behind.
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub response_filter: Option<ResponseFilter>,
+
+    /// Receives a `DownloadEvent` at each milestone of a download (start,
+    /// redirect, retry, parallel chunk, completion or failure)
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub event_callback: Option<EventCallback>,
+
+    /// Receives rate-limited progress updates without blocking downloads, in
+    /// addition to the progress callback passed to a download
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub progress_sink: Option<ProgressSink>,
+
+    /// Learn the total size of bodies sent without Content-Length from a `bytes=0-0` probe
+    ///
+    /// Only when the server advertises range support. The probe runs while
+    /// the body streams; progress callbacks see `total_size` appear once it
+    /// answers.
+    pub probe_total_size: bool,
+
+    /// Digest a file download must have; on a mismatch the file is deleted and the
+    /// download fails with `Error::ChecksumMismatch`
+    ///
+    /// File downloads report their digest in `DownloadResult::checksum` either
+    /// way, with this checksum's algorithm (SHA-256 when unset).
+    pub expected_checksum: Option<Checksum>,
 }
 
 /// HTTP request method
 ///
 /// Supported HTTP methods for download requests. Defaults to GET.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum HttpMethod {
     /// HTTP GET - retrieve resource
     Get,
@@ -157,27 +456,61 @@ pub enum HttpMethod {
     Options,
 }
 
+/// Request timeout used when neither `timeout` nor `response_header_timeout` is set
+const FALLBACK_TIMEOUT: Duration = Duration::from_secs(120);
+
+impl DownloadConfig {
+    /// Timeout for each whole request, if any (see `timeout`)
+    pub(crate) fn request_timeout(&self) -> Option<Duration> {
+        self.timeout.or_else(|| {
+            self.response_header_timeout
+                .is_none()
+                .then_some(FALLBACK_TIMEOUT)
+        })
+    }
+}
+
 impl Default for DownloadConfig {
+    #[allow(clippy::too_many_lines)] // one line per field
     fn default() -> Self {
         Self {
             parallel_chunks: 8,
+            max_parallel_chunks: 32,
             chunk_size: None, // Auto-determine
-            timeout: Duration::from_secs(120),
+            timeout: None,
             connect_timeout: Duration::from_secs(30),
             read_timeout: Duration::from_secs(60),
+            response_header_timeout: Some(Duration::from_secs(30)),
+            max_body_duration: None,
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            connection_max_lifetime: None,
+            address_family: None,
+            bind_address: None,
             user_agent: format!("wget-faster/{}", env!("CARGO_PKG_VERSION")),
             retry: RetryConfig::default(),
             proxy: None,
             auth: None,
+            credential_provider: None,
             headers: HashMap::new(),
+            default_accept: None,
+            header_preset: None,
             follow_redirects: true,
             max_redirects: 20,
             enable_cookies: true,
             cookie_file: None,
+            save_cookie_file: None,
+            keep_session_cookies: false,
+            enable_hsts: true,
+            hsts_file: None,
             enable_compression: true,
+            accepted_encodings: ContentCoding::all(),
+            decompress: false,
+            resume_safe_encoding: true,
             verify_ssl: true,
             client_cert: None,
             ca_cert: None,
+            tls_policy: None,
             speed_limit: None,
             verbose: false,
             method: HttpMethod::Get,
@@ -189,6 +522,7 @@ impl Default for DownloadConfig {
             random_wait: false,
             wait_retry: None,
             quota: None,
+            quota_hard: false,
             timestamping: false,
             if_modified_since: true,
             use_server_timestamps: true,
@@ -200,9 +534,32 @@ impl Default for DownloadConfig {
             parallel_threshold: 10 * 1024 * 1024, // 10MB
             pretty_output: false,                 // wget-compatible by default
             restrict_file_names: Vec::new(),      // No restrictions by default
-            start_pos: None,                      // No start position by default
-            https_only: false,                    // Accept both HTTP and HTTPS by default
+            overwrite_existing: false,
+            start_pos: None,        // No start position by default
+            https_only: false,      // Accept both HTTP and HTTPS by default
             gnu_wget_compat: false, // Disabled by default - use --gnu-wget-compat to enable
+            file_mode: None,        // Inherit umask defaults
+            executable_if_content_type: Vec::new(),
+            check_free_space: false,
+            referer_policy: RefererPolicy::default(), // no-referrer-when-downgrade
+            timestamping_size_check: SizeCheck::Enabled,
+            use_etag: false,
+            timestamping_continue: false,
+            allow_excess_body: false,
+            write_provenance: None,
+            staging_dir: None,
+            follow_output_symlinks: false,
+            warc_file: None,
+            max_buffered_bytes: None,
+            http_cache: None,
+            retry_on_202: false,
+            request_signer: None,
+            url_refresher: None,
+            response_filter: None,
+            event_callback: None,
+            progress_sink: None,
+            probe_total_size: false,
+            expected_checksum: None,
         }
     }
 }
@@ -220,6 +577,14 @@ impl HttpMethod {
             HttpMethod::Options => "OPTIONS",
         }
     }
+
+    /// Whether requests with this method may carry `body_data`
+    pub fn allows_body(&self) -> bool {
+        matches!(
+            self,
+            HttpMethod::Post | HttpMethod::Put | HttpMethod::Patch | HttpMethod::Delete
+        )
+    }
 }
 
 impl std::str::FromStr for HttpMethod {
@@ -241,15 +606,17 @@ impl std::str::FromStr for HttpMethod {
 }
 
 /// Retry configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RetryConfig {
     /// Maximum number of retries
     pub max_retries: usize,
 
     /// Initial retry delay
+    #[serde(serialize_with = "crate::config_serde::duration")]
     pub initial_delay: Duration,
 
     /// Maximum retry delay
+    #[serde(serialize_with = "crate::config_serde::duration")]
     pub max_delay: Duration,
 
     /// Backoff multiplier
@@ -276,12 +643,29 @@ impl Default for RetryConfig {
 }
 
 /// Proxy configuration
-#[derive(Debug, Clone)]
+///
+/// Each scheme can go through its own proxy (`http_proxy` vs `https_proxy`);
+/// `url` covers schemes without a specific one. A scheme with no proxy at
+/// all is fetched directly.
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ProxyConfig {
-    /// Proxy URL
-    pub url: String,
+    /// Proxy URL for schemes without a specific proxy (`all_proxy`)
+    #[serde(serialize_with = "crate::config_serde::option_url")]
+    pub url: Option<String>,
+
+    /// Proxy URL for `http://` requests (`http_proxy`)
+    #[serde(serialize_with = "crate::config_serde::option_url")]
+    pub http_url: Option<String>,
+
+    /// Proxy URL for `https://` requests (`https_proxy`)
+    #[serde(serialize_with = "crate::config_serde::option_url")]
+    pub https_url: Option<String>,
 
     /// Proxy authentication
+    ///
+    /// Sent once the proxy answers 407 (then remembered for that proxy),
+    /// or up front with `auth_no_challenge`.
+    #[serde(serialize_with = "crate::config_serde::credentials")]
     pub auth: Option<(String, String)>,
 
     /// Domains to bypass proxy for (`no_proxy` list)
@@ -289,6 +673,28 @@ pub struct ProxyConfig {
 }
 
 impl ProxyConfig {
+    /// Use one proxy for every scheme
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: Some(url.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Proxy URL to use for `url`, or `None` to connect directly
+    pub fn proxy_for(&self, url: &str) -> Option<&str> {
+        if self.should_bypass(url) {
+            return None;
+        }
+
+        let scheme_proxy = match url::Url::parse(url).ok()?.scheme() {
+            "http" => self.http_url.as_deref(),
+            "https" => self.https_url.as_deref(),
+            _ => None,
+        };
+        scheme_proxy.or(self.url.as_deref())
+    }
+
     /// Check if a URL should bypass the proxy based on `no_proxy` list
     ///
     /// Implements wget's `no_proxy` matching logic:
@@ -336,12 +742,13 @@ impl ProxyConfig {
 }
 
 /// Authentication configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AuthConfig {
     /// Username
     pub username: String,
 
     /// Password
+    #[serde(serialize_with = "crate::config_serde::secret")]
     pub password: String,
 
     /// Authentication type
@@ -349,7 +756,8 @@ pub struct AuthConfig {
 }
 
 /// HTTP authentication type
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AuthType {
     /// HTTP Basic authentication (Base64-encoded credentials)
     Basic,
@@ -361,7 +769,8 @@ pub enum AuthType {
 ///
 /// Controls how filenames are sanitized and transformed.
 /// Multiple restrictions can be applied simultaneously.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FilenameRestriction {
     /// Convert filenames to lowercase
     Lowercase,
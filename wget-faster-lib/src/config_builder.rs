@@ -0,0 +1,340 @@
+/// Fluent construction of `DownloadConfig` with validation
+use crate::config::{AuthConfig, AuthType, DownloadConfig, HttpMethod, ProxyConfig, RetryConfig};
+use crate::{ContentCoding, Error, Result};
+use reqwest::header::{HeaderName, HeaderValue};
+use std::time::Duration;
+
+impl DownloadConfig {
+    /// Start from the defaults and set only what differs
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use wget_faster_lib::DownloadConfig;
+    ///
+    /// let config = DownloadConfig::builder()
+    ///     .timeout(Duration::from_secs(30))
+    ///     .parallel_chunks(4)
+    ///     .header("X-Api-Key", "secret")
+    ///     .build()?;
+    /// # Ok::<(), wget_faster_lib::Error>(())
+    /// ```
+    pub fn builder() -> DownloadConfigBuilder {
+        DownloadConfigBuilder::default()
+    }
+
+    /// Check that the settings fit together
+    ///
+    /// Called by [`DownloadConfigBuilder::build`]; configs filled in field by
+    /// field can call it themselves.
+    pub fn validate(&self) -> Result<()> {
+        if self.body_data.is_some() && !self.method.allows_body() {
+            return Err(Error::ConfigError(format!(
+                "body data cannot be sent with {} - use POST, PUT, PATCH or DELETE",
+                self.method.as_str()
+            )));
+        }
+        if self.parallel_chunks == 0 {
+            return Err(Error::ConfigError("parallel_chunks must be at least 1".to_string()));
+        }
+        if self.chunk_size == Some(0) {
+            return Err(Error::ConfigError("chunk_size must be at least 1 byte".to_string()));
+        }
+        if let Some(ref proxy) = self.proxy {
+            for url in [&proxy.url, &proxy.http_url, &proxy.https_url]
+                .into_iter()
+                .flatten()
+            {
+                url::Url::parse(url)
+                    .map_err(|e| Error::ConfigError(format!("Invalid proxy URL {url}: {e}")))?;
+            }
+        }
+        for (name, value) in &self.headers {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| Error::ConfigError(format!("Invalid header name: {name:?}")))?;
+            HeaderValue::from_str(value)
+                .map_err(|_| Error::ConfigError(format!("Invalid value for header {name}")))?;
+        }
+        Ok(())
+    }
+}
+
+/// Builder for [`DownloadConfig`], created by [`DownloadConfig::builder`]
+///
+/// Setters never fail; [`build`](Self::build) reports the first invalid
+/// combination as [`Error::ConfigError`].
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct DownloadConfigBuilder {
+    config: DownloadConfig,
+}
+
+impl DownloadConfigBuilder {
+    /// Overall timeout for each request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for receiving the response headers of each request
+    pub fn response_header_timeout(mut self, timeout: Duration) -> Self {
+        self.config.response_header_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for establishing a connection
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.connect_timeout = timeout;
+        self
+    }
+
+    /// Timeout between two reads of a response body
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = timeout;
+        self
+    }
+
+    /// Wall-clock limit on each response body
+    pub fn max_body_duration(mut self, limit: Duration) -> Self {
+        self.config.max_body_duration = Some(limit);
+        self
+    }
+
+    /// Number of parallel Range requests for large files
+    pub fn parallel_chunks(mut self, chunks: usize) -> Self {
+        self.config.parallel_chunks = chunks;
+        self
+    }
+
+    /// Most parallel Range requests once the count adapts to the throughput
+    pub fn max_parallel_chunks(mut self, chunks: usize) -> Self {
+        self.config.max_parallel_chunks = chunks;
+        self
+    }
+
+    /// Fixed chunk size instead of the automatic one
+    pub fn chunk_size(mut self, bytes: u64) -> Self {
+        self.config.chunk_size = Some(bytes);
+        self
+    }
+
+    /// Smallest file size downloaded in parallel
+    pub fn parallel_threshold(mut self, bytes: u64) -> Self {
+        self.config.parallel_threshold = bytes;
+        self
+    }
+
+    /// `User-Agent` header
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.config.user_agent = user_agent.into();
+        self
+    }
+
+    /// HTTP Basic credentials
+    pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.config.auth = Some(AuthConfig {
+            username: username.into(),
+            password: password.into(),
+            auth_type: AuthType::Basic,
+        });
+        self
+    }
+
+    /// HTTP Digest credentials
+    pub fn digest_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.config.auth = Some(AuthConfig {
+            username: username.into(),
+            password: password.into(),
+            auth_type: AuthType::Digest,
+        });
+        self
+    }
+
+    /// Send every request through the proxy at `url`
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.config.proxy = Some(ProxyConfig::new(url));
+        self
+    }
+
+    /// Full proxy configuration (per-scheme proxies, `no_proxy`, credentials)
+    pub fn proxy_config(mut self, proxy: ProxyConfig) -> Self {
+        self.config.proxy = Some(proxy);
+        self
+    }
+
+    /// Extra header sent with every request, replacing an earlier one of the
+    /// same name
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Request method
+    pub fn method(mut self, method: HttpMethod) -> Self {
+        self.config.method = method;
+        self
+    }
+
+    /// Request body, sent with the configured method
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.config.body_data = Some(body.into());
+        self
+    }
+
+    /// POST `body`
+    pub fn post_body(self, body: impl Into<Vec<u8>>) -> Self {
+        self.method(HttpMethod::Post).body(body)
+    }
+
+    /// `Content-Type` of the request body
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.config.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Whether to follow redirects, and how many in a row (0 for none)
+    pub fn redirects(mut self, follow: bool, max: usize) -> Self {
+        self.config.follow_redirects = follow;
+        self.config.max_redirects = max;
+        self
+    }
+
+    /// Retry behaviour
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// Bandwidth limit in bytes per second
+    pub fn speed_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.config.speed_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Whether to verify TLS certificates
+    pub fn verify_ssl(mut self, verify: bool) -> Self {
+        self.config.verify_ssl = verify;
+        self
+    }
+
+    /// Whether to decode content-coded bodies before saving them
+    pub fn decompress(mut self, enabled: bool) -> Self {
+        self.config.decompress = enabled;
+        self
+    }
+
+    /// Content-codings offered in `Accept-Encoding`, most preferred first
+    pub fn accepted_encodings(mut self, codings: Vec<ContentCoding>) -> Self {
+        self.config.accepted_encodings = codings;
+        self
+    }
+
+    /// Whether to keep cookies between requests
+    pub fn cookies(mut self, enabled: bool) -> Self {
+        self.config.enable_cookies = enabled;
+        self
+    }
+
+    /// Only download when the remote file is newer than the local one
+    pub fn timestamping(mut self, enabled: bool) -> Self {
+        self.config.timestamping = enabled;
+        self
+    }
+
+    /// Validated configuration
+    pub fn build(self) -> Result<DownloadConfig> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_error(builder: DownloadConfigBuilder) -> String {
+        match builder.build() {
+            Err(Error::ConfigError(msg)) => msg,
+            other => panic!("expected ConfigError, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_builder_sets_fields() {
+        let config = DownloadConfig::builder()
+            .timeout(Duration::from_secs(5))
+            .parallel_chunks(3)
+            .basic_auth("user", "pass")
+            .proxy("http://proxy.example.com:3128")
+            .header("X-Test", "1")
+            .post_body("a=1")
+            .build()
+            .unwrap();
+
+        assert_eq!(config.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.parallel_chunks, 3);
+        let auth = config.auth.unwrap();
+        assert_eq!((auth.username.as_str(), auth.auth_type), ("user", AuthType::Basic));
+        assert_eq!(config.proxy.unwrap().url.as_deref(), Some("http://proxy.example.com:3128"));
+        assert_eq!(config.headers.get("X-Test").map(String::as_str), Some("1"));
+        assert_eq!(config.method, HttpMethod::Post);
+        assert_eq!(config.body_data.as_deref(), Some(&b"a=1"[..]));
+    }
+
+    #[test]
+    fn test_default_builder_is_valid() {
+        assert!(DownloadConfig::builder().build().is_ok());
+    }
+
+    #[test]
+    fn test_body_requires_body_method() {
+        let msg = config_error(DownloadConfig::builder().body("x"));
+        assert!(msg.contains("GET"), "{msg}");
+        let msg = config_error(
+            DownloadConfig::builder()
+                .post_body("x")
+                .method(HttpMethod::Head),
+        );
+        assert!(msg.contains("HEAD"), "{msg}");
+
+        for method in [HttpMethod::Put, HttpMethod::Patch, HttpMethod::Delete] {
+            assert!(DownloadConfig::builder()
+                .method(method)
+                .body("x")
+                .build()
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn test_zero_max_redirects_is_valid() {
+        // A limit of 0 means the 3xx response itself is the result
+        for follow in [true, false] {
+            assert!(DownloadConfig::builder()
+                .redirects(follow, 0)
+                .build()
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn test_chunking_must_be_positive() {
+        let msg = config_error(DownloadConfig::builder().parallel_chunks(0));
+        assert!(msg.contains("parallel_chunks"), "{msg}");
+        let msg = config_error(DownloadConfig::builder().chunk_size(0));
+        assert!(msg.contains("chunk_size"), "{msg}");
+    }
+
+    #[test]
+    fn test_proxy_url_must_parse() {
+        let msg = config_error(DownloadConfig::builder().proxy("not a url"));
+        assert!(msg.contains("proxy"), "{msg}");
+    }
+
+    #[test]
+    fn test_headers_must_be_valid() {
+        let msg = config_error(DownloadConfig::builder().header("Bad Name", "1"));
+        assert!(msg.contains("header name"), "{msg}");
+        let msg = config_error(DownloadConfig::builder().header("X-Test", "line\nbreak"));
+        assert!(msg.contains("X-Test"), "{msg}");
+    }
+}
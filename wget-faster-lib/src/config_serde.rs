@@ -0,0 +1,191 @@
+/// Serializers for the fields of `DownloadConfig` and `RecursiveConfig`
+///
+/// The configs serialize to show what a run was set up with (`--show-config`,
+/// debug logs), so the output must be stable and safe to paste into a ticket:
+/// - Durations are seconds (`30.0`)
+/// - Passwords, credential headers and request bodies are masked
+/// - Proxy URLs lose their user and password
+/// - Callbacks serialize as `"<callback>"` (or `null` when unset)
+/// - Header maps are sorted by name
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Replacement for secret values
+pub(crate) const MASK: &str = "***";
+
+/// Placeholder for a callback, which has no readable form
+const CALLBACK: &str = "<callback>";
+
+/// Header names whose values are credentials (lowercase; substrings for the
+/// token/key families such as `X-Api-Key` or `X-Auth-Token`)
+const SECRET_HEADERS: [&str; 6] = [
+    "authorization",
+    "cookie",
+    "token",
+    "secret",
+    "api-key",
+    "apikey",
+];
+
+pub(crate) fn duration<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(value.as_secs_f64())
+}
+
+#[allow(clippy::ref_option)] // serde's serialize_with passes &Option
+pub(crate) fn option_duration<S: Serializer>(
+    value: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => duration(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// A password or token: masked unless empty
+pub(crate) fn secret<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(if value.is_empty() { "" } else { MASK })
+}
+
+/// A request body, which may hold credentials (`--post-data 'password=...'`)
+#[allow(clippy::ref_option)] // serde's serialize_with passes &Option
+pub(crate) fn body<S: Serializer>(
+    value: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(body) => serializer.serialize_str(&format!("{MASK} ({} bytes)", body.len())),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// An optional URL, without the credentials it may embed
+#[allow(clippy::ref_option)] // serde's serialize_with passes &Option
+pub(crate) fn option_url<S: Serializer>(
+    value: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value
+        .as_deref()
+        .map(crate::provenance::redact_url)
+        .serialize(serializer)
+}
+
+/// Proxy user and password, as `{"username": ..., "password": "***"}`
+#[allow(clippy::ref_option)] // serde's serialize_with passes &Option
+pub(crate) fn credentials<S: Serializer>(
+    value: &Option<(String, String)>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let Some((username, password)) = value else {
+        return serializer.serialize_none();
+    };
+    let mut map = serializer.serialize_map(Some(2))?;
+    map.serialize_entry("username", username)?;
+    map.serialize_entry("password", if password.is_empty() { "" } else { MASK })?;
+    map.end()
+}
+
+/// Headers sorted by name, with credential values masked
+pub(crate) fn headers<S: Serializer>(
+    value: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut names: Vec<&String> = value.keys().collect();
+    names.sort_unstable_by_key(|name| name.to_ascii_lowercase());
+    let mut map = serializer.serialize_map(Some(names.len()))?;
+    for name in names {
+        let lowercase = name.to_ascii_lowercase();
+        if SECRET_HEADERS
+            .iter()
+            .any(|secret| lowercase.contains(secret))
+        {
+            map.serialize_entry(name, MASK)?;
+        } else {
+            map.serialize_entry(name, &value[name])?;
+        }
+    }
+    map.end()
+}
+
+/// Form fields, sorted by name, with every value masked (they carry CSRF
+/// tokens and the like)
+#[cfg(feature = "recursive")]
+pub(crate) fn masked_fields<S: Serializer>(
+    value: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut names: Vec<&String> = value.keys().collect();
+    names.sort_unstable();
+    let mut map = serializer.serialize_map(Some(names.len()))?;
+    for name in names {
+        map.serialize_entry(name, MASK)?;
+    }
+    map.end()
+}
+
+/// An optional callback or trait object
+#[allow(clippy::ref_option)] // serde's serialize_with passes &Option
+pub(crate) fn callback<T, S: Serializer>(
+    value: &Option<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value.as_ref().map(|_| CALLBACK).serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AuthConfig, AuthType, DownloadConfig, ProxyConfig};
+
+    #[test]
+    fn test_secrets_are_masked() {
+        let mut config = DownloadConfig {
+            auth: Some(AuthConfig {
+                username: "alice".to_string(),
+                password: "hunter2".to_string(),
+                auth_type: AuthType::Basic,
+            }),
+            proxy: Some(ProxyConfig {
+                url: Some("http://bob:pw@proxy:3128/".to_string()),
+                auth: Some(("bob".to_string(), "pw".to_string())),
+                ..ProxyConfig::default()
+            }),
+            body_data: Some(b"user=alice&password=hunter2".to_vec()),
+            ..DownloadConfig::default()
+        };
+        config
+            .headers
+            .insert("Authorization".to_string(), "Bearer s3cr3t-token".to_string());
+        config
+            .headers
+            .insert("X-Api-Key".to_string(), "k".to_string());
+        config
+            .headers
+            .insert("Accept".to_string(), "text/html".to_string());
+
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(value["auth"]["username"], "alice");
+        assert_eq!(value["auth"]["password"], "***");
+        assert_eq!(value["proxy"]["url"], "http://proxy:3128/");
+        assert_eq!(value["proxy"]["auth"]["password"], "***");
+        assert_eq!(value["body_data"], "*** (27 bytes)");
+        assert_eq!(value["headers"]["Authorization"], "***");
+        assert_eq!(value["headers"]["X-Api-Key"], "***");
+        assert_eq!(value["headers"]["Accept"], "text/html");
+        let text = value.to_string();
+        assert!(!text.contains("hunter2") && !text.contains("s3cr3t"), "{text}");
+    }
+
+    #[test]
+    fn test_durations_and_callbacks() {
+        let value = serde_json::to_value(DownloadConfig::default()).unwrap();
+        assert_eq!(value["connect_timeout"], 30.0);
+        assert_eq!(value["timeout"], serde_json::Value::Null);
+        assert_eq!(value["retry"]["initial_delay"], 1.0);
+        assert_eq!(value["credential_provider"], serde_json::Value::Null);
+        assert_eq!(value["method"], "get");
+        assert_eq!(value["referer_policy"], "no_referrer_when_downgrade");
+    }
+}
@@ -0,0 +1,371 @@
+/// Decoding content-coded bodies as they stream in (`DownloadConfig::decompress`)
+///
+/// Bodies are saved as received unless `decompress` is set, like wget. With
+/// it, a body sent with `Content-Encoding: gzip`, `deflate`, `br` or (with the
+/// `zstd` feature) `zstd` is decoded on the way to its destination, and its
+/// Content-Length (the coded size) no longer tells how much will be saved.
+/// Stacked or unknown codings are always saved as received.
+///
+/// The codings offered in `Accept-Encoding` are `DownloadConfig::accepted_encodings`,
+/// in order of preference.
+use crate::{DownloadConfig, Error, Result};
+use bytes::Bytes;
+use futures::stream::{self, BoxStream, StreamExt};
+use reqwest::header::HeaderValue;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// Output buffer of the brotli decoder
+const BROTLI_BUFFER: usize = 64 * 1024;
+
+/// Content-coding a body can be decoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentCoding {
+    /// `gzip` (and `x-gzip`)
+    Gzip,
+
+    /// `deflate`: zlib, or the raw deflate some servers send
+    Deflate,
+
+    /// `br`
+    #[serde(rename = "br")]
+    Brotli,
+
+    /// `zstd`
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl ContentCoding {
+    /// Every coding this build decodes, in the default order of preference
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::Gzip,
+            Self::Deflate,
+            Self::Brotli,
+            #[cfg(feature = "zstd")]
+            Self::Zstd,
+        ]
+    }
+
+    /// Name of the coding in `Content-Encoding` and `Accept-Encoding`
+    pub fn token(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+            Self::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Coding named by a Content-Encoding value; `None` for `identity`, unknown or stacked codings
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            "br" => Some(Self::Brotli),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Coding the body of `response` is decoded from, if `decompress` is set
+    pub(crate) fn of_response(
+        response: &reqwest::Response,
+        config: &DownloadConfig,
+    ) -> Option<Self> {
+        if !config.decompress {
+            return None;
+        }
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::parse)
+    }
+}
+
+/// `Accept-Encoding` sent with every request
+///
+/// `identity` when compression is off or no coding is accepted; otherwise the
+/// accepted codings, most preferred first, each named once.
+pub(crate) fn accept_encoding(config: &DownloadConfig) -> HeaderValue {
+    let mut tokens: Vec<&str> = Vec::new();
+    for coding in config
+        .accepted_encodings
+        .iter()
+        .filter(|_| config.enable_compression)
+    {
+        if !tokens.contains(&coding.token()) {
+            tokens.push(coding.token());
+        }
+    }
+    if tokens.is_empty() {
+        return HeaderValue::from_static("identity");
+    }
+    HeaderValue::from_str(&tokens.join(", "))
+        .unwrap_or_else(|_| HeaderValue::from_static("identity"))
+}
+
+/// Length of the body of `response` as it will be saved, if known
+///
+/// The Content-Length, unless the body is decoded.
+pub(crate) fn saved_length(response: &reqwest::Response, config: &DownloadConfig) -> Option<u64> {
+    response
+        .content_length()
+        .filter(|_| ContentCoding::of_response(response, config).is_none())
+}
+
+/// The body of `response`, decoded if `decompress` applies to it
+pub(crate) fn body_stream(
+    response: reqwest::Response,
+    config: &DownloadConfig,
+) -> BoxStream<'static, Result<Bytes>> {
+    let coding = ContentCoding::of_response(&response, config);
+    let chunks = response
+        .bytes_stream()
+        .map(|chunk| chunk.map_err(Error::from))
+        .boxed();
+    decode(chunks, coding)
+}
+
+/// `chunks` decoded from `coding` (unchanged for `None`)
+///
+/// A body that fails to decode ends the stream with an `InvalidData` error.
+pub(crate) fn decode(
+    chunks: BoxStream<'static, Result<Bytes>>,
+    coding: Option<ContentCoding>,
+) -> BoxStream<'static, Result<Bytes>> {
+    let Some(coding) = coding else {
+        return chunks;
+    };
+    let decoder = match Decoder::new(coding) {
+        Ok(decoder) => decoder,
+        Err(e) => return stream::once(async move { Err(invalid_body(&e)) }).boxed(),
+    };
+    stream::unfold(Some((chunks, decoder)), |state| async move {
+        let (mut chunks, mut decoder) = state?;
+        match chunks.next().await {
+            Some(Ok(chunk)) => {
+                let output = decoder.decode(&chunk).map_err(|e| invalid_body(&e));
+                let state = output.is_ok().then_some((chunks, decoder));
+                Some((output, state))
+            },
+            Some(Err(e)) => Some((Err(e), None)),
+            None => Some((decoder.finish().map_err(|e| invalid_body(&e)), None)),
+        }
+    })
+    .boxed()
+}
+
+fn invalid_body(e: &io::Error) -> Error {
+    Error::IoError(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("content-coded body can't be decoded: {e}"),
+    ))
+}
+
+/// zstd push decoder writing to a buffer
+#[cfg(feature = "zstd")]
+type ZstdWriter = zstd::stream::zio::Writer<Vec<u8>, zstd::stream::raw::Decoder<'static>>;
+
+/// Push decoder of one body
+enum Decoder {
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+    Zlib(flate2::write::ZlibDecoder<Vec<u8>>),
+    RawDeflate(flate2::write::DeflateDecoder<Vec<u8>>),
+    Brotli(Box<brotli::DecompressorWriter<Vec<u8>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(Box<ZstdWriter>),
+    /// `deflate` before its first byte, which tells zlib (RFC 9110) from the
+    /// raw deflate some servers send
+    Deflate,
+}
+
+impl Decoder {
+    fn new(coding: ContentCoding) -> io::Result<Self> {
+        Ok(match coding {
+            ContentCoding::Gzip => Self::Gzip(flate2::write::GzDecoder::new(Vec::new())),
+            ContentCoding::Deflate => Self::Deflate,
+            ContentCoding::Brotli => {
+                Self::Brotli(Box::new(brotli::DecompressorWriter::new(Vec::new(), BROTLI_BUFFER)))
+            },
+            #[cfg(feature = "zstd")]
+            ContentCoding::Zstd => {
+                let decoder = zstd::stream::raw::Decoder::new()?;
+                Self::Zstd(Box::new(zstd::stream::zio::Writer::new(Vec::new(), decoder)))
+            },
+        })
+    }
+
+    /// Decode `chunk`, returning the output available so far
+    fn decode(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        if matches!(self, Self::Deflate) {
+            if chunk.is_empty() {
+                return Ok(Bytes::new());
+            }
+            *self = if is_zlib_header(chunk) {
+                Self::Zlib(flate2::write::ZlibDecoder::new(Vec::new()))
+            } else {
+                Self::RawDeflate(flate2::write::DeflateDecoder::new(Vec::new()))
+            };
+        }
+        let output = match self {
+            Self::Gzip(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.get_mut()
+            },
+            Self::Zlib(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.get_mut()
+            },
+            Self::RawDeflate(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.get_mut()
+            },
+            Self::Brotli(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.get_mut()
+            },
+            #[cfg(feature = "zstd")]
+            Self::Zstd(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.writer_mut()
+            },
+            Self::Deflate => return Ok(Bytes::new()),
+        };
+        Ok(Bytes::from(std::mem::take(output)))
+    }
+
+    /// Rest of the output, once the whole body was decoded; fails on a truncated body
+    fn finish(self) -> io::Result<Bytes> {
+        let rest = match self {
+            Self::Gzip(decoder) => decoder.finish()?,
+            Self::Zlib(decoder) => decoder.finish()?,
+            Self::RawDeflate(decoder) => decoder.finish()?,
+            Self::Brotli(mut decoder) => {
+                decoder.close()?;
+                std::mem::take(decoder.get_mut())
+            },
+            #[cfg(feature = "zstd")]
+            Self::Zstd(mut decoder) => {
+                // Unlike flushing, finishing fails on an incomplete frame
+                decoder.finish()?;
+                decoder.into_inner().0
+            },
+            Self::Deflate => Vec::new(),
+        };
+        Ok(Bytes::from(rest))
+    }
+}
+
+/// Whether a deflate body starts with a zlib header (RFC 1950)
+fn is_zlib_header(body: &[u8]) -> bool {
+    let method_is_deflate = body[0] & 0x0f == 8;
+    let check = body
+        .get(1)
+        .is_none_or(|&flags| (u16::from(body[0]) << 8 | u16::from(flags)) % 31 == 0);
+    method_is_deflate && check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode `body` fed in chunks of `size` bytes
+    async fn decode_in_chunks(body: &[u8], coding: ContentCoding, size: usize) -> Result<Vec<u8>> {
+        let chunks: Vec<Result<Bytes>> = body
+            .chunks(size)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        let mut decoded = decode(stream::iter(chunks).boxed(), Some(coding));
+        let mut out = Vec::new();
+        while let Some(chunk) = decoded.next().await {
+            out.extend_from_slice(&chunk?);
+        }
+        Ok(out)
+    }
+
+    #[tokio::test]
+    async fn test_decode_each_coding() {
+        let text = b"hello hello hello hello, compressed world".repeat(50);
+        let compression = flate2::Compression::default();
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), compression);
+        gzip.write_all(&text).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), compression);
+        zlib.write_all(&text).unwrap();
+        let zlib = zlib.finish().unwrap();
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), compression);
+        raw.write_all(&text).unwrap();
+        let raw = raw.finish().unwrap();
+        let mut brotli = Vec::new();
+        brotli::BrotliCompress(&mut &text[..], &mut brotli, &Default::default()).unwrap();
+
+        for (body, coding) in [
+            (&gzip, ContentCoding::Gzip),
+            (&zlib, ContentCoding::Deflate),
+            (&raw, ContentCoding::Deflate),
+            (&brotli, ContentCoding::Brotli),
+        ] {
+            for size in [1, 7, body.len()] {
+                let decoded = decode_in_chunks(body, coding, size).await.unwrap();
+                assert_eq!(decoded, text, "{coding:?} in chunks of {size}");
+            }
+        }
+
+        // A truncated body fails instead of saving part of the content silently
+        let truncated = &gzip[..gzip.len() / 2];
+        assert!(decode_in_chunks(truncated, ContentCoding::Gzip, 16)
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn test_decode_zstd() {
+        let text = b"hello hello hello hello, compressed world".repeat(50);
+        let body = zstd::encode_all(&text[..], 0).unwrap();
+
+        for size in [1, 7, body.len()] {
+            let decoded = decode_in_chunks(&body, ContentCoding::Zstd, size)
+                .await
+                .unwrap();
+            assert_eq!(decoded, text, "zstd in chunks of {size}");
+        }
+        let truncated = &body[..body.len() / 2];
+        assert!(decode_in_chunks(truncated, ContentCoding::Zstd, 16)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_accept_encoding() {
+        let header = |enable_compression, accepted_encodings| {
+            let config = DownloadConfig {
+                enable_compression,
+                accepted_encodings,
+                ..DownloadConfig::default()
+            };
+            accept_encoding(&config).to_str().unwrap().to_string()
+        };
+        let all = ContentCoding::all();
+        assert!(header(true, all.clone()).starts_with("gzip, deflate, br"));
+        assert_eq!(header(false, all), "identity");
+        assert_eq!(header(true, vec![ContentCoding::Gzip, ContentCoding::Gzip]), "gzip");
+        assert_eq!(header(true, Vec::new()), "identity");
+    }
+
+    #[test]
+    fn test_parse_coding() {
+        assert_eq!(ContentCoding::parse("GZIP"), Some(ContentCoding::Gzip));
+        assert_eq!(ContentCoding::parse("x-gzip"), Some(ContentCoding::Gzip));
+        assert_eq!(ContentCoding::parse(" br "), Some(ContentCoding::Brotli));
+        assert_eq!(ContentCoding::parse("identity"), None);
+        assert_eq!(ContentCoding::parse("gzip, br"), None);
+    }
+}
@@ -0,0 +1,110 @@
+/// Cancelling and pausing a running download (`Downloader::download_controlled`)
+///
+/// The download runs with the receiving end of its handle's watch channel in
+/// a task-local, so the chunk loops of the sequential and parallel paths can
+/// check it without the control being passed down to them. Downloads started
+/// any other way have no control and never wait.
+use crate::{Error, Result};
+use std::future::Future;
+use tokio::sync::watch;
+
+tokio::task_local! {
+    static CONTROL: watch::Receiver<State>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// Controls one download started with `Downloader::download_controlled`
+///
+/// Dropping the handle lets the download run to the end, resuming it if paused.
+#[derive(Debug)]
+pub struct DownloadHandle {
+    state: watch::Sender<State>,
+}
+
+impl DownloadHandle {
+    /// Handle and the control it drives, which `future` runs with
+    pub(crate) fn new() -> (Self, Control) {
+        let (state, receiver) = watch::channel(State::Running);
+        (Self { state }, Control { receiver })
+    }
+
+    /// Stop the download as soon as possible
+    ///
+    /// It fails with `Error::Cancelled`. A file download keeps what was
+    /// written so far, so downloading the URL to the same file again resumes it.
+    pub fn cancel(&self) {
+        self.state.send_replace(State::Cancelled);
+    }
+
+    /// Stop requesting and reading body data until [`resume`](Self::resume)
+    ///
+    /// Connections are left open; a read already waiting for data completes first.
+    pub fn pause(&self) {
+        self.transition(State::Running, State::Paused);
+    }
+
+    /// Continue a paused download
+    pub fn resume(&self) {
+        self.transition(State::Paused, State::Running);
+    }
+
+    /// Whether the download is paused
+    pub fn is_paused(&self) -> bool {
+        *self.state.borrow() == State::Paused
+    }
+
+    /// Whether the download was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        *self.state.borrow() == State::Cancelled
+    }
+
+    fn transition(&self, from: State, to: State) {
+        self.state.send_if_modified(|state| {
+            let change = *state == from;
+            if change {
+                *state = to;
+            }
+            change
+        });
+    }
+}
+
+/// The receiving side of a [`DownloadHandle`]
+pub(crate) struct Control {
+    receiver: watch::Receiver<State>,
+}
+
+impl Control {
+    /// Run `future` with this control applying to the chunk loops inside it
+    pub(crate) async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTROL.scope(self.receiver, future).await
+    }
+}
+
+/// Run `future` (a request or the read of a chunk) under the current control
+///
+/// Waits first while the download is paused. Fails with `Error::Cancelled`
+/// if it is cancelled before or while `future` runs.
+pub(crate) async fn run<F: Future>(future: F) -> Result<F::Output> {
+    let Ok(mut receiver) = CONTROL.try_with(watch::Receiver::clone) else {
+        return Ok(future.await);
+    };
+    // A dropped handle lets the download go on
+    let cancelled = receiver
+        .wait_for(|state| *state != State::Paused)
+        .await
+        .is_ok_and(|state| *state == State::Cancelled);
+    if cancelled {
+        return Err(Error::Cancelled);
+    }
+    tokio::select! {
+        output = future => Ok(output),
+        Ok(_) = receiver.wait_for(|state| *state == State::Cancelled) => Err(Error::Cancelled),
+    }
+}
@@ -1,9 +1,9 @@
-use crate::Result;
+use crate::{Error, Result};
 use std::collections::HashMap;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::AsyncWriteExt;
 
 /// An HTTP cookie with all its attributes
 ///
@@ -49,6 +49,21 @@ pub struct Cookie {
     pub value: String,
 }
 
+impl Cookie {
+    /// Whether the cookie's expiration time has been reached (never for session cookies)
+    pub fn is_expired(&self) -> bool {
+        let Some(expiration) = self.expiration else {
+            return false;
+        };
+        // Safe: System time should never be before UNIX_EPOCH (1970-01-01)
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time should be after UNIX epoch")
+            .as_secs();
+        now >= expiration
+    }
+}
+
 /// Cookie jar for managing HTTP cookies
 ///
 /// Stores and manages cookies according to domain and path rules.
@@ -86,36 +101,63 @@ impl CookieJar {
         }
     }
 
-    /// Add a cookie to the jar
+    /// Add a cookie to the jar, replacing any with the same domain, path and name
+    ///
+    /// An expired cookie isn't stored: it deletes the one it would replace,
+    /// which is how servers remove cookies (RFC 6265 section 5.3).
     pub fn add_cookie(&mut self, cookie: Cookie) {
         let domain_key = cookie.domain.to_lowercase();
-        self.cookies.entry(domain_key).or_default().push(cookie);
+        let cookies = self.cookies.entry(domain_key.clone()).or_default();
+        let existing = cookies
+            .iter()
+            .position(|c| c.name == cookie.name && c.path == cookie.path);
+        match (existing, cookie.is_expired()) {
+            (Some(i), true) => {
+                cookies.remove(i);
+            },
+            (Some(i), false) => cookies[i] = cookie,
+            (None, true) => {},
+            (None, false) => cookies.push(cookie),
+        }
+        if cookies.is_empty() {
+            self.cookies.remove(&domain_key);
+        }
+    }
+
+    /// Keep only the cookies for which `keep` returns true
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&Cookie) -> bool) {
+        for cookies in self.cookies.values_mut() {
+            cookies.retain(&mut keep);
+        }
+        self.cookies.retain(|_, cookies| !cookies.is_empty());
     }
 
     /// Get cookies for a domain
+    ///
+    /// At most one cookie per name is returned: the one with the longest path.
     pub fn get_cookies_for_domain(&self, domain: &str) -> Vec<&Cookie> {
+        one_per_name(self.matching_domain(domain))
+    }
+
+    /// Unexpired cookies for a domain, longest path first
+    fn matching_domain(&self, domain: &str) -> Vec<&Cookie> {
         let domain_lower = domain.to_lowercase();
         let mut result = Vec::new();
 
         for (jar_domain, cookies) in &self.cookies {
             if domain_matches(&domain_lower, jar_domain) {
-                for cookie in cookies {
-                    // Check if cookie is expired
-                    if let Some(expiration) = cookie.expiration {
-                        // Safe: System time should never be before UNIX_EPOCH (1970-01-01)
-                        let now = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .expect("System time should be after UNIX epoch")
-                            .as_secs();
-                        if now > expiration {
-                            continue; // Skip expired cookies
-                        }
-                    }
-                    result.push(cookie);
-                }
+                // Skip expired cookies
+                result.extend(cookies.iter().filter(|cookie| !cookie.is_expired()));
             }
         }
 
+        // Longer paths first (RFC 6265 section 5.4), then the more specific domain
+        result.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then_with(|| b.domain.len().cmp(&a.domain.len()))
+        });
         result
     }
 
@@ -141,12 +183,17 @@ impl CookieJar {
     /// Each line contains tab-separated fields:
     /// `domain` `flag` `path` `secure` `expiration` `name` `value`
     pub async fn load_from_file(path: &Path) -> Result<Self> {
-        let file = File::open(path).await?;
-        let reader = BufReader::new(file);
+        let content = tokio::fs::read_to_string(path).await.map_err(|e| {
+            Error::from(e).with_context(format!("while loading cookie file {}", path.display()))
+        })?;
+        Ok(Self::parse_netscape(&content))
+    }
+
+    /// Parse the contents of a Netscape format cookie file, skipping malformed lines
+    pub(crate) fn parse_netscape(content: &str) -> Self {
         let mut jar = CookieJar::new();
 
-        let mut lines = reader.lines();
-        while let Some(line) = lines.next_line().await? {
+        for line in content.lines() {
             let line = line.trim();
 
             // Skip comments and empty lines
@@ -164,7 +211,8 @@ impl CookieJar {
             let include_subdomains = parts[1] == "TRUE";
             let path = parts[2].to_string();
             let secure = parts[3] == "TRUE";
-            let expiration = parts[4].parse::<u64>().ok();
+            // 0 marks a session cookie
+            let expiration = parts[4].parse::<u64>().ok().filter(|&e| e != 0);
             let name = parts[5].to_string();
             let value = parts[6].to_string();
 
@@ -179,12 +227,13 @@ impl CookieJar {
             });
         }
 
-        Ok(jar)
+        jar
     }
 
     /// Save cookies to a Netscape format file
     ///
     /// Writes all cookies to a file in Netscape cookie format (compatible with wget/curl).
+    /// Cookies that have expired are left out.
     ///
     /// # Arguments
     ///
@@ -203,7 +252,7 @@ impl CookieJar {
 
         // Write cookies
         for cookies in self.cookies.values() {
-            for cookie in cookies {
+            for cookie in cookies.iter().filter(|c| !c.is_expired()) {
                 let line = format!(
                     "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
                     cookie.domain,
@@ -229,7 +278,8 @@ impl CookieJar {
     /// Convert cookies to a Cookie header value
     ///
     /// Builds a Cookie header value for a specific domain, path, and security context.
-    /// Only includes cookies that match the domain/path and aren't expired.
+    /// Only includes cookies that match the domain/path and aren't expired, at
+    /// most one per name, longest path first.
     ///
     /// # Arguments
     ///
@@ -261,23 +311,17 @@ impl CookieJar {
     /// assert_eq!(header, Some("session=abc123".to_string()));
     /// ```
     pub fn to_cookie_header(&self, domain: &str, path: &str, secure: bool) -> Option<String> {
-        let cookies = self.get_cookies_for_domain(domain);
-
-        let mut matching_cookies = Vec::new();
-
-        for cookie in cookies {
-            // Check path matching
-            if !path.starts_with(&cookie.path) {
-                continue;
-            }
-
-            // Check secure flag
-            if cookie.secure && !secure {
-                continue;
-            }
+        let cookies = self
+            .matching_domain(domain)
+            .into_iter()
+            // Check path matching and secure flag
+            .filter(|cookie| path.starts_with(&cookie.path) && (secure || !cookie.secure))
+            .collect();
 
-            matching_cookies.push(format!("{}={}", cookie.name, cookie.value));
-        }
+        let matching_cookies: Vec<String> = one_per_name(cookies)
+            .into_iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
 
         if matching_cookies.is_empty() {
             None
@@ -290,6 +334,8 @@ impl CookieJar {
     ///
     /// Parses a Set-Cookie header value and adds the cookie to the jar.
     /// Supports standard cookie attributes like Domain, Path, Secure, Max-Age.
+    /// Max-Age wins over Expires, and a cookie that is already expired
+    /// (such as `Max-Age=0`) deletes the stored one of the same name.
     ///
     /// # Arguments
     ///
@@ -339,6 +385,7 @@ impl CookieJar {
         };
 
         // Parse attributes
+        let mut max_age = None;
         for part in &parts[1..] {
             let part = part.trim();
 
@@ -395,21 +442,39 @@ impl CookieJar {
                     }
                 }
             } else if part.to_lowercase().starts_with("max-age=") {
-                if let Ok(max_age) = part[8..].trim().parse::<u64>() {
-                    // Safe: System time should never be before UNIX_EPOCH (1970-01-01)
-                    let now = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("System time should be after UNIX epoch")
-                        .as_secs();
-                    cookie.expiration = Some(now + max_age);
+                if let Ok(seconds) = part[8..].trim().parse::<i64>() {
+                    max_age = Some(seconds);
                 }
             }
         }
 
+        match max_age {
+            // Zero or negative: expire at once
+            Some(seconds) if seconds <= 0 => cookie.expiration = Some(0),
+            Some(seconds) => {
+                // Safe: System time should never be before UNIX_EPOCH (1970-01-01)
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("System time should be after UNIX epoch")
+                    .as_secs();
+                cookie.expiration = Some(now.saturating_add(seconds.unsigned_abs()));
+            },
+            None => {},
+        }
+
         self.add_cookie(cookie);
     }
 }
 
+/// Keep the first cookie of each name
+fn one_per_name(cookies: Vec<&Cookie>) -> Vec<&Cookie> {
+    let mut seen = std::collections::HashSet::new();
+    cookies
+        .into_iter()
+        .filter(|cookie| seen.insert(cookie.name.as_str()))
+        .collect()
+}
+
 /// Check if a domain matches a cookie domain
 fn domain_matches(request_domain: &str, cookie_domain: &str) -> bool {
     if request_domain == cookie_domain {
@@ -486,7 +551,7 @@ mod tests {
             "future=value; Expires=Wed, 21 Oct 2099 07:28:00 GMT",
         );
 
-        // Test past expiry date (should be stored but filtered out when retrieved)
+        // Test past expiry date (should not be stored)
         jar.add_from_set_cookie(
             "example.com",
             "expired=value; Expires=Sun, 06 Nov 2001 12:32:43 GMT",
@@ -495,21 +560,100 @@ mod tests {
         // Test no expiry (session cookie, should be kept)
         jar.add_from_set_cookie("example.com", "session=value");
 
-        // get_cookies_for_domain filters out expired cookies
         let cookies = jar.get_cookies_for_domain("example.com");
 
-        // Should have 2 cookies (future and session), expired should be filtered out
+        // Should have 2 cookies (future and session)
         assert_eq!(cookies.len(), 2);
 
         let names: Vec<&str> = cookies.iter().map(|c| c.name.as_str()).collect();
         assert!(names.contains(&"future"));
         assert!(names.contains(&"session"));
-        assert!(!names.contains(&"expired"));
 
-        // Verify the expired cookie has an expiration set
-        let all_cookies: Vec<&Cookie> = jar.cookies.values().flatten().collect();
-        let expired_cookie = all_cookies.iter().find(|c| c.name == "expired").unwrap();
-        assert!(expired_cookie.expiration.is_some());
+        // The expired cookie was never stored
+        assert!(jar.cookies.values().flatten().all(|c| c.name != "expired"));
+    }
+
+    #[test]
+    fn test_set_cookie_overwrites_same_name_and_path() {
+        let mut jar = CookieJar::new();
+        jar.add_from_set_cookie("example.com", "token=first; Path=/");
+        jar.add_from_set_cookie("example.com", "token=second; Path=/; Max-Age=3600");
+
+        let cookies = jar.get_cookies_for_domain("example.com");
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].value, "second");
+        assert!(cookies[0].expiration.is_some());
+        assert_eq!(
+            jar.to_cookie_header("example.com", "/", false),
+            Some("token=second".to_string())
+        );
+    }
+
+    #[test]
+    fn test_max_age_zero_deletes_cookie() {
+        let mut jar = CookieJar::new();
+        jar.add_from_set_cookie("example.com", "sid=abc; Path=/");
+        jar.add_from_set_cookie("example.com", "theme=dark; Path=/");
+
+        // Max-Age wins over a future Expires
+        jar.add_from_set_cookie(
+            "example.com",
+            "sid=; Path=/; Expires=Wed, 21 Oct 2099 07:28:00 GMT; Max-Age=0",
+        );
+        jar.add_from_set_cookie("example.com", "theme=gone; Path=/; Max-Age=-1");
+
+        assert!(jar.get_cookies_for_domain("example.com").is_empty());
+        assert!(jar.cookies.is_empty());
+    }
+
+    #[test]
+    fn test_same_name_on_nested_paths() {
+        let mut jar = CookieJar::new();
+        jar.add_from_set_cookie("example.com", "lang=en; Path=/");
+        jar.add_from_set_cookie("example.com", "lang=de; Path=/docs");
+        jar.add_from_set_cookie("example.com", "sid=1; Path=/");
+
+        // One cookie per name, the longest path first
+        let cookies = jar.get_cookies_for_domain("example.com");
+        let pairs: Vec<(&str, &str)> = cookies
+            .iter()
+            .map(|c| (c.name.as_str(), c.value.as_str()))
+            .collect();
+        assert_eq!(pairs, [("lang", "de"), ("sid", "1")]);
+
+        assert_eq!(
+            jar.to_cookie_header("example.com", "/docs/intro", false),
+            Some("lang=de; sid=1".to_string())
+        );
+        // Outside /docs, the cookie for / applies
+        assert_eq!(
+            jar.to_cookie_header("example.com", "/blog", false),
+            Some("lang=en; sid=1".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_to_file_leaves_out_expired_cookies() {
+        let mut jar = CookieJar::new();
+        jar.add_from_set_cookie("example.com", "live=1; Max-Age=3600");
+        // Expired since it was added
+        jar.cookies.get_mut("example.com").unwrap().push(Cookie {
+            domain: "example.com".to_string(),
+            include_subdomains: false,
+            path: "/".to_string(),
+            secure: false,
+            expiration: Some(1),
+            name: "stale".to_string(),
+            value: "1".to_string(),
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cookies.txt");
+        jar.save_to_file(&path).await.unwrap();
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("\tlive\t1"));
+        assert!(!saved.contains("stale"));
     }
 
     #[test]
@@ -529,26 +673,8 @@ mod tests {
             "sess-id=0213; path=/; Expires=Sun, 06 Nov 2001 12:32:43 GMT",
         );
 
-        // The expired cookie should not be included
-        // Since we're adding, not replacing, we'll have both. The jar needs deduplication logic.
-        // For now, let's just test that expired cookies are filtered
-        let cookies = jar.get_cookies_for_domain("localhost");
-        let active_cookies: Vec<_> = cookies
-            .iter()
-            .filter(|c| {
-                if let Some(exp) = c.expiration {
-                    let now = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs();
-                    exp > now
-                } else {
-                    true
-                }
-            })
-            .collect();
-
-        // Should have only the non-expired one
-        assert_eq!(active_cookies.len(), 1);
+        // The expired cookie replaces the live one, deleting it
+        assert!(jar.get_cookies_for_domain("localhost").is_empty());
+        assert_eq!(jar.to_cookie_header("localhost", "/", false), None);
     }
 }
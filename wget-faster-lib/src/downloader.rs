@@ -1,10 +1,22 @@
+use crate::checksum::HashingWriter;
+use crate::clobber::{LazyFile, OutputFile, ReplacedFiles};
+use crate::content_coding::{body_stream, saved_length};
+use crate::control::DownloadHandle;
+use crate::http_cache::HttpCache;
+use crate::memory_budget::{BudgetedBuffer, Collected, MemoryBudget, SpillTarget};
+use crate::timestamping::{TimestampDecision, Validators};
 use crate::{
-    output::DownloadedData, parallel, DownloadConfig, Error, HttpClient, Output, ProgressCallback,
-    ProgressInfo, Result,
+    body_limit::{BodyDeadline, BodyLimit},
+    link_check,
+    output::DownloadedData,
+    parallel, CacheStats, CacheStatus, DownloadConfig, DownloadEvent, DownloadPlan, Error,
+    EstimateOptions, EstimateReport, HttpClient, LinkCheckProgress, LinkCheckResult, MirrorOptions,
+    MirrorOutcome, NameRegistry, Output, ProgressCallback, ProgressInfo, Result,
 };
 use bytes::Bytes;
 use futures_util::StreamExt;
-use std::path::PathBuf;
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
@@ -14,6 +26,7 @@ use tokio::time::sleep;
 ///
 /// The `Downloader` is the main entry point for performing downloads.
 /// It handles parallel downloads, resume functionality, retries, and more.
+/// URLs passed to its methods go through [`prepare_url`](crate::prepare_url) first.
 ///
 /// # Examples
 ///
@@ -30,6 +43,18 @@ use tokio::time::sleep;
 /// ```
 pub struct Downloader {
     client: HttpClient,
+
+    /// Output names claimed during this session
+    names: NameRegistry,
+
+    /// Bytes memory downloads may buffer at once (`max_buffered_bytes`)
+    memory_budget: Option<MemoryBudget>,
+
+    /// Response cache (`http_cache`)
+    cache: Option<HttpCache>,
+
+    /// Files an earlier attempt truncated (`overwrite_existing`)
+    replaced: ReplacedFiles,
 }
 
 impl Downloader {
@@ -39,8 +64,16 @@ impl Downloader {
     ///
     /// Returns an error if the HTTP client cannot be initialized (e.g., invalid proxy configuration)
     pub fn new(config: DownloadConfig) -> Result<Self> {
+        let memory_budget = config.max_buffered_bytes.map(MemoryBudget::new);
+        let cache = config.http_cache.clone().map(HttpCache::new);
         let client = HttpClient::new(config)?;
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            names: NameRegistry::new(),
+            memory_budget,
+            cache,
+            replaced: ReplacedFiles::default(),
+        })
     }
 
     /// Get a reference to the HTTP client
@@ -51,14 +84,104 @@ impl Downloader {
         &self.client
     }
 
+    /// Describe what the following requests are for; see [`HttpClient::set_request_hints`]
+    #[cfg(feature = "recursive")]
+    pub(crate) fn set_request_hints(&mut self, hints: Option<crate::request_hints::RequestHints>) {
+        self.client.set_request_hints(hints);
+    }
+
+    /// Body bytes received by this downloader's downloads, counted against `quota`
+    ///
+    /// Includes every chunk of parallel downloads and all downloads made so
+    /// far, whether through `download_many` or a recursive crawl.
+    pub fn quota_used(&self) -> u64 {
+        self.client.quota().used()
+    }
+
+    /// Whether `quota` is used up, so no new download will start
+    pub fn quota_exceeded(&self) -> bool {
+        self.client.quota().is_exceeded()
+    }
+
+    /// Get the session-scoped registry of claimed output names
+    ///
+    /// Use this to resolve output paths so that concurrent downloads which
+    /// suggest the same filename never overwrite each other.
+    pub fn name_registry(&self) -> &NameRegistry {
+        &self.names
+    }
+
+    /// Hits, revalidations and misses of the HTTP cache, if `http_cache` is set
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(HttpCache::stats)
+    }
+
+    /// Write the cookies received so far to `save_cookie_file`, if set
+    ///
+    /// Cookies aren't saved automatically; call this once the downloads are
+    /// done. Session cookies are only saved with `keep_session_cookies`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cookie file can't be written
+    #[cfg(feature = "cookies-file")]
+    pub async fn save_cookies(&self) -> Result<()> {
+        let Some(path) = &self.client.config().save_cookie_file else {
+            return Ok(());
+        };
+        let jar = self.client.received_cookies();
+        jar.save_to_file(path)
+            .await
+            .map_err(|e| e.with_context(format!("while saving cookie file {}", path.display())))?;
+        tracing::debug!(path = %path.display(), "Saved session cookies");
+        Ok(())
+    }
+
+    /// Write the known HSTS hosts to `hsts_file`, if set and they changed
+    ///
+    /// Hosts learned during the session are added to those read from the file
+    /// when the downloader was created.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written
+    pub async fn save_hsts(&self) -> Result<()> {
+        let (Some(path), Some(store)) = (&self.client.config().hsts_file, self.client.hsts_store())
+        else {
+            return Ok(());
+        };
+        if !store.is_modified() {
+            return Ok(());
+        }
+        store.save(path).await.map_err(|e| {
+            e.with_context(format!("while saving HSTS database {}", path.display()))
+        })?;
+        tracing::debug!(path = %path.display(), hosts = store.len(), "Saved HSTS database");
+        Ok(())
+    }
+
+    /// The HTTP cache, if configured and usable for this request (a GET without a body)
+    fn http_cache(&self) -> Option<&HttpCache> {
+        let config = self.client.config();
+        self.cache.as_ref().filter(|_| {
+            matches!(config.method, crate::config::HttpMethod::Get) && config.body_data.is_none()
+        })
+    }
+
+    /// Budget shared by memory downloads, if `max_buffered_bytes` is set
+    #[cfg(test)]
+    pub(crate) fn memory_budget(&self) -> Option<&MemoryBudget> {
+        self.memory_budget.as_ref()
+    }
+
     /// Build a request with the configured method, headers, and body
-    fn build_request(
+    pub(crate) fn build_request(
         &self,
         url: &str,
         range: Option<&str>,
-        if_modified_since: Option<std::time::SystemTime>,
+        conditional: Option<&Validators>,
     ) -> Result<reqwest::RequestBuilder> {
-        self.build_request_with_auth(url, range, if_modified_since, false)
+        self.build_request_with_auth(url, range, conditional, false)
     }
 
     /// Build a request with optional auth override
@@ -69,7 +192,7 @@ impl Downloader {
         &self,
         url: &str,
         range: Option<&str>,
-        if_modified_since: Option<std::time::SystemTime>,
+        conditional: Option<&Validators>,
         force_preemptive_auth: bool,
     ) -> Result<reqwest::RequestBuilder> {
         let config = self.client.config();
@@ -82,7 +205,7 @@ impl Downloader {
             method = %config.method.as_str(),
             url = %url,
             has_range = range.is_some(),
-            has_if_modified_since = if_modified_since.is_some(),
+            has_conditional = conditional.is_some(),
             force_preemptive_auth,
             "Building HTTP request"
         );
@@ -118,8 +241,12 @@ impl Downloader {
             }
         }
 
-        // Add Referer header
-        if let Some(ref referer) = config.referer {
+        // Add Referer header, filtered through the referer policy
+        if let Some(referer) = config
+            .referer
+            .as_deref()
+            .and_then(|from| config.referer_policy.referer_for(from, url))
+        {
             request = request.header(reqwest::header::REFERER, referer);
         }
 
@@ -129,42 +256,35 @@ impl Downloader {
             request = request.header(reqwest::header::RANGE, range_value);
         }
 
-        // Add If-Modified-Since header if provided (for timestamping/conditional GET)
-        if let Some(time) = if_modified_since {
-            let http_date = httpdate::fmt_http_date(time);
-            tracing::debug!(if_modified_since = %http_date, "Adding If-Modified-Since header");
-            request = request.header(reqwest::header::IF_MODIFIED_SINCE, http_date);
+        // Add If-Modified-Since / If-None-Match if provided (for timestamping/conditional GET)
+        if let Some(conditional) = conditional {
+            request = conditional.apply(request);
         }
 
-        // Add authentication if configured and either:
-        // 1. auth_no_challenge is set (preemptive auth flag), OR
-        // 2. force_preemptive_auth is true (from metadata.auth_succeeded), OR
-        // 3. We've previously authenticated successfully to this host (via HttpClient's authenticated_hosts set)
-        let host_previously_authenticated = url::Url::parse(url)
+        // Add authentication if either:
+        // 1. We've previously authenticated successfully to this host (reuse those credentials), OR
+        // 2. auth_no_challenge is set (preemptive auth flag), OR
+        // 3. force_preemptive_auth is true (from metadata.auth_succeeded)
+        let remembered_auth = url::Url::parse(url)
             .ok()
             .and_then(|u| u.host_str().map(|h| h.to_string()))
-            .map_or(false, |h| self.client.authenticated_hosts_contains(&h));
-
-        if config.auth_no_challenge || force_preemptive_auth || host_previously_authenticated {
-            // Get credentials - either from config.auth or from .netrc
-            let auth_creds = if let Some(ref auth) = config.auth {
-                Some(auth.clone())
-            } else if host_previously_authenticated {
-                // If we've authenticated before but don't have config.auth, try .netrc
-                crate::auth_handler::get_credentials(url, config)
-            } else {
-                None
-            };
-
-            if let Some(auth) = auth_creds {
-                tracing::debug!(
-                    username = %auth.username,
-                    preemptive = force_preemptive_auth,
-                    host_authenticated = host_previously_authenticated,
-                    "Adding preemptive Basic authentication"
-                );
-                request = request.basic_auth(&auth.username, Some(&auth.password));
-            }
+            .and_then(|h| self.client.authenticated_credentials(&h));
+        let host_previously_authenticated = remembered_auth.is_some();
+        let auth_creds = remembered_auth.or_else(|| {
+            config
+                .auth
+                .clone()
+                .filter(|_| config.auth_no_challenge || force_preemptive_auth)
+        });
+
+        if let Some(auth) = auth_creds {
+            tracing::debug!(
+                username = %auth.username,
+                preemptive = force_preemptive_auth,
+                host_authenticated = host_previously_authenticated,
+                "Adding preemptive Basic authentication"
+            );
+            request = request.basic_auth(&auth.username, Some(&auth.password));
         }
 
         Ok(request)
@@ -176,6 +296,11 @@ impl Downloader {
     /// For files larger than 10MB that support Range requests, this will
     /// automatically use parallel downloads for better performance.
     ///
+    /// An error status fails with `Error::InvalidStatus`; with
+    /// `content_on_error` the error page's body is returned instead, with no
+    /// way to tell it from the resource. Use
+    /// [`Downloader::download_to_memory_result`] to see the status.
+    ///
     /// # Arguments
     ///
     /// * `url` - The URL to download
@@ -209,6 +334,7 @@ impl Downloader {
     ///
     /// Downloads the entire file into memory with progress callbacks.
     /// The progress callback is called periodically with download statistics.
+    /// Transient failures are retried as `DownloadConfig::retry` allows.
     ///
     /// # Arguments
     ///
@@ -255,8 +381,148 @@ impl Downloader {
         url: &str,
         progress_callback: Option<ProgressCallback>,
     ) -> Result<Bytes> {
+        let progress_callback = self.feeding_progress_sink(progress_callback);
+        let result = self.load_with_retries(url, progress_callback).await;
+        self.emit_outcome(url, result.as_ref().map(download_summary));
+        result.map(memory_body)
+    }
+
+    /// Download a URL to memory, with the status and headers of the response
+    ///
+    /// The body is in `data` ([`DownloadedData::bytes`]) and the response the
+    /// body came from in `metadata`. Unlike [`Downloader::download_to_memory`],
+    /// this shows whether a body is an error page kept with
+    /// `content_on_error`: `metadata.status_code` is then the error status
+    /// (e.g. 404 with a JSON error payload). Without `content_on_error`, an error
+    /// status fails with `Error::InvalidStatus` like every other download method.
+    /// Transient failures are retried as `DownloadConfig::retry` allows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the download fails (network error, invalid status, etc.)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use wget_faster_lib::{Downloader, DownloadConfig};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let config = DownloadConfig {
+    ///         content_on_error: true,
+    ///         ..DownloadConfig::default()
+    ///     };
+    ///     let downloader = Downloader::new(config)?;
+    ///     let result = downloader
+    ///         .download_to_memory_result("https://example.com/api/item")
+    ///         .await?;
+    ///     if result.metadata.status_code >= 400 {
+    ///         println!("Error payload: {:?}", result.data.bytes());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn download_to_memory_result(&self, url: &str) -> Result<DownloadResult> {
+        let progress_callback = self.feeding_progress_sink(None);
+        let result = self.load_with_retries(url, progress_callback).await;
+        self.emit_outcome(url, result.as_ref().map(download_summary));
+        result
+    }
+
+    /// [`Downloader::download_to_memory`] without retrying, for callers with their own retries
+    #[cfg(feature = "recursive")]
+    pub(crate) async fn download_to_memory_once(&self, url: &str) -> Result<Bytes> {
+        let progress_callback = self.feeding_progress_sink(None);
+        let result = self.load_into_memory(url, progress_callback).await;
+        self.emit_outcome(url, result.as_ref().map(download_summary));
+        result.map(memory_body)
+    }
+
+    /// [`Downloader::load_into_memory`], retried after transient failures
+    async fn load_with_retries(
+        &self,
+        url: &str,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<DownloadResult> {
+        crate::retry::with_retries(&self.client, url, |_| {
+            self.load_into_memory(url, progress_callback.clone())
+        })
+        .await
+    }
+
+    /// One attempt of [`Downloader::download_to_memory_result`], without reporting the outcome as an event
+    async fn load_into_memory(
+        &self,
+        url: &str,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<DownloadResult> {
+        let url = crate::prepare_url(url)?;
+        let url = url.as_str();
+        self.client.quota().check()?;
         tracing::debug!(url = %url, "Starting download to memory");
 
+        if self.client.config().save_headers {
+            let (bytes, metadata) = self
+                .load_with_header_preamble(url, progress_callback)
+                .await?;
+            return Ok(memory_result(url, bytes, metadata, DownloadStats::default()));
+        }
+        if let Some(cache) = self.http_cache() {
+            let (bytes, metadata, cache_status) =
+                self.download_cached(cache, url, progress_callback).await?;
+            let stats = DownloadStats {
+                cache: Some(cache_status),
+                ..DownloadStats::default()
+            };
+            return Ok(memory_result(url, bytes, metadata, stats));
+        }
+        let (collected, metadata) = self.fetch_to_memory(url, progress_callback, None).await?;
+        let bytes = collected.into_bytes().await?;
+        Ok(memory_result(url, bytes, metadata, DownloadStats::default()))
+    }
+
+    /// Memory download with `save_headers`: the header preamble, then the body
+    ///
+    /// Always a single GET (no cache or parallel chunks), so the headers are
+    /// those of the response the body came from. Progress counts the preamble.
+    async fn load_with_header_preamble(
+        &self,
+        url: &str,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<(Bytes, crate::client::ResourceMetadata)> {
+        let response = self.send_sequential(url).await?;
+        let metadata = HttpClient::extract_metadata_from_response(&response);
+        let status = response.status().as_u16();
+        let mut saved =
+            crate::saved_headers::preamble(response.version(), status, response.headers());
+        let header_bytes = saved.len() as u64;
+        let progress_callback = progress_callback.map(|callback| -> ProgressCallback {
+            std::sync::Arc::new(move |mut info: ProgressInfo| {
+                info.downloaded += header_bytes;
+                info.total_size = info.total_size.map(|total| total + header_bytes);
+                callback(info);
+            })
+        });
+        let body = self
+            .collect_sequential_response(response, url, progress_callback, None)
+            .await?
+            .into_bytes()
+            .await?;
+        saved.extend_from_slice(&body);
+        Ok((saved.into(), metadata))
+    }
+
+    /// Download a URL to memory without the HTTP cache (parallel when worthwhile)
+    ///
+    /// With a `spill` target, a body larger than its cap ends up in its file.
+    /// Returned with the metadata of the GET response, or of the HEAD request
+    /// of a parallel download.
+    async fn fetch_to_memory(
+        &self,
+        url: &str,
+        progress_callback: Option<ProgressCallback>,
+        spill: Option<&SpillTarget>,
+    ) -> Result<(Collected, crate::client::ResourceMetadata)> {
         // Only send HEAD request if parallel downloads are enabled AND threshold is set
         // This allows us to check file size and Range support
         let should_check_metadata =
@@ -268,7 +534,9 @@ impl Downloader {
             tracing::debug!(
                 "Skipping HEAD request - going directly to GET (parallel downloads disabled)"
             );
-            return self.download_sequential(url, progress_callback).await;
+            return self
+                .download_sequential_to(url, progress_callback, spill)
+                .await;
         }
 
         // Get metadata (sends HEAD request)
@@ -303,13 +571,15 @@ impl Downloader {
                         chunks = self.client.config().parallel_chunks,
                         "Using parallel download (file size exceeds threshold)"
                     );
-                    return parallel::download_parallel(
-                        &self.client,
-                        url,
-                        total_size,
-                        progress_callback,
-                    )
-                    .await;
+                    return self
+                        .download_parallel_to_memory(
+                            url,
+                            total_size,
+                            &metadata,
+                            spill,
+                            progress_callback,
+                        )
+                        .await;
                 }
                 tracing::debug!(
                     total_size,
@@ -324,7 +594,192 @@ impl Downloader {
         }
 
         // Fall back to sequential download
-        self.download_sequential(url, progress_callback).await
+        self.download_sequential_to(url, progress_callback, spill)
+            .await
+    }
+
+    /// Parallel download of a body of `total_size` bytes meant for memory
+    ///
+    /// A body over the cap of `spill` goes straight to its file.
+    async fn download_parallel_to_memory(
+        &self,
+        url: &str,
+        total_size: u64,
+        metadata: &crate::client::ResourceMetadata,
+        spill: Option<&SpillTarget>,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<(Collected, crate::client::ResourceMetadata)> {
+        if let Some(target) = spill.filter(|t| total_size > t.max_bytes) {
+            let collected = self
+                .download_parallel_spilled(url, total_size, metadata, target, progress_callback)
+                .await?;
+            return Ok((collected, metadata.clone()));
+        }
+        // The chunks are all held until the last one arrives, so the whole
+        // size is reserved up front; too large for the budget means streaming
+        // sequentially, which can spill to disk
+        let reservation = match &self.memory_budget {
+            Some(budget) => budget.reserve(total_size).await.map(Some),
+            None => Some(None),
+        };
+        if let Some(_reservation) = reservation {
+            self.emit_started(url, metadata);
+            let deadline = BodyDeadline::start(self.client.config().max_body_duration);
+            let bytes = deadline
+                .run(parallel::download_parallel(
+                    &self.client,
+                    url,
+                    total_size,
+                    parallel::ObjectIdentity::new(metadata.etag.as_deref()),
+                    progress_callback,
+                ))
+                .await??;
+            return Ok((Collected::Memory(bytes), metadata.clone()));
+        }
+        tracing::debug!(
+            total_size,
+            "Using sequential download (file size exceeds the memory budget)"
+        );
+        self.download_sequential_to(url, progress_callback, spill)
+            .await
+    }
+
+    /// Parallel download of a body known to exceed the cap of `target`, straight to its file
+    async fn download_parallel_spilled(
+        &self,
+        url: &str,
+        total_size: u64,
+        metadata: &crate::client::ResourceMetadata,
+        target: &SpillTarget,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<Collected> {
+        tracing::debug!(
+            total_size,
+            max_bytes = target.max_bytes,
+            path = %target.path.display(),
+            "Body exceeds its memory cap - downloading to disk"
+        );
+        let mut file = File::create(&target.path).await?;
+        self.emit_started(url, metadata);
+        let identity = parallel::ObjectIdentity::new(metadata.etag.as_deref());
+        let deadline = BodyDeadline::start(self.client.config().max_body_duration);
+        deadline
+            .run(parallel::download_parallel_to_writer(
+                &self.client,
+                url,
+                total_size,
+                &identity,
+                &mut file,
+                progress_callback,
+            ))
+            .await??;
+        Ok(Collected::File {
+            path: target.path.clone(),
+            len: total_size,
+        })
+    }
+
+    /// Download a URL as a stream of body chunks
+    ///
+    /// Chunks are yielded in order as they arrive, so the body can be passed on
+    /// (e.g. to an HTTP response) without being held in memory. See
+    /// [`Downloader::download_stream_with_metadata`] for how the transfer is made.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server answers with an error
+    /// status. Failures during the transfer are yielded by the stream, which then ends.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// use wget_faster_lib::{Downloader, DownloadConfig};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let downloader = Downloader::new(DownloadConfig::default())?;
+    ///     let mut chunks = downloader.download_stream("https://example.com/file.iso").await?;
+    ///     while let Some(chunk) = chunks.next().await {
+    ///         println!("Received {} bytes", chunk?.len());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn download_stream(
+        &self,
+        url: &str,
+    ) -> Result<impl futures::Stream<Item = Result<Bytes>> + Send + Unpin + 'static> {
+        let (_, chunks) = self.download_stream_with_metadata(url, None).await?;
+        Ok(chunks)
+    }
+
+    /// Download a URL as a stream of body chunks, with the resource's metadata
+    ///
+    /// Files larger than `parallel_threshold` on servers supporting Range requests
+    /// are fetched as parallel chunks, at most `parallel_chunks` at a time, and
+    /// yielded in order; a chunk failing transiently is retried. Otherwise the body
+    /// of a single GET is streamed, and if the connection drops it is resumed with
+    /// a Range request conditional on the `ETag` or `Last-Modified`. Either way
+    /// there are at most `retry.max_retries` retries, `speed_limit` applies as
+    /// chunks are yielded and `progress_callback` is called for each one.
+    ///
+    /// The metadata comes from the HEAD request of a parallel download, or from
+    /// the GET response.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server answers with an error
+    /// status. Failures during the transfer are yielded by the stream, which then ends.
+    pub async fn download_stream_with_metadata(
+        &self,
+        url: &str,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<(
+        crate::client::ResourceMetadata,
+        impl futures::Stream<Item = Result<Bytes>> + Send + Unpin + 'static,
+    )> {
+        let url = crate::prepare_url(url)?;
+        let url = url.as_str();
+        self.client.quota().check()?;
+        let progress_callback = self.feeding_progress_sink(progress_callback);
+        let config = self.client.config();
+        if config.parallel_threshold > 0 && config.parallel_chunks > 1 {
+            let metadata = self.client.get_metadata(url).await?;
+            let parallel_size = metadata
+                .content_length
+                .filter(|&size| metadata.supports_range && size > config.parallel_threshold);
+            if let Some(total_size) = parallel_size {
+                tracing::info!(
+                    total_size,
+                    chunks = config.parallel_chunks,
+                    "Streaming parallel download"
+                );
+                self.emit_started(url, &metadata);
+                let chunks = crate::stream::parallel_chunks(
+                    &self.client,
+                    url,
+                    total_size,
+                    parallel::ObjectIdentity::new(metadata.etag.as_deref()),
+                    progress_callback,
+                );
+                return Ok((metadata, chunks));
+            }
+        }
+
+        tracing::debug!(url = %url, "Streaming sequential download");
+        let response = self.send_sequential(url).await?;
+        let metadata = HttpClient::extract_metadata_from_response(&response);
+        let status_code = response.status().as_u16();
+        let chunks = match crate::response_handler::should_proceed_download(status_code, config) {
+            Ok(true) => {
+                self.emit_started(url, &metadata);
+                crate::stream::sequential_chunks(&self.client, url, response, progress_callback)?
+            },
+            Ok(false) => futures::stream::empty().boxed(),
+            Err(err_status) => return Err(Error::InvalidStatus(err_status)),
+        };
+        Ok((metadata, chunks))
     }
 
     /// Download a URL to a file
@@ -363,14 +818,14 @@ impl Downloader {
     /// }
     /// ```
     pub async fn download_to_file(&self, url: &str, path: PathBuf) -> Result<DownloadResult> {
-        self.download_to_file_with_progress(url, path, None, false)
-            .await
+        self.download_to_file_with_progress(url, path, None).await
     }
 
     /// Download a URL to a file with progress tracking
     ///
     /// Downloads content to the specified file path with progress callbacks.
-    /// Supports resume functionality and parallel downloads.
+    /// Supports resume functionality and parallel downloads. Transient failures
+    /// are retried as `DownloadConfig::retry` allows, continuing the partial file.
     ///
     /// # Arguments
     ///
@@ -419,18 +874,186 @@ impl Downloader {
         url: &str,
         path: PathBuf,
         progress_callback: Option<ProgressCallback>,
+    ) -> Result<DownloadResult> {
+        let progress_callback = self.feeding_progress_sink(progress_callback);
+        let result = self.save_with_retries(url, path, progress_callback).await;
+        self.emit_outcome(url, result.as_ref().map(download_summary));
+        result
+    }
+
+    /// [`Downloader::download_to_file`] without retrying, for callers with their own retries
+    #[cfg(feature = "recursive")]
+    pub(crate) async fn download_to_file_once(
+        &self,
+        url: &str,
+        path: PathBuf,
+    ) -> Result<DownloadResult> {
+        let progress_callback = self.feeding_progress_sink(None);
+        let result = self.save_to_file(url, path, progress_callback, false).await;
+        self.emit_outcome(url, result.as_ref().map(download_summary));
+        result
+    }
+
+    /// [`Downloader::save_to_file`], retried after transient failures
+    ///
+    /// A retry continues the partial file the failed attempt left. If the
+    /// last attempt fails, a file that didn't exist before is removed.
+    async fn save_with_retries(
+        &self,
+        url: &str,
+        path: PathBuf,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<DownloadResult> {
+        let existed = path.exists();
+        let result = crate::retry::with_retries(&self.client, url, |is_retry| {
+            self.save_to_file(url, path.clone(), progress_callback.clone(), is_retry)
+        })
+        .await;
+        if let Err(e) = &result {
+            if !existed && crate::retry::retryable(e, &self.client.config().retry) {
+                let _ = tokio::fs::remove_file(&path).await;
+            }
+        }
+        result
+    }
+
+    /// One attempt of [`Downloader::download_to_file_with_progress`], without reporting the outcome as an event
+    ///
+    /// With `is_retry`, the HEAD request sent by the first attempt is not
+    /// repeated and the quota doesn't stop the file in progress.
+    async fn save_to_file(
+        &self,
+        url: &str,
+        path: PathBuf,
+        progress_callback: Option<ProgressCallback>,
         is_retry: bool,
     ) -> Result<DownloadResult> {
+        let url = crate::prepare_url(url)?;
+        let url = url.as_str();
+        // A retry finishes the file in progress, which the quota doesn't stop
+        if !is_retry {
+            self.client.quota().check()?;
+        }
+        let staging = self
+            .client
+            .config()
+            .staging_dir
+            .as_deref()
+            .filter(|_| !matches!(self.client.config().method, crate::config::HttpMethod::Head))
+            .map(|dir| (dir, crate::staging::staging_path(dir, &path)))
+            // An existing destination without a staging file (a partial download from
+            // before staging was configured, or a file to timestamp) is handled in place
+            .filter(|(_, staged)| staged.exists() || !path.exists());
+
+        let (result, written) = if let Some(result) = self.copy_from_cache(url, &path).await? {
+            (result, true)
+        } else if let Some((dir, staged)) = staging {
+            tokio::fs::create_dir_all(dir).await?;
+            let (mut result, written) = self
+                .transfer_to_file(url, staged.clone(), progress_callback.clone(), is_retry)
+                .await?;
+            if result.data.file_path.as_ref() == Some(&staged) {
+                crate::staging::move_into_place(&staged, &path, url, progress_callback.as_ref())
+                    .await
+                    .map_err(|e| e.with_context(format!("while moving {url} into place")))?;
+                result.data.file_path = Some(path);
+            }
+            (result, written)
+        } else {
+            self.transfer_to_file(url, path, progress_callback, is_retry)
+                .await?
+        };
+
+        let mut result = result;
+        if self.http_cache().is_some() && result.stats.cache.is_none() {
+            result.stats.cache = Some(CacheStatus::Miss);
+            self.record_cache(CacheStatus::Miss);
+        }
+
+        // Record where the file came from (only if its content was written by this download)
+        if let (Some(provenance), Some(path), true) =
+            (&self.client.config().write_provenance, result.data.file_path.as_ref(), written)
+        {
+            let hints = self.client.request_hints();
+            crate::provenance::record_download(provenance, url, path, &result.metadata, hints)
+                .await?;
+        }
+        Ok(result)
+    }
+
+    /// Download `url` into the file at `path` (resuming, timestamping, parallel or sequential)
+    ///
+    /// Also returns whether the file's content was written by this download.
+    async fn transfer_to_file(
+        &self,
+        url: &str,
+        path: PathBuf,
+        progress_callback: Option<ProgressCallback>,
+        is_retry: bool,
+    ) -> Result<(DownloadResult, bool)> {
+        self.transfer_through(url, path, progress_callback, is_retry, |file| file)
+            .await
+    }
+
+    /// [`Downloader::transfer_to_file`] writing the body through `wrap(file)`
+    ///
+    /// Lets tests put a failing writer in front of the destination file.
+    pub(crate) async fn transfer_through<W: OutputFile>(
+        &self,
+        url: &str,
+        path: PathBuf,
+        progress_callback: Option<ProgressCallback>,
+        is_retry: bool,
+        wrap: impl FnOnce(LazyFile) -> W + Send,
+    ) -> Result<(DownloadResult, bool)> {
         // If method is HEAD, send HEAD request and return without downloading
         // This matches GNU wget --method=HEAD behavior: check headers only, no file creation
         if matches!(self.client.config().method, crate::config::HttpMethod::Head) {
             let metadata = self.client.get_metadata(url).await?;
             tracing::info!(url = %url, "HEAD method requested - returning metadata without download");
-            return Ok(DownloadResult {
-                data: DownloadedData::new_memory(Bytes::new()),
-                url: url.to_string(),
-                metadata,
-            });
+            return Ok((
+                DownloadResult {
+                    data: DownloadedData::new_memory(Bytes::new()),
+                    url: url.to_string(),
+                    metadata,
+                    timestamp_decision: None,
+                    checksum: None,
+                    stats: DownloadStats::default(),
+                },
+                false,
+            ));
+        }
+
+        // -N -c: probe the remote file to resume, restart or keep a local one
+        let config = self.client.config();
+        let mut resume_partial = None;
+        if config.timestamping
+            && config.timestamping_continue
+            && config.start_pos.is_none()
+            && path.exists()
+        {
+            use crate::timestamping::{LocalFileInfo, PartialFileAction, RemoteInfo};
+            let remote = self.client.get_metadata(url).await?;
+            let local = LocalFileInfo::from_metadata(&tokio::fs::metadata(&path).await?)?;
+            let action = PartialFileAction::decide(&local, &RemoteInfo::from_metadata(&remote));
+            tracing::info!(path = %path.display(), action = ?action, "Timestamping with continue");
+            match action {
+                PartialFileAction::Skip => {
+                    return Ok((
+                        DownloadResult {
+                            data: DownloadedData::new_file(path.clone(), local.size, false),
+                            url: url.to_string(),
+                            metadata: remote,
+                            timestamp_decision: Some(TimestampDecision::AlreadyComplete),
+                            checksum: None,
+                            stats: DownloadStats::default(),
+                        },
+                        false,
+                    ));
+                },
+                PartialFileAction::Resume(from) => resume_partial = Some(from),
+                PartialFileAction::Restart => {},
+            }
         }
 
         // Skip HEAD request if:
@@ -449,12 +1072,13 @@ impl Downloader {
 
         // Get metadata first (unless skipping HEAD)
         // If timestamping is enabled, use GET with If-Modified-Since header instead of HEAD
-        let (metadata, if_modified_since) = if skip_head {
+        let (metadata, validators) = if skip_head {
             // Timestamping mode: skip HEAD, use GET with If-Modified-Since directly
             // Create dummy metadata for now - actual metadata will come from GET request
             let dummy_metadata = crate::client::ResourceMetadata {
                 content_length: None,
                 content_type: None,
+                content_encoding: None,
                 supports_range: false,
                 status_code: 200, // Assume success, will be validated in GET
                 last_modified: None,
@@ -462,21 +1086,36 @@ impl Downloader {
                 content_disposition: None,
                 headers: reqwest::header::HeaderMap::new(),
                 auth_succeeded: false,
+                final_url: None,
+                allow: None,
+                accept_ranges_unit: None,
+                cache_control: None,
+                age: None,
+                content_language: None,
+                links: Vec::new(),
+                tls_info: None,
             };
 
-            let if_modified_since_time = if path.exists() {
-                let local_metadata = tokio::fs::metadata(&path).await?;
-                Some(local_metadata.modified()?)
+            // A resumed file is appended to whatever its validators say
+            let validators = if resume_partial.is_some() {
+                Validators::default()
             } else {
-                None
+                Validators::of_file(&path, self.client.config()).await?
             };
-
-            (dummy_metadata, if_modified_since_time)
+            (dummy_metadata, validators)
         } else {
             // Normal mode: use HEAD request to get metadata
-            (self.client.get_metadata(url).await?, None)
+            (self.client.get_metadata(url).await?, Validators::default())
         };
 
+        // Some CDNs answer HEAD with Content-Length: 0 without computing the body.
+        // Treat that as "unknown" for planning; the GET response has the real length.
+        let mut metadata = metadata;
+        if !skip_head && metadata.content_length == Some(0) && metadata.status_code != 204 {
+            tracing::debug!("HEAD reported Content-Length: 0 - treating length as unknown");
+            metadata.content_length = None;
+        }
+
         // Print server response if requested (skip in timestamping mode since we haven't made request yet)
         if !skip_head && self.client.config().print_server_response {
             eprintln!("{}", metadata.format_headers());
@@ -490,11 +1129,32 @@ impl Downloader {
             match response_status {
                 ResponseStatus::NoContent => {
                     tracing::info!("HTTP 204 No Content - skipping file creation");
-                    return Ok(DownloadResult {
-                        data: DownloadedData::new_memory(Bytes::new()),
-                        url: url.to_string(),
-                        metadata,
-                    });
+                    return Ok((
+                        DownloadResult {
+                            data: DownloadedData::new_memory(Bytes::new()),
+                            url: url.to_string(),
+                            metadata,
+                            timestamp_decision: None,
+                            checksum: None,
+                            stats: DownloadStats::default(),
+                        },
+                        false,
+                    ));
+                },
+                ResponseStatus::Redirect if !self.client.config().content_on_error => {
+                    // Not following redirects: the 3xx is the result (see DownloadResult::outcome)
+                    tracing::info!(location = ?metadata.redirect_location(), "Redirect not followed - skipping file creation");
+                    return Ok((
+                        DownloadResult {
+                            data: DownloadedData::new_memory(Bytes::new()),
+                            url: url.to_string(),
+                            metadata,
+                            timestamp_decision: None,
+                            checksum: None,
+                            stats: DownloadStats::default(),
+                        },
+                        false,
+                    ));
                 },
                 ResponseStatus::NotModified => {
                     tracing::info!(path = %path.display(), "HTTP 304 Not Modified - file is up to date");
@@ -502,19 +1162,31 @@ impl Downloader {
                     if path.exists() {
                         let local_metadata = tokio::fs::metadata(&path).await?;
                         let local_size = local_metadata.len();
-                        return Ok(DownloadResult {
-                            data: DownloadedData::new_file(path.clone(), local_size, false),
-                            url: url.to_string(),
-                            metadata,
-                        });
+                        return Ok((
+                            DownloadResult {
+                                data: DownloadedData::new_file(path.clone(), local_size, false),
+                                url: url.to_string(),
+                                metadata,
+                                timestamp_decision: None,
+                                checksum: None,
+                                stats: DownloadStats::default(),
+                            },
+                            false,
+                        ));
                     }
                     // If file doesn't exist, treat as success with empty result
                     tracing::warn!("HTTP 304 but file doesn't exist - returning empty result");
-                    return Ok(DownloadResult {
-                        data: DownloadedData::new_memory(Bytes::new()),
-                        url: url.to_string(),
-                        metadata,
-                    });
+                    return Ok((
+                        DownloadResult {
+                            data: DownloadedData::new_memory(Bytes::new()),
+                            url: url.to_string(),
+                            metadata,
+                            timestamp_decision: None,
+                            checksum: None,
+                            stats: DownloadStats::default(),
+                        },
+                        false,
+                    ));
                 },
                 ResponseStatus::RangeNotSatisfiable => {
                     tracing::info!(path = %path.display(), "HTTP 416 Range Not Satisfiable - file already complete");
@@ -522,11 +1194,17 @@ impl Downloader {
                     if path.exists() {
                         let local_metadata = tokio::fs::metadata(&path).await?;
                         let local_size = local_metadata.len();
-                        return Ok(DownloadResult {
-                            data: DownloadedData::new_file(path.clone(), local_size, false),
-                            url: url.to_string(),
-                            metadata,
-                        });
+                        return Ok((
+                            DownloadResult {
+                                data: DownloadedData::new_file(path.clone(), local_size, false),
+                                url: url.to_string(),
+                                metadata,
+                                timestamp_decision: None,
+                                checksum: None,
+                                stats: DownloadStats::default(),
+                            },
+                            false,
+                        ));
                     }
                     // If file doesn't exist, this is an error
                     tracing::error!("HTTP 416 but file doesn't exist - this is an error");
@@ -562,26 +1240,44 @@ impl Downloader {
             }
         }
 
+        // Reject unwanted responses before any file is created
+        // In skip_head mode, the filter runs on the GET response headers instead
+        if !skip_head {
+            if let Some(ref filter) = self.client.config().response_filter {
+                filter.check(url, &metadata)?;
+            }
+        }
+
         // Check timestamping - skip if local file is newer or delete if we need to re-download
         // In skip_head mode, this check will be done after GET request in download_sequential_to_writer
         let mut should_delete_existing = false;
         if !skip_head && self.client.config().timestamping {
             tracing::debug!(path = %path.display(), "Timestamping enabled - checking local vs remote timestamps");
 
-            let (action, result_data) =
-                crate::timestamping::check_timestamp(&path, &metadata).await?;
+            let (action, result_data) = crate::timestamping::check_timestamp(
+                &path,
+                &metadata,
+                self.client.config().timestamping_size_check,
+            )
+            .await?;
 
             use crate::timestamping::TimestampAction;
             match action {
                 TimestampAction::Skip => {
                     // Local file is up to date, return it
                     // Safe: check_timestamp always returns Some(DownloadedData) when action is Skip
-                    return Ok(DownloadResult {
-                        data: result_data
-                            .expect("check_timestamp should return data when action is Skip"),
-                        url: url.to_string(),
-                        metadata,
-                    });
+                    return Ok((
+                        DownloadResult {
+                            data: result_data
+                                .expect("check_timestamp should return data when action is Skip"),
+                            url: url.to_string(),
+                            metadata,
+                            timestamp_decision: None,
+                            checksum: None,
+                            stats: DownloadStats::default(),
+                        },
+                        false,
+                    ));
                 },
                 TimestampAction::DeleteAndDownload => {
                     // Need to delete and re-download
@@ -599,84 +1295,115 @@ impl Downloader {
             tokio::fs::remove_file(&path).await?;
         }
 
-        // Check if file exists for resume
-        // If --start-pos is specified, it overrides automatic resume from file size
-        // IMPORTANT: When timestamping (-N) is enabled, don't resume - do conditional GET instead
-        let resume_from = if self.client.config().timestamping {
-            // Timestamping mode: always start from 0 and use If-Modified-Since header
-            tracing::debug!("Timestamping enabled - skipping resume, will use conditional GET");
-            0
-        } else if let Some(start_pos) = self.client.config().start_pos {
-            tracing::debug!(start_pos, "Using --start-pos for resume");
-            start_pos
-        } else if path.exists() {
-            let size = tokio::fs::metadata(&path).await?.len();
-            if size > 0 {
-                tracing::info!(path = %path.display(), existing_size = size, "Resuming download from existing file");
+        // -O: replace the file, unless an earlier attempt at this URL already truncated it
+        let replace = replaces_existing(self.client.config());
+        let truncate_lazily = replace && !self.replaced.is_replacing(url, &path);
+        let resume_from =
+            resume_offset(self.client.config(), &path, resume_partial, truncate_lazily).await?;
+
+        // Optionally fail fast if the remaining size won't fit on disk
+        if self.client.config().check_free_space {
+            if let Some(total) = metadata.content_length {
+                crate::storage::ensure_free_space(
+                    &path,
+                    resume_from,
+                    total.saturating_sub(resume_from),
+                )?;
             }
-            size
-        } else {
-            0
-        };
+        }
 
         // In timestamping mode with existing file, download to temp file first
         // Then compare timestamps and decide whether to replace original
-        let (mut file, temp_path) = if self.client.config().timestamping && path.exists() {
-            // Create temporary file path
-            let temp_path = PathBuf::from(format!("{}.wgetf-tmp", path.display()));
-            tracing::debug!(
-                original = %path.display(),
-                temp = %temp_path.display(),
-                "Timestamping mode: downloading to temporary file"
-            );
-            let file = File::create(&temp_path).await?;
-            (file, Some(temp_path))
-        } else if resume_from > 0 && self.client.config().start_pos.is_none() {
-            // Resume mode: append to existing file
-            let file = tokio::fs::OpenOptions::new()
-                .write(true)
-                .append(true)
-                .open(&path)
-                .await?;
-            (file, None)
-        } else {
-            // Normal mode or --start-pos mode or timestamping without existing file: create new file
-            (File::create(&path).await?, None)
-        };
+        let (file, temp_path) =
+            if self.client.config().timestamping && resume_partial.is_none() && path.exists() {
+                // Create temporary file path
+                let temp_path = PathBuf::from(format!("{}.wgetf-tmp", path.display()));
+                tracing::debug!(
+                    original = %path.display(),
+                    temp = %temp_path.display(),
+                    "Timestamping mode: downloading to temporary file"
+                );
+                let file = File::create(&temp_path).await?;
+                (file.into(), Some(temp_path))
+            } else if truncate_lazily {
+                // Replace mode: keep the existing file until a body arrives
+                (LazyFile::pending(path.clone()), None)
+            } else if resume_from > 0 && self.client.config().start_pos.is_none() {
+                // Resume mode: append to existing file
+                let file = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .append(true)
+                    .open(&path)
+                    .await?;
+                (file.into(), None)
+            } else {
+                // Normal mode or --start-pos mode or timestamping without existing file: create new file
+                (File::create(&path).await?.into(), None)
+            };
+
+        // Hash the content as it is written, after what a resumed file already holds
+        let expected_checksum = self.client.config().expected_checksum.as_ref();
+        let mut hasher = crate::checksum::Hasher::new(expected_checksum);
+        if resume_from > 0 && temp_path.is_none() && self.client.config().start_pos.is_none() {
+            hasher.update_from_file(&path).await?;
+        }
+        let mut file = crate::checksum::HashingWriter::new(wrap(file), hasher);
 
         // Track which file to potentially clean up on error
         let created_file_path = if temp_path.is_some() {
             temp_path.clone()
-        } else if resume_from == 0 {
-            // Only clean up if we created a new file (not resuming)
+        } else if resume_from == 0 && !replace {
+            // Only clean up if we created a new file (not resuming or replacing)
             Some(path.clone())
         } else {
             None
         };
 
+        // Where a content-coded response starting the file is recorded (not for temp files)
+        let marker = temp_path.is_none().then(|| encoding_marker(&path));
+
         // Use parallel download if supported and beneficial
         // For sequential downloads, we also capture the actual metadata from the GET response
-        let download_result = if metadata.supports_range && resume_from == 0 {
-            if let Some(total_size) = metadata.content_length {
-                if total_size > self.client.config().parallel_threshold {
-                    // Use parallel for files > threshold
-                    parallel::download_parallel_to_writer(
-                        &self.client,
-                        url,
-                        total_size,
-                        &mut file,
-                        progress_callback,
-                    )
-                    .await
-                    .map(|_| (total_size, metadata.clone()))
+        // With `save_headers`, the saved headers must be those of the GET, so it's always sequential
+        let download_result =
+            if metadata.supports_range && resume_from == 0 && !self.client.config().save_headers {
+                if let Some(total_size) = metadata.content_length {
+                    if total_size > self.client.config().parallel_threshold {
+                        // Use parallel for files > threshold
+                        self.emit_started(url, &metadata);
+                        BodyDeadline::start(self.client.config().max_body_duration)
+                            .run(parallel::download_parallel_to_writer(
+                                &self.client,
+                                url,
+                                total_size,
+                                &parallel::ObjectIdentity::new(metadata.etag.as_deref()),
+                                &mut file,
+                                progress_callback,
+                            ))
+                            .await
+                            .and_then(|result| result)
+                            .map(|_| (total_size, metadata.clone(), DownloadStats::default()))
+                    } else {
+                        self.download_sequential_to_writer(
+                            url,
+                            &mut file,
+                            progress_callback,
+                            resume_from,
+                            &validators,
+                            metadata.auth_succeeded,
+                            marker.as_deref(),
+                        )
+                        .await
+                    }
                 } else {
                     self.download_sequential_to_writer(
                         url,
                         &mut file,
                         progress_callback,
                         resume_from,
-                        if_modified_since,
+                        &validators,
                         metadata.auth_succeeded,
+                        marker.as_deref(),
                     )
                     .await
                 }
@@ -686,32 +1413,82 @@ impl Downloader {
                     &mut file,
                     progress_callback,
                     resume_from,
-                    if_modified_since,
+                    &validators,
                     metadata.auth_succeeded,
+                    marker.as_deref(),
                 )
                 .await
-            }
-        } else {
-            self.download_sequential_to_writer(
-                url,
-                &mut file,
-                progress_callback,
-                resume_from,
-                if_modified_since,
-                metadata.auth_succeeded,
-            )
-            .await
-        };
+            };
 
         // If download failed, clean up the empty file
-        let (total_bytes, actual_metadata) = match download_result {
+        let (total_bytes, actual_metadata, stats) = match download_result {
             Ok(result) => result,
             Err(e) => {
+                // A truncated file is continued by the next attempt
+                if replace && file.get_ref().is_open() {
+                    let _ = file.flush().await;
+                    self.replaced.record(url, &path);
+                }
+
+                // Cancelled or cut off by `quota_hard`: keep what was written so
+                // the download can be resumed
+                if matches!(e.root(), Error::Cancelled | Error::QuotaExceeded(_))
+                    && temp_path.is_none()
+                {
+                    let _ = file.flush().await;
+                    tracing::info!(path = %path.display(), error = %e, "Download stopped - keeping partial file for resume");
+                    return Err(e);
+                }
+
+                // Disk full: keep the partial file (unless it's a timestamping temp file)
+                // so the download can be resumed once space has been freed
+                if temp_path.is_none() {
+                    if let Error::IoError(io_err) = e.root() {
+                        if crate::storage::is_storage_exhausted(io_err) {
+                            // Best effort: push out whatever is still buffered
+                            let _ = file.flush().await;
+                            drop(file);
+                            let written = tokio::fs::metadata(&path).await.map_or(0, |m| m.len());
+                            tracing::warn!(path = %path.display(), written, "Disk full - keeping partial file for resume");
+                            return Err(crate::storage::classify_write_error(
+                                e,
+                                &path,
+                                written,
+                                metadata
+                                    .content_length
+                                    .map(|total| total.saturating_sub(written)),
+                            ));
+                        }
+                    }
+                }
+
+                // Cut off by a transient failure: keep what was written for a retry to continue
+                let keep_partial = temp_path.is_none()
+                    && !matches!(e.root(), Error::ObjectChangedDuringDownload(_))
+                    && crate::retry::retryable(&e, &self.client.config().retry);
+                if keep_partial {
+                    let _ = file.flush().await;
+                }
+
                 // Drop file handle before deleting
                 drop(file);
 
+                if keep_partial && tokio::fs::metadata(&path).await.is_ok_and(|m| m.len() > 0) {
+                    tracing::info!(path = %path.display(), error = %e, "Download interrupted - keeping partial file for retry");
+                    return Err(e);
+                }
+
+                // A resumed file that mixes versions or encodings can't be resumed again
+                let cleanup_path = if temp_path.is_none()
+                    && matches!(e.root(), Error::ObjectChangedDuringDownload(_))
+                {
+                    Some(path.clone())
+                } else {
+                    created_file_path
+                };
+
                 // Clean up empty file if download failed
-                if let Some(ref cleanup_path) = created_file_path {
+                if let Some(ref cleanup_path) = cleanup_path {
                     tracing::debug!(path = %cleanup_path.display(), "Download failed - cleaning up empty file");
                     if let Err(remove_err) = tokio::fs::remove_file(cleanup_path).await {
                         tracing::warn!(
@@ -726,9 +1503,50 @@ impl Downloader {
             },
         };
 
+        // The file is complete, so its encoding no longer matters for resuming
+        if let Some(ref marker) = marker {
+            remove_encoding_marker(marker).await;
+        }
+
+        // An empty success body still replaces the file; anything else leaves it alone
+        if replace {
+            self.replaced.finish(url, &path);
+            let status_code = actual_metadata.status_code;
+            if !file.get_ref().is_open() {
+                if !(200..300).contains(&status_code) || status_code == 204 {
+                    tracing::info!(path = %path.display(), status_code, "Nothing to save - keeping existing file");
+                    return Ok((
+                        DownloadResult {
+                            data: DownloadedData::new_memory(Bytes::new()),
+                            url: url.to_string(),
+                            metadata: actual_metadata,
+                            timestamp_decision: None,
+                            checksum: None,
+                            stats,
+                        },
+                        false,
+                    ));
+                }
+                File::create(&path).await?;
+            }
+        }
+
+        // Verify the digest of what was received (a 304 carries no content)
+        let mut checksum = (actual_metadata.status_code != 304).then(|| file.checksum());
+        if let Some(ref actual) = checksum {
+            if let Err(e) = crate::checksum::verify(expected_checksum, actual) {
+                drop(file);
+                let received = temp_path.as_ref().unwrap_or(&path);
+                tracing::warn!(path = %received.display(), error = %e, "Deleting download with wrong checksum");
+                tokio::fs::remove_file(received).await?;
+                return Err(e);
+            }
+        }
+
         // Handle timestamping mode: decide whether to keep new file or original
         // Use Option to safely handle file ownership
         let mut file_option = Some(file);
+        let mut timestamp_decision = None;
         if let Some(ref tmp_path) = temp_path {
             // We downloaded to a temporary file - compare timestamps
             // Drop file handle before comparing/moving
@@ -736,88 +1554,51 @@ impl Downloader {
 
             // Special case: 304 Not Modified (total_bytes == 0)
             // Delete temp file and keep original
-            if total_bytes == 0 {
+            let decision = if total_bytes == 0 {
                 tracing::info!("HTTP 304 Not Modified - keeping original file, deleting temp");
-                tokio::fs::remove_file(tmp_path).await?;
+                crate::timestamping::TimestampDecision::NotModified
+            } else if validators.etag.is_some() && actual_metadata.etag != validators.etag {
+                crate::timestamping::TimestampDecision::EtagChanged
             } else {
-                // We got 200 OK with content - compare timestamps
-                // Get original file timestamp
-                let original_metadata = tokio::fs::metadata(&path).await?;
-                let original_time = original_metadata.modified()?;
-
-                // Compare with remote timestamp
-                let should_replace =
-                    if let Some(ref remote_modified) = actual_metadata.last_modified {
-                        if let Ok(remote_time) = httpdate::parse_http_date(remote_modified) {
-                            tracing::debug!(
-                                original_time = ?original_time,
-                                remote_time = ?remote_time,
-                                "Comparing timestamps (post-download)"
-                            );
-
-                            match original_time.cmp(&remote_time) {
-                                std::cmp::Ordering::Less => {
-                                    // Remote is newer - replace
-                                    tracing::info!("Remote file is newer - replacing original");
-                                    true
-                                },
-                                std::cmp::Ordering::Greater => {
-                                    // Local is newer - keep original
-                                    tracing::info!("Local file is newer - keeping original");
-                                    false
-                                },
-                                std::cmp::Ordering::Equal => {
-                                    // Same timestamp - check file sizes
-                                    // If sizes differ, download (matches GNU wget behavior)
-                                    let original_size = original_metadata.len();
-                                    if total_bytes != original_size {
-                                        tracing::info!(
-                                            original_size,
-                                            new_size = total_bytes,
-                                            "Same timestamp but different size - replacing file"
-                                        );
-                                        true
-                                    } else {
-                                        tracing::info!(
-                                            "Same timestamp and size - keeping original file"
-                                        );
-                                        false
-                                    }
-                                },
-                            }
-                        } else {
-                            // Can't parse remote timestamp - replace anyway
-                            tracing::warn!("Failed to parse remote Last-Modified - replacing file");
-                            true
-                        }
-                    } else {
-                        // No remote timestamp - replace anyway
-                        tracing::info!(
-                            last_modified = ?actual_metadata.last_modified,
-                            "No remote Last-Modified header - replacing file anyway"
-                        );
-                        true
-                    };
+                // We got 200 OK with content - compare against the original file.
+                // Sizes are compared on disk so transfer encoding doesn't matter.
+                let original = crate::timestamping::LocalFileInfo::from_metadata(
+                    &tokio::fs::metadata(&path).await?,
+                )?;
+                let new_size = tokio::fs::metadata(tmp_path).await?.len();
+                crate::timestamping::decide_replacement(
+                    &original,
+                    &actual_metadata,
+                    new_size,
+                    &crate::timestamping::TimestampPolicy::from_config(self.client.config()),
+                )
+            };
+            tracing::info!(decision = ?decision, "Timestamping decision (post-download)");
 
-                if should_replace {
-                    // Replace original with temp file
-                    tracing::debug!(from = %tmp_path.display(), to = %path.display(), "Replacing original file with new version");
-                    tokio::fs::rename(tmp_path, &path).await?;
-                } else {
-                    // Keep original, delete temp file
-                    tracing::debug!(temp = %tmp_path.display(), "Deleting temporary file, keeping original");
-                    tokio::fs::remove_file(tmp_path).await?;
-                }
+            if decision.replaced() {
+                // Replace original with temp file
+                tracing::debug!(from = %tmp_path.display(), to = %path.display(), "Replacing original file with new version");
+                tokio::fs::rename(tmp_path, &path).await?;
+            } else {
+                // Keep original, delete temp file
+                tracing::debug!(temp = %tmp_path.display(), "Deleting temporary file, keeping original");
+                tokio::fs::remove_file(tmp_path).await?;
+                checksum = None;
             }
+            timestamp_decision = Some(decision);
+        } else if let Some(from) = resume_partial {
+            timestamp_decision = Some(TimestampDecision::Resumed { from });
         }
 
         // Check if we should create/keep the file
         // Remove empty files for 204 No Content or 0 bytes without resume
         // Skip this check if we used temp_path (timestamping mode) - file handling is done above
+        // Use the GET response's status, not HEAD's: HEAD may disagree with the actual body
         if temp_path.is_none()
             && !skip_head
+            && !replace
             && !crate::response_handler::should_create_file(
-                metadata.status_code,
+                actual_metadata.status_code,
                 total_bytes,
                 resume_from,
             )
@@ -838,11 +1619,17 @@ impl Downloader {
             }
 
             // Return empty result without a file
-            return Ok(DownloadResult {
-                data: DownloadedData::new_memory(Bytes::new()),
-                url: url.to_string(),
-                metadata,
-            });
+            return Ok((
+                DownloadResult {
+                    data: DownloadedData::new_memory(Bytes::new()),
+                    url: url.to_string(),
+                    metadata: actual_metadata,
+                    timestamp_decision: None,
+                    checksum: None,
+                    stats: DownloadStats::default(),
+                },
+                false,
+            ));
         }
 
         // Set file modification time from server if configured and available
@@ -857,6 +1644,15 @@ impl Downloader {
             )?;
         }
 
+        // Apply configured permission bits (unix only)
+        if path.exists() {
+            let content_type = actual_metadata
+                .content_type
+                .as_deref()
+                .or(metadata.content_type.as_deref());
+            crate::permissions::apply_file_mode(&path, self.client.config(), content_type)?;
+        }
+
         // In timestamping mode, if we got 0 bytes (304 Not Modified), use the existing file size
         let final_size = if skip_head && total_bytes == 0 && path.exists() {
             tokio::fs::metadata(&path).await?.len()
@@ -864,11 +1660,242 @@ impl Downloader {
             total_bytes
         };
 
-        Ok(DownloadResult {
-            data: DownloadedData::new_file(path, final_size, resume_from > 0),
-            url: url.to_string(),
-            metadata,
-        })
+        // Whether this download wrote the file's content (for provenance)
+        let written = timestamp_decision
+            .as_ref()
+            .is_none_or(|d| d.replaced() || matches!(d, TimestampDecision::Resumed { .. }))
+            && path.exists();
+        if self.client.config().use_etag {
+            use crate::timestamping::write_etag;
+            // A 304 may carry a refreshed ETag for the content kept
+            if written {
+                write_etag(&path, actual_metadata.etag.as_deref()).await;
+            } else if let (Some(TimestampDecision::NotModified), Some(etag)) =
+                (timestamp_decision.as_ref(), actual_metadata.etag.as_deref())
+            {
+                write_etag(&path, Some(etag)).await;
+            }
+        }
+        if let Some(mut f) = file_option.take() {
+            f.flush().await?;
+        }
+
+        // Report the metadata of the response we actually downloaded
+        Ok((
+            DownloadResult {
+                data: DownloadedData::new_file(path, final_size, resume_from > 0),
+                url: url.to_string(),
+                metadata: actual_metadata,
+                timestamp_decision,
+                checksum,
+                stats,
+            },
+            written,
+        ))
+    }
+
+    /// Probe a URL's response headers without downloading the body
+    ///
+    /// Sends HEAD, falling back to a one-byte ranged GET when the server
+    /// rejects HEAD (400, 403, 405, 501) or HEAD is disabled by `gnu_wget_compat`.
+    /// The returned metadata carries the typed headers (Allow, Accept-Ranges,
+    /// Cache-Control, Age, Content-Language).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails
+    pub async fn head(&self, url: &str) -> Result<crate::client::ResourceMetadata> {
+        let url = crate::prepare_url(url)?;
+        let url = url.as_str();
+        if !self.client.config().gnu_wget_compat {
+            let metadata = self.client.get_metadata(url).await?;
+            if !link_check::needs_get_fallback(metadata.status_code) {
+                return Ok(metadata);
+            }
+            tracing::debug!(url = %url, status = metadata.status_code, "HEAD rejected, probing with ranged GET");
+        }
+        self.client.get_metadata_ranged(url).await
+    }
+
+    /// Plan a file download without writing anything (dry run)
+    ///
+    /// Sends at most one HEAD request to learn the size, range support and
+    /// timestamps, then reports whether the download would start fresh,
+    /// resume, or be skipped, and which conditional headers would be sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata probe fails or the target can't be inspected
+    pub async fn plan(&self, url: &str, target: &std::path::Path) -> Result<DownloadPlan> {
+        let url = crate::prepare_url(url)?;
+        let url = url.as_str();
+        let metadata = self.client.get_metadata(url).await?;
+        self.plan_with_metadata(url, target, Some(metadata)).await
+    }
+
+    /// Plan a file download from already known metadata
+    ///
+    /// Pass `None` to plan without any network requests; decisions that need
+    /// remote metadata (timestamp comparison, parallel chunking) are then left
+    /// undetermined.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target can't be inspected
+    pub async fn plan_with_metadata(
+        &self,
+        url: &str,
+        target: &std::path::Path,
+        metadata: Option<crate::client::ResourceMetadata>,
+    ) -> Result<DownloadPlan> {
+        let url = crate::prepare_url(url)?;
+        let url = url.as_str();
+        DownloadPlan::build(self.client.config(), url, target, metadata).await
+    }
+
+    /// Estimate how much a list of downloads would transfer, without downloading
+    ///
+    /// Every URL is probed (HEAD, falling back to a ranged GET) with the same
+    /// concurrency and per-host limits as [`check_links`](Self::check_links),
+    /// and checked against the `-N`, no-clobber and resume rules. Probing stops
+    /// at `options.time_limit`; the remaining URLs are reported as not probed.
+    pub async fn estimate(&self, urls: &[String], options: EstimateOptions) -> EstimateReport {
+        crate::estimate::estimate(self, urls, &options).await
+    }
+
+    /// Check many links for existence without downloading their bodies
+    ///
+    /// Each URL is probed with HEAD (falling back to a one-byte ranged GET
+    /// when HEAD is rejected). At most `concurrency` probes run at once, at most
+    /// `MAX_CHECKS_PER_HOST` per host, and `wait_time` is applied between
+    /// probes to the same host. Results are returned in input order.
+    pub async fn check_links(&self, urls: Vec<String>, concurrency: usize) -> Vec<LinkCheckResult> {
+        link_check::check_links(&self.client, prepare_links(urls), concurrency, None).await
+    }
+
+    /// Check many links, reporting (completed, total) after each one
+    pub async fn check_links_with_progress(
+        &self,
+        urls: Vec<String>,
+        concurrency: usize,
+        progress: LinkCheckProgress,
+    ) -> Vec<LinkCheckResult> {
+        link_check::check_links(&self.client, prepare_links(urls), concurrency, Some(progress))
+            .await
+    }
+
+    /// Ask the origin whether the recorded files changed since they were downloaded
+    ///
+    /// Records with a URL and an `ETag` or `Last-Modified` are probed with
+    /// `If-Modified-Since` (HEAD, falling back to a ranged GET); a 304 or the
+    /// same validators mean unchanged. Concurrency and per-host limits are
+    /// those of [`check_links`](Self::check_links). Records are in the order given.
+    pub async fn verify_remote(
+        &self,
+        records: &crate::VerifyRecords,
+        concurrency: usize,
+    ) -> crate::VerifyRemoteReport {
+        crate::verify::verify_remote(self, records, concurrency).await
+    }
+
+    /// Keep `path` an up-to-date, verified copy of `url`
+    ///
+    /// Only transfers anything if the remote file is newer (or, with
+    /// `use_etag`, has another `ETag`); continues a partial download left by an
+    /// interrupted call when the remote file hasn't changed; verifies the
+    /// result against `options.expected_checksum` or the server's digest; and
+    /// replaces `path` atomically, so it is never left half-written. See the
+    /// `mirror` module for the exact steps.
+    ///
+    /// ```no_run
+    /// use wget_faster_lib::{DownloadConfig, Downloader, MirrorOptions};
+    /// use std::path::Path;
+    ///
+    /// # async fn example() -> wget_faster_lib::Result<()> {
+    /// let downloader = Downloader::new(DownloadConfig::default())?;
+    /// let outcome = downloader
+    ///     .mirror_file("https://example.com/data.csv", Path::new("data.csv"), MirrorOptions::default())
+    ///     .await?;
+    /// println!("changed: {}", outcome.changed());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the probe or download fails or returns an error
+    /// status, or `Error::ChecksumMismatch` if the new file fails
+    /// verification (the existing file is then kept).
+    pub async fn mirror_file(
+        &self,
+        url: &str,
+        path: &Path,
+        options: MirrorOptions,
+    ) -> Result<MirrorOutcome> {
+        let url = crate::prepare_url(url)?;
+        crate::mirror::mirror_file(self, url.as_str(), path, &options).await
+    }
+
+    /// Download many URLs, up to `concurrency` at once
+    ///
+    /// Downloads share this downloader's connection pool. At most
+    /// `MAX_CHECKS_PER_HOST` run against one host, with `wait_time` (and
+    /// `random_wait`) between their starts. Once `quota` bytes have been
+    /// downloaded, URLs not yet started fail with [`Error::QuotaExceeded`].
+    /// A failed URL doesn't stop the others; results are in input order.
+    ///
+    /// Output files are reserved in the [`name_registry`](Self::name_registry)
+    /// while they download, so two URLs saving to the same file get `.1`, `.2`,
+    /// ... suffixes. With `content_disposition`, each file is named after the
+    /// server's suggestion, in the directory of its requested path.
+    ///
+    /// ```no_run
+    /// use wget_faster_lib::{DownloadConfig, Downloader, Output};
+    /// use std::path::PathBuf;
+    ///
+    /// # async fn example() -> wget_faster_lib::Result<()> {
+    /// let downloader = Downloader::new(DownloadConfig::default())?;
+    /// let requests = vec![
+    ///     ("https://example.com/a.zip".to_string(), Output::File(PathBuf::from("a.zip"))),
+    ///     ("https://example.com/b.txt".to_string(), Output::Memory),
+    /// ];
+    /// for result in downloader.download_many(requests, 4, None).await {
+    ///     println!("{} bytes", result?.data.total_bytes);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_many(
+        &self,
+        requests: Vec<(String, Output)>,
+        concurrency: usize,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Vec<Result<DownloadResult>> {
+        crate::batch::download_many(self, requests, concurrency, progress_callback).await
+    }
+
+    /// Like [`Downloader::download_many`], resuming the batch recorded in `state`
+    ///
+    /// URLs `state` has as completed are skipped without a request and yield
+    /// `Ok(None)`. The others are downloaded as usual, so a partial file left
+    /// by an interrupted run is continued, and their start and outcome are
+    /// appended to the state file. Call [`BatchState::compact`](crate::BatchState::compact)
+    /// once the batch is done.
+    pub async fn download_many_with_state(
+        &self,
+        requests: Vec<(String, Output)>,
+        concurrency: usize,
+        progress_callback: Option<ProgressCallback>,
+        state: &crate::BatchState,
+    ) -> Vec<Result<Option<DownloadResult>>> {
+        crate::batch::download_many_with_state(
+            self,
+            requests,
+            concurrency,
+            progress_callback,
+            state,
+        )
+        .await
     }
 
     /// Download with custom output destination
@@ -879,12 +1906,15 @@ impl Downloader {
     /// # Arguments
     ///
     /// * `url` - The URL to download
-    /// * `output` - The output destination (Memory, File, or `AsyncWrite`)
+    /// * `output` - The output destination (`Output::Memory` or `Output::File`)
     /// * `progress_callback` - Optional callback function for progress updates
     ///
     /// # Returns
     ///
-    /// A `DownloadResult` containing download metadata and information
+    /// A `DownloadResult` containing download metadata and information. Its
+    /// `metadata` is that of the response the body came from, so with
+    /// `content_on_error` an error page saved to a file or kept in memory
+    /// carries its real status code.
     ///
     /// # Errors
     ///
@@ -923,26 +1953,299 @@ impl Downloader {
         output: Output,
         progress_callback: Option<ProgressCallback>,
     ) -> Result<DownloadResult> {
+        let progress_callback = self.feeding_progress_sink(progress_callback);
+        let result = self.download_to(url, output, progress_callback).await;
+        self.emit_outcome(url, result.as_ref().map(download_summary));
+        result
+    }
+
+    /// [`Downloader::download`] without reporting the outcome as an event
+    async fn download_to(
+        &self,
+        url: &str,
+        output: Output,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<DownloadResult> {
+        let url = crate::prepare_url(url)?;
+        let url = url.as_str();
+        self.client.quota().check()?;
         match output {
-            Output::Memory => {
-                let bytes = self
-                    .download_to_memory_with_progress(url, progress_callback)
+            Output::Memory => self.load_with_retries(url, progress_callback).await,
+
+            Output::File(path) => self.save_with_retries(url, path, progress_callback).await,
+
+            Output::MemoryCapped {
+                max_bytes,
+                spill_path,
+            } => {
+                let target = SpillTarget {
+                    max_bytes,
+                    path: spill_path,
+                };
+                let (collected, metadata) = self
+                    .fetch_to_memory(url, progress_callback, Some(&target))
                     .await?;
-
-                let metadata = self.client.get_metadata(url).await?;
+                let data = match collected {
+                    Collected::Memory(bytes) => DownloadedData::new_memory(bytes),
+                    Collected::File { path, len } => DownloadedData::new_spilled(path, len),
+                };
 
                 Ok(DownloadResult {
-                    data: DownloadedData::new_memory(bytes),
+                    data,
                     url: url.to_string(),
                     metadata,
+                    timestamp_decision: None,
+                    checksum: None,
+                    stats: DownloadStats::default(),
                 })
             },
+        }
+    }
 
-            Output::File(path) => {
-                self.download_to_file_with_progress(url, path, progress_callback, false)
-                    .await
+    /// Like [`Downloader::download`], with a handle to cancel or pause the download
+    ///
+    /// The download runs while the returned future is awaited; the handle is
+    /// checked before each request for a parallel chunk and each read of a
+    /// response body. A cancelled file download fails with `Error::Cancelled`
+    /// and leaves the partial file, which a later download to the same path resumes.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::PathBuf;
+    /// use wget_faster_lib::{Downloader, DownloadConfig, Output};
+    ///
+    /// # async fn example() -> wget_faster_lib::Result<()> {
+    /// let downloader = Downloader::new(DownloadConfig::default())?;
+    /// let output = Output::File(PathBuf::from("big.iso"));
+    /// let (handle, download) =
+    ///     downloader.download_controlled("https://example.com/big.iso", output, None);
+    /// handle.pause();
+    /// handle.resume();
+    /// let result = download.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn download_controlled<'a>(
+        &'a self,
+        url: &'a str,
+        output: Output,
+        progress_callback: Option<ProgressCallback>,
+    ) -> (DownloadHandle, impl Future<Output = Result<DownloadResult>> + 'a) {
+        let (handle, control) = DownloadHandle::new();
+        (handle, control.scope(self.download(url, output, progress_callback)))
+    }
+
+    /// Download `url` to memory through the HTTP cache
+    ///
+    /// Fresh entries are returned without a request; stale ones are sent as
+    /// conditional requests and reused on 304. Cacheable 200 responses are stored.
+    async fn download_cached(
+        &self,
+        cache: &HttpCache,
+        url: &str,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<(Bytes, crate::client::ResourceMetadata, CacheStatus)> {
+        let config = self.client.config();
+        let cached = match cache.lookup(url, config).await {
+            Some(mut entry) if entry.is_fresh() => {
+                tracing::debug!(url = %url, "Serving fresh response from the HTTP cache");
+                let bytes = entry.body().await?;
+                cache.touch(&mut entry).await;
+                cache.record(CacheStatus::Hit);
+                return Ok((bytes, entry.metadata(), CacheStatus::Hit));
             },
+            cached => cached,
+        };
+
+        let build = || {
+            let mut request = self.build_request(url, None, None)?;
+            if let Some(ref entry) = cached {
+                if let Some(etag) = entry.header("etag") {
+                    request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = entry.header("last-modified") {
+                    request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            Ok(request)
+        };
+        let response = self.client.send(build()?).await?;
+        let response = self.retry_while_accepted(response, build).await?;
+        let status_code = response.status().as_u16();
+        let metadata = HttpClient::extract_metadata_from_response(&response);
+
+        if let (304, Some(entry)) = (status_code, cached) {
+            tracing::debug!(url = %url, "Cached response revalidated");
+            let entry = cache.refresh(entry, &metadata).await?;
+            let bytes = entry.body().await?;
+            cache.record(CacheStatus::Revalidated);
+            return Ok((bytes, entry.metadata(), CacheStatus::Revalidated));
+        }
+
+        cache.record(CacheStatus::Miss);
+        if crate::auth_handler::should_retry_auth(status_code, config) {
+            // Let the regular path answer the challenge; the response isn't cached
+            let bytes = self.download_sequential(url, progress_callback).await?;
+            return Ok((bytes, metadata, CacheStatus::Miss));
         }
+
+        let bytes = self
+            .process_sequential_response(response, url, progress_callback)
+            .await?;
+        if let Err(e) = cache.store(url, config, &metadata, &bytes).await {
+            tracing::warn!(url = %url, error = %e, "Could not store response in the HTTP cache");
+        }
+        Ok((bytes, metadata, CacheStatus::Miss))
+    }
+
+    /// Serve a file download from a fresh cache entry by copying it to `path`
+    ///
+    /// Only for new files: an existing one may be resumed or timestamped instead.
+    async fn copy_from_cache(
+        &self,
+        url: &str,
+        path: &std::path::Path,
+    ) -> Result<Option<DownloadResult>> {
+        let Some(cache) = self.http_cache() else {
+            return Ok(None);
+        };
+        if path.exists() {
+            return Ok(None);
+        }
+        let Some(mut entry) = cache
+            .lookup(url, self.client.config())
+            .await
+            .filter(crate::http_cache::CachedResponse::is_fresh)
+        else {
+            return Ok(None);
+        };
+
+        tracing::debug!(url = %url, path = %path.display(), "Copying fresh response from the HTTP cache");
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::copy(entry.body_path(), path).await?;
+        let expected_checksum = self.client.config().expected_checksum.as_ref();
+        let mut hasher = crate::checksum::Hasher::new(expected_checksum);
+        hasher.update_from_file(path).await?;
+        let checksum = hasher.finish();
+        if let Err(e) = crate::checksum::verify(expected_checksum, &checksum) {
+            tokio::fs::remove_file(path).await?;
+            return Err(e);
+        }
+        cache.touch(&mut entry).await;
+        cache.record(CacheStatus::Hit);
+        Ok(Some(DownloadResult {
+            data: DownloadedData::new_file(path.to_path_buf(), entry.size(), false),
+            url: url.to_string(),
+            metadata: entry.metadata(),
+            timestamp_decision: None,
+            checksum: Some(checksum),
+            stats: DownloadStats {
+                cache: Some(CacheStatus::Hit),
+                ..DownloadStats::default()
+            },
+        }))
+    }
+
+    /// Count a download in the HTTP cache statistics
+    fn record_cache(&self, status: CacheStatus) {
+        if let Some(cache) = &self.cache {
+            cache.record(status);
+        }
+    }
+
+    /// Emit `Redirected` (if the response came from elsewhere) and `Started` as a body transfer begins
+    fn emit_started(&self, url: &str, metadata: &crate::client::ResourceMetadata) {
+        if let Some(to) = metadata.final_url.as_deref().filter(|to| *to != url) {
+            self.client.emit(|| DownloadEvent::Redirected {
+                from: url.to_string(),
+                to: to.to_string(),
+            });
+        }
+        self.client.emit(|| DownloadEvent::Started {
+            url: url.to_string(),
+            metadata: Box::new(metadata.clone()),
+        });
+    }
+
+    /// `progress_callback`, also feeding `config.progress_sink` if one is set
+    ///
+    /// Each download gets its own feed, whose last update is sent once the
+    /// download drops the callback.
+    fn feeding_progress_sink(
+        &self,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Option<ProgressCallback> {
+        let Some(sink) = &self.client.config().progress_sink else {
+            return progress_callback;
+        };
+        let feed = sink.feed();
+        Some(std::sync::Arc::new(move |info: ProgressInfo| {
+            if let Some(callback) = &progress_callback {
+                callback(info.clone());
+            }
+            feed.offer(info);
+        }))
+    }
+
+    /// [`Self::emit_started`] for a response whose body is about to be read
+    fn emit_response_started(&self, url: &str, response: &reqwest::Response) {
+        if self.client.config().event_callback.is_some() {
+            self.emit_started(url, &HttpClient::extract_metadata_from_response(response));
+        }
+    }
+
+    /// Emit `Started` for a response whose body is about to be saved, writing its
+    /// header preamble first with `save_headers`
+    ///
+    /// A resumed file already starts with its preamble. The preamble is not
+    /// part of the body, so it is left out of the checksum. Returns the number
+    /// of preamble bytes written, which the download's byte count includes.
+    async fn start_saving<W>(
+        &self,
+        url: &str,
+        response: &reqwest::Response,
+        writer: &mut HashingWriter<W>,
+        resume_from: u64,
+    ) -> Result<u64>
+    where
+        W: AsyncWriteExt + Unpin + Send,
+    {
+        self.emit_response_started(url, response);
+        if resume_from > 0 || !self.client.config().save_headers {
+            return Ok(0);
+        }
+        let status = response.status().as_u16();
+        let preamble =
+            crate::saved_headers::preamble(response.version(), status, response.headers());
+        writer.get_mut().write_all(&preamble).await?;
+        Ok(preamble.len() as u64)
+    }
+
+    /// Emit `RetryScheduled` for a request repeated at once with credentials after a 401/407
+    fn emit_auth_retry(&self, status_code: u16) {
+        self.client.emit(|| DownloadEvent::RetryScheduled {
+            attempt: 1,
+            delay: Duration::ZERO,
+            cause: format!("HTTP {status_code} - authentication required"),
+        });
+    }
+
+    /// Emit `Completed` with the bytes and file of a finished download, or `Failed`
+    fn emit_outcome(&self, url: &str, outcome: std::result::Result<(u64, Option<&Path>), &Error>) {
+        self.client.emit(|| match outcome {
+            Ok((bytes, path)) => DownloadEvent::Completed {
+                url: url.to_string(),
+                bytes,
+                path: path.map(Path::to_path_buf),
+            },
+            Err(e) => DownloadEvent::Failed {
+                url: url.to_string(),
+                error: e.to_string(),
+            },
+        });
     }
 
     /// Sequential download (fallback for servers that don't support Range)
@@ -951,9 +2254,39 @@ impl Downloader {
         url: &str,
         progress_callback: Option<ProgressCallback>,
     ) -> Result<Bytes> {
+        let (collected, _) = self
+            .download_sequential_to(url, progress_callback, None)
+            .await?;
+        collected.into_bytes().await
+    }
+
+    /// Sequential download, spilling to the file of `spill` if given and needed
+    ///
+    /// Returned with the metadata of the response.
+    async fn download_sequential_to(
+        &self,
+        url: &str,
+        progress_callback: Option<ProgressCallback>,
+        spill: Option<&SpillTarget>,
+    ) -> Result<(Collected, crate::client::ResourceMetadata)> {
         tracing::debug!(url = %url, "Starting sequential download");
+        let response = self.send_sequential(url).await?;
+        let metadata = HttpClient::extract_metadata_from_response(&response);
+        let collected = self
+            .collect_sequential_response(response, url, progress_callback, spill)
+            .await?;
+        Ok((collected, metadata))
+    }
+
+    /// Send the GET of a sequential download, answering an auth challenge if needed
+    ///
+    /// The response's status is not checked beyond the challenge.
+    async fn send_sequential(&self, url: &str) -> Result<reqwest::Response> {
         let request = self.build_request(url, None, None)?;
-        let response = request.send().await?;
+        let response = self.client.send(request).await?;
+        let response = self
+            .retry_while_accepted(response, || self.build_request(url, None, None))
+            .await?;
 
         let status_code = response.status().as_u16();
         tracing::debug!(status_code, "Received response from GET request");
@@ -966,9 +2299,20 @@ impl Downloader {
                 "Authentication challenge received - retrying with credentials"
             );
 
-            // Get credentials (configured auth or .netrc)
-            if let Some(auth) = crate::auth_handler::get_credentials(url, self.client.config()) {
+            // Remembered credentials (if any were sent) were just rejected
+            let host = url::Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.to_string()));
+            if let Some(ref host) = host {
+                self.client.forget_authenticated_host(host);
+            }
+
+            // Get fresh credentials (credential provider, configured auth or .netrc)
+            if let Some(auth) =
+                crate::auth_handler::challenge_credentials(url, self.client.config()).await
+            {
                 tracing::debug!(username = %auth.username, "Retrying with authentication");
+                self.emit_auth_retry(status_code);
                 // Retry with authentication
                 let retry_request = self
                     .client
@@ -976,7 +2320,7 @@ impl Downloader {
                     .get(url)
                     .basic_auth(&auth.username, Some(&auth.password));
 
-                let retry_response = retry_request.send().await?;
+                let retry_response = self.client.send(retry_request).await?;
                 let retry_status = retry_response.status().as_u16();
                 tracing::debug!(retry_status, "Received retry response with auth");
 
@@ -990,39 +2334,68 @@ impl Downloader {
                 tracing::info!("Authentication successful");
 
                 // Remember this host for future preemptive auth (matches HEAD request behavior)
-                if let Some(host) = url::Url::parse(url)
-                    .ok()
-                    .and_then(|u| u.host_str().map(|h| h.to_string()))
-                {
-                    self.client.mark_host_authenticated(host.clone());
+                if let Some(host) = host {
                     tracing::debug!(host = ?host, "GET request authentication successful - will use preemptive auth for subsequent requests");
+                    self.client.mark_host_authenticated(host, auth);
                 }
 
-                return self
-                    .process_sequential_response(retry_response, url, progress_callback)
-                    .await;
+                return Ok(retry_response);
             }
             // No credentials available
             tracing::warn!("No credentials available for authentication");
             return Err(Error::InvalidStatus(status_code));
         }
 
-        // Check if we should proceed based on status code
-        match crate::response_handler::should_proceed_download(status_code, self.client.config()) {
-            Ok(true) => {
-                // Proceed with download
-                self.process_sequential_response(response, url, progress_callback)
-                    .await
-            },
-            Ok(false) => {
-                // Skip download (304/416 - should not reach here in sequential download)
-                Ok(Bytes::new())
-            },
-            Err(err_status) => {
-                // Return error
-                Err(Error::InvalidStatus(err_status))
-            },
+        Ok(response)
+    }
+
+    /// Re-send a request while the server answers 202 Accepted
+    ///
+    /// Only active with `retry_on_202`; the delay comes from `Retry-After`
+    /// (delta-seconds) or `retry.initial_delay`, capped at `retry.max_delay`.
+    async fn retry_while_accepted<F>(
+        &self,
+        mut response: reqwest::Response,
+        build: F,
+    ) -> Result<reqwest::Response>
+    where
+        F: Fn() -> Result<reqwest::RequestBuilder>,
+    {
+        let config = self.client.config();
+        if !config.retry_on_202 {
+            return Ok(response);
         }
+
+        let mut attempts = 0;
+        while response.status() == reqwest::StatusCode::ACCEPTED {
+            if attempts >= config.retry.max_retries {
+                return Err(Error::MaxRetriesExceeded(attempts));
+            }
+            attempts += 1;
+
+            let delay = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok())
+                .map_or(config.retry.initial_delay, Duration::from_secs)
+                .min(config.retry.max_delay);
+            tracing::info!(
+                url = %response.url(),
+                attempt = attempts,
+                delay_ms = delay.as_millis(),
+                "HTTP 202 Accepted - resource not ready, retrying"
+            );
+            self.client.emit(|| DownloadEvent::RetryScheduled {
+                attempt: attempts,
+                delay,
+                cause: "HTTP 202 Accepted - resource not ready".to_string(),
+            });
+            sleep(delay).await;
+
+            response = self.client.send(build()?).await?;
+        }
+        Ok(response)
     }
 
     /// Helper to process response body for sequential downloads
@@ -1032,13 +2405,27 @@ impl Downloader {
         url: &str,
         progress_callback: Option<ProgressCallback>,
     ) -> Result<Bytes> {
+        self.collect_sequential_response(response, url, progress_callback, None)
+            .await?
+            .into_bytes()
+            .await
+    }
+
+    /// Collect the body of a sequential download, in memory or the file of `spill`
+    async fn collect_sequential_response(
+        &self,
+        response: reqwest::Response,
+        url: &str,
+        progress_callback: Option<ProgressCallback>,
+        spill: Option<&SpillTarget>,
+    ) -> Result<Collected> {
         let status_code = response.status().as_u16();
 
         // Check if we should proceed based on status code
         match crate::response_handler::should_proceed_download(status_code, self.client.config()) {
             Ok(false) => {
                 // Skip download (empty response)
-                return Ok(Bytes::new());
+                return Ok(Collected::Memory(Bytes::new()));
             },
             Err(err_status) => {
                 // Return error
@@ -1048,19 +2435,40 @@ impl Downloader {
                 // Proceed with download
             },
         }
+        self.emit_response_started(url, &response);
 
-        let total_size = response.content_length();
-        let mut downloaded = 0u64;
+        let range_total = if status_code == 206 {
+            crate::response_handler::check_partial_content(&response, 0)?
+        } else {
+            None
+        };
+        let total_size = range_total.or_else(|| saved_length(&response, self.client.config()));
         let start_time = Instant::now();
         let mut last_chunk_time = Instant::now();
-
-        let mut stream = response.bytes_stream();
-        let mut buffer = Vec::new();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            buffer.extend_from_slice(&chunk);
-            downloaded += chunk.len() as u64;
+        let mut progress = ProgressInfo::new(url.to_string());
+        progress.total_size = total_size;
+
+        let mut limit = BodyLimit::for_response(&response, self.client.config());
+        let probe_needed =
+            self.wants_total_probe(&response, total_size, progress_callback.is_some());
+        let mut stream = body_stream(response, self.client.config());
+        let mut buffer = BudgetedBuffer::new(self.memory_budget.as_ref()).with_target(spill);
+
+        // Learn the total size concurrently while the body streams in
+        let probe = self.probe_total_size(url, probe_needed);
+        tokio::pin!(probe);
+        let mut probing = probe_needed;
+
+        while let Some(chunk) = limit
+            .within_time(next_chunk(&mut stream, probe.as_mut(), &mut probing, &mut progress))
+            .await?
+        {
+            let chunk = limit.clamp(chunk?);
+            if chunk.is_empty() {
+                continue;
+            }
+            self.client.quota().add(chunk.len() as u64)?;
+            buffer.push(&chunk).await?;
 
             // Apply speed limiting if configured
             if let Some(speed_limit) = self.client.config().speed_limit {
@@ -1076,28 +2484,85 @@ impl Downloader {
             }
 
             if let Some(callback) = &progress_callback {
-                let mut progress = ProgressInfo::new(url.to_string());
-                progress.total_size = total_size;
                 progress.update(chunk.len() as u64, start_time);
-                progress.downloaded = downloaded;
-                callback(progress);
+                callback(progress.clone());
             }
         }
+        limit.warn_if_discarded(url);
+        warn_if_probe_mismatch(url, probe_needed, progress.total_size, buffer.len());
+
+        buffer.finish().await
+    }
+
+    /// Whether to probe for the total size of a body sent without a length
+    ///
+    /// Requires `probe_total_size`, a progress callback to report to, and a
+    /// server advertising range support.
+    fn wants_total_probe(
+        &self,
+        response: &reqwest::Response,
+        total_size: Option<u64>,
+        has_callback: bool,
+    ) -> bool {
+        self.client.config().probe_total_size
+            && total_size.is_none()
+            && has_callback
+            && response
+                .headers()
+                .get(reqwest::header::ACCEPT_RANGES)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.eq_ignore_ascii_case("bytes"))
+    }
+
+    /// Learn the total size from the Content-Range of a `bytes=0-0` request
+    ///
+    /// Resolves to `None` right away unless `enabled`, or if the server
+    /// doesn't answer with a usable 206.
+    async fn probe_total_size(&self, url: &str, enabled: bool) -> Option<u64> {
+        if !enabled {
+            return None;
+        }
 
-        Ok(Bytes::from(buffer))
+        let request = self
+            .build_request_with_auth(url, Some("bytes=0-0"), None, false)
+            .ok()?;
+        let total = match self.client.send(request).await {
+            Ok(response) if response.status().as_u16() == 206 => response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(crate::response_handler::parse_content_range)
+                .and_then(|(_, _, total)| total),
+            Ok(response) => {
+                tracing::debug!(
+                    status = response.status().as_u16(),
+                    "Size probe not answered with 206"
+                );
+                None
+            },
+            Err(e) => {
+                tracing::debug!(error = %e, "Size probe failed");
+                None
+            },
+        };
+        if let Some(total) = total {
+            tracing::debug!(url = %url, total, "Learned total size from range probe");
+        }
+        total
     }
 
     /// Sequential download to writer
-    /// Returns (bytes_downloaded, actual_metadata_from_response)
+    /// Returns (`bytes_downloaded`, `actual_metadata_from_response`, `transfer_stats`)
     async fn download_sequential_to_writer<W>(
         &self,
         url: &str,
-        writer: &mut W,
+        writer: &mut HashingWriter<W>,
         progress_callback: Option<ProgressCallback>,
         resume_from: u64,
-        if_modified_since: Option<std::time::SystemTime>,
+        conditional: &Validators,
         force_preemptive_auth: bool,
-    ) -> Result<(u64, crate::client::ResourceMetadata)>
+        encoding_marker: Option<&Path>,
+    ) -> Result<(u64, crate::client::ResourceMetadata, DownloadStats)>
     where
         W: AsyncWriteExt + Unpin + Send,
     {
@@ -1107,33 +2572,47 @@ impl Downloader {
             None
         };
 
-        let request = self.build_request_with_auth(
-            url,
-            range_header.as_deref(),
-            if_modified_since,
-            force_preemptive_auth,
-        )?;
-        let response = request.send().await?;
+        let build = || {
+            self.build_request_with_auth(
+                url,
+                range_header.as_deref(),
+                Some(conditional),
+                force_preemptive_auth,
+            )
+            .map(|request| self.client.identity_encoding(request))
+        };
+        let response = self.client.send(build()?).await?;
+        let response = self.retry_while_accepted(response, build).await?;
 
         let status_code = response.status().as_u16();
 
         // Handle authentication challenges (401/407)
         // If we have credentials but didn't send them preemptively, retry with auth
         if crate::auth_handler::should_retry_auth(status_code, self.client.config()) {
-            // Get credentials (configured auth or .netrc)
-            if let Some(auth) = crate::auth_handler::get_credentials(url, self.client.config()) {
+            // Remembered credentials (if any were sent) were just rejected
+            let host = url::Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.to_string()));
+            if let Some(ref host) = host {
+                self.client.forget_authenticated_host(host);
+            }
+
+            // Get fresh credentials (credential provider, configured auth or .netrc)
+            if let Some(auth) =
+                crate::auth_handler::challenge_credentials(url, self.client.config()).await
+            {
+                self.emit_auth_retry(status_code);
                 // Retry with authentication (preserving range header if needed)
                 let mut retry_request = self
                     .client
-                    .client()
-                    .get(url)
+                    .identity_encoding(self.client.client().get(url))
                     .basic_auth(&auth.username, Some(&auth.password));
 
                 if let Some(ref range) = range_header {
                     retry_request = retry_request.header(reqwest::header::RANGE, range);
                 }
 
-                let retry_response = retry_request.send().await?;
+                let retry_response = self.client.send(retry_request).await?;
                 let retry_status = retry_response.status().as_u16();
 
                 // Extract metadata from retry response before processing
@@ -1148,15 +2627,17 @@ impl Downloader {
                 // Success! Continue with retry_response
 
                 // Remember this host for future preemptive auth (matches HEAD request behavior)
-                if let Some(host) = url::Url::parse(url)
-                    .ok()
-                    .and_then(|u| u.host_str().map(|h| h.to_string()))
-                {
-                    self.client.mark_host_authenticated(host.clone());
+                if let Some(host) = host {
                     tracing::debug!(host = ?host, "GET request authentication successful - will use preemptive auth for subsequent requests");
+                    self.client.mark_host_authenticated(host, auth);
                 }
 
-                let bytes = self
+                if let Some(ref filter) = self.client.config().response_filter {
+                    filter.check(url, &retry_metadata)?;
+                }
+                record_content_coding(&retry_response, resume_from, encoding_marker).await?;
+
+                let (bytes, stats) = self
                     .process_writer_response(
                         retry_response,
                         url,
@@ -1166,7 +2647,7 @@ impl Downloader {
                     )
                     .await?;
 
-                return Ok((bytes, retry_metadata));
+                return Ok((bytes, retry_metadata, stats));
             }
             // No credentials available
             return Err(Error::InvalidStatus(status_code));
@@ -1182,7 +2663,7 @@ impl Downloader {
         match response_status {
             ResponseStatus::NoContent => {
                 // 204 No Content - don't create file
-                return Ok((0, metadata));
+                return Ok((0, metadata, DownloadStats::default()));
             },
             ResponseStatus::NotModified => {
                 // 304 Not Modified - file is already up to date
@@ -1192,16 +2673,22 @@ impl Downloader {
                 writer.flush().await?;
                 // Return 0 to indicate no new bytes were downloaded
                 // The caller will handle keeping the existing file
-                return Ok((0, metadata));
+                return Ok((0, metadata, DownloadStats::default()));
             },
             ResponseStatus::RangeNotSatisfiable => {
                 // 416 Range Not Satisfiable - file is already complete
-                return Ok((resume_from, metadata));
+                return Ok((resume_from, metadata, DownloadStats::default()));
+            },
+            ResponseStatus::Redirect if !self.client.config().content_on_error => {
+                // Unfollowed redirect - no file, the caller reads the Location from metadata
+                return Ok((0, metadata, DownloadStats::default()));
             },
             ResponseStatus::Success => {
                 // 200 OK or 206 Partial Content - proceed
             },
-            ResponseStatus::ClientError | ResponseStatus::ServerError => {
+            ResponseStatus::ClientError
+            | ResponseStatus::ServerError
+            | ResponseStatus::Redirect => {
                 // Check content_on_error
                 if !self.client.config().content_on_error {
                     return Err(Error::InvalidStatus(status_code));
@@ -1214,9 +2701,15 @@ impl Downloader {
             },
         }
 
+        // Headers are known now - drop the response unread if the filter rejects it
+        if let Some(ref filter) = self.client.config().response_filter {
+            filter.check(url, &metadata)?;
+        }
+        record_content_coding(&response, resume_from, encoding_marker).await?;
+
         self.process_writer_response(response, url, writer, progress_callback, resume_from)
             .await
-            .map(|bytes| (bytes, metadata))
+            .map(|(bytes, stats)| (bytes, metadata, stats))
     }
 
     /// Helper to process response body for sequential downloads to writer
@@ -1224,10 +2717,10 @@ impl Downloader {
         &self,
         response: reqwest::Response,
         url: &str,
-        writer: &mut W,
+        writer: &mut HashingWriter<W>,
         progress_callback: Option<ProgressCallback>,
         resume_from: u64,
-    ) -> Result<u64>
+    ) -> Result<(u64, DownloadStats)>
     where
         W: AsyncWriteExt + Unpin + Send,
     {
@@ -1238,52 +2731,75 @@ impl Downloader {
         let response_status = ResponseStatus::from_status_code(status_code);
 
         match response_status {
-            ResponseStatus::NoContent => {
-                // 204 No Content - don't create file
-                return Ok(0);
-            },
+            // 204 No Content - don't create file
+            ResponseStatus::NoContent => return Ok((0, DownloadStats::default())),
             ResponseStatus::NotModified => {
                 // 304 Not Modified - file is already up to date
                 tracing::info!("HTTP 304 Not Modified - file is up to date");
-                return Ok(resume_from);
+                return Ok((resume_from, DownloadStats::default()));
             },
             ResponseStatus::RangeNotSatisfiable => {
                 // 416 Range Not Satisfiable - file is already complete
-                return Ok(resume_from);
+                return Ok((resume_from, DownloadStats::default()));
             },
-            ResponseStatus::Success => {
-                // 200 OK or 206 Partial Content - proceed
-            },
-            ResponseStatus::ClientError | ResponseStatus::ServerError => {
+            // 200 OK or 206 Partial Content - proceed
+            ResponseStatus::Success => {},
+            ResponseStatus::ClientError
+            | ResponseStatus::ServerError
+            | ResponseStatus::Redirect => {
                 // Check content_on_error
                 if !self.client.config().content_on_error {
                     return Err(Error::InvalidStatus(status_code));
                 }
                 // Proceed to download error page
             },
-            _ => {
-                // Other non-success status codes
-                return Err(Error::InvalidStatus(status_code));
-            },
+            // Other non-success status codes
+            _ => return Err(Error::InvalidStatus(status_code)),
         }
+        let header_bytes = self
+            .start_saving(url, &response, writer, resume_from)
+            .await?;
 
-        let total_size = response.content_length().map(|s| s + resume_from);
-        let mut downloaded = resume_from;
+        let range_total = if status_code == 206 {
+            crate::response_handler::check_partial_content(&response, resume_from)?
+        } else {
+            None
+        };
+        let total_size = range_total
+            .or_else(|| saved_length(&response, self.client.config()).map(|s| s + resume_from));
+        let mut downloaded = resume_from + header_bytes;
         let start_time = Instant::now();
         let mut last_chunk_time = Instant::now();
-
-        let mut stream = response.bytes_stream();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
+        let mut progress = ProgressInfo::new(url.to_string());
+        progress.total_size = total_size.map(|total| total + header_bytes);
+        progress.downloaded = downloaded;
+
+        let mut limit = BodyLimit::for_response(&response, self.client.config());
+        let probe_needed =
+            self.wants_total_probe(&response, total_size, progress_callback.is_some());
+        let mut stream = body_stream(response, self.client.config());
+
+        // Learn the total size concurrently while the body streams in
+        let probe = self.probe_total_size(url, probe_needed);
+        tokio::pin!(probe);
+        let mut probing = probe_needed;
+
+        while let Some(chunk) = limit
+            .within_time(next_chunk(&mut stream, probe.as_mut(), &mut probing, &mut progress))
+            .await?
+        {
+            let chunk = limit.clamp(chunk?);
+            if chunk.is_empty() {
+                continue;
+            }
+            self.client.quota().add(chunk.len() as u64)?;
             writer.write_all(&chunk).await?;
             downloaded += chunk.len() as u64;
 
             // Apply speed limiting if configured
             if let Some(speed_limit) = self.client.config().speed_limit {
-                let chunk_size = chunk.len() as u64;
                 let expected_duration =
-                    Duration::from_secs_f64(chunk_size as f64 / speed_limit as f64);
+                    Duration::from_secs_f64(chunk.len() as f64 / speed_limit as f64);
                 let actual_duration = last_chunk_time.elapsed();
 
                 if actual_duration < expected_duration {
@@ -1293,20 +2809,220 @@ impl Downloader {
             }
 
             if let Some(callback) = &progress_callback {
-                let mut progress = ProgressInfo::new(url.to_string());
-                progress.total_size = total_size;
                 progress.update(chunk.len() as u64, start_time);
-                progress.downloaded = downloaded;
-                callback(progress);
+                callback(progress.clone());
             }
         }
 
         writer.flush().await?;
+        limit.warn_if_discarded(url);
+        warn_if_probe_mismatch(url, probe_needed, progress.total_size, downloaded);
 
-        Ok(downloaded)
+        let stats = DownloadStats {
+            excess_bytes_discarded: limit.discarded(),
+            cache: None,
+        };
+        Ok((downloaded, stats))
     }
 }
 
+/// Whether a download truncates an existing file instead of resuming it (`-O`)
+pub(crate) fn replaces_existing(config: &DownloadConfig) -> bool {
+    config.overwrite_existing && !config.timestamping && config.start_pos.is_none()
+}
+
+/// Byte offset a file download to `path` starts at
+///
+/// `resume_partial` is what `-N -c` decided to resume from, and `truncating`
+/// whether the existing file is about to be replaced. If `--start-pos` is
+/// specified, it overrides automatic resume from the file size; with
+/// timestamping (`-N`) nothing is resumed, a conditional GET is sent instead.
+pub(crate) async fn resume_offset(
+    config: &DownloadConfig,
+    path: &Path,
+    resume_partial: Option<u64>,
+    truncating: bool,
+) -> Result<u64> {
+    if let Some(from) = resume_partial {
+        return Ok(from);
+    }
+    if config.timestamping {
+        tracing::debug!("Timestamping enabled - skipping resume, will use conditional GET");
+        return Ok(0);
+    }
+    if let Some(start_pos) = config.start_pos {
+        tracing::debug!(start_pos, "Using --start-pos for resume");
+        return Ok(start_pos);
+    }
+    if truncating || !path.exists() {
+        return Ok(0);
+    }
+
+    let size = tokio::fs::metadata(path).await?.len();
+    let coding = tokio::fs::read_to_string(encoding_marker(path)).await;
+    if let (true, Ok(coding)) = (size > 0, coding) {
+        // Its byte offsets are into the coded body, which a server
+        // needn't reproduce identically
+        tracing::warn!(
+            path = %path.display(),
+            coding = %coding.trim(),
+            "Partial file was saved from a content-coded response - downloading again from the start"
+        );
+        return Ok(0);
+    }
+    if size > 0 {
+        tracing::info!(path = %path.display(), existing_size = size, "Resuming download from existing file");
+    }
+    // The body of a file saved with `save_headers` starts after its preamble
+    if config.save_headers {
+        Ok(size - saved_preamble_len(path).await?)
+    } else {
+        Ok(size)
+    }
+}
+
+/// Length of the header preamble that a partial file saved with `save_headers` starts with
+async fn saved_preamble_len(path: &Path) -> Result<u64> {
+    use tokio::io::AsyncReadExt;
+
+    // Far more than any response head
+    const MAX_PREAMBLE: u64 = 64 * 1024;
+    let mut head = Vec::new();
+    File::open(path)
+        .await?
+        .take(MAX_PREAMBLE)
+        .read_to_end(&mut head)
+        .await?;
+    Ok(crate::saved_headers::split(&head).1 as u64)
+}
+
+/// Bytes and file of a download, as reported by `DownloadEvent::Completed`
+fn download_summary(result: &DownloadResult) -> (u64, Option<&Path>) {
+    (result.data.total_bytes, result.data.file_path.as_deref())
+}
+
+/// Result of a download to memory of `bytes`, from the response described by `metadata`
+fn memory_result(
+    url: &str,
+    bytes: Bytes,
+    metadata: crate::client::ResourceMetadata,
+    stats: DownloadStats,
+) -> DownloadResult {
+    DownloadResult {
+        data: DownloadedData::new_memory(bytes),
+        url: url.to_string(),
+        metadata,
+        timestamp_decision: None,
+        checksum: None,
+        stats,
+    }
+}
+
+/// The body of a download to memory
+fn memory_body(result: DownloadResult) -> Bytes {
+    result.data.into_bytes().unwrap_or_default()
+}
+
+/// File recording that the partial download at `path` was saved content-coded
+///
+/// Bodies are saved as received, so such a partial file is downloaded again
+/// from the start instead of being resumed.
+pub(crate) fn encoding_marker(path: &Path) -> PathBuf {
+    let mut marker = path.as_os_str().to_owned();
+    marker.push(".wgetf-encoding");
+    PathBuf::from(marker)
+}
+
+/// Delete an encoding marker, if there is one
+async fn remove_encoding_marker(marker: &Path) {
+    match tokio::fs::remove_file(marker).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            tracing::warn!(path = %marker.display(), error = %e, "Failed to remove encoding marker");
+        },
+        _ => {},
+    }
+}
+
+/// Check the content-coding of a response to a file download before its body is saved
+///
+/// A coded response starting the file is recorded in `marker`. One continuing
+/// a partial file (saved uncoded, or it would have been started over) can't
+/// be appended to it.
+async fn record_content_coding(
+    response: &reqwest::Response,
+    resume_from: u64,
+    marker: Option<&Path>,
+) -> Result<()> {
+    let coding = response
+        .headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .filter(|coding| !coding.eq_ignore_ascii_case("identity"));
+    let resumed = resume_from > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+
+    match (coding, marker) {
+        (Some(coding), _) if resumed => Err(Error::ObjectChangedDuringDownload(format!(
+            "resumed response has Content-Encoding {coding}, the partial file has none"
+        ))),
+        (Some(coding), Some(marker)) => {
+            tokio::fs::write(marker, coding).await?;
+            Ok(())
+        },
+        (None, Some(marker)) if resume_from == 0 => {
+            remove_encoding_marker(marker).await;
+            Ok(())
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Next body chunk, recording the size probe's answer in `progress` if it arrives first
+///
+/// Waits while the download is paused, and ends the body with
+/// `Error::Cancelled` once it is cancelled (see [`control`](crate::control)).
+async fn next_chunk<S, P>(
+    stream: &mut S,
+    mut probe: std::pin::Pin<&mut P>,
+    probing: &mut bool,
+    progress: &mut ProgressInfo,
+) -> Option<Result<Bytes>>
+where
+    S: futures::Stream<Item = Result<Bytes>> + Unpin,
+    P: std::future::Future<Output = Option<u64>>,
+{
+    let read = async {
+        loop {
+            tokio::select! {
+                total = probe.as_mut(), if *probing => {
+                    *probing = false;
+                    progress.total_size = total;
+                },
+                chunk = stream.next() => return chunk,
+            }
+        }
+    };
+    crate::control::run(read)
+        .await
+        .unwrap_or_else(|e| Some(Err(e)))
+}
+
+/// Log when the size learned from a range probe didn't match the body received
+fn warn_if_probe_mismatch(url: &str, probed: bool, total: Option<u64>, received: u64) {
+    if let (true, Some(total)) = (probed, total) {
+        if total != received {
+            tracing::warn!(url = %url, probed = total, received, "Range probe total differs from body length");
+        }
+    }
+}
+
+/// `urls` passed through [`prepare_url`](crate::prepare_url), keeping those it
+/// rejects so the link check reports them
+fn prepare_links(urls: Vec<String>) -> Vec<String> {
+    urls.into_iter()
+        .map(|url| crate::prepare_url(&url).map_or(url, String::from))
+        .collect()
+}
+
 /// Result of a download operation
 ///
 /// Contains all information about a completed download, including the downloaded data,
@@ -1341,4 +3057,55 @@ pub struct DownloadResult {
 
     /// Resource metadata from server (content type, length, etc.)
     pub metadata: crate::client::ResourceMetadata,
+
+    /// Whether timestamping (-N) replaced or kept the local file, and why
+    ///
+    /// `None` unless the download compared against an existing file after the transfer.
+    pub timestamp_decision: Option<crate::timestamping::TimestampDecision>,
+
+    /// Digest of the downloaded file (see `DownloadConfig::expected_checksum`)
+    ///
+    /// `None` for downloads to memory and when no content was written, e.g. an
+    /// up-to-date file skipped by timestamping.
+    pub checksum: Option<crate::Checksum>,
+
+    /// Transfer statistics
+    pub stats: DownloadStats,
+}
+
+impl DownloadResult {
+    /// Whether the download got the resource or stopped at a redirect that
+    /// was not followed (`max_redirects` of 0 or `follow_redirects` off)
+    pub fn outcome(&self) -> DownloadOutcome {
+        match self.metadata.redirect_location() {
+            Some(location) => DownloadOutcome::Redirect { location },
+            None => DownloadOutcome::Complete,
+        }
+    }
+}
+
+/// What a download ended with (see [`DownloadResult::outcome`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadOutcome {
+    /// The response was the resource itself (or an error page with `content_on_error`)
+    Complete,
+
+    /// The server redirected and the redirect was not followed
+    ///
+    /// No file is written unless `content_on_error` is set, in which case the
+    /// body of the 3xx response is saved.
+    Redirect {
+        /// Location header, resolved against the requested URL
+        location: String,
+    },
+}
+
+/// Statistics about how a response body was transferred
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadStats {
+    /// Bytes received beyond the declared Content-Length and discarded
+    pub excess_bytes_discarded: u64,
+
+    /// How the HTTP cache was used (`None` when no cache is configured)
+    pub cache: Option<CacheStatus>,
 }
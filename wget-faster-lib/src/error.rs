@@ -1,4 +1,5 @@
 use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// Result type alias using the library's Error type
@@ -7,7 +8,9 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Error types for download operations
 ///
 /// All error types implement the `std::error::Error` trait and can be
-/// displayed with user-friendly error messages.
+/// displayed with user-friendly error messages. Errors from other crates are
+/// kept as the `source()`, and [`Error::with_context`] records what the
+/// downloader was doing when an error occurred.
 #[derive(Error, Debug)]
 pub enum Error {
     /// HTTP request failed (from reqwest)
@@ -28,6 +31,12 @@ pub enum Error {
     #[error("Invalid URL: {0}")]
     InvalidUrl(#[from] url::ParseError),
 
+    /// URL scheme other than `http` or `https`
+    ///
+    /// Rejected by [`prepare_url`](crate::prepare_url) before any request is made.
+    #[error("Unsupported scheme: {0}")]
+    UnsupportedScheme(String),
+
     /// Invalid HTTP header value
     ///
     /// Header values that contain invalid characters or formatting.
@@ -77,6 +86,53 @@ pub enum Error {
     #[error("Chunk download failed: {0}")]
     ChunkError(String),
 
+    /// The object changed while its parallel chunks were being fetched
+    ///
+    /// Chunk responses carried different `ETag`/`Last-Modified` validators or
+    /// Content-Encodings, the server answered an `If-Match` chunk request with
+    /// 412, or a resumed response was content-coded while the partial file isn't.
+    /// The partial data mixes versions, so the file is removed and must be
+    /// downloaded again from the start rather than resumed.
+    #[error("Object changed during download: {0}")]
+    ObjectChangedDuringDownload(String),
+
+    /// Content-Range of a 206 response doesn't match what was requested
+    ///
+    /// E.g. an unsolicited partial response that doesn't start at byte 0.
+    #[error("Unexpected Content-Range: {0}")]
+    InvalidContentRange(String),
+
+    /// Response rejected by `DownloadConfig::response_filter` once its headers arrived
+    ///
+    /// The body is not transferred and no file is kept.
+    #[error("Response rejected: {0}")]
+    ResponseRejected(String),
+
+    /// Downloaded content doesn't have `DownloadConfig::expected_checksum`
+    ///
+    /// The downloaded file is deleted.
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        /// Digest the content was expected to have
+        expected: crate::Checksum,
+        /// Digest of the content received
+        actual: crate::Checksum,
+    },
+
+    /// A response body was still arriving when `DownloadConfig::max_body_duration` ran out
+    ///
+    /// Typically an endpoint that never ends its body (event stream, live log).
+    /// Retrying would run into the same limit, so the download is not retried.
+    #[error("Body transfer exceeded the limit of {0:?}")]
+    BodyDurationExceeded(std::time::Duration),
+
+    /// The server's TLS fell below `DownloadConfig::tls_policy`
+    ///
+    /// E.g. a certificate key that is too small or a missing stapled OCSP
+    /// response; the reason names the certificate. Not retried.
+    #[error("TLS policy violation: {0}")]
+    TlsError(String),
+
     /// Failed to create temporary file
     ///
     /// Temporary file creation for partial downloads or resume.
@@ -89,6 +145,55 @@ pub enum Error {
     #[error("Failed to write to output: {0}")]
     WriteError(String),
 
+    /// A directory on the way to an output file is a symlink
+    ///
+    /// Not followed unless `DownloadConfig::follow_output_symlinks` is set, so
+    /// a link planted in the output directory can't redirect files elsewhere.
+    #[error("Refusing to write through symlinked directory '{}'", .0.display())]
+    UnsafeOutputPath(PathBuf),
+
+    /// Storage exhausted while writing (ENOSPC or EDQUOT)
+    ///
+    /// The partial file is kept so the download can be resumed once space
+    /// has been freed. `needed` is the remaining size when Content-Length is known.
+    #[error("No space left on device writing '{}' ({written} bytes written)", path.display())]
+    DiskFull {
+        /// File that was being written
+        path: PathBuf,
+        /// Bytes present in the file when the error occurred
+        written: u64,
+        /// Bytes still required to complete the download, if known
+        needed: Option<u64>,
+    },
+
+    /// The download quota (`DownloadConfig::quota`) was used up before the
+    /// download started
+    #[error("Download quota of {0} bytes exceeded")]
+    QuotaExceeded(u64),
+
+    /// The download was cancelled through its `DownloadHandle`
+    ///
+    /// A file download keeps what was written so far for resuming.
+    #[error("Download cancelled")]
+    Cancelled,
+
+    /// Form login before a crawl failed
+    ///
+    /// Carries the HTTP status of the last response in the login flow.
+    #[cfg(feature = "recursive")]
+    #[error("Login failed (status {status}): {reason}")]
+    LoginFailed {
+        /// HTTP status of the failing response
+        status: u16,
+        /// What went wrong
+        reason: String,
+    },
+
+    /// A sitemap could not be parsed
+    #[cfg(feature = "recursive")]
+    #[error("Invalid sitemap: {0}")]
+    InvalidSitemap(String),
+
     /// Configuration validation error
     ///
     /// Invalid settings in `DownloadConfig`, such as malformed proxy URL
@@ -101,15 +206,61 @@ pub enum Error {
     /// Catch-all for errors that don't fit other categories.
     #[error("Unknown error: {0}")]
     Unknown(String),
+
+    /// Another error, annotated with what was being done when it occurred
+    ///
+    /// Created by [`Error::with_context`]. Displayed as the inner message followed
+    /// by the context, innermost first; use [`Error::root`] to match on the cause.
+    #[error("{error} ({})", context.join(", "))]
+    WithContext {
+        /// The error that occurred
+        #[source]
+        error: Box<Error>,
+        /// What was being done, innermost first (e.g. "while fetching metadata for URL")
+        context: Vec<String>,
+    },
 }
 
 impl From<anyhow::Error> for Error {
     fn from(err: anyhow::Error) -> Self {
-        Error::Unknown(err.to_string())
+        // The alternate format keeps the whole chain ("outer: inner: cause")
+        Error::Unknown(format!("{err:#}"))
     }
 }
 
 impl Error {
+    /// Annotate the error with what was being done when it occurred
+    ///
+    /// Adding context to an error that already has some extends its list
+    /// instead of nesting another wrapper.
+    #[must_use]
+    pub fn with_context(self, context: impl Into<String>) -> Self {
+        match self {
+            Error::WithContext {
+                error,
+                context: mut outer,
+            } => {
+                outer.push(context.into());
+                Error::WithContext {
+                    error,
+                    context: outer,
+                }
+            },
+            error => Error::WithContext {
+                error: Box::new(error),
+                context: vec![context.into()],
+            },
+        }
+    }
+
+    /// The underlying error, without any context added by [`Error::with_context`]
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::WithContext { error, .. } => error.root(),
+            error => error,
+        }
+    }
+
     /// Get wget-compatible exit code for this error
     ///
     /// Exit codes:
@@ -124,14 +275,21 @@ impl Error {
     /// - 8: Server error response (4xx client errors only)
     pub fn exit_code(&self) -> i32 {
         match self {
+            Error::WithContext { error, .. } => error.exit_code(),
+
             // File I/O errors -> 3
-            Error::IoError(_) | Error::TempFileError(_) | Error::WriteError(_) => 3,
+            Error::IoError(_)
+            | Error::TempFileError(_)
+            | Error::WriteError(_)
+            | Error::UnsafeOutputPath(_)
+            | Error::DiskFull { .. } => 3,
 
             // Network failures -> 4
-            Error::Timeout => 4,
+            Error::Timeout | Error::BodyDurationExceeded(_) => 4,
             Error::HttpError(e) if e.is_timeout() || e.is_connect() => 4,
 
             // SSL verification failure -> 5
+            Error::TlsError(_) => 5,
             Error::HttpError(e)
                 if e.to_string().contains("certificate")
                     || e.to_string().contains("tls")
@@ -142,6 +300,8 @@ impl Error {
 
             // Authentication failure -> 6
             Error::InvalidStatus(401 | 407) => 6,
+            #[cfg(feature = "recursive")]
+            Error::LoginFailed { .. } => 6,
 
             // Client errors (4xx) -> 8
             Error::InvalidStatus(code) if *code >= 400 && *code < 500 => 8,
@@ -150,10 +310,13 @@ impl Error {
             Error::InvalidStatus(code) if *code >= 500 => 4,
 
             // Protocol errors -> 7
-            Error::RangeNotSupported | Error::ContentLengthUnavailable => 7,
+            Error::RangeNotSupported
+            | Error::ContentLengthUnavailable
+            | Error::InvalidContentRange(_) => 7,
 
             // Parse errors -> 2
             Error::InvalidUrl(_) | Error::InvalidHeader(_) | Error::InvalidHeaderName(_) => 2,
+            Error::UnsupportedScheme(_) => 2,
             Error::ConfigError(_) => 2,
 
             // Generic error -> 1
@@ -177,6 +340,7 @@ impl Error {
             },
             Error::IoError(e) => format!("File write error: {e}"),
             Error::InvalidUrl(_) => "Invalid URL format.".to_string(),
+            Error::UnsupportedScheme(_) => "Unsupported scheme.".to_string(),
             Error::RangeNotSupported => "Server does not support byte ranges.".to_string(),
             Error::ContentLengthUnavailable => "Content-Length header missing.".to_string(),
             Error::MaxRetriesExceeded(n) => {
@@ -199,8 +363,14 @@ impl Error {
             Error::ChunkError(msg) => format!("Download failed: {msg}"),
             Error::TempFileError(msg) => format!("Cannot create temp file: {msg}"),
             Error::WriteError(msg) => format!("File write error: {msg}"),
+            Error::DiskFull { path, .. } => {
+                format!("Cannot write to '{}' (No space left on device).", path.display())
+            },
             Error::ConfigError(msg) => format!("Configuration error: {msg}"),
             Error::Unknown(msg) => format!("Error: {msg}"),
+            Error::WithContext { error, context } => {
+                format!("{} ({})", error.format_wget_style(), context.join(", "))
+            },
             _ => self.to_string(),
         }
     }
@@ -233,6 +403,16 @@ mod tests {
         // Authentication errors should return exit code 6
         assert_eq!(Error::InvalidStatus(401).exit_code(), 6, "401 Unauthorized");
         assert_eq!(Error::InvalidStatus(407).exit_code(), 6, "407 Proxy Auth Required");
+        #[cfg(feature = "recursive")]
+        assert_eq!(
+            Error::LoginFailed {
+                status: 200,
+                reason: "bad credentials".to_string(),
+            }
+            .exit_code(),
+            6,
+            "form login failure"
+        );
     }
 
     #[test]
@@ -250,6 +430,15 @@ mod tests {
         // File I/O errors should return exit code 3
         assert_eq!(Error::TempFileError("test".to_string()).exit_code(), 3);
         assert_eq!(Error::WriteError("test".to_string()).exit_code(), 3);
+        assert_eq!(
+            Error::DiskFull {
+                path: PathBuf::from("file.bin"),
+                written: 10,
+                needed: Some(20),
+            }
+            .exit_code(),
+            3
+        );
     }
 
     #[test]
@@ -258,10 +447,35 @@ mod tests {
         assert_eq!(Error::Timeout.exit_code(), 4);
     }
 
+    #[test]
+    fn test_context_chain() {
+        use std::error::Error as _;
+
+        let io_err = io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed");
+        let err = Error::from(io_err)
+            .with_context("while writing chunk 2 of http://example.com/f")
+            .with_context("while downloading http://example.com/f");
+
+        assert_eq!(
+            err.to_string(),
+            "IO error: pipe closed (while writing chunk 2 of http://example.com/f, \
+             while downloading http://example.com/f)"
+        );
+        assert!(matches!(err.root(), Error::IoError(_)));
+        assert_eq!(err.exit_code(), 3);
+
+        // Context is flattened into one wrapper whose source is the original error
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "IO error: pipe closed");
+        assert_eq!(source.source().unwrap().to_string(), "pipe closed");
+        assert!(source.source().unwrap().source().is_none());
+    }
+
     #[test]
     fn test_exit_codes_protocol_errors() {
         // Protocol errors should return exit code 7
         assert_eq!(Error::RangeNotSupported.exit_code(), 7);
+        assert_eq!(Error::InvalidContentRange("bytes 5-9/10".to_string()).exit_code(), 7);
         assert_eq!(Error::ContentLengthUnavailable.exit_code(), 7);
     }
 }
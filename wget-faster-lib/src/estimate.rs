@@ -0,0 +1,286 @@
+/// Transfer size estimates for a list of URLs (no bodies are downloaded)
+///
+/// Each URL is probed like [`Downloader::head`], then run through the same
+/// skip and resume rules as a real download (`-N`, no-clobber, partial
+/// files). Sizes the server doesn't report are counted separately and never
+/// guessed.
+use crate::link_check::for_each_per_host;
+use crate::plan::{DownloadPlan, PlanAction};
+use crate::{Downloader, ResourceMetadata};
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Local file a URL would be saved to, or `None` when it isn't saved to a file
+pub type EstimateTargetFn = Arc<dyn Fn(&str, &ResourceMetadata) -> Option<PathBuf> + Send + Sync>;
+
+/// Options for [`Downloader::estimate`]
+#[derive(Clone)]
+pub struct EstimateOptions {
+    /// Probes in flight at once (at most `MAX_CHECKS_PER_HOST` per host)
+    pub concurrency: usize,
+
+    /// Hard cap on the whole pre-pass; URLs not probed by then are reported
+    /// as [`EstimateOutcome::NotProbed`]
+    pub time_limit: Duration,
+
+    /// Skip URLs whose target file already exists (`-nc`)
+    pub no_clobber: bool,
+
+    /// Names the target file of each URL
+    ///
+    /// `None` uses [`crate::final_filename`] in the current directory, with
+    /// `index.html` for URLs without a file name.
+    pub target: Option<EstimateTargetFn>,
+}
+
+impl Default for EstimateOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 16,
+            time_limit: Duration::from_mins(1),
+            no_clobber: false,
+            target: None,
+        }
+    }
+}
+
+impl fmt::Debug for EstimateOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EstimateOptions")
+            .field("concurrency", &self.concurrency)
+            .field("time_limit", &self.time_limit)
+            .field("no_clobber", &self.no_clobber)
+            .field("target", &self.target.as_ref().map(|_| "<fn>"))
+            .finish()
+    }
+}
+
+/// What the download of one URL would transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum EstimateOutcome {
+    /// Would be downloaded
+    Download {
+        /// Bytes left to transfer (after any resume), `None` if the server
+        /// didn't say
+        bytes: Option<u64>,
+    },
+
+    /// Would be skipped
+    Skip {
+        /// Why, e.g. "local file is up to date"
+        reason: String,
+    },
+
+    /// The probe failed or returned an error status
+    ProbeFailed {
+        /// Error or status of the probe
+        error: String,
+    },
+
+    /// Not probed before the time limit
+    NotProbed,
+}
+
+/// Estimate for one URL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EstimateEntry {
+    /// URL as given
+    pub url: String,
+
+    /// What its download would do
+    #[serde(flatten)]
+    pub outcome: EstimateOutcome,
+}
+
+/// Result of [`Downloader::estimate`]
+#[derive(Debug, Clone, Serialize)]
+pub struct EstimateReport {
+    /// Number of URLs in the list
+    pub urls: usize,
+
+    /// Bytes to transfer for the URLs of known size
+    pub total_bytes: u64,
+
+    /// URLs that would be downloaded and have a known size
+    pub sized: usize,
+
+    /// URLs that would be downloaded but have no known size
+    pub unknown_size: usize,
+
+    /// URLs that would be skipped by `-N` or no-clobber
+    pub skipped: usize,
+
+    /// URLs whose probe failed
+    pub failed: usize,
+
+    /// URLs not probed before the time limit
+    pub not_probed: usize,
+
+    /// Per-URL outcomes, in input order
+    pub entries: Vec<EstimateEntry>,
+}
+
+impl EstimateReport {
+    fn new(entries: Vec<EstimateEntry>) -> Self {
+        let mut report = Self {
+            urls: entries.len(),
+            total_bytes: 0,
+            sized: 0,
+            unknown_size: 0,
+            skipped: 0,
+            failed: 0,
+            not_probed: 0,
+            entries: Vec::new(),
+        };
+        for entry in &entries {
+            match entry.outcome {
+                EstimateOutcome::Download { bytes: Some(bytes) } => {
+                    report.sized += 1;
+                    report.total_bytes += bytes;
+                },
+                EstimateOutcome::Download { bytes: None } => report.unknown_size += 1,
+                EstimateOutcome::Skip { .. } => report.skipped += 1,
+                EstimateOutcome::ProbeFailed { .. } => report.failed += 1,
+                EstimateOutcome::NotProbed => report.not_probed += 1,
+            }
+        }
+        report.entries = entries;
+        report
+    }
+}
+
+impl fmt::Display for EstimateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} URLs: {} ({} bytes) in {} of known size",
+            self.urls,
+            crate::progress::format_bytes(self.total_bytes),
+            self.total_bytes,
+            self.sized
+        )?;
+        writeln!(f, "  unknown size (not counted): {}", self.unknown_size)?;
+        writeln!(f, "  would be skipped: {}", self.skipped)?;
+        writeln!(f, "  probe failed: {}", self.failed)?;
+        write!(f, "  not probed (time limit): {}", self.not_probed)
+    }
+}
+
+pub(crate) async fn estimate(
+    downloader: &Downloader,
+    urls: &[String],
+    options: &EstimateOptions,
+) -> EstimateReport {
+    let deadline = Instant::now() + options.time_limit;
+    let config = downloader.get_client().config();
+    let outcomes = for_each_per_host(
+        urls.to_vec(),
+        options.concurrency,
+        config.wait_time,
+        config.random_wait,
+        Some(deadline),
+        |url| async move { estimate_one(downloader, &url, options).await },
+    )
+    .await;
+
+    let entries = urls
+        .iter()
+        .zip(outcomes)
+        .map(|(url, outcome)| EstimateEntry {
+            url: url.clone(),
+            outcome: outcome.unwrap_or(EstimateOutcome::NotProbed),
+        })
+        .collect();
+    EstimateReport::new(entries)
+}
+
+async fn estimate_one(
+    downloader: &Downloader,
+    url: &str,
+    options: &EstimateOptions,
+) -> EstimateOutcome {
+    let metadata = match downloader.head(url).await {
+        Ok(metadata) if metadata.status_code < 400 => metadata,
+        Ok(metadata) => {
+            return EstimateOutcome::ProbeFailed {
+                error: format!("HTTP {}", metadata.status_code),
+            }
+        },
+        Err(e) => {
+            return EstimateOutcome::ProbeFailed {
+                error: e.to_string(),
+            }
+        },
+    };
+
+    let target = match options.target {
+        Some(ref target) => target(url, &metadata),
+        None => Some(PathBuf::from(
+            crate::naming::final_filename(url, &metadata)
+                .unwrap_or_else(|| "index.html".to_string()),
+        )),
+    };
+    let Some(target) = target else {
+        return EstimateOutcome::Download {
+            bytes: metadata.content_length,
+        };
+    };
+    if options.no_clobber && target.exists() {
+        return EstimateOutcome::Skip {
+            reason: format!("{} already exists", target.display()),
+        };
+    }
+
+    let length = metadata.content_length;
+    match DownloadPlan::build(downloader.get_client().config(), url, &target, Some(metadata)).await
+    {
+        Ok(plan) => match plan.action {
+            PlanAction::Download => EstimateOutcome::Download { bytes: length },
+            PlanAction::Resume { from } => EstimateOutcome::Download {
+                bytes: length.map(|len| len.saturating_sub(from)),
+            },
+            PlanAction::Skip { reason } => EstimateOutcome::Skip { reason },
+        },
+        Err(e) => EstimateOutcome::ProbeFailed {
+            error: e.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(outcome: EstimateOutcome) -> EstimateEntry {
+        EstimateEntry {
+            url: "http://example.com/".to_string(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_report_totals() {
+        let report = EstimateReport::new(vec![
+            entry(EstimateOutcome::Download { bytes: Some(100) }),
+            entry(EstimateOutcome::Download { bytes: Some(20) }),
+            entry(EstimateOutcome::Download { bytes: None }),
+            entry(EstimateOutcome::Skip {
+                reason: "up to date".to_string(),
+            }),
+            entry(EstimateOutcome::NotProbed),
+        ]);
+
+        assert_eq!(report.urls, 5);
+        assert_eq!((report.sized, report.total_bytes), (2, 120));
+        assert_eq!((report.unknown_size, report.skipped), (1, 1));
+        assert_eq!((report.failed, report.not_probed), (0, 1));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["entries"][0]["outcome"], "download");
+        assert_eq!(json["entries"][2]["bytes"], serde_json::Value::Null);
+        assert_eq!(json["entries"][3]["reason"], "up to date");
+    }
+}
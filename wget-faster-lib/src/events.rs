@@ -0,0 +1,86 @@
+/// Structured events describing a download as it happens
+///
+/// Where a `ProgressCallback` reports byte counts, the event callback
+/// (`DownloadConfig::event_callback`) reports the milestones of a download:
+/// its response arriving, redirects, retries, parallel chunks and the final
+/// outcome. Events are delivered synchronously from the task doing the work,
+/// so the callback should be quick (push to a channel or a `Vec`).
+///
+/// A download reports `Started` once its body transfer begins (preceded by
+/// `Redirected` if the response came from another URL). Downloads to a file
+/// or to memory then end with exactly one of `Completed` or `Failed`; a
+/// stream has ended when it yields its last item.
+use crate::client::ResourceMetadata;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Receives every event of the downloads made with a config
+pub type EventCallbackFn = Arc<dyn Fn(DownloadEvent) + Send + Sync>;
+
+/// Event callback carried in `DownloadConfig::event_callback`
+#[derive(Clone)]
+pub struct EventCallback(pub EventCallbackFn);
+
+impl fmt::Debug for EventCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventCallback(..)")
+    }
+}
+
+/// A milestone of a download
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+    /// The body transfer is starting
+    Started {
+        /// URL requested
+        url: String,
+        /// Metadata of the response the body comes from
+        metadata: Box<ResourceMetadata>,
+    },
+
+    /// The request was redirected before the body arrived
+    Redirected {
+        /// URL requested
+        from: String,
+        /// URL the response came from
+        to: String,
+    },
+
+    /// A failed request will be retried after a delay
+    RetryScheduled {
+        /// Retry number, starting at 1
+        attempt: usize,
+        /// Wait before the retry
+        delay: Duration,
+        /// Why the previous attempt failed
+        cause: String,
+    },
+
+    /// A chunk of a parallel download arrived in full
+    ChunkCompleted {
+        /// Position of the chunk in the order it was scheduled
+        index: usize,
+        /// Size of the chunk
+        bytes: u64,
+    },
+
+    /// The download finished
+    Completed {
+        /// URL requested
+        url: String,
+        /// Bytes downloaded (`DownloadedData::total_bytes`)
+        bytes: u64,
+        /// File written (`None` for memory downloads)
+        path: Option<PathBuf>,
+    },
+
+    /// The download failed
+    Failed {
+        /// URL requested
+        url: String,
+        /// The error, as displayed
+        error: String,
+    },
+}
@@ -0,0 +1,69 @@
+/// Cap on the files a recursive crawl and its link converter hold open at once
+use std::sync::Arc;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Files a crawl and its link converter may hold open at once
+///
+/// Far below common descriptor limits (often 1024), leaving room for sockets.
+pub(crate) const MAX_OPEN_FILES: usize = 64;
+
+/// Shared count of open file handles; clones share the same permits
+///
+/// Each file operation holds one permit per file it opens, and only for as
+/// long as the file is open. Operations never hold a permit while waiting for
+/// another, so they can't deadlock on each other.
+#[derive(Debug, Clone)]
+pub(crate) struct FileHandles {
+    permits: Arc<Semaphore>,
+}
+
+impl FileHandles {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(limit.max(2))),
+        }
+    }
+
+    /// Wait until `files` more files may be opened; they count until the permit drops
+    ///
+    /// The semaphore is never closed, so this is only `None` in theory.
+    pub(crate) async fn open(&self, files: u32) -> Option<SemaphorePermit<'_>> {
+        self.permits.acquire_many(files).await.ok()
+    }
+
+    /// Files that could be opened right now
+    #[cfg(test)]
+    pub(crate) fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+}
+
+impl Default for FileHandles {
+    fn default() -> Self {
+        Self::new(MAX_OPEN_FILES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_open_waits_for_released_handles() {
+        let handles = FileHandles::new(3);
+        let shared = handles.clone();
+        let pair = handles.open(2).await;
+        assert_eq!(shared.available(), 1);
+
+        let single = shared.open(1).await;
+        let waiting = shared.open(2);
+        tokio::pin!(waiting);
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        drop(pair);
+        let _pair = waiting.await;
+        assert_eq!(handles.available(), 0);
+        drop(single);
+        assert_eq!(handles.available(), 1);
+    }
+}
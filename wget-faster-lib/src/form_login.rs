@@ -0,0 +1,327 @@
+/// HTML form login before a recursive crawl
+///
+/// Many sites require a POST to a login form (often with a CSRF token
+/// embedded in the page) before anything else can be fetched. This module:
+/// - Fetches the login page and locates the login form
+/// - Captures all named fields (hidden CSRF tokens included)
+/// - Fills in the credentials and POSTs the form
+/// - Verifies success; the session cookies are then shared with the crawl
+use crate::{Error, HttpClient, Result};
+use scraper::{Html, Selector};
+use serde::Serialize;
+use std::collections::HashMap;
+use url::Url;
+
+/// How to decide whether a login attempt succeeded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginSuccessCheck {
+    /// The final URL after the POST (and any redirects) contains this string
+    UrlContains(String),
+
+    /// A cookie with this name is set for the login URL
+    Cookie(String),
+
+    /// The final URL does not contain this string (e.g. "/login")
+    UrlNotContains(String),
+}
+
+/// Form-based login performed before crawling
+#[derive(Debug, Clone, Serialize)]
+pub struct FormLogin {
+    /// URL of the page containing the login form
+    pub login_url: String,
+
+    /// CSS selector for the form (default: first form with a password field)
+    pub form_selector: Option<String>,
+
+    /// Name of the username input
+    pub username_field: String,
+
+    /// Name of the password input
+    pub password_field: String,
+
+    /// Username to submit
+    pub username: String,
+
+    /// Password to submit
+    #[serde(serialize_with = "crate::config_serde::secret")]
+    pub password: String,
+
+    /// Extra fields to submit, overriding values captured from the page
+    #[serde(serialize_with = "crate::config_serde::masked_fields")]
+    pub extra_fields: HashMap<String, String>,
+
+    /// Check used to verify the login succeeded
+    pub success_check: LoginSuccessCheck,
+}
+
+impl FormLogin {
+    /// Create a form login with the common `username`/`password` field names
+    ///
+    /// Success is assumed when the POST does not end up back on the login URL.
+    pub fn new(login_url: &str, username: &str, password: &str) -> Self {
+        let path =
+            Url::parse(login_url).map_or_else(|_| login_url.to_string(), |u| u.path().to_string());
+
+        Self {
+            login_url: login_url.to_string(),
+            form_selector: None,
+            username_field: "username".to_string(),
+            password_field: "password".to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            extra_fields: HashMap::new(),
+            success_check: LoginSuccessCheck::UrlNotContains(path),
+        }
+    }
+}
+
+/// Form found on the login page
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LoginForm {
+    /// Absolute URL the form submits to
+    action: Url,
+
+    /// Named fields with their initial values, in document order
+    fields: Vec<(String, String)>,
+}
+
+/// Locate the login form and capture its fields
+fn parse_login_form(html: &str, page_url: &Url, form_selector: Option<&str>) -> Option<LoginForm> {
+    let document = Html::parse_document(html);
+    let form_sel = Selector::parse(form_selector.unwrap_or("form")).ok()?;
+    let password_sel = Selector::parse("input[type=password]").ok()?;
+    let field_sel = Selector::parse("input[name], select[name], textarea[name]").ok()?;
+    let option_sel = Selector::parse("option").ok()?;
+
+    let mut forms = document.select(&form_sel);
+    let form = if form_selector.is_some() {
+        forms.next()?
+    } else {
+        // Prefer the form that actually has a password field
+        let candidates: Vec<_> = forms.collect();
+        candidates
+            .iter()
+            .find(|f| f.select(&password_sel).next().is_some())
+            .or_else(|| candidates.first())
+            .copied()?
+    };
+
+    let action = match form.value().attr("action").filter(|a| !a.trim().is_empty()) {
+        Some(action) => page_url.join(action.trim()).ok()?,
+        None => page_url.clone(),
+    };
+
+    let mut fields = Vec::new();
+    for field in form.select(&field_sel) {
+        let element = field.value();
+        let Some(name) = element.attr("name") else {
+            continue;
+        };
+
+        let value = match element.name() {
+            "select" => field
+                .select(&option_sel)
+                .find(|o| o.value().attr("selected").is_some())
+                .or_else(|| field.select(&option_sel).next())
+                .and_then(|o| o.value().attr("value").map(str::to_string))
+                .unwrap_or_default(),
+            "textarea" => field.text().collect(),
+            _ => {
+                let input_type = element.attr("type").unwrap_or("text").to_lowercase();
+                // Unchecked boxes and buttons are not submitted by browsers
+                if matches!(input_type.as_str(), "submit" | "button" | "image" | "reset" | "file")
+                    || (matches!(input_type.as_str(), "checkbox" | "radio")
+                        && element.attr("checked").is_none())
+                {
+                    continue;
+                }
+                element.attr("value").unwrap_or("").to_string()
+            },
+        };
+
+        fields.push((name.to_string(), value));
+    }
+
+    Some(LoginForm { action, fields })
+}
+
+/// Merge captured fields with credentials and user-supplied extras
+fn build_form_fields(form: &LoginForm, login: &FormLogin) -> Vec<(String, String)> {
+    let mut overrides: Vec<(String, String)> = vec![
+        (login.username_field.clone(), login.username.clone()),
+        (login.password_field.clone(), login.password.clone()),
+    ];
+    overrides.extend(
+        login
+            .extra_fields
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone())),
+    );
+
+    let mut fields: Vec<(String, String)> = form
+        .fields
+        .iter()
+        .filter(|(name, _)| !overrides.iter().any(|(o, _)| o == name))
+        .cloned()
+        .collect();
+    fields.extend(overrides);
+    fields
+}
+
+/// Perform the form login using the client's session cookie store
+///
+/// # Errors
+///
+/// Returns `Error::LoginFailed` if the login page can't be fetched, no form is
+/// found, the POST is rejected, or the success check does not pass.
+pub(crate) async fn perform_login(client: &HttpClient, login: &FormLogin) -> Result<()> {
+    tracing::info!(url = %login.login_url, "Fetching login page");
+    let page = client.send(client.client().get(&login.login_url)).await?;
+    let status = page.status().as_u16();
+    if !page.status().is_success() {
+        return Err(Error::LoginFailed {
+            status,
+            reason: "login page could not be fetched".to_string(),
+        });
+    }
+
+    let page_url = page.url().clone();
+    let html = page.text().await?;
+    let form =
+        parse_login_form(&html, &page_url, login.form_selector.as_deref()).ok_or_else(|| {
+            Error::LoginFailed {
+                status,
+                reason: "no login form found on page".to_string(),
+            }
+        })?;
+
+    let fields = build_form_fields(&form, login);
+    tracing::debug!(action = %form.action, fields = fields.len(), "Submitting login form");
+
+    let response = client
+        .send(client.client().post(form.action.clone()).form(&fields))
+        .await?;
+    let status = response.status().as_u16();
+    if !response.status().is_success() {
+        return Err(Error::LoginFailed {
+            status,
+            reason: "login form was rejected".to_string(),
+        });
+    }
+
+    let final_url = response.url().clone();
+    let succeeded = match &login.success_check {
+        LoginSuccessCheck::UrlContains(pattern) => final_url.as_str().contains(pattern.as_str()),
+        LoginSuccessCheck::UrlNotContains(pattern) => {
+            !final_url.as_str().contains(pattern.as_str())
+        },
+        LoginSuccessCheck::Cookie(name) => client
+            .session_cookies(&page_url)
+            .is_some_and(|cookies| cookie_header_has(&cookies, name)),
+    };
+
+    if !succeeded {
+        return Err(Error::LoginFailed {
+            status,
+            reason: format!("success check {:?} did not pass", login.success_check),
+        });
+    }
+
+    tracing::info!(url = %final_url, "Form login succeeded");
+    Ok(())
+}
+
+/// Check whether a `Cookie` header value contains a cookie named `name`
+fn cookie_header_has(header: &str, name: &str) -> bool {
+    header
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .any(|(n, _)| n.trim() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIN_PAGE: &str = r#"
+        <html><body>
+          <form action="/search"><input name="q"></form>
+          <form method="post" action="/session">
+            <input type="hidden" name="csrf_token" value="abc123">
+            <input type="text" name="username">
+            <input type="password" name="password">
+            <input type="checkbox" name="remember" value="1">
+            <input type="checkbox" name="tos" value="yes" checked>
+            <select name="lang"><option value="en">EN</option><option value="de" selected>DE</option></select>
+            <input type="submit" name="go" value="Log in">
+          </form>
+        </body></html>
+    "#;
+
+    #[test]
+    fn test_parse_login_form_picks_password_form() {
+        let page = Url::parse("https://example.com/login").unwrap();
+        let form = parse_login_form(LOGIN_PAGE, &page, None).unwrap();
+
+        assert_eq!(form.action.as_str(), "https://example.com/session");
+        assert_eq!(
+            form.fields,
+            vec![
+                ("csrf_token".to_string(), "abc123".to_string()),
+                ("username".to_string(), String::new()),
+                ("password".to_string(), String::new()),
+                ("tos".to_string(), "yes".to_string()),
+                ("lang".to_string(), "de".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_login_form_with_selector() {
+        let page = Url::parse("https://example.com/login").unwrap();
+        let form = parse_login_form(LOGIN_PAGE, &page, Some("form[action='/search']")).unwrap();
+        assert_eq!(form.action.as_str(), "https://example.com/search");
+        assert_eq!(form.fields, vec![("q".to_string(), String::new())]);
+    }
+
+    #[test]
+    fn test_build_form_fields_keeps_csrf_and_sets_credentials() {
+        let page = Url::parse("https://example.com/login").unwrap();
+        let form = parse_login_form(LOGIN_PAGE, &page, None).unwrap();
+        let mut login = FormLogin::new("https://example.com/login", "alice", "s3cret");
+        login
+            .extra_fields
+            .insert("lang".to_string(), "en".to_string());
+
+        let fields = build_form_fields(&form, &login);
+        let get = |name: &str| {
+            fields
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+
+        assert_eq!(get("csrf_token"), Some("abc123"));
+        assert_eq!(get("username"), Some("alice"));
+        assert_eq!(get("password"), Some("s3cret"));
+        assert_eq!(get("lang"), Some("en"));
+        assert_eq!(fields.iter().filter(|(n, _)| n == "username").count(), 1);
+    }
+
+    #[test]
+    fn test_default_success_check() {
+        let login = FormLogin::new("https://example.com/account/login", "u", "p");
+        assert_eq!(
+            login.success_check,
+            LoginSuccessCheck::UrlNotContains("/account/login".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cookie_header_has() {
+        assert!(cookie_header_has("a=1; session=xyz", "session"));
+        assert!(!cookie_header_has("a=1; sessionid=xyz", "session"));
+    }
+}
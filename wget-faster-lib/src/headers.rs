@@ -0,0 +1,741 @@
+/// Tolerant parsers for typed response headers (Allow, Accept-Ranges, Cache-Control, ...)
+///
+/// Malformed values never cause an error: they parse as `None`, or are skipped
+/// when they are one entry of a list.
+use reqwest::header::{HeaderMap, HeaderName};
+use reqwest::Method;
+use std::time::Duration;
+use url::Url;
+
+/// Largest delta-seconds value kept as is
+const MAX_DELTA_SECONDS: u64 = 1 << 31;
+
+/// Caching directives from a Cache-Control header
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    /// `max-age` (`None` if absent or malformed)
+    pub max_age: Option<Duration>,
+
+    /// `no-store`: the response must not be cached
+    pub no_store: bool,
+
+    /// `no-cache`: the response must be revalidated before reuse
+    pub no_cache: bool,
+}
+
+/// One relation from a Link header (RFC 8288), e.g. `<page/2>; rel="next"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkRelation {
+    /// Target, resolved against the response URL
+    pub url: String,
+
+    /// Relation type, lowercased (`next`, `prev`, `preload`, ...)
+    ///
+    /// A link with several types (`rel="next prefetch"`) yields one relation per type.
+    pub rel: String,
+
+    /// Remaining parameters in header order, names lowercased and quotes removed
+    pub params: Vec<(String, String)>,
+}
+
+/// Disposition type and file name from a Content-Disposition header (RFC 6266)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentDisposition {
+    /// Disposition type, lowercased (`attachment`, `inline`, or an extension type)
+    pub disposition: String,
+
+    /// Suggested file name, without directory components
+    ///
+    /// `filename*` (RFC 5987) wins over `filename`. A `filename*` in a charset
+    /// other than UTF-8 or ISO-8859-1, or one that doesn't decode, is ignored.
+    pub filename: Option<String>,
+}
+
+impl ContentDisposition {
+    /// Whether the response is meant to be displayed rather than saved
+    ///
+    /// Unknown disposition types are treated as `attachment` (RFC 6266 section 4.2).
+    pub fn is_inline(&self) -> bool {
+        self.disposition == "inline"
+    }
+}
+
+/// Values of every `name` header, as comma-separated list items
+fn list_items<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<Vec<&'a str>> {
+    let mut values = headers.get_all(name).iter().peekable();
+    values.peek()?;
+    Some(
+        values
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .collect(),
+    )
+}
+
+/// Methods listed in Allow (an empty header means none are allowed)
+pub(crate) fn parse_allow(headers: &HeaderMap) -> Option<Vec<Method>> {
+    let items = list_items(headers, &reqwest::header::ALLOW)?;
+    Some(
+        items
+            .into_iter()
+            .filter_map(|item| Method::from_bytes(item.to_ascii_uppercase().as_bytes()).ok())
+            .collect(),
+    )
+}
+
+/// Range unit from Accept-Ranges, lowercased (`bytes`, `none`, or anything else the server uses)
+pub(crate) fn parse_accept_ranges(headers: &HeaderMap) -> Option<String> {
+    let value = headers
+        .get(reqwest::header::ACCEPT_RANGES)?
+        .to_str()
+        .ok()?
+        .trim();
+    is_token(value).then(|| value.to_ascii_lowercase())
+}
+
+/// Cache-Control directives; unknown directives are ignored
+pub(crate) fn parse_cache_control(headers: &HeaderMap) -> Option<CacheControl> {
+    let items = list_items(headers, &reqwest::header::CACHE_CONTROL)?;
+    let mut cache_control = CacheControl::default();
+    for item in items {
+        let (name, value) = match item.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (item, None),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "max-age" => cache_control.max_age = value.and_then(parse_seconds),
+            "no-store" => cache_control.no_store = true,
+            "no-cache" => cache_control.no_cache = true,
+            _ => {},
+        }
+    }
+    Some(cache_control)
+}
+
+/// Age in seconds
+pub(crate) fn parse_age(headers: &HeaderMap) -> Option<Duration> {
+    parse_seconds(headers.get(reqwest::header::AGE)?.to_str().ok()?.trim())
+}
+
+/// Language tags from Content-Language (`en-US`, `de`); malformed tags are skipped
+pub(crate) fn parse_content_language(headers: &HeaderMap) -> Option<Vec<String>> {
+    let tags: Vec<String> = list_items(headers, &reqwest::header::CONTENT_LANGUAGE)?
+        .into_iter()
+        .filter(|tag| {
+            tag.split('-').all(|part| {
+                (1..=8).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_alphanumeric())
+            })
+        })
+        .map(str::to_string)
+        .collect();
+    (!tags.is_empty()).then_some(tags)
+}
+
+/// Parse a Content-Disposition value
+///
+/// Parameter values may be tokens or quoted strings, so a `;` or an escaped
+/// quote inside quotes is part of the value. Values that don't follow the
+/// grammar (no disposition type, unquoted spaces, a repeated parameter, text
+/// after a closing quote) parse as `None`, as the RFC 6266 test suite expects.
+pub fn parse_content_disposition(value: &str) -> Option<ContentDisposition> {
+    let (disposition, mut rest) = value.split_once(';').unwrap_or((value, ""));
+    let disposition = disposition.trim();
+    if !is_token(disposition) {
+        return None;
+    }
+
+    let mut params: Vec<(String, String, bool)> = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let (name, after) = rest.split_once('=')?;
+        let name = name.trim_end().to_ascii_lowercase();
+        if !is_token(&name) || params.iter().any(|(seen, _, _)| *seen == name) {
+            return None;
+        }
+
+        let after = after.trim_start();
+        let (param_value, after, quoted) = if after.starts_with('"') {
+            let (param_value, after) = take_quoted(after)?;
+            (param_value, after, true)
+        } else {
+            let end = after
+                .find(|c: char| c == ';' || c.is_whitespace())
+                .unwrap_or(after.len());
+            let token = &after[..end];
+            if token.is_empty() || token.contains(|c: char| c == '"' || c.is_control()) {
+                return None;
+            }
+            (token.to_string(), &after[end..], false)
+        };
+
+        let after = after.trim_start();
+        rest = match after.strip_prefix(';') {
+            Some(next) => next,
+            None if after.is_empty() => "",
+            None => return None,
+        };
+        params.push((name, param_value, quoted));
+    }
+
+    let param = |wanted: &str| {
+        params
+            .iter()
+            .find(|(name, _, _)| name == wanted)
+            .map(|(_, value, quoted)| (value.as_str(), *quoted))
+    };
+    // An ext-value is never quoted (RFC 5987 section 3.2)
+    let extended = param("filename*")
+        .filter(|(_, quoted)| !quoted)
+        .and_then(|(value, _)| decode_ext_value(value));
+    let filename = extended
+        .or_else(|| param("filename").map(|(value, _)| value.to_string()))
+        .and_then(|name| strip_directories(&name));
+
+    Some(ContentDisposition {
+        disposition: disposition.to_ascii_lowercase(),
+        filename,
+    })
+}
+
+/// Relations from every Link header; targets are resolved against `base`
+///
+/// Link values without a `rel` parameter or with an unresolvable target are skipped.
+pub(crate) fn parse_link(headers: &HeaderMap, base: &Url) -> Vec<LinkRelation> {
+    let mut relations = Vec::new();
+    for value in headers.get_all(reqwest::header::LINK) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for link_value in split_unquoted(value, ',') {
+            parse_link_value(link_value, base, &mut relations);
+        }
+    }
+    relations
+}
+
+/// Parse one `<target>; param=value; ...` link value into `relations`
+fn parse_link_value(link_value: &str, base: &Url, relations: &mut Vec<LinkRelation>) {
+    let mut parts = split_unquoted(link_value, ';').into_iter();
+    let Some(target) = parts
+        .next()
+        .map(str::trim)
+        .and_then(|t| t.strip_prefix('<'))
+        .and_then(|t| t.strip_suffix('>'))
+    else {
+        return;
+    };
+    let Ok(url) = base.join(target.trim()) else {
+        return;
+    };
+
+    let mut rel = None;
+    let mut params = Vec::new();
+    for param in parts {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        let name = name.trim().to_ascii_lowercase();
+        let value = unquote(value.trim());
+        if name.is_empty() {
+            continue;
+        }
+        // Only the first rel counts (RFC 8288 section 3.3)
+        if name == "rel" {
+            rel.get_or_insert(value);
+        } else {
+            params.push((name, value));
+        }
+    }
+
+    for rel in rel.unwrap_or_default().split_ascii_whitespace() {
+        relations.push(LinkRelation {
+            url: url.to_string(),
+            rel: rel.to_ascii_lowercase(),
+            params: params.clone(),
+        });
+    }
+}
+
+/// Split on `separator`, except inside `"quoted strings"` and `<targets>`
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut in_target = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' if !in_target => in_quotes = !in_quotes,
+            '<' if !in_quotes => in_target = true,
+            '>' if !in_quotes => in_target = false,
+            c if c == separator && !in_quotes && !in_target => {
+                parts.push(&value[start..i]);
+                start = i + c.len_utf8();
+            },
+            _ => {},
+        }
+    }
+    parts.push(&value[start..]);
+    parts.retain(|part| !part.trim().is_empty());
+    parts
+}
+
+/// `value` without surrounding double quotes and with backslash escapes resolved
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+        return value.to_string();
+    };
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unquoted.extend(chars.next());
+        } else {
+            unquoted.push(c);
+        }
+    }
+    unquoted
+}
+
+/// Read a quoted string at the start of `input`, returning its unescaped
+/// content and the text after the closing quote
+fn take_quoted(input: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = input.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &input[i + 1..])),
+            '\\' => value.push(chars.next()?.1),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Decode an RFC 5987 ext-value (`charset'language'percent-encoded`)
+///
+/// Only the UTF-8 and ISO-8859-1 charsets are supported.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let (charset, rest) = value.split_once('\'')?;
+    let (_language, encoded) = rest.split_once('\'')?;
+    if !encoded
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"!#$%&+-.^_`|~".contains(&b))
+    {
+        return None;
+    }
+    let bytes: Vec<u8> = percent_encoding::percent_decode_str(encoded).collect();
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+/// Last path component of a suggested file name (`/` and `\` both separate)
+fn strip_directories(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name).trim();
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+/// Non-negative whole seconds (delta-seconds)
+fn parse_seconds(value: &str) -> Option<Duration> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // Oversized values are capped rather than rejected (RFC 9111 section 1.2.2)
+    let secs: u64 = value.parse().unwrap_or(MAX_DELTA_SECONDS);
+    Some(Duration::from_secs(secs.min(MAX_DELTA_SECONDS)))
+}
+
+/// Whether `value` is a non-empty HTTP token
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_parse_allow() {
+        assert_eq!(
+            parse_allow(&headers(&[("allow", "GET, HEAD,options")])),
+            Some(vec![Method::GET, Method::HEAD, Method::OPTIONS])
+        );
+        // Split across several headers
+        assert_eq!(
+            parse_allow(&headers(&[("allow", "GET"), ("allow", "PUT")])),
+            Some(vec![Method::GET, Method::PUT])
+        );
+        // Present but empty: no methods allowed
+        assert_eq!(parse_allow(&headers(&[("allow", "")])), Some(Vec::new()));
+        // Malformed entries are skipped
+        assert_eq!(
+            parse_allow(&headers(&[("allow", "GET, b@d, ,POST")])),
+            Some(vec![Method::GET, Method::POST])
+        );
+        assert_eq!(parse_allow(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_parse_accept_ranges() {
+        assert_eq!(
+            parse_accept_ranges(&headers(&[("accept-ranges", "bytes")])),
+            Some("bytes".into())
+        );
+        assert_eq!(
+            parse_accept_ranges(&headers(&[("accept-ranges", "None")])),
+            Some("none".into())
+        );
+        assert_eq!(
+            parse_accept_ranges(&headers(&[("accept-ranges", " pages ")])),
+            Some("pages".into())
+        );
+        assert_eq!(parse_accept_ranges(&headers(&[("accept-ranges", "")])), None);
+        assert_eq!(parse_accept_ranges(&headers(&[("accept-ranges", "by tes")])), None);
+        assert_eq!(parse_accept_ranges(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_parse_cache_control() {
+        assert_eq!(
+            parse_cache_control(&headers(&[("cache-control", "public, max-age=3600")])),
+            Some(CacheControl {
+                max_age: Some(Duration::from_hours(1)),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_cache_control(&headers(&[
+                ("cache-control", "No-Store"),
+                ("cache-control", "no-cache")
+            ])),
+            Some(CacheControl {
+                max_age: None,
+                no_store: true,
+                no_cache: true,
+            })
+        );
+        assert_eq!(
+            parse_cache_control(&headers(&[("cache-control", "max-age=\"60\"")])),
+            Some(CacheControl {
+                max_age: Some(Duration::from_mins(1)),
+                ..Default::default()
+            })
+        );
+        // A malformed max-age is dropped, the other directives are kept
+        assert_eq!(
+            parse_cache_control(&headers(&[("cache-control", "max-age=-5, no-cache, max-age")])),
+            Some(CacheControl {
+                max_age: None,
+                no_store: false,
+                no_cache: true,
+            })
+        );
+        assert_eq!(parse_cache_control(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age(&headers(&[("age", "120")])), Some(Duration::from_mins(2)));
+        assert_eq!(parse_age(&headers(&[("age", "0")])), Some(Duration::ZERO));
+        assert_eq!(
+            parse_age(&headers(&[("age", "99999999999999999999999")])),
+            Some(Duration::from_secs(MAX_DELTA_SECONDS))
+        );
+        assert_eq!(parse_age(&headers(&[("age", "-1")])), None);
+        assert_eq!(parse_age(&headers(&[("age", "1.5")])), None);
+        assert_eq!(parse_age(&headers(&[("age", "")])), None);
+        assert_eq!(parse_age(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_parse_content_language() {
+        assert_eq!(
+            parse_content_language(&headers(&[("content-language", "de-DE, en")])),
+            Some(vec!["de-DE".to_string(), "en".to_string()])
+        );
+        assert_eq!(
+            parse_content_language(&headers(&[("content-language", "en, not a tag, -x, fr")])),
+            Some(vec!["en".to_string(), "fr".to_string()])
+        );
+        assert_eq!(parse_content_language(&headers(&[("content-language", " , ")])), None);
+        assert_eq!(parse_content_language(&HeaderMap::new()), None);
+    }
+
+    fn relation(url: &str, rel: &str, params: &[(&str, &str)]) -> LinkRelation {
+        LinkRelation {
+            url: url.to_string(),
+            rel: rel.to_string(),
+            params: params
+                .iter()
+                .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_link() {
+        let base = Url::parse("http://example.com/list/page1").unwrap();
+        assert_eq!(
+            parse_link(
+                &headers(&[(
+                    "link",
+                    r#"<page2>; rel="next", </list/page0>; rel=prev; title="Back, please""#
+                )]),
+                &base
+            ),
+            vec![
+                relation("http://example.com/list/page2", "next", &[]),
+                relation("http://example.com/list/page0", "prev", &[("title", "Back, please")]),
+            ]
+        );
+        // Several Link headers, absolute targets, case-insensitive names
+        assert_eq!(
+            parse_link(
+                &headers(&[
+                    ("link", "<https://cdn.example.com/a.css>; REL=Preload; as=style"),
+                    ("link", "<page3>; rel=next"),
+                ]),
+                &base
+            ),
+            vec![
+                relation("https://cdn.example.com/a.css", "preload", &[("as", "style")]),
+                relation("http://example.com/list/page3", "next", &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_link_quoting_and_multiple_relations() {
+        let base = Url::parse("http://example.com/").unwrap();
+        // One value with two relation types, a quoted parameter containing ';' and '"'
+        assert_eq!(
+            parse_link(
+                &headers(&[("link", r#"<a?x=1,2>; rel="next prefetch"; title="say \"hi\"; bye""#)]),
+                &base
+            ),
+            vec![
+                relation("http://example.com/a?x=1,2", "next", &[("title", "say \"hi\"; bye")]),
+                relation("http://example.com/a?x=1,2", "prefetch", &[("title", "say \"hi\"; bye")]),
+            ]
+        );
+        // Only the first rel parameter counts
+        assert_eq!(
+            parse_link(&headers(&[("link", "<b>; rel=next; rel=prev")]), &base),
+            vec![relation("http://example.com/b", "next", &[])]
+        );
+    }
+
+    #[test]
+    fn test_parse_link_malformed() {
+        let base = Url::parse("http://example.com/").unwrap();
+        // No rel, no angle brackets, empty values: all skipped
+        assert_eq!(
+            parse_link(&headers(&[("link", "<a>; title=x, b; rel=next, , <c>; rel=\"\"")]), &base),
+            Vec::new()
+        );
+        assert_eq!(parse_link(&HeaderMap::new(), &base), Vec::new());
+    }
+
+    #[test]
+    fn test_parse_content_disposition() {
+        // Cases named after the RFC 6266 test suite (greenbytes.de/tech/tc2231)
+        let cases: &[(&str, &str, Option<(&str, Option<&str>)>)] = &[
+            ("inlonly", "inline", Some(("inline", None))),
+            (
+                "inlwithasciifilename",
+                "inline; filename=\"foo.html\"",
+                Some(("inline", Some("foo.html"))),
+            ),
+            ("attonly", "attachment", Some(("attachment", None))),
+            ("attonlyucase", "ATTACHMENT", Some(("attachment", None))),
+            (
+                "attwithasciifilename",
+                "attachment; filename=\"foo.html\"",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            (
+                "attwithasciifilenamenq",
+                "attachment; filename=foo.html",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            ("attwithasciifilenamenqws", "attachment; filename=foo bar.html", None),
+            (
+                "attwithasciifilenameucase",
+                "attachment; FILENAME=\"foo.html\"",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            (
+                "attwithasciifnescapedchar",
+                "attachment; filename=\"f\\oo.html\"",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            (
+                "attwithasciifnescapedquote",
+                "attachment; filename=\"\\\"quoting\\\" tested.html\"",
+                Some(("attachment", Some("\"quoting\" tested.html"))),
+            ),
+            (
+                "attwithquotedsemicolon",
+                "attachment; filename=\"Here's a semicolon;.html\"",
+                Some(("attachment", Some("Here's a semicolon;.html"))),
+            ),
+            (
+                "attwithfilenameandextparam",
+                "attachment; foo=\"bar\"; filename=\"foo.html\"",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            (
+                "attwithfnrawpctenca",
+                "attachment; filename=\"foo-%41.html\"",
+                Some(("attachment", Some("foo-%41.html"))),
+            ),
+            (
+                "attwithfntokensq",
+                "attachment; filename='foo.html'",
+                Some(("attachment", Some("'foo.html'"))),
+            ),
+            (
+                "attabspath",
+                "attachment; filename=\"/foo.html\"",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            (
+                "attabspathwin",
+                "attachment; filename=\"\\\\foo.html\"",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            (
+                "atttraversal",
+                "attachment; filename=\"../../etc/passwd\"",
+                Some(("attachment", Some("passwd"))),
+            ),
+            ("attdotdot", "attachment; filename=\"..\"", Some(("attachment", None))),
+            (
+                "attwith2filenames",
+                "attachment; filename=\"foo.html\"; filename=\"bar.html\"",
+                None,
+            ),
+            (
+                "attfnbrokentoken",
+                "attachment; filename=foo[1](2).html",
+                Some(("attachment", Some("foo[1](2).html"))),
+            ),
+            ("attbrokenquotedfn", "attachment; filename=\"foo.html\".txt", None),
+            ("attbrokenquotedfn2", "attachment; filename=\"bar", None),
+            ("attmissingdisposition", "filename=foo.html", None),
+            ("attreversed", "filename=foo.html; attachment", None),
+            ("attemptyparam", "attachment; ;filename=foo", None),
+            ("attconfusedparam", "attachment; xfilename=foo.html", Some(("attachment", None))),
+            (
+                "atttrailingsemicolon",
+                "attachment; filename=foo.html;",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            (
+                "attwithfn2231utf8",
+                "attachment; filename*=UTF-8''foo-%c3%a4-%e2%82%ac.html",
+                Some(("attachment", Some("foo-\u{e4}-\u{20ac}.html"))),
+            ),
+            (
+                "attwithfn2231iso",
+                "attachment; filename*=iso-8859-1''foo-%E4.html",
+                Some(("attachment", Some("foo-\u{e4}.html"))),
+            ),
+            (
+                "attwithfn2231noc",
+                "attachment; filename*=''foo-%c3%a4-%e2%82%ac.html",
+                Some(("attachment", None)),
+            ),
+            (
+                "attwithfn2231utf8-bad",
+                "attachment; filename*=UTF-8''foo-%E4.html",
+                Some(("attachment", None)),
+            ),
+            (
+                "attwithfn2231nbadcharset",
+                "attachment; filename*=windows-1252''foo-%E4.html",
+                Some(("attachment", None)),
+            ),
+            (
+                "attwithfn2231quot",
+                "attachment; filename*=\"UTF-8''foo-%c3%a4.html\"",
+                Some(("attachment", None)),
+            ),
+            ("attwithfn2231ws1", "attachment; filename *=UTF-8''foo-%c3%a4.html", None),
+            (
+                "attwithfn2231ws2",
+                "attachment; filename*= UTF-8''foo-%c3%a4.html",
+                Some(("attachment", Some("foo-\u{e4}.html"))),
+            ),
+            (
+                "attfnboth",
+                "attachment; filename=\"foo-ae.html\"; filename*=UTF-8''foo-%c3%a4.html",
+                Some(("attachment", Some("foo-\u{e4}.html"))),
+            ),
+            (
+                "attfnboth2",
+                "attachment; filename*=UTF-8''foo-%c3%a4.html; filename=\"foo-ae.html\"",
+                Some(("attachment", Some("foo-\u{e4}.html"))),
+            ),
+            (
+                "attfnboth3",
+                concat!(
+                    "attachment; filename*0*=ISO-8859-15''euro-sign%3d%a4; ",
+                    "filename*=ISO-8859-1''currency-sign%3d%a4"
+                ),
+                Some(("attachment", Some("currency-sign=\u{a4}"))),
+            ),
+            (
+                "attfnbothbadext",
+                "attachment; filename=\"foo-ae.html\"; filename*=koi8-r''foo-%c3%a4.html",
+                Some(("attachment", Some("foo-ae.html"))),
+            ),
+            (
+                "attnewandfn",
+                "attachment; foobar=x; filename=\"foo.html\"",
+                Some(("attachment", Some("foo.html"))),
+            ),
+            ("dispext", "foobar", Some(("foobar", None))),
+            ("emptydisposition", "; filename=foo.html", None),
+        ];
+
+        for (name, header, expected) in cases {
+            let parsed = parse_content_disposition(header);
+            let actual = parsed
+                .as_ref()
+                .map(|cd| (cd.disposition.as_str(), cd.filename.as_deref()));
+            assert_eq!(actual, *expected, "{name}: {header}");
+        }
+    }
+
+    #[test]
+    fn test_content_disposition_is_inline() {
+        assert!(parse_content_disposition("INLINE; filename=a.html")
+            .unwrap()
+            .is_inline());
+        assert!(!parse_content_disposition("attachment").unwrap().is_inline());
+        assert!(!parse_content_disposition("foobar").unwrap().is_inline());
+    }
+}
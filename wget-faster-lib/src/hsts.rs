@@ -0,0 +1,365 @@
+/// HTTP Strict Transport Security (RFC 6797)
+///
+/// Hosts that sent a `Strict-Transport-Security` header over https are only
+/// contacted over https until the policy expires: `http://` URLs to them are
+/// rewritten before the request is sent. The known hosts can be kept in a file
+/// in GNU wget's HSTS database format (`~/.wget-hsts`).
+use crate::Result;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+/// Directives of a `Strict-Transport-Security` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HstsPolicy {
+    /// Seconds the host is to be reached over https only (0 forgets the host)
+    pub max_age: u64,
+
+    /// Whether the policy covers the subdomains of the host
+    pub include_subdomains: bool,
+}
+
+impl HstsPolicy {
+    /// Parse a `Strict-Transport-Security` header value
+    ///
+    /// Returns `None` without a valid `max-age` or with a directive given twice,
+    /// in which case the header is to be ignored. Unknown directives are skipped.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut max_age = None;
+        let mut include_subdomains = false;
+        for directive in value.split(';').map(str::trim).filter(|d| !d.is_empty()) {
+            let (name, value) = directive
+                .split_once('=')
+                .map_or((directive, None), |(n, v)| (n.trim(), Some(v.trim())));
+            if name.eq_ignore_ascii_case("max-age") {
+                let seconds = value?.trim_matches('"').parse().ok()?;
+                if max_age.replace(seconds).is_some() {
+                    return None;
+                }
+            } else if name.eq_ignore_ascii_case("includeSubDomains") {
+                if include_subdomains {
+                    return None;
+                }
+                include_subdomains = true;
+            }
+        }
+        Some(Self {
+            max_age: max_age?,
+            include_subdomains,
+        })
+    }
+}
+
+/// A known HSTS host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HstsEntry {
+    /// Host name, lowercase
+    pub host: String,
+
+    /// Port of the https URL that set the policy (0 for the default port)
+    ///
+    /// Kept for the database file only: RFC 6797 policies apply to every port.
+    pub port: u16,
+
+    /// Whether subdomains of `host` are covered too
+    pub include_subdomains: bool,
+
+    /// When the policy was received, as a Unix timestamp
+    pub created: u64,
+
+    /// Seconds the policy lasts from `created`
+    pub max_age: u64,
+}
+
+impl HstsEntry {
+    /// Whether the policy ran out before `now` (a Unix timestamp)
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.created.saturating_add(self.max_age) <= now
+    }
+}
+
+/// Known HSTS hosts
+///
+/// # Examples
+///
+/// ```
+/// use wget_faster_lib::HstsStore;
+///
+/// let mut store = HstsStore::new();
+/// store.record("example.com", 443, "max-age=31536000; includeSubDomains");
+///
+/// let mut url = url::Url::parse("http://www.example.com/page").unwrap();
+/// assert!(store.upgrade(&mut url));
+/// assert_eq!(url.as_str(), "https://www.example.com/page");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HstsStore {
+    /// Host -> its policy
+    hosts: HashMap<String, HstsEntry>,
+
+    /// Whether hosts were added, updated or removed since the store was created
+    modified: bool,
+}
+
+impl HstsStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a GNU wget HSTS database
+    ///
+    /// Each line is `host port include_subdomains created max_age`, separated by
+    /// whitespace; comments, malformed lines and expired entries are skipped.
+    pub fn parse(content: &str) -> Self {
+        let now = unix_now();
+        let hosts = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(parse_entry)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| (entry.host.clone(), entry))
+            .collect();
+        Self {
+            hosts,
+            modified: false,
+        }
+    }
+
+    /// Write the store to `path` in GNU wget's HSTS database format
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let mut content = String::from(
+            "# HSTS 1.0 Known Hosts database for GNU Wget.\n\
+             # Edit at your own risk.\n\
+             # <hostname>\t<port>\t<incl. subdomains>\t<created>\t<max-age>\n",
+        );
+        let now = unix_now();
+        let mut entries: Vec<&HstsEntry> =
+            self.hosts.values().filter(|e| !e.is_expired(now)).collect();
+        entries.sort_by(|a, b| a.host.cmp(&b.host));
+        for entry in entries {
+            content.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                entry.host,
+                entry.port,
+                u8::from(entry.include_subdomains),
+                entry.created,
+                entry.max_age
+            ));
+        }
+        let mut file = tokio::fs::File::create(path).await?;
+        file.write_all(content.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Record the `Strict-Transport-Security` header `value` received from `host`
+    ///
+    /// Only call this for responses received over https. IP addresses and
+    /// invalid headers are ignored; `max-age=0` forgets the host. Returns
+    /// whether the store changed.
+    pub fn record(&mut self, host: &str, port: u16, value: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if host.is_empty() || is_ip_address(&host) {
+            return false;
+        }
+        let Some(policy) = HstsPolicy::parse(value) else {
+            tracing::debug!(host = %host, value = %value, "Ignoring invalid Strict-Transport-Security header");
+            return false;
+        };
+        if policy.max_age == 0 {
+            let removed = self.hosts.remove(&host).is_some();
+            self.modified |= removed;
+            return removed;
+        }
+        let entry = HstsEntry {
+            host: host.clone(),
+            port: if port == 443 { 0 } else { port },
+            include_subdomains: policy.include_subdomains,
+            created: unix_now(),
+            max_age: policy.max_age,
+        };
+        tracing::debug!(host = %host, max_age = policy.max_age, "Known HSTS host");
+        self.hosts.insert(host, entry);
+        self.modified = true;
+        true
+    }
+
+    /// Whether `host` must only be reached over https
+    pub fn is_known(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let now = unix_now();
+        let covers = |domain: &str, subdomain: bool| {
+            self.hosts
+                .get(domain)
+                .is_some_and(|e| (!subdomain || e.include_subdomains) && !e.is_expired(now))
+        };
+        if covers(&host, false) {
+            return true;
+        }
+        host.match_indices('.')
+            .any(|(dot, _)| covers(&host[dot + 1..], true))
+    }
+
+    /// Rewrite an `http://` URL to a known host to `https://`
+    ///
+    /// An explicit port 80 becomes the default https port; any other port is
+    /// kept. Returns whether the URL was rewritten.
+    pub fn upgrade(&self, url: &mut url::Url) -> bool {
+        if url.scheme() != "http" || !url.host_str().is_some_and(|host| self.is_known(host)) {
+            return false;
+        }
+        let port = url.port();
+        if url.set_scheme("https").is_err() {
+            return false;
+        }
+        if port == Some(80) {
+            let _ = url.set_port(None);
+        }
+        true
+    }
+
+    /// The known hosts
+    pub fn entries(&self) -> impl Iterator<Item = &HstsEntry> {
+        self.hosts.values()
+    }
+
+    /// Number of known hosts
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// Whether no host is known
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    /// Whether hosts were added, updated or removed since the store was created
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+}
+
+/// Parse one line of a wget HSTS database
+fn parse_entry(line: &str) -> Option<HstsEntry> {
+    let mut fields = line.split_whitespace();
+    let entry = HstsEntry {
+        host: fields.next()?.to_ascii_lowercase(),
+        port: fields.next()?.parse().ok()?,
+        include_subdomains: fields.next()?.parse::<u8>().ok()? != 0,
+        created: fields.next()?.parse().ok()?,
+        max_age: fields.next()?.parse().ok()?,
+    };
+    fields.next().is_none().then_some(entry)
+}
+
+fn is_ip_address(host: &str) -> bool {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .is_ok()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(
+            HstsPolicy::parse("max-age=31536000; includeSubDomains"),
+            Some(HstsPolicy {
+                max_age: 31_536_000,
+                include_subdomains: true
+            })
+        );
+        assert_eq!(
+            HstsPolicy::parse("MAX-AGE=\"60\"; preload"),
+            Some(HstsPolicy {
+                max_age: 60,
+                include_subdomains: false
+            })
+        );
+        assert_eq!(HstsPolicy::parse("includeSubDomains"), None);
+        assert_eq!(HstsPolicy::parse("max-age=abc"), None);
+        assert_eq!(HstsPolicy::parse("max-age=1; max-age=2"), None);
+    }
+
+    #[test]
+    fn test_upgrade_known_hosts_only() {
+        let mut store = HstsStore::new();
+        assert!(store.record("Example.com", 443, "max-age=600"));
+        assert!(store.record("secure.org", 8443, "max-age=600; includeSubDomains"));
+        assert!(!store.record("127.0.0.1", 443, "max-age=600"));
+
+        let upgraded = |url: &str| {
+            let mut url = url::Url::parse(url).unwrap();
+            store.upgrade(&mut url).then(|| url.to_string())
+        };
+        assert_eq!(
+            upgraded("http://example.com:80/a?b").as_deref(),
+            Some("https://example.com/a?b")
+        );
+        assert_eq!(
+            upgraded("http://example.com:8080/").as_deref(),
+            Some("https://example.com:8080/")
+        );
+        assert_eq!(upgraded("http://www.example.com/"), None);
+        assert_eq!(upgraded("http://a.b.secure.org/").as_deref(), Some("https://a.b.secure.org/"));
+        assert_eq!(upgraded("http://notsecure.org/"), None);
+        assert_eq!(upgraded("http://127.0.0.1/"), None);
+
+        // max-age=0 forgets the host
+        assert!(store.record("example.com", 443, "max-age=0"));
+        assert!(!store.is_known("example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_store_file_round_trip() {
+        let mut store = HstsStore::new();
+        store.record("example.com", 443, "max-age=600; includeSubDomains");
+        store.record("other.net", 8443, "max-age=1200");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hsts");
+        store.save(&path).await.unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# HSTS 1.0 Known Hosts database for GNU Wget.\n"));
+
+        let loaded = HstsStore::parse(&content);
+        assert!(!loaded.is_modified());
+        let mut original: Vec<&HstsEntry> = store.entries().collect();
+        let mut reloaded: Vec<&HstsEntry> = loaded.entries().collect();
+        original.sort_by(|a, b| a.host.cmp(&b.host));
+        reloaded.sort_by(|a, b| a.host.cmp(&b.host));
+        assert_eq!(original, reloaded);
+        assert_eq!(reloaded[1].port, 8443);
+    }
+
+    #[test]
+    fn test_parse_skips_expired_and_malformed_lines() {
+        let now = unix_now();
+        let content = format!(
+            "# comment\n\
+             live.com\t0\t1\t{now}\t600\n\
+             expired.com\t0\t0\t1000\t60\n\
+             short.com\t0\t1\n"
+        );
+        let store = HstsStore::parse(&content);
+        assert_eq!(store.len(), 1);
+        assert!(store.is_known("sub.live.com"));
+    }
+}
@@ -0,0 +1,501 @@
+/// Opt-in on-disk HTTP cache for URLs fetched again and again
+///
+/// Each entry is a `<key>.body` file with a `<key>.json` sidecar, where the key
+/// is a SHA-256 of the URL. Freshness comes from Cache-Control `max-age` (less
+/// `Age`); `private` responses are cached since this is the end client, while
+/// `no-store` responses never are. Stale entries are revalidated with
+/// `If-None-Match` / `If-Modified-Since`, and the least recently used entries
+/// are evicted once the bodies exceed the configured size.
+use crate::client::{HttpClient, ResourceMetadata};
+use crate::{DownloadConfig, Result};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// Configuration for the HTTP cache (see `DownloadConfig::http_cache`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheConfig {
+    /// Directory holding the cached responses (created on first use)
+    pub directory: PathBuf,
+
+    /// Largest total size of cached bodies, in bytes
+    pub max_size: u64,
+}
+
+impl CacheConfig {
+    /// Cache responses in `directory`, keeping at most `max_size` bytes of bodies
+    pub fn new(directory: impl Into<PathBuf>, max_size: u64) -> Self {
+        Self {
+            directory: directory.into(),
+            max_size,
+        }
+    }
+}
+
+/// How the HTTP cache took part in a download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// Served from a fresh entry without contacting the server
+    Hit,
+
+    /// A stale entry was confirmed unchanged by the server (304 Not Modified)
+    Revalidated,
+
+    /// Fetched from the server
+    Miss,
+}
+
+/// Cache counters for one `Downloader`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Downloads served from a fresh entry
+    pub hits: u64,
+
+    /// Downloads served from an entry after a 304 Not Modified
+    pub revalidated: u64,
+
+    /// Downloads fetched from the server
+    pub misses: u64,
+}
+
+/// Sidecar stored next to each cached body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EntryMeta {
+    url: String,
+    status: u16,
+    /// Response headers, lowercase names
+    headers: Vec<(String, String)>,
+    /// Request header values named by the response's Vary header
+    vary: Vec<(String, Option<String>)>,
+    /// When the response was received or last revalidated (Unix milliseconds)
+    stored_at: i64,
+    /// Seconds the response stays fresh after `stored_at`
+    fresh_for: u64,
+    /// Last time the entry was used (Unix milliseconds), for LRU eviction
+    last_used: i64,
+    size: u64,
+}
+
+/// A cached response matching the current request
+#[derive(Debug)]
+pub(crate) struct CachedResponse {
+    meta: EntryMeta,
+    body_path: PathBuf,
+}
+
+impl CachedResponse {
+    /// Whether the entry can be used without asking the server
+    pub(crate) fn is_fresh(&self) -> bool {
+        let age_ms = now_millis().saturating_sub(self.meta.stored_at);
+        u64::try_from(age_ms).is_ok_and(|age| age < self.meta.fresh_for.saturating_mul(1000))
+    }
+
+    /// Value of a stored response header
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.meta
+            .headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The cached body file
+    pub(crate) fn body_path(&self) -> &Path {
+        &self.body_path
+    }
+
+    /// Size of the cached body
+    pub(crate) fn size(&self) -> u64 {
+        self.meta.size
+    }
+
+    /// Read the cached body
+    pub(crate) async fn body(&self) -> Result<Bytes> {
+        Ok(Bytes::from(tokio::fs::read(&self.body_path).await?))
+    }
+
+    /// Metadata of the stored response, as if it had just been received
+    pub(crate) fn metadata(&self) -> ResourceMetadata {
+        let mut builder = http::Response::builder().status(self.meta.status);
+        for (name, value) in &self.meta.headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder
+            .body(Vec::new())
+            .unwrap_or_else(|_| http::Response::new(Vec::new()));
+        let mut metadata =
+            HttpClient::extract_metadata_from_response(&reqwest::Response::from(response));
+        metadata.final_url = Some(self.meta.url.clone());
+        metadata
+    }
+}
+
+/// The cache shared by all downloads of one `Downloader`
+#[derive(Debug)]
+pub(crate) struct HttpCache {
+    config: CacheConfig,
+    stats: Mutex<CacheStats>,
+    /// Serializes writes and evictions
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl HttpCache {
+    pub(crate) fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            stats: Mutex::default(),
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Counters so far
+    pub(crate) fn stats(&self) -> CacheStats {
+        *self.stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Count one download
+    pub(crate) fn record(&self, outcome: CacheStatus) {
+        let mut stats = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        match outcome {
+            CacheStatus::Hit => stats.hits += 1,
+            CacheStatus::Revalidated => stats.revalidated += 1,
+            CacheStatus::Miss => stats.misses += 1,
+        }
+    }
+
+    /// The entry for `url`, if there is one matching the request's varying headers
+    pub(crate) async fn lookup(
+        &self,
+        url: &str,
+        config: &DownloadConfig,
+    ) -> Option<CachedResponse> {
+        let key = cache_key(url);
+        let json = tokio::fs::read(self.meta_path(&key)).await.ok()?;
+        let meta: EntryMeta = serde_json::from_slice(&json).ok()?;
+        let matches = meta.url == url
+            && meta
+                .vary
+                .iter()
+                .all(|(name, value)| request_header(config, name) == *value);
+        matches.then(|| CachedResponse {
+            meta,
+            body_path: self.body_path(&key),
+        })
+    }
+
+    /// Store a complete 200 response if its Cache-Control allows it
+    ///
+    /// A `no-store` response also drops any entry kept for the URL.
+    pub(crate) async fn store(
+        &self,
+        url: &str,
+        config: &DownloadConfig,
+        metadata: &ResourceMetadata,
+        body: &[u8],
+    ) -> Result<()> {
+        let key = cache_key(url);
+        let _guard = self.write_lock.lock().await;
+
+        let Some(fresh_for) = storable_freshness(metadata) else {
+            let _ = tokio::fs::remove_file(self.meta_path(&key)).await;
+            let _ = tokio::fs::remove_file(self.body_path(&key)).await;
+            return Ok(());
+        };
+        let Some(vary) = vary_values(metadata, config) else {
+            return Ok(());
+        };
+        if metadata.status_code != 200 || body.len() as u64 > self.config.max_size {
+            return Ok(());
+        }
+
+        let now = now_millis();
+        let meta = EntryMeta {
+            url: url.to_string(),
+            status: metadata.status_code,
+            headers: metadata
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+            vary,
+            stored_at: now,
+            fresh_for,
+            last_used: now,
+            size: body.len() as u64,
+        };
+
+        tokio::fs::create_dir_all(&self.config.directory).await?;
+        write_atomically(&self.body_path(&key), body).await?;
+        self.write_meta(&key, &meta).await?;
+        self.evict().await
+    }
+
+    /// Apply a 304 Not Modified to a stale entry: new freshness, updated headers
+    pub(crate) async fn refresh(
+        &self,
+        mut entry: CachedResponse,
+        not_modified: &ResourceMetadata,
+    ) -> Result<CachedResponse> {
+        let _guard = self.write_lock.lock().await;
+        for (name, value) in &not_modified.headers {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            // A 304 has no body; its framing headers don't describe the stored one
+            if matches!(name.as_str(), "content-length" | "transfer-encoding" | "content-encoding")
+            {
+                continue;
+            }
+            match entry
+                .meta
+                .headers
+                .iter_mut()
+                .find(|(header, _)| header == name.as_str())
+            {
+                Some((_, stored)) => *stored = value.to_string(),
+                None => entry
+                    .meta
+                    .headers
+                    .push((name.as_str().to_string(), value.to_string())),
+            }
+        }
+        let now = now_millis();
+        entry.meta.stored_at = now;
+        entry.meta.last_used = now;
+        entry.meta.fresh_for = entry
+            .metadata()
+            .cache_control
+            .map_or(0, |cache_control| fresh_seconds(&cache_control, not_modified.age));
+        self.write_meta(&cache_key(&entry.meta.url), &entry.meta)
+            .await?;
+        Ok(entry)
+    }
+
+    /// Mark an entry as just used
+    pub(crate) async fn touch(&self, entry: &mut CachedResponse) {
+        let _guard = self.write_lock.lock().await;
+        entry.meta.last_used = now_millis();
+        if let Err(e) = self
+            .write_meta(&cache_key(&entry.meta.url), &entry.meta)
+            .await
+        {
+            tracing::debug!(url = %entry.meta.url, error = %e, "Could not update cache entry");
+        }
+    }
+
+    /// Remove least recently used entries until the bodies fit in `max_size`
+    async fn evict(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut dir = tokio::fs::read_dir(&self.config.directory).await?;
+        while let Some(file) = dir.next_entry().await? {
+            let path = file.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Ok(json) = tokio::fs::read(&path).await else {
+                continue;
+            };
+            if let Ok(meta) = serde_json::from_slice::<EntryMeta>(&json) {
+                entries.push((meta.last_used, meta.size, path));
+            }
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort();
+        for (_, size, meta_path) in entries {
+            if total <= self.config.max_size {
+                break;
+            }
+            tracing::debug!(entry = %meta_path.display(), "Evicting least recently used cache entry");
+            let _ = tokio::fs::remove_file(meta_path.with_extension("body")).await;
+            let _ = tokio::fs::remove_file(&meta_path).await;
+            total -= size;
+        }
+        Ok(())
+    }
+
+    async fn write_meta(&self, key: &str, meta: &EntryMeta) -> Result<()> {
+        let json = serde_json::to_vec(meta)
+            .map_err(|e| crate::Error::WriteError(format!("cache entry: {e}")))?;
+        write_atomically(&self.meta_path(key), &json).await
+    }
+
+    fn body_path(&self, key: &str) -> PathBuf {
+        self.config.directory.join(format!("{key}.body"))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.config.directory.join(format!("{key}.json"))
+    }
+}
+
+/// Seconds a response may be served without revalidation, or `None` if it must not be stored
+///
+/// Responses without `max-age` are only worth storing when they carry a
+/// validator, so that they can be revalidated cheaply.
+fn storable_freshness(metadata: &ResourceMetadata) -> Option<u64> {
+    let cache_control = metadata.cache_control.clone().unwrap_or_default();
+    if cache_control.no_store {
+        return None;
+    }
+    let fresh_for = fresh_seconds(&cache_control, metadata.age);
+    let has_validator = metadata.etag.is_some() || metadata.last_modified.is_some();
+    (fresh_for > 0 || has_validator).then_some(fresh_for)
+}
+
+/// `max-age` less the time already spent in caches; zero with `no-cache`
+fn fresh_seconds(cache_control: &crate::CacheControl, age: Option<std::time::Duration>) -> u64 {
+    if cache_control.no_cache {
+        return 0;
+    }
+    let max_age = cache_control.max_age.unwrap_or_default();
+    max_age.saturating_sub(age.unwrap_or_default()).as_secs()
+}
+
+/// Request values of the headers named in Vary, or `None` for `Vary: *`
+fn vary_values(
+    metadata: &ResourceMetadata,
+    config: &DownloadConfig,
+) -> Option<Vec<(String, Option<String>)>> {
+    let mut values = Vec::new();
+    for vary in metadata.headers.get_all(reqwest::header::VARY) {
+        for name in vary.to_str().unwrap_or_default().split(',') {
+            let name = name.trim().to_ascii_lowercase();
+            if name == "*" {
+                return None;
+            }
+            if !name.is_empty() {
+                values.push((name.clone(), request_header(config, &name)));
+            }
+        }
+    }
+    Some(values)
+}
+
+/// Value this downloader sends for request header `name` (lowercase)
+fn request_header(config: &DownloadConfig, name: &str) -> Option<String> {
+    if name == "user-agent" {
+        return Some(config.user_agent.clone());
+    }
+    config
+        .headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.clone())
+}
+
+/// Hex SHA-256 of the URL
+fn cache_key(url: &str) -> String {
+    ring::digest::digest(&ring::digest::SHA256, url.as_bytes())
+        .as_ref()
+        .iter()
+        .fold(String::new(), |mut key, b| {
+            let _ = write!(key, "{b:02x}");
+            key
+        })
+}
+
+/// Write through a temporary file so readers never see a partial entry
+async fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    tokio::fs::write(&temporary, contents).await?;
+    tokio::fs::rename(&temporary, path).await?;
+    Ok(())
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(headers: &[(&'static str, &str)]) -> ResourceMetadata {
+        let mut builder = http::Response::builder().status(200);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let response = reqwest::Response::from(builder.body(Vec::new()).unwrap());
+        HttpClient::extract_metadata_from_response(&response)
+    }
+
+    #[test]
+    fn test_storable_freshness() {
+        assert_eq!(storable_freshness(&metadata(&[("cache-control", "max-age=60")])), Some(60));
+        assert_eq!(
+            storable_freshness(&metadata(&[("cache-control", "max-age=60"), ("age", "50")])),
+            Some(10)
+        );
+        // private is fine for an end client
+        assert_eq!(
+            storable_freshness(&metadata(&[("cache-control", "private, max-age=5")])),
+            Some(5)
+        );
+        assert_eq!(
+            storable_freshness(&metadata(&[("cache-control", "no-store, max-age=60")])),
+            None
+        );
+        // Stored for revalidation only
+        assert_eq!(
+            storable_freshness(&metadata(&[("cache-control", "no-cache"), ("etag", "\"v1\"")])),
+            Some(0)
+        );
+        assert_eq!(storable_freshness(&metadata(&[])), None);
+    }
+
+    #[tokio::test]
+    async fn test_vary_mismatch_is_a_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(CacheConfig::new(dir.path(), 1024));
+        let url = "http://example.com/config.json";
+        let mut config = DownloadConfig::default();
+        config
+            .headers
+            .insert("Accept-Language".to_string(), "en".to_string());
+        let response = metadata(&[("cache-control", "max-age=60"), ("vary", "Accept-Language")]);
+
+        cache.store(url, &config, &response, b"{}").await.unwrap();
+        assert!(cache.lookup(url, &config).await.is_some());
+
+        config
+            .headers
+            .insert("Accept-Language".to_string(), "de".to_string());
+        assert!(cache.lookup(url, &config).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(CacheConfig::new(dir.path(), 10));
+        let config = DownloadConfig::default();
+        let response = metadata(&[("cache-control", "max-age=60")]);
+
+        cache
+            .store("http://a/", &config, &response, b"aaaa")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        cache
+            .store("http://b/", &config, &response, b"bbbb")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let mut a = cache.lookup("http://a/", &config).await.unwrap();
+        cache.touch(&mut a).await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        cache
+            .store("http://c/", &config, &response, b"cccc")
+            .await
+            .unwrap();
+
+        assert!(cache.lookup("http://a/", &config).await.is_some());
+        assert!(cache.lookup("http://b/", &config).await.is_none());
+        assert!(cache.lookup("http://c/", &config).await.is_some());
+    }
+}
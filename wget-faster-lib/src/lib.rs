@@ -4,20 +4,39 @@
 //!
 //! This library provides:
 //! - Full async/non-blocking API
-//! - Multiple output modes: memory, file, or custom `AsyncWrite`
+//! - Multiple output modes: memory or file
 //! - Parallel downloads using HTTP Range requests
 //! - Progress tracking with callbacks
 //! - Resume support for partial downloads
 //! - Cookie, authentication, and proxy support
 //!
+//! ## Cargo features
+//!
+//! | Feature        | Default | Enables                                                             |
+//! |----------------|---------|---------------------------------------------------------------------|
+//! | `recursive`    | yes     | `RecursiveDownloader`, `LinkConverter`, `FormLogin`, `robots` and sitemaps; pulls in `scraper`, `html5ever`, `regex` and `quick-xml` |
+//! | `cookies-file` | yes     | `CookieJar` for Netscape `cookies.txt` files                        |
+//! | `sigv4`        | no      | AWS `SigV4` reference `RequestSigner` (`sigv4` module)              |
+//! | `pack`         | no      | `RecursiveConfig::small_file_threshold`: small files of a crawl go to one `Pack` (implies `recursive`) |
+//! | `archive`      | no      | `RecursiveConfig::archive_output`: a crawl is written into one tar, tar.gz or zip (implies `recursive`) |
+//! | `test-util`    | no      | `test_server`: an embedded HTTP server with Range, drop, delay and status-sequence controls for integration tests |
+//!
+//! The core download path (`Downloader`, `HttpClient`, parallel Range downloads,
+//! progress, configuration and errors) builds with `default-features = false`.
+//!
 //! ## Example
 //!
 //! ```no_run
+//! use std::time::Duration;
 //! use wget_faster_lib::{Downloader, DownloadConfig, Output};
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
-//!     let downloader = Downloader::new(DownloadConfig::default())?;
+//!     let config = DownloadConfig::builder()
+//!         .timeout(Duration::from_secs(60))
+//!         .parallel_chunks(4)
+//!         .build()?;
+//!     let downloader = Downloader::new(config)?;
 //!
 //!     // Download to memory
 //!     let bytes = downloader.download_to_memory("https://example.com/file.txt").await?;
@@ -33,37 +52,159 @@
 //! ```
 
 mod adaptive;
+mod address_family;
+#[cfg(feature = "archive")]
+mod archive;
 mod auth_handler;
+mod batch;
+mod batch_state;
+mod body_limit;
+mod checksum;
 mod client;
+mod clobber;
 mod config;
+mod config_builder;
+mod config_serde;
+mod content_coding;
+mod control;
+#[cfg(feature = "cookies-file")]
 pub mod cookies;
 mod downloader;
 mod error;
+mod estimate;
+mod events;
+#[cfg(feature = "recursive")]
+mod file_handles;
+#[cfg(feature = "recursive")]
+mod form_login;
+mod headers;
+mod hsts;
+mod http_cache;
+mod link_check;
+#[cfg(feature = "recursive")]
 mod link_converter;
+mod memory_budget;
+mod mirror;
+mod naming;
 mod netrc;
 mod output;
+#[cfg(feature = "pack")]
+mod pack;
 mod parallel;
+mod permissions;
+mod plan;
 mod progress;
+mod progress_sink;
+mod provenance;
+mod quota;
+#[cfg(feature = "recursive")]
 mod recursive;
+mod referer;
+mod request_hints;
 mod response_handler;
-mod timestamping;
+mod retry;
+mod safe_paths;
+mod signing;
+#[cfg(feature = "recursive")]
+mod sitemap;
+mod staging;
+mod storage;
+mod stream;
+mod tls;
+#[cfg(feature = "recursive")]
+mod url_dedupe;
+#[cfg(feature = "recursive")]
+mod url_interner;
+mod url_prepare;
+mod verify;
+mod warc;
 
 pub use adaptive::AdaptiveDownloader;
+pub use address_family::AddressFamily;
+#[cfg(feature = "archive")]
+pub use archive::ArchiveFormat;
+pub use auth_handler::{CredentialProvider, CredentialProviderFn, MAX_AUTHENTICATED_HOSTS};
+pub use batch_state::{BatchEntry, BatchState, BatchStatus};
+pub use checksum::Checksum;
 pub use client::{HttpClient, ResourceMetadata};
 pub use config::{
     apply_filename_restrictions, AuthConfig, AuthType, DownloadConfig, FilenameRestriction,
     HttpMethod, ProxyConfig, RetryConfig,
 };
+pub use config_builder::DownloadConfigBuilder;
+pub use content_coding::ContentCoding;
+pub use control::DownloadHandle;
+#[cfg(feature = "cookies-file")]
 pub use cookies::{Cookie, CookieJar};
-pub use downloader::{DownloadResult, Downloader};
+pub use downloader::{DownloadOutcome, DownloadResult, DownloadStats, Downloader};
 pub use error::{Error, Result};
-pub use link_converter::LinkConverter;
+pub use estimate::{
+    EstimateEntry, EstimateOptions, EstimateOutcome, EstimateReport, EstimateTargetFn,
+};
+pub use events::{DownloadEvent, EventCallback, EventCallbackFn};
+#[cfg(feature = "recursive")]
+pub use form_login::{FormLogin, LoginSuccessCheck};
+pub use headers::{parse_content_disposition, CacheControl, ContentDisposition, LinkRelation};
+pub use hsts::{HstsEntry, HstsPolicy, HstsStore};
+pub use http_cache::{CacheConfig, CacheStats, CacheStatus};
+pub use link_check::{LinkCheckProgress, LinkCheckResult, LinkStatus, MAX_CHECKS_PER_HOST};
+#[cfg(feature = "recursive")]
+pub use link_converter::{ConversionMode, LinkConverter, PostProcessor, PostProcessorFn};
+pub use mirror::{MirrorOptions, MirrorOutcome};
+pub use naming::{
+    content_disposition_filename, final_filename, numbered_path, DirectoryLayout, NameRegistry,
+};
 pub use netrc::{Netrc, NetrcEntry};
 pub use output::{DownloadedData, Output};
+#[cfg(feature = "pack")]
+pub use pack::{Pack, PackEntry, PACK_FILE_NAME, PACK_INDEX_NAME};
+pub use plan::{DownloadPlan, PlanAction};
 pub use progress::{
     format_bytes, format_bytes_per_sec, format_duration, ProgressCallback, ProgressInfo,
+    TransferPhase,
+};
+pub use progress_sink::{ProgressSink, DEFAULT_PROGRESS_RATE};
+pub use provenance::{
+    redact_url, ProvenanceConfig, ProvenanceRecord, ProvenanceTarget, DEFAULT_PROVENANCE_SUFFIX,
 };
-pub use recursive::{RecursiveConfig, RecursiveDownloader};
+#[cfg(feature = "recursive")]
+pub use recursive::{
+    CrawlProgress, CrawlProgressCallback, CrawlProgressFn, CrawlStats, CrawlStopReason, Origin,
+    PathMapper, PathMapperContext, PathMapperFn, RecursiveConfig, RecursiveDownloader,
+    SkippedLinks, MAX_ORIGIN_REFERRERS,
+};
+pub use referer::RefererPolicy;
+pub use request_hints::{HeaderPreset, RequestKind, MAX_URGENCY};
+pub use response_handler::{ResponseFilter, ResponseFilterFn};
+pub use safe_paths::{create_dirs_within, replace_links};
+#[cfg(feature = "sigv4")]
+pub use signing::sigv4;
+pub use signing::{signature_expired, RequestSigner, UrlRefresher, UrlRefresherFn};
+#[cfg(feature = "recursive")]
+pub use sitemap::{parse_sitemap, Sitemap, SitemapEntry, MAX_SITEMAP_DEPTH};
+pub use timestamping::{SizeCheck, TimestampDecision};
+pub use tls::{CertificateSummary, TlsInfo, TlsPolicy};
+#[cfg(feature = "recursive")]
+pub use url_dedupe::{query_param_matches, strip_query_params};
+pub use url_prepare::prepare_url;
+pub use verify::{
+    verify_local, RemoteStatus, RemoteVerifyEntry, VerifyEntry, VerifyLocalReport, VerifyProgress,
+    VerifyRecord, VerifyRecords, VerifyRemoteReport, VerifyStatus,
+};
+pub use warc::WarcWriter;
 
 /// robots.txt parsing and handling
+#[cfg(feature = "recursive")]
 pub mod robots;
+
+/// Reading files saved with `save_headers`: [`saved_headers::split`] separates
+/// the header preamble from the body
+pub mod saved_headers;
+
+/// Embedded HTTP server for integration tests
+#[cfg(feature = "test-util")]
+pub mod test_server;
+
+/// Time-stamping (-N): whether a local file is re-downloaded, decided without I/O by
+/// [`timestamping::decide`]
+pub mod timestamping;
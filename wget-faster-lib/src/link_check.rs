@@ -0,0 +1,489 @@
+/// Batch link validation
+///
+/// Checks many URLs for existence without downloading their bodies:
+/// - HEAD probe, falling back to a one-byte ranged GET for servers that reject HEAD
+/// - Global concurrency cap plus a per-host cap and politeness delay (`wait_time`)
+/// - One shared client, so DNS lookups and connections are reused
+/// - Results are returned in input order
+use crate::HttpClient;
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use url::Url;
+
+/// Maximum number of probes in flight to a single host
+pub const MAX_CHECKS_PER_HOST: usize = 4;
+
+/// Progress callback for link checks, called with (completed, total)
+pub type LinkCheckProgress = Arc<dyn Fn(usize, usize) + Send + Sync>;
+
+/// Classification of a link check outcome
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// 2xx at the requested URL
+    Ok,
+
+    /// 2xx after following redirects, or a 3xx when redirects are disabled
+    Redirected,
+
+    /// 4xx response
+    ClientError,
+
+    /// 5xx response
+    ServerError,
+
+    /// The request timed out
+    Timeout,
+
+    /// DNS, connection, or TLS failure
+    ConnectionFailed,
+
+    /// Too many redirects or a redirect loop
+    RedirectLoop,
+
+    /// The URL could not be parsed or is not http(s)
+    InvalidUrl,
+
+    /// Any other failure
+    Other,
+}
+
+impl LinkStatus {
+    /// Whether the link should be reported as broken
+    pub fn is_broken(&self) -> bool {
+        !matches!(self, LinkStatus::Ok | LinkStatus::Redirected)
+    }
+
+    /// Short lowercase name used in reports
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::Ok => "ok",
+            LinkStatus::Redirected => "redirected",
+            LinkStatus::ClientError => "client-error",
+            LinkStatus::ServerError => "server-error",
+            LinkStatus::Timeout => "timeout",
+            LinkStatus::ConnectionFailed => "connection-failed",
+            LinkStatus::RedirectLoop => "redirect-loop",
+            LinkStatus::InvalidUrl => "invalid-url",
+            LinkStatus::Other => "other",
+        }
+    }
+}
+
+/// Result of checking a single link
+#[derive(Debug, Clone)]
+pub struct LinkCheckResult {
+    /// URL as given
+    pub url: String,
+
+    /// Final HTTP status code (`None` if no response was received)
+    pub status: Option<u16>,
+
+    /// URL after following redirects
+    pub final_url: Option<String>,
+
+    /// Content-Type of the final response
+    pub content_type: Option<String>,
+
+    /// Time from sending the first probe to receiving the final response headers
+    pub response_time: Duration,
+
+    /// Outcome classification
+    pub classification: LinkStatus,
+
+    /// Error message for failures without a response
+    pub error: Option<String>,
+}
+
+impl LinkCheckResult {
+    /// Whether the link is broken
+    pub fn is_broken(&self) -> bool {
+        self.classification.is_broken()
+    }
+
+    /// Format the result as a tab-separated report line
+    ///
+    /// Columns: url, classification, status, final URL, content type, response time (ms), error.
+    pub fn to_tsv(&self) -> String {
+        let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            clean(&self.url),
+            self.classification.as_str(),
+            self.status.map(|s| s.to_string()).unwrap_or_default(),
+            clean(self.final_url.as_deref().unwrap_or("")),
+            clean(self.content_type.as_deref().unwrap_or("")),
+            self.response_time.as_millis(),
+            clean(self.error.as_deref().unwrap_or("")),
+        )
+    }
+}
+
+/// Classify a final response status
+fn classify_status(status: u16, redirected: bool) -> LinkStatus {
+    match status {
+        200..=299 if redirected => LinkStatus::Redirected,
+        200..=299 => LinkStatus::Ok,
+        300..=399 => LinkStatus::Redirected,
+        400..=499 => LinkStatus::ClientError,
+        500..=599 => LinkStatus::ServerError,
+        _ => LinkStatus::Other,
+    }
+}
+
+/// Classify a request failure
+fn classify_error(err: &crate::Error) -> LinkStatus {
+    let crate::Error::HttpError(err) = err.root() else {
+        return LinkStatus::Other;
+    };
+    if err.is_timeout() {
+        LinkStatus::Timeout
+    } else if err.is_redirect() {
+        LinkStatus::RedirectLoop
+    } else if err.is_connect() {
+        LinkStatus::ConnectionFailed
+    } else if err.is_builder() {
+        LinkStatus::InvalidUrl
+    } else {
+        LinkStatus::Other
+    }
+}
+
+/// Whether a HEAD response suggests the server doesn't support HEAD
+///
+/// Some servers and CDNs answer HEAD with 400/403/405/501 while GET works.
+pub(crate) fn needs_get_fallback(status: u16) -> bool {
+    matches!(status, 400 | 403 | 405 | 501)
+}
+
+/// Per-host concurrency limits and politeness delays
+struct HostGate {
+    hosts: Mutex<HashMap<String, Arc<HostSlot>>>,
+    wait: Option<Duration>,
+    random_wait: bool,
+}
+
+/// Limits for a single host
+struct HostSlot {
+    permits: Semaphore,
+    next_start: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl HostGate {
+    fn new(wait: Option<Duration>, random_wait: bool) -> Self {
+        Self {
+            hosts: Mutex::new(HashMap::new()),
+            wait,
+            random_wait,
+        }
+    }
+
+    fn slot(&self, host: &str) -> Arc<HostSlot> {
+        let mut hosts = self
+            .hosts
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        hosts
+            .entry(host.to_string())
+            .or_insert_with(|| {
+                Arc::new(HostSlot {
+                    permits: Semaphore::new(MAX_CHECKS_PER_HOST),
+                    next_start: tokio::sync::Mutex::new(None),
+                })
+            })
+            .clone()
+    }
+
+    /// Wait for the politeness delay since the previous start on this host
+    async fn wait_turn(&self, slot: &HostSlot) {
+        let Some(wait) = self.wait else {
+            return;
+        };
+        let wait = if self.random_wait {
+            jittered(wait)
+        } else {
+            wait
+        };
+        let start_at = {
+            let mut next = slot.next_start.lock().await;
+            let now = Instant::now();
+            let start_at = next.map_or(now, |n| n.max(now));
+            *next = Some(start_at + wait);
+            start_at
+        };
+        tokio::time::sleep_until(start_at.into()).await;
+    }
+}
+
+/// Between 0.5 and 1.5 times `wait`, like wget's `--random-wait`
+pub(crate) fn jittered(wait: Duration) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    wait.mul_f64(0.5 + (random % 1001) as f64 / 1000.0)
+}
+
+/// Probe a single URL: HEAD, then a ranged GET if HEAD is rejected
+async fn probe(client: &HttpClient, url: &str) -> LinkCheckResult {
+    let start = Instant::now();
+    let mut result = LinkCheckResult {
+        url: url.to_string(),
+        status: None,
+        final_url: None,
+        content_type: None,
+        response_time: Duration::ZERO,
+        classification: LinkStatus::InvalidUrl,
+        error: None,
+    };
+
+    let parsed = match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => parsed,
+        Ok(parsed) => {
+            result.error = Some(format!("unsupported scheme: {}", parsed.scheme()));
+            return result;
+        },
+        Err(e) => {
+            result.error = Some(e.to_string());
+            return result;
+        },
+    };
+
+    let mut response = client.send(client.client().head(parsed.clone())).await;
+    if let Ok(ref head) = response {
+        if needs_get_fallback(head.status().as_u16()) {
+            tracing::debug!(url = %url, status = head.status().as_u16(), "HEAD rejected, retrying with ranged GET");
+            response = client
+                .send(
+                    client
+                        .client()
+                        .get(parsed.clone())
+                        .header(reqwest::header::RANGE, "bytes=0-0"),
+                )
+                .await;
+        }
+    }
+    result.response_time = start.elapsed();
+
+    match response {
+        Ok(response) => {
+            // A satisfied one-byte range means the resource exists
+            let status = match response.status().as_u16() {
+                206 => 200,
+                other => other,
+            };
+            result.status = Some(status);
+            result.final_url = Some(response.url().to_string());
+            result.content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string);
+            result.classification = classify_status(status, response.url() != &parsed);
+        },
+        Err(e) => {
+            result.classification = classify_error(&e);
+            result.error = Some(e.to_string());
+        },
+    }
+
+    result
+}
+
+/// Check all URLs with bounded concurrency, returning results in input order
+pub(crate) async fn check_links(
+    client: &HttpClient,
+    urls: Vec<String>,
+    concurrency: usize,
+    progress: Option<LinkCheckProgress>,
+) -> Vec<LinkCheckResult> {
+    let total = urls.len();
+    let completed = AtomicUsize::new(0);
+
+    let config = client.config();
+    let results =
+        for_each_per_host(urls, concurrency, config.wait_time, config.random_wait, None, |url| {
+            let completed = &completed;
+            let progress = progress.clone();
+            async move {
+                let result = probe(client, &url).await;
+                let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                if let Some(ref progress) = progress {
+                    progress(done, total);
+                }
+                result
+            }
+        })
+        .await;
+    results.into_iter().flatten().collect()
+}
+
+/// Run `task` for every URL (or item naming one), returning the results in
+/// input order
+///
+/// At most `concurrency` tasks run at once, at most `MAX_CHECKS_PER_HOST` per
+/// host, and `wait` (scaled by 0.5-1.5 when `random_wait` is set) separates
+/// the starts of tasks on the same host. A URL whose task hasn't finished by
+/// `deadline` (including time spent waiting for its turn) yields `None`.
+pub(crate) async fn for_each_per_host<I, T, F, Fut>(
+    items: Vec<I>,
+    concurrency: usize,
+    wait: Option<Duration>,
+    random_wait: bool,
+    deadline: Option<Instant>,
+    task: F,
+) -> Vec<Option<T>>
+where
+    I: AsRef<str>,
+    F: Fn(I) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let gate = HostGate::new(wait, random_wait);
+
+    futures_util::stream::iter(items)
+        .map(|item| {
+            let gate = &gate;
+            let task = &task;
+            let run = async move {
+                let host = Url::parse(item.as_ref())
+                    .ok()
+                    .and_then(|u| u.host_str().map(str::to_string))
+                    .unwrap_or_default();
+                let slot = gate.slot(&host);
+                let result = match slot.permits.acquire().await {
+                    Ok(_permit) => {
+                        gate.wait_turn(&slot).await;
+                        task(item).await
+                    },
+                    Err(_) => task(item).await,
+                };
+                result
+            };
+            async move {
+                match deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline.into(), run).await.ok(),
+                    None => Some(run.await),
+                }
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DownloadConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_classify_status() {
+        assert_eq!(classify_status(200, false), LinkStatus::Ok);
+        assert_eq!(classify_status(204, true), LinkStatus::Redirected);
+        assert_eq!(classify_status(301, false), LinkStatus::Redirected);
+        assert_eq!(classify_status(404, false), LinkStatus::ClientError);
+        assert_eq!(classify_status(503, true), LinkStatus::ServerError);
+        assert!(!LinkStatus::Redirected.is_broken());
+        assert!(LinkStatus::Timeout.is_broken());
+    }
+
+    #[test]
+    fn test_needs_get_fallback() {
+        assert!(needs_get_fallback(405));
+        assert!(needs_get_fallback(501));
+        assert!(!needs_get_fallback(404));
+        assert!(!needs_get_fallback(200));
+    }
+
+    #[test]
+    fn test_tsv_line() {
+        let result = LinkCheckResult {
+            url: "http://a/x".to_string(),
+            status: Some(404),
+            final_url: Some("http://a/x".to_string()),
+            content_type: Some("text/html".to_string()),
+            response_time: Duration::from_millis(12),
+            classification: LinkStatus::ClientError,
+            error: None,
+        };
+        assert_eq!(result.to_tsv(), "http://a/x\tclient-error\t404\thttp://a/x\ttext/html\t12\t");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_urls_are_classified_without_requests() {
+        let client = HttpClient::new(DownloadConfig::default()).unwrap();
+        let results = check_links(
+            &client,
+            vec!["not a url".to_string(), "ftp://example.com/".to_string()],
+            2,
+            None,
+        )
+        .await;
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|r| r.classification == LinkStatus::InvalidUrl && r.error.is_some()));
+    }
+
+    /// Server that holds each request briefly and records peak concurrency
+    async fn counting_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let peak_out = peak.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let in_flight = in_flight.clone();
+                let peak = peak.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        )
+                        .await;
+                });
+            }
+        });
+
+        (format!("http://{addr}"), peak_out)
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_caps() {
+        let client = HttpClient::new(DownloadConfig::default()).unwrap();
+
+        let (base, peak) = counting_server().await;
+        let urls: Vec<String> = (0..12).map(|i| format!("{base}/{i}")).collect();
+        let results = check_links(&client, urls.clone(), 2, None).await;
+        assert!(results.iter().all(|r| r.classification == LinkStatus::Ok));
+        assert_eq!(results.iter().map(|r| r.url.clone()).collect::<Vec<_>>(), urls);
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+
+        // A single host is capped even when global concurrency is higher
+        let (base, peak) = counting_server().await;
+        let urls: Vec<String> = (0..12).map(|i| format!("{base}/{i}")).collect();
+        let reported = Arc::new(AtomicUsize::new(0));
+        let reported_cb = reported.clone();
+        let progress: LinkCheckProgress = Arc::new(move |done, total| {
+            assert_eq!(total, 12);
+            reported_cb.fetch_max(done, Ordering::SeqCst);
+        });
+        check_links(&client, urls, 10, Some(progress)).await;
+        assert!(peak.load(Ordering::SeqCst) <= MAX_CHECKS_PER_HOST);
+        assert_eq!(reported.load(Ordering::SeqCst), 12);
+    }
+}
@@ -4,23 +4,134 @@
 /// - Converts absolute URLs to relative URLs in HTML and CSS files
 /// - Updates href/src attributes in HTML
 /// - Updates @import and `url()` in CSS
+/// - Optionally rewrites only the file name part (--convert-file-only)
 /// - Handles backup of original files with -K flag
+/// - Runs an optional user post-processor on each converted file
+use crate::file_handles::{FileHandles, MAX_OPEN_FILES};
 use crate::{Error, Result};
+use futures::{StreamExt, TryStreamExt};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use scraper::{Html, Selector};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::fmt;
+use std::mem::size_of;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use url::Url;
 
+/// `url(...)` in CSS, quoted or not; capture 1 is the URL
+const CSS_URL_PATTERN: &str = r#"url\s*\(\s*['"]?([^'")]+)['"]?\s*\)"#;
+
+/// `@import "..."` in CSS; capture 1 is the URL
+const CSS_IMPORT_PATTERN: &str = r#"@import\s+['"]([^'"]+)['"]"#;
+
+/// URLs referenced by CSS: the `url()` targets, then the `@import "..."` ones
+///
+/// `@import url(...)` is found as a `url()` reference.
+pub(crate) fn css_references(css: &str) -> Result<(Vec<&str>, Vec<&str>)> {
+    let targets = |pattern: &str| {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| Error::ConfigError(format!("Regex error: {e}")))?;
+        Ok::<_, Error>(
+            regex
+                .captures_iter(css)
+                .filter_map(|cap| cap.get(1))
+                .map(|target| target.as_str())
+                .collect(),
+        )
+    };
+    Ok((targets(CSS_URL_PATTERN)?, targets(CSS_IMPORT_PATTERN)?))
+}
+
+/// Custom rewriter run on each file's content after standard link conversion
+///
+/// Receives the file path and the converted content, and returns the final content.
+pub type PostProcessorFn = Arc<dyn Fn(&Path, String) -> Result<String> + Send + Sync>;
+
+/// Post-processor passed through `RecursiveConfig` to the link converter
+#[derive(Clone)]
+pub struct PostProcessor(pub PostProcessorFn);
+
+impl fmt::Debug for PostProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PostProcessor(..)")
+    }
+}
+
+/// Characters escaped when a local file name is put back into a URL
+const FILE_NAME_ESCAPES: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'\'')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
+
+/// How links to downloaded files are rewritten
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversionMode {
+    /// Replace the whole link with the relative path of the local file (`-k`)
+    #[default]
+    Full,
+    /// Replace only the file name, keeping scheme, host and directories as
+    /// written in the document (`--convert-file-only`)
+    ///
+    /// For mirrors served from a web root with the same layout: a link to
+    /// `http://example.com/docs/page.php` saved as `page.php.html` (with `-E`)
+    /// becomes `http://example.com/docs/page.php.html`.
+    FileOnly,
+}
+
+/// Local file registered with a [`LinkConverter`]
+///
+/// Files under the base directory (all files of a crawl) are stored relative
+/// to it, so the directory isn't repeated for every file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StoredPath {
+    /// Path relative to `base_dir`
+    Relative(Box<Path>),
+    /// Path of a file outside `base_dir`, as registered
+    Outside(Box<Path>),
+}
+
+impl StoredPath {
+    /// Name of the file
+    fn file_name(&self) -> Option<&std::ffi::OsStr> {
+        match self {
+            Self::Relative(path) | Self::Outside(path) => path.file_name(),
+        }
+    }
+}
+
+/// Approximate heap bytes used per registered file besides its path
+const ENTRY_OVERHEAD: usize = size_of::<Arc<str>>() + size_of::<StoredPath>() + 1;
+
 /// Link converter for making downloaded files suitable for local viewing
 pub struct LinkConverter {
-    /// Map of original URL to local file path
-    url_to_path: HashMap<String, PathBuf>,
+    /// Map of original URL to local file
+    url_to_path: HashMap<Arc<str>, StoredPath>,
 
     /// Base directory for all downloads
     base_dir: PathBuf,
 
     /// Whether to backup original files before conversion
     backup_converted: bool,
+
+    /// Custom rewriter applied after link conversion
+    post_processor: Option<PostProcessorFn>,
+
+    /// How matched links are rewritten
+    mode: ConversionMode,
+
+    /// Cap on files open at once (shared with the crawl that registered the files)
+    file_handles: FileHandles,
+
+    /// Files start with a header preamble (`save_headers`), kept as is
+    saved_headers: bool,
 }
 
 impl LinkConverter {
@@ -30,9 +141,40 @@ impl LinkConverter {
             url_to_path: HashMap::new(),
             base_dir,
             backup_converted,
+            post_processor: None,
+            mode: ConversionMode::Full,
+            file_handles: FileHandles::default(),
+            saved_headers: false,
         }
     }
 
+    /// Set how links to downloaded files are rewritten (default `Full`)
+    pub fn set_mode(&mut self, mode: ConversionMode) {
+        self.mode = mode;
+    }
+
+    /// Set a custom rewriter run on each HTML/CSS file after link conversion
+    ///
+    /// The processor sees the converted content before it is written; the file
+    /// (and its `.orig` backup) is only written if the final content differs
+    /// from what is on disk.
+    pub fn set_post_processor(&mut self, processor: PostProcessorFn) {
+        self.post_processor = Some(processor);
+    }
+
+    /// Set whether files were saved with `save_headers`
+    ///
+    /// Links are then converted only after the header preamble (see
+    /// [`crate::saved_headers::split`]), which is written back unchanged.
+    pub fn set_saved_headers(&mut self, saved_headers: bool) {
+        self.saved_headers = saved_headers;
+    }
+
+    /// Share the crawl's cap on open files
+    pub(crate) fn set_file_handles(&mut self, file_handles: FileHandles) {
+        self.file_handles = file_handles;
+    }
+
     /// Register a downloaded file (maps URL to local path)
     pub fn register_file(&mut self, url: &str, path: PathBuf) {
         // Normalize URL (remove fragment)
@@ -44,19 +186,98 @@ impl LinkConverter {
             url.to_string()
         };
 
-        self.url_to_path.insert(normalized_url, path);
+        let stored = self.stored_path(path);
+        self.url_to_path.insert(Arc::from(normalized_url), stored);
     }
 
-    /// Convert links in all registered HTML and CSS files
-    pub async fn convert_all_links(&self) -> Result<()> {
-        for (url, path) in &self.url_to_path {
-            if self.is_html_file(path) {
-                self.convert_html_file(path, url).await?;
-            } else if self.is_css_file(path) {
-                self.convert_css_file(path, url).await?;
+    /// Register a downloaded file under a URL shared with the crawl
+    ///
+    /// `url` must be a serialized [`Url`]; it is stored without a copy unless
+    /// it has a fragment.
+    pub(crate) fn register_interned(&mut self, url: Arc<str>, path: &Path) {
+        if url.contains('#') {
+            self.register_file(&url, path.to_path_buf());
+        } else {
+            let stored = self.stored_path(path.to_path_buf());
+            self.url_to_path.insert(url, stored);
+        }
+    }
+
+    /// Update the registered files after one was moved from `from` to `to`
+    pub fn relocate_file(&mut self, from: &Path, to: &Path) {
+        let from = self.stored_path(from.to_path_buf());
+        let to = self.stored_path(to.to_path_buf());
+        for path in self.url_to_path.values_mut() {
+            if *path == from {
+                path.clone_from(&to);
             }
         }
-        Ok(())
+    }
+
+    /// Number of registered files
+    pub fn len(&self) -> usize {
+        self.url_to_path.len()
+    }
+
+    /// Whether no file is registered
+    pub fn is_empty(&self) -> bool {
+        self.url_to_path.is_empty()
+    }
+
+    /// Approximate heap bytes used by the registered files, not counting URLs
+    /// shared with a crawl
+    pub(crate) fn approx_bytes(&self) -> usize {
+        self.url_to_path
+            .values()
+            .map(|path| match path {
+                StoredPath::Relative(path) | StoredPath::Outside(path) => path.as_os_str().len(),
+            })
+            .sum::<usize>()
+            + self.url_to_path.len() * ENTRY_OVERHEAD
+    }
+
+    /// `path` as stored in the registry
+    fn stored_path(&self, path: PathBuf) -> StoredPath {
+        match path.strip_prefix(&self.base_dir) {
+            Ok(relative) => StoredPath::Relative(relative.into()),
+            Err(_) => StoredPath::Outside(path.into_boxed_path()),
+        }
+    }
+
+    /// Full path of a registered file
+    fn full_path(&self, path: &StoredPath) -> PathBuf {
+        match path {
+            StoredPath::Relative(relative) => self.base_dir.join(relative),
+            StoredPath::Outside(path) => path.to_path_buf(),
+        }
+    }
+
+    /// Convert links in all registered HTML and CSS files
+    ///
+    /// Files are converted concurrently, within the cap on open files.
+    pub async fn convert_all_links(&self) -> Result<()> {
+        futures::stream::iter(&self.url_to_path)
+            .map(Ok)
+            .try_for_each_concurrent(MAX_OPEN_FILES, |(url, path)| async move {
+                let path = &self.full_path(path);
+                let converted = if self.is_html_file(path) {
+                    self.convert_html_file(path, url).await
+                } else if self.is_css_file(path) {
+                    self.convert_css_file(path, url).await
+                } else {
+                    Ok(())
+                };
+                converted.map_err(|e| {
+                    e.with_context(format!("while converting links in {}", path.display()))
+                })
+            })
+            .await
+    }
+
+    /// Whether `convert_all_links` rewrites the file at `path` (HTML and CSS, by extension)
+    #[cfg(feature = "archive")]
+    pub(crate) fn converts(&self, path: &Path) -> bool {
+        self.is_html_file(path) || self.is_css_file(path)
     }
 
     /// Check if file is HTML based on extension
@@ -94,6 +315,7 @@ impl LinkConverter {
             path.with_extension("orig")
         };
 
+        let _handles = self.file_handles.open(2).await;
         tokio::fs::copy(path, backup_path)
             .await
             .map_err(Error::IoError)?;
@@ -103,21 +325,43 @@ impl LinkConverter {
 
     /// Convert links in an HTML file
     async fn convert_html_file(&self, path: &Path, base_url: &str) -> Result<()> {
-        // Read HTML content
+        self.rewrite_file(path, |content| self.convert_html_content(content, base_url))
+            .await
+    }
+
+    /// Rewrite a file with `convert` followed by the post-processor
+    async fn rewrite_file<F>(&self, path: &Path, convert: F) -> Result<()>
+    where
+        F: FnOnce(&str) -> Result<String>,
+    {
+        // Read original content
+        let handle = self.file_handles.open(1).await;
         let content = tokio::fs::read_to_string(path)
             .await
             .map_err(Error::IoError)?;
+        drop(handle);
 
-        // Convert links
-        let converted = self.convert_html_content(&content, base_url)?;
+        // Convert links in the body, then apply the custom rewriter
+        let body_start = if self.saved_headers {
+            crate::saved_headers::split(content.as_bytes()).1
+        } else {
+            0
+        };
+        let (preamble, body) = content.split_at(body_start);
+        let mut converted = convert(body)?;
+        if let Some(ref processor) = self.post_processor {
+            converted = processor(path, converted)?;
+        }
+        converted.insert_str(0, preamble);
 
         // Only backup and save if content actually changed (GNU wget behavior)
-        // If no links were converted, don't create .orig file
+        // If nothing was rewritten, don't create .orig file
         if converted != content {
             // Backup original file before writing converted version
             self.backup_file(path).await?;
 
             // Write converted content back
+            let _handle = self.file_handles.open(1).await;
             tokio::fs::write(path, converted)
                 .await
                 .map_err(Error::IoError)?;
@@ -143,7 +387,7 @@ impl LinkConverter {
         if let Ok(selector) = Selector::parse("a[href]") {
             for element in document.select(&selector) {
                 if let Some(href) = element.value().attr("href") {
-                    if let Some(new_href) = self.convert_url_to_relative(&base, href) {
+                    if let Some(new_href) = self.convert_url(&base, href) {
                         result = result
                             .replace(&format!("href=\"{href}\""), &format!("href=\"{new_href}\""));
                     }
@@ -155,7 +399,7 @@ impl LinkConverter {
         if let Ok(selector) = Selector::parse("img[src]") {
             for element in document.select(&selector) {
                 if let Some(src) = element.value().attr("src") {
-                    if let Some(new_src) = self.convert_url_to_relative(&base, src) {
+                    if let Some(new_src) = self.convert_url(&base, src) {
                         result = result
                             .replace(&format!("src=\"{src}\""), &format!("src=\"{new_src}\""));
                     }
@@ -167,7 +411,7 @@ impl LinkConverter {
         if let Ok(selector) = Selector::parse("link[href]") {
             for element in document.select(&selector) {
                 if let Some(href) = element.value().attr("href") {
-                    if let Some(new_href) = self.convert_url_to_relative(&base, href) {
+                    if let Some(new_href) = self.convert_url(&base, href) {
                         result = result
                             .replace(&format!("href=\"{href}\""), &format!("href=\"{new_href}\""));
                     }
@@ -179,7 +423,7 @@ impl LinkConverter {
         if let Ok(selector) = Selector::parse("script[src]") {
             for element in document.select(&selector) {
                 if let Some(src) = element.value().attr("src") {
-                    if let Some(new_src) = self.convert_url_to_relative(&base, src) {
+                    if let Some(new_src) = self.convert_url(&base, src) {
                         result = result
                             .replace(&format!("src=\"{src}\""), &format!("src=\"{new_src}\""));
                     }
@@ -192,23 +436,8 @@ impl LinkConverter {
 
     /// Convert links in a CSS file
     async fn convert_css_file(&self, path: &Path, base_url: &str) -> Result<()> {
-        // Backup original file if requested
-        self.backup_file(path).await?;
-
-        // Read CSS content
-        let content = tokio::fs::read_to_string(path)
+        self.rewrite_file(path, |content| self.convert_css_content(content, base_url))
             .await
-            .map_err(Error::IoError)?;
-
-        // Convert links
-        let converted = self.convert_css_content(&content, base_url)?;
-
-        // Write converted content back
-        tokio::fs::write(path, converted)
-            .await
-            .map_err(Error::IoError)?;
-
-        Ok(())
     }
 
     /// Convert links in CSS content (`url()` and @import)
@@ -217,53 +446,66 @@ impl LinkConverter {
             .map_err(|e| Error::ConfigError(format!("Invalid base URL: {e}")))?;
 
         let mut result = css.to_string();
-
-        // Find all url() references
-        // Match url("..."), url('...'), and url(...)
-        let url_regex = regex::Regex::new(r#"url\s*\(\s*['"]?([^'")]+)['"]?\s*\)"#)
-            .map_err(|e| Error::ConfigError(format!("Regex error: {e}")))?;
-
-        for cap in url_regex.captures_iter(css) {
-            if let Some(url_match) = cap.get(1) {
-                let original_url = url_match.as_str();
-                if let Some(new_url) = self.convert_url_to_relative(&base, original_url) {
-                    result =
-                        result.replace(&format!("url({original_url})"), &format!("url({new_url})"));
-                    result = result.replace(
-                        &format!("url(\"{original_url}\")"),
-                        &format!("url(\"{new_url}\")"),
-                    );
-                    result = result
-                        .replace(&format!("url('{original_url}')"), &format!("url('{new_url}')"));
-                }
+        let (urls, imports) = css_references(css)?;
+
+        // url("..."), url('...') and url(...)
+        for original_url in urls {
+            if let Some(new_url) = self.convert_url(&base, original_url) {
+                result =
+                    result.replace(&format!("url({original_url})"), &format!("url({new_url})"));
+                result = result
+                    .replace(&format!("url(\"{original_url}\")"), &format!("url(\"{new_url}\")"));
+                result =
+                    result.replace(&format!("url('{original_url}')"), &format!("url('{new_url}')"));
             }
         }
 
-        // Find all @import references
-        let import_regex = regex::Regex::new(r#"@import\s+['"]([^'"]+)['"]"#)
-            .map_err(|e| Error::ConfigError(format!("Regex error: {e}")))?;
-
-        for cap in import_regex.captures_iter(css) {
-            if let Some(url_match) = cap.get(1) {
-                let original_url = url_match.as_str();
-                if let Some(new_url) = self.convert_url_to_relative(&base, original_url) {
-                    result = result.replace(
-                        &format!("@import \"{original_url}\""),
-                        &format!("@import \"{new_url}\""),
-                    );
-                    result = result.replace(
-                        &format!("@import '{original_url}'"),
-                        &format!("@import '{new_url}'"),
-                    );
-                }
+        // @import "..." and @import '...'
+        for original_url in imports {
+            if let Some(new_url) = self.convert_url(&base, original_url) {
+                result = result.replace(
+                    &format!("@import \"{original_url}\""),
+                    &format!("@import \"{new_url}\""),
+                );
+                result = result
+                    .replace(&format!("@import '{original_url}'"), &format!("@import '{new_url}'"));
             }
         }
 
         Ok(result)
     }
 
-    /// Convert an absolute URL to a relative path if the file was downloaded
-    fn convert_url_to_relative(&self, base: &Url, url_str: &str) -> Option<String> {
+    /// Rewrite a link according to the conversion mode
+    fn convert_url(&self, base: &Url, url_str: &str) -> Option<String> {
+        match self.mode {
+            ConversionMode::Full => self.convert_url_to_relative(base, url_str),
+            ConversionMode::FileOnly => self.convert_file_name(base, url_str),
+        }
+    }
+
+    /// Replace the file name in a link with the local file's name, if the file was downloaded
+    ///
+    /// Everything up to the last `/` of the path is kept as written, the query
+    /// is dropped (it is part of the local name) and the fragment is kept.
+    pub fn convert_file_name(&self, base: &Url, url_str: &str) -> Option<String> {
+        let local_name = self
+            .local_path(base, url_str)?
+            .file_name()?
+            .to_string_lossy();
+        let (link, fragment) = url_str
+            .find('#')
+            .map_or((url_str, ""), |i| url_str.split_at(i));
+        let path = link.split('?').next().unwrap_or(link);
+        let directory = path.rfind('/').map_or("", |i| &path[..=i]);
+
+        Some(format!(
+            "{directory}{}{fragment}",
+            utf8_percent_encode(&local_name, FILE_NAME_ESCAPES)
+        ))
+    }
+
+    /// Local file downloaded for a link, if any
+    fn local_path(&self, base: &Url, url_str: &str) -> Option<&StoredPath> {
         // Skip data: URLs, javascript:, mailto:, etc.
         if url_str.starts_with("data:")
             || url_str.starts_with("javascript:")
@@ -274,42 +516,184 @@ impl LinkConverter {
         }
 
         // Resolve the URL relative to base
-        let absolute_url = if let Ok(parsed) = Url::parse(url_str) {
-            // Already absolute
-            parsed
-        } else {
-            // Relative URL - resolve against base
-            match base.join(url_str) {
-                Ok(resolved) => resolved,
-                Err(_) => return None,
-            }
-        };
+        let mut absolute_url = Url::parse(url_str).or_else(|_| base.join(url_str)).ok()?;
 
         // Normalize (remove fragment)
-        let mut normalized = absolute_url.clone();
-        normalized.set_fragment(None);
-        let normalized_str = normalized.to_string();
-
-        // Check if we downloaded this file
-        if let Some(target_path) = self.url_to_path.get(&normalized_str) {
-            // Convert absolute path to relative path from base directory
-            if let Ok(relative) = target_path.strip_prefix(&self.base_dir) {
-                let relative_str = relative.to_string_lossy();
-
-                // GNU wget compatibility: add "./" prefix if the filename contains ':'
-                // and has no directory separators (basedirs == 0)
-                // This prevents filenames like "site;sub:.html" from being misinterpreted
-                // Reference: GNU wget's construct_relative() in src/convert.c
-                let needs_prefix = !relative_str.contains('/') && relative_str.contains(':');
-
-                if needs_prefix {
-                    return Some(format!("./{}", relative_str));
-                } else {
-                    return Some(relative_str.to_string());
-                }
+        absolute_url.set_fragment(None);
+        self.url_to_path.get(absolute_url.as_str())
+    }
+
+    /// Convert a URL to a local relative path if the file was downloaded
+    ///
+    /// `url_str` may be relative to `base`. Returns `None` for special schemes,
+    /// fragments, and URLs that were not registered.
+    pub fn convert_url_to_relative(&self, base: &Url, url_str: &str) -> Option<String> {
+        // Files under the base directory are stored relative to it
+        let Some(StoredPath::Relative(relative)) = self.local_path(base, url_str) else {
+            return None;
+        };
+        let relative_str = relative.to_string_lossy();
+
+        // GNU wget compatibility: add "./" prefix if the filename contains ':'
+        // and has no directory separators (basedirs == 0)
+        // This prevents filenames like "site;sub:.html" from being misinterpreted
+        // Reference: GNU wget's construct_relative() in src/convert.c
+        let needs_prefix = !relative_str.contains('/') && relative_str.contains(':');
+
+        if needs_prefix {
+            Some(format!("./{}", relative_str))
+        } else {
+            Some(relative_str.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BANNER: &str = "<!-- mirrored -->";
+
+    fn banner_processor() -> PostProcessorFn {
+        Arc::new(|_path: &Path, content: String| {
+            if content.contains(BANNER) {
+                Ok(content)
+            } else {
+                Ok(format!("{content}{BANNER}"))
             }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_post_processor_runs_after_conversion() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("index.html");
+        let page = dir.path().join("page.html");
+        let original = r#"<a href="http://example.com/page.html">page</a>"#;
+        std::fs::write(&index, original).unwrap();
+        std::fs::write(&page, "<p>no links</p>").unwrap();
+
+        let mut converter = LinkConverter::new(dir.path().to_path_buf(), true);
+        converter.register_file("http://example.com/index.html", index.clone());
+        converter.register_file("http://example.com/page.html", page.clone());
+        converter.set_post_processor(banner_processor());
+        converter.convert_all_links().await.unwrap();
+
+        let converted = std::fs::read_to_string(&index).unwrap();
+        assert_eq!(converted, format!(r#"<a href="page.html">page</a>{BANNER}"#));
+        assert_eq!(std::fs::read_to_string(dir.path().join("index.orig")).unwrap(), original);
+
+        // A file changed only by the post-processor is still written and backed up
+        assert_eq!(std::fs::read_to_string(&page).unwrap(), format!("<p>no links</p>{BANNER}"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("page.orig")).unwrap(),
+            "<p>no links</p>"
+        );
+
+        // A second pass changes nothing and keeps the original backups
+        converter.convert_all_links().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&index).unwrap(), converted);
+        assert_eq!(std::fs::read_to_string(dir.path().join("index.orig")).unwrap(), original);
+    }
+
+    #[tokio::test]
+    async fn test_post_processor_error_leaves_file_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("index.html");
+        std::fs::write(&index, "<p>x</p>").unwrap();
+
+        let mut converter = LinkConverter::new(dir.path().to_path_buf(), true);
+        converter.register_file("http://example.com/index.html", index.clone());
+        converter.set_post_processor(Arc::new(|_, _| Err(Error::ConfigError("nope".into()))));
+
+        assert!(converter.convert_all_links().await.is_err());
+        assert_eq!(std::fs::read_to_string(&index).unwrap(), "<p>x</p>");
+        assert!(!dir.path().join("index.orig").exists());
+    }
+
+    #[test]
+    fn test_convert_url_to_relative_is_reusable() {
+        let dir = PathBuf::from("/mirror");
+        let mut converter = LinkConverter::new(dir.clone(), false);
+        converter.register_file("http://example.com/a/b.css", dir.join("a/b.css"));
+
+        let base = Url::parse("http://example.com/index.html").unwrap();
+        assert_eq!(
+            converter
+                .convert_url_to_relative(&base, "a/b.css")
+                .as_deref(),
+            Some("a/b.css")
+        );
+        assert_eq!(converter.convert_url_to_relative(&base, "/missing.css"), None);
+        assert_eq!(converter.convert_url_to_relative(&base, "#top"), None);
+    }
+
+    /// Converter for a mirror of example.com saved with -E (and Windows-safe
+    /// names for the stylesheets) under `/mirror/example.com`
+    fn fixture_converter(mode: ConversionMode) -> LinkConverter {
+        let dir = PathBuf::from("/mirror");
+        let site = dir.join("example.com");
+        let mut converter = LinkConverter::new(dir, false);
+        converter.set_mode(mode);
+        for (url, path) in [
+            ("http://example.com/docs/", "docs/index.html"),
+            ("http://example.com/docs/page.php", "docs/page.php.html"),
+            ("http://example.com/docs/list.php?p=2", "docs/list.php?p=2.html"),
+            ("http://example.com/img/logo.png", "img/logo.png"),
+            ("http://example.com/css/site.css", "css/site.css"),
+            ("http://example.com/css/print.css?v=3", "css/print.css@v=3"),
+        ] {
+            converter.register_file(url, site.join(path));
         }
+        converter
+    }
 
-        None
+    #[test]
+    fn test_full_and_file_only_conversion() {
+        let html = concat!(
+            r#"<a href="http://example.com/docs/page.php#intro">abs</a>"#,
+            r#"<a href="list.php?p=2">query</a>"#,
+            r#"<a href="./">dir</a>"#,
+            r#"<a href="/missing.php">missing</a>"#,
+            r#"<img src="../img/logo.png">"#,
+            r#"<link href="/css/site.css" rel="stylesheet">"#,
+        );
+        let css = r#"@import "print.css?v=3"; body { background: url('/img/logo.png') }"#;
+        let page = "http://example.com/docs/page.php";
+        let stylesheet = "http://example.com/css/site.css";
+
+        let full = fixture_converter(ConversionMode::Full);
+        assert_eq!(
+            full.convert_html_content(html, page).unwrap(),
+            concat!(
+                r#"<a href="example.com/docs/page.php.html">abs</a>"#,
+                r#"<a href="example.com/docs/list.php?p=2.html">query</a>"#,
+                r#"<a href="example.com/docs/index.html">dir</a>"#,
+                r#"<a href="/missing.php">missing</a>"#,
+                r#"<img src="example.com/img/logo.png">"#,
+                r#"<link href="example.com/css/site.css" rel="stylesheet">"#,
+            )
+        );
+        assert_eq!(
+            full.convert_css_content(css, stylesheet).unwrap(),
+            r#"@import "example.com/css/print.css@v=3"; body { background: url('example.com/img/logo.png') }"#
+        );
+
+        let file_only = fixture_converter(ConversionMode::FileOnly);
+        assert_eq!(
+            file_only.convert_html_content(html, page).unwrap(),
+            concat!(
+                r#"<a href="http://example.com/docs/page.php.html#intro">abs</a>"#,
+                r#"<a href="list.php%3Fp=2.html">query</a>"#,
+                r#"<a href="./index.html">dir</a>"#,
+                r#"<a href="/missing.php">missing</a>"#,
+                r#"<img src="../img/logo.png">"#,
+                r#"<link href="/css/site.css" rel="stylesheet">"#,
+            )
+        );
+        assert_eq!(
+            file_only.convert_css_content(css, stylesheet).unwrap(),
+            r#"@import "print.css@v=3"; body { background: url('/img/logo.png') }"#
+        );
     }
 }
@@ -0,0 +1,373 @@
+/// Shared budget of bytes buffered in memory by in-flight downloads
+use crate::Result;
+use bytes::Bytes;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Notify;
+
+/// Bytes memory-destined transfers may hold at once (`DownloadConfig::max_buffered_bytes`)
+///
+/// Works like a semaphore counted in bytes. Clones share the same budget.
+#[derive(Debug, Clone)]
+pub(crate) struct MemoryBudget {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    limit: u64,
+    usage: Mutex<Usage>,
+    /// Woken whenever a reservation shrinks
+    released: Notify,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    in_use: u64,
+    peak: u64,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: u64) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                limit,
+                usage: Mutex::default(),
+                released: Notify::new(),
+            }),
+        }
+    }
+
+    /// Wait until `bytes` fit in the budget and reserve them
+    ///
+    /// Returns `None` straight away if `bytes` exceed the whole budget, since
+    /// waiting could never succeed.
+    pub(crate) async fn reserve(&self, bytes: u64) -> Option<Reservation> {
+        if bytes > self.shared.limit {
+            return None;
+        }
+        loop {
+            // Register for wakeups before checking, so a release in between isn't missed
+            let released = self.shared.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            if self.try_take(bytes) {
+                return Some(Reservation {
+                    budget: self.clone(),
+                    bytes,
+                });
+            }
+            released.await;
+        }
+    }
+
+    /// Highest number of bytes reserved at any one time
+    #[cfg(test)]
+    pub(crate) fn peak(&self) -> u64 {
+        self.usage().peak
+    }
+
+    fn try_take(&self, bytes: u64) -> bool {
+        let mut usage = self.usage();
+        if usage.in_use + bytes > self.shared.limit {
+            return false;
+        }
+        usage.in_use += bytes;
+        usage.peak = usage.peak.max(usage.in_use);
+        true
+    }
+
+    fn usage(&self) -> std::sync::MutexGuard<'_, Usage> {
+        self.shared
+            .usage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Bytes held from a [`MemoryBudget`], returned when dropped
+#[derive(Debug)]
+pub(crate) struct Reservation {
+    budget: MemoryBudget,
+    bytes: u64,
+}
+
+impl Reservation {
+    /// Reserve `bytes` more without waiting
+    pub(crate) fn try_grow(&mut self, bytes: u64) -> bool {
+        let grown = self.budget.try_take(bytes);
+        if grown {
+            self.bytes += bytes;
+        }
+        grown
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.usage().in_use -= self.bytes;
+        self.budget.shared.released.notify_waiters();
+    }
+}
+
+/// File a capped memory download moves to once it outgrows its cap (`Output::MemoryCapped`)
+#[derive(Debug, Clone)]
+pub(crate) struct SpillTarget {
+    pub(crate) max_bytes: u64,
+    pub(crate) path: PathBuf,
+}
+
+/// Where a collected body ended up
+#[derive(Debug)]
+pub(crate) enum Collected {
+    Memory(Bytes),
+    /// Spilled to the file of a [`SpillTarget`]
+    File {
+        path: PathBuf,
+        len: u64,
+    },
+}
+
+impl Collected {
+    /// The body in memory, read back from disk if it was spilled
+    pub(crate) async fn into_bytes(self) -> Result<Bytes> {
+        match self {
+            Self::Memory(bytes) => Ok(bytes),
+            Self::File { path, .. } => Ok(Bytes::from(tokio::fs::read(path).await?)),
+        }
+    }
+}
+
+/// Body of a memory download, collected under an optional budget
+///
+/// Until the transfer holds budget, a chunk waits for room. Once it holds some,
+/// it never waits again: two transfers each waiting for the other to finish
+/// would deadlock. If the budget can't grow (or a chunk is bigger than the whole
+/// budget), the body so far moves to an anonymous temporary file, its budget is
+/// released, and the rest streams to disk until the transfer completes.
+///
+/// With a [`SpillTarget`], the body moves to its file instead, and also once it
+/// grows past `max_bytes`; it then stays there rather than being read back.
+#[derive(Debug)]
+pub(crate) struct BudgetedBuffer {
+    budget: Option<MemoryBudget>,
+    reservation: Option<Reservation>,
+    buffer: Vec<u8>,
+    spill: Option<tokio::fs::File>,
+    target: Option<SpillTarget>,
+    len: u64,
+}
+
+impl BudgetedBuffer {
+    pub(crate) fn new(budget: Option<&MemoryBudget>) -> Self {
+        Self {
+            budget: budget.cloned(),
+            reservation: None,
+            buffer: Vec::new(),
+            spill: None,
+            target: None,
+            len: 0,
+        }
+    }
+
+    /// Spill to `target` rather than an anonymous file, and once over its cap
+    pub(crate) fn with_target(mut self, target: Option<&SpillTarget>) -> Self {
+        self.target = target.cloned();
+        self
+    }
+
+    /// Append a chunk, waiting for budget or spilling to disk as needed
+    pub(crate) async fn push(&mut self, chunk: &[u8]) -> Result<()> {
+        self.len += chunk.len() as u64;
+        if let Some(file) = self.spill.as_mut() {
+            file.write_all(chunk).await?;
+            return Ok(());
+        }
+
+        if let Some(ref target) = self.target {
+            if self.len > target.max_bytes {
+                tracing::debug!(
+                    buffered = self.len,
+                    max_bytes = target.max_bytes,
+                    path = %target.path.display(),
+                    "Body exceeds its memory cap - spilling to disk"
+                );
+                return self.spill(chunk).await;
+            }
+        }
+
+        let bytes = chunk.len() as u64;
+        let fits = match (&self.budget, self.reservation.as_mut()) {
+            (None, _) => true,
+            (Some(_), Some(reservation)) => reservation.try_grow(bytes),
+            (Some(budget), None) => {
+                self.reservation = budget.reserve(bytes).await;
+                self.reservation.is_some()
+            },
+        };
+        if fits {
+            self.buffer.extend_from_slice(chunk);
+            return Ok(());
+        }
+
+        tracing::debug!(buffered = self.len, "Memory budget exhausted - spilling body to disk");
+        self.spill(chunk).await
+    }
+
+    /// Move the body so far and `chunk` to disk, releasing the budget
+    async fn spill(&mut self, chunk: &[u8]) -> Result<()> {
+        let mut file = match self.target {
+            Some(ref target) => tokio::fs::File::create(&target.path).await?,
+            None => tokio::fs::File::from_std(tempfile::tempfile()?),
+        };
+        file.write_all(&self.buffer).await?;
+        file.write_all(chunk).await?;
+        self.buffer = Vec::new();
+        self.reservation = None;
+        self.spill = Some(file);
+        Ok(())
+    }
+
+    /// Bytes collected so far
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// The complete body, left in the target file if it was spilled there
+    pub(crate) async fn finish(mut self) -> Result<Collected> {
+        match (self.spill.as_mut(), self.target.take()) {
+            (Some(file), Some(target)) => {
+                file.flush().await?;
+                Ok(Collected::File {
+                    path: target.path,
+                    len: self.len,
+                })
+            },
+            _ => Ok(Collected::Memory(self.into_bytes().await?)),
+        }
+    }
+
+    /// The complete body, read back from disk if it was spilled
+    pub(crate) async fn into_bytes(self) -> Result<Bytes> {
+        let Some(mut file) = self.spill else {
+            return Ok(Bytes::from(self.buffer));
+        };
+        file.rewind().await?;
+        let mut data = Vec::with_capacity(usize::try_from(self.len).unwrap_or(0));
+        file.read_to_end(&mut data).await?;
+        Ok(Bytes::from(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DownloadConfig, Downloader};
+
+    #[tokio::test]
+    async fn test_reserve_waits_for_release() {
+        let budget = MemoryBudget::new(10);
+        let held = budget.reserve(8).await.unwrap();
+        assert!(budget.reserve(11).await.is_none());
+
+        let waiter = tokio::spawn({
+            let budget = budget.clone();
+            async move { budget.reserve(5).await.map(|r| r.bytes) }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        drop(held);
+        assert_eq!(waiter.await.unwrap(), Some(5));
+        assert_eq!(budget.peak(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_spills_to_target_over_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = SpillTarget {
+            max_bytes: 8,
+            path: dir.path().join("body"),
+        };
+
+        let mut buffer = BudgetedBuffer::new(None).with_target(Some(&target));
+        buffer.push(b"12345678").await.unwrap();
+        assert!(matches!(buffer.finish().await.unwrap(), Collected::Memory(b) if b == "12345678"));
+        assert!(!target.path.exists());
+
+        let mut buffer = BudgetedBuffer::new(None).with_target(Some(&target));
+        buffer.push(b"12345").await.unwrap();
+        buffer.push(b"6789").await.unwrap();
+        buffer.push(b"!").await.unwrap();
+        assert!(matches!(buffer.finish().await.unwrap(), Collected::File { len: 10, .. }));
+        assert_eq!(std::fs::read(&target.path).unwrap(), b"123456789!");
+    }
+
+    #[tokio::test]
+    async fn test_buffer_spills_when_budget_cannot_grow() {
+        let budget = MemoryBudget::new(10);
+        let mut buffer = BudgetedBuffer::new(Some(&budget));
+        buffer.push(b"hello ").await.unwrap();
+        buffer.push(b"budget").await.unwrap();
+        buffer.push(b"!").await.unwrap();
+
+        assert!(buffer.spill.is_some());
+        assert_eq!(budget.usage().in_use, 0);
+        assert_eq!(buffer.len(), 13);
+        assert_eq!(buffer.into_bytes().await.unwrap(), Bytes::from("hello budget!"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_memory_downloads_stay_within_budget() {
+        const BUDGET: u64 = 48 * 1024;
+        let mut server = mockito::Server::new_async().await;
+        let bodies: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i; 40 * 1024]).collect();
+        let mut mocks = Vec::new();
+        for (i, body) in bodies.iter().enumerate() {
+            mocks.push(
+                server
+                    .mock("GET", format!("/file{i}").as_str())
+                    .with_body(body)
+                    .create_async()
+                    .await,
+            );
+        }
+        // Larger than the whole budget: must spill rather than wait on itself
+        let oversized = vec![9u8; 200 * 1024];
+        let oversized_mock = server
+            .mock("GET", "/oversized")
+            .with_body(&oversized)
+            .create_async()
+            .await;
+
+        let config = DownloadConfig {
+            max_buffered_bytes: Some(BUDGET),
+            parallel_chunks: 1,
+            ..DownloadConfig::default()
+        };
+        let downloader = Downloader::new(config).unwrap();
+        let mut urls: Vec<String> = (0..bodies.len())
+            .map(|i| format!("{}/file{i}", server.url()))
+            .collect();
+        urls.push(format!("{}/oversized", server.url()));
+
+        let results =
+            futures::future::join_all(urls.iter().map(|url| downloader.download_to_memory(url)))
+                .await;
+
+        for (result, expected) in results.iter().zip(bodies.iter().chain([&oversized])) {
+            assert_eq!(result.as_ref().unwrap().as_ref(), expected.as_slice());
+        }
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        oversized_mock.assert_async().await;
+
+        let budget = downloader.memory_budget().unwrap();
+        assert!(budget.peak() > 0);
+        assert!(budget.peak() <= BUDGET, "peak {} over budget", budget.peak());
+        assert_eq!(budget.usage().in_use, 0);
+    }
+}
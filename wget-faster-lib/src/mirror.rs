@@ -0,0 +1,472 @@
+/// Keeping a local copy of one remote file fresh and intact ([`Downloader::mirror_file`])
+///
+/// One call goes through these steps:
+///
+/// 1. Probe the URL (HEAD, or a ranged GET where HEAD is refused). An error
+///    status fails the call.
+/// 2. If `path` exists, compare it with the remote file like `-N` does: by
+///    `ETag` when one is saved beside the file (`use_etag`), otherwise by
+///    `Last-Modified` and size (`timestamping_size_check`). If the local file
+///    wouldn't be replaced, return [`MirrorOutcome::Unchanged`]; nothing is
+///    transferred.
+/// 3. Download into `<path>.wgetf-mirror`, asking for the body without a
+///    content-coding. A partial file left by an interrupted call is continued
+///    with a Range request made conditional (`If-Range`) on the strong `ETag`
+///    or `Last-Modified` it was started with; if the remote file changed since,
+///    it is downloaded again from the start. A connection dropping mid-body is
+///    resumed the same way, up to `retry.max_retries` times.
+/// 4. Verify the complete partial file against `expected_checksum`, or else
+///    the server's `Repr-Digest` (or the `Content-Digest` of a response that
+///    carried the whole file; SHA-256 and SHA-512 are understood). On a
+///    mismatch the partial file is deleted, `path` is left as it was and the
+///    call fails with `Error::ChecksumMismatch`.
+/// 5. Rename the partial file over `path`, which is atomic on one file system,
+///    set its modification time from `Last-Modified` and save the `ETag`
+///    beside it (`use_etag`).
+use crate::checksum::{self, to_hex, Hasher};
+use crate::response_handler::check_partial_content;
+use crate::stream::{backoff, is_transient};
+use crate::timestamping::{self, LocalFileInfo, RemoteInfo, TimestampPolicy};
+use crate::{Checksum, Downloader, Error, HttpClient, ResourceMetadata, Result, TimestampDecision};
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, ACCEPT_ENCODING, ETAG, IF_RANGE, LAST_MODIFIED};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// Appended to the destination's name while its new version is downloaded
+const PART_SUFFIX: &str = ".wgetf-mirror";
+
+/// Appended to the partial file's name for the validator it was started with
+const VALIDATOR_SUFFIX: &str = ".validator";
+
+/// Options for [`Downloader::mirror_file`]
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    /// Digest the file must have; takes precedence over the server's digests
+    pub expected_checksum: Option<Checksum>,
+
+    /// Verify the file against a `Repr-Digest` or `Content-Digest` the server
+    /// sends, when no `expected_checksum` is given
+    pub verify_server_digest: bool,
+
+    /// Continue a partial file left by an interrupted call
+    pub resume: bool,
+
+    /// Set the file's modification time from `Last-Modified`
+    pub preserve_mtime: bool,
+}
+
+impl Default for MirrorOptions {
+    fn default() -> Self {
+        Self {
+            expected_checksum: None,
+            verify_server_digest: true,
+            resume: true,
+            preserve_mtime: true,
+        }
+    }
+}
+
+/// What [`Downloader::mirror_file`] did with the local file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MirrorOutcome {
+    /// The local file is up to date and was kept; nothing was transferred
+    Unchanged {
+        /// Why the local file was kept
+        decision: TimestampDecision,
+    },
+
+    /// The local file was created or replaced
+    Updated {
+        /// Why the previous local file was replaced, `None` if there was none
+        replaced: Option<TimestampDecision>,
+        /// Bytes of a partial file from an earlier call that were kept (0 if
+        /// the download started from scratch)
+        resumed_from: u64,
+        /// Body bytes received by this call
+        transferred: u64,
+        /// Digest of the new file (SHA-256 unless `expected_checksum` uses
+        /// another algorithm)
+        checksum: Checksum,
+        /// Whether the digest was checked against an expected or server digest
+        verified: bool,
+    },
+}
+
+impl MirrorOutcome {
+    /// Whether the local file changed
+    pub fn changed(&self) -> bool {
+        matches!(self, Self::Updated { .. })
+    }
+}
+
+/// The bytes of one or more responses written to the partial file
+struct Transfer {
+    /// Metadata of the last response
+    metadata: ResourceMetadata,
+    /// Bytes of the partial file continued by the first response, 0 once a
+    /// response restarted it
+    resumed_from: u64,
+    transferred: u64,
+    /// `Content-Digest` of a response that carried the whole file
+    content_digest: Option<Checksum>,
+}
+
+pub(crate) async fn mirror_file(
+    downloader: &Downloader,
+    url: &str,
+    path: &Path,
+    options: &MirrorOptions,
+) -> Result<MirrorOutcome> {
+    let client = downloader.get_client();
+    let remote = client.get_metadata(url).await?;
+    if remote.status_code >= 400 {
+        return Err(Error::InvalidStatus(remote.status_code));
+    }
+
+    let replaced = match local_decision(client, path, &remote).await? {
+        Some(decision) if !decision.replaced() => {
+            tracing::debug!(path = %path.display(), ?decision, "Mirror is up to date");
+            return Ok(MirrorOutcome::Unchanged { decision });
+        },
+        decision => decision,
+    };
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let part = part_path(path);
+    let transfer = fetch(downloader, url, &part, &remote, options).await?;
+
+    let expected = options.expected_checksum.clone().or_else(|| {
+        options
+            .verify_server_digest
+            .then(|| {
+                digest_header(&transfer.metadata.headers, "repr-digest")
+                    .or_else(|| digest_header(&remote.headers, "repr-digest"))
+                    .or_else(|| transfer.content_digest.clone())
+            })
+            .flatten()
+    });
+    let mut hasher = Hasher::new(expected.as_ref());
+    hasher.update_from_file(&part).await?;
+    let actual = hasher.finish();
+    if let Err(e) = checksum::verify(expected.as_ref(), &actual) {
+        discard(&part).await;
+        return Err(e);
+    }
+
+    tokio::fs::rename(&part, path).await?;
+    remove_if_exists(&validator_path(&part)).await?;
+    let config = client.config();
+    if options.preserve_mtime {
+        timestamping::set_file_timestamp(path, &transfer.metadata, config.verbose)?;
+    }
+    if config.use_etag {
+        timestamping::write_etag(path, transfer.metadata.etag.as_deref()).await;
+    }
+    Ok(MirrorOutcome::Updated {
+        replaced,
+        resumed_from: transfer.resumed_from,
+        transferred: transfer.transferred,
+        checksum: actual,
+        verified: expected.is_some(),
+    })
+}
+
+/// How the file at `path` compares with the remote file, `None` if there is no file
+async fn local_decision(
+    client: &HttpClient,
+    path: &Path,
+    remote: &ResourceMetadata,
+) -> Result<Option<TimestampDecision>> {
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let config = client.config();
+    let saved_etag = if config.use_etag {
+        timestamping::read_etag(path).await
+    } else {
+        None
+    };
+    let decision = match (saved_etag, remote.etag.as_deref()) {
+        (Some(saved), Some(etag)) if saved == etag => TimestampDecision::Unchanged,
+        (Some(_), Some(_)) => TimestampDecision::EtagChanged,
+        _ => timestamping::compare(
+            &LocalFileInfo::from_metadata(&metadata)?,
+            &RemoteInfo::from_metadata(remote),
+            &TimestampPolicy::from_config(config),
+        ),
+    };
+    Ok(Some(decision))
+}
+
+/// Download `url` into `part`, continuing it where possible and retrying transient failures
+async fn fetch(
+    downloader: &Downloader,
+    url: &str,
+    part: &Path,
+    remote: &ResourceMetadata,
+    options: &MirrorOptions,
+) -> Result<Transfer> {
+    let retry = &downloader.get_client().config().retry;
+    let initial = resumable_offset(part, remote, options).await?;
+    let mut offset = initial;
+    let mut progress = Progress::default();
+    let mut attempt = 0;
+    loop {
+        match fetch_once(downloader, url, part, offset, &mut progress).await {
+            Ok((metadata, content_digest)) => {
+                return Ok(Transfer {
+                    metadata,
+                    resumed_from: if progress.restarted { 0 } else { initial },
+                    transferred: progress.transferred,
+                    content_digest,
+                });
+            },
+            Err(e) if attempt < retry.max_retries && is_transient(&e, retry) => {
+                attempt += 1;
+                tracing::debug!(url = %url, attempt, error = %e, "Mirror transfer interrupted, resuming");
+                tokio::time::sleep(backoff(retry, attempt)).await;
+                offset = resumable_offset(part, remote, options).await?;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// What the responses of one [`fetch`] have done so far
+#[derive(Default)]
+struct Progress {
+    transferred: u64,
+    /// A response carried the whole file, replacing any partial one
+    restarted: bool,
+}
+
+/// Send one GET for the body from `offset` on and append it to `part`
+///
+/// Whatever arrived before a failure stays in `part` for the next attempt.
+/// Returns the response's metadata and, for a response with the whole file,
+/// its `Content-Digest`.
+async fn fetch_once(
+    downloader: &Downloader,
+    url: &str,
+    part: &Path,
+    offset: u64,
+    progress: &mut Progress,
+) -> Result<(ResourceMetadata, Option<Checksum>)> {
+    let range = (offset > 0).then(|| format!("bytes={offset}-"));
+    let mut request = downloader
+        .build_request(url, range.as_deref(), None)?
+        .header(ACCEPT_ENCODING, "identity");
+    if offset > 0 {
+        if let Some(validator) = read_validator(part).await {
+            request = request.header(IF_RANGE, validator);
+        }
+    }
+
+    let response = downloader.get_client().send(request).await?;
+    let append = match response.status().as_u16() {
+        206 if offset > 0 => {
+            check_partial_content(&response, offset)?;
+            true
+        },
+        // Also the answer to an If-Range that no longer matches
+        200 => false,
+        status => return Err(Error::InvalidStatus(status)),
+    };
+    let metadata = HttpClient::extract_metadata_from_response(&response);
+    let mut file = if append {
+        tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(part)
+            .await?
+    } else {
+        progress.restarted = true;
+        write_validator(part, &metadata.headers).await?;
+        tokio::fs::File::create(part).await?
+    };
+
+    let mut body = response.bytes_stream();
+    let copied = async {
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            progress.transferred += chunk.len() as u64;
+        }
+        Ok::<_, Error>(())
+    }
+    .await;
+    file.flush().await?;
+    copied?;
+
+    let content_digest = if append {
+        None
+    } else {
+        digest_header(&metadata.headers, "content-digest")
+    };
+    Ok((metadata, content_digest))
+}
+
+/// Size of the partial file to continue from, after dropping one that can't be continued
+///
+/// A partial file is only continued if the validator it was started with is
+/// still the remote file's; the Range request is conditional on it as well.
+async fn resumable_offset(
+    part: &Path,
+    remote: &ResourceMetadata,
+    options: &MirrorOptions,
+) -> Result<u64> {
+    let size = match tokio::fs::metadata(part).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let saved = read_validator(part).await;
+    let current = validator(&remote.headers);
+    let continuable = options.resume
+        && remote.supports_range
+        && size > 0
+        && saved.is_some()
+        && (current.is_none() || saved == current);
+    if continuable {
+        return Ok(size);
+    }
+    discard(part).await;
+    Ok(0)
+}
+
+/// Validator a partial file is continued with: the strong `ETag`, else `Last-Modified`
+fn validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    // A weak ETag can't be used in If-Range (RFC 9110 13.1.5)
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(str::to_string)
+}
+
+/// Save the validator of the response `part` is started from, or remove a stale one
+async fn write_validator(part: &Path, headers: &HeaderMap) -> Result<()> {
+    let path = validator_path(part);
+    match validator(headers) {
+        Some(validator) => tokio::fs::write(path, format!("{validator}\n")).await?,
+        None => remove_if_exists(&path).await?,
+    }
+    Ok(())
+}
+
+async fn read_validator(part: &Path) -> Option<String> {
+    let validator = tokio::fs::read_to_string(validator_path(part)).await.ok()?;
+    let validator = validator.trim();
+    (!validator.is_empty()).then(|| validator.to_string())
+}
+
+/// Strongest digest of a `Repr-Digest` or `Content-Digest` header (RFC 9530)
+///
+/// The header is a dictionary of algorithms to base64 byte sequences, such as
+/// `sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:`.
+fn digest_header(headers: &HeaderMap, name: &str) -> Option<Checksum> {
+    let value = headers.get(name)?.to_str().ok()?;
+    value
+        .split(',')
+        .filter_map(|member| {
+            let (algorithm, digest) = member.split_once('=')?;
+            let digest = digest.split(';').next()?.trim();
+            let bytes = decode_base64(digest.strip_prefix(':')?.strip_suffix(':')?)?;
+            match algorithm.trim().to_ascii_lowercase().as_str() {
+                "sha-512" => Some((2, Checksum::Sha512(to_hex(&bytes)))),
+                "sha-256" => Some((1, Checksum::Sha256(to_hex(&bytes)))),
+                _ => None,
+            }
+        })
+        .max_by_key(|(strength, _)| *strength)
+        .map(|(_, checksum)| checksum)
+}
+
+/// Standard base64 with optional padding
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+/// Where the new version of `path` is downloaded
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(PART_SUFFIX);
+    PathBuf::from(part)
+}
+
+fn validator_path(part: &Path) -> PathBuf {
+    let mut validator = part.as_os_str().to_owned();
+    validator.push(VALIDATOR_SUFFIX);
+    PathBuf::from(validator)
+}
+
+/// Delete a partial file and its validator; failures are logged
+async fn discard(part: &Path) {
+    for path in [part.to_path_buf(), validator_path(part)] {
+        if let Err(e) = remove_if_exists(&path).await {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to remove partial mirror file");
+        }
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_digest_header() {
+        let mut headers = HeaderMap::new();
+        // SHA-256 and SHA-512 of "hello world"
+        headers.insert(
+            "repr-digest",
+            HeaderValue::from_static(
+                "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:, md5=:XrY7u+Ae7tCTyyK7j1rNww==:",
+            ),
+        );
+        assert_eq!(
+            digest_header(&headers, "repr-digest"),
+            Some(Checksum::Sha256(
+                "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9".to_string()
+            ))
+        );
+        assert_eq!(digest_header(&headers, "content-digest"), None);
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("aGVsbG8=").as_deref(), Some(&b"hello"[..]));
+        assert_eq!(decode_base64("aGVsbG8").as_deref(), Some(&b"hello"[..]));
+        assert_eq!(decode_base64("a$==").as_deref(), None);
+    }
+}
@@ -0,0 +1,462 @@
+/// Output file naming shared across a download session
+///
+/// When several URLs are downloaded in one run (for example with
+/// `--content-disposition`), two of them may suggest the same filename.
+/// Checking the filesystem alone is not enough: both downloads can resolve
+/// the name before either file exists. The [`NameRegistry`] records which
+/// paths downloads in progress have claimed so that other resolutions get a
+/// `.1`, `.2`, ... suffix, matching GNU wget's numbering of duplicate files.
+///
+/// [`DirectoryLayout`] maps a URL to its local directory (`host/dir/...`), the
+/// same way for recursive crawls and for `-x` single downloads.
+use crate::client::ResourceMetadata;
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Maximum numeric suffix tried before giving up
+const MAX_SUFFIX: usize = 9999;
+
+/// Session-scoped registry of claimed output paths
+///
+/// Cloning the registry is cheap and clones share the same set of claims,
+/// so it can be handed to concurrently running downloads.
+#[derive(Debug, Clone, Default)]
+pub struct NameRegistry {
+    /// Claimed path -> URL that claimed it
+    claimed: Arc<Mutex<HashMap<PathBuf, String>>>,
+}
+
+impl NameRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Atomically reserve an output path for `url`
+    ///
+    /// Returns `path` itself if it is free, otherwise the first free
+    /// `path.N` candidate. A path is taken if it is claimed in this session,
+    /// even by the same URL, or, when `number_existing` is true, if a file
+    /// already exists there. Every call is a new claim, held until
+    /// [`release`](Self::release).
+    pub fn reserve(&self, url: &str, path: &Path, number_existing: bool) -> Result<PathBuf> {
+        let mut claimed = self
+            .claimed
+            .lock()
+            .map_err(|_| Error::Unknown("name registry lock poisoned".to_string()))?;
+
+        for counter in 0..=MAX_SUFFIX {
+            let candidate = if counter == 0 {
+                path.to_path_buf()
+            } else {
+                numbered_path(path, counter)
+            };
+
+            if claimed.contains_key(&candidate) {
+                continue;
+            }
+            if number_existing && candidate.exists() {
+                continue;
+            }
+
+            claimed.insert(candidate.clone(), url.to_string());
+            return Ok(candidate);
+        }
+
+        Err(Error::WriteError(format!("Too many duplicate files for '{}'", path.display())))
+    }
+
+    /// Release a reservation once its download is over
+    ///
+    /// A file the download created is found by later resolutions that
+    /// number existing files; one it never created frees the name entirely.
+    pub fn release(&self, path: &Path) {
+        if let Ok(mut claimed) = self.claimed.lock() {
+            claimed.remove(path);
+        }
+    }
+
+    /// Check whether a path is currently claimed
+    pub fn is_claimed(&self, path: &Path) -> bool {
+        self.claimed
+            .lock()
+            .is_ok_and(|claimed| claimed.contains_key(path))
+    }
+}
+
+/// Append a numeric suffix to the file name (`file.txt` -> `file.txt.1`)
+pub fn numbered_path(path: &Path, counter: usize) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{name}.{counter}"))
+}
+
+/// Extract the filename from a `Content-Disposition` header value
+///
+/// Uses [`parse_content_disposition`](crate::parse_content_disposition), so
+/// `filename*` (RFC 5987) is preferred over `filename` and directory components
+/// are removed. The disposition type is not checked: callers that only honor
+/// `attachment` should parse the header themselves.
+pub fn content_disposition_filename(header: &str) -> Option<String> {
+    crate::headers::parse_content_disposition(header)?.filename
+}
+
+/// Name a response would be saved under, once its headers are known
+///
+/// This is the `Content-Disposition` filename if the server sent one with an
+/// `attachment` disposition, otherwise the last path segment of the final URL
+/// (after redirects), falling back to the requested `url`. Like wget, an
+/// `inline` filename doesn't override the URL unless `--content-disposition`
+/// is given, which callers handle with [`content_disposition_filename`].
+pub fn final_filename(url: &str, metadata: &ResourceMetadata) -> Option<String> {
+    if let Some(name) = metadata
+        .content_disposition
+        .as_deref()
+        .and_then(crate::headers::parse_content_disposition)
+        .filter(|disposition| !disposition.is_inline())
+        .and_then(|disposition| disposition.filename)
+    {
+        return Some(name);
+    }
+
+    let final_url = url::Url::parse(metadata.final_url.as_deref().unwrap_or(url)).ok()?;
+    final_url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            percent_encoding::percent_decode_str(name)
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+}
+
+/// How a URL maps to local directories (`-x`, `-nd`, `-nH`, `--cut-dirs`, `--protocol-directories`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirectoryLayout {
+    /// Drop every directory, keeping only the file name (`-nd`)
+    pub no_directories: bool,
+
+    /// Don't create the host directory (`-nH`)
+    pub no_host_directories: bool,
+
+    /// Put the scheme (`http`, `https`) above the host directory (`--protocol-directories`)
+    pub protocol_directories: bool,
+
+    /// Number of remote directories to drop after the host (`--cut-dirs`)
+    pub cut_dirs: usize,
+}
+
+impl DirectoryLayout {
+    /// Directory `url` is saved in, under `output_dir`
+    ///
+    /// Follows GNU wget's precedence:
+    /// 1. `output_dir` (`-P`) always applies
+    /// 2. `no_directories` (`-nd`) drops every directory
+    /// 3. otherwise `protocol_directories` adds the scheme directory,
+    /// 4. `no_host_directories` (`-nH`) drops the host directory
+    /// 5. and `cut_dirs` (`--cut-dirs`) drops that many remote directories after it
+    pub fn local_dir(&self, url: &url::Url, output_dir: &Path) -> PathBuf {
+        let mut path = output_dir.to_path_buf();
+        if self.no_directories {
+            return path;
+        }
+        if self.protocol_directories {
+            path.push(url.scheme());
+        }
+        if !self.no_host_directories {
+            if let Some(host) = url.host_str() {
+                path.push(host);
+            }
+        }
+
+        let mut segments: Vec<&str> = url
+            .path_segments()
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        // The last segment is the file name
+        segments.pop();
+        for dir in segments
+            .iter()
+            .filter(|s| !s.is_empty())
+            .skip(self.cut_dirs)
+        {
+            path.push(dir);
+        }
+        path
+    }
+
+    /// Local path for `url` under `output_dir`; a URL ending in `/` is saved as `index.html`
+    pub fn local_path(&self, url: &url::Url, output_dir: &Path) -> PathBuf {
+        let file_name = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("index.html");
+        self.local_dir(url, output_dir).join(file_name)
+    }
+}
+
+/// `path` placed inside `dir`, or `None` if it would leave it
+///
+/// A path starting with `dir` is kept as it is, another relative path is
+/// joined to `dir`. The check is lexical: `..` is refused anywhere, and so is
+/// a path naming `dir` itself.
+#[cfg(feature = "recursive")]
+pub(crate) fn path_within(dir: &Path, path: &Path) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let relative = match path.strip_prefix(dir) {
+        Ok(relative) => relative,
+        Err(_) if path.is_relative() => path,
+        Err(_) => return None,
+    };
+    let mut named = false;
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) => named = true,
+            Component::CurDir => {},
+            _ => return None,
+        }
+    }
+    named.then(|| dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "recursive")]
+    fn test_path_within() {
+        let dir = Path::new("out");
+        assert_eq!(path_within(dir, Path::new("ab/x.html")), Some(PathBuf::from("out/ab/x.html")));
+        assert_eq!(
+            path_within(dir, Path::new("out/ab/x.html")),
+            Some(PathBuf::from("out/ab/x.html"))
+        );
+        assert_eq!(path_within(dir, Path::new("out/../x.html")), None);
+        assert_eq!(path_within(dir, Path::new("ab/../../x.html")), None);
+        assert_eq!(path_within(dir, Path::new("/etc/x.html")), None);
+        assert_eq!(path_within(dir, Path::new("./")), None);
+    }
+
+    #[test]
+    fn test_numbered_path() {
+        assert_eq!(numbered_path(Path::new("dir/file.txt"), 1), PathBuf::from("dir/file.txt.1"));
+        assert_eq!(numbered_path(Path::new("index.html"), 12), PathBuf::from("index.html.12"));
+    }
+
+    #[test]
+    fn test_content_disposition_filename() {
+        assert_eq!(
+            content_disposition_filename("attachment; filename=\"report.pdf\"").as_deref(),
+            Some("report.pdf")
+        );
+        assert_eq!(
+            content_disposition_filename("attachment; filename*=UTF-8''na%C3%AFve.txt").as_deref(),
+            Some("na\u{ef}ve.txt")
+        );
+        assert_eq!(
+            content_disposition_filename("inline; filename=\"a;b.html\"").as_deref(),
+            Some("a;b.html")
+        );
+        assert_eq!(content_disposition_filename("inline"), None);
+    }
+
+    #[test]
+    fn test_final_filename() {
+        let mut metadata = ResourceMetadata {
+            supports_range: false,
+            content_length: None,
+            last_modified: None,
+            etag: None,
+            content_type: None,
+            content_encoding: None,
+            content_disposition: None,
+            status_code: 200,
+            headers: reqwest::header::HeaderMap::new(),
+            auth_succeeded: false,
+            final_url: Some("http://host/files/report.pdf".to_string()),
+            allow: None,
+            accept_ranges_unit: None,
+            cache_control: None,
+            age: None,
+            content_language: None,
+            links: Vec::new(),
+            tls_info: None,
+        };
+        assert_eq!(
+            final_filename("http://host/download?id=9", &metadata).as_deref(),
+            Some("report.pdf")
+        );
+
+        metadata.content_disposition = Some("attachment; filename=\"data.csv\"".to_string());
+        assert_eq!(
+            final_filename("http://host/download?id=9", &metadata).as_deref(),
+            Some("data.csv")
+        );
+
+        metadata.content_disposition = Some("inline; filename=\"page.html\"".to_string());
+        assert_eq!(
+            final_filename("http://host/download?id=9", &metadata).as_deref(),
+            Some("report.pdf")
+        );
+
+        metadata.content_disposition = None;
+        metadata.final_url = Some("http://host/dir/".to_string());
+        assert_eq!(final_filename("http://host/dir/", &metadata), None);
+    }
+
+    #[test]
+    fn test_reserve_distinct_urls() {
+        let registry = NameRegistry::new();
+        let path = Path::new("wgetf-naming-test-nonexistent/report.pdf");
+
+        let first = registry.reserve("http://a/1", path, true).unwrap();
+        let second = registry.reserve("http://a/2", path, true).unwrap();
+        let third = registry.reserve("http://a/3", path, false).unwrap();
+
+        assert_eq!(first, path);
+        assert_eq!(second, numbered_path(path, 1));
+        assert_eq!(third, numbered_path(path, 2));
+    }
+
+    #[test]
+    fn test_reserve_same_url_twice_is_numbered() {
+        let registry = NameRegistry::new();
+        let path = Path::new("wgetf-naming-test-nonexistent/a.bin");
+
+        // `wgetf URL URL`: the second download must not overwrite the first
+        let first = registry.reserve("http://a/x", path, true).unwrap();
+        let again = registry.reserve("http://a/x", path, true).unwrap();
+        assert_eq!(first, path);
+        assert_eq!(again, numbered_path(path, 1));
+    }
+
+    #[test]
+    fn test_release() {
+        let registry = NameRegistry::new();
+        let path = Path::new("wgetf-naming-test-nonexistent/b.bin");
+
+        let first = registry.reserve("http://a/1", path, true).unwrap();
+        assert!(registry.is_claimed(&first));
+        registry.release(&first);
+        assert!(!registry.is_claimed(&first));
+
+        // The name is free again
+        let second = registry.reserve("http://a/2", path, true).unwrap();
+        assert_eq!(second, path);
+    }
+
+    #[test]
+    fn test_existing_file_is_numbered() {
+        let dir = std::env::temp_dir().join(format!("wgetf-naming-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("exists.txt");
+        std::fs::write(&path, b"x").unwrap();
+
+        let registry = NameRegistry::new();
+        assert_eq!(registry.reserve("http://a/1", &path, true).unwrap(), numbered_path(&path, 1));
+        assert_eq!(registry.reserve("http://a/2", &path, false).unwrap(), path);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_reservations_are_unique() {
+        let registry = NameRegistry::new();
+        let path = PathBuf::from("wgetf-naming-test-nonexistent/same.zip");
+
+        let handles: Vec<_> = (0..16)
+            .map(|i| {
+                let registry = registry.clone();
+                let path = path.clone();
+                tokio::spawn(async move {
+                    registry
+                        .reserve(&format!("http://a/{i}"), &path, true)
+                        .unwrap()
+                })
+            })
+            .collect();
+
+        let mut paths = std::collections::HashSet::new();
+        for handle in handles {
+            assert!(paths.insert(handle.await.unwrap()));
+        }
+        assert_eq!(paths.len(), 16);
+    }
+
+    #[test]
+    fn test_directory_layout() {
+        let url = url::Url::parse("http://example.com/a/b/c/file.txt").unwrap();
+        let out = Path::new("out");
+        let path = |layout: DirectoryLayout| layout.local_path(&url, out);
+
+        // -x alone
+        assert_eq!(
+            path(DirectoryLayout::default()),
+            PathBuf::from("out/example.com/a/b/c/file.txt")
+        );
+        // -x -nH
+        assert_eq!(
+            path(DirectoryLayout {
+                no_host_directories: true,
+                ..Default::default()
+            }),
+            PathBuf::from("out/a/b/c/file.txt")
+        );
+        // -x --cut-dirs=2 (and more cuts than directories)
+        assert_eq!(
+            path(DirectoryLayout {
+                cut_dirs: 2,
+                ..Default::default()
+            }),
+            PathBuf::from("out/example.com/c/file.txt")
+        );
+        assert_eq!(
+            path(DirectoryLayout {
+                no_host_directories: true,
+                cut_dirs: 5,
+                ..Default::default()
+            }),
+            PathBuf::from("out/file.txt")
+        );
+        // --protocol-directories, with and without -nH
+        assert_eq!(
+            path(DirectoryLayout {
+                protocol_directories: true,
+                ..Default::default()
+            }),
+            PathBuf::from("out/http/example.com/a/b/c/file.txt")
+        );
+        assert_eq!(
+            path(DirectoryLayout {
+                protocol_directories: true,
+                no_host_directories: true,
+                cut_dirs: 1,
+                ..Default::default()
+            }),
+            PathBuf::from("out/http/b/c/file.txt")
+        );
+        // -nd wins over everything
+        assert_eq!(
+            path(DirectoryLayout {
+                no_directories: true,
+                protocol_directories: true,
+                ..Default::default()
+            }),
+            PathBuf::from("out/file.txt")
+        );
+
+        let dir_url = url::Url::parse("https://example.com/docs/").unwrap();
+        assert_eq!(
+            DirectoryLayout::default().local_path(&dir_url, out),
+            PathBuf::from("out/example.com/docs/index.html")
+        );
+    }
+}
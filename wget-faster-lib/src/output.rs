@@ -18,6 +18,12 @@ use std::path::PathBuf;
 ///
 /// // Download to file
 /// let output = Output::File(PathBuf::from("download.zip"));
+///
+/// // Download to memory, or to a file if it turns out larger than 16 MiB
+/// let output = Output::MemoryCapped {
+///     max_bytes: 16 * 1024 * 1024,
+///     spill_path: PathBuf::from("download.part"),
+/// };
 /// ```
 #[derive(Debug)]
 pub enum Output {
@@ -26,6 +32,20 @@ pub enum Output {
 
     /// Write downloaded content to a file at the specified path
     File(PathBuf),
+
+    /// Store downloaded content in memory while it fits in `max_bytes`
+    ///
+    /// A larger body is moved to `spill_path` as soon as it outgrows the cap
+    /// (straight away when its size is known up front) and the rest is
+    /// appended there; [`DownloadedData::spilled`] tells which happened.
+    /// These downloads don't go through the HTTP cache.
+    MemoryCapped {
+        /// Most bytes held in memory
+        max_bytes: u64,
+
+        /// File the content is written to once it exceeds `max_bytes`
+        spill_path: PathBuf,
+    },
 }
 
 /// Container for downloaded data
@@ -58,9 +78,14 @@ pub struct DownloadedData {
     /// Downloaded content as bytes (only present when using `Output::Memory`)
     pub data: Option<Bytes>,
 
-    /// Path to downloaded file (only present when using `Output::File`)
+    /// Path to downloaded file (present when using `Output::File`, or a
+    /// spilled `Output::MemoryCapped`)
     pub file_path: Option<PathBuf>,
 
+    /// Whether an `Output::MemoryCapped` download exceeded its cap and is in
+    /// `file_path` instead of memory
+    pub spilled: bool,
+
     /// Total number of bytes downloaded
     pub total_bytes: u64,
 
@@ -79,11 +104,20 @@ impl DownloadedData {
         Self {
             data: Some(data),
             file_path: None,
+            spilled: false,
             total_bytes,
             was_resumed: false,
         }
     }
 
+    /// Create a new `DownloadedData` for a capped memory download that spilled to `path`
+    pub fn new_spilled(path: PathBuf, total_bytes: u64) -> Self {
+        Self {
+            spilled: true,
+            ..Self::new_file(path, total_bytes, false)
+        }
+    }
+
     /// Create a new `DownloadedData` for file downloads
     ///
     /// # Arguments
@@ -95,6 +129,7 @@ impl DownloadedData {
         Self {
             data: None,
             file_path: Some(path),
+            spilled: false,
             total_bytes,
             was_resumed,
         }
@@ -117,4 +152,14 @@ impl DownloadedData {
     pub fn bytes(&self) -> Option<&Bytes> {
         self.data.as_ref()
     }
+
+    /// The downloaded bytes, if the content is in memory
+    pub fn into_bytes(self) -> Option<Bytes> {
+        self.data
+    }
+
+    /// Path of the downloaded file, if the content is on disk
+    pub fn into_path(self) -> Option<PathBuf> {
+        self.file_path
+    }
 }
@@ -0,0 +1,189 @@
+/// Pack container holding the small files of a crawl (`RecursiveConfig::small_file_threshold`)
+///
+/// The pack is an append-only sequence of records, each the content's length
+/// as a little-endian `u64` followed by the content. A JSON index next to it
+/// lists every record with its URL and the path the file would have been
+/// saved at.
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter};
+
+/// File name of the pack in the crawl's output directory
+pub const PACK_FILE_NAME: &str = "small-files.wgetf-pack";
+
+/// File name of the pack's JSON index in the crawl's output directory
+pub const PACK_INDEX_NAME: &str = "small-files.wgetf-pack.json";
+
+/// Size of the length prefix of each record
+const LENGTH_PREFIX: u64 = 8;
+
+/// A file stored in a pack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackEntry {
+    /// URL the content was downloaded from
+    pub url: String,
+
+    /// Where the file would have been saved, relative to the output directory
+    pub path: PathBuf,
+
+    /// Offset of the content in the pack (after its length prefix)
+    pub offset: u64,
+
+    /// Length of the content in bytes
+    pub length: u64,
+}
+
+/// Writes the pack of a crawl; the index is written by [`PackWriter::finish`]
+pub(crate) struct PackWriter {
+    output_dir: PathBuf,
+    file: BufWriter<tokio::fs::File>,
+    len: u64,
+    entries: Vec<PackEntry>,
+}
+
+impl PackWriter {
+    /// Start a new pack in `output_dir`, replacing any earlier one
+    pub(crate) async fn create(output_dir: &Path) -> Result<Self> {
+        tokio::fs::create_dir_all(output_dir).await?;
+        let file = tokio::fs::File::create(output_dir.join(PACK_FILE_NAME)).await?;
+        Ok(Self {
+            output_dir: output_dir.to_path_buf(),
+            file: BufWriter::new(file),
+            len: 0,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Append the content of `url`, which would have been saved at `local_path`
+    pub(crate) async fn append(
+        &mut self,
+        url: &str,
+        local_path: &Path,
+        content: &[u8],
+    ) -> Result<()> {
+        let length = content.len() as u64;
+        self.file.write_all(&length.to_le_bytes()).await?;
+        self.file.write_all(content).await?;
+        self.entries.push(PackEntry {
+            url: url.to_string(),
+            path: local_path
+                .strip_prefix(&self.output_dir)
+                .unwrap_or(local_path)
+                .to_path_buf(),
+            offset: self.len + LENGTH_PREFIX,
+            length,
+        });
+        self.len += LENGTH_PREFIX + length;
+        Ok(())
+    }
+
+    /// Flush the pack and write its index
+    pub(crate) async fn finish(mut self) -> Result<()> {
+        self.file.flush().await?;
+        let index = serde_json::to_vec_pretty(&self.entries)
+            .map_err(|e| Error::WriteError(format!("pack index: {e}")))?;
+        tokio::fs::write(self.output_dir.join(PACK_INDEX_NAME), index).await?;
+        tracing::info!(files = self.entries.len(), bytes = self.len, "Wrote small-file pack");
+        Ok(())
+    }
+}
+
+/// A pack written by a crawl, opened for reading
+#[derive(Debug, Clone)]
+pub struct Pack {
+    path: PathBuf,
+    entries: Vec<PackEntry>,
+}
+
+impl Pack {
+    /// Open the pack a crawl wrote to `output_dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be read or parsed.
+    pub async fn open(output_dir: &Path) -> Result<Self> {
+        let index = tokio::fs::read(output_dir.join(PACK_INDEX_NAME)).await?;
+        let entries = serde_json::from_slice(&index).map_err(|e| {
+            Error::IoError(io::Error::new(io::ErrorKind::InvalidData, format!("pack index: {e}")))
+        })?;
+        Ok(Self {
+            path: output_dir.join(PACK_FILE_NAME),
+            entries,
+        })
+    }
+
+    /// Files in the pack, in the order they were downloaded
+    pub fn entries(&self) -> &[PackEntry] {
+        &self.entries
+    }
+
+    /// The entry for `url`, if it was packed
+    pub fn find(&self, url: &str) -> Option<&PackEntry> {
+        self.entries.iter().find(|entry| entry.url == url)
+    }
+
+    /// Read the content of `entry`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pack can't be read or its length prefix doesn't
+    /// match the index.
+    pub async fn read(&self, entry: &PackEntry) -> Result<Vec<u8>> {
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        let start = entry.offset.checked_sub(LENGTH_PREFIX).ok_or_else(|| {
+            Error::IoError(io::Error::new(io::ErrorKind::InvalidData, "pack offset too small"))
+        })?;
+        file.seek(io::SeekFrom::Start(start)).await?;
+        let length = file.read_u64_le().await?;
+        if length != entry.length {
+            return Err(Error::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "pack record of {} has length {length}, index says {}",
+                    entry.url, entry.length
+                ),
+            )));
+        }
+        let mut content = vec![0; usize::try_from(length).unwrap_or(usize::MAX)];
+        file.read_exact(&mut content).await?;
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pack_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = PackWriter::create(dir.path()).await.unwrap();
+        writer
+            .append("http://example.com/a.txt", &dir.path().join("example.com/a.txt"), b"alpha")
+            .await
+            .unwrap();
+        writer
+            .append("http://example.com/empty", &dir.path().join("example.com/empty"), b"")
+            .await
+            .unwrap();
+        writer
+            .append("http://example.com/b/c.css", &dir.path().join("example.com/b/c.css"), b"p{}")
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+
+        let pack = Pack::open(dir.path()).await.unwrap();
+        assert_eq!(pack.entries().len(), 3);
+        let entry = pack.find("http://example.com/b/c.css").unwrap();
+        assert_eq!(entry.path, Path::new("example.com/b/c.css"));
+        assert_eq!(pack.read(entry).await.unwrap(), b"p{}");
+        assert_eq!(pack.read(&pack.entries()[0]).await.unwrap(), b"alpha");
+        assert_eq!(pack.read(&pack.entries()[1]).await.unwrap(), b"");
+
+        let mut corrupt = entry.clone();
+        corrupt.length = 4;
+        assert!(pack.read(&corrupt).await.is_err());
+    }
+}
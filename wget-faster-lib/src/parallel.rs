@@ -1,160 +1,447 @@
-use crate::{Error, HttpClient, ProgressCallback, ProgressInfo, Result};
+use crate::adaptive::ChunkConcurrency;
+use crate::stream::{backoff, is_transient};
+use crate::{
+    DownloadConfig, DownloadEvent, Error, HttpClient, ProgressCallback, ProgressInfo, Result,
+};
 use bytes::{Bytes, BytesMut};
-use std::sync::Arc;
-use std::time::Instant;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, CONTENT_ENCODING, ETAG, IF_MATCH, LAST_MODIFIED};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
-use tokio::sync::Mutex;
+use tokio::time::sleep;
 
-/// Download a chunk of data using HTTP Range request
-pub async fn download_chunk(client: &HttpClient, url: &str, start: u64, end: u64) -> Result<Bytes> {
-    let range_header = format!("bytes={start}-{end}");
+/// Smallest automatic chunk
+const MIN_CHUNK_SIZE: u64 = 1024 * 1024;
 
-    let response = client
-        .client()
-        .get(url)
-        .header(reqwest::header::RANGE, range_header)
-        .send()
-        .await?;
+/// Chunks per initial connection when the request count adapts, so some
+/// complete (and are measured) while others still wait
+const ADAPTIVE_ROUNDS: u64 = 4;
 
-    if !response.status().is_success() && response.status().as_u16() != 206 {
-        return Err(Error::InvalidStatus(response.status().as_u16()));
+/// Error context for a failure on the `index`th chunk (numbered from 1 in the message)
+pub(crate) fn chunk_context(action: &str, index: usize, url: &str) -> String {
+    format!("while {action} chunk {} of {url}", index + 1)
+}
+
+/// Checks that every chunk of a parallel download comes from the same version of the object
+///
+/// The first chunk response's `ETag` (or `Last-Modified` when it has none) is
+/// recorded and every other chunk response must repeat it, so CDN edges serving
+/// different versions during a deploy can't produce a silently mixed file.
+/// Its Content-Encoding must match too: ranges of a gzip body and of the plain
+/// body don't join up either.
+#[derive(Debug, Default)]
+pub(crate) struct ObjectIdentity {
+    /// Strong `ETag` from the probe, sent as `If-Match` so servers fail with 412 instead
+    if_match: Option<String>,
+    /// Validator of the first chunk response (`None` if it had neither header)
+    first: OnceLock<Option<String>>,
+}
+
+impl ObjectIdentity {
+    /// Identity check for chunks of the object described by the probe's `ETag`
+    pub(crate) fn new(probe_etag: Option<&str>) -> Self {
+        // A weak ETag never matches If-Match (RFC 9110 13.1.1), so only the check applies
+        let if_match = probe_etag
+            .filter(|etag| !etag.starts_with("W/"))
+            .map(str::to_string);
+        Self {
+            if_match,
+            first: OnceLock::new(),
+        }
     }
 
-    let bytes = response.bytes().await?;
-    Ok(bytes)
+    /// Compare a chunk response's validator against the first one seen
+    fn check(&self, headers: &HeaderMap, start: u64, end: u64) -> Result<()> {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let validator = header(ETAG)
+            .map(|etag| format!("ETag {etag}"))
+            .or_else(|| header(LAST_MODIFIED).map(|modified| format!("Last-Modified {modified}")));
+        let validator =
+            match header(CONTENT_ENCODING).filter(|c| !c.eq_ignore_ascii_case("identity")) {
+                Some(coding) => Some(format!(
+                    "{} with Content-Encoding {coding}",
+                    validator.unwrap_or_else(|| "no validator".into())
+                )),
+                None => validator,
+            };
+
+        let first = self.first.get_or_init(|| validator.clone());
+        if *first == validator {
+            return Ok(());
+        }
+        let describe = |v: &Option<String>| v.clone().unwrap_or_else(|| "no validator".into());
+        Err(Error::ObjectChangedDuringDownload(format!(
+            "chunk {start}-{end} has {}, earlier chunks had {}",
+            describe(&validator),
+            describe(first)
+        )))
+    }
 }
 
-/// Download file in parallel using multiple Range requests
-pub async fn download_parallel(
+/// Download a chunk, retrying transient failures with backoff (`RetryConfig`)
+///
+/// A retry resumes from the first byte not yet received instead of starting
+/// the chunk over.
+pub(crate) async fn download_chunk_with_retry(
     client: &HttpClient,
     url: &str,
-    total_size: u64,
-    progress_callback: Option<ProgressCallback>,
+    start: u64,
+    end: u64,
+    identity: &ObjectIdentity,
 ) -> Result<Bytes> {
-    let num_chunks = client.config().parallel_chunks;
+    let retry = &client.config().retry;
+    let mut data = BytesMut::new();
+    let mut attempt = 0;
+    loop {
+        match fetch_range(client, url, start, end, identity, &mut data).await {
+            Ok(()) => return Ok(data.freeze()),
+            Err(e) if attempt < retry.max_retries && is_transient(&e, retry) => {
+                attempt += 1;
+                let delay = backoff(retry, attempt);
+                tracing::warn!(
+                    url = %url,
+                    start,
+                    end,
+                    received = data.len(),
+                    attempt,
+                    delay_ms = delay.as_millis(),
+                    error = %e,
+                    "Chunk failed - resuming"
+                );
+                client.emit(|| DownloadEvent::RetryScheduled {
+                    attempt,
+                    delay,
+                    cause: e.to_string(),
+                });
+                sleep(delay).await;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Request bytes `start..=end` that aren't in `data` yet and append them to it
+///
+/// On failure `data` keeps what was received, so the next call resumes there.
+async fn fetch_range(
+    client: &HttpClient,
+    url: &str,
+    start: u64,
+    end: u64,
+    identity: &ObjectIdentity,
+    data: &mut BytesMut,
+) -> Result<()> {
+    let expected = end - start + 1;
+    let offset = start + data.len() as u64;
+    let range_header = format!("bytes={offset}-{end}");
+
+    let mut request = client.identity_encoding(
+        client
+            .client()
+            .get(url)
+            .header(reqwest::header::RANGE, range_header),
+    );
+    if let Some(etag) = &identity.if_match {
+        request = request.header(IF_MATCH, etag);
+    }
+    let response = crate::control::run(client.send(request)).await??;
 
-    // Calculate chunk size
-    let chunk_size = if let Some(size) = client.config().chunk_size {
-        size
-    } else {
-        // Auto-determine chunk size (minimum 1MB, maximum total_size / num_chunks)
-        std::cmp::max(1024 * 1024, total_size / num_chunks as u64)
-    };
+    if response.status() == reqwest::StatusCode::PRECONDITION_FAILED && identity.if_match.is_some()
+    {
+        return Err(Error::ObjectChangedDuringDownload(format!(
+            "server rejected If-Match for bytes {offset}-{end} (412)"
+        )));
+    }
+    if !response.status().is_success() && response.status().as_u16() != 206 {
+        return Err(Error::InvalidStatus(response.status().as_u16()));
+    }
 
+    // A 200 means the Range was ignored; only usable for a chunk starting at 0
+    if response.status().as_u16() != 206 && offset > 0 {
+        return Err(Error::ChunkError(format!(
+            "server ignored Range request for bytes {offset}-{end}"
+        )));
+    }
+
+    // Refuse to splice in a chunk of a different version of the object
+    identity.check(response.headers(), start, end)?;
+
+    // Enforce the chunk size strictly: the chunks are concatenated by offset
+    let mut body = response.bytes_stream();
+    let mut excess = 0;
+    while let Some(bytes) = crate::control::run(body.next()).await? {
+        let bytes = bytes?;
+        let remaining = usize::try_from(expected - data.len() as u64).unwrap_or(usize::MAX);
+        let kept = bytes.len().min(remaining);
+        excess += bytes.len() - kept;
+        client.quota().add(kept as u64)?;
+        data.extend_from_slice(&bytes[..kept]);
+    }
+    if excess > 0 {
+        tracing::warn!(start, end, excess, "Chunk longer than requested - excess discarded");
+    }
+    let received = data.len() as u64;
+    if received < expected {
+        return Err(Error::ChunkError(format!(
+            "chunk {start}-{end} was short: expected {expected} bytes, got {received}"
+        )));
+    }
+    Ok(())
+}
+
+/// Inclusive byte ranges splitting `total_size` into the configured chunks
+pub(crate) fn chunk_ranges(config: &DownloadConfig, total_size: u64) -> Vec<(u64, u64)> {
+    // Auto-determine chunk size (minimum 1MB, maximum total_size / num_chunks)
+    let chunk_size = config.chunk_size.unwrap_or_else(|| {
+        std::cmp::max(MIN_CHUNK_SIZE, total_size / config.parallel_chunks as u64)
+    });
+    split_evenly(total_size, chunk_size)
+}
+
+/// Inclusive byte ranges of `chunk_size` bytes covering `total_size`
+fn split_evenly(total_size: u64, chunk_size: u64) -> Vec<(u64, u64)> {
     let mut chunks = Vec::new();
     let mut start = 0u64;
-
     while start < total_size {
         let end = std::cmp::min(start + chunk_size - 1, total_size - 1);
         chunks.push((start, end));
         start = end + 1;
     }
+    chunks
+}
 
-    // Track progress
-    let downloaded = Arc::new(Mutex::new(0u64));
-    let start_time = Instant::now();
-
-    // Download chunks in parallel
-    let mut tasks = Vec::new();
+/// Ranges of a download whose request count adapts: smaller automatic chunks
+/// than [`chunk_ranges`], so there is something left to spread out
+fn adaptive_ranges(config: &DownloadConfig, total_size: u64) -> Vec<(u64, u64)> {
+    if config.chunk_size.is_some() || config.max_parallel_chunks <= config.parallel_chunks {
+        return chunk_ranges(config, total_size);
+    }
+    let chunks = config.parallel_chunks.max(1) as u64 * ADAPTIVE_ROUNDS;
+    split_evenly(total_size, std::cmp::max(MIN_CHUNK_SIZE, total_size / chunks))
+}
 
-    for (start, end) in chunks {
-        let client = client.clone();
-        let url = url.to_string();
-        let downloaded = Arc::clone(&downloaded);
-        let progress_callback = progress_callback.clone();
-        let url_for_progress = url.clone();
+/// Halve the largest of `pending` until there are `wanted` ranges, keeping
+/// every range at least `min_size` bytes and the list in offset order
+fn split_pending(pending: &mut VecDeque<(u64, u64)>, wanted: usize, min_size: u64) {
+    while pending.len() < wanted {
+        let Some((index, &(start, end))) = pending
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (start, end))| end - start)
+        else {
+            return;
+        };
+        let size = end - start + 1;
+        if size < 2 * min_size {
+            return;
+        }
+        let middle = start + size / 2;
+        pending[index] = (start, middle - 1);
+        pending.insert(index + 1, (middle, end));
+    }
+}
 
-        let task = tokio::spawn(async move {
-            let chunk_data = download_chunk(&client, &url, start, end).await?;
+/// A chunk fetch: its number, range, result, duration and the request count it started under
+type ChunkFetch<'a> = BoxFuture<'a, (usize, u64, Result<Bytes>, Duration, usize)>;
 
-            // Update progress
-            if let Some(callback) = progress_callback {
-                let mut downloaded_guard = downloaded.lock().await;
-                *downloaded_guard += chunk_data.len() as u64;
+/// Chunks of a parallel download, handed out in offset order
+///
+/// Up to [`ChunkConcurrency::current`] ranges are requested at once. When
+/// the count grows, ranges not requested yet are split so the extra
+/// connections have work (automatic chunk sizes only); when it shrinks, fewer
+/// new requests start. Chunks that arrive early wait in memory, at most about
+/// twice the request count of them.
+struct ChunkScheduler<'a> {
+    client: &'a HttpClient,
+    url: &'a str,
+    identity: &'a ObjectIdentity,
+    pending: VecDeque<(u64, u64)>,
+    splittable: bool,
+    concurrency: ChunkConcurrency,
+    in_flight: FuturesUnordered<ChunkFetch<'a>>,
+    arrived: BTreeMap<u64, Bytes>,
+    next_offset: u64,
+    started: usize,
+}
 
-                let mut progress = ProgressInfo::new(url_for_progress);
-                progress.total_size = Some(total_size);
-                progress.update(chunk_data.len() as u64, start_time);
-                progress.downloaded = *downloaded_guard;
+impl<'a> ChunkScheduler<'a> {
+    fn new(
+        client: &'a HttpClient,
+        url: &'a str,
+        total_size: u64,
+        identity: &'a ObjectIdentity,
+    ) -> Self {
+        let config = client.config();
+        Self {
+            client,
+            url,
+            identity,
+            pending: adaptive_ranges(config, total_size).into(),
+            splittable: config.chunk_size.is_none(),
+            concurrency: ChunkConcurrency::new(config.parallel_chunks, config.max_parallel_chunks),
+            in_flight: FuturesUnordered::new(),
+            arrived: BTreeMap::new(),
+            next_offset: 0,
+            started: 0,
+        }
+    }
 
-                callback(progress);
+    /// Next chunk in offset order, `None` once the download is complete
+    async fn next(&mut self) -> Option<Result<Bytes>> {
+        loop {
+            if let Some(chunk) = self.arrived.remove(&self.next_offset) {
+                self.next_offset += chunk.len() as u64;
+                return Some(Ok(chunk));
             }
+            self.start_requests();
 
-            Ok::<_, Error>((start, chunk_data))
-        });
-
-        tasks.push(task);
+            let (index, start, result, elapsed, started_at) = self.in_flight.next().await?;
+            let chunk = match result {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    return Some(Err(e.with_context(chunk_context("downloading", index, self.url))))
+                },
+            };
+            self.client.emit(|| DownloadEvent::ChunkCompleted {
+                index,
+                bytes: chunk.len() as u64,
+            });
+            if let Some(count) = self
+                .concurrency
+                .record(chunk.len() as u64, elapsed, started_at)
+            {
+                tracing::debug!(url = %self.url, requests = count, "Adjusting parallel chunk requests");
+                if self.splittable {
+                    split_pending(
+                        &mut self.pending,
+                        count.saturating_sub(self.in_flight.len()),
+                        MIN_CHUNK_SIZE,
+                    );
+                }
+            }
+            self.arrived.insert(start, chunk);
+        }
     }
 
-    // Wait for all chunks to complete
-    let mut results = Vec::new();
-    for task in tasks {
-        let result = task
-            .await
-            .map_err(|e| Error::ChunkError(format!("Task join error: {e}")))?
-            .map_err(|e| Error::ChunkError(format!("Chunk download failed: {e}")))?;
-        results.push(result);
+    /// Start requests until the current count is in flight
+    fn start_requests(&mut self) {
+        let count = self.concurrency.current();
+        while self.in_flight.len() < count && self.in_flight.len() + self.arrived.len() < 2 * count
+        {
+            let Some((start, end)) = self.pending.pop_front() else {
+                return;
+            };
+            let (client, url, identity) = (self.client, self.url, self.identity);
+            let index = self.started;
+            self.started += 1;
+            self.in_flight.push(Box::pin(async move {
+                let began = Instant::now();
+                let result = download_chunk_with_retry(client, url, start, end, identity).await;
+                (index, start, result, began.elapsed(), count)
+            }));
+        }
     }
+}
 
-    // Sort by start position
-    results.sort_by_key(|(start, _)| *start);
+/// Download file in parallel using multiple Range requests
+///
+/// The number of requests in flight adapts between 1 and `max_parallel_chunks`
+/// (see [`ChunkConcurrency`]).
+pub async fn download_parallel(
+    client: &HttpClient,
+    url: &str,
+    total_size: u64,
+    identity: ObjectIdentity,
+    progress_callback: Option<ProgressCallback>,
+) -> Result<Bytes> {
+    let mut progress = ProgressInfo::new(url.to_string());
+    progress.total_size = Some(total_size);
+    let start_time = Instant::now();
 
-    // Combine chunks
+    let mut chunks = ChunkScheduler::new(client, url, total_size, &identity);
     let mut combined = BytesMut::with_capacity(total_size as usize);
-    for (_, data) in results {
-        combined.extend_from_slice(&data);
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        combined.extend_from_slice(&chunk);
+
+        // Chunks come in offset order, so progress only ever grows
+        if let Some(callback) = &progress_callback {
+            progress.update(chunk.len() as u64, start_time);
+            callback(progress.clone());
+        }
     }
 
     Ok(combined.freeze())
 }
 
-/// Download to a writer in parallel
+/// Download to a writer in parallel, writing the chunks in offset order
 pub async fn download_parallel_to_writer<W>(
     client: &HttpClient,
     url: &str,
     total_size: u64,
+    identity: &ObjectIdentity,
     writer: &mut W,
     progress_callback: Option<ProgressCallback>,
 ) -> Result<()>
 where
     W: AsyncWriteExt + Unpin + Send,
 {
-    // For writers, we download sequentially to maintain order
-    // In a more advanced implementation, we could use a temp file for random writes
-
-    let num_chunks = client.config().parallel_chunks;
-    let chunk_size = if let Some(size) = client.config().chunk_size {
-        size
-    } else {
-        std::cmp::max(1024 * 1024, total_size / num_chunks as u64)
-    };
-
-    let downloaded = Arc::new(Mutex::new(0u64));
+    let mut progress = ProgressInfo::new(url.to_string());
+    progress.total_size = Some(total_size);
     let start_time = Instant::now();
 
-    let mut start = 0u64;
-    while start < total_size {
-        let end = std::cmp::min(start + chunk_size - 1, total_size - 1);
-
-        let chunk_data = download_chunk(client, url, start, end).await?;
-        writer.write_all(&chunk_data).await?;
+    let mut chunks = ChunkScheduler::new(client, url, total_size, identity);
+    let mut index = 0;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        writer
+            .write_all(&chunk)
+            .await
+            .map_err(|e| Error::from(e).with_context(chunk_context("writing", index, url)))?;
+        index += 1;
 
         // Update progress
         if let Some(callback) = &progress_callback {
-            let mut downloaded_guard = downloaded.lock().await;
-            *downloaded_guard += chunk_data.len() as u64;
-
-            let mut progress = ProgressInfo::new(url.to_string());
-            progress.total_size = Some(total_size);
-            progress.update(chunk_data.len() as u64, start_time);
-            progress.downloaded = *downloaded_guard;
-
-            callback(progress);
+            progress.update(chunk.len() as u64, start_time);
+            callback(progress.clone());
         }
-
-        start = end + 1;
     }
 
     writer.flush().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_pending_halves_largest() {
+        let mut pending = VecDeque::from([(0, 99), (100, 399)]);
+        split_pending(&mut pending, 5, 100);
+        // The 150-byte halves can't be split into 100-byte pieces
+        assert_eq!(pending, [(0, 99), (100, 249), (250, 399)]);
+    }
+
+    #[test]
+    fn test_adaptive_ranges_start_smaller() {
+        let config = DownloadConfig {
+            parallel_chunks: 2,
+            max_parallel_chunks: 8,
+            ..DownloadConfig::default()
+        };
+        let total = 16 * MIN_CHUNK_SIZE;
+        assert_eq!(chunk_ranges(&config, total).len(), 2);
+        assert_eq!(adaptive_ranges(&config, total).len(), 8);
+
+        let fixed = DownloadConfig {
+            max_parallel_chunks: 2,
+            ..config
+        };
+        assert_eq!(adaptive_ranges(&fixed, total).len(), 2);
+    }
+}
@@ -0,0 +1,141 @@
+/// File permission handling for downloaded files
+///
+/// Applies `DownloadConfig::file_mode` and `executable_if_content_type`
+/// once a file has been finalized. Only unix platforms support mode bits;
+/// elsewhere the settings are ignored with a debug log.
+use crate::{DownloadConfig, Result};
+use std::path::Path;
+
+/// Check whether a Content-Type matches one of the configured types
+///
+/// Comparison is case-insensitive and ignores parameters such as `charset`.
+fn content_type_matches(content_type: &str, types: &[String]) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase();
+
+    types
+        .iter()
+        .any(|t| t.trim().eq_ignore_ascii_case(&media_type))
+}
+
+/// Compute the mode to apply, if any
+///
+/// Starts from `file_mode` (or `current_mode` when unset) and adds execute
+/// bits for every class that can read the file when the content type matches.
+fn resolve_mode(
+    config: &DownloadConfig,
+    content_type: Option<&str>,
+    current_mode: u32,
+) -> Option<u32> {
+    let make_executable =
+        content_type.is_some_and(|ct| content_type_matches(ct, &config.executable_if_content_type));
+
+    if config.file_mode.is_none() && !make_executable {
+        return None;
+    }
+
+    let mut mode = config.file_mode.unwrap_or(current_mode) & 0o7777;
+    if make_executable {
+        // r bits (0o444) shifted down by two become x bits (0o111)
+        mode |= (mode & 0o444) >> 2;
+    }
+    Some(mode)
+}
+
+/// Apply the configured permission policy to a downloaded file
+#[cfg(unix)]
+pub(crate) fn apply_file_mode(
+    path: &Path,
+    config: &DownloadConfig,
+    content_type: Option<&str>,
+) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if config.file_mode.is_none() && config.executable_if_content_type.is_empty() {
+        return Ok(());
+    }
+
+    let current_mode = std::fs::metadata(path)?.permissions().mode();
+    if let Some(mode) = resolve_mode(config, content_type, current_mode) {
+        tracing::debug!(path = %path.display(), mode = format!("{mode:o}"), "Setting file mode");
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(())
+}
+
+/// Apply the configured permission policy to a downloaded file
+#[cfg(not(unix))]
+pub(crate) fn apply_file_mode(
+    path: &Path,
+    config: &DownloadConfig,
+    _content_type: Option<&str>,
+) -> Result<()> {
+    if config.file_mode.is_some() || !config.executable_if_content_type.is_empty() {
+        tracing::debug!(path = %path.display(), "File mode settings are ignored on this platform");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(file_mode: Option<u32>, types: &[&str]) -> DownloadConfig {
+        DownloadConfig {
+            file_mode,
+            executable_if_content_type: types.iter().map(|t| (*t).to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_content_type_matches() {
+        let types = vec!["application/x-executable".to_string()];
+        assert!(content_type_matches("application/x-executable", &types));
+        assert!(content_type_matches("Application/X-Executable; charset=binary", &types));
+        assert!(!content_type_matches("text/plain", &types));
+    }
+
+    #[test]
+    fn test_resolve_mode() {
+        assert_eq!(resolve_mode(&config(None, &[]), Some("text/plain"), 0o644), None);
+        assert_eq!(resolve_mode(&config(Some(0o600), &[]), None, 0o644), Some(0o600));
+
+        let exec = config(None, &["application/x-sh"]);
+        assert_eq!(resolve_mode(&exec, Some("application/x-sh"), 0o100_644), Some(0o755));
+        assert_eq!(resolve_mode(&exec, Some("text/html"), 0o644), None);
+
+        let both = config(Some(0o600), &["application/x-sh"]);
+        assert_eq!(resolve_mode(&both, Some("application/x-sh"), 0o644), Some(0o700));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("wgetf-perm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let secret = dir.join("secret.txt");
+        std::fs::write(&secret, b"x").unwrap();
+        apply_file_mode(&secret, &config(Some(0o600), &[]), Some("text/plain")).unwrap();
+        let mode = std::fs::metadata(&secret).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let script = dir.join("run.sh");
+        std::fs::write(&script, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o644)).unwrap();
+        apply_file_mode(&script, &config(None, &["application/x-sh"]), Some("application/x-sh"))
+            .unwrap();
+        let mode = std::fs::metadata(&script).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
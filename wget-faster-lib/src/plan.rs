@@ -0,0 +1,306 @@
+/// Download planning (dry-run support)
+///
+/// A [`DownloadPlan`] describes what a file download would do without
+/// writing anything: whether it would start fresh, resume, or be skipped,
+/// which conditional headers would be sent, and whether parallel range
+/// requests would be used.
+use crate::timestamping::{LocalFileInfo, PartialFileAction, RemoteInfo};
+use crate::{client::ResourceMetadata, config::HttpMethod, DownloadConfig, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Decision a download would take for its target file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanAction {
+    /// Download the whole resource
+    Download,
+
+    /// Resume from the given byte offset
+    Resume {
+        /// Offset the Range request would start at
+        from: u64,
+    },
+
+    /// Skip the download entirely
+    Skip {
+        /// Why the download would be skipped
+        reason: String,
+    },
+}
+
+/// Planned download of a single URL
+#[derive(Debug, Clone)]
+pub struct DownloadPlan {
+    /// URL that would be downloaded
+    pub url: String,
+
+    /// Local file the download would write to
+    pub target: PathBuf,
+
+    /// HTTP method that would be used
+    pub method: HttpMethod,
+
+    /// What the download would do with the target
+    pub action: PlanAction,
+
+    /// Conditional or range headers that would be sent (name, value)
+    pub conditional_headers: Vec<(String, String)>,
+
+    /// Number of parallel range requests, or `None` for a sequential download
+    pub parallel_chunks: Option<usize>,
+
+    /// Metadata from the probe request (`None` when planned offline)
+    pub metadata: Option<ResourceMetadata>,
+
+    /// Whether the target location is writable
+    pub writable: bool,
+}
+
+impl DownloadPlan {
+    /// Build a plan from configuration, the target path, and optional probe metadata
+    pub(crate) async fn build(
+        config: &DownloadConfig,
+        url: &str,
+        target: &Path,
+        metadata: Option<ResourceMetadata>,
+    ) -> Result<Self> {
+        let exists = target.exists();
+
+        let partial = partial_file_action(config, target, metadata.as_ref()).await?;
+        let resume_partial = match partial {
+            Some(PartialFileAction::Resume(from)) => Some(from),
+            _ => None,
+        };
+        let resume_from = crate::downloader::resume_offset(
+            config,
+            target,
+            resume_partial,
+            crate::downloader::replaces_existing(config),
+        )
+        .await?;
+
+        let mut action = if resume_from > 0 {
+            PlanAction::Resume { from: resume_from }
+        } else {
+            PlanAction::Download
+        };
+
+        let mut conditional_headers = Vec::new();
+        if resume_from > 0 {
+            conditional_headers.push(("Range".to_string(), format!("bytes={resume_from}-")));
+        }
+
+        if partial == Some(PartialFileAction::Skip) {
+            action = PlanAction::Skip {
+                reason: "local file is complete".to_string(),
+            };
+        } else if config.timestamping && exists && resume_from == 0 {
+            if let Some(ref metadata) = metadata {
+                let (ts_action, _) = crate::timestamping::check_timestamp(
+                    target,
+                    metadata,
+                    config.timestamping_size_check,
+                )
+                .await?;
+                if ts_action == crate::timestamping::TimestampAction::Skip {
+                    action = PlanAction::Skip {
+                        reason: "local file is up to date".to_string(),
+                    };
+                }
+            }
+            if config.if_modified_since {
+                let modified = tokio::fs::metadata(target).await?.modified()?;
+                conditional_headers
+                    .push(("If-Modified-Since".to_string(), httpdate::fmt_http_date(modified)));
+            }
+            if config.use_etag {
+                if let Some(etag) = crate::timestamping::read_etag(target).await {
+                    conditional_headers.push(("If-None-Match".to_string(), etag));
+                }
+            }
+        }
+
+        let parallel_chunks = metadata.as_ref().and_then(|m| {
+            let large = m
+                .content_length
+                .is_some_and(|len| len > config.parallel_threshold);
+            (m.supports_range && resume_from == 0 && large && config.parallel_chunks > 1)
+                .then_some(config.parallel_chunks)
+        });
+
+        Ok(Self {
+            url: url.to_string(),
+            target: target.to_path_buf(),
+            method: config.method,
+            action,
+            conditional_headers,
+            parallel_chunks,
+            metadata,
+            writable: crate::storage::is_writable(target),
+        })
+    }
+}
+
+/// What `-N -c` would do with an existing target, decided from the probe
+async fn partial_file_action(
+    config: &DownloadConfig,
+    target: &Path,
+    metadata: Option<&ResourceMetadata>,
+) -> Result<Option<PartialFileAction>> {
+    let Some(metadata) = metadata else {
+        return Ok(None);
+    };
+    let continues =
+        config.timestamping && config.timestamping_continue && config.start_pos.is_none();
+    if !continues || !target.exists() {
+        return Ok(None);
+    }
+    let local = LocalFileInfo::from_metadata(&tokio::fs::metadata(target).await?)?;
+    Ok(Some(PartialFileAction::decide(&local, &RemoteInfo::from_metadata(metadata))))
+}
+
+impl fmt::Display for DownloadPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}: ", self.url, self.target.display())?;
+
+        match &self.action {
+            PlanAction::Download => write!(f, "download")?,
+            PlanAction::Resume { from } => write!(f, "resume from {from}")?,
+            PlanAction::Skip { reason } => write!(f, "skip ({reason})")?,
+        }
+
+        if let Some(chunks) = self.parallel_chunks {
+            write!(f, ", parallel x{chunks}")?;
+        }
+        if let Some(len) = self.metadata.as_ref().and_then(|m| m.content_length) {
+            write!(f, ", {len} bytes")?;
+        }
+
+        write!(f, " [{}", self.method.as_str())?;
+        for (name, value) in &self.conditional_headers {
+            write!(f, "; {name}: {value}")?;
+        }
+        write!(f, "]")?;
+
+        if !self.writable {
+            write!(f, " (not writable)")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(content_length: u64, supports_range: bool) -> ResourceMetadata {
+        ResourceMetadata {
+            supports_range,
+            content_length: Some(content_length),
+            last_modified: None,
+            etag: None,
+            content_type: None,
+            content_encoding: None,
+            content_disposition: None,
+            status_code: 200,
+            headers: reqwest::header::HeaderMap::new(),
+            auth_succeeded: false,
+            final_url: None,
+            allow: None,
+            accept_ranges_unit: None,
+            cache_control: None,
+            age: None,
+            content_language: None,
+            links: Vec::new(),
+            tls_info: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offline_plan_new_file() {
+        let dir = std::env::temp_dir().join(format!("wgetf-plan-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("file.bin");
+
+        let config = DownloadConfig::default();
+        let plan = DownloadPlan::build(&config, "http://example.com/file.bin", &target, None)
+            .await
+            .unwrap();
+
+        assert_eq!(plan.action, PlanAction::Download);
+        assert!(plan.conditional_headers.is_empty());
+        assert_eq!(plan.parallel_chunks, None);
+        assert!(plan.writable);
+        assert_eq!(
+            plan.to_string(),
+            format!("http://example.com/file.bin -> {}: download [GET]", target.display())
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plan_resume_and_parallel() {
+        let dir = std::env::temp_dir().join(format!("wgetf-plan-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let partial = dir.join("partial.bin");
+        std::fs::write(&partial, vec![0u8; 100]).unwrap();
+        let config = DownloadConfig::default();
+        let plan = DownloadPlan::build(&config, "http://h/partial.bin", &partial, None)
+            .await
+            .unwrap();
+        assert_eq!(plan.action, PlanAction::Resume { from: 100 });
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "http://h/partial.bin -> {}: resume from 100 [GET; Range: bytes=100-]",
+                partial.display()
+            )
+        );
+
+        // Like the download itself, -O truncates instead of resuming
+        let replacing = DownloadConfig {
+            overwrite_existing: true,
+            ..Default::default()
+        };
+        let plan = DownloadPlan::build(&replacing, "http://h/partial.bin", &partial, None)
+            .await
+            .unwrap();
+        assert_eq!(plan.action, PlanAction::Download);
+        assert!(plan.conditional_headers.is_empty());
+
+        // A file saved with its headers resumes after them
+        let with_headers = dir.join("headers.bin");
+        std::fs::write(&with_headers, "HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nbody").unwrap();
+        let saving_headers = DownloadConfig {
+            save_headers: true,
+            ..Default::default()
+        };
+        let plan = DownloadPlan::build(&saving_headers, "http://h/h.bin", &with_headers, None)
+            .await
+            .unwrap();
+        assert_eq!(plan.action, PlanAction::Resume { from: 4 });
+
+        let big = dir.join("big.bin");
+        let plan = DownloadPlan::build(
+            &config,
+            "http://h/big.bin",
+            &big,
+            Some(metadata(50 * 1024 * 1024, true)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(plan.parallel_chunks, Some(config.parallel_chunks));
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "http://h/big.bin -> {}: download, parallel x8, 52428800 bytes [GET]",
+                big.display()
+            )
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
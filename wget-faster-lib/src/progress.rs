@@ -1,6 +1,29 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+/// Window over which `speed_average` is computed
+const SPEED_WINDOW: Duration = Duration::from_secs(5);
+
+/// Minimum span for `speed_instant`, so back-to-back chunks don't spike it
+const INSTANT_MIN_SPAN: Duration = Duration::from_millis(250);
+
+/// Longest ETA reported (99 hours)
+const MAX_ETA: Duration = Duration::from_hours(99);
+
+/// What a download is doing when progress is reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransferPhase {
+    /// Receiving the body
+    #[default]
+    Downloading,
+
+    /// Copying a finished download from `DownloadConfig::staging_dir` to its destination
+    ///
+    /// `downloaded` and `total_size` count the bytes copied.
+    Finalizing,
+}
+
 /// Progress information for a download
 #[derive(Debug, Clone)]
 pub struct ProgressInfo {
@@ -10,10 +33,22 @@ pub struct ProgressInfo {
     /// Downloaded bytes so far
     pub downloaded: u64,
 
-    /// Download speed in bytes per second
+    /// Download speed in bytes per second (same as `speed_average`)
+    #[deprecated(note = "use `speed_average`, or `speed_instant` and `speed_peak`")]
     pub speed: f64,
 
-    /// Estimated time remaining (None if unknown)
+    /// Speed over the last fraction of a second, in bytes per second
+    pub speed_instant: f64,
+
+    /// Speed averaged over the last 5 seconds, in bytes per second
+    ///
+    /// Used for display and the ETA.
+    pub speed_average: f64,
+
+    /// Highest `speed_instant` seen so far, in bytes per second
+    pub speed_peak: f64,
+
+    /// Estimated time remaining (None if unknown), capped at 99 hours
     pub eta: Option<Duration>,
 
     /// Elapsed time since download started
@@ -21,18 +56,30 @@ pub struct ProgressInfo {
 
     /// Current URL being downloaded
     pub url: String,
+
+    /// Whether the body is being received or a staged file moved into place
+    pub phase: TransferPhase,
+
+    /// Recent `(elapsed, downloaded)` samples for the rolling speeds
+    samples: VecDeque<(Duration, u64)>,
 }
 
 impl ProgressInfo {
     /// Create new progress tracker for a URL
+    #[allow(deprecated)]
     pub fn new(url: String) -> Self {
         Self {
             total_size: None,
             downloaded: 0,
             speed: 0.0,
+            speed_instant: 0.0,
+            speed_average: 0.0,
+            speed_peak: 0.0,
             eta: None,
             elapsed: Duration::ZERO,
             url,
+            phase: TransferPhase::Downloading,
+            samples: VecDeque::new(),
         }
     }
 
@@ -48,28 +95,69 @@ impl ProgressInfo {
     }
 
     /// Update progress with new downloaded bytes
+    ///
+    /// Keep one `ProgressInfo` per download and call this for every chunk:
+    /// the speeds are computed from the samples of previous calls.
     pub fn update(&mut self, new_bytes: u64, start_time: Instant) {
+        self.update_at(new_bytes, start_time.elapsed());
+    }
+
+    /// Update progress with new bytes received at `elapsed` since the start
+    pub fn update_at(&mut self, new_bytes: u64, elapsed: Duration) {
+        if self.samples.is_empty() {
+            // Baseline: what was already there (e.g. resumed bytes) at the start
+            self.samples.push_back((Duration::ZERO, self.downloaded));
+        }
         self.downloaded += new_bytes;
-        self.elapsed = start_time.elapsed();
+        self.elapsed = elapsed;
+        self.samples.push_back((elapsed, self.downloaded));
 
-        // Calculate speed (bytes per second)
-        if self.elapsed.as_secs_f64() > 0.0 {
-            self.speed = self.downloaded as f64 / self.elapsed.as_secs_f64();
+        // Keep one sample at or before the window start as the baseline
+        let window_start = elapsed.saturating_sub(SPEED_WINDOW);
+        while self.samples.len() > 2 && self.samples[1].0 <= window_start {
+            self.samples.pop_front();
         }
 
-        // Calculate ETA
-        if let Some(total) = self.total_size {
-            if self.speed > 0.0 && self.downloaded < total {
-                let remaining_bytes = total - self.downloaded;
-                let eta_secs = remaining_bytes as f64 / self.speed;
-                self.eta = Some(Duration::from_secs_f64(eta_secs));
+        if let Some(speed) = self.rate_since(window_start) {
+            self.speed_average = speed;
+            #[allow(deprecated)]
+            {
+                self.speed = speed;
             }
         }
+        if let Some(speed) = self.rate_since(elapsed.saturating_sub(INSTANT_MIN_SPAN)) {
+            self.speed_instant = speed;
+            self.speed_peak = self.speed_peak.max(speed);
+        }
+
+        self.eta = self.total_size.and_then(|total| {
+            if self.downloaded >= total {
+                Some(Duration::ZERO)
+            } else if self.speed_average > 0.0 {
+                let eta_secs = (total - self.downloaded) as f64 / self.speed_average;
+                Some(Duration::from_secs_f64(eta_secs.min(MAX_ETA.as_secs_f64())))
+            } else {
+                None
+            }
+        });
     }
 
-    /// Format speed in human-readable format (KB/s, MB/s, etc.)
+    /// Average rate from the newest sample at or before `since` to now
+    fn rate_since(&self, since: Duration) -> Option<f64> {
+        let &(now, downloaded) = self.samples.back()?;
+        let &(then, before) = self
+            .samples
+            .iter()
+            .rev()
+            .find(|(t, _)| *t <= since)
+            .or_else(|| self.samples.front())?;
+        let span = now.saturating_sub(then).as_secs_f64();
+        (span > 0.0).then(|| downloaded.saturating_sub(before) as f64 / span)
+    }
+
+    /// Format the rolling average speed in human-readable format (KB/s, MB/s, etc.)
     pub fn format_speed(&self) -> String {
-        format_bytes_per_sec(self.speed)
+        format_bytes_per_sec(self.speed_average)
     }
 
     /// Format downloaded size in human-readable format
@@ -257,12 +345,95 @@ mod tests {
         assert_eq!(format_duration(Duration::from_secs(3661)), "1h 1m 1s");
     }
 
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_steady_transfer() {
+        let mut progress = ProgressInfo::new("https://example.com/f".to_string());
+        progress.total_size = Some(100_000);
+
+        // 1000 bytes every 100ms = 10 KB/s
+        for i in 1..=50 {
+            progress.update_at(1000, ms(i * 100));
+        }
+
+        assert!((progress.speed_average - 10_000.0).abs() < 1.0);
+        assert!((progress.speed_instant - 10_000.0).abs() < 1.0);
+        assert!((progress.speed_peak - 10_000.0).abs() < 1.0);
+        #[allow(deprecated)]
+        let speed = progress.speed;
+        assert!((speed - progress.speed_average).abs() < f64::EPSILON);
+        // 50 KB left at 10 KB/s
+        assert_eq!(progress.eta, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_burst_then_stall() {
+        let mut progress = ProgressInfo::new("https://example.com/f".to_string());
+        progress.total_size = Some(1_000_000);
+
+        // 1 second at 100 KB/s
+        for i in 1..=10 {
+            progress.update_at(10_000, ms(i * 100));
+        }
+        let peak = progress.speed_peak;
+        assert!((peak - 100_000.0).abs() < 1.0);
+
+        // Stall: zero-byte updates every 500ms; speeds and ETA must move monotonically
+        let mut last_average = progress.speed_average;
+        let mut last_eta = progress.eta.unwrap();
+        for i in 1..=12 {
+            progress.update_at(0, ms(1000 + i * 500));
+            assert!(progress.speed_average <= last_average);
+            if let Some(eta) = progress.eta {
+                assert!(eta >= last_eta);
+                last_eta = eta;
+            }
+            last_average = progress.speed_average;
+        }
+        assert_eq!(progress.speed_instant, 0.0);
+        assert!(progress.speed_average.abs() < f64::EPSILON);
+        assert_eq!(progress.eta, None);
+        assert!((progress.speed_peak - peak).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_resumed_transfer_ignores_existing_bytes() {
+        let mut progress = ProgressInfo::new("https://example.com/f".to_string());
+        progress.total_size = Some(1_000_000);
+        progress.downloaded = 900_000;
+
+        for i in 1..=10 {
+            progress.update_at(1000, ms(i * 100));
+        }
+
+        assert_eq!(progress.downloaded, 910_000);
+        assert!((progress.speed_average - 10_000.0).abs() < 1.0);
+        assert_eq!(progress.eta, Some(Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn test_eta_clamped() {
+        let mut progress = ProgressInfo::new("https://example.com/f".to_string());
+        progress.total_size = Some(u64::MAX / 2);
+        progress.update_at(1, Duration::from_secs(1));
+        assert_eq!(progress.eta, Some(MAX_ETA));
+
+        // Overshooting the declared size never yields a negative ETA
+        let mut progress = ProgressInfo::new("https://example.com/f".to_string());
+        progress.total_size = Some(10);
+        progress.update_at(20, Duration::from_secs(1));
+        assert_eq!(progress.eta, Some(Duration::ZERO));
+    }
+
     #[test]
     fn test_wget_style_format() {
         let mut progress = ProgressInfo::new("https://example.com/file.zip".to_string());
         progress.total_size = Some(10 * 1024 * 1024); // 10MB
         progress.downloaded = 5 * 1024 * 1024; // 5MB
-        progress.speed = 1.5 * 1024.0 * 1024.0; // 1.5MB/s
+        progress.speed_average = 1.5 * 1024.0 * 1024.0; // 1.5MB/s
         progress.eta = Some(Duration::from_secs(3));
 
         let output = progress.format_wget_style();
@@ -278,7 +449,7 @@ mod tests {
         let mut progress = ProgressInfo::new("https://example.com/file.zip".to_string());
         progress.total_size = Some(10 * 1024 * 1024); // 10MB
         progress.downloaded = 5 * 1024 * 1024; // 5MB
-        progress.speed = 1.5 * 1024.0 * 1024.0; // 1.5MB/s
+        progress.speed_average = 1.5 * 1024.0 * 1024.0; // 1.5MB/s
         progress.eta = Some(Duration::from_secs(3));
 
         let output = progress.format_compact();
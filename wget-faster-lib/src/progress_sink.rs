@@ -0,0 +1,201 @@
+/// Rate-limited progress delivery that never blocks a download
+///
+/// A `ProgressCallback` runs inline for every chunk, so a slow callback slows
+/// the transfer and a fast link calls it thousands of times per second. A
+/// `ProgressSink` (`DownloadConfig::progress_sink`) instead offers updates to a
+/// bounded channel, at most `max_rate` times per second per download. An update
+/// that finds the channel full is dropped, never waited for. The last update of
+/// each download is always delivered (by a separate task once the channel has
+/// room), so consumers see the final byte count.
+///
+/// Parallel downloads feed the sink their aggregated progress, like the
+/// callback. [`ProgressSink::from_callback`] runs an existing callback on its
+/// own task, off the download's path.
+use crate::{ProgressCallback, ProgressInfo};
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Updates per second offered by default (the final one is always extra)
+pub const DEFAULT_PROGRESS_RATE: f64 = 10.0;
+
+/// Channel capacity used by [`ProgressSink::from_callback`]
+const CALLBACK_CHANNEL_CAPACITY: usize = 16;
+
+/// Bounded, rate-limited receiver of progress updates
+#[derive(Clone)]
+pub struct ProgressSink {
+    sender: mpsc::Sender<ProgressInfo>,
+    min_interval: Duration,
+}
+
+impl fmt::Debug for ProgressSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressSink")
+            .field("min_interval", &self.min_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProgressSink {
+    /// Sink offering updates to `sender` at `DEFAULT_PROGRESS_RATE`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wget_faster_lib::{DownloadConfig, ProgressSink};
+    ///
+    /// let (sender, mut receiver) = tokio::sync::mpsc::channel(8);
+    /// let config = DownloadConfig {
+    ///     progress_sink: Some(ProgressSink::new(sender).with_max_rate(4.0)),
+    ///     ..DownloadConfig::default()
+    /// };
+    /// // Another task: while let Some(info) = receiver.recv().await { ... }
+    /// # let _ = (config, receiver.try_recv());
+    /// ```
+    pub fn new(sender: mpsc::Sender<ProgressInfo>) -> Self {
+        Self {
+            sender,
+            min_interval: Duration::from_secs_f64(1.0 / DEFAULT_PROGRESS_RATE),
+        }
+    }
+
+    /// Offer at most `per_second` updates per second (no limit if not positive and finite)
+    #[must_use]
+    pub fn with_max_rate(mut self, per_second: f64) -> Self {
+        self.min_interval = if per_second.is_finite() && per_second > 0.0 {
+            Duration::from_secs_f64(1.0 / per_second)
+        } else {
+            Duration::ZERO
+        };
+        self
+    }
+
+    /// Sink calling `callback` from a dedicated task that consumes the channel
+    ///
+    /// The task ends once every clone of the sink (and the config holding it)
+    /// is dropped and the remaining updates are delivered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn from_callback(callback: ProgressCallback) -> Self {
+        let (sender, mut receiver) = mpsc::channel(CALLBACK_CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(info) = receiver.recv().await {
+                callback(info);
+            }
+        });
+        Self::new(sender)
+    }
+
+    /// Per-download state: each download is rate-limited and finished on its own
+    pub(crate) fn feed(&self) -> SinkFeed {
+        SinkFeed {
+            sink: self.clone(),
+            state: Mutex::new(FeedState::default()),
+        }
+    }
+}
+
+/// One download's updates to a `ProgressSink`
+///
+/// Dropping it (when the download is done with its progress callback) sends
+/// the last update if it wasn't already.
+pub(crate) struct SinkFeed {
+    sink: ProgressSink,
+    state: Mutex<FeedState>,
+}
+
+#[derive(Default)]
+struct FeedState {
+    last_sent: Option<Instant>,
+    unsent: Option<ProgressInfo>,
+}
+
+impl SinkFeed {
+    /// Offer an update: sent if the rate allows and the channel has room
+    pub(crate) fn offer(&self, info: ProgressInfo) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let due = state
+            .last_sent
+            .is_none_or(|last| now.duration_since(last) >= self.sink.min_interval);
+        if !due {
+            state.unsent = Some(info);
+            return;
+        }
+        match self.sink.sender.try_send(info) {
+            Ok(()) => {
+                state.last_sent = Some(now);
+                state.unsent = None;
+            },
+            Err(mpsc::error::TrySendError::Full(info)) => state.unsent = Some(info),
+            Err(mpsc::error::TrySendError::Closed(_)) => state.unsent = None,
+        }
+    }
+}
+
+impl Drop for SinkFeed {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let Some(info) = state.unsent.take() else {
+            return;
+        };
+        if let Err(mpsc::error::TrySendError::Full(info)) = self.sink.sender.try_send(info) {
+            // Wait for room without holding up the download
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let sender = self.sink.sender.clone();
+                runtime.spawn(async move {
+                    let _ = sender.send(info).await;
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(downloaded: u64) -> ProgressInfo {
+        let mut info = ProgressInfo::new("http://example.com/".to_string());
+        info.downloaded = downloaded;
+        info
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_keeps_first_and_last() {
+        let (sender, mut receiver) = mpsc::channel(8);
+        let feed = ProgressSink::new(sender.clone()).with_max_rate(1.0).feed();
+        for downloaded in 1..=100 {
+            feed.offer(update(downloaded));
+        }
+        drop(feed);
+        drop(sender);
+
+        let mut received = Vec::new();
+        while let Some(info) = receiver.recv().await {
+            received.push(info.downloaded);
+        }
+        assert_eq!(received, [1, 100]);
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_drops_but_delivers_last() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let feed = ProgressSink::new(sender.clone()).with_max_rate(0.0).feed();
+        for downloaded in 1..=5 {
+            feed.offer(update(downloaded));
+        }
+        drop(feed);
+        drop(sender);
+
+        let mut received = Vec::new();
+        while let Some(info) = receiver.recv().await {
+            received.push(info.downloaded);
+        }
+        assert_eq!(received, [1, 5]);
+    }
+}
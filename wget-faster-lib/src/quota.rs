@@ -0,0 +1,66 @@
+/// Download quota (`DownloadConfig::quota`) shared by every download of a client
+///
+/// Bytes are counted as bodies arrive, including every chunk of a parallel
+/// download and the robots.txt files a crawl reads, and the count carries over from one download to the next for the
+/// life of the `Downloader`. As with wget, an exceeded quota stops new
+/// downloads from starting while the ones already running finish; with
+/// `quota_hard` they are cut off as soon as the quota is passed.
+use crate::{DownloadConfig, Error, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug)]
+pub(crate) struct Quota {
+    /// Bytes allowed (`None` for unlimited)
+    limit: Option<u64>,
+
+    /// Abort downloads in progress once the quota is passed
+    hard: bool,
+
+    /// Body bytes received so far
+    used: AtomicU64,
+}
+
+impl Quota {
+    pub(crate) fn new(config: &DownloadConfig) -> Self {
+        Self {
+            limit: config.quota,
+            hard: config.quota_hard,
+            used: AtomicU64::new(0),
+        }
+    }
+
+    /// Body bytes received so far
+    pub(crate) fn used(&self) -> u64 {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// Whether the quota is used up
+    pub(crate) fn is_exceeded(&self) -> bool {
+        self.limit.is_some_and(|limit| self.used() >= limit)
+    }
+
+    /// Fail with `Error::QuotaExceeded` if the quota is used up, before starting a download
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.limit {
+            Some(limit) if self.is_exceeded() => Err(Error::QuotaExceeded(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Count `bytes` of a body kept whatever the quota, returning the new total
+    pub(crate) fn record(&self, bytes: u64) -> u64 {
+        self.used.fetch_add(bytes, Ordering::SeqCst) + bytes
+    }
+
+    /// Count `bytes` of body received
+    ///
+    /// With `quota_hard`, fails once the total passes the quota: the chunk
+    /// that crossed it is not to be kept.
+    pub(crate) fn add(&self, bytes: u64) -> Result<()> {
+        let used = self.record(bytes);
+        match self.limit {
+            Some(limit) if self.hard && used > limit => Err(Error::QuotaExceeded(limit)),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,12 +1,35 @@
 /// Recursive download functionality for downloading entire websites
-use crate::{DownloadConfig, Downloader, Error, LinkConverter, Result};
+#[cfg(feature = "archive")]
+use crate::archive::{ArchiveFormat, ArchiveWriter};
+use crate::file_handles::FileHandles;
+#[cfg(feature = "pack")]
+use crate::pack::PackWriter;
+use crate::request_hints::RequestHints;
+use crate::robots::RobotsDirectives;
+use crate::stream::{is_transient, retry_delay};
+use crate::url_dedupe::UrlDeduper;
+use crate::url_interner::UrlInterner;
+use crate::{
+    ConversionMode, DirectoryLayout, DownloadConfig, Downloader, Error, FormLogin, LinkConverter,
+    LinkRelation, PostProcessor, RequestKind, ResourceMetadata, ResponseFilter, Result, Sitemap,
+    SitemapEntry, MAX_SITEMAP_DEPTH,
+};
+use chrono::{DateTime, Utc};
+use regex::Regex;
 use scraper::{Html, Selector};
-use std::collections::{HashMap, HashSet, VecDeque};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use url::Url;
 
 /// Configuration for recursive downloads
-#[derive(Debug, Clone)]
+///
+/// Serializes like [`DownloadConfig`], with the form login password masked.
+#[derive(Debug, Clone, Serialize)]
 pub struct RecursiveConfig {
     /// Maximum recursion depth (0 = infinite)
     pub max_depth: usize,
@@ -20,6 +43,9 @@ pub struct RecursiveConfig {
     /// Convert links for local viewing
     pub convert_links: bool,
 
+    /// How `convert_links` rewrites links (`FileOnly` for --convert-file-only)
+    pub conversion_mode: ConversionMode,
+
     /// Backup original files before converting (with -K flag)
     pub backup_converted: bool,
 
@@ -27,6 +53,9 @@ pub struct RecursiveConfig {
     pub adjust_extension: bool,
 
     /// Download page requisites (images, CSS, JS)
+    ///
+    /// Requisites are fetched even past `max_depth`, like wget -p, along with
+    /// what their stylesheets reference.
     pub page_requisites: bool,
 
     /// Accepted file extensions (empty = all)
@@ -35,6 +64,16 @@ pub struct RecursiveConfig {
     /// Rejected file extensions
     pub reject_extensions: Vec<String>,
 
+    /// Only follow links whose full URL matches this regex (`--accept-regex`)
+    ///
+    /// Uses the [`regex`](https://docs.rs/regex) crate syntax, which includes
+    /// POSIX bracket classes such as `[[:digit:]]`. The starting URL is always
+    /// downloaded.
+    pub accept_regex: Option<String>,
+
+    /// Don't follow links whose full URL matches this regex (`--reject-regex`)
+    pub reject_regex: Option<String>,
+
     /// Accepted domains
     pub accepted_domains: Vec<String>,
 
@@ -56,11 +95,112 @@ pub struct RecursiveConfig {
     /// Spider mode - only check links, don't download files
     pub spider: bool,
 
+    /// Delete each saved file once its links are extracted (`--delete-after`)
+    ///
+    /// Directories left empty are removed too, so the crawl still fetches and
+    /// follows everything but leaves nothing behind (e.g. to warm a proxy
+    /// cache). `download_recursive` then returns no files; the URLs fetched
+    /// are in [`fetched_urls`](RecursiveDownloader::fetched_urls). Disables
+    /// `convert_links`.
+    pub delete_after: bool,
+
     /// Log rejected URLs to a file
     pub rejected_log: Option<PathBuf>,
 
     /// Don't create directories (save all files in output directory)
     pub no_directories: bool,
+
+    /// Number of leading remote directories to drop (after the host directory)
+    pub cut_dirs: usize,
+
+    /// Save under a directory named after the scheme, above the host directory
+    pub protocol_directories: bool,
+
+    /// Custom local path of each crawled URL, asked before the layout options
+    ///
+    /// Returning `None` keeps the default path. A relative path is taken
+    /// relative to the output directory; a path leaving it (through `..` or
+    /// an absolute path elsewhere) is ignored with a warning. The path is
+    /// final: `adjust_extension` and filename truncation don't apply to it.
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub path_mapper: Option<PathMapper>,
+
+    /// Log in through an HTML form before crawling (session cookies are shared with the crawl)
+    pub form_login: Option<FormLogin>,
+
+    /// Custom rewriter run on each file after link conversion (with `convert_links`)
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub post_processor: Option<PostProcessor>,
+
+    /// Initial delay before retrying a robots.txt that failed transiently (doubles per failure)
+    #[serde(serialize_with = "crate::config_serde::duration")]
+    pub robots_retry_delay: Duration,
+
+    /// Ignore robots.txt `Crawl-delay` (`wait_time` and `random_wait` still
+    /// space out requests to one host)
+    pub ignore_robots_delay: bool,
+
+    /// Query parameters ignored when deciding whether a URL was already visited
+    /// (globs such as `utm_*`; fragments are always ignored)
+    pub strip_query_params: Vec<String>,
+
+    /// Also remove `strip_query_params` (and detected session parameters) from the
+    /// URLs that are requested, not only from the visited-set key
+    pub strip_from_request: bool,
+
+    /// Treat a query parameter as a session id once two URLs differing only in its
+    /// value return identical bodies, and strip it from then on
+    pub session_param_detection: bool,
+
+    /// Follow `rel="next"`/`rel="prev"` pagination from Link headers and `<link>` tags
+    /// (`<a rel="next">` is skipped when disabled)
+    pub follow_pagination: bool,
+
+    /// Called with a progress snapshot after each queue item (the last one has an empty queue)
+    #[serde(serialize_with = "crate::config_serde::callback")]
+    pub crawl_progress: Option<CrawlProgressCallback>,
+
+    /// RFC 9218 urgency sent as `Priority: u=N` when fetching pages (0 is the highest)
+    pub page_priority: Option<u8>,
+
+    /// RFC 9218 urgency sent as `Priority: u=N` when fetching images, stylesheets,
+    /// scripts and other requisites, so pages aren't starved by them
+    pub requisite_priority: Option<u8>,
+
+    /// Store bodies of at most this many bytes in one pack in the output directory
+    /// instead of a file each (see [`Pack`](crate::Pack)); disables `convert_links`
+    #[cfg(feature = "pack")]
+    pub small_file_threshold: Option<u64>,
+
+    /// Stop visiting and queueing new URLs once this many are tracked as visited
+    /// (see [`CrawlStopReason::MaxTrackedUrls`]); `None` for no limit
+    pub max_tracked_urls: Option<usize>,
+
+    /// Wall-clock cap on each body fetched by the crawl (pages and requisites)
+    ///
+    /// A body still arriving after it, such as a live log linked from a page,
+    /// is abandoned: its partial file is removed, the URL is logged with reason
+    /// `TIMEOUT` and the crawl goes on. The tighter of this and
+    /// `DownloadConfig::max_body_duration` applies; `None` for no cap.
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
+    pub max_requisite_duration: Option<Duration>,
+
+    /// Write the crawl into one archive instead of a directory tree
+    ///
+    /// Entries are named by the paths the files would have been saved at,
+    /// relative to the output directory, and dated by Last-Modified; the
+    /// returned files and [`download_origins`](RecursiveDownloader::download_origins)
+    /// use these names. With `convert_links`, HTML and CSS files wait in a
+    /// staging directory until their links are converted. A file later
+    /// displaced by a directory of the same name (`/a` saved before `/a/b`)
+    /// keeps its entry name. Ignored in spider mode.
+    #[cfg(feature = "archive")]
+    pub archive_output: Option<ArchiveFormat>,
+
+    /// Where the archive is written; `None` for
+    /// [`ArchiveFormat::default_file_name`] in the output directory
+    #[cfg(feature = "archive")]
+    pub archive_path: Option<PathBuf>,
 }
 
 impl Default for RecursiveConfig {
@@ -70,11 +210,14 @@ impl Default for RecursiveConfig {
             span_hosts: false,
             relative_only: false,
             convert_links: false,
+            conversion_mode: ConversionMode::Full,
             backup_converted: false,
             adjust_extension: false,
             page_requisites: false,
             accept_extensions: Vec::new(),
             reject_extensions: Vec::new(),
+            accept_regex: None,
+            reject_regex: None,
             accepted_domains: Vec::new(),
             rejected_domains: Vec::new(),
             include_directories: Vec::new(),
@@ -82,24 +225,693 @@ impl Default for RecursiveConfig {
             no_parent: false,
             no_host_directories: false,
             spider: false,
+            delete_after: false,
             rejected_log: None,
             no_directories: false,
+            cut_dirs: 0,
+            protocol_directories: false,
+            path_mapper: None,
+            form_login: None,
+            post_processor: None,
+            robots_retry_delay: Duration::from_secs(5),
+            ignore_robots_delay: false,
+            strip_query_params: Vec::new(),
+            strip_from_request: false,
+            session_param_detection: false,
+            follow_pagination: true,
+            crawl_progress: None,
+            page_priority: Some(1),
+            requisite_priority: Some(5),
+            #[cfg(feature = "pack")]
+            small_file_threshold: None,
+            max_tracked_urls: None,
+            max_requisite_duration: Some(Duration::from_mins(10)),
+            #[cfg(feature = "archive")]
+            archive_output: None,
+            #[cfg(feature = "archive")]
+            archive_path: None,
+        }
+    }
+}
+
+impl RecursiveConfig {
+    /// Directory layout described by `no_directories`, `no_host_directories`,
+    /// `protocol_directories` and `cut_dirs`
+    pub fn layout(&self) -> DirectoryLayout {
+        DirectoryLayout {
+            no_directories: self.no_directories,
+            no_host_directories: self.no_host_directories,
+            protocol_directories: self.protocol_directories,
+            cut_dirs: self.cut_dirs,
+        }
+    }
+
+    /// Local path for `url` in a crawl saved under `output_dir`
+    ///
+    /// See [`DirectoryLayout::local_path`] for the precedence of the options.
+    /// A URL ending in `/` is saved as `index.html`. Page files, requisites and
+    /// robots.txt all go through this function.
+    pub fn local_path(&self, url: &Url, output_dir: &Path) -> PathBuf {
+        self.layout().local_path(url, output_dir)
+    }
+}
+
+/// Name a page gets when its URL also has to be a directory (`/docs` -> `docs/index.html`)
+const DEFAULT_PAGE: &str = "index.html";
+
+/// Queue items looked at for one whose host is ready before waiting on the front one
+const POLITENESS_LOOKAHEAD: usize = 64;
+
+/// Longest wait between robots.txt retries
+const ROBOTS_RETRY_MAX_DELAY: Duration = Duration::from_mins(10);
+
+/// Counters describing a recursive crawl
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrawlStats {
+    /// robots.txt requests sent
+    pub robots_fetches: u64,
+
+    /// robots.txt lookups answered from the cache
+    pub robots_cache_hits: u64,
+
+    /// robots.txt requests that failed with a network error or 5xx
+    pub robots_temporary_failures: u64,
+
+    /// robots.txt re-fetches after a temporary failure expired
+    pub robots_retries: u64,
+
+    /// Requests held back until their host's politeness delay had passed
+    pub host_waits: u64,
+
+    /// Fetches scheduled again after a transient failure (network error or a
+    /// `retry.retry_on_status` status)
+    pub fetch_retries: u64,
+
+    /// URLs fetched more than once because of transient failures, with the
+    /// attempts made (including the first)
+    pub fetch_attempts: BTreeMap<String, u32>,
+
+    /// Spider-mode HEAD probes retried after a transient failure
+    pub metadata_probe_retries: u64,
+
+    /// Spider-mode HEAD probes that failed twice (URL skipped)
+    pub metadata_probe_failures: u64,
+
+    /// Downloads aborted after the headers because the final name failed
+    /// accept/reject or the body is an endless stream
+    pub late_rejections: u64,
+
+    /// Bodies abandoned after `max_requisite_duration`
+    pub timed_out: u64,
+
+    /// Fetches skipped because the URL matched a visited one after normalization
+    /// (fragment, stripped or session query parameters)
+    pub duplicate_fetches_avoided: u64,
+
+    /// Query parameters detected as session ids
+    pub session_params_detected: u64,
+
+    /// Sitemap documents fetched (including the children of sitemap indexes)
+    pub sitemaps_fetched: u64,
+
+    /// Sitemap URLs skipped because their `<lastmod>` is not newer than the local file
+    pub sitemap_unchanged: u64,
+
+    /// Pages that asked not to be indexed (still crawled for links)
+    pub noindex_pages: u64,
+
+    /// URLs not saved because their path crossed a symlinked directory
+    /// (without `DownloadConfig::follow_output_symlinks`)
+    pub unsafe_paths_skipped: u64,
+
+    /// Links found in pages that can't be fetched, by type (never queued)
+    pub skipped_links: SkippedLinks,
+
+    /// Body bytes saved to disk (or fetched for link extraction in spider mode)
+    pub bytes_downloaded: u64,
+
+    /// URLs in the visited set
+    pub tracked_urls: usize,
+
+    /// Files registered with the link converter (with `convert_links`)
+    pub converter_entries: usize,
+
+    /// Approximate heap bytes held by the visited set, its URLs and the link converter
+    pub tracked_bytes: usize,
+
+    /// Why the crawl stopped before its queue ran out, if it did
+    pub stop_reason: Option<CrawlStopReason>,
+}
+
+/// Counts of the links extraction skipped because they can't be fetched
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SkippedLinks {
+    /// `#section` links into the page itself, and empty links
+    pub fragment: u64,
+
+    /// `javascript:` links
+    pub javascript: u64,
+
+    /// `mailto:` links
+    pub mailto: u64,
+
+    /// `tel:` links
+    pub tel: u64,
+
+    /// `data:` URLs
+    pub data: u64,
+
+    /// Links with any other scheme than http and https
+    pub other_scheme: u64,
+
+    /// Links that don't resolve to a valid URL
+    pub invalid: u64,
+}
+
+impl SkippedLinks {
+    /// Total number of skipped links
+    pub fn total(&self) -> u64 {
+        self.fragment
+            + self.javascript
+            + self.mailto
+            + self.tel
+            + self.data
+            + self.other_scheme
+            + self.invalid
+    }
+
+    fn count(&mut self, link: SkippedLink) {
+        let counter = match link {
+            SkippedLink::Fragment => &mut self.fragment,
+            SkippedLink::JavaScript => &mut self.javascript,
+            SkippedLink::Mailto => &mut self.mailto,
+            SkippedLink::Tel => &mut self.tel,
+            SkippedLink::Data => &mut self.data,
+            SkippedLink::OtherScheme => &mut self.other_scheme,
+            SkippedLink::Invalid => &mut self.invalid,
+        };
+        *counter += 1;
+    }
+}
+
+/// Why an extracted link can't be fetched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SkippedLink {
+    Fragment,
+    JavaScript,
+    Mailto,
+    Tel,
+    Data,
+    OtherScheme,
+    Invalid,
+}
+
+/// Why a crawl stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrawlStopReason {
+    /// [`RecursiveConfig::max_tracked_urls`] URLs were visited; the remaining
+    /// queue was dropped and no new links were queued
+    MaxTrackedUrls(usize),
+
+    /// `DownloadConfig::quota` bytes were downloaded; the remaining queue was dropped
+    QuotaExceeded(u64),
+}
+
+impl fmt::Display for CrawlStopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MaxTrackedUrls(max) => write!(f, "limit of {max} tracked URLs reached"),
+            Self::QuotaExceeded(quota) => write!(f, "download quota of {quota} bytes exceeded"),
+        }
+    }
+}
+
+/// Referring pages kept per file in [`Origin::referrers`]
+pub const MAX_ORIGIN_REFERRERS: usize = 16;
+
+/// Why a file was saved by a crawl: where its URL was first found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// URL the file was downloaded from
+    pub url: String,
+
+    /// Page (or sitemap) that first linked to the URL; `None` for a start URL
+    pub referrer: Option<String>,
+
+    /// Depth the URL was first found at (0 for a start URL)
+    pub depth: usize,
+
+    /// Distinct pages linking to the URL in the order found, the first
+    /// [`MAX_ORIGIN_REFERRERS`] of them
+    pub referrers: Vec<String>,
+
+    /// Links to the URL found during the crawl, including repeats and those
+    /// beyond `referrers`
+    pub referral_count: usize,
+
+    /// The page asked not to be indexed (see [`RecursiveDownloader::noindex_pages`])
+    pub noindex: bool,
+
+    /// Fetches of the URL it took to save the file (more than 1 after
+    /// transient failures were retried)
+    pub attempts: u32,
+}
+
+impl Origin {
+    fn new(url: &str, referrer: Option<&str>, depth: usize) -> Self {
+        let mut origin = Self {
+            url: url.to_string(),
+            referrer: referrer.map(str::to_string),
+            depth,
+            referrers: Vec::new(),
+            referral_count: 0,
+            noindex: false,
+            attempts: 1,
+        };
+        if let Some(referrer) = referrer {
+            origin.add_referrer(referrer);
+        }
+        origin
+    }
+
+    fn add_referrer(&mut self, referrer: &str) {
+        self.referral_count += 1;
+        if self.referrers.len() < MAX_ORIGIN_REFERRERS
+            && !self.referrers.iter().any(|r| r == referrer)
+        {
+            self.referrers.push(referrer.to_string());
+        }
+    }
+}
+
+/// Snapshot of a running crawl, passed to [`RecursiveConfig::crawl_progress`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrawlProgress {
+    /// URLs fetched so far (or checked, in spider mode)
+    pub visited: usize,
+
+    /// Queue items still to process, including links that will turn out to be
+    /// duplicates or rejected
+    pub queued: usize,
+
+    /// Body bytes downloaded so far
+    pub bytes_downloaded: u64,
+
+    /// Approximate heap bytes held by the visited set and the link converter
+    /// (see [`CrawlStats::tracked_bytes`])
+    pub tracked_bytes: usize,
+
+    /// Visited URLs per depth: `depths[0]` is the start page, `depths[1]` its links, ...
+    pub depths: Vec<usize>,
+
+    /// Queue item that was just processed
+    pub current_url: String,
+
+    /// Time since the crawl started
+    pub elapsed: Duration,
+}
+
+/// Function receiving crawl progress snapshots
+pub type CrawlProgressFn = Arc<dyn Fn(CrawlProgress) + Send + Sync>;
+
+/// Crawl progress callback passed through `RecursiveConfig`
+#[derive(Clone)]
+pub struct CrawlProgressCallback(pub CrawlProgressFn);
+
+impl fmt::Debug for CrawlProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CrawlProgressCallback(..)")
+    }
+}
+
+/// What a path mapper knows about the URL it places
+#[derive(Debug, Clone, Copy)]
+pub struct PathMapperContext<'a> {
+    /// Directory the crawl is saved under
+    pub output_dir: &'a Path,
+
+    /// Response headers, when the path is looked up after they arrived
+    ///
+    /// `None` when deciding where to save a body; set when an untyped response
+    /// is classified by the extension of its local name.
+    pub metadata: Option<&'a ResourceMetadata>,
+
+    /// Whether the URL is fetched as a page requisite (image, stylesheet, script...)
+    pub is_requisite: bool,
+
+    /// Path the URL gets without a mapper
+    pub default_path: &'a Path,
+}
+
+/// Function giving a crawled URL its local path, or `None` for the default
+pub type PathMapperFn = Arc<dyn Fn(&Url, &PathMapperContext<'_>) -> Option<PathBuf> + Send + Sync>;
+
+/// Path mapper passed through `RecursiveConfig`
+#[derive(Clone)]
+pub struct PathMapper(pub PathMapperFn);
+
+impl fmt::Debug for PathMapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PathMapper(..)")
+    }
+}
+
+/// `path` with `.html` added to a server-side script extension (`-E`), if it has one
+///
+/// Matches wget: `file.php` -> `file.php.html`.
+fn adjusted_extension(path: &Path) -> Option<PathBuf> {
+    let ext = path.extension()?.to_str()?;
+    matches!(ext, "php" | "asp" | "aspx" | "jsp" | "cgi" | "pl" | "py" | "rb")
+        .then(|| path.with_extension(format!("{ext}.html")))
+}
+
+/// Why `name` fails the accept/reject extension lists, if it does
+///
+/// Names without an extension are never rejected.
+fn extension_rejection(accept: &[String], reject: &[String], name: &str) -> Option<String> {
+    let ext = Path::new(name)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+
+    if !accept.is_empty() && !accept.contains(&ext) {
+        return Some(format!("Extension not in accepted list: {ext}"));
+    }
+
+    if reject.contains(&ext) {
+        return Some(format!("Extension in rejected list: {ext}"));
+    }
+
+    None
+}
+
+/// Compile the `--accept-regex` / `--reject-regex` pattern named `kind`
+fn compile_url_regex(kind: &str, pattern: Option<&str>) -> Result<Option<Regex>> {
+    pattern
+        .map(|pattern| {
+            Regex::new(pattern)
+                .map_err(|e| Error::ConfigError(format!("Invalid {kind} regex {pattern:?}: {e}")))
+        })
+        .transpose()
+}
+
+/// Why `url` is rejected by the URL regexes, if it is
+fn regex_rejection(
+    accept: Option<&Regex>,
+    reject: Option<&Regex>,
+    url: &str,
+) -> Option<&'static str> {
+    if accept.is_some_and(|re| !re.is_match(url)) {
+        return Some("URL did not match accept regex");
+    }
+    if reject.is_some_and(|re| re.is_match(url)) {
+        return Some("URL matched reject regex");
+    }
+    None
+}
+
+/// Response filter re-applying the accept/reject lists to the final file name
+///
+/// A URL like `/download?id=9` passes the URL-based check but may redirect
+/// to `report.pdf` or name it in Content-Disposition. HTML pages are exempt
+/// so the crawl can still follow their links. Any filter already configured
+/// runs first.
+fn final_name_filter(config: &RecursiveConfig, inner: Option<ResponseFilter>) -> ResponseFilter {
+    let accept = config.accept_extensions.clone();
+    let reject = config.reject_extensions.clone();
+
+    ResponseFilter(Arc::new(move |url, metadata| {
+        if let Some(ResponseFilter(ref inner)) = inner {
+            if let Some(reason) = inner(url, metadata) {
+                return Some(reason);
+            }
+        }
+
+        if metadata
+            .content_type
+            .as_deref()
+            .is_some_and(|ct| ct.contains("text/html"))
+        {
+            return None;
+        }
+
+        let name = crate::naming::final_filename(url, metadata)?;
+        extension_rejection(&accept, &reject, &name)
+            .map(|reason| format!("Final name {name} rejected: {reason}"))
+    }))
+}
+
+/// Content types of bodies that never end
+const STREAMING_CONTENT_TYPES: &[&str] = &["text/event-stream", "multipart/x-mixed-replace"];
+
+/// Response filter rejecting bodies that never end (server-sent events, MJPEG
+/// and other pushed streams) before they are transferred
+///
+/// Any filter already configured runs first.
+fn streaming_filter(inner: Option<ResponseFilter>) -> ResponseFilter {
+    ResponseFilter(Arc::new(move |url, metadata| {
+        if let Some(ResponseFilter(ref inner)) = inner {
+            if let Some(reason) = inner(url, metadata) {
+                return Some(reason);
+            }
+        }
+
+        let content_type = metadata.content_type.as_deref()?;
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        STREAMING_CONTENT_TYPES
+            .iter()
+            .any(|stream| essence.eq_ignore_ascii_case(stream))
+            .then(|| format!("Streaming content type {essence}"))
+    }))
+}
+
+/// Whether a space-separated `rel` value names a pagination relation
+fn is_pagination_rel(rel: &str) -> bool {
+    rel.split_ascii_whitespace().any(|rel| {
+        rel.eq_ignore_ascii_case("next")
+            || rel.eq_ignore_ascii_case("prev")
+            || rel.eq_ignore_ascii_case("previous")
+    })
+}
+
+/// Resolve a link found in a page against the page's URL
+///
+/// Fails for links that can't be fetched: fragment-only links (which would
+/// only lead back to the page itself) and anything not http or https.
+fn resolve_link(base: &Url, href: &str) -> std::result::Result<String, SkippedLink> {
+    let href = href.trim();
+    if href.is_empty() || href.starts_with('#') {
+        return Err(SkippedLink::Fragment);
+    }
+    let url = base.join(href).map_err(|_| SkippedLink::Invalid)?;
+    match url.scheme() {
+        "http" | "https" => Ok(url.into()),
+        "javascript" => Err(SkippedLink::JavaScript),
+        "mailto" => Err(SkippedLink::Mailto),
+        "tel" => Err(SkippedLink::Tel),
+        "data" => Err(SkippedLink::Data),
+        _ => Err(SkippedLink::OtherScheme),
+    }
+}
+
+/// Create the parent directories of `local_path`
+///
+/// A file saved earlier can sit where a directory is needed: `/docs` saved as
+/// `docs` before `/docs/intro.html` comes along, or a redirect from `/docs` to
+/// `/docs/`. Like wget, that file moves into the new directory as
+/// [`DEFAULT_PAGE`] instead of being deleted. Returns the move, if one happened.
+///
+/// Directories in `created_dirs` are known to exist (no file can be saved
+/// where one is), so files sharing a directory cost no filesystem calls after
+/// the first. Directories below `output_dir` are created without following
+/// symlinks (see [`create_dirs_within`](crate::create_dirs_within)).
+async fn create_parent_dirs(
+    local_path: &Path,
+    output_dir: &Path,
+    follow_symlinks: bool,
+    created_dirs: &mut HashSet<PathBuf>,
+) -> Result<Option<(PathBuf, PathBuf)>> {
+    let Some(parent) = local_path.parent() else {
+        return Ok(None);
+    };
+    if created_dirs.contains(parent) {
+        return Ok(None);
+    }
+
+    let mut moved = None;
+    // A symlink to a file is left for create_dirs_within to refuse
+    let is_file = |dir: &&Path| dir.symlink_metadata().is_ok_and(|m| m.is_file());
+    if let Some(file) = parent.ancestors().find(is_file) {
+        let page = file.join(DEFAULT_PAGE);
+        let mut aside = file.as_os_str().to_owned();
+        aside.push(".wgetf-move");
+        tokio::fs::rename(file, &aside).await?;
+        tokio::fs::create_dir(file).await?;
+        tokio::fs::rename(&aside, &page).await?;
+        tracing::info!(
+            from = %file.display(),
+            to = %page.display(),
+            "Moved saved page into a directory needed by another URL"
+        );
+        moved = Some((file.to_path_buf(), page));
+    }
+
+    crate::create_dirs_within(output_dir, parent, follow_symlinks).await?;
+    for dir in parent.ancestors() {
+        if !created_dirs.insert(dir.to_path_buf()) {
+            break;
+        }
+    }
+    Ok(moved)
+}
+
+/// Cached robots.txt state for a host
+#[derive(Debug, Clone)]
+enum RobotsCacheEntry {
+    /// Parsed file, or `None` if the host definitively has none; kept for the crawl
+    Final(Option<crate::robots::RobotsTxt>),
+
+    /// Transient failure; fetch again once `retry_at` has passed
+    Unavailable { retry_at: Instant, failures: u32 },
+}
+
+/// Key of a host in the robots.txt cache (`https://example.com:8443`)
+fn robots_cache_key(scheme: &str, host: &str, port: Option<u16>) -> String {
+    format!("{}://{}{}", scheme, host, port.map(|p| format!(":{p}")).unwrap_or_default())
+}
+
+/// Host whose politeness delay applies to `url` (keyed like the robots.txt cache)
+fn politeness_key(url: &Url) -> Option<String> {
+    Some(robots_cache_key(url.scheme(), url.host_str()?, url.port()))
+}
+
+/// Delay before the next robots.txt attempt after `failures` consecutive failures
+fn robots_retry_backoff(base: Duration, failures: u32) -> Duration {
+    let factor = 1u32 << failures.saturating_sub(1).min(16);
+    base.saturating_mul(factor).min(ROBOTS_RETRY_MAX_DELAY)
+}
+
+/// Type of a body links are extracted from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DocumentKind {
+    Html,
+    Css,
+}
+
+/// Body of an HTML or CSS response, to extract links from
+struct Document {
+    kind: DocumentKind,
+    text: String,
+}
+
+impl Document {
+    fn new(kind: DocumentKind, body: &[u8]) -> Self {
+        Self {
+            kind,
+            text: String::from_utf8_lossy(body).into_owned(),
         }
     }
 }
 
+/// What the fetch step of a crawl produced for one URL
+struct Fetched {
+    /// Saved file (`None` in spider mode)
+    path: Option<PathBuf>,
+    /// Body to extract links from, for HTML and CSS responses
+    document: Option<Document>,
+    /// Link header relations of the response
+    relations: Vec<LinkRelation>,
+    /// Directives of the response's `X-Robots-Tag` headers
+    robots: RobotsDirectives,
+}
+
+/// URL waiting to be crawled: (URL, depth, `parent_url`, kind)
+type QueueItem = (String, usize, Option<Arc<str>>, RequestKind);
+
+/// Queue item that passed the filters and was marked visited
+struct Admitted {
+    /// Normalized URL, the key in `visited`
+    key: Arc<str>,
+    /// URL to request
+    url: Arc<str>,
+    depth: usize,
+    parent_url: Option<Arc<str>>,
+    kind: RequestKind,
+}
+
+/// URL to fetch again once `due`, after a transient failure
+struct PendingRetry {
+    due: Instant,
+    /// Attempts made so far
+    attempts: u32,
+    item: Admitted,
+}
+
+// `BinaryHeap` is a max-heap: the earliest `due` is the greatest
+impl Ord for PendingRetry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.due.cmp(&self.due)
+    }
+}
+
+impl PartialOrd for PendingRetry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PendingRetry {
+    fn eq(&self, other: &Self) -> bool {
+        self.due == other.due
+    }
+}
+
+impl Eq for PendingRetry {}
+
+/// Next thing the crawl loop does
+enum CrawlStep {
+    /// Filter, fetch and follow a queued URL
+    Queued(QueueItem),
+    /// Fetch an admitted URL again
+    Retry(PendingRetry),
+}
+
+/// Link found in a fetched URL: (URL, kind)
+type Link = (String, RequestKind);
+
 /// Recursive downloader
 pub struct RecursiveDownloader {
     downloader: Downloader,
     config: RecursiveConfig,
-    visited: HashSet<String>,
-    queue: VecDeque<(String, usize, Option<String>)>, // (URL, depth, parent_url)
-    base_url: Option<String>,                         // Base URL for no_parent check
-    broken_links: Vec<(String, u16)>, // (URL, status_code) for tracking broken links
+    visited: HashMap<Arc<str>, Arc<str>>, // Normalized URL -> first URL visited under it
+    urls: UrlInterner,                    // Storage of the URLs in `visited`
+    deduper: UrlDeduper,
+    accept_regex: Option<Regex>, // Compiled `RecursiveConfig::accept_regex`
+    reject_regex: Option<Regex>, // Compiled `RecursiveConfig::reject_regex`
+    queue: VecDeque<QueueItem>,
+    retries: BinaryHeap<PendingRetry>, // URLs to fetch again after transient failures, soonest first
+    base_url: Option<String>,          // Base URL for no_parent check
+    broken_links: Vec<(String, u16)>,  // (URL, status_code) for tracking broken links
+    noindex_pages: Vec<String>,        // Pages with a noindex directive, in crawl order
+    timed_out_urls: Vec<String>,       // URLs abandoned after max_requisite_duration
+    fetched_urls: Vec<String>,         // URLs whose files were deleted (with delete_after)
     link_converter: Option<LinkConverter>, // Link converter for -k flag
     rejected_urls: Vec<(String, String, Option<String>)>, // (URL, reason, parent_url) for tracking rejected URLs
-    robots_cache: HashMap<String, Option<crate::robots::RobotsTxt>>, // Cache of robots.txt per host (None if not found/failed)
-    spider_content_cache: HashMap<String, Option<String>>, // Cache of HTML content in spider mode (None if download failed)
+    robots_cache: HashMap<String, RobotsCacheEntry>,      // Cache of robots.txt per host
+    host_next_request: HashMap<String, Instant>, // Host -> earliest time of its next request
+    logged_in: bool, // Whether the form login (if configured) has been performed
+    saved_paths: HashSet<PathBuf>, // Local files written during this crawl
+    moved_paths: HashMap<PathBuf, PathBuf>, // Saved file -> where it moved to make room for a directory
+    depths: Vec<usize>,                     // Visited URLs per depth, for crawl progress
+    stats: CrawlStats,
+    file_handles: FileHandles, // Cap on open files, shared with the link converter
+    created_dirs: HashSet<PathBuf>, // Directories known to exist, so they aren't created again
+    origins: HashMap<PathBuf, Origin>, // Saved file -> where its URL was found
+    origin_paths: HashMap<Arc<str>, PathBuf>, // Normalized URL -> file saved for it
+    #[cfg(feature = "pack")]
+    pack: Option<PackWriter>, // Pack receiving small files (with small_file_threshold)
+    #[cfg(feature = "archive")]
+    archive: Option<ArchiveWriter>, // Archive receiving every saved file (with archive_output)
 }
 
 impl RecursiveDownloader {
@@ -113,7 +925,7 @@ impl RecursiveDownloader {
     /// # Returns
     ///
     /// Returns `Ok(RecursiveDownloader)` on success, or `Err` if the downloader
-    /// configuration fails.
+    /// configuration fails or a URL regex doesn't compile.
     ///
     /// # Examples
     ///
@@ -139,150 +951,853 @@ impl RecursiveDownloader {
         if !recursive_config.spider {
             download_config.parallel_chunks = 1;
             download_config.parallel_threshold = 0;
+
+            // Redirects and Content-Disposition can change the name after the URL check
+            if !recursive_config.accept_extensions.is_empty()
+                || !recursive_config.reject_extensions.is_empty()
+            {
+                download_config.response_filter = Some(final_name_filter(
+                    &recursive_config,
+                    download_config.response_filter.take(),
+                ));
+            }
+            download_config.response_filter =
+                Some(streaming_filter(download_config.response_filter.take()));
         }
+        download_config.max_body_duration =
+            match (download_config.max_body_duration, recursive_config.max_requisite_duration) {
+                (Some(limit), Some(cap)) => Some(limit.min(cap)),
+                (limit, cap) => limit.or(cap),
+            };
+
+        let deduper = UrlDeduper::new(
+            recursive_config.strip_query_params.clone(),
+            recursive_config.session_param_detection,
+        );
+        let accept_regex = compile_url_regex("accept", recursive_config.accept_regex.as_deref())?;
+        let reject_regex = compile_url_regex("reject", recursive_config.reject_regex.as_deref())?;
 
         Ok(Self {
             downloader: Downloader::new(download_config)?,
             config: recursive_config,
-            visited: HashSet::new(),
+            visited: HashMap::new(),
+            urls: UrlInterner::default(),
+            deduper,
+            accept_regex,
+            reject_regex,
             queue: VecDeque::new(),
+            retries: BinaryHeap::new(),
             base_url: None,
             broken_links: Vec::new(),
+            noindex_pages: Vec::new(),
+            timed_out_urls: Vec::new(),
+            fetched_urls: Vec::new(),
             link_converter: None,
             rejected_urls: Vec::new(),
             robots_cache: HashMap::new(),
-            spider_content_cache: HashMap::new(),
+            host_next_request: HashMap::new(),
+            logged_in: false,
+            saved_paths: HashSet::new(),
+            moved_paths: HashMap::new(),
+            depths: Vec::new(),
+            stats: CrawlStats::default(),
+            file_handles: FileHandles::default(),
+            created_dirs: HashSet::new(),
+            origins: HashMap::new(),
+            origin_paths: HashMap::new(),
+            #[cfg(feature = "pack")]
+            pack: None,
+            #[cfg(feature = "archive")]
+            archive: None,
         })
     }
 
-    /// Get the list of broken links encountered during spider mode
+    /// Get the URLs that answered with an error status, with that status
     pub fn broken_links(&self) -> &[(String, u16)] {
         &self.broken_links
     }
 
+    /// Get the URLs whose body was abandoned after `max_requisite_duration`, in crawl order
+    pub fn timed_out_urls(&self) -> &[String] {
+        &self.timed_out_urls
+    }
+
+    /// Get the URLs downloaded and then deleted with `delete_after`, in crawl order
+    pub fn fetched_urls(&self) -> &[String] {
+        &self.fetched_urls
+    }
+
+    /// Get the pages that asked not to be indexed, in crawl order
+    ///
+    /// From `noindex` (or `none`) in an `X-Robots-Tag` header or a robots
+    /// `<meta>` tag. Such pages are still saved and crawled for links.
+    pub fn noindex_pages(&self) -> &[String] {
+        &self.noindex_pages
+    }
+
+    /// Get where the URL of every file saved so far was found, by file
+    ///
+    /// Files moved to make room for a directory are listed under their new path.
+    pub fn download_origins(&self) -> &HashMap<PathBuf, Origin> {
+        &self.origins
+    }
+
+    /// Get counters describing the crawl so far
+    pub fn stats(&self) -> &CrawlStats {
+        &self.stats
+    }
+
+    /// Body bytes counted against `DownloadConfig::quota` so far
+    ///
+    /// Unlike `CrawlStats::bytes_downloaded`, includes the robots.txt files read.
+    pub fn quota_used(&self) -> u64 {
+        self.downloader.quota_used()
+    }
+
+    /// Write the cookies received during the crawl to `save_cookie_file`, if set
+    ///
+    /// See [`Downloader::save_cookies`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cookie file can't be written
+    #[cfg(feature = "cookies-file")]
+    pub async fn save_cookies(&self) -> Result<()> {
+        self.downloader.save_cookies().await
+    }
+
+    /// Write the known HSTS hosts to `hsts_file`, if set
+    ///
+    /// See [`Downloader::save_hsts`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written
+    pub async fn save_hsts(&self) -> Result<()> {
+        self.downloader.save_hsts().await
+    }
+
+    /// Create the link converter for -k, with the configured mode and post-processor
+    fn new_link_converter(&self, output_dir: &Path) -> LinkConverter {
+        let mut converter =
+            LinkConverter::new(output_dir.to_path_buf(), self.config.backup_converted);
+        converter.set_mode(self.config.conversion_mode);
+        converter.set_saved_headers(self.downloader.get_client().config().save_headers);
+        converter.set_file_handles(self.file_handles.clone());
+        if let Some(PostProcessor(ref processor)) = self.config.post_processor {
+            converter.set_post_processor(processor.clone());
+        }
+        converter
+    }
+
     /// Start recursive download from a URL
+    ///
+    /// Returns the saved files (none in spider mode), or the entry names of
+    /// the archive with `archive_output`. URLs answering with an error status
+    /// are skipped and listed in [`broken_links`](Self::broken_links).
     pub async fn download_recursive(
         &mut self,
         start_url: &str,
         output_dir: &Path,
     ) -> Result<Vec<PathBuf>> {
+        #[cfg(feature = "archive")]
+        if let Some(format) = self.config.archive_output.filter(|_| !self.config.spider) {
+            return self.crawl_to_archive(start_url, output_dir, format).await;
+        }
+        self.crawl(start_url, output_dir).await
+    }
+
+    /// Archive mode: crawl into a staging directory that each saved file is moved out of
+    ///
+    /// A crawl that fails removes the unfinished archive.
+    #[cfg(feature = "archive")]
+    async fn crawl_to_archive(
+        &mut self,
+        start_url: &str,
+        output_dir: &Path,
+        format: ArchiveFormat,
+    ) -> Result<Vec<PathBuf>> {
+        tokio::fs::create_dir_all(output_dir).await?;
+        let staging = tempfile::Builder::new()
+            .prefix(".wgetf-archive-")
+            .tempdir_in(output_dir)?;
+        let archive_path = self
+            .config
+            .archive_path
+            .clone()
+            .unwrap_or_else(|| output_dir.join(format.default_file_name()));
+        self.archive = Some(ArchiveWriter::create(&archive_path, format, staging.path()).await?);
+
+        let crawled = self.crawl(start_url, staging.path()).await;
+        let Some(archive) = self.archive.take() else {
+            return crawled;
+        };
+        let entry_path = |path: PathBuf| archive.entry_path(&path);
+        let crawled = crawled.map(|files| files.into_iter().map(entry_path).collect::<Vec<_>>());
+        self.origins = std::mem::take(&mut self.origins)
+            .into_iter()
+            .map(|(path, origin)| (entry_path(path), origin))
+            .collect();
+        let finished = match crawled {
+            Ok(entries) => archive.finish().await.map(|()| entries),
+            Err(e) => Err(e),
+        };
+        if finished.is_err() {
+            let _ = tokio::fs::remove_file(&archive_path).await;
+        }
+        finished
+    }
+
+    /// Archive mode: move a saved file into the archive, unless links are still to be converted in it
+    #[cfg(feature = "archive")]
+    async fn archive_saved(&mut self, path: &Path) -> Result<()> {
+        let converted_later = self
+            .link_converter
+            .as_ref()
+            .is_some_and(|converter| converter.converts(path));
+        if let Some(archive) = self.archive.as_mut().filter(|_| !converted_later) {
+            let _handle = self.file_handles.open(1).await;
+            archive.append_file(path).await?;
+        }
+        Ok(())
+    }
+
+    /// Crawl from `start_url`, saving under `output_dir`
+    async fn crawl(&mut self, start_url: &str, output_dir: &Path) -> Result<Vec<PathBuf>> {
+        let start_url = crate::prepare_url(start_url)?;
+        let start_url = start_url.as_str();
         let mut downloaded_files = Vec::new();
+        self.log_in_once().await?;
+
+        #[cfg(feature = "pack")]
+        if self.config.small_file_threshold.is_some() && !self.config.spider {
+            if self.config.convert_links {
+                tracing::warn!("Link conversion is disabled while packing small files");
+            }
+            self.pack = Some(PackWriter::create(output_dir).await?);
+        }
+
+        if self.config.convert_links && self.config.delete_after {
+            tracing::warn!("Link conversion is disabled when deleting files after download");
+        }
 
         // Initialize link converter if convert_links is enabled
-        if self.config.convert_links {
-            self.link_converter =
-                Some(LinkConverter::new(output_dir.to_path_buf(), self.config.backup_converted));
+        if self.config.convert_links && !self.packing() && !self.config.delete_after {
+            self.link_converter = Some(self.new_link_converter(output_dir));
         }
 
         // Set base URL for no_parent check
         self.base_url = Some(start_url.to_string());
 
         // Add starting URL to queue (no parent URL)
-        self.queue.push_back((start_url.to_string(), 0, None));
-
-        while let Some((url, depth, parent_url)) = self.queue.pop_front() {
-            // Skip if already visited (log as BLACKLIST - recursive loop)
-            if self.visited.contains(&url) {
-                // Log this as a rejection if it has a parent (i.e., it's a link from another page)
-                // This prevents logging the starting URL when it's first queued
-                if parent_url.is_some() {
-                    self.log_rejected_url(
-                        &url,
-                        "Already visited (recursive loop)",
-                        parent_url.as_deref(),
-                    );
-                }
-                continue;
+        self.queue
+            .push_back((start_url.to_string(), 0, None, RequestKind::Page));
+
+        let started = Instant::now();
+        while let Some(step) = self.next_step().await {
+            let (url, saved) = match step {
+                CrawlStep::Queued(item) => {
+                    let url = item.0.clone();
+                    (url, self.crawl_queue_item(item, output_dir).await?)
+                },
+                CrawlStep::Retry(retry) => {
+                    let url = retry.item.url.to_string();
+                    let attempt = retry.attempts + 1;
+                    (
+                        url,
+                        self.fetch_and_follow(retry.item, attempt, output_dir)
+                            .await?,
+                    )
+                },
+            };
+            if let Some(file_path) = saved {
+                downloaded_files.push(file_path);
             }
+            self.update_tracking_stats();
+            self.report_progress(&url, started);
+        }
 
-            // Skip if max depth exceeded
-            if self.config.max_depth > 0 && depth >= self.config.max_depth {
-                continue;
+        // Convert links after all files are downloaded
+        if let Some(ref converter) = self.link_converter {
+            converter.convert_all_links().await?;
+        }
+
+        #[cfg(feature = "pack")]
+        if let Some(pack) = self.pack.take() {
+            pack.finish().await?;
+        }
+
+        self.write_rejected_log().await?;
+
+        Ok(self.current_paths(downloaded_files))
+    }
+
+    /// Next step of the crawl: a due retry, else the next queue item
+    ///
+    /// Once the queue is empty, waits for the earliest retry.
+    async fn next_step(&mut self) -> Option<CrawlStep> {
+        if self
+            .retries
+            .peek()
+            .is_some_and(|retry| retry.due <= Instant::now())
+        {
+            return self.retries.pop().map(CrawlStep::Retry);
+        }
+        if let Some(item) = self.next_queue_item() {
+            return Some(CrawlStep::Queued(item));
+        }
+        let retry = self.retries.pop()?;
+        tokio::time::sleep_until(retry.due.into()).await;
+        Some(CrawlStep::Retry(retry))
+    }
+
+    /// Next queue item to crawl, preferring one whose host may be requested now
+    ///
+    /// Items keep their order unless the front one's host is still inside its
+    /// politeness delay; then the first ready item within `POLITENESS_LOOKAHEAD`
+    /// goes first, so other hosts proceed while that one waits.
+    fn next_queue_item(&mut self) -> Option<QueueItem> {
+        let now = Instant::now();
+        self.host_next_request.retain(|_, at| *at > now);
+        if self.host_next_request.is_empty() {
+            return self.queue.pop_front();
+        }
+        let ready = self
+            .queue
+            .iter()
+            .take(POLITENESS_LOOKAHEAD)
+            .position(|(url, ..)| {
+                let host = Url::parse(url).ok().and_then(|url| politeness_key(&url));
+                host.is_none_or(|host| !self.host_next_request.contains_key(&host))
+            });
+        self.queue.remove(ready.unwrap_or(0))
+    }
+
+    /// Sleep until `url`'s host may be requested, then start its next delay
+    ///
+    /// The delay is the longest of `wait_time` (scaled by 0.5-1.5 with
+    /// `random_wait`) and the host's robots.txt `Crawl-delay` for our user
+    /// agent, unless `ignore_robots_delay` is set.
+    async fn wait_for_host(&mut self, url: &str) {
+        let Some(host) = Url::parse(url).ok().and_then(|url| politeness_key(&url)) else {
+            return;
+        };
+        if let Some(at) = self.host_next_request.get(&host).copied() {
+            if at > Instant::now() {
+                self.stats.host_waits += 1;
+                tokio::time::sleep_until(at.into()).await;
             }
+        }
 
-            // Skip if URL doesn't match filters
-            // Note: Pass depth to should_download so it can handle --https-only correctly
-            // (starting URL is allowed even if HTTP, but extracted links are filtered)
-            match self
-                .should_download(&url, depth, parent_url.as_deref(), output_dir)
-                .await
-            {
-                Ok(true) => {
-                    // URL passed all filters
-                },
-                Ok(false) => {
-                    // URL was rejected - the reason was already logged
-                    continue;
-                },
-                Err(e) => return Err(e),
+        let config = self.downloader.get_client().config();
+        let wait = config.wait_time.map(|wait| {
+            if config.random_wait {
+                crate::link_check::jittered(wait)
+            } else {
+                wait
             }
+        });
+        let crawl_delay = match self.robots_cache.get(&host) {
+            Some(RobotsCacheEntry::Final(Some(robots))) if !self.config.ignore_robots_delay => {
+                robots.crawl_delay(&config.user_agent)
+            },
+            _ => None,
+        };
+        if let Some(delay) = wait.max(crawl_delay).filter(|delay| !delay.is_zero()) {
+            self.host_next_request.insert(host, Instant::now() + delay);
+        }
+    }
+
+    /// Whether small files go to a pack instead of their own files
+    fn packing(&self) -> bool {
+        #[cfg(feature = "pack")]
+        return self.pack.is_some();
+        #[cfg(not(feature = "pack"))]
+        false
+    }
+
+    /// Process one queue item: filter, download and queue its links
+    ///
+    /// Returns the saved file, or `None` if the item was skipped or rejected.
+    async fn crawl_queue_item(
+        &mut self,
+        (url, depth, parent, kind): QueueItem,
+        output_dir: &Path,
+    ) -> Result<Option<PathBuf>> {
+        let url = url.as_str();
+        let parent_url = parent.as_deref();
+        // Skip if already visited (log as BLACKLIST - recursive loop)
+        let Some(key) = self.unvisited_key(url, parent_url) else {
+            return Ok(None);
+        };
+        if self.tracking_limit_reached() || self.quota_reached() {
+            return Ok(None);
+        }
+
+        // Skip if max depth exceeded; with page_requisites, like wget -p, the
+        // requisites of the last pages are still fetched
+        if self.beyond_max_depth(depth) && !(self.config.page_requisites && kind.is_requisite()) {
+            return Ok(None);
+        }
+
+        // Skip if URL doesn't match filters
+        // Note: Pass depth to should_download so it can handle --https-only correctly
+        // (starting URL is allowed even if HTTP, but extracted links are filtered)
+        if !self
+            .should_download(url, depth, parent_url, output_dir)
+            .await?
+        {
+            // URL was rejected - the reason was already logged
+            return Ok(None);
+        }
 
-            // Mark as visited
-            self.visited.insert(url.clone());
+        // Mark as visited
+        let key = self.urls.intern(&key);
+        let url = self.urls.intern(url);
+        self.visited.insert(key.clone(), url.clone());
+        if self.depths.len() <= depth {
+            self.depths.resize(depth + 1, 0);
+        }
+        self.depths[depth] += 1;
+        let url = if self.config.strip_from_request {
+            key.clone()
+        } else {
+            url
+        };
+        let item = Admitted {
+            key,
+            url,
+            depth,
+            parent_url: parent,
+            kind,
+        };
+        self.fetch_and_follow(item, 1, output_dir).await
+    }
 
-            // Download the file
-            let file_path = self.download_and_save(&url, output_dir, depth).await?;
+    /// Fetch an admitted URL (attempt number `attempt`) and queue its links
+    ///
+    /// A transient failure (network error or a `retry.retry_on_status` status)
+    /// is scheduled for another attempt, up to `retry.max_retries` times, after
+    /// `wait_retry` or the retry backoff; the crawl goes on meanwhile. Only
+    /// the last attempt's failure counts, e.g. as a broken link.
+    async fn fetch_and_follow(
+        &mut self,
+        item: Admitted,
+        attempt: u32,
+        output_dir: &Path,
+    ) -> Result<Option<PathBuf>> {
+        if attempt > 1 {
+            self.stats
+                .fetch_attempts
+                .insert(item.url.to_string(), attempt);
+            if self.quota_reached() {
+                return Ok(None);
+            }
+        }
+        let retry = &self.downloader.get_client().config().retry;
+        let may_retry = usize::try_from(attempt).is_ok_and(|attempt| attempt <= retry.max_retries);
+        let (url, parent_url, depth) = (item.url.clone(), item.parent_url.clone(), item.depth);
+        let parent_url = parent_url.as_deref();
+
+        // Download the file, or probe it in spider mode (skipped if its final name is rejected)
+        let fetched = match self
+            .fetch_unless_rejected(&url, output_dir, parent_url, item.kind, depth, may_retry)
+            .await
+        {
+            Err(e)
+                if may_retry && is_transient(&e, &self.downloader.get_client().config().retry) =>
+            {
+                self.schedule_retry(item, attempt, &e);
+                return Ok(None);
+            },
+            fetched => fetched?,
+        };
+        let Some(fetched) = fetched else {
+            return Ok(None);
+        };
 
+        if let Some(ref file_path) = fetched.path {
+            self.record_origin(item.key, &url, parent_url, depth, file_path)
+                .attempts = attempt;
             // Register file with link converter if enabled
             if let Some(ref mut converter) = self.link_converter {
-                converter.register_file(&url, file_path.clone());
+                converter.register_interned(url.clone(), file_path);
             }
+            self.detect_session_param(&url, file_path).await;
+            #[cfg(feature = "archive")]
+            self.archive_saved(file_path).await?;
+        }
 
-            downloaded_files.push(file_path.clone());
+        // From here on spider and download mode select URLs identically
+        let (mut links, robots) = self.fetched_links(&fetched, &url, depth)?;
+        if robots.noindex {
+            self.record_noindex(&url, fetched.path.as_deref());
+        }
+        if robots.nofollow {
+            // Don't follow any links from pages with a nofollow directive
+            links.clear();
+        }
 
-            // Parse HTML and extract links if this is an HTML file/URL
-            // In spider mode, we always try to extract links from HTML content
-            // In normal mode, check if saved file is HTML
-            let should_extract_links = if self.config.spider {
-                // In spider mode, check if URL points to HTML content
-                self.is_html_url(&url).await
-            } else {
-                // In normal mode, check if saved file is HTML
-                self.is_html_file(&file_path)
-            };
+        // Add links to queue (with current URL as parent)
+        // Note: We queue ALL links, even if already visited, so we can log them as rejected
+        if !links.is_empty() && !self.tracking_limit_reached() {
+            for (link, kind) in links {
+                let link = self.hsts_link(link);
+                self.queue
+                    .push_back((link, depth + 1, Some(url.clone()), kind));
+            }
+        }
 
-            if should_extract_links {
-                let links = self.extract_links(&file_path, &url).await?;
+        match fetched.path {
+            Some(path) => self.keep_or_delete(&url, path, output_dir).await,
+            None => Ok(None),
+        }
+    }
 
-                // Add links to queue (with current URL as parent)
-                // Note: We queue ALL links, even if already visited, so we can log them as rejected
-                for link in links {
-                    self.queue.push_back((link, depth + 1, Some(url.clone())));
-                }
+    /// `path`, saved for `url`, unless `delete_after` removes it now
+    async fn keep_or_delete(
+        &mut self,
+        url: &str,
+        path: PathBuf,
+        output_dir: &Path,
+    ) -> Result<Option<PathBuf>> {
+        if !self.config.delete_after {
+            return Ok(Some(path));
+        }
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => tracing::debug!(path = %path.display(), "Deleted after download"),
+        }
+        self.saved_paths.remove(&path);
+        self.fetched_urls.push(url.to_string());
+
+        // Remove the directories this leaves empty, up to the output directory
+        let parents = path.ancestors().skip(1);
+        for dir in parents.take_while(|dir| *dir != output_dir && dir.starts_with(output_dir)) {
+            if tokio::fs::remove_dir(dir).await.is_err() {
+                break; // Not empty
             }
+            self.created_dirs.remove(dir);
         }
+        Ok(None)
+    }
 
-        // Convert links after all files are downloaded
-        if let Some(ref converter) = self.link_converter {
-            converter.convert_all_links().await?;
+    /// Fetch `item` again after the retry delay of failed attempt number `attempt`
+    fn schedule_retry(&mut self, item: Admitted, attempt: u32, error: &Error) {
+        let delay = retry_delay(self.downloader.get_client().config(), attempt as usize);
+        tracing::info!(
+            url = %item.url,
+            attempt,
+            delay_ms = delay.as_millis(),
+            error = %error,
+            "Transient failure, retrying later"
+        );
+        self.stats.fetch_retries += 1;
+        self.retries.push(PendingRetry {
+            due: Instant::now() + delay,
+            attempts: attempt,
+            item,
+        });
+    }
+
+    /// Whether `depth` is past `max_depth` (0 means unlimited)
+    fn beyond_max_depth(&self, depth: usize) -> bool {
+        self.config.max_depth > 0 && depth >= self.config.max_depth
+    }
+
+    /// Links to follow from a fetched URL, and its robots directives
+    ///
+    /// A requisite fetched past `max_depth` is a leaf, except that a stylesheet
+    /// still brings in the images, fonts and stylesheets it references.
+    fn fetched_links(
+        &mut self,
+        fetched: &Fetched,
+        url: &str,
+        depth: usize,
+    ) -> Result<(Vec<Link>, RobotsDirectives)> {
+        let leaf = self.beyond_max_depth(depth);
+        let mut robots = fetched.robots;
+        let mut links = if leaf {
+            Vec::new()
+        } else {
+            self.header_links(&fetched.relations)
+        };
+        match &fetched.document {
+            Some(Document {
+                kind: DocumentKind::Html,
+                text,
+            }) if !leaf => {
+                let document = Html::parse_document(text);
+                robots = robots.merge(self.meta_robots(&document));
+                links.extend(self.extract_links(&document, url)?);
+            },
+            Some(Document {
+                kind: DocumentKind::Css,
+                text,
+            }) => links.extend(self.extract_css_links(text, url)?),
+            _ => {},
+        }
+        Ok((links, robots))
+    }
+
+    /// `link` with `https://` if it points to a known HSTS host
+    ///
+    /// Done before queueing, so filters, visited URLs and file names all see
+    /// the URL that is actually requested.
+    fn hsts_link(&self, link: String) -> String {
+        let Ok(mut url) = url::Url::parse(&link) else {
+            return link;
+        };
+        if self.downloader.get_client().hsts_upgrade(&mut url) {
+            url.into()
+        } else {
+            link
+        }
+    }
+
+    /// Whether `max_tracked_urls` URLs were visited
+    ///
+    /// The first time it is, the stop reason is recorded and the queue dropped.
+    fn tracking_limit_reached(&mut self) -> bool {
+        let Some(max) = self.config.max_tracked_urls else {
+            return false;
+        };
+        if self.visited.len() < max {
+            return false;
         }
+        if self.stats.stop_reason.is_none() {
+            let reason = CrawlStopReason::MaxTrackedUrls(max);
+            tracing::warn!(queued = self.queue.len(), "Crawl stopped: {reason}");
+            self.stats.stop_reason = Some(reason);
+            self.queue = VecDeque::new();
+        }
+        true
+    }
 
-        // Write rejected URLs to log file if configured
-        if let Some(ref log_path) = self.config.rejected_log {
-            if !self.rejected_urls.is_empty() {
-                use tokio::io::AsyncWriteExt;
-                let mut file = tokio::fs::File::create(log_path).await?;
+    /// Whether the download quota is used up
+    ///
+    /// The first time it is, the stop reason is recorded and the queue dropped.
+    fn quota_reached(&mut self) -> bool {
+        let Some(quota) = self.downloader.get_client().config().quota else {
+            return false;
+        };
+        if !self.downloader.quota_exceeded() {
+            return false;
+        }
+        if self.stats.stop_reason.is_none() {
+            let reason = CrawlStopReason::QuotaExceeded(quota);
+            tracing::warn!(queued = self.queue.len(), "Crawl stopped: {reason}");
+            self.stats.stop_reason = Some(reason);
+            self.queue = VecDeque::new();
+            self.retries.clear();
+        }
+        true
+    }
 
-                // Write CSV header
-                file.write_all(b"REASON\tU_URL\tU_SCHEME\tU_HOST\tU_PORT\tU_PATH\tU_PARAMS\tU_QUERY\tU_FRAGMENT\tP_URL\tP_SCHEME\tP_HOST\tP_PORT\tP_PATH\tP_PARAMS\tP_QUERY\tP_FRAGMENT\n")
-                    .await?;
+    /// Refresh the entry counts and memory estimate in the crawl stats
+    fn update_tracking_stats(&mut self) {
+        // Keys and values of `visited` point into `urls`
+        let visited_bytes = self.visited.capacity() * (2 * std::mem::size_of::<Arc<str>>() + 1);
+        let converter = self.link_converter.as_ref();
+        self.stats.tracked_urls = self.visited.len();
+        self.stats.converter_entries = converter.map_or(0, LinkConverter::len);
+        self.stats.tracked_bytes = self.urls.approx_bytes()
+            + visited_bytes
+            + converter.map_or(0, LinkConverter::approx_bytes);
+    }
 
-                // Write rejected URLs in CSV format
-                for (url, reason, parent_url) in &self.rejected_urls {
-                    if let Ok(csv_line) =
-                        self.format_rejected_url_csv(url, reason, parent_url.as_deref())
-                    {
-                        file.write_all(csv_line.as_bytes()).await?;
-                        file.write_all(b"\n").await?;
-                    }
+    /// Send a progress snapshot to the configured callback, if any
+    fn report_progress(&self, current_url: &str, started: Instant) {
+        if let Some(CrawlProgressCallback(ref callback)) = self.config.crawl_progress {
+            callback(CrawlProgress {
+                visited: self.visited.len(),
+                queued: self.queue.len(),
+                bytes_downloaded: self.stats.bytes_downloaded,
+                tracked_bytes: self.stats.tracked_bytes,
+                depths: self.depths.clone(),
+                current_url: current_url.to_string(),
+                elapsed: started.elapsed(),
+            });
+        }
+    }
+
+    /// Download every page listed in a sitemap or sitemap index, without crawling HTML
+    ///
+    /// Child sitemaps of an index are fetched up to [`MAX_SITEMAP_DEPTH`] levels
+    /// deep. Listed URLs go through the usual filters (accept/reject, domains,
+    /// robots.txt) and naming rules. Like timestamping, a URL whose `<lastmod>`
+    /// is not newer than the local file is skipped, and downloaded files take
+    /// `<lastmod>` as their modification time.
+    pub async fn download_from_sitemap(
+        &mut self,
+        sitemap_url: &str,
+        output_dir: &Path,
+    ) -> Result<Vec<PathBuf>> {
+        let sitemap_url = crate::prepare_url(sitemap_url)?;
+        let sitemap_url = sitemap_url.as_str();
+        self.log_in_once().await?;
+
+        // Host and parent checks are relative to the sitemap
+        self.base_url = Some(sitemap_url.to_string());
+
+        let mut downloaded_files = Vec::new();
+        for entry in self.collect_sitemap_entries(sitemap_url).await? {
+            if self.quota_reached() {
+                break;
+            }
+            if let Some(file_path) = self
+                .download_sitemap_entry(&entry, sitemap_url, output_dir)
+                .await?
+            {
+                downloaded_files.push(file_path);
+            }
+        }
+        self.update_tracking_stats();
+
+        self.write_rejected_log().await?;
+
+        Ok(self.current_paths(downloaded_files))
+    }
+
+    /// Page entries of a sitemap, following sitemap indexes breadth-first
+    ///
+    /// Only a failure of the first sitemap is an error; broken child sitemaps are skipped.
+    async fn collect_sitemap_entries(&mut self, sitemap_url: &str) -> Result<Vec<SitemapEntry>> {
+        let mut pending = VecDeque::from([(sitemap_url.to_string(), 0)]);
+        let mut fetched = HashSet::new();
+        let mut entries = Vec::new();
+
+        while let Some((url, depth)) = pending.pop_front() {
+            if !fetched.insert(url.clone()) {
+                continue;
+            }
+            self.stats.sitemaps_fetched += 1;
+
+            let sitemap = match self.downloader.download_to_memory(&url).await {
+                Ok(body) => crate::sitemap::parse_sitemap(self.saved_body(&body)),
+                Err(e) => Err(e),
+            };
+            match sitemap {
+                Ok(Sitemap::UrlSet(urls)) => entries.extend(urls),
+                Ok(Sitemap::Index(children)) if depth < MAX_SITEMAP_DEPTH => {
+                    pending.extend(children.into_iter().map(|child| (child.loc, depth + 1)));
+                },
+                Ok(Sitemap::Index(_)) => {
+                    tracing::warn!(url = %url, "Sitemap index nested too deeply, skipped");
+                },
+                Err(e) if depth == 0 => return Err(e),
+                Err(e) => tracing::warn!(url = %url, error = %e, "Skipping broken sitemap"),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Download one sitemap entry unless it is filtered out or unchanged since `<lastmod>`
+    async fn download_sitemap_entry(
+        &mut self,
+        entry: &SitemapEntry,
+        sitemap_url: &str,
+        output_dir: &Path,
+    ) -> Result<Option<PathBuf>> {
+        let url = &entry.loc;
+        let Some(key) = self.unvisited_key(url, Some(sitemap_url)) else {
+            return Ok(None);
+        };
+        if self.tracking_limit_reached() {
+            return Ok(None);
+        }
+        // Listed pages are filtered like links extracted from the sitemap (depth 1)
+        if !self
+            .should_download(url, 1, Some(sitemap_url), output_dir)
+            .await?
+        {
+            return Ok(None);
+        }
+        let key = self.urls.intern(&key);
+        let interned_url = self.urls.intern(url);
+        self.visited.insert(key.clone(), interned_url);
+
+        let lastmod = entry.lastmod.filter(|_| !self.config.spider);
+        if let Some(lastmod) = lastmod {
+            let local_path = self.url_to_local_path(url, output_dir, None)?;
+            if let Ok(modified) = tokio::fs::metadata(&local_path)
+                .await
+                .and_then(|m| m.modified())
+            {
+                if DateTime::<Utc>::from(modified) >= lastmod {
+                    tracing::info!(url = %url, "Local file is not older than sitemap lastmod, skipping");
+                    self.stats.sitemap_unchanged += 1;
+                    return Ok(None);
                 }
+                // Replace the outdated file instead of resuming into it
+                tokio::fs::remove_file(&local_path).await?;
+            }
+        }
+
+        let Some(Fetched {
+            path: Some(file_path),
+            ..
+        }) = self
+            .fetch_unless_rejected(url, output_dir, Some(sitemap_url), RequestKind::Page, 1, false)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        self.record_origin(key, url, Some(sitemap_url), 1, &file_path);
+        if let Some(lastmod) = lastmod {
+            let mtime = filetime::FileTime::from_unix_time(lastmod.timestamp(), 0);
+            if let Err(e) = filetime::set_file_mtime(&file_path, mtime) {
+                tracing::warn!(path = %file_path.display(), error = %e, "Failed to set modification time");
             }
         }
+        self.keep_or_delete(url, file_path, output_dir).await
+    }
 
-        Ok(downloaded_files)
+    /// Perform the configured form login before the first download
+    async fn log_in_once(&mut self) -> Result<()> {
+        // Cookies live in the shared client, so one login serves every crawl
+        if !self.logged_in {
+            if let Some(ref login) = self.config.form_login {
+                crate::form_login::perform_login(self.downloader.get_client(), login).await?;
+            }
+            self.logged_in = true;
+        }
+        Ok(())
+    }
+
+    /// Write rejected URLs to the `rejected_log` file, if configured
+    async fn write_rejected_log(&self) -> Result<()> {
+        let Some(ref log_path) = self.config.rejected_log else {
+            return Ok(());
+        };
+        if self.rejected_urls.is_empty() {
+            return Ok(());
+        }
+
+        use tokio::io::AsyncWriteExt;
+        let _handle = self.file_handles.open(1).await;
+        let mut file = tokio::fs::File::create(log_path).await?;
+
+        // Write CSV header
+        file.write_all(b"REASON\tU_URL\tU_SCHEME\tU_HOST\tU_PORT\tU_PATH\tU_PARAMS\tU_QUERY\tU_FRAGMENT\tP_URL\tP_SCHEME\tP_HOST\tP_PORT\tP_PATH\tP_PARAMS\tP_QUERY\tP_FRAGMENT\n")
+            .await?;
+
+        // Write rejected URLs in CSV format
+        for (url, reason, parent_url) in &self.rejected_urls {
+            if let Ok(csv_line) = self.format_rejected_url_csv(url, reason, parent_url.as_deref()) {
+                file.write_all(csv_line.as_bytes()).await?;
+                file.write_all(b"\n").await?;
+            }
+        }
+        Ok(())
     }
 
     /// Fetch and parse robots.txt for a given host
+    ///
+    /// Successful fetches and definitive 4xx answers are cached for the whole
+    /// crawl. Network errors and 5xx responses are cached only until a backoff
+    /// expires (doubling on each failure), and allow everything meanwhile.
     async fn fetch_robots_txt(
         &mut self,
         host: &str,
@@ -291,12 +1806,23 @@ impl RecursiveDownloader {
         output_dir: &Path,
     ) -> Option<crate::robots::RobotsTxt> {
         // Check cache first
-        let cache_key =
-            format!("{}://{}{}", scheme, host, port.map(|p| format!(":{p}")).unwrap_or_default());
+        let cache_key = robots_cache_key(scheme, host, port);
 
-        if let Some(cached) = self.robots_cache.get(&cache_key) {
-            return cached.clone();
-        }
+        let failures = match self.robots_cache.get(&cache_key) {
+            Some(RobotsCacheEntry::Final(robots)) => {
+                self.stats.robots_cache_hits += 1;
+                return robots.clone();
+            },
+            Some(RobotsCacheEntry::Unavailable { retry_at, failures }) => {
+                if Instant::now() < *retry_at {
+                    self.stats.robots_cache_hits += 1;
+                    return None;
+                }
+                self.stats.robots_retries += 1;
+                *failures
+            },
+            None => 0,
+        };
 
         // Build robots.txt URL
         let robots_url = if let Some(p) = port {
@@ -305,49 +1831,86 @@ impl RecursiveDownloader {
             format!("{scheme}://{host}/robots.txt")
         };
 
-        // Try to fetch robots.txt
+        self.stats.robots_fetches += 1;
+        let entry = match self.request_robots_txt(&robots_url, output_dir).await {
+            Ok(robots) => RobotsCacheEntry::Final(robots),
+            Err(reason) => {
+                let failures = failures + 1;
+                let delay = robots_retry_backoff(self.config.robots_retry_delay, failures);
+                tracing::warn!(url = %robots_url, %reason, failures, retry_in = ?delay, "robots.txt temporarily unavailable");
+                self.stats.robots_temporary_failures += 1;
+                RobotsCacheEntry::Unavailable {
+                    retry_at: Instant::now() + delay,
+                    failures,
+                }
+            },
+        };
+
+        let robots_txt = match entry {
+            RobotsCacheEntry::Final(ref robots) => robots.clone(),
+            RobotsCacheEntry::Unavailable { .. } => None,
+        };
+        self.robots_cache.insert(cache_key, entry);
+        robots_txt
+    }
+
+    /// Request robots.txt once
+    ///
+    /// Returns `Ok(Some)` for a parsed file, `Ok(None)` if the server definitively
+    /// has none (4xx), and `Err` with a reason for transient failures.
+    async fn request_robots_txt(
+        &self,
+        robots_url: &str,
+        output_dir: &Path,
+    ) -> std::result::Result<Option<crate::robots::RobotsTxt>, String> {
         // Use client().get() directly to avoid HEAD request (robots.txt doesn't need metadata)
-        let robots_txt = match self
-            .downloader
-            .get_client()
-            .client()
-            .get(&robots_url)
-            .send()
+        let client = self.downloader.get_client();
+        let response = client
+            .send(client.client().get(robots_url))
             .await
-        {
-            Ok(response) if response.status().is_success() => {
-                match response.bytes().await {
-                    Ok(bytes) => {
-                        // Parse the content
-                        let content = String::from_utf8_lossy(&bytes);
-
-                        // Save robots.txt to disk (unless in spider mode)
-                        if !self.config.spider {
-                            if let Ok(local_path) = self.url_to_local_path(&robots_url, output_dir)
-                            {
-                                // Create parent directories
-                                if let Some(parent) = local_path.parent() {
-                                    let _ = tokio::fs::create_dir_all(parent).await;
-                                }
-                                // Write the file
-                                let _ = tokio::fs::write(&local_path, bytes.as_ref()).await;
-                            }
-                        }
+            .map_err(|e| e.to_string())?;
 
-                        Some(crate::robots::RobotsTxt::parse(&content))
-                    },
-                    Err(_) => None,
+        let status = response.status();
+        if status.is_client_error() {
+            // robots.txt not found or forbidden - allow everything
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(format!("HTTP {}", status.as_u16()));
+        }
+
+        let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+        // Needed to crawl at all, so never cut off, but still counted
+        client.quota().record(bytes.len() as u64);
+        let content = String::from_utf8_lossy(&bytes);
+
+        // Save robots.txt to disk (unless in spider mode)
+        if !self.config.spider {
+            if let Ok(local_path) = self.url_to_local_path(robots_url, output_dir, None) {
+                let _handle = self.file_handles.open(1).await;
+                if let Err(e) = self.save_robots_txt(&local_path, output_dir, &bytes).await {
+                    tracing::warn!(path = %local_path.display(), error = %e, "robots.txt not saved");
                 }
-            },
-            _ => {
-                // robots.txt not found or error - allow everything
-                None
-            },
-        };
+            }
+        }
 
-        // Cache the result
-        self.robots_cache.insert(cache_key, robots_txt.clone());
-        robots_txt
+        Ok(Some(crate::robots::RobotsTxt::parse(&content)))
+    }
+
+    /// Save a fetched robots.txt at `local_path`, without following links planted there
+    async fn save_robots_txt(
+        &self,
+        local_path: &Path,
+        output_dir: &Path,
+        body: &[u8],
+    ) -> Result<()> {
+        let follow_symlinks = self.downloader.get_client().config().follow_output_symlinks;
+        if let Some(parent) = local_path.parent() {
+            crate::create_dirs_within(output_dir, parent, follow_symlinks).await?;
+        }
+        crate::replace_links(local_path, follow_symlinks).await?;
+        tokio::fs::write(local_path, body).await?;
+        Ok(())
     }
 
     /// Check if URL should be downloaded
@@ -358,8 +1921,15 @@ impl RecursiveDownloader {
         parent_url: Option<&str>,
         output_dir: &Path,
     ) -> Result<bool> {
-        let parsed_url =
-            Url::parse(url).map_err(|e| Error::ConfigError(format!("Invalid URL: {e}")))?;
+        // URLs that can't be fetched are rejected rather than failing the crawl
+        let Ok(parsed_url) = Url::parse(url) else {
+            self.log_rejected_url(url, "Invalid URL", parent_url);
+            return Ok(false);
+        };
+        if !matches!(parsed_url.scheme(), "http" | "https") {
+            self.log_rejected_url(url, "Unsupported scheme", parent_url);
+            return Ok(false);
+        }
 
         // Check HTTPS-only mode
         // Note: --https-only only applies to extracted links (depth > 0), not the starting URL
@@ -372,9 +1942,10 @@ impl RecursiveDownloader {
             return Ok(false);
         }
 
-        let domain = parsed_url
-            .host_str()
-            .ok_or_else(|| Error::ConfigError("URL has no host".to_string()))?;
+        let Some(domain) = parsed_url.host_str() else {
+            self.log_rejected_url(url, "URL has no host", parent_url);
+            return Ok(false);
+        };
 
         // Check robots.txt (only for depth > 0, i.e., extracted links, not the starting URL)
         if depth > 0 {
@@ -439,30 +2010,25 @@ impl RecursiveDownloader {
             return Ok(false);
         }
 
-        // Check extension filters
-        let path = parsed_url.path();
-        if let Some(extension) = Path::new(path).extension() {
-            let ext = extension.to_string_lossy().to_lowercase();
-
-            if !self.config.accept_extensions.is_empty()
-                && !self.config.accept_extensions.contains(&ext)
+        // Check URL regex filters (only for extracted links, not starting URL)
+        if depth > 0 {
+            if let Some(reason) =
+                regex_rejection(self.accept_regex.as_ref(), self.reject_regex.as_ref(), url)
             {
-                self.log_rejected_url(
-                    url,
-                    &format!("Extension not in accepted list: {ext}"),
-                    parent_url,
-                );
+                self.log_rejected_url(url, reason, parent_url);
                 return Ok(false);
             }
+        }
 
-            if self.config.reject_extensions.contains(&ext) {
-                self.log_rejected_url(
-                    url,
-                    &format!("Extension in rejected list: {ext}"),
-                    parent_url,
-                );
-                return Ok(false);
-            }
+        // Check extension filters
+        let path = parsed_url.path();
+        if let Some(reason) = extension_rejection(
+            &self.config.accept_extensions,
+            &self.config.reject_extensions,
+            path,
+        ) {
+            self.log_rejected_url(url, &reason, parent_url);
+            return Ok(false);
         }
 
         // Check directory filters
@@ -543,174 +2109,449 @@ impl RecursiveDownloader {
         }
     }
 
-    /// Download and save a file (or just check in spider mode)
-    async fn download_and_save(
+    /// Normalized key for `url`, or `None` if a URL with the same key was already visited
+    fn unvisited_key(&mut self, url: &str, parent_url: Option<&str>) -> Option<String> {
+        let key = self.deduper.normalize(url);
+        let Some(first_url) = self.visited.get(key.as_str()) else {
+            return Some(key);
+        };
+
+        if **first_url != *url {
+            tracing::debug!(url = %url, visited = %first_url, "Duplicate URL skipped");
+            self.stats.duplicate_fetches_avoided += 1;
+        }
+        // Log this as a rejection if it has a parent (i.e., it's a link from another page)
+        // This prevents logging the starting URL when it's first queued
+        if let Some(parent) = parent_url {
+            let origin = self
+                .origin_paths
+                .get(key.as_str())
+                .and_then(|path| self.origins.get_mut(path));
+            if let Some(origin) = origin {
+                origin.add_referrer(parent);
+            }
+            self.log_rejected_url(url, "Already visited (recursive loop)", parent_url);
+        }
+        None
+    }
+
+    /// Remember where the URL (with normalized `key`) of a saved file was found
+    fn record_origin(
+        &mut self,
+        key: Arc<str>,
+        url: &str,
+        parent_url: Option<&str>,
+        depth: usize,
+        path: &Path,
+    ) -> &mut Origin {
+        self.origin_paths.insert(key, path.to_path_buf());
+        self.origins
+            .entry(path.to_path_buf())
+            .or_insert_with(|| Origin::new(url, parent_url, depth))
+    }
+
+    /// Body of a saved file or memory download, after the header preamble
+    /// written with `save_headers`
+    fn saved_body<'a>(&self, saved: &'a [u8]) -> &'a [u8] {
+        if self.downloader.get_client().config().save_headers {
+            &saved[crate::saved_headers::split(saved).1..]
+        } else {
+            saved
+        }
+    }
+
+    /// Learn session parameters from the body saved for `url` (with `session_param_detection`)
+    async fn detect_session_param(&mut self, url: &str, file_path: &Path) {
+        if !self.config.session_param_detection || self.config.spider {
+            return;
+        }
+
+        let handle = self.file_handles.open(1).await;
+        let Ok(body) = tokio::fs::read(file_path).await else {
+            return;
+        };
+        drop(handle);
+        if let Some(param) = self.deduper.record_body(url, self.saved_body(&body)) {
+            tracing::info!(param = %param, url = %url, "Query parameter detected as session id");
+            self.stats.session_params_detected += 1;
+            // Variants queued before the parameter was known now share this key
+            let key = self.urls.intern(&self.deduper.normalize(url));
+            let url = self.urls.intern(url);
+            self.visited.entry(key).or_insert(url);
+        }
+    }
+
+    /// Fetch a URL, or `None` if the response was rejected by its final name or failed
+    ///
+    /// Its requests carry the preset headers and priority of `kind`. With
+    /// `may_retry`, a transient failure is returned for the caller to retry.
+    #[allow(clippy::too_many_arguments)] // The request hints need the item's kind, parent and depth
+    async fn fetch_unless_rejected(
         &mut self,
         url: &str,
         output_dir: &Path,
-        _depth: usize,
-    ) -> Result<PathBuf> {
-        // In spider mode, just check if URL exists without downloading
+        parent_url: Option<&str>,
+        kind: RequestKind,
+        depth: usize,
+        may_retry: bool,
+    ) -> Result<Option<Fetched>> {
+        let urgency = if kind.is_requisite() {
+            self.config.requisite_priority
+        } else {
+            self.config.page_priority
+        };
+        self.downloader.set_request_hints(Some(RequestHints {
+            kind,
+            initiator: parent_url.and_then(|parent| Url::parse(parent).ok()),
+            urgency,
+            depth,
+        }));
+        // The hints stay until the partial file of a timed out body is found again
+        let fetched = match self.fetch(url, output_dir, may_retry).await {
+            Err(Error::ResponseRejected(reason)) => {
+                tracing::info!(url = %url, reason = %reason, "Rejected after response headers");
+                self.stats.late_rejections += 1;
+                self.log_rejected_url(url, &reason, parent_url);
+                Ok(None)
+            },
+            Err(e) if matches!(e.root(), Error::BodyDurationExceeded(_)) => {
+                tracing::warn!(url = %url, error = %e, "Body timed out - skipped");
+                self.stats.timed_out += 1;
+                self.timed_out_urls.push(url.to_string());
+                self.remove_partial(url, output_dir).await;
+                self.log_rejected_url(url, &format!("Timed out: {e}"), parent_url);
+                Ok(None)
+            },
+            Err(Error::UnsafeOutputPath(path)) => {
+                tracing::warn!(url = %url, path = %path.display(), "Skipped for safety: symlinked directory in the output tree");
+                self.stats.unsafe_paths_skipped += 1;
+                let reason = format!("Symlinked directory {}", path.display());
+                self.log_rejected_url(url, &reason, parent_url);
+                Ok(None)
+            },
+            // Cut off by `quota_hard`: the crawl stops, keeping the partial file
+            Err(e) if matches!(e.root(), Error::QuotaExceeded(_)) => {
+                tracing::warn!(url = %url, "Download quota exceeded during the download");
+                self.quota_reached();
+                Ok(None)
+            },
+            fetched => fetched,
+        };
+        self.downloader.set_request_hints(None);
+        fetched
+    }
+
+    /// Delete the partial file an abandoned download of `url` left behind
+    async fn remove_partial(&self, url: &str, output_dir: &Path) {
         if self.config.spider {
-            // Spider mode two-phase approach (matches GNU wget behavior):
-            // Phase 1: Always send HEAD first to check status and content-type
-            // Phase 2: Only send GET if HEAD returns 200 OK AND content is HTML
-            //
-            // This ensures broken links (404) only get HEAD, not GET
+            return;
+        }
+        let Ok(path) = self.url_to_local_path(url, output_dir, None) else {
+            return;
+        };
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                tracing::warn!(path = %path.display(), error = %e, "Failed to remove partial file");
+            },
+            _ => {},
+        }
+    }
+
+    /// Fetch a URL: the only step of the crawl that differs between spider and download mode
+    ///
+    /// Download mode saves the body; spider mode sends HEAD and only GETs HTML
+    /// pages into memory. Either way an HTML body is returned for link extraction,
+    /// so both modes select the same URLs. An error status is recorded as a
+    /// broken link and returns `None`, unless it is transient and `may_retry`.
+    async fn fetch(
+        &mut self,
+        url: &str,
+        output_dir: &Path,
+        may_retry: bool,
+    ) -> Result<Option<Fetched>> {
+        self.wait_for_host(url).await;
+        let fetched = if self.config.spider {
+            self.probe(url, output_dir).await
+        } else {
+            self.download_and_save(url, output_dir).await
+        };
+        match fetched {
+            Err(e)
+                if may_retry && is_transient(&e, &self.downloader.get_client().config().retry) =>
+            {
+                Err(e)
+            },
+            Err(e) => match e.root() {
+                Error::InvalidStatus(status) => {
+                    tracing::info!(url = %url, status, "Broken link");
+                    self.broken_links.push((url.to_string(), *status));
+                    Ok(None)
+                },
+                _ => Err(e),
+            },
+            fetched => fetched.map(Some),
+        }
+    }
 
+    /// Spider mode: HEAD, then GET into memory only if the response is HTML
+    ///
+    /// This keeps broken links and non-HTML files to a single HEAD (like GNU
+    /// wget). A transient HEAD failure (network error or 5xx) is retried once.
+    async fn probe(&mut self, url: &str, output_dir: &Path) -> Result<Fetched> {
+        let mut attempt = 0;
+        let metadata = loop {
+            attempt += 1;
             match self.downloader.get_client().get_metadata(url).await {
+                Ok(metadata) if metadata.status_code < 500 => break metadata,
+                _ if attempt == 1 => self.stats.metadata_probe_retries += 1,
                 Ok(metadata) => {
-                    // Check if URL returned success status
-                    if metadata.status_code >= 400 {
-                        // Track broken link (4xx/5xx errors)
-                        self.broken_links
-                            .push((url.to_string(), metadata.status_code));
-                        // Cache failure - no GET needed for broken links
-                        self.spider_content_cache.insert(url.to_string(), None);
-                        return Ok(PathBuf::from("/dev/null"));
-                    }
-
-                    // HEAD returned 200 OK - check if we need to GET (for HTML content only)
-                    let is_html = if let Some(ref content_type) = metadata.content_type {
-                        content_type.contains("text/html")
-                    } else {
-                        // No content-type - check URL extension
-                        self.is_html_url_fast(url)
-                    };
-
-                    if is_html {
-                        // HTML content - send GET to extract links
-                        match self.downloader.download_to_memory(url).await {
-                            Ok(bytes) => {
-                                // Cache the content for link extraction
-                                let content = String::from_utf8_lossy(&bytes).to_string();
-                                self.spider_content_cache
-                                    .insert(url.to_string(), Some(content));
-                                Ok(PathBuf::from("/dev/null"))
-                            },
-                            Err(e) => {
-                                // GET failed after successful HEAD - track as error
-                                if let crate::Error::InvalidStatus(status_code) = &e {
-                                    self.broken_links.push((url.to_string(), *status_code));
-                                }
-                                self.spider_content_cache.insert(url.to_string(), None);
-                                Ok(PathBuf::from("/dev/null"))
-                            },
-                        }
-                    } else {
-                        // Non-HTML file - HEAD only, no GET needed
-                        self.spider_content_cache.insert(url.to_string(), None);
-                        Ok(PathBuf::from("/dev/null"))
-                    }
+                    self.stats.metadata_probe_failures += 1;
+                    return Err(Error::InvalidStatus(metadata.status_code));
                 },
                 Err(e) => {
-                    // HEAD request failed - track as broken link
-                    if let crate::Error::InvalidStatus(status_code) = &e {
-                        self.broken_links.push((url.to_string(), *status_code));
-                    }
-                    // Cache failure - no GET needed
-                    self.spider_content_cache.insert(url.to_string(), None);
-                    Ok(PathBuf::from("/dev/null"))
+                    self.stats.metadata_probe_failures += 1;
+                    return Err(e);
                 },
             }
-        } else {
-            // Normal mode - download and save
-            // Generate local file path
-            let local_path = self.url_to_local_path(url, output_dir)?;
-
-            // Create parent directories
-            // Handle the case where a file exists with the same name as a directory we need
-            // This can happen with redirects: /directory (saved as file) -> /directory/ (needs directory)
-            if let Some(parent) = local_path.parent() {
-                match tokio::fs::create_dir_all(parent).await {
-                    Ok(()) => {},
-                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-                        // Check if parent exists as a file (not a directory)
-                        if let Ok(metadata) = tokio::fs::metadata(parent).await {
-                            if metadata.is_file() {
-                                // Parent exists as a file - remove it and create directory
-                                tracing::warn!(
-                                    path = %parent.display(),
-                                    "Removing file to create directory (likely due to redirect from /path to /path/)"
-                                );
-                                tokio::fs::remove_file(parent).await?;
-                                tokio::fs::create_dir_all(parent).await?;
-                            }
-                            // If it's already a directory, we're good
-                        } else {
-                            // Metadata failed - propagate original error
-                            return Err(e.into());
-                        }
-                    },
-                    Err(e) => return Err(e.into()),
-                }
-            }
+        };
+        if metadata.status_code >= 400 {
+            return Err(Error::InvalidStatus(metadata.status_code));
+        }
+
+        let document = match self.document_kind(url, &metadata, output_dir) {
+            Some(kind) => {
+                let bytes = self.downloader.download_to_memory_once(url).await?;
+                self.stats.bytes_downloaded += bytes.len() as u64;
+                Some(Document::new(kind, self.saved_body(&bytes)))
+            },
+            None => None,
+        };
+        Ok(Fetched {
+            path: None,
+            document,
+            relations: metadata.links,
+            robots: self.header_robots(&metadata.headers),
+        })
+    }
 
-            // Download to file
-            self.downloader
-                .download_to_file(url, local_path.clone())
-                .await?;
+    /// Download mode: save the body under `output_dir`
+    async fn download_and_save(&mut self, url: &str, output_dir: &Path) -> Result<Fetched> {
+        let local_path = self.url_to_local_path(url, output_dir, None)?;
 
-            Ok(local_path)
+        #[cfg(feature = "pack")]
+        if let Some(threshold) = self.config.small_file_threshold.filter(|_| self.packing()) {
+            return self
+                .download_to_pack(url, local_path, output_dir, threshold)
+                .await;
         }
-    }
 
-    /// Convert URL to local file path
-    fn url_to_local_path(&self, url: &str, output_dir: &Path) -> Result<PathBuf> {
-        let parsed =
-            Url::parse(url).map_err(|e| Error::ConfigError(format!("Invalid URL: {e}")))?;
+        if let Some((from, to)) = self.create_parent_dirs(&local_path, output_dir).await? {
+            self.record_move(from, to);
+        }
 
-        let mut path = output_dir.to_path_buf();
+        // Another URL of this crawl (e.g. a query variant) already wrote this file:
+        // overwrite it like wget -r instead of resuming into it
+        if self.saved_paths.contains(&local_path) && local_path.is_file() {
+            tokio::fs::remove_file(&local_path).await?;
+        }
+        let follow_symlinks = self.downloader.get_client().config().follow_output_symlinks;
+        crate::replace_links(&local_path, follow_symlinks).await?;
+
+        let handle = self.file_handles.open(1).await;
+        let result = self
+            .downloader
+            .download_to_file_once(url, local_path.clone())
+            .await?;
+        drop(handle);
+        self.saved_paths.insert(local_path.clone());
+        self.stats.bytes_downloaded += result.data.total_bytes;
+
+        // The file may have been renamed (-E, Content-Disposition)
+        let path = result.data.file_path.unwrap_or(local_path);
+        let document = match self.document_kind(url, &result.metadata, output_dir) {
+            Some(kind) => {
+                let _handle = self.file_handles.open(1).await;
+                let saved = tokio::fs::read(&path).await?;
+                Some(Document::new(kind, self.saved_body(&saved)))
+            },
+            None => None,
+        };
+        Ok(Fetched {
+            path: Some(path),
+            document,
+            relations: result.metadata.links,
+            robots: self.header_robots(&result.metadata.headers),
+        })
+    }
 
-        // If no_directories is set, just use the filename without any directory structure
-        if self.config.no_directories {
-            // Extract just the filename from the URL
-            let filename = parsed
-                .path_segments()
-                .and_then(|mut segments| segments.next_back())
-                .filter(|name| !name.is_empty())
-                .unwrap_or("index.html");
+    /// Pack mode: add a body of at most `threshold` bytes to the pack, save a larger one
+    ///
+    /// Only the content is kept: the body is streamed, so timestamping, renames
+    /// (`-E`, Content-Disposition) and filters on the final name don't apply.
+    /// A packed file has no path in the returned `Fetched`.
+    #[cfg(feature = "pack")]
+    async fn download_to_pack(
+        &mut self,
+        url: &str,
+        local_path: PathBuf,
+        output_dir: &Path,
+        threshold: u64,
+    ) -> Result<Fetched> {
+        use futures::StreamExt;
+        use tokio::io::AsyncWriteExt;
 
-            path.push(filename);
-        } else {
-            // Add host directory (unless no_host_directories is set)
-            if !self.config.no_host_directories {
-                if let Some(host) = parsed.host_str() {
-                    path.push(host);
+        let (metadata, mut chunks) = self
+            .downloader
+            .download_stream_with_metadata(url, None)
+            .await?;
+        let document_kind = self.document_kind(url, &metadata, output_dir);
+        let file_handles = self.file_handles.clone();
+        let mut body = Vec::new();
+        let mut received = 0u64;
+        // Once the body outgrows the pack: the open file and its handle
+        let mut saved: Option<(tokio::fs::File, _)> = None;
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            received += chunk.len() as u64;
+            if let Some((file, _)) = saved.as_mut() {
+                file.write_all(&chunk).await?;
+                continue;
+            }
+            body.extend_from_slice(&chunk);
+            if received > threshold {
+                // Too large for the pack: the rest streams to the file
+                if let Some((from, to)) = self.create_parent_dirs(&local_path, output_dir).await? {
+                    self.record_move(from, to);
                 }
+                let follow_symlinks = self.downloader.get_client().config().follow_output_symlinks;
+                crate::replace_links(&local_path, follow_symlinks).await?;
+                let handle = file_handles.open(1).await;
+                let mut file = tokio::fs::File::create(&local_path).await?;
+                file.write_all(&body).await?;
+                body = Vec::new();
+                saved = Some((file, handle));
             }
+        }
+        self.stats.bytes_downloaded += received;
 
-            // Add path components
-            if let Some(segments) = parsed.path_segments() {
-                for segment in segments {
-                    if !segment.is_empty() {
-                        path.push(segment);
-                    }
-                }
+        let Some((mut file, handle)) = saved else {
+            if let Some(pack) = self.pack.as_mut() {
+                pack.append(url, &local_path, &body).await?;
             }
+            return Ok(Fetched {
+                path: None,
+                document: document_kind.map(|kind| Document::new(kind, &body)),
+                relations: metadata.links,
+                robots: self.header_robots(&metadata.headers),
+            });
+        };
+        file.flush().await?;
+        drop(file);
+        drop(handle);
+        self.saved_paths.insert(local_path.clone());
+
+        let document = match document_kind {
+            Some(kind) => {
+                let _handle = file_handles.open(1).await;
+                Some(Document::new(kind, &tokio::fs::read(&local_path).await?))
+            },
+            None => None,
+        };
+        Ok(Fetched {
+            path: Some(local_path),
+            document,
+            relations: metadata.links,
+            robots: self.header_robots(&metadata.headers),
+        })
+    }
+
+    /// [`create_parent_dirs`] for a file of the crawl into `output_dir`
+    async fn create_parent_dirs(
+        &mut self,
+        local_path: &Path,
+        output_dir: &Path,
+    ) -> Result<Option<(PathBuf, PathBuf)>> {
+        let follow_symlinks = self.downloader.get_client().config().follow_output_symlinks;
+        create_parent_dirs(local_path, output_dir, follow_symlinks, &mut self.created_dirs).await
+    }
+
+    /// Point the crawl's bookkeeping at a saved file's new location
+    fn record_move(&mut self, from: PathBuf, to: PathBuf) {
+        if self.saved_paths.remove(&from) {
+            self.saved_paths.insert(to.clone());
+        }
+        if let Some(ref mut converter) = self.link_converter {
+            converter.relocate_file(&from, &to);
+        }
+        if let Some(origin) = self.origins.remove(&from) {
+            self.origins.insert(to.clone(), origin);
+        }
+        for path in self.origin_paths.values_mut().filter(|path| **path == from) {
+            path.clone_from(&to);
+        }
+        self.moved_paths.insert(from, to);
+    }
+
+    /// `files` with every moved file replaced by its new location
+    fn current_paths(&self, files: Vec<PathBuf>) -> Vec<PathBuf> {
+        files
+            .into_iter()
+            .map(|path| self.moved_paths.get(&path).cloned().unwrap_or(path))
+            .collect()
+    }
+
+    /// Convert URL to local file path
+    ///
+    /// The `path_mapper` decides first; `metadata` is passed on to it.
+    fn url_to_local_path(
+        &self,
+        url: &str,
+        output_dir: &Path,
+        metadata: Option<&ResourceMetadata>,
+    ) -> Result<PathBuf> {
+        let parsed =
+            Url::parse(url).map_err(|e| Error::ConfigError(format!("Invalid URL: {e}")))?;
+        let path = self.default_local_path(&parsed, output_dir);
+        let Some(ref path_mapper) = self.config.path_mapper else {
+            return Ok(path);
+        };
+
+        let context = PathMapperContext {
+            output_dir,
+            metadata,
+            is_requisite: self
+                .downloader
+                .get_client()
+                .request_hints()
+                .is_some_and(|hints| hints.kind.is_requisite()),
+            default_path: &path,
+        };
+        let Some(mapped) = (path_mapper.0)(&parsed, &context) else {
+            return Ok(path);
+        };
+        let Some(mapped) = crate::naming::path_within(output_dir, &mapped) else {
+            tracing::warn!(url = %url, path = %mapped.display(), "Path mapper left the output directory - using the default path");
+            return Ok(path);
+        };
+        if self.config.adjust_extension && adjusted_extension(&mapped).is_some() {
+            tracing::debug!(path = %mapped.display(), "Not adjusting the extension of a mapped path");
         }
+        Ok(mapped)
+    }
 
-        // If path ends with /, add index.html
-        if path.is_dir() || url.ends_with('/') {
-            path.push("index.html");
+    /// Local path of `url` from the layout options, `-E` and filename limits
+    fn default_local_path(&self, url: &Url, output_dir: &Path) -> PathBuf {
+        let mut path = self.config.local_path(url, output_dir);
+
+        // A directory already exists where the file would go (e.g. /a saved after /a/b)
+        if path.is_dir() {
+            path.push(DEFAULT_PAGE);
         }
 
         // Adjust extension if requested (-E flag)
-        // Add .html extension to files that don't have one but are HTML/CSS content
         if self.config.adjust_extension {
-            let current_ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-
-            // If the file doesn't already have .html extension, add it
-            // This matches wget -E behavior: file.php -> file.php.html
-            if !current_ext.is_empty() && current_ext != "html" && current_ext != "htm" {
-                // Only add .html if it looks like server-side script or no extension
-                // Common server-side extensions: php, asp, aspx, jsp, cgi, pl
-                if matches!(
-                    current_ext,
-                    "php" | "asp" | "aspx" | "jsp" | "cgi" | "pl" | "py" | "rb"
-                ) {
-                    path.set_extension(format!("{current_ext}.html"));
-                }
+            if let Some(adjusted) = adjusted_extension(&path) {
+                path = adjusted;
             }
         }
 
@@ -761,151 +2602,129 @@ impl RecursiveDownloader {
             tracing::debug!(original_len, truncated_len, "Truncated filename to fit system limits");
         }
 
-        Ok(path)
+        path
     }
 
-    /// Check if file is HTML
-    fn is_html_file(&self, path: &Path) -> bool {
-        if let Some(ext) = path.extension() {
-            let ext = ext.to_string_lossy().to_lowercase();
-            matches!(ext.as_str(), "html" | "htm" | "xhtml")
-        } else {
-            false
-        }
-    }
-
-    /// Check if URL points to HTML content (fast path - extension only)
-    /// Used in spider mode to avoid duplicate HEAD requests
-    fn is_html_url_fast(&self, url: &str) -> bool {
-        // Check URL extension first (fast path - avoids HEAD request)
-        // This matches GNU wget behavior: only send HEAD if content type is uncertain
-        if url.ends_with(".html") || url.ends_with(".htm") || url.ends_with('/') {
-            return true;
-        }
-
-        // Non-HTML extensions (skip HEAD request)
-        if url.ends_with(".jpg")
-            || url.ends_with(".jpeg")
-            || url.ends_with(".png")
-            || url.ends_with(".gif")
-            || url.ends_with(".webp")
-            || url.ends_with(".css")
-            || url.ends_with(".js")
-            || url.ends_with(".ico")
-            || url.ends_with(".pdf")
-            || url.ends_with(".zip")
-            || url.ends_with(".tar")
-            || url.ends_with(".gz")
-            || url.ends_with(".txt")
-        {
-            return false;
+    /// Whether a response should be parsed for links, and as what
+    ///
+    /// Decided by Content-Type; without one, by the extension of the local name
+    /// the URL maps to (so `dir/` counts as `index.html`). Spider and download
+    /// mode use the same rule.
+    fn document_kind(
+        &self,
+        url: &str,
+        metadata: &ResourceMetadata,
+        output_dir: &Path,
+    ) -> Option<DocumentKind> {
+        if let Some(ref content_type) = metadata.content_type {
+            return if content_type.contains("text/html")
+                || content_type.contains("application/xhtml+xml")
+            {
+                Some(DocumentKind::Html)
+            } else if content_type.contains("text/css") {
+                Some(DocumentKind::Css)
+            } else {
+                None
+            };
         }
-
-        // Default: treat as HTML if uncertain (matches wget behavior)
-        // In spider mode, we'll send GET and check actual content
-        true
-    }
-
-    /// Check if URL points to HTML content (for spider mode)
-    async fn is_html_url(&self, url: &str) -> bool {
-        // Check URL extension first (fast path - avoids HEAD request)
-        // This matches GNU wget behavior: only send HEAD if content type is uncertain
-        if url.ends_with(".html") || url.ends_with(".htm") || url.ends_with('/') {
-            return true;
-        }
-
-        // Non-HTML extensions (skip HEAD request)
-        if url.ends_with(".jpg")
-            || url.ends_with(".jpeg")
-            || url.ends_with(".png")
-            || url.ends_with(".gif")
-            || url.ends_with(".webp")
-            || url.ends_with(".css")
-            || url.ends_with(".js")
-            || url.ends_with(".ico")
-            || url.ends_with(".pdf")
-            || url.ends_with(".zip")
-            || url.ends_with(".tar")
-            || url.ends_with(".gz")
-        {
-            return false;
+        let path = self
+            .url_to_local_path(url, output_dir, Some(metadata))
+            .ok()?;
+        match path.extension()?.to_string_lossy().to_lowercase().as_str() {
+            "html" | "htm" | "xhtml" => Some(DocumentKind::Html),
+            "css" => Some(DocumentKind::Css),
+            _ => None,
         }
+    }
 
-        // Uncertain - only NOW send HEAD request to check content type
-        if let Ok(metadata) = self.downloader.get_client().get_metadata(url).await {
-            if let Some(content_type) = metadata.content_type {
-                return content_type.contains("text/html");
-            }
-        }
+    /// Directives of the `X-Robots-Tag` headers of a response
+    fn header_robots(&self, headers: &reqwest::header::HeaderMap) -> RobotsDirectives {
+        let values = headers
+            .get_all("x-robots-tag")
+            .iter()
+            .filter_map(|value| value.to_str().ok());
+        RobotsDirectives::from_headers(values, &self.downloader.get_client().config().user_agent)
+    }
 
-        // Default: treat as HTML if uncertain (matches wget behavior)
-        true
+    /// Directives of the robots `<meta>` tags of an HTML document
+    fn meta_robots(&self, document: &Html) -> RobotsDirectives {
+        let Ok(selector) = Selector::parse("meta[name][content]") else {
+            return RobotsDirectives::default();
+        };
+        let tags = document.select(&selector).filter_map(|element| {
+            Some((element.value().attr("name")?, element.value().attr("content")?))
+        });
+        RobotsDirectives::from_meta(tags, &self.downloader.get_client().config().user_agent)
     }
 
-    /// Check if HTML document has meta robots nofollow directive
-    fn has_meta_robots_nofollow(&self, document: &Html) -> bool {
-        // Parse meta tags with name="robots" (case-insensitive)
-        if let Ok(selector) = Selector::parse("meta[name]") {
-            for element in document.select(&selector) {
-                if let Some(name) = element.value().attr("name") {
-                    // Check if name attribute is "robots" (case-insensitive)
-                    if name.eq_ignore_ascii_case("robots") {
-                        if let Some(content) = element.value().attr("content") {
-                            // Parse comma-separated values in content attribute
-                            // Check for "nofollow" (case-insensitive)
-                            let directives: Vec<&str> = content.split(',').map(str::trim).collect();
-
-                            for directive in directives {
-                                if directive.eq_ignore_ascii_case("nofollow") {
-                                    return true;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    /// Note that the page at `url` (saved at `path`, if it was) asked not to be indexed
+    fn record_noindex(&mut self, url: &str, path: Option<&Path>) {
+        tracing::debug!(url = %url, "Page has a noindex directive");
+        self.stats.noindex_pages += 1;
+        self.noindex_pages.push(url.to_string());
+        if let Some(origin) = path.and_then(|path| self.origins.get_mut(path)) {
+            origin.noindex = true;
         }
-        false
     }
 
-    /// Extract links from HTML file (or URL in spider mode)
-    async fn extract_links(&self, file_path: &Path, base_url: &str) -> Result<Vec<String>> {
-        // In spider mode, fetch the content from URL instead of file
-        let content = if self.config.spider {
-            // Check cache first - content was already downloaded in download_and_save()
-            if let Some(cached) = self.spider_content_cache.get(base_url) {
-                if let Some(content) = cached {
-                    content.clone()
+    /// Links to follow from a response's Link header relations, with what they fetch
+    ///
+    /// Pagination (`next`/`prev`) when `follow_pagination` is set, and `preload`
+    /// targets as page requisites.
+    fn header_links(&self, relations: &[LinkRelation]) -> Vec<(String, RequestKind)> {
+        relations
+            .iter()
+            .filter_map(|relation| {
+                if self.config.follow_pagination && is_pagination_rel(&relation.rel) {
+                    Some((relation.url.clone(), RequestKind::Page))
+                } else if self.config.page_requisites && relation.rel == "preload" {
+                    let destination = relation
+                        .params
+                        .iter()
+                        .find(|(name, _)| name == "as")
+                        .map(|(_, value)| value.as_str());
+                    let kind = RequestKind::from_preload_destination(destination);
+                    Some((relation.url.clone(), kind))
                 } else {
-                    return Ok(Vec::new()); // Download failed, already tracked
-                }
-            } else {
-                // Cache miss (shouldn't happen in normal flow, but handle gracefully)
-                match self.downloader.download_to_memory(base_url).await {
-                    Ok(bytes) => String::from_utf8_lossy(&bytes).to_string(),
-                    Err(_) => return Ok(Vec::new()), // Can't extract links if download failed
+                    None
                 }
-            }
-        } else {
-            tokio::fs::read_to_string(file_path).await?
-        };
-
-        let document = Html::parse_document(&content);
-
-        // Check for meta robots nofollow directive
-        if self.has_meta_robots_nofollow(&document) {
-            // Don't extract any links from pages with nofollow directive
-            return Ok(Vec::new());
-        }
+            })
+            .collect()
+    }
 
+    /// Extract the links to follow from an HTML page, with what they fetch
+    fn extract_links(
+        &mut self,
+        document: &Html,
+        base_url: &str,
+    ) -> Result<Vec<(String, RequestKind)>> {
+        let base = Url::parse(base_url)
+            .map_err(|e| Error::ConfigError(format!("Invalid base URL: {e}")))?;
         let mut links = Vec::new();
 
         // Extract from <a> tags
         if let Ok(selector) = Selector::parse("a[href]") {
             for element in document.select(&selector) {
+                if !self.config.follow_pagination
+                    && element.value().attr("rel").is_some_and(is_pagination_rel)
+                {
+                    continue;
+                }
                 if let Some(href) = element.value().attr("href") {
-                    if let Ok(absolute_url) = self.resolve_url(base_url, href) {
-                        links.push(absolute_url);
+                    self.push_link(&mut links, &base, href, RequestKind::Page);
+                }
+            }
+        }
+
+        // Pagination from <link rel="next"> / <link rel="prev">
+        if self.config.follow_pagination {
+            if let Ok(selector) = Selector::parse("link[rel][href]") {
+                for element in document.select(&selector) {
+                    if !is_pagination_rel(element.value().attr("rel").unwrap_or_default()) {
+                        continue;
+                    }
+                    if let Some(href) = element.value().attr("href") {
+                        self.push_link(&mut links, &base, href, RequestKind::Page);
                     }
                 }
             }
@@ -917,9 +2736,7 @@ impl RecursiveDownloader {
         if let Ok(selector) = Selector::parse("img[src]") {
             for element in document.select(&selector) {
                 if let Some(src) = element.value().attr("src") {
-                    if let Ok(absolute_url) = self.resolve_url(base_url, src) {
-                        links.push(absolute_url);
-                    }
+                    self.push_link(&mut links, &base, src, RequestKind::Image);
                 }
             }
         }
@@ -934,9 +2751,7 @@ impl RecursiveDownloader {
                         // Split on whitespace and take first part (URL)
                         // The rest are descriptors (150w, 2x, etc.)
                         if let Some(url) = entry.split_whitespace().next() {
-                            if let Ok(absolute_url) = self.resolve_url(base_url, url) {
-                                links.push(absolute_url);
-                            }
+                            self.push_link(&mut links, &base, url, RequestKind::Image);
                         }
                     }
                 }
@@ -949,9 +2764,7 @@ impl RecursiveDownloader {
                 if let Some(srcset) = element.value().attr("srcset") {
                     for entry in srcset.split(',') {
                         if let Some(url) = entry.split_whitespace().next() {
-                            if let Ok(absolute_url) = self.resolve_url(base_url, url) {
-                                links.push(absolute_url);
-                            }
+                            self.push_link(&mut links, &base, url, RequestKind::Image);
                         }
                     }
                 }
@@ -964,9 +2777,7 @@ impl RecursiveDownloader {
             if let Ok(selector) = Selector::parse("link[rel=stylesheet][href]") {
                 for element in document.select(&selector) {
                     if let Some(href) = element.value().attr("href") {
-                        if let Ok(absolute_url) = self.resolve_url(base_url, href) {
-                            links.push(absolute_url);
-                        }
+                        self.push_link(&mut links, &base, href, RequestKind::Stylesheet);
                     }
                 }
             }
@@ -975,9 +2786,7 @@ impl RecursiveDownloader {
             if let Ok(selector) = Selector::parse("script[src]") {
                 for element in document.select(&selector) {
                     if let Some(src) = element.value().attr("src") {
-                        if let Ok(absolute_url) = self.resolve_url(base_url, src) {
-                            links.push(absolute_url);
-                        }
+                        self.push_link(&mut links, &base, src, RequestKind::Script);
                     }
                 }
             }
@@ -986,24 +2795,40 @@ impl RecursiveDownloader {
         Ok(links)
     }
 
-    /// Resolve relative URL to absolute
-    fn resolve_url(&self, base: &str, relative: &str) -> Result<String> {
-        let base_url =
-            Url::parse(base).map_err(|e| Error::ConfigError(format!("Invalid base URL: {e}")))?;
-
-        // Skip data: URLs and javascript: URLs
-        if relative.starts_with("data:") || relative.starts_with("javascript:") {
-            return Err(Error::ConfigError("Skipping non-HTTP URL".to_string()));
+    /// Extract the `url()` and `@import` targets of a stylesheet, all page requisites
+    fn extract_css_links(
+        &mut self,
+        css: &str,
+        base_url: &str,
+    ) -> Result<Vec<(String, RequestKind)>> {
+        let base = Url::parse(base_url)
+            .map_err(|e| Error::ConfigError(format!("Invalid base URL: {e}")))?;
+        let (urls, imports) = crate::link_converter::css_references(css)?;
+        let mut links = Vec::new();
+        for target in urls {
+            self.push_link(&mut links, &base, target, RequestKind::Resource);
         }
+        for target in imports {
+            self.push_link(&mut links, &base, target, RequestKind::Stylesheet);
+        }
+        Ok(links)
+    }
 
-        let absolute = base_url
-            .join(relative)
-            .map_err(|e| Error::ConfigError(format!("Failed to resolve URL: {e}")))?;
-
-        // Don't filter based on span_hosts here - let should_download() handle it
-        // so rejected URLs can be logged properly
-        // Just return the resolved URL
-        Ok(absolute.to_string())
+    /// Resolve `href` against `base` and add it to `links`, or count why it can't be fetched
+    fn push_link(
+        &mut self,
+        links: &mut Vec<(String, RequestKind)>,
+        base: &Url,
+        href: &str,
+        kind: RequestKind,
+    ) {
+        match resolve_link(base, href) {
+            Ok(url) => links.push((url, kind)),
+            Err(skipped) => {
+                tracing::trace!(href = %href, reason = ?skipped, "Skipping unfetchable link");
+                self.stats.skipped_links.count(skipped);
+            },
+        }
     }
 
     /// Format a rejected URL as a CSV line
@@ -1036,6 +2861,8 @@ impl RecursiveDownloader {
             "BLACKLIST"
         } else if reason.contains("Already visited") {
             "BLACKLIST" // Recursive loops
+        } else if reason.starts_with("Timed out") {
+            "TIMEOUT"
         } else {
             "BLACKLIST" // Default to BLACKLIST for unknown reasons
         };
@@ -1107,3 +2934,157 @@ impl RecursiveDownloader {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_rejection_reasons() {
+        let accept = Regex::new(r"\.pdf$").unwrap();
+        let reject = Regex::new("draft").unwrap();
+
+        let reason = |url| regex_rejection(Some(&accept), Some(&reject), url);
+        assert_eq!(reason("http://a.com/x.pdf"), None);
+        assert_eq!(reason("http://a.com/x.txt"), Some("URL did not match accept regex"));
+        assert_eq!(reason("http://a.com/draft.pdf"), Some("URL matched reject regex"));
+        assert_eq!(regex_rejection(None, None, "http://a.com/x.txt"), None);
+    }
+
+    #[test]
+    fn test_local_path_layout_matrix() {
+        let url = Url::parse("http://example.com/a/b/c/file.html").unwrap();
+
+        // (-P pfx, -nd, -nH, --cut-dirs=2) -> path relative to the working directory
+        let cases = [
+            (false, false, false, false, "example.com/a/b/c/file.html"),
+            (false, false, false, true, "example.com/c/file.html"),
+            (false, false, true, false, "a/b/c/file.html"),
+            (false, false, true, true, "c/file.html"),
+            (false, true, false, false, "file.html"),
+            (false, true, false, true, "file.html"),
+            (false, true, true, false, "file.html"),
+            (false, true, true, true, "file.html"),
+            (true, false, false, false, "pfx/example.com/a/b/c/file.html"),
+            (true, false, false, true, "pfx/example.com/c/file.html"),
+            (true, false, true, false, "pfx/a/b/c/file.html"),
+            (true, false, true, true, "pfx/c/file.html"),
+            (true, true, false, false, "pfx/file.html"),
+            (true, true, false, true, "pfx/file.html"),
+            (true, true, true, false, "pfx/file.html"),
+            (true, true, true, true, "pfx/file.html"),
+        ];
+
+        for (prefix, no_directories, no_host_directories, cut, expected) in cases {
+            let config = RecursiveConfig {
+                no_directories,
+                no_host_directories,
+                cut_dirs: if cut { 2 } else { 0 },
+                ..Default::default()
+            };
+            let output_dir = if prefix {
+                Path::new("pfx")
+            } else {
+                Path::new("")
+            };
+            assert_eq!(
+                config.local_path(&url, output_dir),
+                PathBuf::from(expected),
+                "-P={prefix} -nd={no_directories} -nH={no_host_directories} --cut-dirs={cut}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_local_path_directories_and_robots() {
+        let config = RecursiveConfig {
+            no_host_directories: true,
+            cut_dirs: 5,
+            ..Default::default()
+        };
+        let prefix = Path::new("pfx");
+
+        // Cutting more directories than the URL has leaves just the file
+        let url = Url::parse("http://example.com/a/b/").unwrap();
+        assert_eq!(config.local_path(&url, prefix), PathBuf::from("pfx/index.html"));
+
+        let flat = RecursiveConfig {
+            no_directories: true,
+            ..Default::default()
+        };
+        assert_eq!(flat.local_path(&url, prefix), PathBuf::from("pfx/index.html"));
+
+        let robots = Url::parse("http://example.com/robots.txt").unwrap();
+        assert_eq!(
+            RecursiveConfig::default().local_path(&robots, prefix),
+            PathBuf::from("pfx/example.com/robots.txt")
+        );
+        assert_eq!(flat.local_path(&robots, prefix), PathBuf::from("pfx/robots.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_unfetchable_links_are_skipped_and_counted() {
+        let page = Html::parse_document(
+            r##"<html><head>
+            <link rel="stylesheet" href="style.css">
+            <script src="javascript:void(0)"></script>
+            </head><body>
+            <a href="about.html">About</a>
+            <a href="#section">Section</a>
+            <a href="">Self</a>
+            <a href="JavaScript:alert(1)">Alert</a>
+            <a href="mailto:team@example.com">Mail</a>
+            <a href="tel:+15550100">Call</a>
+            <a href="ftp://example.com/file">FTP</a>
+            <a href="http://[::1">Broken</a>
+            <a href="/docs/#intro">Docs</a>
+            <img src="data:image/png;base64,iVBORw0KGgo=" srcset="big.png 2x">
+            <img src="data:text/plain,hi">
+            </body></html>"##,
+        );
+        let mut crawler = RecursiveDownloader::new(
+            DownloadConfig::default(),
+            RecursiveConfig {
+                page_requisites: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let links = crawler
+            .extract_links(&page, "http://example.com/dir/page.html")
+            .unwrap();
+        let urls: Vec<&str> = links.iter().map(|(url, _)| url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "http://example.com/dir/about.html",
+                "http://example.com/docs/#intro",
+                "http://example.com/dir/big.png",
+                "http://example.com/dir/style.css",
+            ]
+        );
+        assert_eq!(
+            crawler.stats().skipped_links,
+            SkippedLinks {
+                fragment: 2,
+                javascript: 2,
+                mailto: 1,
+                tel: 1,
+                data: 2,
+                other_scheme: 1,
+                invalid: 1,
+            }
+        );
+        assert_eq!(crawler.stats().skipped_links.total(), 10);
+
+        // Queued anyway (e.g. from a Link header), such URLs are rejected, not errors
+        let output_dir = tempfile::tempdir().unwrap();
+        for url in ["mailto:team@example.com", "not a url", "data:,x"] {
+            let fetch = crawler
+                .should_download(url, 1, Some("http://example.com/"), output_dir.path())
+                .await;
+            assert!(matches!(fetch, Ok(false)), "{url}: {fetch:?}");
+        }
+    }
+}
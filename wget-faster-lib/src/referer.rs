@@ -0,0 +1,212 @@
+/// Referer header policy
+///
+/// Controls how much of the referring URL is revealed in the `Referer`
+/// header, following the semantics of the W3C Referrer Policy:
+/// - Credentials and fragments are always stripped
+/// - The default never leaks an https referrer to an http target
+/// - Stricter policies send only the origin, or only same-origin referrers
+use serde::Serialize;
+use url::Url;
+
+/// Policy deciding the `Referer` value sent for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefererPolicy {
+    /// Never send a Referer header
+    NoReferrer,
+
+    /// Send the full URL, except from https to http (browser default)
+    #[default]
+    NoReferrerWhenDowngrade,
+
+    /// Send the full URL only to the same origin, nothing cross-origin
+    SameOrigin,
+
+    /// Send only the origin (scheme, host, port) with the path stripped
+    Origin,
+
+    /// Always send the full URL, even on downgrade
+    UnsafeUrl,
+}
+
+impl RefererPolicy {
+    /// Convert the policy to its `--referer-policy` name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefererPolicy::NoReferrer => "no-referrer",
+            RefererPolicy::NoReferrerWhenDowngrade => "no-referrer-when-downgrade",
+            RefererPolicy::SameOrigin => "same-origin",
+            RefererPolicy::Origin => "origin",
+            RefererPolicy::UnsafeUrl => "unsafe-url",
+        }
+    }
+
+    /// Compute the Referer value for a request from `from` to `to`
+    ///
+    /// Returns `None` if no header should be sent. A URL is sent as given
+    /// apart from its credentials and fragment; a `from` that isn't a URL has
+    /// no origin to compare, so only the policies sending full URLs send it,
+    /// unchanged.
+    pub fn referer_for(&self, from: &str, to: &str) -> Option<String> {
+        if *self == RefererPolicy::NoReferrer {
+            return None;
+        }
+        let target = Url::parse(to).ok();
+        let Ok(referrer) = Url::parse(from) else {
+            return matches!(
+                self,
+                RefererPolicy::NoReferrerWhenDowngrade | RefererPolicy::UnsafeUrl
+            )
+            .then(|| from.to_string());
+        };
+
+        // Only http(s) referrers are ever sent
+        if !matches!(referrer.scheme(), "http" | "https") {
+            return None;
+        }
+        // Never reveal credentials or fragments
+        let stripped = strip_credentials_and_fragment(from);
+
+        let downgrade =
+            referrer.scheme() == "https" && target.as_ref().is_some_and(|t| t.scheme() != "https");
+        let same_origin = target
+            .as_ref()
+            .is_some_and(|t| t.origin() == referrer.origin());
+
+        match self {
+            RefererPolicy::NoReferrer => None,
+            RefererPolicy::NoReferrerWhenDowngrade => (!downgrade).then_some(stripped),
+            RefererPolicy::SameOrigin => same_origin.then_some(stripped),
+            RefererPolicy::Origin => Some(format!("{}/", referrer.origin().ascii_serialization())),
+            RefererPolicy::UnsafeUrl => Some(stripped),
+        }
+    }
+}
+
+/// `url` without its userinfo and fragment, otherwise byte for byte
+fn strip_credentials_and_fragment(url: &str) -> String {
+    let url = url.split_once('#').map_or(url, |(before, _)| before);
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    let rest = match rest[..authority_end].rfind('@') {
+        Some(at) => &rest[at + 1..],
+        None => rest,
+    };
+    format!("{scheme}://{rest}")
+}
+
+impl std::str::FromStr for RefererPolicy {
+    type Err = String;
+
+    /// Parse a policy name (case-insensitive); `strip-path` and `full` are accepted aliases
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "no-referrer" | "none" => Ok(RefererPolicy::NoReferrer),
+            "no-referrer-when-downgrade" | "default" => Ok(RefererPolicy::NoReferrerWhenDowngrade),
+            "same-origin" => Ok(RefererPolicy::SameOrigin),
+            "origin" | "strip-path" => Ok(RefererPolicy::Origin),
+            "unsafe-url" | "full" => Ok(RefererPolicy::UnsafeUrl),
+            _ => Err(format!("Invalid referer policy: {s}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = "https://user:pw@example.com/dir/page.html?q=1#frag";
+    const CLEAN: &str = "https://example.com/dir/page.html?q=1";
+
+    #[test]
+    fn test_policy_matrix() {
+        use RefererPolicy::{NoReferrer, NoReferrerWhenDowngrade, Origin, SameOrigin, UnsafeUrl};
+
+        let cases: &[(RefererPolicy, &str, &str, Option<&str>)] = &[
+            // Same origin
+            (NoReferrer, PAGE, "https://example.com/a", None),
+            (NoReferrerWhenDowngrade, PAGE, "https://example.com/a", Some(CLEAN)),
+            (SameOrigin, PAGE, "https://example.com/a", Some(CLEAN)),
+            (Origin, PAGE, "https://example.com/a", Some("https://example.com/")),
+            (UnsafeUrl, PAGE, "https://example.com/a", Some(CLEAN)),
+            // Cross origin, same scheme
+            (NoReferrer, PAGE, "https://other.org/", None),
+            (NoReferrerWhenDowngrade, PAGE, "https://other.org/", Some(CLEAN)),
+            (SameOrigin, PAGE, "https://other.org/", None),
+            (Origin, PAGE, "https://other.org/", Some("https://example.com/")),
+            (UnsafeUrl, PAGE, "https://other.org/", Some(CLEAN)),
+            // Downgrade https -> http
+            (NoReferrer, PAGE, "http://example.com/", None),
+            (NoReferrerWhenDowngrade, PAGE, "http://example.com/", None),
+            (SameOrigin, PAGE, "http://example.com/", None),
+            (Origin, PAGE, "http://example.com/", Some("https://example.com/")),
+            (UnsafeUrl, PAGE, "http://example.com/", Some(CLEAN)),
+            // Upgrade http -> https
+            (
+                NoReferrerWhenDowngrade,
+                "http://a.com/x",
+                "https://a.com/y",
+                Some("http://a.com/x"),
+            ),
+            (SameOrigin, "http://a.com/x", "https://a.com/y", None),
+            // Non-default port is part of the origin
+            (Origin, "http://a.com:8080/x/y", "http://b.com/", Some("http://a.com:8080/")),
+            (SameOrigin, "http://a.com:8080/x", "http://a.com/x", None),
+            // Sent as given, not as re-serialized by the URL parser
+            (UnsafeUrl, "https://Example.com", "https://a.com/", Some("https://Example.com")),
+            (
+                NoReferrerWhenDowngrade,
+                "https://u:p@Example.com:443/a%7e?b#c",
+                "https://example.com/",
+                Some("https://Example.com:443/a%7e?b"),
+            ),
+            (
+                SameOrigin,
+                "https://Example.com",
+                "https://example.com/x",
+                Some("https://Example.com"),
+            ),
+            // Not a URL: sent unchanged unless the policy needs its origin
+            (UnsafeUrl, "not a url", "http://a.com/", Some("not a url")),
+            (NoReferrerWhenDowngrade, "/dir/page", "http://a.com/", Some("/dir/page")),
+            (SameOrigin, "/dir/page", "http://a.com/", None),
+            (Origin, "/dir/page", "http://a.com/", None),
+            (NoReferrer, "/dir/page", "http://a.com/", None),
+        ];
+
+        for (policy, from, to, expected) in cases {
+            assert_eq!(
+                policy.referer_for(from, to).as_deref(),
+                *expected,
+                "{policy:?} from {from} to {to}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_http_referrer_is_never_sent() {
+        assert_eq!(RefererPolicy::UnsafeUrl.referer_for("file:///etc/passwd", "http://a/"), None);
+        assert_eq!(RefererPolicy::UnsafeUrl.referer_for("ftp://u:p@host/f", "http://a/"), None);
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!("no-referrer".parse(), Ok(RefererPolicy::NoReferrer));
+        assert_eq!("Same-Origin".parse(), Ok(RefererPolicy::SameOrigin));
+        assert_eq!("strip-path".parse(), Ok(RefererPolicy::Origin));
+        assert_eq!("full".parse(), Ok(RefererPolicy::UnsafeUrl));
+        assert!("bogus".parse::<RefererPolicy>().is_err());
+
+        for policy in [
+            RefererPolicy::NoReferrer,
+            RefererPolicy::NoReferrerWhenDowngrade,
+            RefererPolicy::SameOrigin,
+            RefererPolicy::Origin,
+            RefererPolicy::UnsafeUrl,
+        ] {
+            assert_eq!(policy.as_str().parse(), Ok(policy));
+        }
+    }
+}
@@ -0,0 +1,228 @@
+/// Request headers that depend on what a request fetches: header presets, `default_accept` and priority
+use crate::DownloadConfig;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, ACCEPT_LANGUAGE};
+use serde::Serialize;
+use url::Url;
+
+const PRIORITY: HeaderName = HeaderName::from_static("priority");
+const SEC_FETCH_DEST: HeaderName = HeaderName::from_static("sec-fetch-dest");
+const SEC_FETCH_MODE: HeaderName = HeaderName::from_static("sec-fetch-mode");
+const SEC_FETCH_SITE: HeaderName = HeaderName::from_static("sec-fetch-site");
+
+/// Lowest priority of RFC 9218 urgencies (0 is the highest)
+pub const MAX_URGENCY: u8 = 7;
+
+/// Set of headers added to every request (`DownloadConfig::header_preset`)
+///
+/// Preset headers never replace a header set in `DownloadConfig::headers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderPreset {
+    /// `Accept`, `Accept-Language` and `Sec-Fetch-*` as a browser sends them for
+    /// each kind of request, for origins that serve less to other clients
+    Browser,
+
+    /// Only `Accept: */*`
+    Minimal,
+}
+
+/// What a request fetches, which decides its preset headers and priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RequestKind {
+    /// An HTML page, or any URL downloaded on its own
+    #[default]
+    Page,
+
+    /// An image (`<img>`, `srcset`)
+    Image,
+
+    /// A stylesheet (`<link rel=stylesheet>`)
+    Stylesheet,
+
+    /// A script (`<script src>`)
+    Script,
+
+    /// Any other page requisite, e.g. a font preloaded by a Link header
+    Resource,
+}
+
+impl RequestKind {
+    /// Whether this is a page requisite rather than a page
+    pub fn is_requisite(self) -> bool {
+        self != Self::Page
+    }
+
+    /// Kind of a `preload` Link header target from its `as` parameter
+    #[cfg(feature = "recursive")]
+    pub(crate) fn from_preload_destination(destination: Option<&str>) -> Self {
+        match destination {
+            Some("image") => Self::Image,
+            Some("style") => Self::Stylesheet,
+            Some("script") => Self::Script,
+            _ => Self::Resource,
+        }
+    }
+
+    /// `Accept` a browser sends for this kind of request
+    fn browser_accept(self) -> &'static str {
+        match self {
+            Self::Page => {
+                "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8"
+            },
+            Self::Image => "image/avif,image/webp,image/apng,image/svg+xml,image/*,*/*;q=0.8",
+            Self::Stylesheet => "text/css,*/*;q=0.1",
+            Self::Script | Self::Resource => "*/*",
+        }
+    }
+
+    /// `Sec-Fetch-Dest` of this kind of request
+    fn destination(self) -> &'static str {
+        match self {
+            Self::Page => "document",
+            Self::Image => "image",
+            Self::Stylesheet => "style",
+            Self::Script => "script",
+            Self::Resource => "empty",
+        }
+    }
+}
+
+/// What a crawl knows about one request, set on the client for its duration
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(feature = "recursive"), allow(dead_code))]
+pub(crate) struct RequestHints {
+    pub(crate) kind: RequestKind,
+
+    /// Page the URL was found on (`None` for a start URL)
+    pub(crate) initiator: Option<Url>,
+
+    /// RFC 9218 urgency sent in a `Priority` header
+    pub(crate) urgency: Option<u8>,
+
+    /// Crawl depth the URL was found at (0 for a start URL), for provenance records
+    pub(crate) depth: usize,
+}
+
+/// `Sec-Fetch-Site` of a request to `url` made from `initiator`
+///
+/// Hosts sharing their last two labels count as the same site; this is wrong
+/// for domains under public suffixes like `co.uk`, which become `same-site`.
+fn fetch_site(initiator: Option<&Url>, url: &Url) -> &'static str {
+    let Some(initiator) = initiator else {
+        return "none";
+    };
+    if initiator.origin() == url.origin() {
+        return "same-origin";
+    }
+    let site = |url: &Url| {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let labels: Vec<&str> = host.rsplit('.').take(2).collect();
+        (url.scheme().to_string(), labels.join("."))
+    };
+    if site(initiator) == site(url) {
+        "same-site"
+    } else {
+        "cross-site"
+    }
+}
+
+/// Add the hint headers for a request to `url` to `headers`
+///
+/// A header the request already has, or one set in `config.headers`, is left
+/// alone. Without `hints` the request is treated as a page with no initiator
+/// and gets no `Priority`.
+pub(crate) fn apply(
+    config: &DownloadConfig,
+    hints: Option<&RequestHints>,
+    url: &Url,
+    headers: &mut HeaderMap,
+) {
+    let kind = hints.map(|h| h.kind).unwrap_or_default();
+    let mut hinted: Vec<(HeaderName, String)> = Vec::new();
+
+    match config.header_preset {
+        Some(HeaderPreset::Browser) => {
+            let initiator = hints.and_then(|h| h.initiator.as_ref());
+            let mode = if kind.is_requisite() {
+                "no-cors"
+            } else {
+                "navigate"
+            };
+            hinted.push((ACCEPT, kind.browser_accept().to_string()));
+            hinted.push((ACCEPT_LANGUAGE, "en-US,en;q=0.9".to_string()));
+            hinted.push((SEC_FETCH_DEST, kind.destination().to_string()));
+            hinted.push((SEC_FETCH_MODE, mode.to_string()));
+            hinted.push((SEC_FETCH_SITE, fetch_site(initiator, url).to_string()));
+        },
+        Some(HeaderPreset::Minimal) => hinted.push((ACCEPT, "*/*".to_string())),
+        None => {},
+    }
+    // default_accept takes precedence over the preset's Accept
+    if let Some(ref accept) = config.default_accept {
+        hinted.retain(|(name, _)| *name != ACCEPT);
+        hinted.push((ACCEPT, accept.clone()));
+    }
+    if let Some(urgency) = hints.and_then(|h| h.urgency) {
+        hinted.push((PRIORITY, format!("u={}", urgency.min(MAX_URGENCY))));
+    }
+
+    for (name, value) in hinted {
+        let explicit = config
+            .headers
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case(name.as_str()));
+        if explicit || headers.contains_key(&name) {
+            continue;
+        }
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_fetch_site() {
+        let page = url("https://www.example.com/index.html");
+        assert_eq!(fetch_site(None, &page), "none");
+        assert_eq!(fetch_site(Some(&page), &url("https://www.example.com/a.png")), "same-origin");
+        assert_eq!(fetch_site(Some(&page), &url("https://cdn.example.com/a.png")), "same-site");
+        assert_eq!(fetch_site(Some(&page), &url("http://www.example.com/a.png")), "cross-site");
+        assert_eq!(fetch_site(Some(&page), &url("https://cdn.other.net/a.png")), "cross-site");
+    }
+
+    #[test]
+    fn test_explicit_headers_win() {
+        let mut config = DownloadConfig {
+            header_preset: Some(HeaderPreset::Browser),
+            default_accept: Some("application/json".to_string()),
+            ..DownloadConfig::default()
+        };
+        config
+            .headers
+            .insert("accept-language".to_string(), "de".to_string());
+        let hints = RequestHints {
+            kind: RequestKind::Image,
+            initiator: None,
+            urgency: Some(9),
+            depth: 1,
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(SEC_FETCH_MODE, HeaderValue::from_static("cors"));
+
+        apply(&config, Some(&hints), &url("https://example.com/a.png"), &mut headers);
+
+        assert_eq!(headers[ACCEPT], "application/json");
+        assert!(!headers.contains_key(ACCEPT_LANGUAGE));
+        assert_eq!(headers[SEC_FETCH_MODE], "cors");
+        assert_eq!(headers[SEC_FETCH_DEST], "image");
+        assert_eq!(headers[PRIORITY], "u=7");
+    }
+}
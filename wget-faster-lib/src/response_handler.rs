@@ -3,8 +3,39 @@
 /// Consolidates HTTP response logic including:
 /// - Status code validation and classification
 /// - Special status handling (204, 304, 416)
+/// - Redirects returned unfollowed (`max_redirects` of 0)
+/// - Content-Range validation for 206 responses
 /// - Error response handling with `content_on_error` support
-use crate::DownloadConfig;
+/// - Response filters that reject a download once its headers are known
+use crate::client::ResourceMetadata;
+use crate::{DownloadConfig, Error};
+use std::fmt;
+use std::sync::Arc;
+
+/// Decides from the request URL and response headers whether to keep a download
+///
+/// Returns `Some(reason)` to reject the response, `None` to accept it.
+pub type ResponseFilterFn = Arc<dyn Fn(&str, &ResourceMetadata) -> Option<String> + Send + Sync>;
+
+/// Filter checked before the body of a file download is transferred (see `DownloadConfig::response_filter`)
+#[derive(Clone)]
+pub struct ResponseFilter(pub ResponseFilterFn);
+
+impl ResponseFilter {
+    /// Apply the filter, turning a rejection into `Error::ResponseRejected`
+    pub(crate) fn check(&self, url: &str, metadata: &ResourceMetadata) -> Result<(), Error> {
+        match (self.0)(url, metadata) {
+            Some(reason) => Err(Error::ResponseRejected(reason)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for ResponseFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResponseFilter(..)")
+    }
+}
 
 /// Response status category for decision making
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,6 +48,8 @@ pub enum ResponseStatus {
     NotModified,
     /// Range not satisfiable - file already complete (416)
     RangeNotSatisfiable,
+    /// Redirect (3xx other than 304), seen only when redirects are not followed
+    Redirect,
     /// Client error (4xx) - may save content if `content_on_error` is true
     ClientError,
     /// Server error (5xx) - may save content if `content_on_error` is true
@@ -39,7 +72,8 @@ impl ResponseStatus {
     /// Returns the response status category
     pub fn from_status_code(status_code: u16) -> Self {
         match status_code {
-            // Success codes
+            // Success codes (including 202 Accepted, 203 Non-Authoritative,
+            // 206 Partial Content, 208 Already Reported and 226 IM Used)
             200..=203 | 205..=299 => Self::Success,
 
             // Special success cases
@@ -47,6 +81,9 @@ impl ResponseStatus {
             304 => Self::NotModified,
             416 => Self::RangeNotSatisfiable,
 
+            // Redirects left unfollowed
+            300..=399 => Self::Redirect,
+
             // Authentication challenges
             401 | 407 => Self::AuthChallenge,
 
@@ -111,8 +148,8 @@ pub fn should_create_file(status_code: u16, downloaded_bytes: u64, resumed_from:
 ///
 /// # Returns
 ///
-/// Returns `Ok(true)` if download should proceed, `Ok(false)` if should skip (304/416),
-/// or `Err(status_code)` if should return error.
+/// Returns `Ok(true)` if download should proceed, `Ok(false)` if should skip (304/416,
+/// or an unfollowed redirect without `content_on_error`), or `Err(status_code)` if should return error.
 pub fn should_proceed_download(status_code: u16, config: &DownloadConfig) -> Result<bool, u16> {
     let status = ResponseStatus::from_status_code(status_code);
 
@@ -129,6 +166,10 @@ pub fn should_proceed_download(status_code: u16, config: &DownloadConfig) -> Res
         // Auth challenge - should be handled before this check
         ResponseStatus::AuthChallenge => Err(status_code),
 
+        // Unfollowed redirect - the result carries the Location; the body only
+        // matters with content_on_error
+        ResponseStatus::Redirect => Ok(config.content_on_error),
+
         // Error responses - check content_on_error
         ResponseStatus::ClientError | ResponseStatus::ServerError => {
             if config.content_on_error {
@@ -145,6 +186,66 @@ pub fn should_proceed_download(status_code: u16, config: &DownloadConfig) -> Res
     }
 }
 
+/// Parse a `Content-Range: bytes start-end/total` header value
+///
+/// Returns `(start, end, total)` with inclusive `end`; `total` is `None`
+/// when the server sent `*`.
+pub fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+    let range = value.trim().strip_prefix("bytes")?.trim_start();
+    let (span, total) = range.split_once('/')?;
+    let (start, end) = span.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = end.trim().parse().ok()?;
+    let total = match total.trim() {
+        "*" => None,
+        t => Some(t.parse().ok()?),
+    };
+    (start <= end && total.is_none_or(|t| end < t)).then_some((start, end, total))
+}
+
+/// Validate a 206 response against the offset that was requested
+///
+/// Some mirrors answer every GET with 206 and a full-range Content-Range even
+/// when no Range header was sent. Such a body is accepted as long as it starts
+/// at `expected_start` (0 for unsolicited responses) and, when unsolicited,
+/// covers the whole resource.
+///
+/// # Returns
+///
+/// Returns the complete resource size from Content-Range, if known.
+pub(crate) fn check_partial_content(
+    response: &reqwest::Response,
+    expected_start: u64,
+) -> crate::Result<Option<u64>> {
+    let Some(value) = response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+    else {
+        tracing::debug!("206 response without Content-Range - treating body as requested");
+        return Ok(None);
+    };
+
+    let Some((start, end, total)) = parse_content_range(value) else {
+        return Err(Error::InvalidContentRange(value.to_string()));
+    };
+
+    if start != expected_start {
+        return Err(Error::InvalidContentRange(format!(
+            "{value} (expected data from byte {expected_start})"
+        )));
+    }
+
+    // An unsolicited partial response must carry the whole resource
+    if expected_start == 0 && total.is_some_and(|t| end + 1 < t) {
+        return Err(Error::InvalidContentRange(format!(
+            "{value} (server sent only part of the file)"
+        )));
+    }
+
+    Ok(total)
+}
+
 /// Check if status code indicates a special case that needs handling
 ///
 /// # Arguments
@@ -174,6 +275,8 @@ mod tests {
         assert_eq!(ResponseStatus::from_status_code(201), ResponseStatus::Success);
         assert_eq!(ResponseStatus::from_status_code(204), ResponseStatus::NoContent);
         assert_eq!(ResponseStatus::from_status_code(304), ResponseStatus::NotModified);
+        assert_eq!(ResponseStatus::from_status_code(301), ResponseStatus::Redirect);
+        assert_eq!(ResponseStatus::from_status_code(308), ResponseStatus::Redirect);
         assert_eq!(ResponseStatus::from_status_code(401), ResponseStatus::AuthChallenge);
         assert_eq!(ResponseStatus::from_status_code(404), ResponseStatus::ClientError);
         assert_eq!(ResponseStatus::from_status_code(416), ResponseStatus::RangeNotSatisfiable);
@@ -222,15 +325,37 @@ mod tests {
         // Range not satisfiable - skip
         assert_eq!(should_proceed_download(416, &config), Ok(false));
 
-        // Errors without content_on_error - error
+        // Errors without content_on_error - error; unfollowed redirects - skip the body
         config.content_on_error = false;
         assert_eq!(should_proceed_download(404, &config), Err(404));
         assert_eq!(should_proceed_download(500, &config), Err(500));
+        assert_eq!(should_proceed_download(301, &config), Ok(false));
 
-        // Errors with content_on_error - proceed
+        // Errors and redirects with content_on_error - proceed
         config.content_on_error = true;
         assert_eq!(should_proceed_download(404, &config), Ok(true));
         assert_eq!(should_proceed_download(500, &config), Ok(true));
+        assert_eq!(should_proceed_download(301, &config), Ok(true));
+    }
+
+    #[test]
+    fn test_unusual_success_codes() {
+        let config = DownloadConfig::default();
+        for code in [202, 203, 205, 206, 207, 208, 226] {
+            assert_eq!(ResponseStatus::from_status_code(code), ResponseStatus::Success);
+            assert_eq!(should_proceed_download(code, &config), Ok(true));
+        }
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 0-99/100"), Some((0, 99, Some(100))));
+        assert_eq!(parse_content_range("bytes 100-199/*"), Some((100, 199, None)));
+        assert_eq!(parse_content_range(" bytes 5-5/6 "), Some((5, 5, Some(6))));
+        assert_eq!(parse_content_range("bytes 0-99/50"), None);
+        assert_eq!(parse_content_range("bytes 10-5/100"), None);
+        assert_eq!(parse_content_range("bytes */100"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
     }
 
     #[test]
@@ -0,0 +1,64 @@
+/// Retrying whole downloads after transient failures (`DownloadConfig::retry`)
+///
+/// `Downloader::download_to_memory*`, `download_to_file*` and `download`
+/// repeat a failed attempt while [`retryable`] says the failure may not
+/// happen again, at most `retry.max_retries` times. The wait before retry `n`
+/// is `n` seconds capped at `wait_retry` (wget's `--waitretry`), or without
+/// it the exponential backoff of `retry`. A retried file download continues
+/// from the bytes the failed attempt wrote.
+use crate::stream::{is_transient, retry_delay};
+use crate::{DownloadEvent, Error, HttpClient, Result, RetryConfig};
+use std::future::Future;
+
+/// Whether a download that failed with `error` is worth another attempt
+///
+/// Statuses in `retry_on_status`, timeouts and connections dropped mid-body
+/// are. Refused connections only are with `retry_on_conn_refused`. A file
+/// that changed while being resumed is removed, so the next attempt starts
+/// over.
+pub(crate) fn retryable(error: &Error, retry: &RetryConfig) -> bool {
+    match error.root() {
+        Error::HttpError(e) if e.is_connect() => retry.retry_on_conn_refused,
+        Error::HttpError(e) if e.is_builder() || e.is_redirect() => false,
+        Error::ObjectChangedDuringDownload(_) => true,
+        _ => is_transient(error, retry),
+    }
+}
+
+/// Run `attempt` until it succeeds, fails for good or runs out of retries
+///
+/// `attempt` is told whether it is a retry.
+pub(crate) async fn with_retries<T, F, Fut>(
+    client: &HttpClient,
+    url: &str,
+    mut attempt: F,
+) -> Result<T>
+where
+    F: FnMut(bool) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let config = client.config();
+    let mut retries = 0;
+    loop {
+        let error = match attempt(retries > 0).await {
+            Ok(value) => return Ok(value),
+            Err(e) if retries < config.retry.max_retries && retryable(&e, &config.retry) => e,
+            Err(e) => return Err(e),
+        };
+        retries += 1;
+        let delay = retry_delay(config, retries);
+        tracing::info!(
+            url = %url,
+            attempt = retries,
+            delay_ms = delay.as_millis(),
+            error = %error,
+            "Download failed, retrying"
+        );
+        client.emit(|| DownloadEvent::RetryScheduled {
+            attempt: retries,
+            delay,
+            cause: error.to_string(),
+        });
+        tokio::time::sleep(delay).await;
+    }
+}
@@ -6,13 +6,21 @@
 /// - Allow and Disallow directives
 /// - Wildcard matching (*) in user-agent
 /// - Most specific path matching
+/// - `Crawl-delay` (the crawl's per-host wait)
+///
+/// Also parses the per-page indexing directives of `X-Robots-Tag` headers and
+/// `<meta name="robots">` tags ([`RobotsDirectives`]).
 use std::collections::HashMap;
+use std::time::Duration;
 
 /// robots.txt parser
 #[derive(Debug, Clone)]
 pub struct RobotsTxt {
     /// Rules grouped by user-agent
     rules: HashMap<String, Vec<RobotRule>>,
+
+    /// `Crawl-delay` by user-agent
+    crawl_delays: HashMap<String, Duration>,
 }
 
 /// A single robot rule (Allow or Disallow)
@@ -52,7 +60,10 @@ impl RobotsTxt {
     /// ```
     pub fn parse(content: &str) -> Self {
         let mut rules: HashMap<String, Vec<RobotRule>> = HashMap::new();
+        let mut crawl_delays = HashMap::new();
         let mut current_agents: Vec<String> = Vec::new();
+        // A user-agent line after a group's rules starts the next group
+        let mut in_rules = false;
 
         for line in content.lines() {
             // Remove comments and trim
@@ -72,9 +83,14 @@ impl RobotsTxt {
                 let field = field.trim().to_lowercase();
                 let value = value.trim();
 
+                in_rules |= matches!(field.as_str(), "disallow" | "allow" | "crawl-delay");
                 match field.as_str() {
                     "user-agent" => {
                         // New user-agent section
+                        if in_rules {
+                            current_agents.clear();
+                            in_rules = false;
+                        }
                         let agent = value.to_lowercase();
                         if !current_agents.contains(&agent) {
                             current_agents.push(agent);
@@ -98,14 +114,47 @@ impl RobotsTxt {
                             });
                         }
                     },
+                    "crawl-delay" => {
+                        // Seconds, possibly fractional; invalid values are ignored
+                        let delay = value
+                            .parse()
+                            .ok()
+                            .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+                        if let Some(delay) = delay {
+                            for agent in &current_agents {
+                                crawl_delays.insert(agent.clone(), delay);
+                            }
+                        }
+                    },
                     _ => {
-                        // Ignore other fields (Crawl-delay, Sitemap, etc.)
+                        // Ignore other fields (Sitemap, etc.)
                     },
                 }
             }
         }
 
-        Self { rules }
+        Self {
+            rules,
+            crawl_delays,
+        }
+    }
+
+    /// `Crawl-delay` for a user-agent: its own, or else the one for `*`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use wget_faster_lib::robots::RobotsTxt;
+    ///
+    /// let robots = RobotsTxt::parse("User-agent: *\nCrawl-delay: 2.5\n");
+    /// assert_eq!(robots.crawl_delay("wget"), Some(Duration::from_millis(2500)));
+    /// ```
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
+        self.crawl_delays
+            .get(&user_agent.to_lowercase())
+            .or_else(|| self.crawl_delays.get("*"))
+            .copied()
     }
 
     /// Check if a URL path is allowed for a given user-agent
@@ -178,6 +227,159 @@ impl RobotsTxt {
     }
 }
 
+/// Directives of `X-Robots-Tag` that take a value after a colon, so the colon
+/// doesn't name an agent
+const VALUED_DIRECTIVES: &[&str] = &[
+    "unavailable_after",
+    "max-snippet",
+    "max-image-preview",
+    "max-video-preview",
+];
+
+/// Indexing directives of a page, from `X-Robots-Tag` headers and robots `<meta>` tags
+///
+/// Only `noindex` and `nofollow` are kept (`none` sets both); other directives
+/// are ignored. Within one source, directives addressed to the crawler's agent
+/// override the global ones for the same decision (an agent-specific `index`
+/// lifts a global `noindex`). Between the header and the page, the stricter
+/// one wins ([`merge`](Self::merge)).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RobotsDirectives {
+    /// The page asks not to be indexed (`noindex` or `none`)
+    pub noindex: bool,
+
+    /// The page asks that its links not be followed (`nofollow` or `none`)
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// Directives of the `X-Robots-Tag` header `values` for `user_agent`
+    ///
+    /// Each value is a comma-separated list of directives, optionally prefixed
+    /// by the agent it applies to (`otherbot: noindex`).
+    ///
+    /// ```
+    /// use wget_faster_lib::robots::RobotsDirectives;
+    ///
+    /// let robots = RobotsDirectives::from_headers(["noindex", "otherbot: nofollow"], "wget-faster/1.0");
+    /// assert!(robots.noindex && !robots.nofollow);
+    /// ```
+    pub fn from_headers<'a>(values: impl IntoIterator<Item = &'a str>, user_agent: &str) -> Self {
+        let mut scoped = Scoped::default();
+        for value in values {
+            let (agent, directives) = match value.split_once(':') {
+                Some((agent, rest))
+                    if !agent.contains(',')
+                        && !VALUED_DIRECTIVES
+                            .iter()
+                            .any(|d| agent.trim().eq_ignore_ascii_case(d)) =>
+                {
+                    (Some(agent.trim()), rest)
+                },
+                _ => (None, value),
+            };
+            scoped.add(agent, directives, user_agent);
+        }
+        scoped.resolve()
+    }
+
+    /// Directives of a page's `<meta>` tags, given as (name, content) pairs
+    ///
+    /// `name="robots"` applies to every crawler, a name matching
+    /// `user_agent` only to this one; other names are ignored.
+    pub fn from_meta<'a>(
+        tags: impl IntoIterator<Item = (&'a str, &'a str)>,
+        user_agent: &str,
+    ) -> Self {
+        let mut scoped = Scoped::default();
+        for (name, content) in tags {
+            let agent = (!name.trim().eq_ignore_ascii_case("robots")).then_some(name.trim());
+            scoped.add(agent, content, user_agent);
+        }
+        scoped.resolve()
+    }
+
+    /// Directives of both sources: a directive in either applies
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        }
+    }
+}
+
+/// Whether the agent named in a directive is the crawler with `user_agent`
+///
+/// Names are compared case-insensitively with the full user agent and with
+/// its product token (`wget-faster` for `wget-faster/1.0`).
+fn names_agent(name: &str, user_agent: &str) -> bool {
+    let product = user_agent.split(['/', ' ']).next().unwrap_or_default();
+    name.eq_ignore_ascii_case(user_agent)
+        || (!product.is_empty() && name.eq_ignore_ascii_case(product))
+}
+
+/// Index and follow decisions (`None` when not stated), global and for this agent
+#[derive(Debug, Default)]
+struct Scoped {
+    global: Decisions,
+    agent: Decisions,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Decisions {
+    index: Option<bool>,
+    follow: Option<bool>,
+}
+
+impl Decisions {
+    /// Record a decision; when one list says both, the restrictive one wins
+    fn set(slot: &mut Option<bool>, allowed: bool) {
+        *slot = Some(slot.unwrap_or(true) && allowed);
+    }
+
+    fn add(&mut self, directive: &str) {
+        match directive.trim().to_ascii_lowercase().as_str() {
+            "noindex" => Self::set(&mut self.index, false),
+            "index" => Self::set(&mut self.index, true),
+            "nofollow" => Self::set(&mut self.follow, false),
+            "follow" => Self::set(&mut self.follow, true),
+            "none" => {
+                Self::set(&mut self.index, false);
+                Self::set(&mut self.follow, false);
+            },
+            "all" => {
+                Self::set(&mut self.index, true);
+                Self::set(&mut self.follow, true);
+            },
+            _ => {},
+        }
+    }
+}
+
+impl Scoped {
+    /// Add the comma-separated `directives` for `agent` (`None` for everyone)
+    fn add(&mut self, agent: Option<&str>, directives: &str, user_agent: &str) {
+        let decisions = match agent {
+            None => &mut self.global,
+            Some(name) if names_agent(name, user_agent) => &mut self.agent,
+            Some(_) => return,
+        };
+        for directive in directives.split(',') {
+            decisions.add(directive);
+        }
+    }
+
+    fn resolve(&self) -> RobotsDirectives {
+        let index = self.agent.index.or(self.global.index).unwrap_or(true);
+        let follow = self.agent.follow.or(self.global.follow).unwrap_or(true);
+        RobotsDirectives {
+            noindex: !index,
+            nofollow: !follow,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,4 +471,97 @@ Disallow: /private/
         assert!(!robots.is_allowed("/private/file", "MYBOT"));
         assert!(!robots.is_allowed("/private/file", "MyBot"));
     }
+
+    const AGENT: &str = "wget-faster/0.1";
+
+    #[test]
+    fn test_crawl_delay() {
+        let robots = RobotsTxt::parse(
+            "User-agent: *\nCrawl-delay: 1\n\nUser-agent: slowbot\nCrawl-delay: 10\n\nUser-agent: other\nCrawl-delay: soon\n",
+        );
+        assert_eq!(robots.crawl_delay("wget"), Some(Duration::from_secs(1)));
+        assert_eq!(robots.crawl_delay("SlowBot"), Some(Duration::from_secs(10)));
+        assert_eq!(RobotsTxt::parse("User-agent: *\nDisallow: /x\n").crawl_delay("wget"), None);
+    }
+
+    #[test]
+    fn test_header_directives() {
+        let robots = RobotsDirectives::from_headers(["noindex, nofollow"], AGENT);
+        assert_eq!(
+            robots,
+            RobotsDirectives {
+                noindex: true,
+                nofollow: true
+            }
+        );
+
+        // Several headers add up; unknown and valued directives are ignored
+        let robots = RobotsDirectives::from_headers(
+            [
+                "unavailable_after: 25 Jun 2010 15:00:00 PST",
+                "noarchive",
+                "NoFollow",
+            ],
+            AGENT,
+        );
+        assert_eq!(
+            robots,
+            RobotsDirectives {
+                noindex: false,
+                nofollow: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_none_shorthand() {
+        let both = RobotsDirectives {
+            noindex: true,
+            nofollow: true,
+        };
+        assert_eq!(RobotsDirectives::from_headers(["none"], AGENT), both);
+        assert_eq!(RobotsDirectives::from_meta([("robots", "NONE")], AGENT), both);
+    }
+
+    #[test]
+    fn test_agent_specific_overrides_global() {
+        // Directives for other agents don't apply
+        let robots = RobotsDirectives::from_headers(["otherbot: noindex, nofollow"], AGENT);
+        assert_eq!(robots, RobotsDirectives::default());
+
+        // Ours override the global ones for the same decision only
+        let robots =
+            RobotsDirectives::from_headers(["noindex, nofollow", "Wget-Faster: index"], AGENT);
+        assert_eq!(
+            robots,
+            RobotsDirectives {
+                noindex: false,
+                nofollow: true
+            }
+        );
+
+        let robots = RobotsDirectives::from_meta(
+            [
+                ("robots", "noindex"),
+                ("wget-faster", "all"),
+                ("otherbot", "nofollow"),
+            ],
+            AGENT,
+        );
+        assert_eq!(robots, RobotsDirectives::default());
+    }
+
+    #[test]
+    fn test_header_and_meta_stricter_wins() {
+        let header = RobotsDirectives::from_headers(["noindex"], AGENT);
+        let meta = RobotsDirectives::from_meta([("robots", "index, nofollow")], AGENT);
+        assert_eq!(
+            header.merge(meta),
+            RobotsDirectives {
+                noindex: true,
+                nofollow: true
+            }
+        );
+        assert_eq!(meta.merge(header), header.merge(meta));
+    }
 }
@@ -0,0 +1,175 @@
+/// Writing into output directories that may hold planted links
+///
+/// Mirroring again into an existing directory trusts whatever is already in
+/// it: a symlink `output/host -> /etc` left by an attacker (or an earlier buggy
+/// run) would send every file of the crawl outside the output directory, and
+/// a file hardlinked to another one would have that file overwritten.
+///
+/// Directories are therefore created one component at a time, each checked
+/// with `lstat` instead of being followed, and a symlink or hardlink found
+/// where a file is about to be written is replaced instead of written through
+/// (like wget's `--unlink`). `DownloadConfig::follow_output_symlinks` trusts
+/// the symlinks again.
+use crate::{Error, Result};
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+/// Create `dir` and the directories leading to it below `root`, refusing symlinks
+///
+/// `root` itself is trusted (created if missing); a `dir` outside of it (or
+/// an absolute `dir` with an empty `root`) is created as is. A symlinked component fails with
+/// [`Error::UnsafeOutputPath`] unless `follow_symlinks` is set.
+///
+/// # Errors
+///
+/// Returns `Error::UnsafeOutputPath` for a symlinked directory, or
+/// `Error::IoError` if a directory can't be created (e.g. a file is in the way).
+pub async fn create_dirs_within(root: &Path, dir: &Path, follow_symlinks: bool) -> Result<()> {
+    let Some(relative) = dir.strip_prefix(root).ok().filter(|r| r.is_relative()) else {
+        tokio::fs::create_dir_all(dir).await?;
+        return Ok(());
+    };
+    if !root.as_os_str().is_empty() {
+        tokio::fs::create_dir_all(root).await?;
+    }
+
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => current.push(name),
+            Component::CurDir => continue,
+            // URL paths are normalized, so `..` only comes from a misbehaving path mapper
+            _ => return Err(Error::UnsafeOutputPath(dir.to_path_buf())),
+        }
+        // Checked again after creating, in case a link appeared in between
+        let metadata = match tokio::fs::symlink_metadata(&current).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                match tokio::fs::create_dir(&current).await {
+                    Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(e.into()),
+                    _ => tokio::fs::symlink_metadata(&current).await?,
+                }
+            },
+            metadata => metadata?,
+        };
+        if metadata.is_symlink() && !follow_symlinks {
+            tracing::warn!(
+                path = %current.display(),
+                "Directory in the output tree is a symlink - not following it"
+            );
+            return Err(Error::UnsafeOutputPath(current));
+        }
+    }
+    Ok(())
+}
+
+/// Replace a symlink or hardlink at `path` so writing a file there changes nothing else
+///
+/// A symlink is removed (unless `follow_symlinks`); a file with other hard
+/// links is swapped for a copy of itself, keeping its contents and
+/// modification time for resuming and timestamping. Meant for paths built
+/// below an output directory: a path the user named is written through.
+///
+/// # Errors
+///
+/// Returns `Error::IoError` if the link can't be removed or the copy made.
+pub async fn replace_links(path: &Path, follow_symlinks: bool) -> Result<()> {
+    let Ok(metadata) = tokio::fs::symlink_metadata(path).await else {
+        return Ok(());
+    };
+    if metadata.is_symlink() {
+        if !follow_symlinks {
+            tracing::warn!(path = %path.display(), "Output file is a symlink - replacing it");
+            tokio::fs::remove_file(path).await?;
+        }
+        return Ok(());
+    }
+    #[cfg(unix)]
+    if metadata.is_file() && std::os::unix::fs::MetadataExt::nlink(&metadata) > 1 {
+        tracing::warn!(path = %path.display(), "Output file has other hard links - breaking them");
+        let copy = unlinked_copy_path(path);
+        tokio::fs::copy(path, &copy).await?;
+        let modified = filetime::FileTime::from_last_modification_time(&metadata);
+        filetime::set_file_mtime(&copy, modified)?;
+        tokio::fs::rename(&copy, path).await?;
+    }
+    Ok(())
+}
+
+/// Where the copy replacing a hardlinked file is made
+#[cfg(unix)]
+fn unlinked_copy_path(path: &Path) -> PathBuf {
+    let mut copy = path.as_os_str().to_owned();
+    copy.push(".wgetf-unlink");
+    PathBuf::from(copy)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[tokio::test]
+    async fn test_symlinked_directory_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        symlink(outside.path(), root.path().join("host")).unwrap();
+
+        let dir = root.path().join("host/etc");
+        let result = create_dirs_within(root.path(), &dir, false).await;
+        assert!(matches!(result, Err(Error::UnsafeOutputPath(ref p)) if p.ends_with("host")));
+        assert_eq!(std::fs::read_dir(outside.path()).unwrap().count(), 0);
+
+        create_dirs_within(root.path(), &dir, true).await.unwrap();
+        assert!(outside.path().join("etc").is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_directories_created_below_root() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("./a/b/c");
+        create_dirs_within(root.path(), &dir, false).await.unwrap();
+        assert!(root.path().join("a/b/c").is_dir());
+        // Existing directories are fine
+        create_dirs_within(root.path(), &dir, false).await.unwrap();
+        let escaping = root.path().join("a/../../x");
+        assert!(create_dirs_within(root.path(), &escaping, false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_symlinked_file_replaced() {
+        let root = tempfile::tempdir().unwrap();
+        let target = root.path().join("passwd");
+        std::fs::write(&target, "secret").unwrap();
+        let link = root.path().join("robots.txt");
+        symlink(&target, &link).unwrap();
+
+        replace_links(&link, true).await.unwrap();
+        assert!(link.is_symlink());
+        replace_links(&link, false).await.unwrap();
+        assert!(!link.exists());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "secret");
+    }
+
+    #[tokio::test]
+    async fn test_hardlinked_file_separated() {
+        let root = tempfile::tempdir().unwrap();
+        let original = root.path().join("original");
+        std::fs::write(&original, "partial").unwrap();
+        let link = root.path().join("page.html");
+        std::fs::hard_link(&original, &link).unwrap();
+        let modified = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(&link, modified).unwrap();
+
+        replace_links(&link, false).await.unwrap();
+        let metadata = std::fs::metadata(&link).unwrap();
+        assert_eq!(filetime::FileTime::from_last_modification_time(&metadata), modified);
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "partial");
+        std::fs::write(&link, "rewritten").unwrap();
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "partial");
+        let metadata = std::fs::metadata(&original).unwrap();
+        assert_eq!(std::os::unix::fs::MetadataExt::nlink(&metadata), 1);
+        assert!(!unlinked_copy_path(&link).exists());
+    }
+}
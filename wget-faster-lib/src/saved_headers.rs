@@ -0,0 +1,183 @@
+/// Header preamble of files saved with `save_headers` (`--save-headers`)
+///
+/// Such a file starts with the response's status line and headers, ended by
+/// an empty line, followed by the body exactly as received. [`split`] finds
+/// where the body starts, so scripts (and link extraction and conversion)
+/// can read the file without taking the headers for content. Files written
+/// by GNU wget are read the same way: lines may end with `\r\n` or `\n`, and
+/// folded header lines are joined.
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// Status line and headers recovered from a saved file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedHeaders {
+    /// Protocol of the status line (`HTTP/1.1`)
+    pub version: String,
+
+    /// Status code
+    pub status: u16,
+
+    /// Reason phrase (`OK`), empty if the status line had none
+    pub reason: String,
+
+    /// Response headers, in the order they were saved
+    pub headers: HeaderMap,
+}
+
+/// Split a saved file into its header preamble and the offset where its body starts
+///
+/// Returns `(None, 0)` if `bytes` doesn't start with a complete preamble
+/// (a status line and headers ended by an empty line), so a file saved
+/// without `save_headers` is all body. Header lines that aren't `Name: value`
+/// are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use wget_faster_lib::saved_headers;
+///
+/// let file = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>hi</p>";
+/// let (headers, body_start) = saved_headers::split(file);
+/// let headers = headers.unwrap();
+/// assert_eq!(headers.status, 200);
+/// assert_eq!(headers.headers["content-type"], "text/html");
+/// assert_eq!(&file[body_start..], b"<p>hi</p>");
+/// ```
+pub fn split(bytes: &[u8]) -> (Option<SavedHeaders>, usize) {
+    if !bytes.starts_with(b"HTTP/") {
+        return (None, 0);
+    }
+    let Some((head_len, body_start)) = find_head_end(bytes) else {
+        return (None, 0);
+    };
+    let head = String::from_utf8_lossy(&bytes[..head_len]);
+    let mut lines = head.lines();
+
+    let Some((version, status, reason)) = lines.next().and_then(parse_status_line) else {
+        return (None, 0);
+    };
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in lines {
+        if line.starts_with([' ', '\t']) {
+            // Obsolete line folding: the line continues the previous value
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in fields {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    let saved = SavedHeaders {
+        version,
+        status,
+        reason,
+        headers,
+    };
+    (Some(saved), body_start)
+}
+
+/// Preamble to write before the body of a file saved with `save_headers`
+///
+/// As GNU wget writes it: the status line and header lines, each ended by
+/// `\r\n`, then an empty line.
+pub(crate) fn preamble(version: reqwest::Version, status: u16, headers: &HeaderMap) -> Vec<u8> {
+    let reason = reqwest::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("");
+    let mut preamble = format!("{version:?} {status} {reason}\r\n").into_bytes();
+    for (name, value) in headers {
+        preamble.extend_from_slice(name.as_str().as_bytes());
+        preamble.extend_from_slice(b": ");
+        preamble.extend_from_slice(value.as_bytes());
+        preamble.extend_from_slice(b"\r\n");
+    }
+    preamble.extend_from_slice(b"\r\n");
+    preamble
+}
+
+/// Length of the head (without the empty line ending it) and offset of the body
+fn find_head_end(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut line_start = 0;
+    while let Some(newline) = bytes[line_start..].iter().position(|&b| b == b'\n') {
+        let line_end = line_start + newline;
+        let line = &bytes[line_start..line_end];
+        if line.is_empty() || line == b"\r" {
+            return Some((line_start, line_end + 1));
+        }
+        line_start = line_end + 1;
+    }
+    None
+}
+
+/// `HTTP/1.1 200 OK` as (version, status, reason)
+fn parse_status_line(line: &str) -> Option<(String, u16, String)> {
+    let mut parts = line.splitn(3, ' ');
+    let version = parts.next()?;
+    let status = parts.next()?.parse().ok()?;
+    let reason = parts.next().unwrap_or("").trim();
+    Some((version.to_string(), status, reason.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gnu_wget_preamble() {
+        let file = b"HTTP/1.1 404 Not Found\r\nServer: nginx\r\nX-Long: one\r\n  two\r\n\r\nbody\r\n\r\nmore";
+        let (headers, body_start) = split(file);
+        let headers = headers.unwrap();
+        assert_eq!(headers.version, "HTTP/1.1");
+        assert_eq!(headers.status, 404);
+        assert_eq!(headers.reason, "Not Found");
+        assert_eq!(headers.headers["server"], "nginx");
+        assert_eq!(headers.headers["x-long"], "one two");
+        assert_eq!(&file[body_start..], b"body\r\n\r\nmore");
+    }
+
+    #[test]
+    fn test_bare_newlines_and_missing_reason() {
+        let file = b"HTTP/2 204\nA: 1\n\n";
+        let (headers, body_start) = split(file);
+        let headers = headers.unwrap();
+        assert_eq!((headers.status, headers.reason.as_str()), (204, ""));
+        assert_eq!(headers.headers["a"], "1");
+        assert_eq!(body_start, file.len());
+    }
+
+    #[test]
+    fn test_no_preamble() {
+        assert_eq!(split(b"<html>HTTP/1.1 200 OK\r\n\r\n"), (None, 0));
+        // Headers never ended: not a preamble
+        assert_eq!(split(b"HTTP/1.1 200 OK\r\nA: 1\r\n"), (None, 0));
+        assert_eq!(split(b"HTTP/1.1 abc\r\n\r\nbody"), (None, 0));
+    }
+
+    #[test]
+    fn test_preamble_round_trip() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        headers.append("set-cookie", HeaderValue::from_static("a=1"));
+        headers.append("set-cookie", HeaderValue::from_static("b=2"));
+        let mut file = preamble(reqwest::Version::HTTP_11, 200, &headers);
+        assert!(file.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        file.extend_from_slice(b"body");
+
+        let (saved, body_start) = split(&file);
+        let saved = saved.unwrap();
+        assert_eq!(saved.headers, headers);
+        assert_eq!(&file[body_start..], b"body");
+    }
+}
@@ -0,0 +1,287 @@
+/// sitemap.xml parsing: `<urlset>` and `<sitemapindex>` documents, plain or gzipped
+use crate::{Error, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::Read;
+
+/// How many levels of sitemap indexes are followed below the first sitemap
+pub const MAX_SITEMAP_DEPTH: usize = 3;
+
+/// Largest decompressed sitemap accepted (the sitemaps.org limit is 50 MB)
+const MAX_SITEMAP_BYTES: u64 = 50 * 1024 * 1024;
+
+/// One `<url>` or `<sitemap>` entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SitemapEntry {
+    /// `<loc>`: the page, or the child sitemap of an index
+    pub loc: String,
+
+    /// `<lastmod>`, if present and in W3C datetime format
+    pub lastmod: Option<DateTime<Utc>>,
+}
+
+/// A parsed sitemap document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sitemap {
+    /// `<urlset>`: pages to download
+    UrlSet(Vec<SitemapEntry>),
+
+    /// `<sitemapindex>`: further sitemaps to fetch
+    Index(Vec<SitemapEntry>),
+}
+
+/// Parse a sitemap or sitemap index
+///
+/// Gzipped bodies are recognized by their magic bytes, so `.xml.gz` files work
+/// whether or not the server also sent `Content-Encoding: gzip`. Entries without
+/// a `<loc>` are skipped; a malformed `<lastmod>` is ignored.
+pub fn parse_sitemap(body: &[u8]) -> Result<Sitemap> {
+    let xml = if body.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(body)
+            .take(MAX_SITEMAP_BYTES + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| Error::InvalidSitemap(format!("gzip: {e}")))?;
+        if decompressed.len() as u64 > MAX_SITEMAP_BYTES {
+            return Err(Error::InvalidSitemap(format!(
+                "larger than {MAX_SITEMAP_BYTES} bytes when decompressed"
+            )));
+        }
+        decompressed
+    } else {
+        body.to_vec()
+    };
+    parse_xml(&xml)
+}
+
+/// Parse the decompressed XML document
+fn parse_xml(xml: &[u8]) -> Result<Sitemap> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    let mut collector = EntryCollector::default();
+
+    loop {
+        match reader.read_event_into(&mut buf).map_err(invalid)? {
+            Event::Start(start) => collector.start(start.local_name().as_ref())?,
+            Event::End(end) => collector.end(end.local_name().as_ref()),
+            Event::Text(text) => collector.text(&text.xml_content().map_err(invalid)?),
+            Event::CData(cdata) => collector.text(&cdata.decode().map_err(invalid)?),
+            Event::GeneralRef(reference) => {
+                let resolved = if reference.is_char_ref() {
+                    reference.resolve_char_ref().map_err(invalid)?
+                } else {
+                    let name = reference.decode().map_err(invalid)?;
+                    quick_xml::escape::resolve_predefined_entity(&name)
+                        .and_then(|s| s.chars().next())
+                };
+                collector.text(&resolved.map(String::from).unwrap_or_default());
+            },
+            Event::Eof => break,
+            _ => {},
+        }
+        buf.clear();
+    }
+
+    match collector.root.as_deref() {
+        Some(b"sitemapindex") => Ok(Sitemap::Index(collector.entries)),
+        Some(_) => Ok(Sitemap::UrlSet(collector.entries)),
+        None => Err(Error::InvalidSitemap("no <urlset> or <sitemapindex> element".to_string())),
+    }
+}
+
+/// Entries collected while reading a sitemap document
+#[derive(Default)]
+struct EntryCollector {
+    /// Root element name (`urlset` or `sitemapindex`), once seen
+    root: Option<Vec<u8>>,
+    entries: Vec<SitemapEntry>,
+    /// `<url>` or `<sitemap>` entry being read
+    entry: Option<SitemapEntry>,
+    /// Field being read (`loc` or `lastmod`) and its text so far
+    field: Option<(Vec<u8>, String)>,
+}
+
+impl EntryCollector {
+    fn start(&mut self, name: &[u8]) -> Result<()> {
+        let name = name.to_ascii_lowercase();
+        match (&self.root, name.as_slice()) {
+            (None, b"urlset" | b"sitemapindex") => self.root = Some(name),
+            (None, _) => {
+                return Err(Error::InvalidSitemap(format!(
+                    "unexpected root element <{}>",
+                    String::from_utf8_lossy(&name)
+                )));
+            },
+            (Some(_), b"url" | b"sitemap") => {
+                self.entry = Some(SitemapEntry {
+                    loc: String::new(),
+                    lastmod: None,
+                });
+            },
+            (Some(_), b"loc" | b"lastmod") if self.entry.is_some() => {
+                self.field = Some((name, String::new()));
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some((_, ref mut value)) = self.field {
+            value.push_str(text);
+        }
+    }
+
+    fn end(&mut self, name: &[u8]) {
+        match name.to_ascii_lowercase().as_slice() {
+            b"loc" | b"lastmod" => {
+                if let (Some((field, value)), Some(entry)) = (self.field.take(), &mut self.entry) {
+                    let value = value.trim();
+                    if field == b"loc" {
+                        entry.loc = value.to_string();
+                    } else {
+                        entry.lastmod = parse_w3c_datetime(value);
+                    }
+                }
+            },
+            b"url" | b"sitemap" => {
+                self.entries
+                    .extend(self.entry.take().filter(|e| !e.loc.is_empty()));
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Wrap a quick-xml error as `InvalidSitemap`
+fn invalid(e: impl std::fmt::Display) -> Error {
+    Error::InvalidSitemap(e.to_string())
+}
+
+/// Parse a W3C datetime as used by `<lastmod>`
+///
+/// Accepts a full timestamp with offset (seconds optional) or a bare date,
+/// which is taken as midnight UTC.
+fn parse_w3c_datetime(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    if let Ok(datetime) = DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M%#z") {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|datetime| datetime.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::io::Write;
+
+    const INDEX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap>
+    <loc>http://example.com/sitemap-posts.xml</loc>
+    <lastmod>2024-03-01T12:00:00+02:00</lastmod>
+  </sitemap>
+  <sitemap>
+    <loc>http://example.com/sitemap-pages.xml.gz</loc>
+  </sitemap>
+</sitemapindex>"#;
+
+    const URLSET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url>
+    <loc>http://example.com/a.html</loc>
+    <lastmod>2024-01-15</lastmod>
+    <changefreq>daily</changefreq>
+  </url>
+  <url>
+    <loc> http://example.com/search?q=1&amp;page=2 </loc>
+    <lastmod>yesterday</lastmod>
+  </url>
+  <url><loc><![CDATA[http://example.com/c.html]]></loc></url>
+  <url><lastmod>2024-01-15</lastmod></url>
+</urlset>"#;
+
+    #[test]
+    fn test_parse_sitemap_index() {
+        let Sitemap::Index(children) = parse_sitemap(INDEX.as_bytes()).unwrap() else {
+            panic!("expected a sitemap index");
+        };
+        assert_eq!(
+            children,
+            vec![
+                SitemapEntry {
+                    loc: "http://example.com/sitemap-posts.xml".to_string(),
+                    lastmod: Some(Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap()),
+                },
+                SitemapEntry {
+                    loc: "http://example.com/sitemap-pages.xml.gz".to_string(),
+                    lastmod: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_urlset() {
+        let Sitemap::UrlSet(urls) = parse_sitemap(URLSET.as_bytes()).unwrap() else {
+            panic!("expected a urlset");
+        };
+        let locs: Vec<&str> = urls.iter().map(|u| u.loc.as_str()).collect();
+        // Entities resolved, whitespace trimmed, entries without <loc> dropped
+        assert_eq!(
+            locs,
+            vec![
+                "http://example.com/a.html",
+                "http://example.com/search?q=1&page=2",
+                "http://example.com/c.html",
+            ]
+        );
+        assert_eq!(urls[0].lastmod, Some(Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap()));
+        assert_eq!(urls[1].lastmod, None);
+    }
+
+    #[test]
+    fn test_parse_gzipped_sitemap() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(URLSET.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_eq!(parse_sitemap(&gzipped).unwrap(), parse_sitemap(URLSET.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid_sitemap() {
+        assert!(matches!(
+            parse_sitemap(b"<html><body>Not found</body></html>"),
+            Err(Error::InvalidSitemap(_))
+        ));
+        assert!(matches!(parse_sitemap(b""), Err(Error::InvalidSitemap(_))));
+        assert!(matches!(
+            parse_sitemap(b"<urlset><url><loc>x</url></urlset>"),
+            Err(Error::InvalidSitemap(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_w3c_datetime() {
+        let expected = Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 0).unwrap();
+        assert_eq!(parse_w3c_datetime("2024-05-06T07:08:00Z"), Some(expected));
+        assert_eq!(parse_w3c_datetime("2024-05-06T09:08+02:00"), Some(expected));
+        assert_eq!(
+            parse_w3c_datetime("2024-05-06T07:08:00.5Z").map(|d| d.timestamp()),
+            Some(expected.timestamp())
+        );
+        assert_eq!(
+            parse_w3c_datetime("2024-05-06"),
+            Some(Utc.with_ymd_and_hms(2024, 5, 6, 0, 0, 0).unwrap())
+        );
+        assert_eq!(parse_w3c_datetime("May 6"), None);
+    }
+}
@@ -0,0 +1,230 @@
+/// Staged file downloads: download into a local directory, then move the file into place
+use crate::{ProgressCallback, ProgressInfo, Result, TransferPhase};
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Appended to the names of files being downloaded in the staging directory
+const STAGING_SUFFIX: &str = ".wgetf-staging";
+
+/// Appended to the destination's name while a cross-device copy is in progress
+const MOVE_SUFFIX: &str = ".wgetf-move";
+
+/// Copy buffer size for cross-device moves
+const COPY_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Staging file for `destination` inside `staging_dir`
+///
+/// The name combines the destination's file name with a digest of its full
+/// path: two destinations with the same name don't collide, and a rerun finds
+/// the partial file again to resume it.
+pub(crate) fn staging_path(staging_dir: &Path, destination: &Path) -> PathBuf {
+    let absolute = std::path::absolute(destination).unwrap_or_else(|_| destination.to_path_buf());
+    let digest =
+        ring::digest::digest(&ring::digest::SHA256, absolute.as_os_str().as_encoded_bytes());
+    let tag = digest.as_ref()[..8]
+        .iter()
+        .fold(String::new(), |mut tag, b| {
+            let _ = write!(tag, "{b:02x}");
+            tag
+        });
+    let name = destination
+        .file_name()
+        .map_or_else(|| "download".into(), |name| name.to_string_lossy());
+    staging_dir.join(format!("{name}.{tag}{STAGING_SUFFIX}"))
+}
+
+/// Move a finished staging file to `destination`
+///
+/// Renames when both are on the same filesystem. Otherwise the file is copied
+/// next to the destination (reporting `TransferPhase::Finalizing` progress),
+/// synced, and renamed over it, so the destination never holds a partial copy.
+pub(crate) async fn move_into_place(
+    staged: &Path,
+    destination: &Path,
+    url: &str,
+    progress_callback: Option<&ProgressCallback>,
+) -> Result<()> {
+    move_with(staged, destination, url, progress_callback, |from, to| {
+        std::fs::rename(from, to)
+    })
+    .await
+}
+
+/// `move_into_place` with the initial rename injected (tests force it to fail)
+async fn move_with<R>(
+    staged: &Path,
+    destination: &Path,
+    url: &str,
+    progress_callback: Option<&ProgressCallback>,
+    rename: R,
+) -> Result<()>
+where
+    R: Fn(&Path, &Path) -> io::Result<()>,
+{
+    match rename(staged, destination) {
+        Ok(()) => return Ok(()),
+        Err(e) => {
+            tracing::debug!(from = %staged.display(), to = %destination.display(), error = %e, "Rename failed - copying across filesystems");
+        },
+    }
+
+    let mut partial = destination.as_os_str().to_owned();
+    partial.push(MOVE_SUFFIX);
+    let partial = PathBuf::from(partial);
+    if let Err(e) = copy_synced(staged, &partial, url, progress_callback).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&partial, destination).await?;
+    tokio::fs::remove_file(staged).await?;
+    Ok(())
+}
+
+/// Copy `from` to `to` with progress, keeping permissions and modification time, then fsync
+async fn copy_synced(
+    from: &Path,
+    to: &Path,
+    url: &str,
+    progress_callback: Option<&ProgressCallback>,
+) -> Result<()> {
+    let source_metadata = tokio::fs::metadata(from).await?;
+    let mut source = tokio::fs::File::open(from).await?;
+    let mut target = tokio::fs::File::create(to).await?;
+
+    let mut progress = ProgressInfo::new(url.to_string());
+    progress.phase = TransferPhase::Finalizing;
+    progress.total_size = Some(source_metadata.len());
+    let start_time = Instant::now();
+
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    loop {
+        let read = source.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        target.write_all(&buffer[..read]).await?;
+        if let Some(callback) = progress_callback {
+            progress.update(read as u64, start_time);
+            callback(progress.clone());
+        }
+    }
+    target.flush().await?;
+    target.sync_all().await?;
+    drop(target);
+
+    tokio::fs::set_permissions(to, source_metadata.permissions()).await?;
+    let modified = filetime::FileTime::from_last_modification_time(&source_metadata);
+    filetime::set_file_mtime(to, modified)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_staging_path_is_stable_and_unique() {
+        let staging = Path::new("/tmp/staging");
+        let a = staging_path(staging, Path::new("/mnt/nfs/a/file.iso"));
+        let b = staging_path(staging, Path::new("/mnt/nfs/b/file.iso"));
+
+        assert_eq!(a, staging_path(staging, Path::new("/mnt/nfs/a/file.iso")));
+        assert_ne!(a, b);
+        assert_eq!(a.parent(), Some(staging));
+        let name = a.file_name().unwrap().to_string_lossy();
+        assert!(name.starts_with("file.iso.") && name.ends_with(STAGING_SUFFIX), "{name}");
+    }
+
+    #[tokio::test]
+    async fn test_move_into_place_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged = dir.path().join("staged");
+        let destination = dir.path().join("file.bin");
+        std::fs::write(&staged, b"payload").unwrap();
+
+        move_into_place(&staged, &destination, "http://example.com/file.bin", None)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&destination).unwrap(), b"payload");
+        assert!(!staged.exists());
+    }
+
+    #[tokio::test]
+    async fn test_staged_download_resumes_from_staging_file() {
+        let mut server = mockito::Server::new_async().await;
+        let resumed = server
+            .mock("GET", "/file.txt")
+            .match_header("range", "bytes=6-")
+            .with_status(206)
+            .with_header("content-range", "bytes 6-10/11")
+            .with_body("world")
+            .create_async()
+            .await;
+
+        let staging_dir = tempfile::tempdir().unwrap();
+        let destination_dir = tempfile::tempdir().unwrap();
+        let destination = destination_dir.path().join("file.txt");
+        std::fs::write(staging_path(staging_dir.path(), &destination), b"hello ").unwrap();
+
+        let config = crate::DownloadConfig {
+            staging_dir: Some(staging_dir.path().to_path_buf()),
+            ..crate::DownloadConfig::default()
+        };
+        let result = crate::Downloader::new(config)
+            .unwrap()
+            .download_to_file(&format!("{}/file.txt", server.url()), destination.clone())
+            .await
+            .unwrap();
+
+        resumed.assert_async().await;
+        assert_eq!(result.data.path(), Some(&destination));
+        assert_eq!(std::fs::read(&destination).unwrap(), b"hello world");
+        assert_eq!(std::fs::read_dir(staging_dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cross_device_move_copies_with_progress() {
+        let staging_dir = tempfile::tempdir().unwrap();
+        let destination_dir = tempfile::tempdir().unwrap();
+        let staged = staging_dir.path().join("staged");
+        let destination = destination_dir.path().join("file.bin");
+        let content = vec![7u8; COPY_BUFFER_SIZE * 2 + 10];
+        std::fs::write(&staged, &content).unwrap();
+        let mtime = filetime::FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(&staged, mtime).unwrap();
+
+        let reports: Arc<Mutex<Vec<ProgressInfo>>> = Arc::default();
+        let recorded = reports.clone();
+        let callback: ProgressCallback = Arc::new(move |p| recorded.lock().unwrap().push(p));
+        let cross_device = |_: &Path, _: &Path| Err(io::Error::from(io::ErrorKind::CrossesDevices));
+
+        move_with(
+            &staged,
+            &destination,
+            "http://example.com/file.bin",
+            Some(&callback),
+            cross_device,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(std::fs::read(&destination).unwrap(), content);
+        assert!(!staged.exists());
+        let mut leftovers = std::fs::read_dir(destination_dir.path()).unwrap();
+        assert_eq!(leftovers.next().unwrap().unwrap().file_name(), "file.bin");
+        assert!(leftovers.next().is_none());
+        let metadata = std::fs::metadata(&destination).unwrap();
+        assert_eq!(filetime::FileTime::from_last_modification_time(&metadata), mtime);
+
+        let reports = reports.lock().unwrap();
+        assert!(reports.len() >= 3);
+        assert!(reports.iter().all(|p| p.phase == TransferPhase::Finalizing));
+        assert_eq!(reports.last().unwrap().downloaded, content.len() as u64);
+        assert_eq!(reports.last().unwrap().total_size, Some(content.len() as u64));
+    }
+}
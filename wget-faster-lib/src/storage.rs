@@ -0,0 +1,255 @@
+/// Storage exhaustion detection and free space checks
+///
+/// Running out of disk space mid-download is recoverable: the user frees
+/// space and resumes. These helpers let the writer paths recognize ENOSPC
+/// and EDQUOT so partial files are kept instead of being cleaned up.
+use crate::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Check whether an I/O error means the disk (or quota) is full
+pub(crate) fn is_storage_exhausted(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded)
+}
+
+/// Convert a write failure into `Error::DiskFull` if storage is exhausted
+///
+/// Context added with `Error::with_context` is dropped along with the I/O error;
+/// other errors are returned unchanged.
+pub(crate) fn classify_write_error(
+    err: Error,
+    path: &Path,
+    written: u64,
+    needed: Option<u64>,
+) -> Error {
+    match err.root() {
+        Error::IoError(io_err) if is_storage_exhausted(io_err) => Error::DiskFull {
+            path: path.to_path_buf(),
+            written,
+            needed,
+        },
+        _ => err,
+    }
+}
+
+/// Available space in bytes on the filesystem containing `path`
+///
+/// Returns `None` if it cannot be determined (unsupported platform or the
+/// filesystem query failed), in which case callers should not fail.
+#[cfg(unix)]
+pub(crate) fn available_space(path: &Path) -> Option<u64> {
+    let dir = existing_ancestor(path)?;
+    let stat = rustix::fs::statvfs(&dir).ok()?;
+    Some(stat.f_bavail.saturating_mul(stat.f_frsize))
+}
+
+/// Available space in bytes on the filesystem containing `path`
+#[cfg(not(unix))]
+pub(crate) fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Check whether a file could be written at `path`
+///
+/// An existing file must not be read-only; otherwise the nearest existing
+/// ancestor directory must not be read-only (missing directories are created).
+pub(crate) fn is_writable(path: &Path) -> bool {
+    if let Ok(metadata) = std::fs::metadata(path) {
+        return metadata.is_file() && !metadata.permissions().readonly();
+    }
+    existing_ancestor(path)
+        .and_then(|dir| std::fs::metadata(dir).ok())
+        .is_some_and(|m| !m.permissions().readonly())
+}
+
+/// Nearest existing directory for a (possibly not yet created) file path
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    let mut current = path.parent().map(Path::to_path_buf);
+    while let Some(dir) = current {
+        let dir = if dir.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            dir
+        };
+        if dir.is_dir() {
+            return Some(dir);
+        }
+        current = dir.parent().map(Path::to_path_buf);
+    }
+    None
+}
+
+/// Fail fast if there is not enough free space for `needed` bytes
+pub(crate) fn ensure_free_space(path: &Path, written: u64, needed: u64) -> crate::Result<()> {
+    match available_space(path) {
+        Some(available) if available < needed => {
+            tracing::warn!(
+                path = %path.display(),
+                available,
+                needed,
+                "Not enough free space for download"
+            );
+            Err(Error::DiskFull {
+                path: path.to_path_buf(),
+                written,
+                needed: Some(needed),
+            })
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+    /// Writer shim passing `limit` bytes on to `inner`, then failing with ENOSPC
+    struct FullDisk<W> {
+        inner: W,
+        written: usize,
+        limit: usize,
+    }
+
+    impl<W> FullDisk<W> {
+        fn new(inner: W, limit: usize) -> Self {
+            Self {
+                inner,
+                written: 0,
+                limit,
+            }
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin> AsyncWrite for FullDisk<W> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            if self.written >= self.limit {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::StorageFull)));
+            }
+            let n = buf.len().min(self.limit - self.written);
+            let n = std::task::ready!(Pin::new(&mut self.inner).poll_write(cx, &buf[..n]))?;
+            self.written += n;
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    impl crate::clobber::OutputFile for FullDisk<crate::clobber::LazyFile> {
+        fn is_open(&self) -> bool {
+            self.inner.is_open()
+        }
+    }
+
+    #[test]
+    fn test_is_storage_exhausted() {
+        assert!(is_storage_exhausted(&io::Error::from(io::ErrorKind::StorageFull)));
+        assert!(is_storage_exhausted(&io::Error::from(io::ErrorKind::QuotaExceeded)));
+        assert!(!is_storage_exhausted(&io::Error::from(io::ErrorKind::PermissionDenied)));
+    }
+
+    #[tokio::test]
+    async fn test_failing_writer_classified_as_disk_full() {
+        let mut writer = FullDisk::new(tokio::io::sink(), 10);
+        let err = writer.write_all(&[0u8; 32]).await.unwrap_err();
+        assert_eq!(writer.written, 10);
+
+        let path = Path::new("out.bin");
+        match classify_write_error(Error::IoError(err), path, 10, Some(32)) {
+            Error::DiskFull {
+                path: p,
+                written,
+                needed,
+            } => {
+                assert_eq!(p, path);
+                assert_eq!(written, 10);
+                assert_eq!(needed, Some(32));
+            },
+            other => panic!("expected DiskFull, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_other_errors_unchanged() {
+        let err = classify_write_error(Error::Timeout, Path::new("x"), 0, None);
+        assert!(matches!(err, Error::Timeout));
+    }
+
+    #[tokio::test]
+    async fn test_disk_full_download_keeps_partial_file_for_resume() {
+        let body: Vec<u8> = (0..=255u8).cycle().take(64 * 1024).collect();
+        let total = body.len() as u64;
+        let mut server = mockito::Server::new_async().await;
+        let _head = server
+            .mock("HEAD", "/file.bin")
+            .with_header("accept-ranges", "bytes")
+            .with_header("content-length", &total.to_string())
+            .create_async()
+            .await;
+        let _whole = server
+            .mock("GET", "/file.bin")
+            .match_header("range", mockito::Matcher::Missing)
+            .with_body(&body)
+            .create_async()
+            .await;
+        let rest = server
+            .mock("GET", "/file.bin")
+            .match_header("range", "bytes=10000-")
+            .with_status(206)
+            .with_header("content-range", &format!("bytes 10000-{}/{total}", total - 1))
+            .with_body(&body[10_000..])
+            .create_async()
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.bin");
+        let url = format!("{}/file.bin", server.url());
+        let downloader = crate::Downloader::new(crate::DownloadConfig::default()).unwrap();
+
+        let result = downloader
+            .transfer_through(&url, path.clone(), None, false, |file| FullDisk::new(file, 10_000))
+            .await;
+        match result {
+            Err(Error::DiskFull {
+                path: p,
+                written,
+                needed,
+            }) => {
+                assert_eq!(p, path);
+                assert_eq!(written, 10_000);
+                assert_eq!(needed, Some(total - 10_000));
+            },
+            other => panic!("expected DiskFull, got {other:?}"),
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), &body[..10_000]);
+
+        // Once space is freed, the download continues from the partial file
+        downloader
+            .download_to_file(&url, path.clone())
+            .await
+            .unwrap();
+        rest.assert_async().await;
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_free_space_check() {
+        let path = std::env::temp_dir().join("wgetf-free-space-probe.bin");
+        assert!(available_space(&path).is_some());
+        assert!(ensure_free_space(&path, 0, 1).is_ok());
+        assert!(matches!(ensure_free_space(&path, 0, u64::MAX), Err(Error::DiskFull { .. })));
+    }
+}
@@ -0,0 +1,315 @@
+/// Streaming downloads: body chunks are yielded in order as they arrive
+use crate::body_limit::BodyDeadline;
+use crate::content_coding::{self, ContentCoding};
+use crate::parallel::{self, ObjectIdentity};
+use crate::response_handler::check_partial_content;
+use crate::{
+    DownloadConfig, DownloadEvent, Error, HttpClient, ProgressCallback, ProgressInfo, Result,
+    RetryConfig,
+};
+use bytes::Bytes;
+use futures::stream::{self, BoxStream, StreamExt};
+use reqwest::header::{ACCEPT_RANGES, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// Body chunks of a streamed download
+pub(crate) type ChunkStream = BoxStream<'static, Result<Bytes>>;
+
+/// Applies the speed limit and time limit, and reports progress for each chunk
+/// handed to the caller
+struct Pacer {
+    speed_limit: Option<u64>,
+    deadline: BodyDeadline,
+    last_chunk: Instant,
+    start_time: Instant,
+    progress: ProgressInfo,
+    callback: Option<ProgressCallback>,
+}
+
+impl Pacer {
+    fn new(
+        client: &HttpClient,
+        url: &str,
+        total_size: Option<u64>,
+        callback: Option<ProgressCallback>,
+    ) -> Self {
+        let mut progress = ProgressInfo::new(url.to_string());
+        progress.total_size = total_size;
+        Self {
+            speed_limit: client.config().speed_limit,
+            deadline: BodyDeadline::start(client.config().max_body_duration),
+            last_chunk: Instant::now(),
+            start_time: Instant::now(),
+            progress,
+            callback,
+        }
+    }
+
+    async fn pass(&mut self, chunk: &Bytes) {
+        let chunk_size = chunk.len() as u64;
+        if let Some(speed_limit) = self.speed_limit {
+            let expected_duration = Duration::from_secs_f64(chunk_size as f64 / speed_limit as f64);
+            if let Some(remaining) = expected_duration.checked_sub(self.last_chunk.elapsed()) {
+                sleep(remaining).await;
+            }
+            self.last_chunk = Instant::now();
+        }
+        if let Some(callback) = &self.callback {
+            self.progress.update(chunk_size, self.start_time);
+            callback(self.progress.clone());
+        }
+    }
+}
+
+/// Whether sending the request again may succeed where `error` failed
+pub(crate) fn is_transient(error: &Error, retry: &RetryConfig) -> bool {
+    match error.root() {
+        Error::HttpError(_) | Error::ChunkError(_) | Error::Timeout => true,
+        Error::InvalidStatus(status) => retry.retry_on_status.contains(status),
+        _ => false,
+    }
+}
+
+/// Delay before retry number `attempt` (from 1), growing by `backoff_multiplier`
+pub(crate) fn backoff(retry: &RetryConfig, attempt: usize) -> Duration {
+    let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+    let delay = retry.initial_delay.as_secs_f64() * retry.backoff_multiplier.powi(exponent);
+    Duration::from_secs_f64(delay.min(retry.max_delay.as_secs_f64()))
+}
+
+/// Delay before retry number `attempt` (from 1) of a whole request
+///
+/// With `wait_retry`, like wget's `--waitretry`: 1 second after the first
+/// failure, 2 after the second and so on, up to `wait_retry`. Otherwise the
+/// exponential [`backoff`].
+pub(crate) fn retry_delay(config: &DownloadConfig, attempt: usize) -> Duration {
+    match config.wait_retry {
+        Some(max) => Duration::from_secs(attempt as u64).min(max),
+        None => backoff(&config.retry, attempt),
+    }
+}
+
+/// Stream the body of a sequential download's GET response
+///
+/// If the connection drops, the rest of the body is requested with a Range
+/// request made conditional (`If-Range`) on the response's strong `ETag` or
+/// `Last-Modified`. A response without either, or from a server not accepting
+/// byte ranges, can't be resumed safely and the failure ends the stream.
+pub(crate) fn sequential_chunks(
+    client: &HttpClient,
+    url: &str,
+    response: reqwest::Response,
+    progress_callback: Option<ProgressCallback>,
+) -> Result<ChunkStream> {
+    let range_total = if response.status().as_u16() == 206 {
+        check_partial_content(&response, 0)?
+    } else {
+        None
+    };
+    let total_size = range_total.or_else(|| response.content_length());
+    // Progress counts the bytes received, so `total_size` stays the coded size
+    let coding = ContentCoding::of_response(&response, client.config());
+
+    let headers = response.headers();
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let validator = header(ACCEPT_RANGES)
+        .filter(|v| v.eq_ignore_ascii_case("bytes"))
+        .and_then(|_| {
+            // A weak ETag can't be used in If-Range (RFC 9110 13.1.5)
+            header(ETAG)
+                .filter(|etag| !etag.starts_with("W/"))
+                .or_else(|| header(LAST_MODIFIED))
+        })
+        .map(str::to_string);
+
+    let body = SequentialBody {
+        client: client.clone(),
+        url: url.to_string(),
+        body: response.bytes_stream().boxed(),
+        received: 0,
+        validator,
+        retries: 0,
+        pacer: Pacer::new(client, url, total_size, progress_callback),
+    };
+    let chunks = stream::unfold(Some(body), |body| async move {
+        let mut body = body?;
+        let item = body.next().await?;
+        // An error ends the stream
+        let body = item.is_ok().then_some(body);
+        Some((item, body))
+    })
+    .boxed();
+    Ok(content_coding::decode(chunks, coding))
+}
+
+/// Body of a sequential download, resumed with a Range request if the connection drops
+struct SequentialBody {
+    client: HttpClient,
+    url: String,
+    body: BoxStream<'static, reqwest::Result<Bytes>>,
+    /// Bytes handed to the caller so far
+    received: u64,
+    /// `If-Range` value for resuming (`None` if the body can't be resumed)
+    validator: Option<String>,
+    /// Resumes attempted so far, limited by `retry.max_retries`
+    retries: usize,
+    pacer: Pacer,
+}
+
+impl SequentialBody {
+    /// Next chunk, resuming the body after a transient failure
+    async fn next(&mut self) -> Option<Result<Bytes>> {
+        loop {
+            let next = match self.pacer.deadline.run(self.body.next()).await {
+                Ok(next) => next?,
+                Err(e) => return Some(Err(e)),
+            };
+            let error = match next {
+                Ok(chunk) if chunk.is_empty() => continue,
+                Ok(chunk) => {
+                    if let Err(e) = self.client.quota().add(chunk.len() as u64) {
+                        return Some(Err(e));
+                    }
+                    self.received += chunk.len() as u64;
+                    self.pacer.pass(&chunk).await;
+                    return Some(Ok(chunk));
+                },
+                Err(e) => Error::from(e),
+            };
+            if let Err(e) = self.resume(error).await {
+                return Some(Err(e));
+            }
+        }
+    }
+
+    /// Reopen the body at `received` after it failed with `error`, backing off between attempts
+    async fn resume(&mut self, mut error: Error) -> Result<()> {
+        let retry = self.client.config().retry.clone();
+        loop {
+            let Some(validator) = self.validator.clone() else {
+                return Err(error);
+            };
+            if self.retries >= retry.max_retries || !is_transient(&error, &retry) {
+                return Err(error);
+            }
+            self.retries += 1;
+            let delay = backoff(&retry, self.retries);
+            tracing::warn!(
+                url = %self.url,
+                received = self.received,
+                attempt = self.retries,
+                delay_ms = delay.as_millis(),
+                error = %error,
+                "Stream interrupted - resuming"
+            );
+            self.client.emit(|| DownloadEvent::RetryScheduled {
+                attempt: self.retries,
+                delay,
+                cause: error.to_string(),
+            });
+            sleep(delay).await;
+
+            match reopen(&self.client, &self.url, self.received, &validator).await {
+                Ok(body) => {
+                    self.body = body;
+                    return Ok(());
+                },
+                Err(e) => error = e,
+            }
+        }
+    }
+}
+
+/// Request the body of `url` from `received` on, refusing a different version of the object
+async fn reopen(
+    client: &HttpClient,
+    url: &str,
+    received: u64,
+    validator: &str,
+) -> Result<BoxStream<'static, reqwest::Result<Bytes>>> {
+    let mut request = client
+        .client()
+        .get(url)
+        .header(RANGE, format!("bytes={received}-"))
+        .header(IF_RANGE, validator);
+    let remembered_auth = url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .and_then(|host| client.authenticated_credentials(&host));
+    if let Some(auth) = remembered_auth {
+        request = request.basic_auth(&auth.username, Some(&auth.password));
+    }
+
+    let response = client.send(request).await?;
+    match response.status().as_u16() {
+        206 => {
+            check_partial_content(&response, received)?;
+        },
+        // If-Range didn't match, so the server sent the new version in full
+        200 => {
+            return Err(Error::ObjectChangedDuringDownload(format!(
+                "{url} changed after {received} bytes were streamed"
+            )));
+        },
+        status => return Err(Error::InvalidStatus(status)),
+    }
+    Ok(response.bytes_stream().boxed())
+}
+
+/// Stream a parallel download: up to `parallel_chunks` ranges are fetched at once and yielded in order
+pub(crate) fn parallel_chunks(
+    client: &HttpClient,
+    url: &str,
+    total_size: u64,
+    identity: ObjectIdentity,
+    progress_callback: Option<ProgressCallback>,
+) -> ChunkStream {
+    let concurrency = client.config().parallel_chunks.max(1);
+    let ranges = parallel::chunk_ranges(client.config(), total_size);
+    let identity = Arc::new(identity);
+    let chunks = {
+        let client = client.clone();
+        let url = url.to_string();
+        stream::iter(ranges.into_iter().enumerate())
+            .map(move |(index, (start, end))| {
+                let client = client.clone();
+                let url = url.clone();
+                let identity = Arc::clone(&identity);
+                async move {
+                    let chunk =
+                        parallel::download_chunk_with_retry(&client, &url, start, end, &identity)
+                            .await
+                            .map_err(|e| {
+                                e.with_context(parallel::chunk_context("downloading", index, &url))
+                            })?;
+                    client.emit(|| DownloadEvent::ChunkCompleted {
+                        index,
+                        bytes: chunk.len() as u64,
+                    });
+                    Ok(chunk)
+                }
+            })
+            .buffered(concurrency)
+            .boxed()
+    };
+
+    let pacer = Pacer::new(client, url, Some(total_size), progress_callback);
+    stream::unfold(Some((chunks, pacer)), |state| async move {
+        let (mut chunks, mut pacer) = state?;
+        let next = match pacer.deadline.run(chunks.next()).await {
+            Ok(next) => next?,
+            Err(e) => Err(e),
+        };
+        match next {
+            Ok(chunk) => {
+                pacer.pass(&chunk).await;
+                Some((Ok(chunk), Some((chunks, pacer))))
+            },
+            // An error ends the stream
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+    .boxed()
+}
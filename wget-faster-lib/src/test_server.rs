@@ -0,0 +1,598 @@
+/// Small HTTP/1.1 server for integration tests of code built on this crate
+///
+/// Each [`Route`] serves one in-memory resource and can be told to honour
+/// Range requests, answer with a sequence of statuses, drop the connection
+/// part-way through the body, send it slowly or never end it. The server records every
+/// request and the header expectations that did not hold, so a test can
+/// assert on them after the download.
+use bytes::Bytes;
+use futures::stream;
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::{JoinHandle, JoinSet};
+
+type Body = BoxBody<Bytes, std::io::Error>;
+
+/// Bytes per body frame unless [`Route::chunk_size`] says otherwise
+const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Start describing the resource served at `path`
+pub fn route(path: impl Into<String>) -> Route {
+    Route {
+        path: path.into(),
+        body: Bytes::new(),
+        ranges: false,
+        statuses: Vec::new(),
+        head_status: None,
+        headers: Vec::new(),
+        etag: None,
+        drop_after: None,
+        chunk_size: DEFAULT_CHUNK_SIZE,
+        delay_per_chunk: None,
+        endless: false,
+        expected_headers: Vec::new(),
+    }
+}
+
+/// One resource of a [`TestServer`], created by [`route`]
+#[derive(Debug, Clone)]
+pub struct Route {
+    path: String,
+    body: Bytes,
+    ranges: bool,
+    statuses: Vec<u16>,
+    head_status: Option<u16>,
+    headers: Vec<(String, String)>,
+    etag: Option<String>,
+    drop_after: Option<u64>,
+    chunk_size: usize,
+    delay_per_chunk: Option<Duration>,
+    endless: bool,
+    expected_headers: Vec<(String, Option<String>)>,
+}
+
+impl Route {
+    /// Content of the resource
+    #[must_use]
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Advertise `Accept-Ranges: bytes` and answer a single byte range with
+    /// 206 (or 416 when it lies past the end)
+    ///
+    /// Without this, Range headers are ignored and the whole body is sent.
+    #[must_use]
+    pub fn ranges(mut self, enabled: bool) -> Self {
+        self.ranges = enabled;
+        self
+    }
+
+    /// Status of every non-HEAD request
+    #[must_use]
+    pub fn status(self, status: u16) -> Self {
+        self.status_sequence([status])
+    }
+
+    /// Statuses of successive non-HEAD requests; the last one repeats
+    ///
+    /// Non-2xx statuses are sent with an empty body.
+    #[must_use]
+    pub fn status_sequence(mut self, statuses: impl IntoIterator<Item = u16>) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Answer HEAD requests with `status` and no headers of the resource,
+    /// whatever GET returns
+    #[must_use]
+    pub fn head_status(mut self, status: u16) -> Self {
+        self.head_status = Some(status);
+        self
+    }
+
+    /// Extra response header
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Strong `ETag` of the resource, also used to evaluate `If-Range`
+    #[must_use]
+    pub fn etag(mut self, etag: impl Into<String>) -> Self {
+        self.etag = Some(format!("\"{}\"", etag.into().trim_matches('"')));
+        self
+    }
+
+    /// Reset the connection when a response reaches byte `offset` of the
+    /// resource
+    ///
+    /// Only the first response that covers `offset` is cut, so a request that
+    /// resumes from there succeeds.
+    #[must_use]
+    pub fn drop_after(mut self, offset: u64) -> Self {
+        self.drop_after = Some(offset);
+        self
+    }
+
+    /// Bytes per body frame
+    #[must_use]
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Pause before sending each body frame
+    #[must_use]
+    pub fn delay_per_chunk(mut self, delay: Duration) -> Self {
+        self.delay_per_chunk = Some(delay);
+        self
+    }
+
+    /// Send the body over and over without a Content-Length, never ending
+    /// the response (like an event stream or a live log)
+    ///
+    /// Ranges are ignored. Combine with [`delay_per_chunk`](Self::delay_per_chunk)
+    /// to trickle it.
+    #[must_use]
+    pub fn endless(mut self) -> Self {
+        self.endless = true;
+        self
+    }
+
+    /// Record a failure for every request to this route without header
+    /// `name: value`
+    #[must_use]
+    pub fn expect_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.expected_headers
+            .push((name.into(), Some(value.into())));
+        self
+    }
+
+    /// Record a failure for every request to this route that sends header `name`
+    #[must_use]
+    pub fn expect_no_header(mut self, name: impl Into<String>) -> Self {
+        self.expected_headers.push((name.into(), None));
+        self
+    }
+
+    /// Header expectations `headers` does not meet
+    fn check(&self, method: &Method, headers: &HeaderMap) -> Vec<String> {
+        let mut failures = Vec::new();
+        for (name, expected) in &self.expected_headers {
+            let actual = headers
+                .get(name.as_str())
+                .map(|v| String::from_utf8_lossy(v.as_bytes()));
+            match (expected, actual) {
+                (Some(expected), Some(actual)) if actual == expected.as_str() => {},
+                (None, None) => {},
+                (expected, actual) => failures.push(format!(
+                    "{method} {}: expected header {name} {}, got {}",
+                    self.path,
+                    expected
+                        .as_deref()
+                        .map_or_else(|| "absent".to_string(), |v| format!("{v:?}")),
+                    actual.map_or_else(|| "none".to_string(), |v| format!("{v:?}")),
+                )),
+            }
+        }
+        failures
+    }
+}
+
+/// A request the server received
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    /// Request method
+    pub method: Method,
+    /// Path without the query
+    pub path: String,
+    /// Request headers
+    pub headers: HeaderMap,
+}
+
+/// Route together with what it has served so far
+struct RouteState {
+    route: Route,
+    non_head_requests: usize,
+    dropped: bool,
+}
+
+#[derive(Default)]
+struct State {
+    routes: HashMap<String, RouteState>,
+    requests: Vec<RecordedRequest>,
+    failures: Vec<String>,
+}
+
+/// Running test server, stopped by [`shutdown`](Self::shutdown) or on drop
+///
+/// Connections are served concurrently, with keep-alive.
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// use std::time::Duration;
+/// use wget_faster_lib::test_server::{route, TestServer};
+///
+/// let server = TestServer::start([route("/file")
+///     .body(vec![0u8; 4096])
+///     .ranges(true)
+///     .drop_after(1024)
+///     .delay_per_chunk(Duration::from_millis(50))
+///     .status_sequence([503, 503, 200])
+///     .etag("abc")])
+/// .await?;
+/// let url = server.url_for("/file");
+/// // ... download `url` ...
+/// server.assert_no_failures();
+/// server.shutdown().await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TestServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl TestServer {
+    /// Bind to a free port on 127.0.0.1 and serve `routes`
+    ///
+    /// Unknown paths get 404.
+    pub async fn start(routes: impl IntoIterator<Item = Route>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State::default()));
+        lock(&state).routes = routes
+            .into_iter()
+            .map(|route| {
+                let state = RouteState {
+                    route,
+                    non_head_requests: 0,
+                    dropped: false,
+                };
+                (state.route.path.clone(), state)
+            })
+            .collect();
+
+        let shared = Arc::clone(&state);
+        let task = tokio::spawn(async move {
+            // Dropped with this task on shutdown, which aborts open connections
+            let mut connections = JoinSet::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                while connections.try_join_next().is_some() {}
+                let shared = Arc::clone(&shared);
+                let service = hyper::service::service_fn(move |request: Request<Incoming>| {
+                    let response = respond(&shared, &request);
+                    async move { Ok::<_, Infallible>(response) }
+                });
+                connections.spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service),
+                );
+            }
+        });
+
+        Ok(Self { url, state, task })
+    }
+
+    /// Base URL, such as `http://127.0.0.1:40123`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Absolute URL of `path`
+    pub fn url_for(&self, path: &str) -> String {
+        format!("{}{path}", self.url)
+    }
+
+    /// Every request received so far, in arrival order
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        lock(&self.state).requests.clone()
+    }
+
+    /// Number of `method` requests to `path`
+    pub fn hits(&self, method: &Method, path: &str) -> usize {
+        lock(&self.state)
+            .requests
+            .iter()
+            .filter(|r| r.method == *method && r.path == path)
+            .count()
+    }
+
+    /// Header expectations that did not hold, one message per request and header
+    pub fn failures(&self) -> Vec<String> {
+        lock(&self.state).failures.clone()
+    }
+
+    /// Panic with the recorded failures, if any
+    pub fn assert_no_failures(&self) {
+        let failures = self.failures();
+        assert!(failures.is_empty(), "test server expectations failed:\n{}", failures.join("\n"));
+    }
+
+    /// Stop accepting, close every connection and free the port
+    pub async fn shutdown(mut self) {
+        self.task.abort();
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Lock the server state, even if a panicking test poisoned it
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+fn respond(shared: &Mutex<State>, request: &Request<Incoming>) -> Response<Body> {
+    let mut state = lock(shared);
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    state.requests.push(RecordedRequest {
+        method: method.clone(),
+        path: path.clone(),
+        headers: request.headers().clone(),
+    });
+
+    let Some(entry) = state.routes.get_mut(&path) else {
+        return empty(StatusCode::NOT_FOUND);
+    };
+    let failures = entry.route.check(&method, request.headers());
+    let response = serve(entry, &method, request.headers());
+    state.failures.extend(failures);
+    response
+}
+
+/// Response of `entry` to one request
+fn serve(entry: &mut RouteState, method: &Method, headers: &HeaderMap) -> Response<Body> {
+    let route = &entry.route;
+    let is_head = method == Method::HEAD;
+    let status = if is_head {
+        route.head_status.unwrap_or(200)
+    } else {
+        let index = entry
+            .non_head_requests
+            .min(route.statuses.len().saturating_sub(1));
+        entry.non_head_requests += 1;
+        route.statuses.get(index).copied().unwrap_or(200)
+    };
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    if !status.is_success() || (is_head && route.head_status.is_some()) {
+        return empty(status);
+    }
+
+    let total = route.body.len() as u64;
+    let mut response = Response::builder().status(status);
+    for (name, value) in &route.headers {
+        response = response.header(name, value);
+    }
+    if let Some(ref etag) = route.etag {
+        response = response.header(header::ETAG, etag);
+    }
+    if route.endless {
+        let body = if is_head {
+            Empty::new().map_err(|e| match e {}).boxed()
+        } else {
+            endless_stream(route.body.clone(), route.delay_per_chunk)
+        };
+        return response
+            .body(body)
+            .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    let range = if route.ranges {
+        requested_range(headers, route.etag.as_deref(), total)
+    } else {
+        RequestedRange::Whole
+    };
+    let (start, end) = match range {
+        RequestedRange::Range(start, end) => {
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{total}"));
+            (start, end + 1)
+        },
+        RequestedRange::Unsatisfiable => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{total}"))
+                .body(Empty::new().map_err(|e| match e {}).boxed())
+                .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR));
+        },
+        RequestedRange::Whole => (0, total),
+    };
+    if route.ranges {
+        response = response.header(header::ACCEPT_RANGES, "bytes");
+    }
+    response = response.header(header::CONTENT_LENGTH, end - start);
+
+    let body = if is_head {
+        Empty::new().map_err(|e| match e {}).boxed()
+    } else {
+        let cut = route
+            .drop_after
+            .filter(|&offset| !entry.dropped && start <= offset && offset < end);
+        entry.dropped |= cut.is_some();
+        body_stream(
+            route
+                .body
+                .slice(start as usize..cut.unwrap_or(end) as usize),
+            cut.is_some(),
+            route.chunk_size,
+            route.delay_per_chunk,
+        )
+    };
+    response
+        .body(body)
+        .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// Part of the body a request asked for
+#[derive(Debug, PartialEq, Eq)]
+enum RequestedRange {
+    /// No (usable) range: the whole body
+    Whole,
+    /// Bytes `start..=end`
+    Range(u64, u64),
+    /// A range outside the body
+    Unsatisfiable,
+}
+
+/// Range to serve for the request
+///
+/// Only a single `bytes=` range is honoured, and only while `If-Range` (if
+/// sent) matches `etag`.
+fn requested_range(headers: &HeaderMap, etag: Option<&str>, total: u64) -> RequestedRange {
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        if etag.is_none_or(|etag| if_range != etag) {
+            return RequestedRange::Whole;
+        }
+    }
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("bytes="))
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| parse_range(spec, total));
+    range.unwrap_or(RequestedRange::Whole)
+}
+
+/// A single `first-last` range spec, `None` if malformed
+fn parse_range(spec: &str, total: u64) -> Option<RequestedRange> {
+    let (first, last) = spec.split_once('-')?;
+    let range = if first.is_empty() {
+        let suffix: u64 = last.parse().ok()?;
+        (suffix > 0 && total > 0).then(|| (total.saturating_sub(suffix), total - 1))
+    } else {
+        let start: u64 = first.parse().ok()?;
+        let end = if last.is_empty() {
+            u64::MAX
+        } else {
+            last.parse().ok()?
+        };
+        (start < total && start <= end).then(|| (start, end.min(total - 1)))
+    };
+    Some(
+        range.map_or(RequestedRange::Unsatisfiable, |(start, end)| {
+            RequestedRange::Range(start, end)
+        }),
+    )
+}
+
+/// Body sent `chunk_size` bytes at a time, ending in a connection reset when
+/// `reset` is set
+fn body_stream(data: Bytes, reset: bool, chunk_size: usize, delay: Option<Duration>) -> Body {
+    let frames = stream::unfold(Some(data), move |remaining| async move {
+        let mut remaining = remaining?;
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+        if remaining.is_empty() {
+            if !reset {
+                return None;
+            }
+            // Pending once so hyper flushes what was sent before the reset
+            tokio::task::yield_now().await;
+            let error = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+            return Some((Err(error), None));
+        }
+        let frame = remaining.split_to(chunk_size.min(remaining.len()));
+        Some((Ok(Frame::data(frame)), Some(remaining)))
+    });
+    StreamBody::new(frames).boxed()
+}
+
+/// `data` sent again and again, after `delay` each time
+fn endless_stream(data: Bytes, delay: Option<Duration>) -> Body {
+    let frames = stream::unfold(data, move |data| async move {
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+        Some((Ok(Frame::data(data.clone())), data))
+    });
+    StreamBody::new(frames).boxed()
+}
+
+fn empty(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Empty::new().map_err(|e| match e {}).boxed());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(header::CONTENT_LENGTH, HeaderValue::from_static("0"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range_headers(range: &str, if_range: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, range.parse().unwrap());
+        if let Some(if_range) = if_range {
+            headers.insert(header::IF_RANGE, if_range.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_requested_range() {
+        let range = |spec| requested_range(&range_headers(spec, None), None, 100);
+        assert_eq!(range("bytes=0-9"), RequestedRange::Range(0, 9));
+        assert_eq!(range("bytes=90-"), RequestedRange::Range(90, 99));
+        assert_eq!(range("bytes=95-200"), RequestedRange::Range(95, 99));
+        assert_eq!(range("bytes=-10"), RequestedRange::Range(90, 99));
+        assert_eq!(range("bytes=100-"), RequestedRange::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,5-6"), RequestedRange::Whole);
+        assert_eq!(range("items=0-1"), RequestedRange::Whole);
+    }
+
+    #[tokio::test]
+    async fn test_failed_expectations_recorded() {
+        let server = TestServer::start([route("/a").body("x").expect_header("x-token", "t")])
+            .await
+            .unwrap();
+        let client = reqwest::Client::new();
+        client
+            .get(server.url_for("/a"))
+            .header("x-token", "t")
+            .send()
+            .await
+            .unwrap();
+        client.get(server.url_for("/a")).send().await.unwrap();
+        let missing = client.get(server.url_for("/b")).send().await.unwrap();
+
+        assert_eq!(missing.status(), 404);
+        assert_eq!(server.hits(&Method::GET, "/a"), 2);
+        assert_eq!(server.failures(), ["GET /a: expected header x-token \"t\", got none"]);
+
+        let url = server.url_for("/a");
+        server.shutdown().await;
+        assert!(client.get(url).send().await.is_err());
+    }
+
+    #[test]
+    fn test_if_range_mismatch_serves_whole_body() {
+        let headers = range_headers("bytes=10-", Some("\"old\""));
+        assert_eq!(requested_range(&headers, Some("\"new\""), 100), RequestedRange::Whole);
+        let headers = range_headers("bytes=10-", Some("\"new\""));
+        assert_eq!(requested_range(&headers, Some("\"new\""), 100), RequestedRange::Range(10, 99));
+    }
+}
@@ -5,8 +5,112 @@
 /// - Skip download if local file is newer or same
 /// - Re-download if remote file is newer
 /// - Handle edge cases (missing timestamps, size mismatches)
-use crate::{client::ResourceMetadata, output::DownloadedData, Result};
-use std::path::Path;
+/// - Optionally revalidate by `ETag`, kept in a sidecar file (`use_etag`)
+use crate::{client::ResourceMetadata, output::DownloadedData, DownloadConfig, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// How file sizes are compared when local and remote timestamps are equal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeCheck {
+    /// Compare the local file size against the downloaded file size on disk
+    #[default]
+    Enabled,
+
+    /// Ignore sizes; equal timestamps always keep the local file
+    Disabled,
+
+    /// Compare only against a server-sent Content-Length (keep the file if absent)
+    ContentLengthOnly,
+}
+
+/// Post-download timestamping decision: whether the local file was replaced and why
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampDecision {
+    /// Server answered 304 Not Modified - local file kept
+    NotModified,
+
+    /// Remote file is newer - local file replaced
+    RemoteNewer,
+
+    /// Local file is newer - local file kept
+    LocalNewer,
+
+    /// Same timestamp but different size - local file replaced
+    SizeChanged {
+        /// Size of the previous local file
+        local_size: u64,
+        /// Size the new file was compared by
+        remote_size: u64,
+    },
+
+    /// Same timestamp and size (or size check disabled) - local file kept
+    Unchanged,
+
+    /// Server sent no usable Last-Modified - local file replaced
+    NoRemoteTimestamp,
+
+    /// Server's `ETag` differs from the one saved with the file (`use_etag`) -
+    /// local file replaced
+    EtagChanged,
+
+    /// Remote file isn't newer and the local file is shorter - local file
+    /// continued (`timestamping_continue`)
+    Resumed {
+        /// Size of the local file the download continued from
+        from: u64,
+    },
+
+    /// Remote file isn't newer and the local file is at least as long - local
+    /// file kept without a transfer (`timestamping_continue`)
+    AlreadyComplete,
+}
+
+impl TimestampDecision {
+    /// Whether the local file was replaced by the new download
+    pub fn replaced(&self) -> bool {
+        matches!(
+            self,
+            TimestampDecision::RemoteNewer
+                | TimestampDecision::SizeChanged { .. }
+                | TimestampDecision::NoRemoteTimestamp
+                | TimestampDecision::EtagChanged
+        )
+    }
+}
+
+/// What `-N -c` does with an existing local file, decided by a probe before the transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PartialFileAction {
+    /// Continue the local file from its current size
+    Resume(u64),
+    /// Download the whole file again
+    Restart,
+    /// Keep the local file as it is
+    Skip,
+}
+
+impl PartialFileAction {
+    /// wget's precedence of `-N` over `-c`
+    ///
+    /// A remote file that is newer, or whose time or size is unknown, is
+    /// downloaded again from the start. Otherwise a shorter local file is
+    /// continued and a complete one kept.
+    pub(crate) fn decide(local: &LocalFileInfo, remote: &RemoteInfo) -> Self {
+        match (remote.last_modified, remote.content_length) {
+            (Some(remote_time), Some(remote_size)) if remote_time <= local.mtime => {
+                if local.size < remote_size {
+                    Self::Resume(local.size)
+                } else {
+                    Self::Skip
+                }
+            },
+            _ => Self::Restart,
+        }
+    }
+}
 
 /// Result of timestamp comparison
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -19,12 +123,134 @@ pub enum TimestampAction {
     DeleteAndDownload,
 }
 
+/// The local copy of a file, as far as timestamping is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalFileInfo {
+    /// Modification time
+    pub mtime: SystemTime,
+
+    /// Size in bytes
+    pub size: u64,
+}
+
+impl LocalFileInfo {
+    /// Modification time and size from file system metadata
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the platform doesn't report modification times
+    pub fn from_metadata(metadata: &std::fs::Metadata) -> std::io::Result<Self> {
+        Ok(Self {
+            mtime: metadata.modified()?,
+            size: metadata.len(),
+        })
+    }
+}
+
+/// What is known about the remote file, from a response or an earlier crawl
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteInfo {
+    /// Parsed `Last-Modified` (`None` if absent or unparsable)
+    pub last_modified: Option<SystemTime>,
+
+    /// Size to compare the local file with
+    pub content_length: Option<u64>,
+
+    /// `ETag`, carried along for callers; wget's rules don't look at it
+    pub etag: Option<String>,
+}
+
+impl RemoteInfo {
+    /// Remote information from response metadata
+    pub fn from_metadata(metadata: &ResourceMetadata) -> Self {
+        let last_modified = metadata.last_modified.as_deref().and_then(|lm| {
+            let parsed = httpdate::parse_http_date(lm).ok();
+            if parsed.is_none() {
+                tracing::warn!(last_modified = %lm, "Failed to parse Last-Modified header");
+            }
+            parsed
+        });
+        Self {
+            last_modified,
+            content_length: metadata.content_length,
+            etag: metadata.etag.clone(),
+        }
+    }
+}
+
+/// Flags changing how timestamping compares files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimestampPolicy {
+    /// How sizes are compared when the timestamps are equal
+    pub size_check: SizeCheck,
+}
+
+impl TimestampPolicy {
+    /// The policy configured in `config` (`timestamping_size_check`)
+    pub fn from_config(config: &DownloadConfig) -> Self {
+        Self {
+            size_check: config.timestamping_size_check,
+        }
+    }
+}
+
+/// Decide what wget's timestamping (-N) does with a URL, without any I/O
+///
+/// A missing local file is downloaded. Otherwise the file is re-downloaded if
+/// [`compare`] says it would be replaced, and skipped if not.
+pub fn decide(
+    local: Option<LocalFileInfo>,
+    remote: &RemoteInfo,
+    policy: &TimestampPolicy,
+) -> TimestampAction {
+    match local {
+        None => TimestampAction::Download,
+        Some(local) if compare(&local, remote, policy).replaced() => {
+            TimestampAction::DeleteAndDownload
+        },
+        Some(_) => TimestampAction::Skip,
+    }
+}
+
+/// Compare a local file with the remote one, with the reason for the outcome
+///
+/// The remote file replaces the local one if it has no usable `Last-Modified`
+/// or is newer. With equal timestamps it does only if the sizes differ, unless
+/// `policy.size_check` is `Disabled`; an unknown remote size keeps the local file.
+pub fn compare(
+    local: &LocalFileInfo,
+    remote: &RemoteInfo,
+    policy: &TimestampPolicy,
+) -> TimestampDecision {
+    let Some(remote_time) = remote.last_modified else {
+        return TimestampDecision::NoRemoteTimestamp;
+    };
+
+    match local.mtime.cmp(&remote_time) {
+        std::cmp::Ordering::Less => TimestampDecision::RemoteNewer,
+        std::cmp::Ordering::Greater => TimestampDecision::LocalNewer,
+        std::cmp::Ordering::Equal => {
+            let remote_size = remote
+                .content_length
+                .filter(|_| policy.size_check != SizeCheck::Disabled);
+            match remote_size {
+                Some(remote_size) if remote_size != local.size => TimestampDecision::SizeChanged {
+                    local_size: local.size,
+                    remote_size,
+                },
+                _ => TimestampDecision::Unchanged,
+            }
+        },
+    }
+}
+
 /// Check if we should download based on timestamping rules
 ///
 /// # Arguments
 ///
 /// * `path` - Local file path
 /// * `metadata` - Remote resource metadata
+/// * `size_check` - How sizes are compared when timestamps are equal
 ///
 /// # Returns
 ///
@@ -32,73 +258,122 @@ pub enum TimestampAction {
 pub async fn check_timestamp(
     path: &Path,
     metadata: &ResourceMetadata,
+    size_check: SizeCheck,
 ) -> Result<(TimestampAction, Option<DownloadedData>)> {
-    // If file doesn't exist, download
-    if !path.exists() {
-        tracing::debug!("File doesn't exist - will download");
-        return Ok((TimestampAction::Download, None));
-    }
-
-    // Get local file metadata
-    let local_metadata = tokio::fs::metadata(path).await?;
-    let local_size = local_metadata.len();
-    let local_time = local_metadata.modified()?;
-
-    // If no remote Last-Modified header, download (server doesn't provide timestamp info)
-    // This matches wget behavior
-    let Some(ref remote_modified) = metadata.last_modified else {
-        tracing::info!("No Last-Modified header from server - will download");
-        return Ok((TimestampAction::DeleteAndDownload, None));
-    };
-
-    // Parse remote Last-Modified header
-    let Ok(remote_time) = httpdate::parse_http_date(remote_modified) else {
-        tracing::warn!(last_modified = %remote_modified, "Failed to parse Last-Modified header");
-        return Ok((TimestampAction::DeleteAndDownload, None));
+    let local = if path.exists() {
+        Some(LocalFileInfo::from_metadata(&tokio::fs::metadata(path).await?)?)
+    } else {
+        None
     };
+    let remote = RemoteInfo::from_metadata(metadata);
+    let action = decide(local, &remote, &TimestampPolicy { size_check });
 
     tracing::debug!(
-        local_time = ?local_time,
-        remote_time = ?remote_time,
-        local_size,
-        remote_size = ?metadata.content_length,
-        "Comparing timestamps"
+        local = ?local,
+        remote_time = ?remote.last_modified,
+        remote_size = ?remote.content_length,
+        action = ?action,
+        "Compared timestamps"
     );
-
-    // Compare timestamps
-    match local_time.cmp(&remote_time) {
-        std::cmp::Ordering::Less => {
-            // Local file is older, delete and re-download
-            tracing::info!("Local file is older than remote - will re-download");
-            Ok((TimestampAction::DeleteAndDownload, None))
-        },
-        std::cmp::Ordering::Greater => {
-            // Local file is newer, skip download
-            tracing::info!("Local file is newer than remote - skipping download");
-            let result = DownloadedData::new_file(path.to_path_buf(), local_size, false);
-            Ok((TimestampAction::Skip, Some(result)))
+    let data = match (action, local) {
+        (TimestampAction::Skip, Some(local)) => {
+            Some(DownloadedData::new_file(path.to_path_buf(), local.size, false))
         },
-        std::cmp::Ordering::Equal => {
-            // Same timestamp - check file size
-            tracing::debug!("Same timestamp - checking file size");
-
-            if let Some(remote_size) = metadata.content_length {
-                if local_size == remote_size {
-                    // Same timestamp and size, skip download
-                    tracing::info!("Same timestamp and size - skipping download");
-                    let result = DownloadedData::new_file(path.to_path_buf(), local_size, false);
-                    return Ok((TimestampAction::Skip, Some(result)));
-                }
-                // Same timestamp but different size - delete and re-download
-                tracing::info!("Same timestamp but different size - will re-download");
-                Ok((TimestampAction::DeleteAndDownload, None))
+        _ => None,
+    };
+    Ok((action, data))
+}
+
+/// Decide whether a freshly downloaded file replaces the local one
+///
+/// `new_size` is the on-disk size of the downloaded (temporary) file, so it is
+/// compared like-for-like with the local size regardless of transfer encoding;
+/// only `SizeCheck::ContentLengthOnly` compares the response's Content-Length.
+pub(crate) fn decide_replacement(
+    local: &LocalFileInfo,
+    metadata: &ResourceMetadata,
+    new_size: u64,
+    policy: &TimestampPolicy,
+) -> TimestampDecision {
+    let mut remote = RemoteInfo::from_metadata(metadata);
+    if policy.size_check == SizeCheck::Enabled {
+        remote.content_length = Some(new_size);
+    }
+    compare(local, &remote, policy)
+}
+
+/// Validators of the local copy, sent on the conditional GET of a timestamping download
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Validators {
+    /// Sent as `If-Modified-Since`
+    pub(crate) modified_since: Option<SystemTime>,
+
+    /// Sent as `If-None-Match`
+    pub(crate) etag: Option<String>,
+}
+
+impl Validators {
+    /// Validators of the file at `path`, if it exists
+    ///
+    /// The `ETag` is read from the sidecar, and only with `use_etag`.
+    pub(crate) async fn of_file(path: &Path, config: &DownloadConfig) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(Self {
+            modified_since: Some(tokio::fs::metadata(path).await?.modified()?),
+            etag: if config.use_etag {
+                read_etag(path).await
             } else {
-                // No remote size info, skip download (same timestamp)
-                tracing::info!("Same timestamp, no remote size - skipping download");
-                let result = DownloadedData::new_file(path.to_path_buf(), local_size, false);
-                Ok((TimestampAction::Skip, Some(result)))
-            }
+                None
+            },
+        })
+    }
+
+    /// Add the conditional headers to `request`
+    pub(crate) fn apply(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(time) = self.modified_since {
+            let http_date = httpdate::fmt_http_date(time);
+            tracing::debug!(if_modified_since = %http_date, "Adding If-Modified-Since header");
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, http_date);
+        }
+        if let Some(ref etag) = self.etag {
+            tracing::debug!(if_none_match = %etag, "Adding If-None-Match header");
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        request
+    }
+}
+
+/// Sidecar file holding the `ETag` the file at `path` was downloaded with
+pub(crate) fn etag_sidecar(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".etag");
+    PathBuf::from(sidecar)
+}
+
+/// `ETag` saved with the file at `path`, if any
+pub(crate) async fn read_etag(path: &Path) -> Option<String> {
+    let etag = tokio::fs::read_to_string(etag_sidecar(path)).await.ok()?;
+    let etag = etag.trim();
+    (!etag.is_empty()).then(|| etag.to_string())
+}
+
+/// Save `etag` with the file at `path`, or remove a stale sidecar when there is none
+///
+/// Failures are logged: without the sidecar, the next run falls back to
+/// `If-Modified-Since`.
+pub(crate) async fn write_etag(path: &Path, etag: Option<&str>) {
+    let sidecar = etag_sidecar(path);
+    let result = match etag {
+        Some(etag) => tokio::fs::write(&sidecar, format!("{etag}\n")).await,
+        None => match tokio::fs::remove_file(&sidecar).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
         },
+    };
+    if let Err(e) = result {
+        tracing::warn!(path = %sidecar.display(), error = %e, "Failed to update ETag sidecar");
     }
 }
 
@@ -158,13 +433,22 @@ mod tests {
             last_modified: Some("Mon, 01 Jan 2024 00:00:00 GMT".to_string()),
             etag: None,
             content_type: None,
+            content_encoding: None,
             content_disposition: None,
             status_code: 200,
             headers: reqwest::header::HeaderMap::new(),
             auth_succeeded: false,
+            final_url: None,
+            allow: None,
+            accept_ranges_unit: None,
+            cache_control: None,
+            age: None,
+            content_language: None,
+            links: Vec::new(),
+            tls_info: None,
         };
 
-        let (action, _) = check_timestamp(path, &metadata)
+        let (action, _) = check_timestamp(path, &metadata, SizeCheck::Enabled)
             .await
             .expect("Failed to check timestamp");
         assert_eq!(action, TimestampAction::Download);
@@ -177,4 +461,239 @@ mod tests {
         assert_eq!(TimestampAction::DeleteAndDownload, TimestampAction::DeleteAndDownload);
         assert_ne!(TimestampAction::Download, TimestampAction::Skip);
     }
+
+    fn metadata(last_modified: Option<&str>, content_length: Option<u64>) -> ResourceMetadata {
+        ResourceMetadata {
+            supports_range: false,
+            content_length,
+            last_modified: last_modified.map(str::to_string),
+            etag: None,
+            content_type: None,
+            content_encoding: None,
+            content_disposition: None,
+            status_code: 200,
+            headers: reqwest::header::HeaderMap::new(),
+            auth_succeeded: false,
+            final_url: None,
+            allow: None,
+            accept_ranges_unit: None,
+            cache_control: None,
+            age: None,
+            content_language: None,
+            links: Vec::new(),
+            tls_info: None,
+        }
+    }
+
+    #[test]
+    fn test_decide_replacement() {
+        let date = "Mon, 01 Jan 2024 00:00:00 GMT";
+        let time = httpdate::parse_http_date(date).unwrap();
+        let older = time - std::time::Duration::from_secs(60);
+        let newer = time + std::time::Duration::from_secs(60);
+        let decide_with = |mtime, last_modified, new_size, content_length, size_check| {
+            let local = LocalFileInfo { mtime, size: 100 };
+            let policy = TimestampPolicy { size_check };
+            decide_replacement(&local, &metadata(last_modified, content_length), new_size, &policy)
+        };
+        let decide = |mtime, size_check| decide_with(mtime, Some(date), 100, Some(40), size_check);
+
+        assert_eq!(decide(older, SizeCheck::Enabled), TimestampDecision::RemoteNewer);
+        assert_eq!(decide(newer, SizeCheck::Enabled), TimestampDecision::LocalNewer);
+
+        // Same timestamp, identical content on disk (Content-Length of the compressed
+        // transfer differs, which must not matter by default)
+        assert_eq!(decide(time, SizeCheck::Enabled), TimestampDecision::Unchanged);
+        assert_eq!(decide(time, SizeCheck::Disabled), TimestampDecision::Unchanged);
+        assert_eq!(
+            decide(time, SizeCheck::ContentLengthOnly),
+            TimestampDecision::SizeChanged {
+                local_size: 100,
+                remote_size: 40
+            }
+        );
+
+        // Same timestamp, content truly changed
+        let changed = decide_with(time, Some(date), 120, None, SizeCheck::Enabled);
+        assert!(changed.replaced());
+        let unknown = decide_with(time, Some(date), 120, None, SizeCheck::ContentLengthOnly);
+        assert_eq!(unknown, TimestampDecision::Unchanged);
+
+        let missing = decide_with(time, None, 100, None, SizeCheck::Enabled);
+        assert_eq!(missing, TimestampDecision::NoRemoteTimestamp);
+        assert!(missing.replaced());
+        let unparsable = decide_with(time, Some("yesterday"), 100, None, SizeCheck::Enabled);
+        assert_eq!(unparsable, TimestampDecision::NoRemoteTimestamp);
+        assert!(!TimestampDecision::NotModified.replaced());
+    }
+
+    const POLICIES: [TimestampPolicy; 3] = [
+        TimestampPolicy {
+            size_check: SizeCheck::Enabled,
+        },
+        TimestampPolicy {
+            size_check: SizeCheck::Disabled,
+        },
+        TimestampPolicy {
+            size_check: SizeCheck::ContentLengthOnly,
+        },
+    ];
+
+    /// Local files and remote infos over a grid of times and sizes
+    fn grid() -> (Vec<LocalFileInfo>, Vec<RemoteInfo>) {
+        let base = httpdate::parse_http_date("Mon, 01 Jan 2024 00:00:00 GMT").unwrap();
+        let times: Vec<SystemTime> = [0, 1, 3600]
+            .into_iter()
+            .map(|secs| base + std::time::Duration::from_secs(secs))
+            .collect();
+        let sizes = [0, 100, 101];
+        let locals = times
+            .iter()
+            .flat_map(|&mtime| sizes.map(|size| LocalFileInfo { mtime, size }))
+            .collect();
+        let mut remotes = Vec::new();
+        for last_modified in times.iter().copied().map(Some).chain([None]) {
+            for content_length in sizes.map(Some).into_iter().chain([None]) {
+                for etag in [None, Some("\"v1\"".to_string())] {
+                    remotes.push(RemoteInfo {
+                        last_modified,
+                        content_length,
+                        etag,
+                    });
+                }
+            }
+        }
+        (locals, remotes)
+    }
+
+    #[test]
+    fn test_decide_properties() {
+        let (locals, remotes) = grid();
+        for policy in &POLICIES {
+            for remote in &remotes {
+                // A missing local file is always downloaded
+                assert_eq!(decide(None, remote, policy), TimestampAction::Download);
+
+                for local in &locals {
+                    let action = decide(Some(*local), remote, policy);
+                    let decision = compare(local, remote, policy);
+                    assert_ne!(action, TimestampAction::Download);
+                    assert_eq!(action == TimestampAction::Skip, !decision.replaced());
+                    // Deterministic, and the ETag never matters
+                    assert_eq!(decide(Some(*local), remote, policy), action);
+                    let without_etag = RemoteInfo {
+                        etag: None,
+                        ..remote.clone()
+                    };
+                    assert_eq!(decide(Some(*local), &without_etag, policy), action);
+
+                    let Some(remote_time) = remote.last_modified else {
+                        assert_eq!(action, TimestampAction::DeleteAndDownload);
+                        continue;
+                    };
+                    // Swapping which side is newer swaps the outcome
+                    if local.mtime != remote_time {
+                        let swapped_local = LocalFileInfo {
+                            mtime: remote_time,
+                            size: local.size,
+                        };
+                        let swapped_remote = RemoteInfo {
+                            last_modified: Some(local.mtime),
+                            ..remote.clone()
+                        };
+                        let swapped = decide(Some(swapped_local), &swapped_remote, policy);
+                        assert_ne!(swapped, action, "{local:?} {remote:?} {policy:?}");
+                    }
+                    // The remote copy of the local file is never downloaded again
+                    let same = RemoteInfo {
+                        last_modified: Some(local.mtime),
+                        content_length: Some(local.size),
+                        etag: remote.etag.clone(),
+                    };
+                    assert_eq!(decide(Some(*local), &same, policy), TimestampAction::Skip);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decide_wget_scenarios() {
+        use TimestampAction::{DeleteAndDownload, Download, Skip};
+
+        let time = httpdate::parse_http_date("Mon, 01 Jan 2024 00:00:00 GMT").unwrap();
+        let hour = std::time::Duration::from_secs(3600);
+        let local = |mtime, size| Some(LocalFileInfo { mtime, size });
+        let remote = |last_modified, content_length| RemoteInfo {
+            last_modified,
+            content_length,
+            etag: None,
+        };
+        let enabled = TimestampPolicy::default();
+        let disabled = POLICIES[1];
+
+        let scenarios = [
+            // (description, local, remote, policy, expected)
+            ("no local file", None, remote(Some(time), Some(10)), enabled, Download),
+            ("no local file, no Last-Modified", None, remote(None, None), enabled, Download),
+            (
+                "remote newer",
+                local(time - hour, 10),
+                remote(Some(time), Some(10)),
+                enabled,
+                DeleteAndDownload,
+            ),
+            (
+                "local newer",
+                local(time + hour, 10),
+                remote(Some(time), Some(10)),
+                enabled,
+                Skip,
+            ),
+            (
+                "local newer, size differs",
+                local(time + hour, 10),
+                remote(Some(time), Some(99)),
+                enabled,
+                Skip,
+            ),
+            (
+                "same time and size",
+                local(time, 10),
+                remote(Some(time), Some(10)),
+                enabled,
+                Skip,
+            ),
+            (
+                "same time, size differs",
+                local(time, 10),
+                remote(Some(time), Some(99)),
+                enabled,
+                DeleteAndDownload,
+            ),
+            (
+                "same time, size differs, size check disabled",
+                local(time, 10),
+                remote(Some(time), Some(99)),
+                disabled,
+                Skip,
+            ),
+            (
+                "same time, no Content-Length",
+                local(time, 10),
+                remote(Some(time), None),
+                enabled,
+                Skip,
+            ),
+            (
+                "no Last-Modified",
+                local(time, 10),
+                remote(None, Some(10)),
+                enabled,
+                DeleteAndDownload,
+            ),
+        ];
+        for (description, local, remote, policy, expected) in scenarios {
+            assert_eq!(decide(local, &remote, &policy), expected, "{description}");
+        }
+    }
 }
@@ -0,0 +1,707 @@
+/// Optional TLS policy and the TLS details of responses
+///
+/// With `DownloadConfig::tls_policy` set, server certificates are first
+/// verified as usual (chain to a trusted root, host name, validity) and then
+/// checked against the [`TlsPolicy`]: key sizes, SHA-1 signatures and OCSP
+/// stapling. A violation fails the handshake, and the request with
+/// [`Error::TlsError`] naming the certificate and the rule. Responses carry
+/// the protocol version and a summary of the certificate chain in
+/// `ResourceMetadata::tls_info`.
+///
+/// reqwest doesn't expose the negotiated cipher suite, so [`TlsInfo`] doesn't
+/// report it.
+use crate::{DownloadConfig, Error, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Requirements on the TLS of the servers downloaded from
+///
+/// # Examples
+///
+/// ```
+/// use wget_faster_lib::{DownloadConfig, TlsPolicy};
+///
+/// let config = DownloadConfig {
+///     tls_policy: Some(TlsPolicy {
+///         require_ocsp_stapling: true,
+///         ..Default::default()
+///     }),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TlsPolicy {
+    /// Smallest RSA or DSA key accepted in the server's certificates, in bits
+    pub min_rsa_bits: u32,
+
+    /// Smallest elliptic curve key accepted in the server's certificates, in bits
+    pub min_ec_bits: u32,
+
+    /// Refuse certificates signed with SHA-1
+    ///
+    /// Self-signed certificates are exempt: the signature of a trust anchor on
+    /// itself isn't relied on.
+    pub reject_sha1: bool,
+
+    /// Warn about certificates expiring within this long (`None` never warns)
+    #[serde(serialize_with = "crate::config_serde::option_duration")]
+    pub expiry_warning: Option<Duration>,
+
+    /// Refuse servers that don't staple an OCSP response to their certificate
+    ///
+    /// Only the presence of the response is checked, not its contents.
+    pub require_ocsp_stapling: bool,
+}
+
+impl Default for TlsPolicy {
+    fn default() -> Self {
+        Self {
+            min_rsa_bits: 2048,
+            min_ec_bits: 256,
+            reject_sha1: true,
+            expiry_warning: Some(Duration::from_hours(30 * 24)),
+            require_ocsp_stapling: false,
+        }
+    }
+}
+
+/// TLS details of the connection a response came over
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsInfo {
+    /// Protocol version, e.g. `TLSv1.3` (`None` if unknown)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Certificates the server presented, its own first
+    pub chain: Vec<CertificateSummary>,
+
+    /// Whether the server stapled an OCSP response
+    pub ocsp_stapled: bool,
+
+    /// Policy warnings, e.g. a certificate about to expire
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl TlsInfo {
+    /// Format for display (wget --server-response style, one item per line)
+    pub fn format_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "TLS: {}, OCSP response {}",
+            self.version.as_deref().unwrap_or("unknown version"),
+            if self.ocsp_stapled {
+                "stapled"
+            } else {
+                "not stapled"
+            }
+        )];
+        for (depth, certificate) in self.chain.iter().enumerate() {
+            lines.push(format!(
+                "Certificate {depth}: {} ({} {} bits, {}), issued by {}, expires {}",
+                certificate.subject,
+                certificate.key_type,
+                certificate.key_bits,
+                certificate.signature_algorithm,
+                certificate.issuer,
+                certificate.not_after
+            ));
+        }
+        lines.extend(self.warnings.iter().map(|w| format!("Warning: {w}")));
+        lines
+    }
+}
+
+/// Summary of one certificate of a server's chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateSummary {
+    /// Subject name, e.g. `CN=example.com, O=Example`
+    pub subject: String,
+
+    /// Issuer name
+    pub issuer: String,
+
+    /// End of the validity period (RFC 3339, UTC)
+    pub not_after: String,
+
+    /// Public key algorithm: `RSA`, `DSA`, `EC P-256`, `Ed25519`, ... (the OID if unknown)
+    pub key_type: String,
+
+    /// Size of the public key in bits (0 if unknown)
+    pub key_bits: u32,
+
+    /// Algorithm the issuer signed the certificate with, e.g. `sha256WithRSAEncryption`
+    pub signature_algorithm: String,
+}
+
+/// What the policy verifier learned, shared with the client
+#[derive(Debug, Default)]
+pub(crate) struct TlsState {
+    /// TLS details of each server, by the DER of its certificate
+    servers: Mutex<HashMap<Vec<u8>, TlsInfo>>,
+
+    /// Why the last handshake with a server name was refused
+    violations: Mutex<HashMap<String, String>>,
+}
+
+impl TlsState {
+    /// TLS details of the server that presented `certificate`
+    pub(crate) fn info(&self, certificate: &[u8]) -> Option<TlsInfo> {
+        lock(&self.servers).get(certificate).cloned()
+    }
+
+    /// Why the policy refused `host`, if it did since the last call
+    pub(crate) fn take_violation(&self, host: &str) -> Option<String> {
+        lock(&self.violations).remove(host)
+    }
+
+    fn set_version(&self, certificate: &[u8], version: &str) {
+        if let Some(info) = lock(&self.servers).get_mut(certificate) {
+            info.version = Some(version.to_string());
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// rustls configuration for `config` that enforces `policy` on every server
+///
+/// Trusts the same roots as reqwest (webpki-roots) plus `ca_cert`, and
+/// presents `client_cert` if set.
+pub(crate) fn client_config(
+    config: &DownloadConfig,
+    policy: &TlsPolicy,
+    state: Arc<TlsState>,
+) -> Result<rustls::ClientConfig> {
+    if !config.verify_ssl {
+        return Err(Error::ConfigError(
+            "tls_policy requires certificate verification (verify_ssl)".to_string(),
+        ));
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let mut roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    if let Some(ca_cert_path) = &config.ca_cert {
+        let pem = std::fs::read(ca_cert_path)?;
+        for cert in CertificateDer::pem_slice_iter(&pem) {
+            let invalid = |e: &dyn std::fmt::Display| {
+                Error::ConfigError(format!("Invalid CA certificate: {e}"))
+            };
+            roots
+                .add(cert.map_err(|e| invalid(&e))?)
+                .map_err(|e| invalid(&e))?;
+        }
+    }
+    let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .map_err(|e| Error::ConfigError(format!("Invalid CA certificate: {e}")))?;
+
+    let builder = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::ConfigError(format!("Failed to build TLS configuration: {e}")))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PolicyVerifier {
+            inner,
+            policy: policy.clone(),
+            state,
+        }));
+
+    let mut tls = match &config.client_cert {
+        Some(client_cert_path) => {
+            let pem = std::fs::read(client_cert_path)?;
+            let invalid = |e: &dyn std::fmt::Display| {
+                Error::ConfigError(format!("Invalid client certificate: {e}"))
+            };
+            let certs = CertificateDer::pem_slice_iter(&pem)
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| invalid(&e))?;
+            let key = PrivateKeyDer::from_pem_slice(&pem).map_err(|e| invalid(&e))?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(|e| invalid(&e))?
+        },
+        None => builder.with_no_client_auth(),
+    };
+    tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(tls)
+}
+
+/// Standard certificate verification followed by the [`TlsPolicy`]
+#[derive(Debug)]
+struct PolicyVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    policy: TlsPolicy,
+    state: Arc<TlsState>,
+}
+
+impl PolicyVerifier {
+    /// Check the certificates a server presented against the policy
+    fn inspect(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        ocsp_stapled: bool,
+    ) -> std::result::Result<TlsInfo, String> {
+        let chain = std::iter::once(end_entity)
+            .chain(intermediates)
+            .enumerate()
+            .map(|(depth, der)| {
+                parse_certificate(der)
+                    .ok_or_else(|| format!("cannot parse certificate {depth} of the chain"))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let warnings = self.policy.check(&chain, ocsp_stapled, Utc::now())?;
+        Ok(TlsInfo {
+            version: None,
+            chain: chain.into_iter().map(|c| c.summary).collect(),
+            ocsp_stapled,
+            warnings,
+        })
+    }
+}
+
+impl ServerCertVerifier for PolicyVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        let host = server_name.to_str().into_owned();
+        match self.inspect(end_entity, intermediates, !ocsp_response.is_empty()) {
+            Ok(info) => {
+                for warning in &info.warnings {
+                    tracing::warn!(host = %host, "{warning}");
+                }
+                lock(&self.state.servers).insert(end_entity.to_vec(), info);
+                Ok(verified)
+            },
+            Err(reason) => {
+                tracing::debug!(host = %host, reason = %reason, "TLS policy violation");
+                lock(&self.state.violations).insert(host, reason.clone());
+                Err(rustls::Error::General(reason))
+            },
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.state.set_version(cert, "TLSv1.2");
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.state.set_version(cert, "TLSv1.3");
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+impl TlsPolicy {
+    /// Check the certificates of a server's chain, returning the warnings or the violation
+    fn check(
+        &self,
+        chain: &[Certificate],
+        ocsp_stapled: bool,
+        now: DateTime<Utc>,
+    ) -> std::result::Result<Vec<String>, String> {
+        if self.require_ocsp_stapling && !ocsp_stapled {
+            return Err("server did not staple an OCSP response".to_string());
+        }
+        let mut warnings = Vec::new();
+        for certificate in chain {
+            let summary = &certificate.summary;
+            let minimum = match certificate.family {
+                KeyFamily::Rsa => self.min_rsa_bits,
+                KeyFamily::Ec => self.min_ec_bits,
+                KeyFamily::Other => 0,
+            };
+            if summary.key_bits < minimum {
+                return Err(format!(
+                    "certificate '{}' has a {}-bit {} key (policy minimum {minimum})",
+                    summary.subject, summary.key_bits, summary.key_type
+                ));
+            }
+            if self.reject_sha1 && certificate.sha1_signature && summary.subject != summary.issuer {
+                return Err(format!(
+                    "certificate '{}' is signed with SHA-1 ({})",
+                    summary.subject, summary.signature_algorithm
+                ));
+            }
+            if let Some(window) = self.expiry_warning {
+                let remaining = certificate.not_after.signed_duration_since(now).to_std();
+                if !remaining.is_ok_and(|left| left >= window) {
+                    warnings.push(format!(
+                        "certificate '{}' expires soon ({})",
+                        summary.subject, summary.not_after
+                    ));
+                }
+            }
+        }
+        Ok(warnings)
+    }
+}
+
+/// What the policy needs to know about a certificate
+struct Certificate {
+    summary: CertificateSummary,
+    family: KeyFamily,
+    not_after: DateTime<Utc>,
+    sha1_signature: bool,
+}
+
+/// Which key size minimum applies to a certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyFamily {
+    Rsa,
+    Ec,
+    Other,
+}
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+/// `[0]` tag of the version field of `TBSCertificate`
+const EXPLICIT_VERSION: u8 = 0xa0;
+
+const RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+const DSA: &str = "1.2.840.10040.4.1";
+const EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+
+/// Named curves: OID, name, key size in bits
+const CURVES: &[(&str, &str, u32)] = &[
+    ("1.2.840.10045.3.1.7", "P-256", 256),
+    ("1.3.132.0.34", "P-384", 384),
+    ("1.3.132.0.35", "P-521", 521),
+];
+
+/// Other public key algorithms: OID, name, key size in bits
+const KEY_ALGORITHMS: &[(&str, &str, u32)] = &[
+    ("1.3.101.112", "Ed25519", 256),
+    ("1.3.101.113", "Ed448", 456),
+];
+
+/// Certificate signature algorithms: OID, name
+const SIGNATURE_ALGORITHMS: &[(&str, &str)] = &[
+    ("1.2.840.113549.1.1.4", "md5WithRSAEncryption"),
+    ("1.2.840.113549.1.1.5", "sha1WithRSAEncryption"),
+    ("1.2.840.113549.1.1.10", "rsassaPss"),
+    ("1.2.840.113549.1.1.11", "sha256WithRSAEncryption"),
+    ("1.2.840.113549.1.1.12", "sha384WithRSAEncryption"),
+    ("1.2.840.113549.1.1.13", "sha512WithRSAEncryption"),
+    ("1.2.840.10040.4.3", "dsa-with-SHA1"),
+    ("1.2.840.10045.4.1", "ecdsa-with-SHA1"),
+    ("1.2.840.10045.4.3.2", "ecdsa-with-SHA256"),
+    ("1.2.840.10045.4.3.3", "ecdsa-with-SHA384"),
+    ("1.2.840.10045.4.3.4", "ecdsa-with-SHA512"),
+    ("1.3.101.112", "Ed25519"),
+    ("1.3.101.113", "Ed448"),
+];
+
+/// Signature algorithms hashing with SHA-1
+const SHA1_SIGNATURES: &[&str] = &[
+    "1.2.840.113549.1.1.5",
+    "1.2.840.10040.4.3",
+    "1.2.840.10045.4.1",
+];
+
+/// Attribute types of distinguished names: OID, label
+const NAME_ATTRIBUTES: &[(&str, &str)] = &[
+    ("2.5.4.3", "CN"),
+    ("2.5.4.6", "C"),
+    ("2.5.4.7", "L"),
+    ("2.5.4.8", "ST"),
+    ("2.5.4.10", "O"),
+    ("2.5.4.11", "OU"),
+];
+
+/// Parse the fields of a DER-encoded X.509 certificate the policy looks at
+fn parse_certificate(der: &[u8]) -> Option<Certificate> {
+    let mut certificate = element(&mut &*der, SEQUENCE)?;
+    let mut tbs = element(&mut certificate, SEQUENCE)?;
+    let mut algorithm = element(&mut certificate, SEQUENCE)?;
+    let signature_oid = oid(element(&mut algorithm, OBJECT_IDENTIFIER)?);
+
+    let (tag, _) = any(&mut tbs)?;
+    if tag == EXPLICIT_VERSION {
+        any(&mut tbs)?; // serial number
+    }
+    element(&mut tbs, SEQUENCE)?; // signature algorithm, repeated
+    let issuer = name(element(&mut tbs, SEQUENCE)?)?;
+    let mut validity = element(&mut tbs, SEQUENCE)?;
+    any(&mut validity)?; // notBefore
+    let (tag, value) = any(&mut validity)?;
+    let not_after = time(tag, value)?;
+    let subject = name(element(&mut tbs, SEQUENCE)?)?;
+    let (family, key_type, key_bits) = public_key(element(&mut tbs, SEQUENCE)?)?;
+
+    Some(Certificate {
+        summary: CertificateSummary {
+            subject,
+            issuer,
+            not_after: not_after.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            key_type,
+            key_bits,
+            signature_algorithm: lookup(SIGNATURE_ALGORITHMS, &signature_oid)
+                .map_or_else(|| signature_oid.clone(), str::to_string),
+        },
+        family,
+        not_after,
+        sha1_signature: SHA1_SIGNATURES.contains(&signature_oid.as_str()),
+    })
+}
+
+/// Key family, algorithm name and size of a `SubjectPublicKeyInfo`
+fn public_key(mut spki: &[u8]) -> Option<(KeyFamily, String, u32)> {
+    let mut algorithm = element(&mut spki, SEQUENCE)?;
+    let algorithm_oid = oid(element(&mut algorithm, OBJECT_IDENTIFIER)?);
+    // Skip the count of unused bits
+    let key = element(&mut spki, BIT_STRING)?.get(1..)?;
+
+    Some(match algorithm_oid.as_str() {
+        RSA_ENCRYPTION => {
+            let mut rsa = element(&mut &*key, SEQUENCE)?;
+            let modulus = element(&mut rsa, INTEGER)?;
+            (KeyFamily::Rsa, "RSA".to_string(), integer_bits(modulus))
+        },
+        DSA => {
+            let mut parameters = element(&mut algorithm, SEQUENCE)?;
+            let prime = element(&mut parameters, INTEGER)?;
+            (KeyFamily::Rsa, "DSA".to_string(), integer_bits(prime))
+        },
+        EC_PUBLIC_KEY => {
+            let curve = oid(element(&mut algorithm, OBJECT_IDENTIFIER)?);
+            let (name, bits) = CURVES.iter().find(|(oid, ..)| *oid == curve).map_or_else(
+                // An uncompressed point holds both coordinates
+                || (curve.clone(), u32::try_from(key.len() / 2 * 8).unwrap_or(0)),
+                |(_, name, bits)| ((*name).to_string(), *bits),
+            );
+            (KeyFamily::Ec, format!("EC {name}"), bits)
+        },
+        other => KEY_ALGORITHMS
+            .iter()
+            .find(|(oid, ..)| *oid == other)
+            .map_or_else(
+                || (KeyFamily::Other, other.to_string(), 0),
+                |(_, name, bits)| (KeyFamily::Other, (*name).to_string(), *bits),
+            ),
+    })
+}
+
+/// A distinguished name as `CN=..., O=...`, in the order it is encoded
+fn name(mut rdns: &[u8]) -> Option<String> {
+    let mut attributes = Vec::new();
+    while !rdns.is_empty() {
+        let mut set = element(&mut rdns, SET)?;
+        while !set.is_empty() {
+            let mut attribute = element(&mut set, SEQUENCE)?;
+            let attribute_type = oid(element(&mut attribute, OBJECT_IDENTIFIER)?);
+            let (_, value) = any(&mut attribute)?;
+            attributes.push(format!(
+                "{}={}",
+                lookup(NAME_ATTRIBUTES, &attribute_type).unwrap_or(&attribute_type),
+                String::from_utf8_lossy(value)
+            ));
+        }
+    }
+    Some(attributes.join(", "))
+}
+
+/// A `UTCTime` or `GeneralizedTime` in the `Z` form DER requires
+fn time(tag: u8, value: &[u8]) -> Option<DateTime<Utc>> {
+    let value = std::str::from_utf8(value).ok()?;
+    let value = match tag {
+        // Two-digit years from 50 are 19xx (RFC 5280 4.1.2.5.1)
+        UTC_TIME => {
+            let year: u32 = value.get(..2)?.parse().ok()?;
+            format!("{}{value}", if year >= 50 { "19" } else { "20" })
+        },
+        GENERALIZED_TIME => value.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&value, "%Y%m%d%H%M%SZ")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Number of significant bits of a DER `INTEGER`
+fn integer_bits(integer: &[u8]) -> u32 {
+    let start = integer
+        .iter()
+        .position(|b| *b != 0)
+        .unwrap_or(integer.len());
+    let significant = &integer[start..];
+    significant.first().map_or(0, |first| {
+        u32::try_from(significant.len() * 8).unwrap_or(u32::MAX) - first.leading_zeros()
+    })
+}
+
+/// Dotted form of an `OBJECT IDENTIFIER`
+fn oid(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let mut arcs = Vec::new();
+    let mut arc = 0u64;
+    for byte in bytes {
+        arc = arc << 7 | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    let mut dotted = String::new();
+    for (index, arc) in arcs.into_iter().enumerate() {
+        if index == 0 {
+            // The first byte packs the first two arcs
+            let (first, second) = if arc < 80 {
+                (arc / 40, arc % 40)
+            } else {
+                (2, arc - 80)
+            };
+            let _ = write!(dotted, "{first}.{second}");
+        } else {
+            let _ = write!(dotted, ".{arc}");
+        }
+    }
+    dotted
+}
+
+fn lookup<'a>(table: &[(&str, &'a str)], oid: &str) -> Option<&'a str> {
+    table.iter().find(|(o, _)| *o == oid).map(|(_, name)| *name)
+}
+
+/// Read the next DER element, which must have `tag`, returning its contents
+fn element<'a>(input: &mut &'a [u8], tag: u8) -> Option<&'a [u8]> {
+    let (found, contents) = any(input)?;
+    (found == tag).then_some(contents)
+}
+
+/// Read the next DER element, returning its tag and contents
+fn any<'a>(input: &mut &'a [u8]) -> Option<(u8, &'a [u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let length = if first < 0x80 {
+        usize::from(first)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > std::mem::size_of::<usize>() {
+            return None;
+        }
+        let (bytes, after) = rest.split_at_checked(count)?;
+        rest = after;
+        bytes
+            .iter()
+            .fold(0, |length, b| length << 8 | usize::from(*b))
+    };
+    let (contents, rest) = rest.split_at_checked(length)?;
+    *input = rest;
+    Some((tag, contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Certificate {
+        let path = format!("{}/tests/fixtures/tls/{name}", env!("CARGO_MANIFEST_DIR"));
+        let pem = std::fs::read(path).unwrap();
+        parse_certificate(&CertificateDer::from_pem_slice(&pem).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_certificates() {
+        let leaf = fixture("localhost.pem");
+        assert_eq!(leaf.summary.subject, "CN=localhost");
+        assert_eq!(leaf.summary.issuer, "CN=wget-faster test CA");
+        assert_eq!(leaf.summary.key_type, "EC P-256");
+        assert_eq!(leaf.summary.key_bits, 256);
+        assert_eq!(leaf.summary.signature_algorithm, "ecdsa-with-SHA256");
+        assert_eq!(leaf.summary.not_after, "2126-09-21T10:40:33Z");
+
+        let weak = fixture("weak.pem");
+        assert_eq!(weak.family, KeyFamily::Rsa);
+        assert_eq!(weak.summary.key_bits, 1024);
+    }
+
+    #[test]
+    fn test_policy_rules() {
+        let policy = TlsPolicy::default();
+        let now = Utc::now();
+        assert_eq!(policy.check(&[fixture("localhost.pem")], false, now), Ok(Vec::new()));
+
+        let error = policy
+            .check(&[fixture("weak.pem")], false, now)
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "certificate 'CN=localhost' has a 1024-bit RSA key (policy minimum 2048)"
+        );
+
+        let mut sha1 = fixture("localhost.pem");
+        sha1.sha1_signature = true;
+        assert!(policy
+            .check(&[sha1], false, now)
+            .unwrap_err()
+            .contains("SHA-1"));
+
+        let stapling = TlsPolicy {
+            require_ocsp_stapling: true,
+            ..TlsPolicy::default()
+        };
+        assert!(stapling
+            .check(&[fixture("localhost.pem")], false, now)
+            .is_err());
+        assert!(stapling
+            .check(&[fixture("localhost.pem")], true, now)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_oid_and_time() {
+        assert_eq!(oid(&[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01]), EC_PUBLIC_KEY);
+        assert_eq!(
+            time(UTC_TIME, b"491231235959Z").map(|t| t.to_rfc3339()),
+            Some("2049-12-31T23:59:59+00:00".to_string())
+        );
+        assert_eq!(
+            time(UTC_TIME, b"500101000000Z").map(|t| t.to_rfc3339()),
+            Some("1950-01-01T00:00:00+00:00".to_string())
+        );
+    }
+}
@@ -0,0 +1,195 @@
+/// URL normalization for recursive crawls: fragments, tracking and session parameters
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+/// Whether query parameter `name` matches `pattern` (`*` and `?` wildcards)
+///
+/// Matching is case-insensitive: `utm_*` matches `UTM_Source`.
+pub fn query_param_matches(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[u8], name: &[u8]) -> bool {
+        match (pattern.split_first(), name.split_first()) {
+            (None, None) => true,
+            (Some((b'*', rest)), _) => {
+                matches(rest, name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            },
+            (Some((b'?', rest)), Some((_, name_rest))) => matches(rest, name_rest),
+            (Some((p, rest)), Some((n, name_rest))) => {
+                p.eq_ignore_ascii_case(n) && matches(rest, name_rest)
+            },
+            _ => false,
+        }
+    }
+    matches(pattern.as_bytes(), name.as_bytes())
+}
+
+/// `url` without its fragment and without query parameters whose name matches any of `patterns`
+///
+/// The remaining parameters keep their order; an emptied query is removed
+/// entirely (`page?utm_source=x` becomes `page`).
+pub fn strip_query_params(url: &Url, patterns: &[String]) -> Url {
+    let mut stripped = url.clone();
+    stripped.set_fragment(None);
+    retain_query_params(&mut stripped, |name| {
+        !patterns.iter().any(|p| query_param_matches(p, name))
+    });
+    stripped
+}
+
+/// Keep only the query parameters for which `keep` returns true
+fn retain_query_params(url: &mut Url, keep: impl Fn(&str) -> bool) {
+    let Some(query) = url.query() else {
+        return;
+    };
+    let pairs: Vec<&str> = query.split('&').filter(|pair| !pair.is_empty()).collect();
+    let kept: Vec<&str> = pairs
+        .iter()
+        .copied()
+        .filter(|pair| keep(&param_name(pair)))
+        .collect();
+    if kept.len() == pairs.len() {
+        return;
+    }
+    let kept = kept.join("&");
+    url.set_query((!kept.is_empty()).then_some(kept.as_str()));
+}
+
+/// Decoded name of a `name=value` query pair
+fn param_name(pair: &str) -> String {
+    let name = pair.split('=').next().unwrap_or_default();
+    percent_encoding::percent_decode_str(&name.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// (query parameter, URL without that parameter)
+type SiblingKey = (String, String);
+
+/// Crawl keys for URLs, learning session-like query parameters along the way
+///
+/// A parameter is session-like when two URLs that differ only in its value
+/// return byte-identical bodies. From then on it is stripped like the
+/// configured patterns.
+#[derive(Debug, Default)]
+pub(crate) struct UrlDeduper {
+    patterns: Vec<String>,
+    detect_sessions: bool,
+    session_params: HashSet<String>,
+    /// Parameter value and body digest of the first fetch per sibling key
+    bodies: HashMap<SiblingKey, (String, Vec<u8>)>,
+}
+
+impl UrlDeduper {
+    pub(crate) fn new(patterns: Vec<String>, detect_sessions: bool) -> Self {
+        Self {
+            patterns,
+            detect_sessions,
+            ..Self::default()
+        }
+    }
+
+    /// `url` with the fragment, configured parameters and learned session parameters removed
+    pub(crate) fn normalize(&self, url: &str) -> String {
+        let Ok(parsed) = Url::parse(url) else {
+            return url.to_string();
+        };
+        let mut normalized = strip_query_params(&parsed, &self.patterns);
+        retain_query_params(&mut normalized, |name| !self.session_params.contains(name));
+        normalized.to_string()
+    }
+
+    /// Record the body of a fetched `url`; returns a newly detected session parameter
+    pub(crate) fn record_body(&mut self, url: &str, body: &[u8]) -> Option<String> {
+        if !self.detect_sessions {
+            return None;
+        }
+        let parsed = Url::parse(&self.normalize(url)).ok()?;
+        let params: Vec<(String, String)> = parsed
+            .query_pairs()
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        if params.is_empty() {
+            return None;
+        }
+
+        let digest = ring::digest::digest(&ring::digest::SHA256, body)
+            .as_ref()
+            .to_vec();
+        for (name, value) in params {
+            let mut sibling = parsed.clone();
+            retain_query_params(&mut sibling, |n| n != name);
+
+            let key = (name.clone(), sibling.to_string());
+            match self.bodies.get(&key) {
+                Some((seen_value, seen_digest))
+                    if *seen_value != value && *seen_digest == digest =>
+                {
+                    self.session_params.insert(name.clone());
+                    return Some(name);
+                },
+                Some(_) => {},
+                None => {
+                    self.bodies.insert(key, (value, digest.clone()));
+                },
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_param_matches() {
+        assert!(query_param_matches("utm_*", "utm_source"));
+        assert!(query_param_matches("utm_*", "UTM_Campaign"));
+        assert!(query_param_matches("fbclid", "fbclid"));
+        assert!(query_param_matches("s?d", "sid"));
+        assert!(query_param_matches("*", "anything"));
+        assert!(!query_param_matches("utm_*", "xutm_source"));
+        assert!(!query_param_matches("fbclid", "fbclid2"));
+    }
+
+    #[test]
+    fn test_strip_query_params() {
+        let patterns = vec!["utm_*".to_string(), "fbclid".to_string()];
+        let strip =
+            |url: &str| strip_query_params(&Url::parse(url).unwrap(), &patterns).to_string();
+
+        assert_eq!(
+            strip("http://example.com/a?utm_source=x&id=3&fbclid=abc#top"),
+            "http://example.com/a?id=3"
+        );
+        assert_eq!(strip("http://example.com/a?utm_medium=mail"), "http://example.com/a");
+        assert_eq!(strip("http://example.com/a?b=1&c=2"), "http://example.com/a?b=1&c=2");
+        assert_eq!(strip("http://example.com/a#frag"), "http://example.com/a");
+    }
+
+    #[test]
+    fn test_session_param_detection() {
+        let mut deduper = UrlDeduper::new(Vec::new(), true);
+
+        assert_eq!(deduper.record_body("http://example.com/p?sid=1&page=2", b"same"), None);
+        // Different page, different body: not a session parameter
+        assert_eq!(deduper.record_body("http://example.com/p?sid=1&page=3", b"other"), None);
+        // Same page, only the sid differs, identical body
+        assert_eq!(
+            deduper.record_body("http://example.com/p?sid=2&page=2", b"same"),
+            Some("sid".to_string())
+        );
+
+        assert_eq!(
+            deduper.normalize("http://example.com/p?sid=9&page=2"),
+            "http://example.com/p?page=2"
+        );
+    }
+
+    #[test]
+    fn test_session_detection_disabled() {
+        let mut deduper = UrlDeduper::new(Vec::new(), false);
+        deduper.record_body("http://example.com/p?sid=1", b"same");
+        assert_eq!(deduper.record_body("http://example.com/p?sid=2", b"same"), None);
+        assert_eq!(deduper.normalize("http://example.com/p?sid=2"), "http://example.com/p?sid=2");
+    }
+}
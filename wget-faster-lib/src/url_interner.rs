@@ -0,0 +1,54 @@
+/// Shared storage for the URLs a crawl keeps track of
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::sync::Arc;
+
+/// Bookkeeping bytes per interned URL: the set slot, its control byte and the
+/// two reference counts in front of the string
+const ENTRY_OVERHEAD: usize = size_of::<Arc<str>>() + 1 + 2 * size_of::<usize>();
+
+/// Set of URLs stored once and handed out as shared [`Arc<str>`]s
+///
+/// The visited set, the queue and the link converter hold clones of the same
+/// allocation instead of a copy each. URLs are kept for the whole crawl.
+#[derive(Debug, Default)]
+pub(crate) struct UrlInterner {
+    urls: HashSet<Arc<str>>,
+    bytes: usize,
+}
+
+impl UrlInterner {
+    /// Shared copy of `url`, allocated the first time it is seen
+    pub(crate) fn intern(&mut self, url: &str) -> Arc<str> {
+        if let Some(interned) = self.urls.get(url) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(url);
+        self.bytes += url.len() + ENTRY_OVERHEAD;
+        self.urls.insert(interned.clone());
+        interned
+    }
+
+    /// Approximate heap bytes used by the stored URLs
+    pub(crate) fn approx_bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_allocation() {
+        let mut interner = UrlInterner::default();
+        let first = interner.intern("http://example.com/a");
+        let second = interner.intern(&String::from("http://example.com/a"));
+        let other = interner.intern("http://example.com/b");
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert_eq!(interner.urls.len(), 2);
+        assert_eq!(interner.approx_bytes(), 2 * (20 + ENTRY_OVERHEAD));
+    }
+}
@@ -0,0 +1,178 @@
+/// Clean-up of URLs typed by users or read from input files before they are parsed
+use crate::{Error, Result};
+use url::Url;
+
+/// Characters wget percent-encodes in a path, query or fragment
+const UNSAFE: &[u8] = b" \"<>\\^`{|}";
+
+/// Whether wget would send `byte` percent-encoded
+fn is_unsafe(byte: u8) -> bool {
+    UNSAFE.contains(&byte) || byte.is_ascii_control() || !byte.is_ascii()
+}
+
+/// Value of the escape starting at `bytes[at]` (a `%` and two hex digits)
+fn escape_at(bytes: &[u8], at: usize) -> Option<u8> {
+    let hex = bytes.get(at + 1..at + 3)?;
+    u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+}
+
+/// Whether every escape in `rest` is `%25XX` encoding an unsafe byte
+///
+/// Such a URL was encoded twice (`my%2520file` for `my file`); any other
+/// escape, like a lone `%25` for a literal `%`, means the escapes are deliberate.
+fn double_encoded(rest: &str) -> bool {
+    let bytes = rest.as_bytes();
+    let mut found = false;
+    for at in (0..bytes.len()).filter(|&i| bytes[i] == b'%') {
+        let inner = (escape_at(bytes, at) == Some(b'%'))
+            .then(|| escape_at(bytes, at + 2))
+            .flatten();
+        match inner {
+            Some(byte) if is_unsafe(byte) => found = true,
+            _ => return false,
+        }
+    }
+    found
+}
+
+/// Percent-encode what wget would encode in the path, query and fragment `rest`
+///
+/// Valid escapes are kept, a `%` not starting one becomes `%25` and every
+/// `#` after the first (which starts the fragment) becomes `%23`.
+fn encode_rest(rest: &str) -> String {
+    let bytes = rest.as_bytes();
+    let mut encoded = String::with_capacity(rest.len());
+    let mut in_fragment = false;
+    for (at, &byte) in bytes.iter().enumerate() {
+        let escaped = match byte {
+            b'%' => escape_at(bytes, at).is_none(),
+            b'#' => std::mem::replace(&mut in_fragment, true),
+            _ => is_unsafe(byte),
+        };
+        if escaped {
+            encoded.push_str(&format!("%{byte:02X}"));
+        } else {
+            encoded.push(char::from(byte));
+        }
+    }
+    encoded
+}
+
+/// Parse a URL given by a user, encoding the characters a server can't receive
+///
+/// Spaces, `"<>\^{|}`, backticks, control characters and non-ASCII characters
+/// in the path, query and fragment are percent-encoded the way wget sends them
+/// (`my file.pdf` becomes `my%20file.pdf`, `café` becomes `caf%C3%A9`), as is a
+/// `%` that doesn't start an escape. A URL encoded twice (`%2520`) is decoded
+/// once, but only when all of its escapes are double encodings of such
+/// characters. The path, query and fragment of a URL that needs no encoding
+/// are kept byte for byte. A URL without a scheme is taken as `http://`, as by
+/// wget.
+///
+/// # Errors
+///
+/// Returns [`Error::UnsupportedScheme`] for schemes other than `http` and
+/// `https`, and [`Error::InvalidUrl`] when the host is missing or the URL
+/// still can't be parsed.
+pub fn prepare_url(input: &str) -> Result<Url> {
+    let input = input.trim();
+    let url = match input.split_once("://") {
+        Some((scheme, _))
+            if !scheme.is_empty()
+                && scheme
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b)) =>
+        {
+            if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+                return Err(Error::UnsupportedScheme(scheme.to_string()));
+            }
+            input.to_string()
+        },
+        _ => format!("http://{input}"),
+    };
+
+    let (scheme, remainder) = url.split_once("://").unwrap_or_default();
+    let authority_end = remainder.find(['/', '?', '#']).unwrap_or(remainder.len());
+    let (authority, rest) = remainder.split_at(authority_end);
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    if host.is_empty() || host.starts_with(':') {
+        return Err(Error::InvalidUrl(url::ParseError::EmptyHost));
+    }
+
+    let rest = if double_encoded(rest) {
+        rest.replace("%25", "%")
+    } else {
+        rest.to_string()
+    };
+    Ok(Url::parse(&format!("{scheme}://{authority}{}", encode_rest(&rest)))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_url_matches_wget() {
+        // (input, URL as wget sends it)
+        let cases = [
+            ("http://example.com/my file (1).pdf", "http://example.com/my%20file%20(1).pdf"),
+            ("http://example.com/a{b}c", "http://example.com/a%7Bb%7Dc"),
+            ("http://example.com/a|b^c`d", "http://example.com/a%7Cb%5Ec%60d"),
+            ("http://example.com/a\\b", "http://example.com/a%5Cb"),
+            ("http://example.com/\"q\"<x>", "http://example.com/%22q%22%3Cx%3E"),
+            ("http://example.com/café", "http://example.com/caf%C3%A9"),
+            ("http://example.com/100%", "http://example.com/100%25"),
+            ("http://example.com/100%zz", "http://example.com/100%25zz"),
+            ("http://example.com/a%20b", "http://example.com/a%20b"),
+            ("http://example.com/a%2fb%2Fc", "http://example.com/a%2fb%2Fc"),
+            ("http://example.com/s?q=a b&r=x|y", "http://example.com/s?q=a%20b&r=x%7Cy"),
+            ("http://example.com/p#one#two", "http://example.com/p#one%23two"),
+            ("http://example.com/tab\there", "http://example.com/tab%09here"),
+            ("  http://example.com/trimmed  ", "http://example.com/trimmed"),
+            ("HTTPS://example.com/x", "https://example.com/x"),
+            ("example.com/x", "http://example.com/x"),
+            ("example.com:8080/x", "http://example.com:8080/x"),
+            ("http://user:pw@example.com/a b", "http://user:pw@example.com/a%20b"),
+            // Double encoding is undone only when every escape is one
+            ("http://example.com/my%2520file", "http://example.com/my%20file"),
+            ("http://example.com/caf%25C3%25A9", "http://example.com/caf%C3%A9"),
+            ("http://example.com/100%25", "http://example.com/100%25"),
+            ("http://example.com/a%2520b%20c", "http://example.com/a%2520b%20c"),
+            ("http://example.com/a%252Fb", "http://example.com/a%252Fb"),
+        ];
+        for (input, expected) in cases {
+            let url = prepare_url(input).unwrap_or_else(|e| panic!("{input}: {e}"));
+            assert_eq!(url.as_str(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_prepare_url_keeps_valid_urls() {
+        for input in [
+            "http://example.com/",
+            "https://example.com/a/b.html?x=1&y=%2B#frag",
+            "http://example.com/%E2%82%AC/~user/a;b=c",
+            "https://example.com:8443/path?q=a+b",
+        ] {
+            assert_eq!(prepare_url(input).unwrap().as_str(), input);
+        }
+    }
+
+    #[test]
+    fn test_prepare_url_rejects_structural_problems() {
+        for input in ["ftp://example.com/f", "file:///etc/passwd", "gopher://x/"] {
+            assert!(matches!(prepare_url(input), Err(Error::UnsupportedScheme(_))), "{input}");
+        }
+        for input in [
+            "http://",
+            "http:///path",
+            "https://user@/x",
+            "http://:80/x",
+            "",
+        ] {
+            assert!(matches!(prepare_url(input), Err(Error::InvalidUrl(_))), "{input}");
+        }
+    }
+}
@@ -0,0 +1,714 @@
+/// Verifying previously downloaded files against their records
+///
+/// Records come from provenance sidecars (`<file>.provenance.json`) or a
+/// checksum file in `sha256sum` format. [`verify_local`] rehashes the files
+/// without any network use; [`Downloader::verify_remote`] asks the origin
+/// whether the files of records with an `ETag` or `Last-Modified` changed.
+use crate::checksum::Hasher;
+use crate::link_check::for_each_per_host;
+use crate::provenance::{ProvenanceRecord, DEFAULT_PROVENANCE_SUFFIX};
+use crate::{Checksum, Downloader, Error, Result};
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+
+/// Progress callback for [`verify_local`], called with (bytes hashed, total bytes)
+pub type VerifyProgress = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// What is known about one file to verify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyRecord {
+    /// Path of the file, relative to the verified directory
+    pub path: PathBuf,
+
+    /// Digest the file must have
+    pub checksum: Checksum,
+
+    /// Size the file must have, if recorded
+    pub size: Option<u64>,
+
+    /// URL the file was downloaded from, if recorded
+    pub url: Option<String>,
+
+    /// `ETag` of the download, if recorded
+    pub etag: Option<String>,
+
+    /// `Last-Modified` of the download, if recorded
+    pub last_modified: Option<String>,
+}
+
+/// Records of the files of one directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyRecords {
+    /// One record per file, in path order
+    pub files: Vec<VerifyRecord>,
+
+    /// Files under the directory holding the records themselves (relative
+    /// paths), never reported as extra
+    pub record_files: Vec<PathBuf>,
+}
+
+impl VerifyRecords {
+    /// Records of the provenance sidecars found under `dir`
+    ///
+    /// Each sidecar describes the file next to it, named without the
+    /// `.provenance.json` suffix.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `dir` can't be read or a sidecar isn't a provenance record
+    pub async fn from_sidecars(dir: &Path) -> Result<Self> {
+        let mut records = Self::default();
+        for sidecar in list_files(dir).await? {
+            let Some(name) = sidecar.to_str() else {
+                continue;
+            };
+            let Some(file) = name.strip_suffix(DEFAULT_PROVENANCE_SUFFIX) else {
+                continue;
+            };
+            let text = tokio::fs::read_to_string(dir.join(&sidecar)).await?;
+            let record: ProvenanceRecord = serde_json::from_str(&text)
+                .map_err(|e| invalid_records(&format!("{}: {e}", sidecar.display())))?;
+            records.files.push(VerifyRecord {
+                path: PathBuf::from(file),
+                checksum: Checksum::Sha256(record.sha256),
+                size: Some(record.size),
+                url: Some(record.url),
+                etag: record.headers.get("etag").cloned(),
+                last_modified: record.headers.get("last-modified").cloned(),
+            });
+            records.record_files.push(sidecar);
+        }
+        Ok(records)
+    }
+
+    /// Records of a checksum file in `sha256sum` format (`<hex>  <path>` per line)
+    ///
+    /// The algorithm follows from the digest length (MD5, SHA-256 or SHA-512).
+    /// Paths are relative to `dir`, as when running `sha256sum -c` in it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or a line isn't a checksum line
+    pub async fn from_checksum_file(path: &Path, dir: &Path) -> Result<Self> {
+        let text = tokio::fs::read_to_string(path).await?;
+        let mut records = Self::parse_checksums(&text)?;
+        if let Ok(inside) = path.strip_prefix(dir) {
+            records.record_files.push(normalize(inside));
+        }
+        Ok(records)
+    }
+
+    /// Records of the lines of a `sha256sum`-style checksum file
+    ///
+    /// Blank lines and `#` comments are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first line that isn't a checksum line
+    pub fn parse_checksums(text: &str) -> Result<Self> {
+        let mut files = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let record = parse_checksum_line(line)
+                .ok_or_else(|| invalid_records(&format!("checksum line {}: {line}", number + 1)))?;
+            files.push(record);
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self {
+            files,
+            record_files: Vec::new(),
+        })
+    }
+}
+
+/// `<hex>  <path>`, or `<hex> *<path>` for binary mode
+fn parse_checksum_line(line: &str) -> Option<VerifyRecord> {
+    let (hex, path) = line.split_once(' ')?;
+    let path = path.strip_prefix([' ', '*'])?;
+    if path.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let checksum = match hex.len() {
+        32 => Checksum::Md5(hex.to_string()),
+        64 => Checksum::Sha256(hex.to_string()),
+        128 => Checksum::Sha512(hex.to_string()),
+        _ => return None,
+    };
+    Some(VerifyRecord {
+        path: normalize(Path::new(path)),
+        checksum,
+        size: None,
+        url: None,
+        etag: None,
+        last_modified: None,
+    })
+}
+
+fn invalid_records(message: &str) -> Error {
+    Error::IoError(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid verify records: {message}"),
+    ))
+}
+
+/// `path` without `.` components
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// Every file under `dir`, relative to it, in path order
+async fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&current).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Outcome of verifying one file offline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyStatus {
+    /// Size and digest match the record
+    Ok,
+
+    /// The digest differs from the record
+    Corrupted {
+        /// Digest in the record
+        expected: Checksum,
+        /// Digest of the file
+        actual: Checksum,
+    },
+
+    /// The size differs from the record (the file isn't hashed)
+    WrongSize {
+        /// Size in the record
+        expected: u64,
+        /// Size of the file
+        actual: u64,
+    },
+
+    /// Recorded but not on disk
+    Missing,
+
+    /// On disk but not recorded
+    Extra,
+
+    /// The file couldn't be read
+    Unreadable {
+        /// The read error
+        error: String,
+    },
+}
+
+impl VerifyStatus {
+    /// Whether the file fails verification (extra files don't)
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Self::Ok | Self::Extra)
+    }
+
+    /// Short uppercase name used in reports
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Corrupted { .. } | Self::WrongSize { .. } => "CORRUPTED",
+            Self::Missing => "MISSING",
+            Self::Extra => "EXTRA",
+            Self::Unreadable { .. } => "UNREADABLE",
+        }
+    }
+}
+
+impl fmt::Display for VerifyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Corrupted { expected, actual } => {
+                write!(f, "CORRUPTED (expected {expected}, got {actual})")
+            },
+            Self::WrongSize { expected, actual } => {
+                write!(f, "CORRUPTED (expected {expected} bytes, got {actual})")
+            },
+            Self::Unreadable { error } => write!(f, "UNREADABLE ({error})"),
+            other => f.write_str(other.as_str()),
+        }
+    }
+}
+
+/// Offline verification outcome of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyEntry {
+    /// Path relative to the verified directory
+    pub path: PathBuf,
+
+    /// What was found
+    pub status: VerifyStatus,
+}
+
+/// Result of [`verify_local`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyLocalReport {
+    /// Files whose size and digest match
+    pub ok: usize,
+
+    /// Files whose size or digest differ
+    pub corrupted: usize,
+
+    /// Recorded files not on disk
+    pub missing: usize,
+
+    /// Files on disk without a record
+    pub extra: usize,
+
+    /// Files that couldn't be read
+    pub unreadable: usize,
+
+    /// Bytes hashed
+    pub bytes: u64,
+
+    /// Per-file outcomes, recorded files in record order followed by extra files
+    pub entries: Vec<VerifyEntry>,
+}
+
+impl VerifyLocalReport {
+    fn new(entries: Vec<VerifyEntry>, bytes: u64) -> Self {
+        let mut report = Self {
+            ok: 0,
+            corrupted: 0,
+            missing: 0,
+            extra: 0,
+            unreadable: 0,
+            bytes,
+            entries: Vec::new(),
+        };
+        for entry in &entries {
+            match entry.status {
+                VerifyStatus::Ok => report.ok += 1,
+                VerifyStatus::Corrupted { .. } | VerifyStatus::WrongSize { .. } => {
+                    report.corrupted += 1;
+                },
+                VerifyStatus::Missing => report.missing += 1,
+                VerifyStatus::Extra => report.extra += 1,
+                VerifyStatus::Unreadable { .. } => report.unreadable += 1,
+            }
+        }
+        report.entries = entries;
+        report
+    }
+
+    /// Whether every recorded file is present and intact
+    pub fn is_ok(&self) -> bool {
+        self.corrupted + self.missing + self.unreadable == 0
+    }
+
+    /// Entries other than intact files
+    pub fn problems(&self) -> impl Iterator<Item = &VerifyEntry> {
+        self.entries.iter().filter(|e| e.status != VerifyStatus::Ok)
+    }
+}
+
+impl fmt::Display for VerifyLocalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} files checked, {} hashed",
+            self.entries.len() - self.extra,
+            crate::progress::format_bytes(self.bytes)
+        )?;
+        writeln!(f, "  ok:         {}", self.ok)?;
+        writeln!(f, "  corrupted:  {}", self.corrupted)?;
+        writeln!(f, "  missing:    {}", self.missing)?;
+        writeln!(f, "  unreadable: {}", self.unreadable)?;
+        write!(f, "  extra:      {}", self.extra)
+    }
+}
+
+/// Rehash the files of `dir` and compare them with `records`, without network use
+///
+/// Files are hashed one at a time in reads of 64 kilobytes, so memory use doesn't
+/// depend on their size; `progress` gets the bytes hashed so far against the
+/// total size of the recorded files present. Files whose size differs from
+/// the record aren't hashed. Files under `dir` without a record are reported
+/// as extra, except the record files themselves.
+///
+/// # Errors
+///
+/// Returns an error if `dir` can't be listed; unreadable files are reported
+/// in the result instead.
+pub async fn verify_local(
+    dir: &Path,
+    records: &VerifyRecords,
+    progress: Option<VerifyProgress>,
+) -> Result<VerifyLocalReport> {
+    let on_disk = list_files(dir).await?;
+
+    let mut sizes = Vec::with_capacity(records.files.len());
+    for record in &records.files {
+        let size = tokio::fs::metadata(dir.join(&record.path))
+            .await
+            .map(|m| m.len());
+        sizes.push(size);
+    }
+    let total: u64 = sizes
+        .iter()
+        .zip(&records.files)
+        .filter_map(|(size, record)| {
+            let size = *size.as_ref().ok()?;
+            record
+                .size
+                .is_none_or(|expected| expected == size)
+                .then_some(size)
+        })
+        .sum();
+
+    let mut hashing = Hashing {
+        hashed: 0,
+        total,
+        progress,
+    };
+    let mut entries = Vec::with_capacity(on_disk.len());
+    for (record, size) in records.files.iter().zip(sizes) {
+        let status = match size {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => VerifyStatus::Missing,
+            Err(e) => VerifyStatus::Unreadable {
+                error: e.to_string(),
+            },
+            Ok(actual) => match record.size {
+                Some(expected) if expected != actual => {
+                    VerifyStatus::WrongSize { expected, actual }
+                },
+                _ => {
+                    hashing
+                        .verify(&dir.join(&record.path), &record.checksum)
+                        .await
+                },
+            },
+        };
+        if status.is_failure() {
+            tracing::debug!(path = %record.path.display(), status = %status, "File failed verification");
+        }
+        entries.push(VerifyEntry {
+            path: record.path.clone(),
+            status,
+        });
+    }
+
+    let known: std::collections::HashSet<&Path> = records
+        .files
+        .iter()
+        .map(|r| r.path.as_path())
+        .chain(records.record_files.iter().map(PathBuf::as_path))
+        .collect();
+    entries.extend(
+        on_disk
+            .iter()
+            .filter(|path| !known.contains(path.as_path()))
+            .map(|path| VerifyEntry {
+                path: path.clone(),
+                status: VerifyStatus::Extra,
+            }),
+    );
+
+    Ok(VerifyLocalReport::new(entries, hashing.hashed))
+}
+
+/// Progress of the hashing done by `verify_local`
+struct Hashing {
+    hashed: u64,
+    total: u64,
+    progress: Option<VerifyProgress>,
+}
+
+impl Hashing {
+    /// Hash the file at `path` and compare it with `expected`
+    async fn verify(&mut self, path: &Path, expected: &Checksum) -> VerifyStatus {
+        match self.hash(path, expected).await {
+            Ok(actual) if actual.matches(expected) => VerifyStatus::Ok,
+            Ok(actual) => VerifyStatus::Corrupted {
+                expected: expected.clone(),
+                actual,
+            },
+            Err(e) => VerifyStatus::Unreadable {
+                error: e.to_string(),
+            },
+        }
+    }
+
+    /// Digest of the file at `path` with the algorithm of `expected`
+    async fn hash(&mut self, path: &Path, expected: &Checksum) -> Result<Checksum> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut hasher = Hasher::new(Some(expected));
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(hasher.finish());
+            }
+            hasher.update(&buffer[..read]);
+            self.hashed += read as u64;
+            if let Some(ref progress) = self.progress {
+                progress(self.hashed, self.total);
+            }
+        }
+    }
+}
+
+/// Outcome of revalidating one file against its origin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteStatus {
+    /// The origin still serves what was downloaded (304, or the same validators)
+    Unchanged,
+
+    /// The origin serves something else now
+    Changed {
+        /// Which validator differs
+        reason: String,
+    },
+
+    /// The probe failed or returned an error status
+    Failed {
+        /// Error or status of the probe
+        error: String,
+    },
+}
+
+/// Remote verification outcome of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteVerifyEntry {
+    /// Path relative to the verified directory
+    pub path: PathBuf,
+
+    /// URL the file was downloaded from
+    pub url: String,
+
+    /// What the origin said
+    pub status: RemoteStatus,
+}
+
+/// Result of [`Downloader::verify_remote`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyRemoteReport {
+    /// Files the origin still serves unchanged
+    pub unchanged: usize,
+
+    /// Files changed at the origin
+    pub changed: usize,
+
+    /// Files whose probe failed
+    pub failed: usize,
+
+    /// Records without a URL and validator, not checked
+    pub skipped: usize,
+
+    /// Per-file outcomes of the checked records, in record order
+    pub entries: Vec<RemoteVerifyEntry>,
+}
+
+impl VerifyRemoteReport {
+    /// Whether no checked file changed or failed
+    pub fn is_ok(&self) -> bool {
+        self.changed + self.failed == 0
+    }
+}
+
+impl fmt::Display for VerifyRemoteReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} files revalidated", self.entries.len())?;
+        writeln!(f, "  unchanged:  {}", self.unchanged)?;
+        writeln!(f, "  changed:    {}", self.changed)?;
+        writeln!(f, "  failed:     {}", self.failed)?;
+        write!(f, "  no validator (skipped): {}", self.skipped)
+    }
+}
+
+pub(crate) async fn verify_remote(
+    downloader: &Downloader,
+    records: &VerifyRecords,
+    concurrency: usize,
+) -> VerifyRemoteReport {
+    let checked: Vec<Revalidation> = records
+        .files
+        .iter()
+        .filter_map(|record| {
+            let url = record.url.as_deref()?;
+            (record.etag.is_some() || record.last_modified.is_some())
+                .then_some(Revalidation { record, url })
+        })
+        .collect();
+    let skipped = records.files.len() - checked.len();
+
+    let config = downloader.get_client().config();
+    let statuses = for_each_per_host(
+        checked.clone(),
+        concurrency,
+        config.wait_time,
+        config.random_wait,
+        None,
+        |item| async move { revalidate(downloader, item).await },
+    )
+    .await;
+
+    let mut report = VerifyRemoteReport {
+        unchanged: 0,
+        changed: 0,
+        failed: 0,
+        skipped,
+        entries: Vec::with_capacity(checked.len()),
+    };
+    for (Revalidation { record, url }, status) in checked.into_iter().zip(statuses) {
+        let status = status.unwrap_or(RemoteStatus::Failed {
+            error: "not probed".to_string(),
+        });
+        match status {
+            RemoteStatus::Unchanged => report.unchanged += 1,
+            RemoteStatus::Changed { .. } => report.changed += 1,
+            RemoteStatus::Failed { .. } => report.failed += 1,
+        }
+        report.entries.push(RemoteVerifyEntry {
+            path: record.path.clone(),
+            url: url.to_string(),
+            status,
+        });
+    }
+    report
+}
+
+/// A record with a URL and validator, probed by `verify_remote`
+#[derive(Clone, Copy)]
+struct Revalidation<'a> {
+    record: &'a VerifyRecord,
+    url: &'a str,
+}
+
+impl AsRef<str> for Revalidation<'_> {
+    fn as_ref(&self) -> &str {
+        self.url
+    }
+}
+
+/// Conditional probe of the URL of a record, compared with its validators
+async fn revalidate(
+    downloader: &Downloader,
+    Revalidation { record, url }: Revalidation<'_>,
+) -> RemoteStatus {
+    let since = record
+        .last_modified
+        .as_deref()
+        .and_then(|date| httpdate::parse_http_date(date).ok());
+    let metadata = match downloader
+        .get_client()
+        .get_metadata_conditional(url, since)
+        .await
+    {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            return RemoteStatus::Failed {
+                error: e.to_string(),
+            }
+        },
+    };
+    match metadata.status_code {
+        304 => RemoteStatus::Unchanged,
+        200..=299 => {
+            compare_validators(record, metadata.etag.as_deref(), metadata.last_modified.as_deref())
+        },
+        status => RemoteStatus::Failed {
+            error: format!("HTTP {status}"),
+        },
+    }
+}
+
+/// Compare the validators of a full response with the recorded ones, `ETag` first
+fn compare_validators(
+    record: &VerifyRecord,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> RemoteStatus {
+    let changed = |name: &str, recorded: &str, current: Option<&str>| RemoteStatus::Changed {
+        reason: format!("{name} {recorded} is now {}", current.unwrap_or("absent")),
+    };
+    if let Some(ref recorded) = record.etag {
+        return if etag == Some(recorded.as_str()) {
+            RemoteStatus::Unchanged
+        } else {
+            changed("ETag", recorded, etag)
+        };
+    }
+    match record.last_modified {
+        Some(ref recorded) => {
+            let same = last_modified.is_some_and(|current| {
+                current == recorded
+                    || httpdate::parse_http_date(current).ok()
+                        == httpdate::parse_http_date(recorded).ok()
+            });
+            if same {
+                RemoteStatus::Unchanged
+            } else {
+                changed("Last-Modified", recorded, last_modified)
+            }
+        },
+        None => RemoteStatus::Unchanged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checksums() {
+        let sha = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let text = format!(
+            "# release sums\n{sha}  ./b.txt\r\n\n900150983cd24fb0d6963f7d28e17f72 *dir/a b.bin\n"
+        );
+        let records = VerifyRecords::parse_checksums(&text).unwrap();
+        let paths: Vec<&Path> = records.files.iter().map(|r| r.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("b.txt"), Path::new("dir/a b.bin")]);
+        assert_eq!(records.files[0].checksum, Checksum::Sha256(sha.to_string()));
+        assert!(matches!(records.files[1].checksum, Checksum::Md5(_)));
+
+        for bad in ["abc  file", &format!("{sha}file"), &format!("{sha}  ")] {
+            assert!(VerifyRecords::parse_checksums(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_compare_validators() {
+        let mut record = VerifyRecord {
+            path: PathBuf::from("a"),
+            checksum: Checksum::Sha256(String::new()),
+            size: None,
+            url: Some("http://example.com/a".to_string()),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+        };
+        assert_eq!(compare_validators(&record, Some("\"v1\""), None), RemoteStatus::Unchanged);
+        assert!(matches!(
+            compare_validators(&record, Some("\"v2\""), Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            RemoteStatus::Changed { .. }
+        ));
+
+        record.etag = None;
+        assert_eq!(
+            compare_validators(&record, None, Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            RemoteStatus::Unchanged
+        );
+        assert!(matches!(compare_validators(&record, None, None), RemoteStatus::Changed { .. }));
+    }
+}
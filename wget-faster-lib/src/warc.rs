@@ -0,0 +1,458 @@
+/// WARC 1.1 archive output (`DownloadConfig::warc_file`, wget's `--warc-file`)
+///
+/// Every response the client receives is archived as a `request` record (the
+/// request line and the headers sent) followed by a `response` record (status
+/// line, headers and the body exactly as received, still content-coded). The
+/// body is recorded as the downloader streams it, spooled to a temporary file,
+/// and both records are appended once it ends. A body cut short, or dropped
+/// before it was read to the end, is marked with `WARC-Truncated`.
+///
+/// Each record is its own gzip member, so the `.warc.gz` file can be appended
+/// to by later runs and read by `zcat` or `warcio`. Opening a file writes a
+/// `warcinfo` record that the following records refer to. Writing an archive
+/// blocks briefly on file I/O; a failed write is logged and doesn't fail the
+/// download.
+use crate::Result;
+use bytes::Bytes;
+use hyper::body::{Body, Frame, SizeHint};
+use reqwest::header::HeaderMap;
+use ring::digest;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Seek, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+
+/// RFC 4648 base32 alphabet, used for WARC digests
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Appends WARC records to a `.warc.gz` file
+#[derive(Debug)]
+pub struct WarcWriter {
+    path: PathBuf,
+    file: Mutex<File>,
+    warcinfo_id: String,
+}
+
+impl WarcWriter {
+    /// Open `path` for appending (creating it if needed) and write a `warcinfo` record
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IoError` if the file can't be opened or written.
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        let writer = Self {
+            file: Mutex::new(file),
+            warcinfo_id: record_id(),
+            path,
+        };
+        writer.write_warcinfo()?;
+        Ok(writer)
+    }
+
+    /// The file records are appended to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `response` with its body recorded as it is read, archived with `request`
+    pub(crate) fn capture(
+        self: &Arc<Self>,
+        request: SentRequest,
+        response: reqwest::Response,
+    ) -> reqwest::Response {
+        let url = response.url().clone();
+        let remote_addr = response.remote_addr();
+        let mut head = format!(
+            "{:?} {} {}\r\n",
+            response.version(),
+            response.status().as_str(),
+            response.status().canonical_reason().unwrap_or("")
+        )
+        .into_bytes();
+        append_headers(&mut head, response.headers());
+
+        let response: http::Response<reqwest::Body> = response.into();
+        let (mut parts, body) = response.into_parts();
+        let body = RecordedBody {
+            inner: body,
+            archive: Some(PendingRecord {
+                writer: Arc::clone(self),
+                request,
+                url: url.to_string(),
+                remote_addr,
+                head,
+                spool: None,
+                received: 0,
+                payload_digest: digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+            }),
+        };
+        // Keep the final URL, which reqwest stores in a private extension
+        let builder = reqwest::ResponseBuilderExt::url(http::Response::builder(), url);
+        if let Ok(with_url) = builder.body(()) {
+            parts.extensions.extend(with_url.into_parts().0.extensions);
+        }
+        reqwest::Response::from(http::Response::from_parts(parts, reqwest::Body::wrap(body)))
+    }
+
+    fn write_warcinfo(&self) -> io::Result<()> {
+        let filename = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let block = format!(
+            "software: wget-faster/{}\r\nformat: WARC File Format 1.1\r\nconformsTo: https://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        let fields = vec![
+            ("WARC-Type", "warcinfo".to_string()),
+            ("WARC-Record-ID", self.warcinfo_id.clone()),
+            ("WARC-Date", warc_date()),
+            ("WARC-Filename", filename),
+            ("Content-Type", "application/warc-fields".to_string()),
+            (
+                "WARC-Block-Digest",
+                sha1_label(digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, block.as_bytes())),
+            ),
+        ];
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        write_record(&mut file, &fields, block.as_bytes(), None)
+    }
+}
+
+/// What was sent for a request, for its `request` record
+pub(crate) struct SentRequest {
+    url: String,
+    head: Vec<u8>,
+    body: Option<Bytes>,
+}
+
+impl SentRequest {
+    /// `request` as sent with the client's `default_headers` (and `cookie`, if any)
+    pub(crate) fn new(
+        request: &reqwest::Request,
+        default_headers: &HeaderMap,
+        cookie: Option<reqwest::header::HeaderValue>,
+    ) -> Self {
+        let url = request.url();
+        let mut target = url.path().to_string();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        let mut head =
+            format!("{} {target} {:?}\r\n", request.method(), request.version()).into_bytes();
+        let mut headers = request.headers().clone();
+        for (name, value) in default_headers {
+            if !headers.contains_key(name) {
+                headers.insert(name, value.clone());
+            }
+        }
+        if let Some(cookie) = cookie.filter(|_| !headers.contains_key(reqwest::header::COOKIE)) {
+            headers.insert(reqwest::header::COOKIE, cookie);
+        }
+        if let Some(host) = url.host_str() {
+            let host = match url.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host.to_string(),
+            };
+            head.extend_from_slice(format!("host: {host}\r\n").as_bytes());
+        }
+        append_headers(&mut head, &headers);
+        Self {
+            url: url.to_string(),
+            head,
+            body: request
+                .body()
+                .and_then(reqwest::Body::as_bytes)
+                .map(Bytes::copy_from_slice),
+        }
+    }
+}
+
+/// A response body passed through unchanged while it's recorded
+struct RecordedBody {
+    inner: reqwest::Body,
+    /// Until the records are written
+    archive: Option<PendingRecord>,
+}
+
+/// A response being recorded
+struct PendingRecord {
+    writer: Arc<WarcWriter>,
+    request: SentRequest,
+    url: String,
+    remote_addr: Option<SocketAddr>,
+    /// Status line and headers
+    head: Vec<u8>,
+    /// Body received so far (created with the first bytes)
+    spool: Option<File>,
+    received: u64,
+    payload_digest: digest::Context,
+}
+
+impl PendingRecord {
+    fn record(&mut self, data: &[u8]) -> io::Result<()> {
+        self.payload_digest.update(data);
+        self.received += data.len() as u64;
+        let spool = match &mut self.spool {
+            Some(spool) => spool,
+            None => self.spool.insert(tempfile::tempfile()?),
+        };
+        spool.write_all(data)
+    }
+
+    /// Append the request and response records; `truncated` gives the reason the body is incomplete
+    fn finish(self, truncated: Option<&str>) -> io::Result<()> {
+        let Self {
+            writer,
+            request,
+            url,
+            remote_addr,
+            head,
+            mut spool,
+            received,
+            payload_digest,
+        } = self;
+        let response_id = record_id();
+        let date = warc_date();
+
+        let mut request_block = request.head;
+        request_block.extend_from_slice(b"\r\n");
+        if let Some(body) = &request.body {
+            request_block.extend_from_slice(body);
+        }
+        let request_fields = vec![
+            ("WARC-Type", "request".to_string()),
+            ("WARC-Record-ID", record_id()),
+            ("WARC-Date", date.clone()),
+            ("WARC-Target-URI", request.url),
+            ("WARC-Warcinfo-ID", writer.warcinfo_id.clone()),
+            ("WARC-Concurrent-To", response_id.clone()),
+            ("Content-Type", "application/http;msgtype=request".to_string()),
+            (
+                "WARC-Block-Digest",
+                sha1_label(digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &request_block)),
+            ),
+        ];
+
+        let mut response_head = head;
+        response_head.extend_from_slice(b"\r\n");
+        let mut block_digest = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+        block_digest.update(&response_head);
+        if let Some(spool) = &mut spool {
+            spool.rewind()?;
+            let mut reader = io::BufReader::new(&*spool);
+            let mut buffer = [0; 64 * 1024];
+            loop {
+                let read = io::Read::read(&mut reader, &mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                block_digest.update(&buffer[..read]);
+            }
+            spool.rewind()?;
+        }
+        let mut response_fields = vec![
+            ("WARC-Type", "response".to_string()),
+            ("WARC-Record-ID", response_id),
+            ("WARC-Date", date),
+            ("WARC-Target-URI", url),
+            ("WARC-Warcinfo-ID", writer.warcinfo_id.clone()),
+        ];
+        if let Some(addr) = remote_addr {
+            response_fields.push(("WARC-IP-Address", addr.ip().to_string()));
+        }
+        response_fields.extend([
+            ("Content-Type", "application/http;msgtype=response".to_string()),
+            ("WARC-Block-Digest", sha1_label(block_digest.finish())),
+            ("WARC-Payload-Digest", sha1_label(payload_digest.finish())),
+        ]);
+        if let Some(reason) = truncated {
+            response_fields.push(("WARC-Truncated", reason.to_string()));
+        }
+
+        let mut file = writer.file.lock().unwrap_or_else(PoisonError::into_inner);
+        write_record(&mut file, &request_fields, &request_block, None)?;
+        let body = spool.as_mut().map(|spool| (spool, received));
+        write_record(&mut file, &response_fields, &response_head, body)
+    }
+
+    fn finish_logged(self, truncated: Option<&str>) {
+        let (path, url) = (self.writer.path.clone(), self.url.clone());
+        if let Err(e) = self.finish(truncated) {
+            tracing::warn!(path = %path.display(), url = %crate::redact_url(&url), error = %e, "Failed to write WARC records");
+        }
+    }
+}
+
+impl Body for RecordedBody {
+    type Data = Bytes;
+    type Error = reqwest::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Frame<Bytes>, reqwest::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let (Some(data), Some(archive)) = (frame.data_ref(), self.archive.as_mut()) {
+                    if let Err(e) = archive.record(data) {
+                        tracing::warn!(error = %e, "Failed to spool body for WARC - not archiving it");
+                        self.archive = None;
+                    }
+                }
+            },
+            Poll::Ready(Some(Err(_))) => {
+                if let Some(archive) = self.archive.take() {
+                    archive.finish_logged(Some("disconnect"));
+                }
+            },
+            Poll::Ready(None) => {
+                if let Some(archive) = self.archive.take() {
+                    archive.finish_logged(None);
+                }
+            },
+            Poll::Pending => {},
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for RecordedBody {
+    fn drop(&mut self) {
+        // The body wasn't read to its end (or at all, like that of an error page)
+        if let Some(archive) = self.archive.take() {
+            let truncated = (!self.inner.is_end_stream()).then_some("unspecified");
+            archive.finish_logged(truncated);
+        }
+    }
+}
+
+/// Write one record as its own gzip member: `fields`, then `head` and the spooled body
+fn write_record(
+    file: &mut File,
+    fields: &[(&str, String)],
+    head: &[u8],
+    body: Option<(&mut File, u64)>,
+) -> io::Result<()> {
+    let length = head.len() as u64 + body.as_ref().map_or(0, |(_, len)| *len);
+    let mut header = String::from("WARC/1.1\r\n");
+    for (name, value) in fields {
+        let _ = write!(header, "{name}: {value}\r\n");
+    }
+    let _ = write!(header, "Content-Length: {length}\r\n\r\n");
+
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(header.as_bytes())?;
+    gzip.write_all(head)?;
+    if let Some((body, _)) = body {
+        io::copy(body, &mut gzip)?;
+    }
+    gzip.write_all(b"\r\n\r\n")?;
+    // One write per record, so records appended concurrently don't interleave
+    file.write_all(&gzip.finish()?)
+}
+
+/// Headers as `name: value` lines
+fn append_headers(out: &mut Vec<u8>, headers: &HeaderMap) {
+    for (name, value) in headers {
+        out.extend_from_slice(name.as_str().as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// A new `<urn:uuid:...>` record ID (UUID version 4)
+fn record_id() -> String {
+    use ring::rand::SecureRandom;
+    let mut bytes = [0u8; 16];
+    // Only fails if the OS has no randomness to give, which nothing else here survives either
+    let _ = ring::rand::SystemRandom::new().fill(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = crate::checksum::to_hex(&bytes);
+    format!(
+        "<urn:uuid:{}-{}-{}-{}-{}>",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// The current time as a `WARC-Date`
+fn warc_date() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// `sha1:` followed by the base32 digest, as WARC digests are written
+fn sha1_label(digest: digest::Digest) -> String {
+    format!("sha1:{}", base32(digest.as_ref()))
+}
+
+/// RFC 4648 base32 (with padding)
+fn base32(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    for group in bytes.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..group.len()].copy_from_slice(group);
+        let bits = buffer
+            .iter()
+            .fold(0u64, |bits, &byte| bits << 8 | u64::from(byte));
+        let symbols = (group.len() * 8).div_ceil(5);
+        for index in 0..8 {
+            if index < symbols {
+                let value = (bits >> (35 - index * 5)) & 0x1f;
+                out.push(char::from(BASE32[value as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"f"), "MY======");
+        assert_eq!(base32(b"fooba"), "MZXW6YTB");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI======");
+        let sha1 = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, b"");
+        assert_eq!(sha1_label(sha1), "sha1:3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ");
+    }
+
+    #[test]
+    fn test_record_id_is_uuid_v4() {
+        let id = record_id();
+        assert_eq!(id.len(), "<urn:uuid:>".len() + 36);
+        assert!(id.starts_with("<urn:uuid:") && id.ends_with('>'));
+        assert_eq!(&id[24..25], "4");
+        assert_ne!(record_id(), id);
+    }
+}
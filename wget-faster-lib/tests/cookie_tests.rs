@@ -1,4 +1,4 @@
-use wget_faster_lib::{Cookie, CookieJar};
+use wget_faster_lib::{Cookie, CookieJar, DownloadConfig, Downloader};
 
 #[test]
 fn test_cookie_creation() {
@@ -198,3 +198,204 @@ fn test_parse_set_cookie_with_domain() {
     assert_eq!(cookies[0].domain, ".example.com");
     assert_eq!(cookies[0].path, "/admin");
 }
+
+/// A server setting a persistent and a session cookie on `/login`, and updating
+/// the persistent one on `/page`
+async fn cookie_server() -> mockito::ServerGuard {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/login")
+        .with_header("set-cookie", "token=first; Path=/; Max-Age=3600")
+        .with_header("set-cookie", "sid=abc123; Path=/")
+        .with_body("logged in")
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/page")
+        .with_header("set-cookie", "token=second; Path=/; Max-Age=3600")
+        .with_body("page")
+        .create_async()
+        .await;
+    server
+}
+
+async fn download_and_save(server: &mockito::ServerGuard, config: DownloadConfig) {
+    let downloader = Downloader::new(config).unwrap();
+    for path in ["/login", "/page"] {
+        downloader
+            .download_to_memory(&format!("{}{path}", server.url()))
+            .await
+            .unwrap();
+    }
+    downloader.save_cookies().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_save_cookies_round_trip() {
+    let server = cookie_server().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cookies.txt");
+
+    let config = DownloadConfig {
+        save_cookie_file: Some(path.clone()),
+        ..DownloadConfig::default()
+    };
+    download_and_save(&server, config).await;
+
+    let jar = CookieJar::load_from_file(&path).await.unwrap();
+    let cookies = jar.get_cookies_for_domain("127.0.0.1");
+    // The session cookie is dropped; the persistent one has its latest value
+    assert_eq!(cookies.len(), 1);
+    assert_eq!(cookies[0].name, "token");
+    assert_eq!(cookies[0].value, "second");
+    assert!(cookies[0].expiration.is_some());
+}
+
+#[tokio::test]
+async fn test_saved_cookies_leave_out_deleted_cookie() {
+    let mut server = cookie_server().await;
+    server
+        .mock("GET", "/logout")
+        .with_header("set-cookie", "token=; Path=/; Max-Age=0")
+        .with_body("logged out")
+        .create_async()
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cookies.txt");
+
+    let config = DownloadConfig {
+        save_cookie_file: Some(path.clone()),
+        keep_session_cookies: true,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    for page in ["/login", "/page", "/logout"] {
+        downloader
+            .download_to_memory(&format!("{}{page}", server.url()))
+            .await
+            .unwrap();
+    }
+    downloader.save_cookies().await.unwrap();
+
+    let jar = CookieJar::load_from_file(&path).await.unwrap();
+    assert_eq!(jar.to_cookie_header("127.0.0.1", "/", false), Some("sid=abc123".to_string()));
+}
+
+#[tokio::test]
+async fn test_save_cookies_keeps_session_cookies() {
+    let server = cookie_server().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cookies.txt");
+
+    let config = DownloadConfig {
+        save_cookie_file: Some(path.clone()),
+        keep_session_cookies: true,
+        ..DownloadConfig::default()
+    };
+    download_and_save(&server, config).await;
+
+    let jar = CookieJar::load_from_file(&path).await.unwrap();
+    assert_eq!(
+        jar.to_cookie_header("127.0.0.1", "/", false)
+            .map(|h| h.len()),
+        Some("token=second; sid=abc123".len())
+    );
+    let session = jar
+        .get_cookies_for_domain("127.0.0.1")
+        .into_iter()
+        .find(|c| c.name == "sid")
+        .unwrap();
+    assert_eq!(session.value, "abc123");
+    assert_eq!(session.expiration, None);
+}
+
+#[tokio::test]
+async fn test_save_cookies_without_file_is_noop() {
+    let server = cookie_server().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    download_and_save(&server, DownloadConfig::default()).await;
+
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn test_load_cookies_sends_matching_cookies() {
+    let mut server = mockito::Server::new_async().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cookies.txt");
+    std::fs::write(
+        &path,
+        "# Netscape HTTP Cookie File\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t0\ttheme\tdark\n\
+         127.0.0.1\tFALSE\t/private\tFALSE\t0\tadmin\tyes\n\
+         127.0.0.1\tFALSE\t/\tTRUE\t0\tsecure\tonly-https\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t1\told\texpired\n",
+    )
+    .unwrap();
+
+    let head = server
+        .mock("HEAD", "/page")
+        .match_header("cookie", "theme=dark")
+        .with_status(200)
+        .expect_at_least(1)
+        .create_async()
+        .await;
+    let get = server
+        .mock("GET", "/page")
+        .match_header("cookie", "theme=dark")
+        .with_status(200)
+        .with_body("ok")
+        .create_async()
+        .await;
+
+    let config = DownloadConfig {
+        cookie_file: Some(path),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/page", server.url());
+    downloader.head(&url).await.unwrap();
+    assert_eq!(downloader.download_to_memory(&url).await.unwrap(), "ok");
+
+    head.assert_async().await;
+    get.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_session_cookie_overrides_loaded_cookie() {
+    let mut server = mockito::Server::new_async().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cookies.txt");
+    std::fs::write(&path, "127.0.0.1\tFALSE\t/\tFALSE\t0\ttheme\tdark\n").unwrap();
+
+    server
+        .mock("GET", "/set")
+        .match_header("cookie", "theme=dark")
+        .with_status(200)
+        .with_header("set-cookie", "theme=light; Path=/")
+        .create_async()
+        .await;
+    let after = server
+        .mock("GET", "/after")
+        .match_header("cookie", "theme=light")
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let config = DownloadConfig {
+        cookie_file: Some(path),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    downloader
+        .download_to_memory(&format!("{}/set", server.url()))
+        .await
+        .unwrap();
+    downloader
+        .download_to_memory(&format!("{}/after", server.url()))
+        .await
+        .unwrap();
+
+    after.assert_async().await;
+}
@@ -1,11 +1,12 @@
 use mockito::Server;
-use std::time::Duration;
-use wget_faster_lib::{DownloadConfig, Downloader};
+use std::time::{Duration, Instant};
+use wget_faster_lib::{DownloadConfig, Downloader, Error};
 
 #[tokio::test]
 async fn test_network_timeout() {
     let mut config = DownloadConfig::default();
-    config.timeout = Duration::from_millis(100);
+    config.timeout = Some(Duration::from_millis(100));
+    config.retry.max_retries = 0;
 
     let downloader = Downloader::new(config).unwrap();
 
@@ -17,6 +18,32 @@ async fn test_network_timeout() {
     assert!(result.is_err());
 }
 
+#[tokio::test]
+async fn test_response_header_timeout() {
+    // Accepts connections (via the listen backlog) but never responds
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/silent", listener.local_addr().unwrap());
+
+    let mut config = DownloadConfig::default();
+    config.response_header_timeout = Some(Duration::from_millis(200));
+    config.retry.max_retries = 0;
+    let downloader = Downloader::new(config).unwrap();
+
+    let started = Instant::now();
+    let result = downloader.download_to_memory(&url).await;
+    assert!(
+        matches!(result.as_ref().map_err(Error::root), Err(Error::Timeout)),
+        "{result:?}"
+    );
+    assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+
+    let result = downloader.head(&url).await;
+    assert!(
+        matches!(result.as_ref().map_err(Error::root), Err(Error::Timeout)),
+        "{result:?}"
+    );
+}
+
 #[tokio::test]
 async fn test_invalid_url() {
     let config = DownloadConfig::default();
@@ -60,7 +87,8 @@ async fn test_500_internal_server_error() {
         .create_async()
         .await;
 
-    let config = DownloadConfig::default();
+    let mut config = DownloadConfig::default();
+    config.retry.max_retries = 0;
     let downloader = Downloader::new(config).unwrap();
 
     let url = format!("{}/error", server.url());
@@ -82,7 +110,8 @@ async fn test_503_service_unavailable() {
         .create_async()
         .await;
 
-    let config = DownloadConfig::default();
+    let mut config = DownloadConfig::default();
+    config.retry.max_retries = 0;
     let downloader = Downloader::new(config).unwrap();
 
     let url = format!("{}/unavailable", server.url());
@@ -152,10 +181,14 @@ async fn test_large_content_length_mismatch() {
         .with_status(200)
         .with_header("content-length", "1000000") // Claim 1MB
         .with_body(body) // But only send 10 bytes
+        // The server drops the connection before completing the response, so the
+        // GET is replayed once on a fresh connection
+        .expect(2)
         .create_async()
         .await;
 
-    let config = DownloadConfig::default();
+    let mut config = DownloadConfig::default();
+    config.retry.max_retries = 0;
     let downloader = Downloader::new(config).unwrap();
 
     let url = format!("{}/mismatch", server.url());
@@ -286,10 +319,8 @@ async fn test_partial_content_response_206() {
     let url = format!("{}/partial", server.url());
     let result = downloader.download_to_memory(&url).await;
 
-    // Should successfully handle 206 responses
-    assert!(result.is_ok());
-    let bytes = result.unwrap();
-    assert_eq!(bytes, partial_content);
+    // An unsolicited 206 must start at byte 0, otherwise the file would be truncated
+    assert!(matches!(result, Err(wget_faster_lib::Error::InvalidContentRange(_))));
 
     mock.assert_async().await;
 }
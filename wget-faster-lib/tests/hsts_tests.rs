@@ -0,0 +1,208 @@
+use std::sync::{Arc, Mutex};
+use wget_faster_lib::{DownloadConfig, Downloader};
+
+/// Requests a [`DualServer`] received, as `scheme path`
+type Seen = Arc<Mutex<Vec<String>>>;
+
+/// Server answering both https and plain http on one port of `localhost`
+///
+/// The https side uses the certificate in `fixtures/tls`, signed by
+/// `fixtures/tls/ca.pem`, and sends `Strict-Transport-Security` with every
+/// response. `/index.html` links to `/page.html` over plain http.
+struct DualServer {
+    port: u16,
+    seen: Seen,
+}
+
+impl DualServer {
+    async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = tls_acceptor();
+        let seen = Seen::default();
+        let requests = Arc::clone(&seen);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, acceptor.clone(), Arc::clone(&requests), port));
+            }
+        });
+        Self { port, seen }
+    }
+
+    fn url(&self, scheme: &str, path: &str) -> String {
+        format!("{scheme}://localhost:{}{path}", self.port)
+    }
+
+    fn seen(&self) -> Vec<String> {
+        self.seen.lock().unwrap().clone()
+    }
+}
+
+fn tls_acceptor() -> tokio_rustls::TlsAcceptor {
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    let certs = CertificateDer::pem_slice_iter(include_bytes!("fixtures/tls/localhost.pem"))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let key =
+        PrivateKeyDer::from_pem_slice(include_bytes!("fixtures/tls/localhost-key.pem")).unwrap();
+    let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+    let config = tokio_rustls::rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .unwrap();
+    tokio_rustls::TlsAcceptor::from(Arc::new(config))
+}
+
+/// Serve one connection, over TLS if it starts with a handshake record
+async fn serve(
+    stream: tokio::net::TcpStream,
+    acceptor: tokio_rustls::TlsAcceptor,
+    seen: Seen,
+    port: u16,
+) {
+    let mut first = [0u8; 1];
+    if stream.peek(&mut first).await.is_err() {
+        return;
+    }
+    let http = hyper::server::conn::http1::Builder::new();
+    if first[0] == 0x16 {
+        let Ok(stream) = acceptor.accept(stream).await else {
+            return;
+        };
+        let service = service("https", seen, port);
+        let _ = http
+            .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+            .await;
+    } else {
+        let service = service("http", seen, port);
+        let _ = http
+            .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+            .await;
+    }
+}
+
+fn service(
+    scheme: &'static str,
+    seen: Seen,
+    port: u16,
+) -> impl hyper::service::Service<
+    hyper::Request<hyper::body::Incoming>,
+    Response = hyper::Response<http_body_util::Full<bytes::Bytes>>,
+    Error = std::convert::Infallible,
+    Future = impl Send,
+> + Clone {
+    hyper::service::service_fn(move |request: hyper::Request<hyper::body::Incoming>| {
+        let path = request.uri().path().to_string();
+        seen.lock().unwrap().push(format!("{scheme} {path}"));
+        let body = if path == "/index.html" {
+            format!(
+                "<html><body><a href=\"http://localhost:{port}/page.html\">page</a></body></html>"
+            )
+        } else {
+            format!("contents of {path}")
+        };
+        let mut response = hyper::Response::builder().header(
+            "Content-Type",
+            if path == "/index.html" {
+                "text/html"
+            } else {
+                "text/plain"
+            },
+        );
+        if scheme == "https" {
+            response = response.header("Strict-Transport-Security", "max-age=3600");
+        }
+        let response = response
+            .body(http_body_util::Full::new(bytes::Bytes::from(body)))
+            .unwrap();
+        async move { Ok(response) }
+    })
+}
+
+fn config(hsts_file: Option<std::path::PathBuf>) -> DownloadConfig {
+    DownloadConfig {
+        ca_cert: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/ca.pem").into()),
+        hsts_file,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_sts_header_upgrades_later_plain_http_requests() {
+    let server = DualServer::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let hsts_file = dir.path().join("wget-hsts");
+
+    let downloader = Downloader::new(config(Some(hsts_file.clone()))).unwrap();
+    downloader
+        .download_to_memory(&server.url("https", "/first"))
+        .await
+        .unwrap();
+    let body = downloader
+        .download_to_memory(&server.url("http", "/second"))
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"contents of /second");
+    assert!(server.seen().iter().all(|r| r.starts_with("https ")), "{:?}", server.seen());
+    assert!(server.seen().contains(&"https /second".to_string()));
+
+    // The known host survives in the database for the next downloader
+    downloader.save_hsts().await.unwrap();
+    let database = std::fs::read_to_string(&hsts_file).unwrap();
+    assert!(database.contains(&format!("localhost\t{}\t0\t", server.port)), "{database}");
+    let downloader = Downloader::new(config(Some(hsts_file))).unwrap();
+    downloader
+        .download_to_memory(&server.url("http", "/third"))
+        .await
+        .unwrap();
+    assert!(server.seen().contains(&"https /third".to_string()));
+    assert!(!server.seen().iter().any(|r| r.starts_with("http ")));
+}
+
+#[tokio::test]
+async fn test_sts_header_ignored_without_hsts() {
+    let server = DualServer::start().await;
+    let downloader = Downloader::new(DownloadConfig {
+        enable_hsts: false,
+        ..config(None)
+    })
+    .unwrap();
+
+    downloader
+        .download_to_memory(&server.url("https", "/first"))
+        .await
+        .unwrap();
+    downloader
+        .download_to_memory(&server.url("http", "/second"))
+        .await
+        .unwrap();
+    assert!(server.seen().contains(&"http /second".to_string()));
+}
+
+#[cfg(feature = "recursive")]
+#[tokio::test]
+async fn test_recursive_crawl_upgrades_extracted_links() {
+    use wget_faster_lib::{RecursiveConfig, RecursiveDownloader};
+
+    let server = DualServer::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let mut downloader = RecursiveDownloader::new(
+        config(None),
+        RecursiveConfig {
+            max_depth: 2,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    downloader
+        .download_recursive(&server.url("https", "/index.html"), dir.path())
+        .await
+        .unwrap();
+    assert!(server.seen().contains(&"https /page.html".to_string()));
+    assert!(!server.seen().iter().any(|r| r.starts_with("http ")));
+}
@@ -1,8 +1,14 @@
-use mockito::Server;
+use mockito::{Matcher, Server};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use wget_faster_lib::test_server::{route, TestServer};
 use wget_faster_lib::{
-    AuthConfig, AuthType, DownloadConfig, Downloader, HttpClient, HttpMethod, ProgressInfo,
+    numbered_path, AddressFamily, AuthConfig, AuthType, BatchState, BatchStatus, CacheConfig,
+    CacheStats, CacheStatus, Checksum, ContentCoding, CredentialProvider, DownloadConfig,
+    DownloadEvent, DownloadOutcome, DownloadResult, Downloader, Error, EstimateOptions,
+    EstimateOutcome, EventCallback, HttpClient, HttpMethod, MirrorOptions, MirrorOutcome, Output,
+    ProgressCallback, ProgressInfo, ProgressSink, ProvenanceConfig, ProvenanceRecord, SizeCheck,
+    TimestampDecision,
 };
 
 #[tokio::test]
@@ -124,6 +130,190 @@ async fn test_redirect_following() {
     final_mock.assert_async().await;
 }
 
+#[tokio::test]
+async fn test_redirect_chain_reports_final_url() {
+    let mut server = Server::new_async().await;
+
+    for (from, to) in [
+        ("/download?id=7", "/mirror/latest"),
+        ("/mirror/latest", "/files/tool-1.2.tar.gz"),
+    ] {
+        server
+            .mock("HEAD", from)
+            .with_status(302)
+            .with_header("location", &format!("{}{to}", server.url()))
+            .create_async()
+            .await;
+        server
+            .mock("GET", from)
+            .with_status(302)
+            .with_header("location", &format!("{}{to}", server.url()))
+            .create_async()
+            .await;
+    }
+    server
+        .mock("HEAD", "/files/tool-1.2.tar.gz")
+        .with_status(200)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/files/tool-1.2.tar.gz")
+        .with_status(200)
+        .with_body("tarball")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let url = format!("{}/download?id=7", server.url());
+    let final_url = format!("{}/files/tool-1.2.tar.gz", server.url());
+
+    let metadata = downloader.get_client().get_metadata(&url).await.unwrap();
+    assert_eq!(metadata.final_url.as_deref(), Some(final_url.as_str()));
+
+    let dir = tempfile::tempdir().unwrap();
+    let result = downloader
+        .download_to_file(&url, dir.path().join("out"))
+        .await
+        .unwrap();
+    assert_eq!(result.url, url);
+    assert_eq!(result.metadata.final_url.as_deref(), Some(final_url.as_str()));
+}
+
+#[tokio::test]
+async fn test_zero_max_redirects_returns_the_redirect() {
+    let mut server = Server::new_async().await;
+    for method in ["HEAD", "GET"] {
+        server
+            .mock(method, "/old")
+            .with_status(301)
+            .with_header("location", "/new")
+            .with_body("moved")
+            .create_async()
+            .await;
+    }
+    let target = server.mock("GET", "/new").expect(0).create_async().await;
+
+    let config = DownloadConfig::builder()
+        .redirects(true, 0)
+        .build()
+        .unwrap();
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/old", server.url());
+    let location = format!("{}/new", server.url());
+
+    let metadata = downloader.get_client().get_metadata(&url).await.unwrap();
+    assert_eq!(metadata.status_code, 301);
+    assert_eq!(metadata.redirect_location(), Some(location.clone()));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out");
+    let result = downloader
+        .download_to_file(&url, path.clone())
+        .await
+        .unwrap();
+    assert_eq!(result.outcome(), DownloadOutcome::Redirect { location });
+    assert!(!path.exists());
+
+    assert!(downloader
+        .download_to_memory(&url)
+        .await
+        .unwrap()
+        .is_empty());
+    target.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_unfollowed_redirect_body_saved_with_content_on_error() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/old")
+        .with_status(302)
+        .with_header("location", "http://elsewhere.example/new")
+        .with_body("moved")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig {
+        max_redirects: 0,
+        content_on_error: true,
+        parallel_threshold: 0,
+        ..Default::default()
+    })
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out");
+    let result = downloader
+        .download_to_file(&format!("{}/old", server.url()), path.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        result.outcome(),
+        DownloadOutcome::Redirect {
+            location: "http://elsewhere.example/new".to_string()
+        }
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "moved");
+}
+
+#[tokio::test]
+async fn test_error_body_kept_in_memory_and_file_with_content_on_error() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/api/item")
+        .with_status(404)
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"error":"no such item"}"#)
+        .create_async()
+        .await;
+    // HEAD disagrees with GET: the status must come from the response with the body
+    server
+        .mock("HEAD", "/api/item")
+        .with_status(200)
+        .create_async()
+        .await;
+    let url = format!("{}/api/item", server.url());
+    let payload = br#"{"error":"no such item"}"#.as_slice();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("item.json");
+
+    let downloader = |content_on_error| {
+        Downloader::new(DownloadConfig {
+            content_on_error,
+            ..Default::default()
+        })
+        .unwrap()
+    };
+    let keeping = downloader(true);
+    let result = keeping.download_to_memory_result(&url).await.unwrap();
+    assert_eq!(result.metadata.status_code, 404);
+    assert_eq!(result.data.bytes().unwrap().as_ref(), payload);
+    let result = keeping.download(&url, Output::Memory, None).await.unwrap();
+    assert_eq!(result.metadata.status_code, 404);
+    assert_eq!(result.data.bytes().unwrap().as_ref(), payload);
+    assert_eq!(keeping.download_to_memory(&url).await.unwrap().as_ref(), payload);
+    let result = keeping
+        .download(&url, Output::File(path.clone()), None)
+        .await
+        .unwrap();
+    assert_eq!(result.metadata.status_code, 404);
+    assert_eq!(std::fs::read(&path).unwrap(), payload);
+
+    std::fs::remove_file(&path).unwrap();
+    let failing = downloader(false);
+    let not_found = |result: Result<_, Error>| matches!(result, Err(Error::InvalidStatus(404)));
+    assert!(not_found(failing.download_to_memory_result(&url).await.map(|_| ())));
+    assert!(not_found(
+        failing
+            .download(&url, Output::Memory, None)
+            .await
+            .map(|_| ())
+    ));
+    assert!(not_found(failing.download_to_memory(&url).await.map(|_| ())));
+    let to_file = failing.download(&url, Output::File(path.clone()), None);
+    assert!(not_found(to_file.await.map(|_| ())));
+    assert!(!path.exists());
+}
+
 #[tokio::test]
 async fn test_404_error() {
     let mut server = Server::new_async().await;
@@ -178,7 +368,7 @@ async fn test_custom_config() {
     let mut config = DownloadConfig::default();
 
     config.parallel_chunks = 16;
-    config.timeout = Duration::from_secs(60);
+    config.timeout = Some(Duration::from_secs(60));
     config.user_agent = "TestAgent/1.0".to_string();
     config.method = HttpMethod::Post;
     config.body_data = Some(b"test=data".to_vec());
@@ -272,7 +462,8 @@ fn test_config_defaults() {
     let config = DownloadConfig::default();
 
     assert_eq!(config.parallel_chunks, 8);
-    assert_eq!(config.timeout, Duration::from_secs(120));
+    assert_eq!(config.timeout, None);
+    assert_eq!(config.response_header_timeout, Some(Duration::from_secs(30)));
     assert_eq!(config.connect_timeout, Duration::from_secs(30));
     assert!(config.follow_redirects);
     assert_eq!(config.max_redirects, 20);
@@ -389,21 +580,11 @@ async fn test_download_to_file() {
 
 #[tokio::test]
 async fn test_range_request_support() {
-    let mut server = Server::new_async().await;
-
-    // HEAD request to check capabilities
-    let head_mock = server
-        .mock("HEAD", "/large-file.bin")
-        .with_status(200)
-        .with_header("accept-ranges", "bytes")
-        .with_header("content-length", "1000")
-        .create_async()
-        .await;
-
-    let config = DownloadConfig::default();
-    let downloader = Downloader::new(config).unwrap();
-
-    let url = format!("{}/large-file.bin", server.url());
+    let body: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+    let server = TestServer::start([route("/large-file.bin").body(body.clone()).ranges(true)])
+        .await
+        .unwrap();
+    let url = server.url_for("/large-file.bin");
 
     // Check if server supports range
     let client = HttpClient::new(DownloadConfig::default()).unwrap();
@@ -411,8 +592,31 @@ async fn test_range_request_support() {
 
     assert!(supports_range.is_ok());
     assert!(supports_range.unwrap());
+    assert_eq!(server.hits(&hyper::Method::HEAD, "/large-file.bin"), 1);
 
-    head_mock.assert_async().await;
+    // And a parallel download assembles the ranges in order
+    let config = DownloadConfig {
+        parallel_chunks: 4,
+        parallel_threshold: 1,
+        chunk_size: Some(250),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large-file.bin");
+    downloader
+        .download_to_file(&url, path.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), body);
+    let ranges: Vec<_> = server
+        .requests()
+        .into_iter()
+        .filter_map(|r| r.headers.get("range").cloned())
+        .collect();
+    assert_eq!(ranges.len(), 4);
+    server.shutdown().await;
 }
 
 #[tokio::test]
@@ -452,7 +656,8 @@ async fn test_500_server_error() {
         .create_async()
         .await;
 
-    let config = DownloadConfig::default();
+    let mut config = DownloadConfig::default();
+    config.retry.max_retries = 0;
     let downloader = Downloader::new(config).unwrap();
 
     let url = format!("{}/server-error", server.url());
@@ -463,6 +668,51 @@ async fn test_500_server_error() {
     mock.assert_async().await;
 }
 
+#[cfg(unix)]
+#[tokio::test]
+async fn test_file_mode_applied_to_downloaded_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut server = Server::new_async().await;
+    let files = [
+        ("notes.txt", "text/plain", "notes"),
+        ("install.sh", "application/x-sh; charset=utf-8", "#!/bin/sh\n"),
+    ];
+    let mut mocks = Vec::new();
+    for (name, content_type, body) in files {
+        for method in ["HEAD", "GET"] {
+            let mock = server
+                .mock(method, format!("/{name}").as_str())
+                .with_status(200)
+                .with_header("content-type", content_type)
+                .with_body(body)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+    }
+    let downloader = Downloader::new(DownloadConfig {
+        file_mode: Some(0o640),
+        executable_if_content_type: vec!["application/x-sh".to_string()],
+        ..Default::default()
+    })
+    .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let mode = |name: &str| {
+        let metadata = std::fs::metadata(dir.path().join(name)).unwrap();
+        metadata.permissions().mode() & 0o777
+    };
+    for (name, _, _) in files {
+        downloader
+            .download_to_file(&format!("{}/{name}", server.url()), dir.path().join(name))
+            .await
+            .unwrap();
+    }
+    assert_eq!(mode("notes.txt"), 0o640);
+    assert_eq!(mode("install.sh"), 0o750);
+}
+
 #[tokio::test]
 async fn test_server_response_display() {
     let mut server = Server::new_async().await;
@@ -721,3 +971,3256 @@ async fn test_if_modified_since_with_newer_remote() {
     head_mock.assert_async().await;
     get_mock.assert_async().await;
 }
+
+/// Download `remote` over a local file holding `local` with the same mtime as Last-Modified
+async fn timestamped_redownload(
+    local: &[u8],
+    remote: &[u8],
+    size_check: SizeCheck,
+) -> (Option<TimestampDecision>, Vec<u8>) {
+    use std::time::SystemTime;
+
+    let mut server = Server::new_async().await;
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_483_228_800); // Jan 1, 2017
+    let http_date = httpdate::fmt_http_date(time);
+
+    // Compressed, chunked response: no Content-Length for the decoded body
+    let body = remote.to_vec();
+    let get_mock = server
+        .mock("GET", "/data.json")
+        .with_status(200)
+        .with_header("Last-Modified", &http_date)
+        .with_header("Content-Encoding", "gzip")
+        .with_chunked_body(move |w| w.write_all(&body))
+        .create_async()
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("data.json");
+    std::fs::write(&file_path, local).unwrap();
+    filetime::set_file_mtime(&file_path, filetime::FileTime::from_system_time(time)).unwrap();
+
+    let config = DownloadConfig {
+        timestamping: true,
+        timestamping_size_check: size_check,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/data.json", server.url());
+    let result = downloader
+        .download_to_file(&url, file_path.clone())
+        .await
+        .unwrap();
+
+    get_mock.assert_async().await;
+    (result.timestamp_decision, std::fs::read(&file_path).unwrap())
+}
+
+#[tokio::test]
+async fn test_timestamping_compressed_identical_content_is_kept() {
+    let content = b"\x1f\x8b compressed payload";
+    let (decision, on_disk) = timestamped_redownload(content, content, SizeCheck::Enabled).await;
+
+    assert_eq!(decision, Some(TimestampDecision::Unchanged));
+    assert_eq!(on_disk, content);
+}
+
+/// Download a gzip-encoded page to a file, returning what was saved and the last progress report
+async fn download_gzip_page(decompress: bool) -> (Vec<u8>, Vec<u8>, ProgressInfo, DownloadResult) {
+    use std::io::Write;
+
+    let page = b"<html><body>compressed page</body></html>".repeat(20);
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&page).unwrap();
+    let gzip = encoder.finish().unwrap();
+
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("GET", "/page.html")
+        .with_status(200)
+        .with_header("Content-Encoding", "gzip")
+        .with_header("Content-Type", "text/html")
+        .with_body(&gzip)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let config = DownloadConfig {
+        decompress,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("page.html");
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&reports);
+    let result = downloader
+        .download_to_file_with_progress(
+            &format!("{}/page.html", server.url()),
+            path.clone(),
+            Some(Arc::new(move |p| recorded.lock().unwrap().push(p))),
+        )
+        .await
+        .unwrap();
+    mock.assert_async().await;
+
+    let last = reports.lock().unwrap().last().cloned().unwrap();
+    let expected = if decompress { page } else { gzip };
+    (std::fs::read(&path).unwrap(), expected, last, result)
+}
+
+#[tokio::test]
+async fn test_gzip_body_saved_as_received_by_default() {
+    let (saved, gzip, progress, result) = download_gzip_page(false).await;
+
+    assert_eq!(saved, gzip);
+    assert_eq!(result.metadata.content_encoding.as_deref(), Some("gzip"));
+    // The Content-Length counts the bytes saved
+    assert_eq!(progress.total_size, Some(gzip.len() as u64));
+    assert_eq!(progress.downloaded, gzip.len() as u64);
+    assert_eq!(result.metadata.saved_length(false), Some(gzip.len() as u64));
+}
+
+#[tokio::test]
+async fn test_gzip_body_decoded_with_decompress() {
+    let (saved, page, progress, result) = download_gzip_page(true).await;
+
+    assert_eq!(saved, page);
+    assert_eq!(result.data.total_bytes, page.len() as u64);
+    // The Content-Length is the compressed size, so there is no total to report
+    assert_eq!(progress.total_size, None);
+    assert_eq!(progress.downloaded, page.len() as u64);
+    assert_eq!(result.metadata.saved_length(true), None);
+}
+
+/// `Accept-Encoding` sent by default: every coding this build decodes
+fn default_accept_encoding() -> String {
+    let tokens: Vec<_> = ContentCoding::all()
+        .into_iter()
+        .map(ContentCoding::token)
+        .collect();
+    tokens.join(", ")
+}
+
+const CODED_PAGE: &[u8] = include_bytes!("fixtures/coding/page.html");
+
+/// Download `/page.html`, served as the pre-compressed `body` with `Content-Encoding: coding`
+/// to a client accepting `accepted`, into memory with `decompress`
+async fn download_coded_page(coding: &str, body: &[u8], accepted: Vec<ContentCoding>) -> Vec<u8> {
+    let header: Vec<_> = accepted.iter().map(|c| c.token()).collect();
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("GET", "/page.html")
+        .match_header("accept-encoding", header.join(", ").as_str())
+        .with_header("content-encoding", coding)
+        .with_body(body)
+        .create_async()
+        .await;
+
+    let config = DownloadConfig {
+        decompress: true,
+        accepted_encodings: accepted,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let bytes = downloader
+        .download_to_memory(&format!("{}/page.html", server.url()))
+        .await
+        .unwrap();
+    mock.assert_async().await;
+    bytes.to_vec()
+}
+
+#[tokio::test]
+async fn test_brotli_body_decoded() {
+    let body = include_bytes!("fixtures/coding/page.html.br");
+    let saved = download_coded_page("br", body, vec![ContentCoding::Brotli]).await;
+
+    assert_eq!(saved, CODED_PAGE);
+}
+
+#[cfg(feature = "zstd")]
+#[tokio::test]
+async fn test_zstd_body_decoded() {
+    let body = include_bytes!("fixtures/coding/page.html.zst");
+    let accepted = vec![ContentCoding::Zstd, ContentCoding::Gzip];
+    let saved = download_coded_page("zstd", body, accepted).await;
+
+    assert_eq!(saved, CODED_PAGE);
+}
+
+#[tokio::test]
+async fn test_accept_encoding_per_setting() {
+    let server = TestServer::start([route("/page.html").body("page")])
+        .await
+        .unwrap();
+    let sent = |config: DownloadConfig| {
+        let server = &server;
+        async move {
+            let downloader = Downloader::new(config).unwrap();
+            downloader
+                .download_to_memory(&server.url_for("/page.html"))
+                .await
+                .unwrap();
+            let request = server.requests().pop().unwrap();
+            request.headers["accept-encoding"]
+                .to_str()
+                .unwrap()
+                .to_string()
+        }
+    };
+    let accepting = |codings: Vec<ContentCoding>| DownloadConfig {
+        accepted_encodings: codings,
+        ..DownloadConfig::default()
+    };
+
+    assert_eq!(sent(DownloadConfig::default()).await, default_accept_encoding());
+    assert_eq!(sent(accepting(vec![ContentCoding::Gzip])).await, "gzip");
+    let preferred = vec![
+        ContentCoding::Brotli,
+        ContentCoding::Gzip,
+        ContentCoding::Brotli,
+    ];
+    assert_eq!(sent(accepting(preferred)).await, "br, gzip");
+    assert_eq!(sent(accepting(Vec::new())).await, "identity");
+    let uncompressed = DownloadConfig {
+        enable_compression: false,
+        ..DownloadConfig::default()
+    };
+    assert_eq!(sent(uncompressed).await, "identity");
+}
+
+/// Revalidate a local file saved with the `ETag` `"v1"` against a server answering `status`
+async fn etag_revalidation(
+    status: usize,
+    etag: &str,
+    body: &str,
+) -> (Option<TimestampDecision>, String, String) {
+    use std::time::SystemTime;
+
+    let mut server = Server::new_async().await;
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_483_228_800); // Jan 1, 2017
+    let http_date = httpdate::fmt_http_date(time);
+    let get_mock = server
+        .mock("GET", "/page.html")
+        .match_header("If-None-Match", "\"v1\"")
+        .match_header("If-Modified-Since", http_date.as_str())
+        .with_status(status)
+        .with_header("Last-Modified", &http_date)
+        .with_header("ETag", etag)
+        .with_body(body)
+        .create_async()
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("page.html");
+    let sidecar = temp_dir.path().join("page.html.etag");
+    std::fs::write(&file_path, "old page").unwrap();
+    std::fs::write(&sidecar, "\"v1\"\n").unwrap();
+    filetime::set_file_mtime(&file_path, filetime::FileTime::from_system_time(time)).unwrap();
+
+    let config = DownloadConfig {
+        timestamping: true,
+        use_etag: true,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/page.html", server.url());
+    let result = downloader
+        .download_to_file(&url, file_path.clone())
+        .await
+        .unwrap();
+
+    get_mock.assert_async().await;
+    (
+        result.timestamp_decision,
+        std::fs::read_to_string(&file_path).unwrap(),
+        std::fs::read_to_string(&sidecar).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_etag_not_modified_keeps_file() {
+    let (decision, on_disk, etag) = etag_revalidation(304, "\"v1\"", "").await;
+
+    assert_eq!(decision, Some(TimestampDecision::NotModified));
+    assert_eq!(on_disk, "old page");
+    assert_eq!(etag, "\"v1\"\n");
+}
+
+#[tokio::test]
+async fn test_etag_changed_replaces_file_with_same_timestamp() {
+    // Same Last-Modified and size: only the ETag tells the content changed
+    let (decision, on_disk, etag) = etag_revalidation(200, "\"v2\"", "new page").await;
+
+    assert_eq!(decision, Some(TimestampDecision::EtagChanged));
+    assert_eq!(on_disk, "new page");
+    assert_eq!(etag, "\"v2\"\n");
+}
+
+/// What `-N -c` did with a local copy of `local` whose time is `local_offset`
+/// seconds from the `Last-Modified` of a 100-byte remote file
+///
+/// Also returns whether the download was resumed with a Range request and
+/// whether it was restarted with a conditional GET.
+async fn timestamped_continue(
+    local: &[u8],
+    local_offset: i64,
+) -> (Option<TimestampDecision>, Vec<u8>, bool, bool) {
+    use std::time::SystemTime;
+
+    let remote = b"0123456789".repeat(10);
+    let remote_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_483_228_800); // Jan 1, 2017
+    let local_time = SystemTime::UNIX_EPOCH
+        + Duration::from_secs(1_483_228_800_u64.saturating_add_signed(local_offset));
+    let http_date = httpdate::fmt_http_date(remote_time);
+
+    let mut server = Server::new_async().await;
+    let _head_mock = server
+        .mock("HEAD", "/file.bin")
+        .with_status(200)
+        .with_header("content-length", "100")
+        .with_header("Last-Modified", &http_date)
+        .create_async()
+        .await;
+    let resume_mock = server
+        .mock("GET", "/file.bin")
+        .match_header("range", format!("bytes={}-", local.len()).as_str())
+        .match_header("if-modified-since", Matcher::Missing)
+        .with_status(206)
+        .with_header("content-range", &format!("bytes {}-99/100", local.len()))
+        .with_header("Last-Modified", &http_date)
+        .with_body(&remote[local.len().min(remote.len())..])
+        .create_async()
+        .await;
+    let restart_mock = server
+        .mock("GET", "/file.bin")
+        .match_header("range", Matcher::Missing)
+        .match_header("if-modified-since", httpdate::fmt_http_date(local_time).as_str())
+        .with_status(200)
+        .with_header("Last-Modified", &http_date)
+        .with_body(&remote)
+        .create_async()
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("file.bin");
+    std::fs::write(&file_path, local).unwrap();
+    filetime::set_file_mtime(&file_path, filetime::FileTime::from_system_time(local_time)).unwrap();
+
+    let config = DownloadConfig {
+        timestamping: true,
+        timestamping_continue: true,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/file.bin", server.url());
+    let result = downloader
+        .download_to_file(&url, file_path.clone())
+        .await
+        .unwrap();
+
+    (
+        result.timestamp_decision,
+        std::fs::read(&file_path).unwrap(),
+        resume_mock.matched_async().await,
+        restart_mock.matched_async().await,
+    )
+}
+
+#[tokio::test]
+async fn test_timestamped_continue_restarts_when_remote_newer() {
+    let remote = b"0123456789".repeat(10);
+    for local in [&b"old partial"[..], &[b'x'; 100][..]] {
+        let (decision, on_disk, resumed, restarted) = timestamped_continue(local, -86_400).await;
+
+        assert_eq!(decision, Some(TimestampDecision::RemoteNewer));
+        assert_eq!(on_disk, remote);
+        assert!(!resumed && restarted);
+    }
+}
+
+#[tokio::test]
+async fn test_timestamped_continue_resumes_partial_file() {
+    let remote = b"0123456789".repeat(10);
+    // The remote file is as old as the partial file, or older
+    for local_offset in [0, 86_400] {
+        let (decision, on_disk, resumed, restarted) =
+            timestamped_continue(&remote[..40], local_offset).await;
+
+        assert_eq!(decision, Some(TimestampDecision::Resumed { from: 40 }));
+        assert_eq!(on_disk, remote);
+        assert!(resumed && !restarted);
+    }
+}
+
+#[tokio::test]
+async fn test_timestamped_continue_skips_complete_file() {
+    let remote = b"0123456789".repeat(10);
+    for local_offset in [0, 86_400] {
+        let (decision, on_disk, resumed, restarted) =
+            timestamped_continue(&remote, local_offset).await;
+
+        assert_eq!(decision, Some(TimestampDecision::AlreadyComplete));
+        assert_eq!(on_disk, remote);
+        assert!(!resumed && !restarted);
+    }
+}
+
+#[tokio::test]
+async fn test_timestamping_compressed_changed_content_is_replaced() {
+    let (decision, on_disk) =
+        timestamped_redownload(b"old payload", b"new, longer payload", SizeCheck::Enabled).await;
+
+    assert_eq!(
+        decision,
+        Some(TimestampDecision::SizeChanged {
+            local_size: 11,
+            remote_size: 19
+        })
+    );
+    assert_eq!(on_disk, b"new, longer payload");
+
+    // Without a Content-Length to compare, ContentLengthOnly keeps the local file
+    let (decision, on_disk) = timestamped_redownload(
+        b"old payload",
+        b"new, longer payload",
+        SizeCheck::ContentLengthOnly,
+    )
+    .await;
+    assert_eq!(decision, Some(TimestampDecision::Unchanged));
+    assert_eq!(on_disk, b"old payload");
+}
+
+#[tokio::test]
+async fn test_head_zero_length_does_not_delete_get_body() {
+    let mut server = Server::new_async().await;
+    let body = vec![b'x'; 10 * 1024];
+
+    // CDN-style HEAD that reports an empty body
+    let head_mock = server
+        .mock("HEAD", "/cdn-file")
+        .with_status(200)
+        .with_header("content-length", "0")
+        .create_async()
+        .await;
+
+    let get_mock = server
+        .mock("GET", "/cdn-file")
+        .with_status(200)
+        .with_body(&body)
+        .create_async()
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("cdn-file");
+
+    let totals = Arc::new(Mutex::new(Vec::new()));
+    let totals_clone = totals.clone();
+    let callback = Arc::new(move |progress: ProgressInfo| {
+        totals_clone.lock().unwrap().push(progress.total_size);
+    });
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let url = format!("{}/cdn-file", server.url());
+    let result = downloader
+        .download_to_file_with_progress(&url, file_path.clone(), Some(callback))
+        .await
+        .unwrap();
+
+    head_mock.assert_async().await;
+    get_mock.assert_async().await;
+
+    // File survives with the GET body and the result reflects the GET response
+    assert_eq!(std::fs::metadata(&file_path).unwrap().len(), body.len() as u64);
+    assert_eq!(result.data.total_bytes, body.len() as u64);
+    assert_eq!(result.metadata.content_length, Some(body.len() as u64));
+
+    // Progress total comes from the GET headers
+    let totals = totals.lock().unwrap();
+    assert!(!totals.is_empty());
+    assert_eq!(totals.last().copied().flatten(), Some(body.len() as u64));
+}
+
+#[tokio::test]
+async fn test_write_provenance_sidecar() {
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("GET", "/release.tar")
+        .with_status(200)
+        .with_header("content-type", "application/x-tar")
+        .with_header("etag", "\"v1\"")
+        .with_body("hello")
+        .create_async()
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("release.tar");
+
+    let config = DownloadConfig {
+        write_provenance: Some(ProvenanceConfig::sidecar()),
+        ..Default::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/release.tar", server.url()).replace("http://", "http://user:pass@");
+    downloader
+        .download_to_file(&url, file_path.clone())
+        .await
+        .unwrap();
+
+    mock.assert_async().await;
+
+    let sidecar = temp_dir.path().join("release.tar.provenance.json");
+    let text = std::fs::read_to_string(sidecar).unwrap();
+    assert!(!text.contains("pass"));
+
+    let record: ProvenanceRecord = serde_json::from_str(&text).unwrap();
+    assert_eq!(record.url, format!("{}/release.tar", server.url()));
+    assert_eq!(record.status, 200);
+    assert_eq!(record.size, 5);
+    assert_eq!(
+        record.sha256,
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+    assert_eq!(record.headers.get("etag").map(String::as_str), Some("\"v1\""));
+}
+
+/// Download from a mock answering GET with `status` and a 5-byte body
+async fn download_with_status(status: usize, headers: &[(&str, &str)]) -> Vec<u8> {
+    let mut server = Server::new_async().await;
+    let mut mock = server
+        .mock("GET", "/file")
+        .with_status(status)
+        .with_body("hello");
+    for (name, value) in headers {
+        mock = mock.with_header(*name, value);
+    }
+    let mock = mock.create_async().await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("file");
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let result = downloader
+        .download_to_file(&format!("{}/file", server.url()), file_path.clone())
+        .await
+        .unwrap();
+
+    mock.assert_async().await;
+    assert_eq!(result.metadata.status_code, status as u16);
+    assert_eq!(result.data.total_bytes, 5);
+    std::fs::read(&file_path).unwrap()
+}
+
+#[tokio::test]
+async fn test_non_authoritative_203_is_success() {
+    let content = download_with_status(203, &[]).await;
+    assert_eq!(content, b"hello");
+}
+
+#[tokio::test]
+async fn test_already_reported_208_and_im_used_226_pass_through() {
+    for status in [208, 226] {
+        let content = download_with_status(status, &[]).await;
+        assert_eq!(content, b"hello");
+    }
+}
+
+#[tokio::test]
+async fn test_unsolicited_full_range_206_is_accepted() {
+    let content = download_with_status(206, &[("content-range", "bytes 0-4/5")]).await;
+    assert_eq!(content, b"hello");
+}
+
+#[tokio::test]
+async fn test_unsolicited_206_not_starting_at_zero_fails() {
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("GET", "/file")
+        .with_status(206)
+        .with_header("content-range", "bytes 5-9/10")
+        .with_body("world")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let result = downloader
+        .download_to_memory(&format!("{}/file", server.url()))
+        .await;
+
+    assert!(matches!(result, Err(wget_faster_lib::Error::InvalidContentRange(_))));
+    mock.assert_async().await;
+}
+
+/// Download `/saved.bin` (`body`, parallel in 10-byte chunks with `parallel`)
+/// with `save_headers`, returning the file and the response metadata
+async fn download_with_saved_headers(
+    server: &TestServer,
+    parallel: bool,
+) -> (Vec<u8>, wget_faster_lib::ResourceMetadata) {
+    let config = DownloadConfig {
+        save_headers: true,
+        parallel_chunks: if parallel { 3 } else { 1 },
+        parallel_threshold: u64::from(parallel),
+        chunk_size: Some(10),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("saved.bin");
+    let result = downloader
+        .download_to_file(&server.url_for("/saved.bin"), path.clone())
+        .await
+        .unwrap();
+    (std::fs::read(&path).unwrap(), result.metadata)
+}
+
+#[tokio::test]
+async fn test_saved_headers_round_trip() {
+    let body = b"HTTP/1.1 200 OK\r\n\r\nnot a preamble".to_vec();
+    let server = TestServer::start([route("/saved.bin")
+        .body(body.clone())
+        .ranges(true)
+        .header("x-build", "42")])
+    .await
+    .unwrap();
+
+    for parallel in [false, true] {
+        let (file, metadata) = download_with_saved_headers(&server, parallel).await;
+        let (saved, body_start) = wget_faster_lib::saved_headers::split(&file);
+        let saved = saved.unwrap();
+        assert_eq!(saved.status, 200);
+        assert_eq!(saved.reason, "OK");
+        assert_eq!(saved.headers["x-build"], "42");
+        assert_eq!(saved.headers, metadata.headers);
+        assert_eq!(&file[body_start..], body, "parallel: {parallel}");
+    }
+    // The saved headers are the GET's, so the body is never fetched as ranges
+    assert!(requested_ranges(&server).is_empty());
+}
+
+#[tokio::test]
+async fn test_save_headers_counted_in_file_and_memory_downloads() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/page.txt")
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .with_body("hello")
+        .expect(2)
+        .create_async()
+        .await;
+    let url = format!("{}/page.txt", server.url());
+    let downloader = Downloader::new(DownloadConfig {
+        save_headers: true,
+        ..DownloadConfig::default()
+    })
+    .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("page.txt");
+    let result = downloader
+        .download_to_file(&url, path.clone())
+        .await
+        .unwrap();
+    let file = std::fs::read(&path).unwrap();
+    let text = String::from_utf8_lossy(&file);
+    assert!(text.starts_with("HTTP/1.1 200 OK\r\n"), "{text}");
+    assert!(
+        text.to_ascii_lowercase()
+            .contains("\r\ncontent-type: text/plain\r\n"),
+        "{text}"
+    );
+    assert!(text.ends_with("\r\n\r\nhello"), "{text}");
+    assert_eq!(result.data.total_bytes, file.len() as u64);
+
+    let last_progress = Arc::new(Mutex::new(None));
+    let recorded = Arc::clone(&last_progress);
+    let callback: ProgressCallback =
+        Arc::new(move |info: ProgressInfo| *recorded.lock().unwrap() = Some(info.downloaded));
+    let bytes = downloader
+        .download_to_memory_with_progress(&url, Some(callback))
+        .await
+        .unwrap();
+    assert_eq!(bytes, file);
+    assert_eq!(*last_progress.lock().unwrap(), Some(file.len() as u64));
+}
+
+#[tokio::test]
+async fn test_saved_headers_file_resumes_after_preamble() {
+    let body: Vec<u8> = (0..100u8).collect();
+    let server = TestServer::start([route("/saved.bin").body(body.clone()).ranges(true)])
+        .await
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("saved.bin");
+    let preamble = b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n";
+    std::fs::write(&path, [&preamble[..], &body[..40]].concat()).unwrap();
+
+    let config = DownloadConfig {
+        save_headers: true,
+        ..DownloadConfig::default()
+    };
+    Downloader::new(config)
+        .unwrap()
+        .download_to_file(&server.url_for("/saved.bin"), path.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), [&preamble[..], &body[..]].concat());
+    assert_eq!(requested_ranges(&server), ["bytes=40-"]);
+}
+
+#[tokio::test]
+async fn test_accepted_202_retried_when_enabled() {
+    let mut server = Server::new_async().await;
+
+    // Mocks with outstanding expectations are matched first
+    let pending = server
+        .mock("GET", "/artifact")
+        .with_status(202)
+        .with_header("retry-after", "0")
+        .with_body("building")
+        .expect(2)
+        .create_async()
+        .await;
+    let ready = server
+        .mock("GET", "/artifact")
+        .with_status(200)
+        .with_body("artifact")
+        .create_async()
+        .await;
+
+    let mut config = DownloadConfig::default();
+    config.retry_on_202 = true;
+    let downloader = Downloader::new(config).unwrap();
+    let bytes = downloader
+        .download_to_memory(&format!("{}/artifact", server.url()))
+        .await
+        .unwrap();
+
+    assert_eq!(bytes, "artifact");
+    pending.assert_async().await;
+    ready.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_accepted_202_saved_when_retry_disabled() {
+    let content = download_with_status(202, &[]).await;
+    assert_eq!(content, b"hello");
+}
+
+/// Download a chunked body without Content-Length with `probe_total_size` on,
+/// returning the `total_size` seen by each progress callback
+async fn probed_progress_totals(accept_ranges: bool) -> Vec<Option<u64>> {
+    let mut server = Server::new_async().await;
+    let body_len = 4 * 1024;
+
+    let probe_mock = server
+        .mock("GET", "/export.csv")
+        .match_header("range", "bytes=0-0")
+        .with_status(206)
+        .with_header("content-range", &format!("bytes 0-0/{body_len}"))
+        .with_body("x")
+        .expect(usize::from(accept_ranges))
+        .create_async()
+        .await;
+
+    let mut get_mock = server
+        .mock("GET", "/export.csv")
+        .match_header("range", Matcher::Missing)
+        .with_status(200);
+    if accept_ranges {
+        get_mock = get_mock.with_header("accept-ranges", "bytes");
+    }
+    let get_mock = get_mock
+        .with_chunked_body(|w| {
+            // Trickle the body so the probe answers mid-transfer
+            for _ in 0..4 {
+                w.write_all(&[b'x'; 1024])?;
+                w.flush()?;
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Ok(())
+        })
+        .create_async()
+        .await;
+
+    let totals = Arc::new(Mutex::new(Vec::new()));
+    let totals_clone = totals.clone();
+    let callback = Arc::new(move |progress: ProgressInfo| {
+        totals_clone.lock().unwrap().push(progress.total_size);
+    });
+
+    let config = DownloadConfig {
+        probe_total_size: true,
+        parallel_chunks: 1,
+        ..Default::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("export.csv");
+    let url = format!("{}/export.csv", server.url());
+    let result = downloader
+        .download_to_file_with_progress(&url, file_path.clone(), Some(callback))
+        .await
+        .unwrap();
+
+    probe_mock.assert_async().await;
+    get_mock.assert_async().await;
+    assert_eq!(result.data.total_bytes, body_len);
+    assert_eq!(std::fs::metadata(&file_path).unwrap().len(), body_len);
+
+    let totals = totals.lock().unwrap();
+    totals.clone()
+}
+
+#[tokio::test]
+async fn test_probe_total_size_with_range_support() {
+    let totals = probed_progress_totals(true).await;
+
+    // The total appears once the probe answers and stays for the rest of the transfer
+    assert_eq!(totals.last().copied().flatten(), Some(4 * 1024));
+}
+
+#[tokio::test]
+async fn test_probe_total_size_without_range_support() {
+    let totals = probed_progress_totals(false).await;
+
+    // No Accept-Ranges: no probe, progress stays without a total (spinner)
+    assert!(!totals.is_empty());
+    assert!(totals.iter().all(Option::is_none));
+}
+
+#[tokio::test]
+async fn test_head_returns_typed_headers() {
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("HEAD", "/doc")
+        .with_status(200)
+        .with_header("allow", "GET, HEAD, OPTIONS")
+        .with_header("accept-ranges", "bytes")
+        .with_header("cache-control", "public, max-age=600, no-cache")
+        .with_header("age", "42")
+        .with_header("content-language", "de-DE, en")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let metadata = downloader
+        .head(&format!("{}/doc", server.url()))
+        .await
+        .unwrap();
+
+    mock.assert_async().await;
+    assert_eq!(
+        metadata.allow,
+        Some(vec![
+            reqwest::Method::GET,
+            reqwest::Method::HEAD,
+            reqwest::Method::OPTIONS
+        ])
+    );
+    assert_eq!(metadata.accept_ranges_unit.as_deref(), Some("bytes"));
+    let cache_control = metadata.cache_control.unwrap();
+    assert_eq!(cache_control.max_age, Some(Duration::from_mins(10)));
+    assert!(cache_control.no_cache);
+    assert!(!cache_control.no_store);
+    assert_eq!(metadata.age, Some(Duration::from_secs(42)));
+    assert_eq!(metadata.content_language, Some(vec!["de-DE".to_string(), "en".to_string()]));
+}
+
+#[tokio::test]
+async fn test_head_malformed_typed_headers_are_none() {
+    let mut server = Server::new_async().await;
+    let _mock = server
+        .mock("HEAD", "/doc")
+        .with_status(200)
+        .with_header("age", "soon")
+        .with_header("accept-ranges", "")
+        .with_header("content-language", "not a tag")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let metadata = downloader
+        .head(&format!("{}/doc", server.url()))
+        .await
+        .unwrap();
+
+    assert_eq!(metadata.status_code, 200);
+    assert_eq!(metadata.allow, None);
+    assert_eq!(metadata.accept_ranges_unit, None);
+    assert_eq!(metadata.cache_control, None);
+    assert_eq!(metadata.age, None);
+    assert_eq!(metadata.content_language, None);
+}
+
+#[tokio::test]
+async fn test_head_falls_back_to_ranged_get() {
+    let mut server = Server::new_async().await;
+    let head_mock = server
+        .mock("HEAD", "/file")
+        .with_status(405)
+        .with_header("allow", "GET")
+        .create_async()
+        .await;
+    let get_mock = server
+        .mock("GET", "/file")
+        .match_header("range", "bytes=0-0")
+        .with_status(206)
+        .with_header("content-range", "bytes 0-0/1234")
+        .with_header("content-language", "fr")
+        .with_body("x")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let metadata = downloader
+        .head(&format!("{}/file", server.url()))
+        .await
+        .unwrap();
+
+    head_mock.assert_async().await;
+    get_mock.assert_async().await;
+    assert_eq!(metadata.status_code, 200);
+    assert_eq!(metadata.content_length, Some(1234));
+    assert!(metadata.supports_range);
+    assert_eq!(metadata.content_language, Some(vec!["fr".to_string()]));
+}
+
+#[tokio::test]
+async fn test_parallel_download_when_head_rejected() {
+    let mut server = Server::new_async().await;
+    let head_mock = server
+        .mock("HEAD", "/chunked.bin")
+        .with_status(405)
+        .expect(2)
+        .create_async()
+        .await;
+    let probe_mock = server
+        .mock("GET", "/chunked.bin")
+        .match_header("range", "bytes=0-0")
+        .with_status(206)
+        .with_header("content-range", "bytes 0-0/30")
+        .with_header("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT")
+        .with_header("etag", "\"v1\"")
+        .with_body("a")
+        .expect(1)
+        .create_async()
+        .await;
+    let mut chunk_mocks = Vec::new();
+    for (start, end, byte) in [(0, 9, b'a'), (10, 19, b'b'), (20, 29, b'c')] {
+        chunk_mocks.push(
+            server
+                .mock("GET", "/chunked.bin")
+                .match_header("range", format!("bytes={start}-{end}").as_str())
+                .with_status(206)
+                .with_header("content-range", &format!("bytes {start}-{end}/30"))
+                .with_body([byte; 10])
+                .expect(1)
+                .create_async()
+                .await,
+        );
+    }
+
+    let config = DownloadConfig {
+        parallel_chunks: 3,
+        parallel_threshold: 1,
+        chunk_size: Some(10),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/chunked.bin", server.url());
+    let metadata = downloader.get_client().get_metadata(&url).await.unwrap();
+    assert_eq!(metadata.status_code, 200);
+    assert_eq!(metadata.content_length, Some(30));
+    assert!(metadata.supports_range);
+    assert_eq!(metadata.last_modified.as_deref(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+    assert_eq!(metadata.etag.as_deref(), Some("\"v1\""));
+
+    probe_mock.remove_async().await;
+    let probe_mock = server
+        .mock("GET", "/chunked.bin")
+        .match_header("range", "bytes=0-0")
+        .with_status(206)
+        .with_header("content-range", "bytes 0-0/30")
+        .with_body("a")
+        .expect(1)
+        .create_async()
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chunked.bin");
+    downloader
+        .download_to_file(&url, path.clone())
+        .await
+        .unwrap();
+
+    head_mock.assert_async().await;
+    probe_mock.assert_async().await;
+    for mock in chunk_mocks {
+        mock.assert_async().await;
+    }
+    assert_eq!(std::fs::read(&path).unwrap(), [[b'a'; 10], [b'b'; 10], [b'c'; 10]].concat());
+}
+
+#[tokio::test]
+async fn test_metadata_probe_when_range_ignored() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("HEAD", "/big.iso")
+        .with_status(403)
+        .create_async()
+        .await;
+    // A server ignoring the range answers the probe with the whole file
+    server
+        .mock("GET", "/big.iso")
+        .with_status(200)
+        .with_header("content-type", "application/octet-stream")
+        .with_body(vec![0u8; 4 * 1024 * 1024])
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let metadata = downloader
+        .get_client()
+        .get_metadata(&format!("{}/big.iso", server.url()))
+        .await
+        .unwrap();
+
+    assert_eq!(metadata.status_code, 200);
+    assert_eq!(metadata.content_length, Some(4 * 1024 * 1024));
+    assert!(!metadata.supports_range);
+    assert_eq!(metadata.content_type.as_deref(), Some("application/octet-stream"));
+}
+
+const OLD_CREDENTIALS: &str = "Basic dXNlcjpvbGQtc2VjcmV0"; // user:old-secret
+const NEW_CREDENTIALS: &str = "Basic dXNlcjpuZXctc2VjcmV0"; // user:new-secret
+
+/// Provider handing out `old-secret` on the first ask and `new-secret` afterwards
+fn rotating_provider(asks: Arc<Mutex<u32>>) -> CredentialProvider {
+    CredentialProvider(Arc::new(move |_url: &str| {
+        let ask = {
+            let mut asks = asks.lock().unwrap();
+            *asks += 1;
+            *asks
+        };
+        let password = if ask == 1 { "old-secret" } else { "new-secret" };
+        Box::pin(async move {
+            Some(AuthConfig {
+                username: "user".to_string(),
+                password: password.to_string(),
+                auth_type: AuthType::Basic,
+            })
+        })
+    }))
+}
+
+#[tokio::test]
+async fn test_rotated_credentials_refetched_after_401() {
+    let mut server = Server::new_async().await;
+    let challenge = server
+        .mock("GET", "/data")
+        .match_header("authorization", Matcher::Missing)
+        .with_status(401)
+        .with_header("www-authenticate", "Basic realm=\"Test\"")
+        .create_async()
+        .await;
+    let old_accepted = server
+        .mock("GET", "/data")
+        .match_header("authorization", OLD_CREDENTIALS)
+        .with_status(200)
+        .with_body("first")
+        .expect(1)
+        .create_async()
+        .await;
+
+    let asks = Arc::new(Mutex::new(0));
+    let mut config = DownloadConfig::default();
+    config.credential_provider = Some(rotating_provider(asks.clone()));
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/data", server.url());
+
+    assert_eq!(&downloader.download_to_memory(&url).await.unwrap()[..], b"first");
+    old_accepted.assert_async().await;
+    challenge.assert_async().await;
+
+    // The password rotates: the old credentials are now rejected
+    old_accepted.remove_async().await;
+    let old_rejected = server
+        .mock("GET", "/data")
+        .match_header("authorization", OLD_CREDENTIALS)
+        .with_status(401)
+        .with_header("www-authenticate", "Basic realm=\"Test\"")
+        .expect(1)
+        .create_async()
+        .await;
+    let new_accepted = server
+        .mock("GET", "/data")
+        .match_header("authorization", NEW_CREDENTIALS)
+        .with_status(200)
+        .with_body("second")
+        .expect(2)
+        .create_async()
+        .await;
+
+    // Preemptive old credentials fail once, then the provider is asked again
+    assert_eq!(&downloader.download_to_memory(&url).await.unwrap()[..], b"second");
+    // The new credentials are remembered for the next request
+    assert_eq!(&downloader.download_to_memory(&url).await.unwrap()[..], b"second");
+
+    old_rejected.assert_async().await;
+    new_accepted.assert_async().await;
+    assert_eq!(*asks.lock().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn test_rejected_fresh_credentials_fail_with_auth_error() {
+    let mut server = Server::new_async().await;
+    let _challenge = server
+        .mock("GET", "/data")
+        .with_status(401)
+        .with_header("www-authenticate", "Basic realm=\"Test\"")
+        .create_async()
+        .await;
+
+    let asks = Arc::new(Mutex::new(0));
+    let mut config = DownloadConfig::default();
+    config.credential_provider = Some(rotating_provider(asks.clone()));
+    let downloader = Downloader::new(config).unwrap();
+
+    let error = downloader
+        .download_to_memory(&format!("{}/data", server.url()))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error.root(), wget_faster_lib::Error::InvalidStatus(401)));
+    assert_eq!(error.exit_code(), 6);
+    assert_eq!(*asks.lock().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_staged_download_moves_file_into_place() {
+    let mut server = Server::new_async().await;
+    let _mock = server
+        .mock("GET", "/archive.tar")
+        .with_status(200)
+        .with_body("staged content")
+        .create_async()
+        .await;
+
+    let staging_dir = tempfile::tempdir().unwrap();
+    let destination_dir = tempfile::tempdir().unwrap();
+    let destination = destination_dir.path().join("archive.tar");
+    let mut config = DownloadConfig::default();
+    config.staging_dir = Some(staging_dir.path().join("staging"));
+    let downloader = Downloader::new(config).unwrap();
+
+    let result = downloader
+        .download_to_file(&format!("{}/archive.tar", server.url()), destination.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(result.data.path(), Some(&destination));
+    assert_eq!(std::fs::read(&destination).unwrap(), b"staged content");
+    // The staging directory is created on demand and left empty
+    assert_eq!(
+        std::fs::read_dir(staging_dir.path().join("staging"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+/// Downloader with an HTTP cache in `dir` (sequential, so only GET requests are sent)
+fn caching_downloader(dir: &std::path::Path) -> Downloader {
+    let config = DownloadConfig {
+        http_cache: Some(CacheConfig::new(dir, 1024 * 1024)),
+        parallel_chunks: 1,
+        ..DownloadConfig::default()
+    };
+    Downloader::new(config).unwrap()
+}
+
+#[tokio::test]
+async fn test_http_cache_serves_fresh_response_without_request() {
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("GET", "/config.json")
+        .with_header("cache-control", "private, max-age=300")
+        .with_body(r#"{"feature": true}"#)
+        .expect(1)
+        .create_async()
+        .await;
+    let cache_dir = tempfile::tempdir().unwrap();
+    let downloader = caching_downloader(cache_dir.path());
+    let url = format!("{}/config.json", server.url());
+
+    let first = downloader.download_to_memory(&url).await.unwrap();
+    let second = downloader
+        .download(&url, Output::Memory, None)
+        .await
+        .unwrap();
+
+    mock.assert_async().await;
+    assert_eq!(second.data.bytes(), Some(&first));
+    assert_eq!(second.stats.cache, Some(CacheStatus::Hit));
+    assert_eq!(
+        downloader.cache_stats(),
+        Some(CacheStats {
+            hits: 1,
+            revalidated: 0,
+            misses: 1,
+        })
+    );
+
+    // A new file is copied from the cache as well
+    let out_dir = tempfile::tempdir().unwrap();
+    let path = out_dir.path().join("config.json");
+    let result = downloader
+        .download_to_file(&url, path.clone())
+        .await
+        .unwrap();
+    assert_eq!(result.stats.cache, Some(CacheStatus::Hit));
+    assert_eq!(std::fs::read(&path).unwrap(), first.as_ref());
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_http_cache_never_stores_no_store() {
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("GET", "/live.json")
+        .with_header("cache-control", "no-store, max-age=300")
+        .with_header("etag", "\"v1\"")
+        .with_body("{}")
+        .expect(2)
+        .create_async()
+        .await;
+    let cache_dir = tempfile::tempdir().unwrap();
+    let downloader = caching_downloader(cache_dir.path());
+    let url = format!("{}/live.json", server.url());
+
+    downloader.download_to_memory(&url).await.unwrap();
+    downloader.download_to_memory(&url).await.unwrap();
+
+    mock.assert_async().await;
+    assert_eq!(downloader.cache_stats().map(|s| s.misses), Some(2));
+    let stored = std::fs::read_dir(cache_dir.path()).map_or(0, Iterator::count);
+    assert_eq!(stored, 0);
+}
+
+#[tokio::test]
+async fn test_http_cache_revalidates_stale_entry() {
+    let mut server = Server::new_async().await;
+    let last_modified = "Tue, 01 Oct 2024 10:00:00 GMT";
+    let initial = server
+        .mock("GET", "/settings.json")
+        .match_header("if-none-match", Matcher::Missing)
+        .with_header("cache-control", "no-cache")
+        .with_header("etag", "\"v1\"")
+        .with_header("last-modified", last_modified)
+        .with_body(r#"{"version": 1}"#)
+        .expect(1)
+        .create_async()
+        .await;
+    let revalidation = server
+        .mock("GET", "/settings.json")
+        .match_header("if-none-match", "\"v1\"")
+        .match_header("if-modified-since", last_modified)
+        .with_status(304)
+        .with_header("etag", "\"v1\"")
+        .expect(2)
+        .create_async()
+        .await;
+    let cache_dir = tempfile::tempdir().unwrap();
+    let downloader = caching_downloader(cache_dir.path());
+    let url = format!("{}/settings.json", server.url());
+
+    let first = downloader.download_to_memory(&url).await.unwrap();
+    let second = downloader.download_to_memory(&url).await.unwrap();
+    let third = downloader
+        .download(&url, Output::Memory, None)
+        .await
+        .unwrap();
+
+    initial.assert_async().await;
+    revalidation.assert_async().await;
+    assert_eq!(second, first);
+    assert_eq!(third.data.bytes(), Some(&first));
+    assert_eq!(third.stats.cache, Some(CacheStatus::Revalidated));
+    assert_eq!(third.metadata.etag.as_deref(), Some("\"v1\""));
+    assert_eq!(downloader.cache_stats().map(|s| s.revalidated), Some(2));
+}
+
+#[tokio::test]
+async fn test_parallel_download_restarts_when_object_changes() {
+    let mut server = Server::new_async().await;
+    let _head = server
+        .mock("HEAD", "/release.tar")
+        .with_header("content-length", "30")
+        .with_header("accept-ranges", "bytes")
+        .with_header("etag", "\"v1\"")
+        .create_async()
+        .await;
+    // Each range comes from a different edge; the third one already serves the new
+    // version and ignores If-Match
+    let mut chunks = Vec::new();
+    for (index, etag) in ["\"v1\"", "\"v1\"", "\"v2\""].into_iter().enumerate() {
+        let (start, end) = (index * 10, index * 10 + 9);
+        chunks.push(
+            server
+                .mock("GET", "/release.tar")
+                .match_header("range", format!("bytes={start}-{end}").as_str())
+                .match_header("if-match", "\"v1\"")
+                .with_status(206)
+                .with_header("content-range", &format!("bytes {start}-{end}/30"))
+                .with_header("etag", etag)
+                .with_body([b'a' + index as u8; 10])
+                .create_async()
+                .await,
+        );
+    }
+    // The retry downloads the new version whole
+    let _whole = server
+        .mock("GET", "/release.tar")
+        .match_header("range", Matcher::Missing)
+        .with_header("etag", "\"v2\"")
+        .with_body([b'z'; 30])
+        .create_async()
+        .await;
+
+    let (callback, events) = recording_events();
+    let mut config = DownloadConfig {
+        parallel_chunks: 3,
+        parallel_threshold: 1,
+        chunk_size: Some(10),
+        event_callback: Some(callback),
+        ..DownloadConfig::default()
+    };
+    config.retry.initial_delay = Duration::from_millis(10);
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("release.tar");
+
+    downloader
+        .download_to_file(&format!("{}/release.tar", server.url()), path.clone())
+        .await
+        .unwrap();
+
+    for chunk in chunks {
+        chunk.assert_async().await;
+    }
+    let causes: Vec<_> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|event| match event {
+            DownloadEvent::RetryScheduled { cause, .. } => Some(cause.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(causes.len(), 1, "{causes:?}");
+    assert!(causes[0].contains("ETag \"v2\""), "{causes:?}");
+    assert_eq!(std::fs::read(&path).unwrap(), [b'z'; 30]);
+}
+
+/// 30-byte `/chunked.bin`: ten each of `a`, `b` and `c`
+fn chunked_route() -> wget_faster_lib::test_server::Route {
+    route("/chunked.bin")
+        .body([[b'a'; 10], [b'b'; 10], [b'c'; 10]].concat())
+        .ranges(true)
+}
+
+/// Download `/chunked.bin` in 10-byte chunks, returning the file and the last progress report
+async fn download_chunked(server: &TestServer) -> (Vec<u8>, ProgressInfo) {
+    let mut config = DownloadConfig {
+        parallel_chunks: 3,
+        parallel_threshold: 1,
+        chunk_size: Some(10),
+        ..DownloadConfig::default()
+    };
+    config.retry.initial_delay = Duration::from_millis(10);
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chunked.bin");
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&reports);
+
+    downloader
+        .download_to_file_with_progress(
+            &server.url_for("/chunked.bin"),
+            path.clone(),
+            Some(Arc::new(move |p| recorded.lock().unwrap().push(p))),
+        )
+        .await
+        .unwrap();
+
+    let last = reports.lock().unwrap().last().cloned().unwrap();
+    (std::fs::read(&path).unwrap(), last)
+}
+
+/// `Range` headers of the GET requests `server` received, sorted
+fn requested_ranges(server: &TestServer) -> Vec<String> {
+    let mut ranges: Vec<_> = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == hyper::Method::GET)
+        .filter_map(|r| Some(r.headers.get("range")?.to_str().ok()?.to_string()))
+        .collect();
+    ranges.sort();
+    ranges
+}
+
+#[tokio::test]
+async fn test_parallel_chunk_retried_after_503() {
+    // The first range request fails, then every request succeeds
+    let server = TestServer::start([chunked_route().status_sequence([503, 200])])
+        .await
+        .unwrap();
+
+    let (file, progress) = download_chunked(&server).await;
+
+    assert_eq!(file, [[b'a'; 10], [b'b'; 10], [b'c'; 10]].concat());
+    assert_eq!(progress.downloaded, 30);
+    assert_eq!(server.hits(&hyper::Method::GET, "/chunked.bin"), 4);
+    let mut ranges = requested_ranges(&server);
+    ranges.dedup();
+    assert_eq!(ranges, ["bytes=0-9", "bytes=10-19", "bytes=20-29"]);
+}
+
+#[tokio::test]
+async fn test_parallel_chunk_resumes_after_connection_drops() {
+    // The second range drops after 4 bytes; only the remaining 6 are requested again
+    let server = TestServer::start([chunked_route().drop_after(14)])
+        .await
+        .unwrap();
+
+    let (file, progress) = download_chunked(&server).await;
+
+    assert_eq!(file, [[b'a'; 10], [b'b'; 10], [b'c'; 10]].concat());
+    assert_eq!(progress.downloaded, 30);
+    assert_eq!(
+        requested_ranges(&server),
+        ["bytes=0-9", "bytes=10-19", "bytes=14-19", "bytes=20-29"]
+    );
+}
+
+/// Event callback recording every event, with the events recorded
+fn recording_events() -> (EventCallback, Arc<Mutex<Vec<DownloadEvent>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&events);
+    let callback = EventCallback(Arc::new(move |event| recorded.lock().unwrap().push(event)));
+    (callback, events)
+}
+
+#[tokio::test]
+async fn test_events_of_redirected_sequential_download() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/old")
+        .with_status(301)
+        .with_header("location", &format!("{}/new", server.url()))
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/new")
+        .with_status(200)
+        .with_body("hello")
+        .create_async()
+        .await;
+
+    let (callback, events) = recording_events();
+    let config = DownloadConfig {
+        parallel_threshold: 0,
+        event_callback: Some(callback),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/old", server.url());
+    downloader.download_to_memory(&url).await.unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 3, "{events:?}");
+    assert!(
+        matches!(&events[0], DownloadEvent::Redirected { from, to } if *from == url && to.ends_with("/new")),
+        "{events:?}"
+    );
+    assert!(
+        matches!(&events[1], DownloadEvent::Started { metadata, .. } if metadata.status_code == 200),
+        "{events:?}"
+    );
+    assert!(
+        matches!(
+            &events[2],
+            DownloadEvent::Completed {
+                bytes: 5,
+                path: None,
+                ..
+            }
+        ),
+        "{events:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_events_of_retried_parallel_download() {
+    // The first range request fails, then every request succeeds
+    let server = TestServer::start([chunked_route().status_sequence([503, 200])])
+        .await
+        .unwrap();
+    let (callback, events) = recording_events();
+    let mut config = DownloadConfig {
+        parallel_chunks: 3,
+        parallel_threshold: 1,
+        chunk_size: Some(10),
+        event_callback: Some(callback),
+        ..DownloadConfig::default()
+    };
+    config.retry.initial_delay = Duration::from_millis(10);
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chunked.bin");
+    downloader
+        .download_to_file(&server.url_for("/chunked.bin"), path.clone())
+        .await
+        .unwrap();
+
+    let events = events.lock().unwrap();
+    assert!(matches!(events.first(), Some(DownloadEvent::Started { .. })), "{events:?}");
+    assert!(
+        matches!(events.last(), Some(DownloadEvent::Completed { bytes: 30, path: Some(p), .. }) if *p == path),
+        "{events:?}"
+    );
+    let retries: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            DownloadEvent::RetryScheduled { attempt, cause, .. } => Some((*attempt, cause.clone())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(retries.len(), 1, "{events:?}");
+    assert_eq!(retries[0].0, 1);
+    assert!(retries[0].1.contains("503"), "{}", retries[0].1);
+    let mut chunks: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            DownloadEvent::ChunkCompleted { index, bytes } => Some((*index, *bytes)),
+            _ => None,
+        })
+        .collect();
+    chunks.sort_unstable();
+    assert_eq!(chunks, [(0, 10), (1, 10), (2, 10)]);
+}
+
+#[tokio::test]
+async fn test_events_of_failed_download() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/missing")
+        .with_status(404)
+        .create_async()
+        .await;
+
+    let (callback, events) = recording_events();
+    let config = DownloadConfig {
+        parallel_threshold: 0,
+        event_callback: Some(callback),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/missing", server.url());
+    downloader.download_to_memory(&url).await.unwrap_err();
+
+    let events = events.lock().unwrap();
+    assert!(
+        matches!(&events[..], [DownloadEvent::Failed { url: failed, error }] if *failed == url && error.contains("404")),
+        "{events:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_adaptive_parallel_download_in_order_with_monotonic_progress() {
+    let body: Vec<u8> = (0..8 * 1024 * 1024u32).map(|i| (i / 1024) as u8).collect();
+    let server = TestServer::start([route("/big.bin").body(body.clone()).ranges(true)])
+        .await
+        .unwrap();
+
+    // Two requests to start with, in 1 MB chunks so the count can adapt
+    let config = DownloadConfig {
+        parallel_chunks: 2,
+        max_parallel_chunks: 8,
+        parallel_threshold: 1,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&reports);
+    let bytes = downloader
+        .download_to_memory_with_progress(
+            &server.url_for("/big.bin"),
+            Some(Arc::new(move |p: ProgressInfo| {
+                recorded.lock().unwrap().push(p.downloaded);
+            })),
+        )
+        .await
+        .unwrap();
+
+    assert!(bytes == body, "chunks assembled out of order");
+    assert_eq!(requested_ranges(&server).len(), 8);
+    let reports = reports.lock().unwrap();
+    assert!(reports.windows(2).all(|w| w[0] <= w[1]), "{reports:?}");
+    assert_eq!(reports.last(), Some(&(body.len() as u64)));
+}
+
+#[tokio::test]
+async fn test_stalled_body_fails_with_timeout() {
+    let server = TestServer::start([route("/slow.txt")
+        .body("trickle")
+        .chunk_size(1)
+        .delay_per_chunk(Duration::from_millis(500))])
+    .await
+    .unwrap();
+    let mut config = DownloadConfig {
+        timeout: Some(Duration::from_millis(300)),
+        ..DownloadConfig::default()
+    };
+    config.retry.max_retries = 0;
+    let downloader = Downloader::new(config).unwrap();
+
+    let started = std::time::Instant::now();
+    let result = downloader
+        .download_to_memory(&server.url_for("/slow.txt"))
+        .await;
+
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+/// Download `path` from `server` into `out.txt` (holding "previous copy") the
+/// way `-O` does, retrying quickly; returns the file afterwards
+async fn overwrite_download(server: &TestServer, path: &str) -> String {
+    let mut config = DownloadConfig {
+        overwrite_existing: true,
+        ..DownloadConfig::default()
+    };
+    config.retry.initial_delay = Duration::from_millis(10);
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out.txt");
+    std::fs::write(&out, "previous copy").unwrap();
+
+    let _ = downloader
+        .download_to_file(&server.url_for(path), out.clone())
+        .await;
+    std::fs::read_to_string(&out).unwrap()
+}
+
+#[tokio::test]
+async fn test_overwrite_keeps_existing_file_on_error() {
+    let server = TestServer::start([route("/missing.txt").status(404)])
+        .await
+        .unwrap();
+
+    assert_eq!(overwrite_download(&server, "/missing.txt").await, "previous copy");
+}
+
+#[tokio::test]
+async fn test_overwrite_retry_writes_final_body_once() {
+    let server = TestServer::start([route("/doc.txt")
+        .body("final body")
+        .status_sequence([503, 200])])
+    .await
+    .unwrap();
+
+    assert_eq!(overwrite_download(&server, "/doc.txt").await, "final body");
+}
+
+#[tokio::test]
+async fn test_overwrite_retry_continues_truncated_file() {
+    let server = TestServer::start([route("/doc.txt")
+        .body("0123456789")
+        .ranges(true)
+        .drop_after(4)])
+    .await
+    .unwrap();
+
+    assert_eq!(overwrite_download(&server, "/doc.txt").await, "0123456789");
+    assert_eq!(requested_ranges(&server), ["bytes=4-"]);
+}
+
+#[tokio::test]
+async fn test_download_retried_until_server_recovers() {
+    let server = TestServer::start([route("/flaky.txt")
+        .body("recovered")
+        .status_sequence([503, 503, 200])])
+    .await
+    .unwrap();
+    let (callback, events) = recording_events();
+    let mut config = DownloadConfig {
+        event_callback: Some(callback),
+        ..DownloadConfig::default()
+    };
+    config.retry.initial_delay = Duration::from_millis(10);
+    let downloader = Downloader::new(config).unwrap();
+
+    let bytes = downloader
+        .download_to_memory(&server.url_for("/flaky.txt"))
+        .await
+        .unwrap();
+
+    assert_eq!(bytes, "recovered");
+    assert_eq!(server.hits(&hyper::Method::GET, "/flaky.txt"), 3);
+    let retries: Vec<_> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|event| match event {
+            DownloadEvent::RetryScheduled { attempt, .. } => Some(*attempt),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(retries, [1, 2]);
+}
+
+#[tokio::test]
+async fn test_interrupted_file_download_resumed_by_retry() {
+    let server = TestServer::start([route("/doc.txt")
+        .body("0123456789")
+        .ranges(true)
+        .drop_after(4)])
+    .await
+    .unwrap();
+    let mut config = DownloadConfig::default();
+    config.retry.initial_delay = Duration::from_millis(10);
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("doc.txt");
+
+    let result = downloader
+        .download_to_file(&server.url_for("/doc.txt"), path.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "0123456789");
+    assert_eq!(result.data.total_bytes, 10);
+    assert_eq!(requested_ranges(&server), ["bytes=4-"]);
+}
+
+/// Start an HTTP/1.1 server that answers "pong" and closes keep-alive
+/// connections idle for longer than `idle_timeout`, like a load balancer
+async fn spawn_idle_closing_server(idle_timeout: Duration) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let service = hyper::service::service_fn(|_request| async {
+                Ok::<_, std::convert::Infallible>(hyper::Response::new(http_body_util::Full::new(
+                    bytes::Bytes::from_static(b"pong"),
+                )))
+            });
+            tokio::spawn(
+                hyper::server::conn::http1::Builder::new()
+                    .timer(hyper_util::rt::TokioTimer::new())
+                    .header_read_timeout(idle_timeout)
+                    .serve_connection(hyper_util::rt::TokioIo::new(stream), service),
+            );
+        }
+    });
+    format!("http://{addr}/ping")
+}
+
+#[tokio::test]
+async fn test_request_after_server_closed_idle_connection() {
+    let url = spawn_idle_closing_server(Duration::from_millis(100)).await;
+    let config = DownloadConfig {
+        pool_idle_timeout: Some(Duration::from_secs(60)),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+
+    assert_eq!(downloader.download_to_memory(&url).await.unwrap(), "pong");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(downloader.download_to_memory(&url).await.unwrap(), "pong");
+}
+
+#[tokio::test]
+async fn test_request_replayed_when_pooled_connection_drops_it() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // The first connection answers one request, then reads the next and hangs up
+    // without a response: the race with a load balancer's idle timeout
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/ping", listener.local_addr().unwrap());
+    let connections = Arc::new(Mutex::new(0));
+    let accepted = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let first = {
+                let mut count = accepted.lock().unwrap();
+                *count += 1;
+                *count == 1
+            };
+            tokio::spawn(async move {
+                let mut answered = 0;
+                let mut buffer = Vec::new();
+                let mut chunk = [0u8; 1024];
+                while let Ok(read @ 1..) = stream.read(&mut chunk).await {
+                    buffer.extend_from_slice(&chunk[..read]);
+                    if !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+                        continue;
+                    }
+                    buffer.clear();
+                    if first && answered == 1 {
+                        return;
+                    }
+                    answered += 1;
+                    let response = "HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\npong";
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+        }
+    });
+
+    let config = DownloadConfig {
+        parallel_chunks: 1,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+
+    assert_eq!(downloader.download_to_memory(&url).await.unwrap(), "pong");
+    assert_eq!(downloader.download_to_memory(&url).await.unwrap(), "pong");
+    assert_eq!(*connections.lock().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn test_download_stream_yields_chunks_as_they_arrive() {
+    use futures::StreamExt;
+
+    let mut server = Server::new_async().await;
+    let (release, released) = std::sync::mpsc::channel::<()>();
+    let released = Mutex::new(released);
+    let _mock = server
+        .mock("GET", "/live.log")
+        .with_chunked_body(move |w| {
+            w.write_all(b"first ")?;
+            // The rest is only sent once the client has received the first chunk
+            let released = released
+                .lock()
+                .unwrap()
+                .recv_timeout(Duration::from_secs(5))
+                .is_ok();
+            w.write_all(if released { b"second" } else { b"late!!" })
+        })
+        .create_async()
+        .await;
+
+    let config = DownloadConfig {
+        parallel_chunks: 1,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let reports: Arc<Mutex<Vec<ProgressInfo>>> = Arc::default();
+    let recorded = reports.clone();
+    let (metadata, mut chunks) = downloader
+        .download_stream_with_metadata(
+            &format!("{}/live.log", server.url()),
+            Some(Arc::new(move |p| recorded.lock().unwrap().push(p))),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(metadata.status_code, 200);
+    assert_eq!(chunks.next().await.unwrap().unwrap(), "first ");
+    release.send(()).unwrap();
+    let mut rest = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        rest.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(rest, b"second");
+    assert_eq!(reports.lock().unwrap().last().unwrap().downloaded, 12);
+}
+
+#[tokio::test]
+async fn test_download_stream_resumes_after_connection_drops() {
+    use futures::StreamExt;
+
+    let server = TestServer::start([route("/big.bin")
+        .body("hello world")
+        .ranges(true)
+        .etag("v1")
+        .drop_after(6)])
+    .await
+    .unwrap();
+
+    let mut config = DownloadConfig {
+        parallel_chunks: 1,
+        ..DownloadConfig::default()
+    };
+    config.retry.initial_delay = Duration::from_millis(10);
+    let downloader = Downloader::new(config).unwrap();
+    let chunks: Vec<_> = downloader
+        .download_stream(&server.url_for("/big.bin"))
+        .await
+        .unwrap()
+        .collect()
+        .await;
+
+    let body: Vec<u8> = chunks.into_iter().flat_map(|c| c.unwrap()).collect();
+    assert_eq!(body, b"hello world");
+    let gets: Vec<_> = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == hyper::Method::GET)
+        .collect();
+    assert_eq!(gets.len(), 2);
+    assert!(gets[0].headers.get("range").is_none());
+    assert_eq!(gets[1].headers["range"], "bytes=6-");
+    assert_eq!(gets[1].headers["if-range"], "\"v1\"");
+}
+
+#[tokio::test]
+async fn test_download_stream_parallel_chunks_in_order() {
+    use futures::StreamExt;
+
+    let mut server = Server::new_async().await;
+    let _head = server
+        .mock("HEAD", "/disk.img")
+        .with_header("content-length", "30")
+        .with_header("accept-ranges", "bytes")
+        .create_async()
+        .await;
+    let mut mocks = Vec::new();
+    for index in 0..3u8 {
+        let (start, end) = (u64::from(index) * 10, u64::from(index) * 10 + 9);
+        mocks.push(
+            server
+                .mock("GET", "/disk.img")
+                .match_header("range", format!("bytes={start}-{end}").as_str())
+                .with_status(206)
+                .with_header("content-range", &format!("bytes {start}-{end}/30"))
+                // The first chunk finishes last
+                .with_chunked_body(move |w| {
+                    if index == 0 {
+                        std::thread::sleep(Duration::from_millis(200));
+                    }
+                    w.write_all(&[b'a' + index; 10])
+                })
+                .create_async()
+                .await,
+        );
+    }
+
+    let config = DownloadConfig {
+        parallel_chunks: 3,
+        parallel_threshold: 1,
+        chunk_size: Some(10),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let (metadata, chunks) = downloader
+        .download_stream_with_metadata(&format!("{}/disk.img", server.url()), None)
+        .await
+        .unwrap();
+    let chunks: Vec<_> = chunks.map(Result::unwrap).collect().await;
+
+    for mock in mocks {
+        mock.assert_async().await;
+    }
+    assert_eq!(metadata.content_length, Some(30));
+    assert_eq!(chunks, [&[b'a'; 10][..], &[b'b'; 10], &[b'c'; 10]]);
+}
+
+#[tokio::test]
+async fn test_unsafe_url_characters_are_encoded() {
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("GET", "/my%20file%20%7B1%7D.txt?q=caf%C3%A9")
+        .with_status(200)
+        .with_body("encoded")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let url = format!("{}/my file {{1}}.txt?q=café", server.url());
+    let bytes = downloader.download_to_memory(&url).await.unwrap();
+    assert_eq!(bytes, "encoded");
+    mock.assert_async().await;
+
+    let err = downloader
+        .download_to_memory("ftp://example.com/file.txt")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, wget_faster_lib::Error::UnsupportedScheme(_)));
+    assert_eq!(err.exit_code(), 2);
+}
+
+const ARTIFACT_SHA256: &str = "133cfccb5b503cf4040c95f3dfad56d07c1574283a1e39066b594f6ee33711ba";
+
+async fn mock_artifact(server: &mut mockito::ServerGuard) -> mockito::Mock {
+    server
+        .mock("GET", "/artifact.tar")
+        .with_status(200)
+        .with_body("release artifact")
+        .create_async()
+        .await
+}
+
+#[tokio::test]
+async fn test_checksum_reported_and_verified() {
+    let mut server = Server::new_async().await;
+    mock_artifact(&mut server).await;
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/artifact.tar", server.url());
+
+    // Without an expectation the SHA-256 is reported
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let result = downloader
+        .download_to_file(&url, dir.path().join("plain.tar"))
+        .await
+        .unwrap();
+    assert_eq!(result.checksum, Some(Checksum::Sha256(ARTIFACT_SHA256.to_string())));
+
+    let config = DownloadConfig {
+        expected_checksum: Some(Checksum::Md5("AD3BABB5F619DCAF5EE1634C75932C81".to_string())),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let path = dir.path().join("verified.tar");
+    let result = downloader
+        .download_to_file(&url, path.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        result.checksum,
+        Some(Checksum::Md5("ad3babb5f619dcaf5ee1634c75932c81".to_string()))
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), b"release artifact");
+}
+
+#[tokio::test]
+async fn test_checksum_mismatch_deletes_file() {
+    let mut server = Server::new_async().await;
+    mock_artifact(&mut server).await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("artifact.tar");
+
+    let config = DownloadConfig {
+        expected_checksum: Some(Checksum::Sha256("00".repeat(32))),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let err = downloader
+        .download_to_file(&format!("{}/artifact.tar", server.url()), path.clone())
+        .await
+        .unwrap_err();
+
+    match err.root() {
+        wget_faster_lib::Error::ChecksumMismatch { actual, .. } => {
+            assert_eq!(actual.hex(), ARTIFACT_SHA256);
+        },
+        other => panic!("unexpected error: {other}"),
+    }
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_checksum_of_parallel_download_in_offset_order() {
+    let server = TestServer::start([chunked_route()]).await.unwrap();
+
+    let config = DownloadConfig {
+        parallel_chunks: 3,
+        parallel_threshold: 1,
+        chunk_size: Some(10),
+        expected_checksum: Some(Checksum::Sha512(
+            "1dfb26ba64f077281fd40f78dba38589c6d3a169b1e44f8154afc8e7956e5934\
+             373fd718606f737703feec2411e6fdb41aac82d1799122f1276285c5831cf7f2"
+                .to_string(),
+        )),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let result = downloader
+        .download_to_file(&server.url_for("/chunked.bin"), dir.path().join("chunked.bin"))
+        .await
+        .unwrap();
+    assert!(matches!(result.checksum, Some(Checksum::Sha512(_))));
+}
+
+const PLAIN_BODY: &str = "plain text body that the server can also send gzip-encoded\n";
+
+fn gzipped(body: &str) -> Vec<u8> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(body.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Marker recording that the partial file at `path` was saved content-coded
+fn encoding_marker(path: &std::path::Path) -> std::path::PathBuf {
+    std::path::PathBuf::from(format!("{}.wgetf-encoding", path.display()))
+}
+
+#[tokio::test]
+async fn test_file_download_asks_for_identity_encoding() {
+    let mut server = Server::new_async().await;
+    let head_mock = server
+        .mock("HEAD", "/doc.txt")
+        .match_header("accept-encoding", "identity")
+        .with_header("content-length", &PLAIN_BODY.len().to_string())
+        .create_async()
+        .await;
+    let get_mock = server
+        .mock("GET", "/doc.txt")
+        .match_header("accept-encoding", "identity")
+        .with_body(PLAIN_BODY)
+        .create_async()
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    downloader
+        .download_to_file(&format!("{}/doc.txt", server.url()), dir.path().join("doc.txt"))
+        .await
+        .unwrap();
+
+    head_mock.assert_async().await;
+    get_mock.assert_async().await;
+    assert_eq!(std::fs::read_to_string(dir.path().join("doc.txt")).unwrap(), PLAIN_BODY);
+}
+
+#[tokio::test]
+async fn test_coded_response_recorded_until_complete() {
+    let mut server = Server::new_async().await;
+    let gzip = gzipped(PLAIN_BODY);
+    server
+        .mock("GET", "/doc.txt")
+        .match_header("accept-encoding", default_accept_encoding().as_str())
+        .with_header("content-encoding", "gzip")
+        .with_body(&gzip)
+        .create_async()
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("doc.txt");
+    let marker = encoding_marker(&path);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&seen);
+    let callback_marker = marker.clone();
+    let callback = Arc::new(move |_: ProgressInfo| {
+        recorded.lock().unwrap().push(callback_marker.exists());
+    });
+
+    let config = DownloadConfig {
+        resume_safe_encoding: false,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    downloader
+        .download_to_file_with_progress(
+            &format!("{}/doc.txt", server.url()),
+            path.clone(),
+            Some(callback),
+        )
+        .await
+        .unwrap();
+
+    // Saved as received; the marker only lasts while the file is incomplete
+    assert_eq!(std::fs::read(&path).unwrap(), gzip);
+    assert!(seen.lock().unwrap().iter().all(|&exists| exists));
+    assert!(!seen.lock().unwrap().is_empty());
+    assert!(!marker.exists());
+}
+
+#[tokio::test]
+async fn test_coded_partial_downloaded_again() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("HEAD", "/doc.txt")
+        .with_header("content-length", &PLAIN_BODY.len().to_string())
+        .create_async()
+        .await;
+    let full_mock = server
+        .mock("GET", "/doc.txt")
+        .match_header("range", Matcher::Missing)
+        .with_body(PLAIN_BODY)
+        .expect(1)
+        .create_async()
+        .await;
+    let resume_mock = server
+        .mock("GET", "/doc.txt")
+        .match_header("range", Matcher::Any)
+        .expect(0)
+        .create_async()
+        .await;
+
+    // A previous run was interrupted halfway through a gzip response
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("doc.txt");
+    let gzip = gzipped(PLAIN_BODY);
+    std::fs::write(&path, &gzip[..gzip.len() / 2]).unwrap();
+    std::fs::write(encoding_marker(&path), "gzip").unwrap();
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let result = downloader
+        .download_to_file(&format!("{}/doc.txt", server.url()), path.clone())
+        .await
+        .unwrap();
+
+    full_mock.assert_async().await;
+    resume_mock.assert_async().await;
+    assert!(!result.data.was_resumed);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), PLAIN_BODY);
+    assert!(!encoding_marker(&path).exists());
+}
+
+#[tokio::test]
+async fn test_coded_resume_response_not_appended() {
+    let mut server = Server::new_async().await;
+    let half = PLAIN_BODY.len() / 2;
+    server
+        .mock("HEAD", "/doc.txt")
+        .with_header("content-length", &PLAIN_BODY.len().to_string())
+        .create_async()
+        .await;
+    // The server ignores Accept-Encoding: identity for the rest of the file
+    server
+        .mock("GET", "/doc.txt")
+        .match_header("range", format!("bytes={half}-").as_str())
+        .with_status(206)
+        .with_header("content-encoding", "gzip")
+        .with_header(
+            "content-range",
+            &format!("bytes {half}-{}/{}", PLAIN_BODY.len() - 1, PLAIN_BODY.len()),
+        )
+        .with_body(gzipped(&PLAIN_BODY[half..]))
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/doc.txt")
+        .match_header("range", Matcher::Missing)
+        .with_body(PLAIN_BODY)
+        .create_async()
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("doc.txt");
+    std::fs::write(&path, &PLAIN_BODY[..half]).unwrap();
+
+    let mut config = DownloadConfig::default();
+    config.retry.initial_delay = Duration::from_millis(10);
+    let downloader = Downloader::new(config).unwrap();
+    let url = format!("{}/doc.txt", server.url());
+
+    // The mixed file is removed and the retry starts over
+    let result = downloader
+        .download_to_file(&url, path.clone())
+        .await
+        .unwrap();
+    assert!(!result.data.was_resumed);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), PLAIN_BODY);
+}
+
+/// Estimate options saving each URL under its last path segment in `dir`
+fn estimate_into(dir: &std::path::Path) -> EstimateOptions {
+    let dir = dir.to_path_buf();
+    EstimateOptions {
+        target: Some(Arc::new(move |url, _| {
+            Some(dir.join(url.rsplit('/').next().unwrap_or("index.html")))
+        })),
+        ..EstimateOptions::default()
+    }
+}
+
+#[tokio::test]
+async fn test_estimate_sums_known_sizes() {
+    let mut server = Server::new_async().await;
+    for (path, length) in [("/a.bin", "1000"), ("/b.bin", "500")] {
+        server
+            .mock("HEAD", path)
+            .with_header("content-length", length)
+            .create_async()
+            .await;
+    }
+    // No Content-Length: counted apart, never guessed
+    server.mock("HEAD", "/live.txt").create_async().await;
+    server
+        .mock("HEAD", "/gone.bin")
+        .with_status(404)
+        .create_async()
+        .await;
+    let no_body = server
+        .mock("GET", Matcher::Any)
+        .expect(0)
+        .create_async()
+        .await;
+
+    // A partial download of b.bin
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("b.bin"), [0u8; 200]).unwrap();
+    let urls: Vec<String> = ["/a.bin", "/b.bin", "/live.txt", "/gone.bin"]
+        .iter()
+        .map(|path| format!("{}{path}", server.url()))
+        .collect();
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+
+    let report = downloader.estimate(&urls, estimate_into(dir.path())).await;
+
+    assert_eq!(report.urls, 4);
+    assert_eq!((report.sized, report.total_bytes), (2, 1000 + 300));
+    assert_eq!(report.unknown_size, 1);
+    assert_eq!((report.skipped, report.failed, report.not_probed), (0, 1, 0));
+    assert_eq!(report.entries[1].outcome, EstimateOutcome::Download { bytes: Some(300) });
+    assert_eq!(report.entries[2].outcome, EstimateOutcome::Download { bytes: None });
+
+    // -nc keeps the existing file instead of resuming it
+    let mut options = estimate_into(dir.path());
+    options.no_clobber = true;
+    let report = downloader.estimate(&urls, options).await;
+
+    assert_eq!((report.sized, report.total_bytes), (1, 1000));
+    assert_eq!(report.skipped, 1);
+    assert!(matches!(report.entries[1].outcome, EstimateOutcome::Skip { .. }));
+    no_body.assert_async().await;
+    assert_eq!(std::fs::read(dir.path().join("b.bin")).unwrap(), [0u8; 200]);
+}
+
+#[tokio::test]
+async fn test_estimate_skips_up_to_date_files_with_timestamping() {
+    use std::time::SystemTime;
+
+    let mut server = Server::new_async().await;
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_483_228_800);
+    for path in ["/same.bin", "/newer.bin"] {
+        server
+            .mock("HEAD", path)
+            .with_header("content-length", "64")
+            .with_header("last-modified", &httpdate::fmt_http_date(modified))
+            .create_async()
+            .await;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    for (name, mtime) in [
+        ("same.bin", modified),
+        ("newer.bin", SystemTime::UNIX_EPOCH),
+    ] {
+        let path = dir.path().join(name);
+        std::fs::write(&path, [0u8; 64]).unwrap();
+        filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(mtime)).unwrap();
+    }
+    let urls = vec![
+        format!("{}/same.bin", server.url()),
+        format!("{}/newer.bin", server.url()),
+    ];
+
+    let config = DownloadConfig {
+        timestamping: true,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let report = downloader.estimate(&urls, estimate_into(dir.path())).await;
+
+    assert_eq!(report.skipped, 1);
+    assert_eq!((report.sized, report.total_bytes), (1, 64));
+    assert!(matches!(report.entries[0].outcome, EstimateOutcome::Skip { .. }));
+}
+
+#[tokio::test]
+async fn test_estimate_stops_probing_at_time_limit() {
+    // Accepts connections but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut open = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            open.push(stream);
+        }
+    });
+    let urls: Vec<String> = (0..3).map(|i| format!("{base}/{i}")).collect();
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let options = EstimateOptions {
+        time_limit: Duration::from_millis(200),
+        ..EstimateOptions::default()
+    };
+    let started = std::time::Instant::now();
+    let report = downloader.estimate(&urls, options).await;
+
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(report.not_probed, 3);
+    assert_eq!(report.total_bytes, 0);
+}
+
+#[tokio::test]
+async fn test_download_many_bounds_concurrency_in_input_order() {
+    let routes = (0..6).map(|i| {
+        route(format!("/{i}.txt"))
+            .body(vec![b'0' + i; 100])
+            .delay_per_chunk(Duration::from_millis(150))
+    });
+    let server = TestServer::start(routes.chain([route("/missing.txt").status(404)]))
+        .await
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut requests: Vec<_> = (0..6)
+        .map(|i| {
+            (
+                server.url_for(&format!("/{i}.txt")),
+                Output::File(dir.path().join(format!("{i}.txt"))),
+            )
+        })
+        .collect();
+    requests.insert(2, (server.url_for("/missing.txt"), Output::Memory));
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let started = std::time::Instant::now();
+    let results = downloader.download_many(requests, 2, None).await;
+    let elapsed = started.elapsed();
+
+    // Three rounds of two, not six in a row and not all at once
+    assert!(elapsed >= Duration::from_millis(450), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(850), "{elapsed:?}");
+    assert_eq!(results.len(), 7);
+    assert!(results[2].is_err());
+    for (i, result) in results.iter().filter_map(|r| r.as_ref().ok()).enumerate() {
+        assert!(result.url.ends_with(&format!("/{i}.txt")));
+        let path = dir.path().join(format!("{i}.txt"));
+        assert_eq!(std::fs::read(path).unwrap(), vec![b'0' + i as u8; 100]);
+    }
+}
+
+#[tokio::test]
+async fn test_download_many_numbers_same_content_disposition_name() {
+    // Both downloads are in flight when the second resolves its name
+    let routes = ["/a", "/b"].map(|path| {
+        route(path)
+            .body(format!("body of {path}").repeat(100))
+            .header("Content-Disposition", "attachment; filename=\"report.txt\"")
+            .chunk_size(100)
+            .delay_per_chunk(Duration::from_millis(20))
+    });
+    let server = TestServer::start(routes).await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let requests = ["/a", "/b"]
+        .into_iter()
+        .map(|path| (server.url_for(path), Output::File(dir.path().join("unnamed"))))
+        .collect();
+
+    let config = DownloadConfig {
+        content_disposition: true,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let results = downloader.download_many(requests, 2, None).await;
+
+    let mut paths = std::collections::HashSet::new();
+    for (path, result) in ["/a", "/b"].iter().zip(results) {
+        let saved = result.unwrap().data.file_path.unwrap();
+        assert_eq!(std::fs::read_to_string(&saved).unwrap(), format!("body of {path}").repeat(100));
+        paths.insert(saved);
+    }
+    let report = dir.path().join("report.txt");
+    assert_eq!(paths, [report.clone(), numbered_path(&report, 1)].into());
+    assert!(!downloader.name_registry().is_claimed(&report));
+}
+
+#[tokio::test]
+async fn test_download_many_stops_starting_downloads_at_quota() {
+    let server =
+        TestServer::start((0..4).map(|i| route(format!("/{i}.txt")).body(vec![b'x'; 100])))
+            .await
+            .unwrap();
+    let requests = (0..4)
+        .map(|i| (server.url_for(&format!("/{i}.txt")), Output::Memory))
+        .collect();
+
+    let config = DownloadConfig {
+        quota: Some(250),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let results = downloader.download_many(requests, 1, None).await;
+
+    // The third download starts below the quota and finishes above it
+    assert!(results[..3].iter().all(Result::is_ok));
+    assert!(matches!(results[3], Err(Error::QuotaExceeded(250))));
+    assert_eq!(server.hits(&http::Method::GET, "/3.txt"), 0);
+}
+
+#[tokio::test]
+async fn test_quota_finishes_file_in_progress() {
+    let server = TestServer::start([
+        route("/big.bin").body(vec![b'b'; 40_000]).ranges(true),
+        route("/next.bin").body("next"),
+    ])
+    .await
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    // The parallel chunks all count against the one quota
+    let config = DownloadConfig {
+        quota: Some(1000),
+        parallel_threshold: 1000,
+        parallel_chunks: 4,
+        chunk_size: Some(10_000),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let result = downloader
+        .download_to_file(&server.url_for("/big.bin"), dir.path().join("big.bin"))
+        .await
+        .unwrap();
+    assert_eq!(result.data.total_bytes, 40_000);
+    assert_eq!(downloader.quota_used(), 40_000);
+    assert!(downloader.quota_exceeded());
+
+    let error = downloader
+        .download_to_file(&server.url_for("/next.bin"), dir.path().join("next.bin"))
+        .await
+        .unwrap_err();
+    assert!(matches!(error.root(), Error::QuotaExceeded(1000)), "{error:?}");
+    assert_eq!(server.hits(&http::Method::GET, "/next.bin"), 0);
+}
+
+#[tokio::test]
+async fn test_quota_hard_aborts_download_in_progress() {
+    let server = TestServer::start([route("/big.bin")
+        .body(vec![b'b'; 10_000])
+        .chunk_size(1000)
+        .delay_per_chunk(Duration::from_millis(5))])
+    .await
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("big.bin");
+
+    let config = DownloadConfig {
+        quota: Some(2500),
+        quota_hard: true,
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let error = downloader
+        .download_to_file(&server.url_for("/big.bin"), path.clone())
+        .await
+        .unwrap_err();
+    assert!(matches!(error.root(), Error::QuotaExceeded(2500)), "{error:?}");
+
+    // Cut off at the chunk that crossed the quota; the partial file is kept
+    let kept = std::fs::metadata(&path).unwrap().len();
+    assert!(kept <= 2500, "{kept}");
+    assert!(downloader.quota_used() < 10_000);
+}
+
+#[tokio::test]
+async fn test_batch_state_resumes_interrupted_batch() {
+    // /3.txt trickles in, so the batch can be interrupted in the middle of it
+    let slow = vec![b'3'; 10_000];
+    let routes = (0..5).map(|i| match i {
+        3 => route("/3.txt")
+            .body(slow.clone())
+            .ranges(true)
+            .chunk_size(1000)
+            .delay_per_chunk(Duration::from_millis(100)),
+        _ => route(format!("/{i}.txt")).body(vec![b'0' + i; 100]),
+    });
+    let server = TestServer::start(routes).await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let state_path = dir.path().join("batch.jsonl");
+    let requests = || {
+        (0..5)
+            .map(|i| {
+                let name = format!("{i}.txt");
+                (server.url_for(&format!("/{name}")), Output::File(dir.path().join(name)))
+            })
+            .collect::<Vec<_>>()
+    };
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+
+    // First run: drop the batch once part of /3.txt is on disk
+    let state = BatchState::open(&state_path).await.unwrap();
+    let partial = dir.path().join("3.txt");
+    let interrupted = async {
+        while std::fs::metadata(&partial).map_or(0, |m| m.len()) < 2000 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::select! {
+        _ = downloader.download_many_with_state(requests(), 1, None, &state) => {
+            panic!("batch finished before it was interrupted")
+        },
+        () = interrupted => {},
+    }
+    drop(state);
+    let partial_len = std::fs::metadata(&partial).unwrap().len();
+    assert!(partial_len < 10_000);
+
+    // Second run: no requests for the completed files, /3.txt continued, /4.txt fetched
+    let state = BatchState::open(&state_path).await.unwrap();
+    assert_eq!(state.entry(&server.url_for("/3.txt")).unwrap().status, BatchStatus::Started);
+    let results = downloader
+        .download_many_with_state(requests(), 1, None, &state)
+        .await;
+    state.compact().await.unwrap();
+
+    assert!(results[..3].iter().all(|r| matches!(r, Ok(None))));
+    assert!(results[3..].iter().all(|r| matches!(r, Ok(Some(_)))));
+    for i in [0, 1, 2, 4] {
+        assert_eq!(server.hits(&http::Method::GET, &format!("/{i}.txt")), 1);
+    }
+    assert_eq!(std::fs::read(&partial).unwrap(), slow);
+    assert!(requested_ranges(&server).contains(&format!("bytes={partial_len}-")));
+
+    // Compacted to one complete line per URL
+    let state = BatchState::open(&state_path).await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&state_path)
+            .unwrap()
+            .lines()
+            .count(),
+        5
+    );
+    let entry = state.completed(&server.url_for("/3.txt")).unwrap();
+    assert_eq!(entry.size, 10_000);
+    assert!(entry.checksum.as_deref().unwrap().starts_with("sha256:"));
+}
+
+#[tokio::test]
+async fn test_memory_capped_output_spills_large_bodies() {
+    let large: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+    let server = TestServer::start([
+        route("/small.txt").body("fits in memory"),
+        route("/large.bin").body(large.clone()),
+        route("/ranged.bin").body(large.clone()).ranges(true),
+    ])
+    .await
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let config = DownloadConfig {
+        parallel_threshold: 10_000,
+        chunk_size: Some(8_192),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let capped = |name: &str| Output::MemoryCapped {
+        max_bytes: 1_024,
+        spill_path: dir.path().join(name),
+    };
+
+    let small = downloader
+        .download(&server.url_for("/small.txt"), capped("small.part"), None)
+        .await
+        .unwrap()
+        .data;
+    assert!(!small.spilled);
+    assert!(!dir.path().join("small.part").exists());
+    assert_eq!(small.into_bytes().unwrap(), "fits in memory");
+
+    // Streamed past the cap, and downloaded in parallel straight to disk
+    for (path, name) in [("/large.bin", "large.part"), ("/ranged.bin", "ranged.part")] {
+        let data = downloader
+            .download(&server.url_for(path), capped(name), None)
+            .await
+            .unwrap()
+            .data;
+        assert!(data.spilled, "{path}");
+        assert!(data.bytes().is_none());
+        assert_eq!(data.total_bytes, large.len() as u64);
+        let spilled = data.into_path().unwrap();
+        assert_eq!(spilled, dir.path().join(name));
+        assert_eq!(std::fs::read(spilled).unwrap(), large, "{path}");
+    }
+    assert!(server.hits(&http::Method::GET, "/ranged.bin") > 1);
+}
+
+#[tokio::test]
+async fn test_max_body_duration_ends_endless_body() {
+    let server = TestServer::start([route("/stream")
+        .body("tick\n")
+        .endless()
+        .delay_per_chunk(Duration::from_millis(10))])
+    .await
+    .unwrap();
+    let config = DownloadConfig::builder()
+        .max_body_duration(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let downloader = Downloader::new(config).unwrap();
+
+    let started = std::time::Instant::now();
+    let result = downloader
+        .download_to_memory(&server.url_for("/stream"))
+        .await;
+    assert!(matches!(result, Err(Error::BodyDurationExceeded(_))), "{result:?}");
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    let dir = tempfile::tempdir().unwrap();
+    let result = downloader
+        .download_to_file(&server.url_for("/stream"), dir.path().join("stream"))
+        .await;
+    assert!(matches!(result.unwrap_err().root(), Error::BodyDurationExceeded(_)));
+}
+
+/// Body of `/slow.bin`, sent 100 bytes at a time
+fn slow_body() -> Vec<u8> {
+    (0..1000u32).map(|i| (i % 251) as u8).collect()
+}
+
+/// Download `/slow.bin` to `path` with a handle, cancelling it once `cancel_at` bytes arrived
+async fn download_cancelled_at(
+    server: &TestServer,
+    config: DownloadConfig,
+    path: &std::path::Path,
+    cancel_at: u64,
+) -> wget_faster_lib::Result<DownloadResult> {
+    let (sender, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let progress: ProgressCallback = Arc::new(move |info: ProgressInfo| {
+        let _ = sender.send(info.downloaded);
+    });
+    let downloader = Downloader::new(config).unwrap();
+    let url = server.url_for("/slow.bin");
+    let (handle, download) =
+        downloader.download_controlled(&url, Output::File(path.to_path_buf()), Some(progress));
+
+    let cancel = async {
+        while let Some(downloaded) = received.recv().await {
+            if downloaded >= cancel_at {
+                handle.cancel();
+                break;
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(download, cancel);
+    assert!(handle.is_cancelled());
+    result
+}
+
+#[tokio::test]
+async fn test_cancel_keeps_partial_file() {
+    let body = slow_body();
+    let server = TestServer::start([route("/slow.bin")
+        .body(body.clone())
+        .ranges(true)
+        .chunk_size(100)
+        .delay_per_chunk(Duration::from_millis(20))])
+    .await
+    .unwrap();
+    let parallel = DownloadConfig {
+        parallel_chunks: 2,
+        parallel_threshold: 1,
+        chunk_size: Some(250),
+        ..DownloadConfig::default()
+    };
+
+    for config in [DownloadConfig::default(), parallel] {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("slow.bin");
+        let result = download_cancelled_at(&server, config, &path, 200).await;
+
+        assert!(matches!(result.unwrap_err().root(), Error::Cancelled));
+        let partial = std::fs::read(&path).unwrap();
+        assert!(partial.len() >= 200 && partial.len() < body.len(), "{}", partial.len());
+        assert_eq!(partial, body[..partial.len()]);
+    }
+}
+
+#[tokio::test]
+async fn test_download_after_cancel_resumes_partial_file() {
+    let body = slow_body();
+    let server = TestServer::start([route("/slow.bin")
+        .body(body.clone())
+        .ranges(true)
+        .chunk_size(100)
+        .delay_per_chunk(Duration::from_millis(20))])
+    .await
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("slow.bin");
+    let result = download_cancelled_at(&server, DownloadConfig::default(), &path, 300).await;
+    assert!(matches!(result.unwrap_err().root(), Error::Cancelled));
+    let partial = std::fs::metadata(&path).unwrap().len();
+
+    // A paused download goes on once resumed
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let url = server.url_for("/slow.bin");
+    let (handle, download) = downloader.download_controlled(&url, Output::File(path.clone()), None);
+    handle.pause();
+    assert!(handle.is_paused());
+    let resume = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        handle.resume();
+    };
+    let (result, ()) = tokio::join!(download, resume);
+
+    assert!(result.unwrap().data.was_resumed);
+    assert_eq!(std::fs::read(&path).unwrap(), body);
+    assert_eq!(requested_ranges(&server), [format!("bytes={partial}-")]);
+}
+
+#[tokio::test]
+async fn test_progress_sink_not_slowed_by_sleeping_consumer() {
+    let server = TestServer::start([chunked_route()]).await.unwrap();
+    let (sender, mut receiver) = tokio::sync::mpsc::channel::<ProgressInfo>(1);
+    let consumer = tokio::spawn(async move {
+        let mut received = Vec::new();
+        while let Some(info) = receiver.recv().await {
+            received.push(info.downloaded);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        received
+    });
+    let config = DownloadConfig {
+        parallel_chunks: 3,
+        parallel_threshold: 1,
+        chunk_size: Some(10),
+        progress_sink: Some(ProgressSink::new(sender).with_max_rate(1000.0)),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let started = std::time::Instant::now();
+    let bytes = downloader
+        .download_to_memory(&server.url_for("/chunked.bin"))
+        .await
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+    assert_eq!(bytes.len(), 30);
+
+    // Closing the channel lets the consumer finish with what it was sent
+    drop(downloader);
+    let received = tokio::time::timeout(Duration::from_secs(10), consumer)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(received.len() < 4, "{received:?}");
+    assert_eq!(received.last(), Some(&30), "{received:?}");
+}
+
+#[tokio::test]
+async fn test_progress_sink_from_callback() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/file.bin")
+        .with_status(200)
+        .with_body(vec![b'x'; 100_000])
+        .create_async()
+        .await;
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&received);
+    let callback: ProgressCallback =
+        Arc::new(move |info: ProgressInfo| recorded.lock().unwrap().push(info.downloaded));
+    let config = DownloadConfig {
+        parallel_threshold: 0,
+        progress_sink: Some(ProgressSink::from_callback(callback)),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    downloader
+        .download_to_memory(&format!("{}/file.bin", server.url()))
+        .await
+        .unwrap();
+
+    // The callback runs on its own task: wait for the final update to reach it
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while received.lock().unwrap().last() != Some(&100_000) {
+        assert!(std::time::Instant::now() < deadline, "{:?}", received.lock().unwrap());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_bind_address_and_address_family() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/file.txt")
+        .with_status(200)
+        .with_body("bound")
+        .create_async()
+        .await;
+    let url = format!("{}/file.txt", server.url());
+
+    let config = DownloadConfig {
+        bind_address: Some(std::net::Ipv4Addr::LOCALHOST.into()),
+        address_family: Some(AddressFamily::V4Only),
+        ..DownloadConfig::default()
+    };
+    let bytes = Downloader::new(config)
+        .unwrap()
+        .download_to_memory(&url)
+        .await
+        .unwrap();
+    assert_eq!(bytes, "bound");
+
+    // The server only listens on IPv4
+    let mut config = DownloadConfig {
+        address_family: Some(AddressFamily::V6Only),
+        ..DownloadConfig::default()
+    };
+    config.retry.max_retries = 0;
+    let downloader = Downloader::new(config).unwrap();
+    let err = downloader.download_to_memory(&url).await.unwrap_err();
+    assert!(matches!(err.root(), Error::ConfigError(_)), "{err}");
+    let port = url::Url::parse(&url).unwrap().port().unwrap();
+    let by_name = format!("http://localhost:{port}/file.txt");
+    assert!(downloader.download_to_memory(&by_name).await.is_err());
+}
+
+const MIRROR_LAST_MODIFIED: &str = "Mon, 01 Jan 2024 00:00:00 GMT";
+
+/// SHA-256 of "hello world", as hex and as a `Repr-Digest` header
+const HELLO_SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+const HELLO_REPR_DIGEST: &str = "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:";
+
+/// Local file with `contents`, last modified long before `MIRROR_LAST_MODIFIED`
+fn old_local_file(dir: &tempfile::TempDir, contents: &str) -> std::path::PathBuf {
+    let path = dir.path().join("mirrored.txt");
+    std::fs::write(&path, contents).unwrap();
+    let old_time = std::time::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(old_time)).unwrap();
+    path
+}
+
+fn mirror_part(path: &std::path::Path) -> std::path::PathBuf {
+    std::path::PathBuf::from(format!("{}.wgetf-mirror", path.display()))
+}
+
+#[tokio::test]
+async fn test_mirror_file_unchanged_remote_not_transferred() {
+    let server = TestServer::start([route("/mirrored.txt")
+        .body("hello world")
+        .header("last-modified", MIRROR_LAST_MODIFIED)
+        .header("repr-digest", HELLO_REPR_DIGEST)])
+    .await
+    .unwrap();
+    let url = server.url_for("/mirrored.txt");
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mirrored.txt");
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+
+    let outcome = downloader
+        .mirror_file(&url, &path, MirrorOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        outcome,
+        MirrorOutcome::Updated {
+            replaced: None,
+            resumed_from: 0,
+            transferred: 11,
+            checksum: Checksum::Sha256(HELLO_SHA256.to_string()),
+            verified: true,
+        }
+    );
+    let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+    assert_eq!(mtime, httpdate::parse_http_date(MIRROR_LAST_MODIFIED).unwrap());
+
+    let outcome = downloader
+        .mirror_file(&url, &path, MirrorOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        outcome,
+        MirrorOutcome::Unchanged {
+            decision: TimestampDecision::Unchanged
+        }
+    );
+    assert!(!outcome.changed());
+    assert_eq!(server.hits(&hyper::Method::GET, "/mirrored.txt"), 1);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
+}
+
+#[tokio::test]
+async fn test_mirror_file_changed_remote_replaced() {
+    let server = TestServer::start([route("/mirrored.txt")
+        .body("hello world")
+        .header("last-modified", MIRROR_LAST_MODIFIED)])
+    .await
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = old_local_file(&dir, "old contents");
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+
+    let outcome = downloader
+        .mirror_file(&server.url_for("/mirrored.txt"), &path, MirrorOptions::default())
+        .await
+        .unwrap();
+
+    let MirrorOutcome::Updated {
+        replaced, verified, ..
+    } = outcome
+    else {
+        panic!("expected an update, got {outcome:?}");
+    };
+    assert_eq!(replaced, Some(TimestampDecision::RemoteNewer));
+    assert!(!verified, "the server sent no digest");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
+    assert!(!mirror_part(&path).exists());
+}
+
+#[tokio::test]
+async fn test_mirror_file_resumes_interrupted_fetch() {
+    let body: Vec<u8> = (0..100u8).collect();
+    let server = TestServer::start([route("/mirrored.bin")
+        .body(body.clone())
+        .ranges(true)
+        .etag("v1")
+        .chunk_size(10)
+        .drop_after(40)])
+    .await
+    .unwrap();
+    let url = server.url_for("/mirrored.bin");
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mirrored.bin");
+    let downloader = Downloader::new(DownloadConfig {
+        retry: wget_faster_lib::RetryConfig {
+            max_retries: 0,
+            ..wget_faster_lib::RetryConfig::default()
+        },
+        ..DownloadConfig::default()
+    })
+    .unwrap();
+
+    // The first call is cut off and leaves the partial file, not `path`
+    let error = downloader
+        .mirror_file(&url, &path, MirrorOptions::default())
+        .await;
+    assert!(error.is_err());
+    assert!(!path.exists());
+    assert_eq!(std::fs::read(mirror_part(&path)).unwrap(), body[..40]);
+
+    let outcome = downloader
+        .mirror_file(&url, &path, MirrorOptions::default())
+        .await
+        .unwrap();
+    let MirrorOutcome::Updated {
+        resumed_from,
+        transferred,
+        ..
+    } = outcome
+    else {
+        panic!("expected an update, got {outcome:?}");
+    };
+    assert_eq!((resumed_from, transferred), (40, 60));
+    assert_eq!(std::fs::read(&path).unwrap(), body);
+    assert!(!mirror_part(&path).exists());
+
+    let resumed = server.requests().pop().unwrap();
+    assert_eq!(resumed.headers["range"], "bytes=40-");
+    assert_eq!(resumed.headers["if-range"], "\"v1\"");
+}
+
+#[tokio::test]
+async fn test_mirror_file_digest_mismatch_keeps_old_file() {
+    let server = TestServer::start([route("/mirrored.txt")
+        .body("hello, tampered world")
+        .header("last-modified", MIRROR_LAST_MODIFIED)
+        .header("repr-digest", HELLO_REPR_DIGEST)])
+    .await
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = old_local_file(&dir, "old contents");
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+
+    let result = downloader
+        .mirror_file(&server.url_for("/mirrored.txt"), &path, MirrorOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(Error::ChecksumMismatch { .. })), "{result:?}");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "old contents");
+    assert!(!mirror_part(&path).exists());
+}
+
+/// A WARC record: its header fields and block
+struct WarcRecord {
+    fields: Vec<(String, String)>,
+    block: Vec<u8>,
+}
+
+impl WarcRecord {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The HTTP body in the block of a request or response record
+    fn payload(&self) -> &[u8] {
+        let end = self
+            .block
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .unwrap();
+        &self.block[end + 4..]
+    }
+}
+
+/// Every record of a `.warc.gz` file, checking each is its own gzip member
+fn read_warc(path: &std::path::Path) -> Vec<WarcRecord> {
+    use std::io::Read;
+    let compressed = std::fs::read(path).unwrap();
+    let mut data = Vec::new();
+    flate2::read::MultiGzDecoder::new(&compressed[..])
+        .read_to_end(&mut data)
+        .unwrap();
+    let members = compressed
+        .windows(3)
+        .filter(|w| w == &[0x1f, 0x8b, 0x08])
+        .count();
+
+    let mut records = Vec::new();
+    let mut rest = &data[..];
+    while !rest.is_empty() {
+        let end = rest.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = std::str::from_utf8(&rest[..end]).unwrap();
+        let mut lines = head.split("\r\n");
+        assert_eq!(lines.next(), Some("WARC/1.1"));
+        let fields: Vec<(String, String)> = lines
+            .map(|line| {
+                let (name, value) = line.split_once(": ").unwrap();
+                (name.to_string(), value.to_string())
+            })
+            .collect();
+        let record = WarcRecord {
+            fields,
+            block: Vec::new(),
+        };
+        let length: usize = record.field("Content-Length").unwrap().parse().unwrap();
+        let block = rest[end + 4..end + 4 + length].to_vec();
+        assert_eq!(&rest[end + 4 + length..end + 8 + length], b"\r\n\r\n");
+        rest = &rest[end + 8 + length..];
+        records.push(WarcRecord { block, ..record });
+    }
+    assert_eq!(members, records.len());
+    records
+}
+
+/// `sha1:` and the base32 SHA-1 of `data`, as WARC digests are written
+fn warc_sha1(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, data);
+    // 160 bits are exactly 32 base32 symbols
+    let bits = digest.as_ref().iter().fold(Vec::new(), |mut bits, byte| {
+        bits.extend((0..8).rev().map(|shift| (byte >> shift) & 1));
+        bits
+    });
+    let symbols: String = bits
+        .chunks(5)
+        .map(|chunk| ALPHABET[chunk.iter().fold(0, |v, bit| v << 1 | bit) as usize] as char)
+        .collect();
+    format!("sha1:{symbols}")
+}
+
+#[tokio::test]
+async fn test_warc_records_download() {
+    let body = "archived body\n".repeat(100);
+    let server = TestServer::start([route("/file.txt")
+        .body(body.clone())
+        .header("content-type", "text/plain")])
+    .await
+    .unwrap();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let warc = temp_dir.path().join("out.warc.gz");
+    let config = DownloadConfig {
+        warc_file: Some(warc.clone()),
+        headers: [("X-Test".to_string(), "warc".to_string())].into(),
+        ..DownloadConfig::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let url = server.url_for("/file.txt");
+    let output = temp_dir.path().join("file.txt");
+    downloader
+        .download_to_file(&url, output.clone())
+        .await
+        .unwrap();
+    drop(downloader);
+
+    let records = read_warc(&warc);
+    let warcinfo = &records[0];
+    assert_eq!(warcinfo.field("WARC-Type"), Some("warcinfo"));
+    assert_eq!(warcinfo.field("WARC-Filename"), Some("out.warc.gz"));
+    assert!(String::from_utf8_lossy(&warcinfo.block).contains("format: WARC File Format 1.1"));
+    let warcinfo_id = warcinfo.field("WARC-Record-ID").unwrap();
+
+    for record in &records {
+        let id = record.field("WARC-Record-ID").unwrap();
+        assert!(id.starts_with("<urn:uuid:") && id.len() == 47, "{id}");
+        let date = record.field("WARC-Date").unwrap();
+        assert!(chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%SZ").is_ok());
+        assert_eq!(record.field("WARC-Block-Digest"), Some(warc_sha1(&record.block).as_str()));
+    }
+    let ids: std::collections::HashSet<_> =
+        records.iter().map(|r| r.field("WARC-Record-ID")).collect();
+    assert_eq!(ids.len(), records.len());
+
+    // The GET and its response, recorded as a pair
+    let get = records
+        .iter()
+        .position(|r| r.field("WARC-Type") == Some("request") && r.block.starts_with(b"GET "))
+        .unwrap();
+    let (request, response) = (&records[get], &records[get + 1]);
+    let sent = String::from_utf8_lossy(&request.block);
+    assert!(sent.starts_with("GET /file.txt HTTP/1.1\r\n"), "{sent}");
+    assert!(sent.contains("x-test: warc\r\n") && sent.contains("user-agent: "), "{sent}");
+    assert_eq!(request.field("WARC-Target-URI"), Some(url.as_str()));
+    assert_eq!(request.field("Content-Type"), Some("application/http;msgtype=request"));
+    assert_eq!(request.field("WARC-Warcinfo-ID"), Some(warcinfo_id));
+    assert_eq!(response.field("WARC-Type"), Some("response"));
+    assert_eq!(request.field("WARC-Concurrent-To"), response.field("WARC-Record-ID"));
+    assert_eq!(response.field("WARC-Target-URI"), Some(url.as_str()));
+    assert_eq!(response.field("WARC-IP-Address"), Some("127.0.0.1"));
+    assert!(response.block.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    assert_eq!(response.payload(), body.as_bytes());
+    assert_eq!(response.field("WARC-Payload-Digest"), Some(warc_sha1(body.as_bytes()).as_str()));
+    assert_eq!(response.field("WARC-Truncated"), None);
+    assert_eq!(std::fs::read_to_string(output).unwrap(), body);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_download_writes_through_symlink_given_as_path() {
+    let mut server = Server::new_async().await;
+    let _mock = server
+        .mock("GET", "/file.txt")
+        .with_status(200)
+        .with_body("downloaded")
+        .create_async()
+        .await;
+
+    // Like `-O link.txt`: the path is the caller's, so its symlink is kept
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("target.txt");
+    let link = dir.path().join("link.txt");
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    downloader
+        .download_to_file(&format!("{}/file.txt", server.url()), link.clone())
+        .await
+        .unwrap();
+    assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "downloaded");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_download_to_dev_stdout_keeps_it() {
+    let stdout = std::path::Path::new("/dev/stdout");
+    let Ok(before) = std::fs::symlink_metadata(stdout) else {
+        return;
+    };
+    let mut server = Server::new_async().await;
+    let _mock = server
+        .mock("GET", "/file.txt")
+        .with_status(200)
+        .with_body("\n")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let _ = downloader
+        .download_to_file(&format!("{}/file.txt", server.url()), stdout.to_path_buf())
+        .await;
+    let after = std::fs::symlink_metadata(stdout).unwrap();
+    assert_eq!(after.file_type(), before.file_type());
+}
@@ -0,0 +1,150 @@
+use mockito::{Matcher, Server};
+use std::time::Duration;
+use wget_faster_lib::{DownloadConfig, Downloader, LinkStatus};
+
+#[tokio::test]
+async fn test_check_links_classifications() {
+    let mut server = Server::new_async().await;
+
+    let ok = server
+        .mock("HEAD", "/ok")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .expect(2) // direct check and redirect target
+        .create_async()
+        .await;
+    let moved = server
+        .mock("HEAD", "/old")
+        .with_status(301)
+        .with_header("location", "/ok")
+        .create_async()
+        .await;
+    let missing = server
+        .mock("HEAD", "/missing")
+        .with_status(404)
+        .create_async()
+        .await;
+    let broken = server
+        .mock("HEAD", "/broken")
+        .with_status(500)
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let urls = vec![
+        format!("{}/ok", server.url()),
+        format!("{}/old", server.url()),
+        format!("{}/missing", server.url()),
+        format!("{}/broken", server.url()),
+    ];
+    let results = downloader.check_links(urls.clone(), 4).await;
+
+    // Input order is preserved
+    let returned: Vec<_> = results.iter().map(|r| r.url.clone()).collect();
+    assert_eq!(returned, urls);
+
+    assert_eq!(results[0].classification, LinkStatus::Ok);
+    assert_eq!(results[0].status, Some(200));
+    assert_eq!(results[0].content_type.as_deref(), Some("text/html"));
+
+    assert_eq!(results[1].classification, LinkStatus::Redirected);
+    assert_eq!(results[1].status, Some(200));
+    assert_eq!(results[1].final_url.as_deref(), Some(urls[0].as_str()));
+
+    assert_eq!(results[2].classification, LinkStatus::ClientError);
+    assert_eq!(results[2].status, Some(404));
+    assert!(results[2].is_broken());
+
+    assert_eq!(results[3].classification, LinkStatus::ServerError);
+    assert!(results[3].is_broken());
+
+    ok.assert_async().await;
+    moved.assert_async().await;
+    missing.assert_async().await;
+    broken.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_check_links_falls_back_to_ranged_get() {
+    let mut server = Server::new_async().await;
+
+    let head = server
+        .mock("HEAD", "/no-head")
+        .with_status(405)
+        .create_async()
+        .await;
+    let get = server
+        .mock("GET", "/no-head")
+        .match_header("range", Matcher::Exact("bytes=0-0".to_string()))
+        .with_status(206)
+        .with_header("content-range", "bytes 0-0/1000")
+        .with_body("x")
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let results = downloader
+        .check_links(vec![format!("{}/no-head", server.url())], 1)
+        .await;
+
+    assert_eq!(results[0].classification, LinkStatus::Ok);
+    assert_eq!(results[0].status, Some(200));
+
+    head.assert_async().await;
+    get.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_check_links_timeout() {
+    // Accepts connections (via the listen backlog) but never responds
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = DownloadConfig {
+        timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    let results = downloader
+        .check_links(vec![format!("http://{addr}/slow")], 1)
+        .await;
+
+    assert_eq!(results[0].classification, LinkStatus::Timeout);
+    assert_eq!(results[0].status, None);
+    assert!(results[0].error.is_some());
+
+    drop(listener);
+}
+
+#[tokio::test]
+async fn test_check_links_progress_counts() {
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("HEAD", Matcher::Regex("^/page/\\d+$".to_string()))
+        .with_status(200)
+        .expect(5)
+        .create_async()
+        .await;
+
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let urls: Vec<String> = (0..5)
+        .map(|i| format!("{}/page/{i}", server.url()))
+        .collect();
+
+    let counts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let counts_cb = counts.clone();
+    let results = downloader
+        .check_links_with_progress(
+            urls,
+            2,
+            std::sync::Arc::new(move |done, total| counts_cb.lock().unwrap().push((done, total))),
+        )
+        .await;
+
+    assert!(results.iter().all(|r| !r.is_broken()));
+    let mut counts = counts.lock().unwrap().clone();
+    counts.sort_unstable();
+    assert_eq!(counts, (1..=5).map(|d| (d, 5)).collect::<Vec<_>>());
+
+    mock.assert_async().await;
+}
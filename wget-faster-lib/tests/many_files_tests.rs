@@ -0,0 +1,364 @@
+//! Crawls of sites with thousands of tiny files
+//!
+//! Kept in their own test binary: lowering the open file limit affects the
+//! whole process.
+use mockito::{Matcher, Server, ServerGuard};
+#[cfg(feature = "archive")]
+use std::collections::BTreeMap;
+use std::path::Path;
+use wget_faster_lib::{CrawlStopReason, DownloadConfig, RecursiveConfig, RecursiveDownloader};
+
+const SECTIONS: usize = 20;
+const FILES_PER_SECTION: usize = 100;
+
+/// Lower the soft limit on open files, as on a busy host or a small container
+fn limit_open_files(limit: u64) {
+    #[cfg(unix)]
+    {
+        use rustix::process::{getrlimit, setrlimit, Resource};
+        let mut rlimit = getrlimit(Resource::Nofile);
+        rlimit.current = Some(rlimit.maximum.map_or(limit, |max| max.min(limit)));
+        setrlimit(Resource::Nofile, rlimit).unwrap();
+    }
+    #[cfg(not(unix))]
+    let _ = limit;
+}
+
+/// A site whose index links to `SECTIONS` pages, each linking `FILES_PER_SECTION` tiny files
+async fn tiny_file_site() -> ServerGuard {
+    let mut server = Server::new_async().await;
+    let index: String = (0..SECTIONS)
+        .map(|s| format!("<a href=\"/s{s}/index.html\">section {s}</a>\n"))
+        .collect();
+    server
+        .mock("GET", "/")
+        .with_header("content-type", "text/html")
+        .with_body(format!("<html><body>{index}</body></html>"))
+        .create_async()
+        .await;
+    server
+        .mock("GET", Matcher::Regex(r"^/s\d+/index\.html$".to_string()))
+        .with_header("content-type", "text/html")
+        .with_body_from_request(|request| {
+            let section = request.path().trim_end_matches("/index.html").to_string();
+            let links: String = (0..FILES_PER_SECTION)
+                .map(|f| format!("<a href=\"{section}/f{f}.txt\">{f}</a>\n"))
+                .collect();
+            format!("<html><body>{links}</body></html>").into_bytes()
+        })
+        .create_async()
+        .await;
+    server
+        .mock("GET", Matcher::Regex(r"^/s\d+/f\d+\.txt$".to_string()))
+        .with_header("content-type", "text/plain")
+        .with_header("last-modified", "Sun, 01 Jan 2017 00:00:00 GMT")
+        .with_body_from_request(|request| request.path().as_bytes().to_vec())
+        .create_async()
+        .await;
+    server
+}
+
+fn crawl_config() -> RecursiveConfig {
+    RecursiveConfig {
+        max_depth: 3,
+        no_host_directories: true,
+        convert_links: true,
+        ..RecursiveConfig::default()
+    }
+}
+
+fn count_files(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                count_files(&entry.path())
+            } else {
+                1
+            }
+        })
+        .sum()
+}
+
+#[tokio::test]
+async fn test_crawl_thousands_of_tiny_files_under_low_fd_limit() {
+    limit_open_files(256);
+    let server = tiny_file_site().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut crawler = RecursiveDownloader::new(DownloadConfig::default(), crawl_config()).unwrap();
+    let files = crawler
+        .download_recursive(&format!("{}/", server.url()), dir.path())
+        .await
+        .unwrap();
+
+    let expected = 1 + SECTIONS * (1 + FILES_PER_SECTION);
+    assert_eq!(files.len(), expected);
+    assert_eq!(count_files(dir.path()), expected);
+    assert_eq!(std::fs::read(dir.path().join("s7/f42.txt")).unwrap(), b"/s7/f42.txt");
+    let section = std::fs::read_to_string(dir.path().join("s7/index.html")).unwrap();
+    // Converted links are relative to the output directory
+    assert!(section.contains("href=\"s7/f42.txt\""), "{section}");
+}
+
+#[cfg(feature = "pack")]
+#[tokio::test]
+async fn test_small_files_go_to_pack() {
+    use wget_faster_lib::{Pack, PACK_FILE_NAME, PACK_INDEX_NAME};
+
+    let server = tiny_file_site().await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let config = RecursiveConfig {
+        // Section pages are larger than this, the tiny files aren't
+        small_file_threshold: Some(64),
+        ..crawl_config()
+    };
+    let mut crawler = RecursiveDownloader::new(DownloadConfig::default(), config).unwrap();
+    let files = crawler
+        .download_recursive(&format!("{}/", server.url()), dir.path())
+        .await
+        .unwrap();
+
+    // Only the index and section pages were saved as files, unconverted
+    assert_eq!(files.len(), 1 + SECTIONS);
+    assert_eq!(count_files(dir.path()), 1 + SECTIONS + 2);
+    let section = std::fs::read_to_string(dir.path().join("s7/index.html")).unwrap();
+    assert!(section.contains("href=\"/s7/f42.txt\""), "{section}");
+
+    assert!(dir.path().join(PACK_FILE_NAME).is_file());
+    assert!(dir.path().join(PACK_INDEX_NAME).is_file());
+    let pack = Pack::open(dir.path()).await.unwrap();
+    assert_eq!(pack.entries().len(), SECTIONS * FILES_PER_SECTION);
+    let entry = pack.find(&format!("{}/s7/f42.txt", server.url())).unwrap();
+    assert_eq!(entry.path, Path::new("s7/f42.txt"));
+    assert_eq!(pack.read(entry).await.unwrap(), b"/s7/f42.txt");
+}
+
+/// Content of every file under `dir`, by `/`-separated path relative to `root`
+#[cfg(feature = "archive")]
+fn read_tree(root: &Path, dir: &Path, files: &mut BTreeMap<String, Vec<u8>>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            read_tree(root, &path, files);
+        } else {
+            let name = path
+                .strip_prefix(root)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            files.insert(name, std::fs::read(&path).unwrap());
+        }
+    }
+}
+
+/// Entries of a tar stream as (name, mtime, content), GNU long names resolved
+#[cfg(feature = "archive")]
+fn untar(mut data: &[u8]) -> Vec<(String, u64, Vec<u8>)> {
+    let field = |header: &[u8], range: std::ops::Range<usize>| {
+        let text = std::str::from_utf8(&header[range]).unwrap();
+        u64::from_str_radix(text.trim_end_matches('\0'), 8).unwrap()
+    };
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    while data[..512].iter().any(|&byte| byte != 0) {
+        let (header, rest) = data.split_at(512);
+        let size = usize::try_from(field(header, 124..136)).unwrap();
+        let content = rest[..size].to_vec();
+        data = &rest[size.div_ceil(512) * 512..];
+        if header[156] == b'L' {
+            long_name = Some(String::from_utf8(content[..size - 1].to_vec()).unwrap());
+            continue;
+        }
+        let name = long_name.take().unwrap_or_else(|| {
+            let end = header[..100]
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(100);
+            String::from_utf8(header[..end].to_vec()).unwrap()
+        });
+        entries.push((name, field(header, 136..148), content));
+    }
+    entries
+}
+
+/// Entries of a zip archive as (name, content), read through its central directory
+#[cfg(feature = "archive")]
+fn unzip(data: &[u8]) -> BTreeMap<String, Vec<u8>> {
+    use std::io::Read;
+
+    let u16_at = |at: usize| usize::from(u16::from_le_bytes([data[at], data[at + 1]]));
+    let u32_at = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap()) as usize;
+    let end = data.len() - 22;
+    assert_eq!(u32_at(end), 0x0605_4b50);
+    let mut at = u32_at(end + 16);
+    let mut files = BTreeMap::new();
+    for _ in 0..u16_at(end + 10) {
+        assert_eq!(u32_at(at), 0x0201_4b50);
+        let compressed = u32_at(at + 20);
+        let name_len = u16_at(at + 28);
+        let name = String::from_utf8(data[at + 46..at + 46 + name_len].to_vec()).unwrap();
+        let local = u32_at(at + 42);
+        let start = local + 30 + u16_at(local + 26) + u16_at(local + 28);
+        let mut content = Vec::new();
+        flate2::read::DeflateDecoder::new(&data[start..start + compressed])
+            .read_to_end(&mut content)
+            .unwrap();
+        files.insert(name, content);
+        at += 46 + name_len + u16_at(at + 30) + u16_at(at + 32);
+    }
+    files
+}
+
+#[cfg(feature = "archive")]
+#[tokio::test]
+async fn test_crawl_into_archive_matches_directory_crawl() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use wget_faster_lib::ArchiveFormat;
+
+    let server = tiny_file_site().await;
+    let start = format!("{}/", server.url());
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut crawler = RecursiveDownloader::new(DownloadConfig::default(), crawl_config()).unwrap();
+    let saved = crawler
+        .download_recursive(&start, dir.path())
+        .await
+        .unwrap();
+    let mut expected = BTreeMap::new();
+    read_tree(dir.path(), dir.path(), &mut expected);
+
+    let crawl_into = |format| {
+        let start = start.clone();
+        async move {
+            let dir = tempfile::tempdir().unwrap();
+            let config = RecursiveConfig {
+                archive_output: Some(format),
+                ..crawl_config()
+            };
+            let mut crawler = RecursiveDownloader::new(DownloadConfig::default(), config).unwrap();
+            let entries = crawler
+                .download_recursive(&start, dir.path())
+                .await
+                .unwrap();
+            // Only the archive is left in the output directory
+            let names: Vec<_> = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            assert_eq!(names, [format.default_file_name()]);
+            let archive = std::fs::read(dir.path().join(format.default_file_name())).unwrap();
+            (entries, archive)
+        }
+    };
+
+    let (entries, archive) = crawl_into(ArchiveFormat::TarGz).await;
+    assert_eq!(entries.len(), saved.len());
+    assert!(entries.contains(&Path::new("s7/f42.txt").to_path_buf()));
+    let mut tar = Vec::new();
+    GzDecoder::new(&archive[..]).read_to_end(&mut tar).unwrap();
+    let tar = untar(&tar);
+    let (_, mtime, _) = tar.iter().find(|(name, ..)| name == "s7/f42.txt").unwrap();
+    assert_eq!(*mtime, 1_483_228_800);
+    let files: BTreeMap<_, _> = tar
+        .into_iter()
+        .map(|(name, _, content)| (name, content))
+        .collect();
+    // Same files, links converted the same way
+    assert_eq!(files, expected);
+
+    let (entries, archive) = crawl_into(ArchiveFormat::Zip).await;
+    assert_eq!(entries.len(), saved.len());
+    assert_eq!(unzip(&archive), expected);
+}
+
+/// A site of `pages` pages where `/p{n}.html` links to `/p{10n+1}.html` .. `/p{10n+10}.html`
+async fn page_tree_site(pages: usize) -> ServerGuard {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", Matcher::Regex(r"^/p\d+\.html$".to_string()))
+        .with_header("content-type", "text/html")
+        .with_body_from_request(move |request| {
+            let page: usize = request.path()[2..]
+                .trim_end_matches(".html")
+                .parse()
+                .unwrap();
+            let links: String = (10 * page + 1..=10 * page + 10)
+                .take_while(|&child| child < pages)
+                .map(|child| format!("<a href=\"/p{child}.html\">{child}</a>\n"))
+                .collect();
+            format!("<html><body>{links}</body></html>").into_bytes()
+        })
+        .create_async()
+        .await;
+    server
+}
+
+/// Crawl a [`page_tree_site`] of `pages` pages with at most `max_tracked_urls` URLs tracked
+async fn crawl_page_tree(
+    pages: usize,
+    max_tracked_urls: Option<usize>,
+) -> (RecursiveDownloader, usize) {
+    let server = page_tree_site(pages).await;
+    let dir = tempfile::tempdir().unwrap();
+    let config = RecursiveConfig {
+        max_depth: 0,
+        max_tracked_urls,
+        ..crawl_config()
+    };
+    let mut crawler = RecursiveDownloader::new(DownloadConfig::default(), config).unwrap();
+    let files = crawler
+        .download_recursive(&format!("{}/p0.html", server.url()), dir.path())
+        .await
+        .unwrap();
+
+    let page = std::fs::read_to_string(dir.path().join("p1.html")).unwrap();
+    assert!(page.contains("href=\"p11.html\""), "{page}");
+    (crawler, files.len())
+}
+
+#[tokio::test]
+async fn test_max_tracked_urls_stops_crawl() {
+    let (crawler, saved) = crawl_page_tree(500, Some(120)).await;
+
+    let stats = crawler.stats();
+    assert_eq!(saved, 120);
+    assert_eq!(stats.tracked_urls, 120);
+    assert_eq!(stats.converter_entries, 120);
+    assert_eq!(stats.stop_reason, Some(CrawlStopReason::MaxTrackedUrls(120)));
+    assert!(stats.tracked_bytes > 120 * "http://127.0.0.1/p0.html".len());
+}
+
+#[tokio::test]
+async fn test_limit_met_by_last_page_is_not_a_stop() {
+    let (crawler, saved) = crawl_page_tree(300, Some(300)).await;
+
+    assert_eq!(saved, 300);
+    assert_eq!(crawler.stats().tracked_urls, 300);
+    assert_eq!(crawler.stats().stop_reason, None);
+}
+
+#[tokio::test]
+#[ignore = "crawls 100k pages; run with --ignored"]
+async fn test_stress_hundred_thousand_pages() {
+    const PAGES: usize = 100_000;
+    const CAP: usize = 60_000;
+
+    let (crawler, saved) = crawl_page_tree(PAGES, None).await;
+    let stats = crawler.stats();
+    assert_eq!(saved, PAGES);
+    assert_eq!(stats.tracked_urls, PAGES);
+    assert_eq!(stats.converter_entries, PAGES);
+    assert_eq!(stats.stop_reason, None);
+    // URLs are stored once, not per map
+    assert!(stats.tracked_bytes < PAGES * 200, "{}", stats.tracked_bytes);
+
+    let (crawler, saved) = crawl_page_tree(PAGES, Some(CAP)).await;
+    let stats = crawler.stats();
+    assert_eq!(saved, CAP);
+    assert_eq!(stats.tracked_urls, CAP);
+    assert_eq!(stats.stop_reason, Some(CrawlStopReason::MaxTrackedUrls(CAP)));
+}
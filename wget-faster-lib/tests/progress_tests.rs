@@ -8,7 +8,7 @@ fn test_progress_info_creation() {
     assert_eq!(progress.url, "https://example.com/file.zip");
     assert_eq!(progress.downloaded, 0);
     assert!(progress.total_size.is_none());
-    assert_eq!(progress.speed, 0.0);
+    assert_eq!(progress.speed_average, 0.0);
 }
 
 #[test]
@@ -40,7 +40,7 @@ fn test_progress_eta() {
 
     progress.downloaded = 50;
     progress.total_size = Some(100);
-    progress.speed = 10.0; // 10 bytes per second
+    progress.speed_average = 10.0; // 10 bytes per second
     progress.eta = Some(Duration::from_secs(5)); // Manually set ETA for testing
 
     assert!(progress.eta.is_some());
@@ -56,7 +56,7 @@ fn test_progress_eta_zero_speed() {
 
     progress.downloaded = 50;
     progress.total_size = Some(100);
-    progress.speed = 0.0;
+    progress.speed_average = 0.0;
     progress.eta = None; // No ETA when speed is zero
 
     assert!(progress.eta.is_none());
@@ -68,7 +68,7 @@ fn test_progress_eta_unknown_total() {
 
     progress.downloaded = 50;
     progress.total_size = None;
-    progress.speed = 10.0;
+    progress.speed_average = 10.0;
     progress.eta = None; // No ETA when total size is unknown
 
     assert!(progress.eta.is_none());
@@ -150,7 +150,7 @@ fn test_progress_format_total_unknown() {
 fn test_progress_format_speed() {
     let mut progress = ProgressInfo::new("https://example.com/file.zip".to_string());
 
-    progress.speed = 1024.0 * 1024.0; // 1 MB/s
+    progress.speed_average = 1024.0 * 1024.0; // 1 MB/s
 
     let formatted = progress.format_speed();
     assert_eq!(formatted, "1.00MB/s");
@@ -1,6 +1,11 @@
-use mockito::Server;
+use mockito::{Matcher, Server};
+use std::sync::{Arc, Mutex};
 use tempfile::TempDir;
-use wget_faster_lib::{DownloadConfig, RecursiveConfig, RecursiveDownloader};
+use wget_faster_lib::{
+    CrawlProgress, CrawlProgressCallback, DownloadConfig, Error, FormLogin, LoginSuccessCheck,
+    PathMapper, PathMapperFn, ProvenanceConfig, ProvenanceRecord, RecursiveConfig,
+    RecursiveDownloader,
+};
 
 #[tokio::test]
 async fn test_recursive_config_defaults() {
@@ -219,6 +224,60 @@ async fn test_query_string_preservation() {
     assert_eq!(parsed.query(), Some("id=123&sort=asc"));
 }
 
+#[cfg(unix)]
+#[tokio::test]
+async fn test_executable_mode_follows_each_files_content_type() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut server = Server::new_async().await;
+    let index = format!(
+        r#"<html><a href="{0}/bin/run.sh">run</a><a href="{0}/notes.txt">notes</a></html>"#,
+        server.url()
+    );
+    let files = [
+        ("/", "text/html", index.as_str()),
+        ("/bin/run.sh", "application/x-sh", "#!/bin/sh\n"),
+        ("/notes.txt", "text/plain", "notes"),
+    ];
+    let mut mocks = Vec::new();
+    for (path, content_type, body) in files {
+        for method in ["HEAD", "GET"] {
+            let mock = server
+                .mock(method, path)
+                .with_status(200)
+                .with_header("content-type", content_type)
+                .with_body(body)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+    }
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = DownloadConfig {
+        file_mode: Some(0o644),
+        executable_if_content_type: vec!["application/x-sh".to_string()],
+        ..Default::default()
+    };
+    let recursive_config = RecursiveConfig {
+        no_host_directories: true,
+        ..Default::default()
+    };
+    let mut downloader = RecursiveDownloader::new(config, recursive_config).unwrap();
+    downloader
+        .download_recursive(&server.url(), temp_dir.path())
+        .await
+        .unwrap();
+
+    let mode = |path: &str| {
+        let metadata = std::fs::metadata(temp_dir.path().join(path)).unwrap();
+        metadata.permissions().mode() & 0o777
+    };
+    assert_eq!(mode("bin/run.sh"), 0o755);
+    assert_eq!(mode("notes.txt"), 0o644);
+    assert_eq!(mode("index.html"), 0o644);
+}
+
 #[tokio::test]
 async fn test_recursive_with_links() {
     let mut server = Server::new_async().await;
@@ -297,3 +356,1974 @@ async fn test_recursive_with_links() {
 
     drop(page1_mock);
 }
+
+/// Set up a minimal CSRF login flow: GET /login serves a form with a token,
+/// POST /session only accepts the right token and credentials and sets a cookie,
+/// and / is only served with that cookie.
+async fn csrf_login_server(server: &mut mockito::ServerGuard) -> Vec<mockito::Mock> {
+    let login_page = r#"<html><body>
+        <form method="post" action="/session">
+          <input type="hidden" name="csrf" value="tok-42">
+          <input name="user"><input type="password" name="pass">
+        </form></body></html>"#;
+
+    vec![
+        server
+            .mock("GET", "/login")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(login_page)
+            .create_async()
+            .await,
+        server
+            .mock("POST", "/session")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("csrf".into(), "tok-42".into()),
+                Matcher::UrlEncoded("user".into(), "alice".into()),
+                Matcher::UrlEncoded("pass".into(), "right".into()),
+            ]))
+            .with_status(200)
+            .with_header("set-cookie", "sid=s3ss10n; Path=/")
+            .with_body("welcome")
+            .create_async()
+            .await,
+        server
+            .mock("POST", "/session")
+            .with_status(403)
+            .with_body("bad credentials")
+            .expect_at_most(1)
+            .create_async()
+            .await,
+        server
+            .mock("GET", "/")
+            .match_header("cookie", Matcher::Regex("sid=s3ss10n".into()))
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html><body>members only</body></html>")
+            .create_async()
+            .await,
+    ]
+}
+
+fn login_config(server_url: &str, password: &str) -> RecursiveConfig {
+    let mut login = FormLogin::new(&format!("{server_url}/login"), "alice", password);
+    login.username_field = "user".to_string();
+    login.password_field = "pass".to_string();
+    login.success_check = LoginSuccessCheck::Cookie("sid".to_string());
+
+    let mut config = RecursiveConfig::default();
+    config.max_depth = 1;
+    config.form_login = Some(login);
+    config
+}
+
+#[tokio::test]
+async fn test_form_login_before_crawl() {
+    let mut server = Server::new_async().await;
+    let _mocks = csrf_login_server(&mut server).await;
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), login_config(&server.url(), "right"))
+            .unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let files = downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    assert_eq!(files.len(), 1);
+    let content = std::fs::read_to_string(&files[0]).unwrap();
+    assert!(content.contains("members only"));
+}
+
+#[tokio::test]
+async fn test_form_login_wrong_credentials() {
+    let mut server = Server::new_async().await;
+    let _mocks = csrf_login_server(&mut server).await;
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), login_config(&server.url(), "wrong"))
+            .unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let result = downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await;
+
+    assert!(matches!(result, Err(Error::LoginFailed { status: 403, .. })));
+}
+
+#[tokio::test]
+async fn test_robots_retried_after_transient_failure() {
+    let mut server = Server::new_async().await;
+
+    let index_html = r#"<html><body>
+        <a href="/a.html">A</a>
+        <a href="/b.html">B</a>
+    </body></html>"#;
+
+    server
+        .mock("GET", "/")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(index_html)
+        .create_async()
+        .await;
+
+    // First robots.txt request fails transiently, later ones disallow everything
+    let robots_unavailable = server
+        .mock("GET", "/robots.txt")
+        .with_status(503)
+        .expect(1)
+        .create_async()
+        .await;
+    let robots_disallow = server
+        .mock("GET", "/robots.txt")
+        .with_status(200)
+        .with_body("User-agent: *\nDisallow: /\n")
+        .expect_at_least(1)
+        .create_async()
+        .await;
+
+    let page_a = server
+        .mock("GET", "/a.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body("<p>A</p>")
+        .expect(1)
+        .create_async()
+        .await;
+    let page_b = server
+        .mock("GET", "/b.html")
+        .with_status(200)
+        .with_body("<p>B</p>")
+        .expect(0)
+        .create_async()
+        .await;
+
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        robots_retry_delay: std::time::Duration::ZERO,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    // a.html was fetched while robots.txt was unavailable; b.html is blocked once it loads
+    robots_unavailable.assert_async().await;
+    robots_disallow.assert_async().await;
+    page_a.assert_async().await;
+    page_b.assert_async().await;
+
+    let stats = downloader.stats();
+    assert_eq!(stats.robots_temporary_failures, 1);
+    assert_eq!(stats.robots_retries, 1);
+    assert_eq!(stats.robots_fetches, 2);
+}
+
+/// File names saved anywhere under `dir`
+fn saved_file_names(dir: &std::path::Path) -> Vec<String> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            names.extend(saved_file_names(&path));
+        } else {
+            names.push(path.file_name().unwrap().to_string_lossy().into_owned());
+        }
+    }
+    names
+}
+
+/// Serve an index linking to `/download?id=9` (redirects to `/files/report.pdf`)
+/// and `/export` (names `notes.txt` in Content-Disposition), crawl it with
+/// `recursive_config` and return the crawler and the saved file names
+async fn crawl_final_name_site(
+    recursive_config: RecursiveConfig,
+) -> (RecursiveDownloader, Vec<String>) {
+    let mut server = Server::new_async().await;
+
+    server
+        .mock("GET", "/")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<html><body><a href="/download?id=9">PDF</a><a href="/export">Export</a></body></html>"#)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/download")
+        .match_query(Matcher::UrlEncoded("id".into(), "9".into()))
+        .with_status(302)
+        .with_header("location", "/files/report.pdf")
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/files/report.pdf")
+        .with_status(200)
+        .with_header("content-type", "application/pdf")
+        .with_body("%PDF-1.4")
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/export")
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .with_header("content-disposition", "attachment; filename=\"notes.txt\"")
+        .with_body("notes")
+        .create_async()
+        .await;
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    let names = saved_file_names(temp_dir.path());
+    (downloader, names)
+}
+
+#[tokio::test]
+async fn test_reject_applies_to_redirect_target() {
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        reject_extensions: vec!["pdf".to_string()],
+        ..Default::default()
+    };
+    let (downloader, names) = crawl_final_name_site(recursive_config).await;
+
+    // The PDF behind /download?id=9 is dropped, the text export is kept
+    assert_eq!(names.len(), 2, "saved: {names:?}");
+    assert!(names.iter().any(|n| n == "export"), "saved: {names:?}");
+    assert_eq!(downloader.stats().late_rejections, 1);
+}
+
+#[tokio::test]
+async fn test_accept_applies_to_final_names() {
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        accept_extensions: vec!["pdf".to_string()],
+        ..Default::default()
+    };
+    let (downloader, names) = crawl_final_name_site(recursive_config).await;
+
+    // The index page (HTML) and the PDF are kept; notes.txt is rejected by -A pdf
+    assert_eq!(names.len(), 2, "saved: {names:?}");
+    assert!(!names.iter().any(|n| n == "export"), "saved: {names:?}");
+    assert_eq!(downloader.stats().late_rejections, 1);
+}
+
+/// Serve an index linking to `/files/a1.txt`, `/files/b2.txt` and
+/// `/docs/c.txt`, crawl it with `recursive_config` and return the saved
+/// file names, sorted
+async fn crawl_regex_site(recursive_config: RecursiveConfig) -> Vec<String> {
+    let mut server = Server::new_async().await;
+
+    server
+        .mock("GET", "/")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<html><body><a href="/files/a1.txt">a</a><a href="/files/b2.txt">b</a><a href="/docs/c.txt">c</a></body></html>"#)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+    server
+        .mock("GET", Matcher::Regex(r"^/(files|docs)/".to_string()))
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .with_body("text")
+        .expect_at_least(0)
+        .create_async()
+        .await;
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    let mut names = saved_file_names(temp_dir.path());
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn test_accept_regex_matches_full_url() {
+    let names = crawl_regex_site(RecursiveConfig {
+        max_depth: 2,
+        accept_regex: Some(r"/files/.*[[:digit:]]\.txt$".to_string()),
+        ..Default::default()
+    })
+    .await;
+
+    // The starting page is always kept
+    assert_eq!(names, ["a1.txt", "b2.txt", "index.html"]);
+}
+
+#[tokio::test]
+async fn test_reject_regex_matches_full_url() {
+    let names = crawl_regex_site(RecursiveConfig {
+        max_depth: 2,
+        reject_regex: Some(r"^http://[^/]+/files/b".to_string()),
+        ..Default::default()
+    })
+    .await;
+
+    assert_eq!(names, ["a1.txt", "c.txt", "index.html"]);
+}
+
+#[tokio::test]
+async fn test_accept_and_reject_regex_together() {
+    let names = crawl_regex_site(RecursiveConfig {
+        max_depth: 2,
+        accept_regex: Some(r"/files/".to_string()),
+        reject_regex: Some(r"b2".to_string()),
+        ..Default::default()
+    })
+    .await;
+
+    assert_eq!(names, ["a1.txt", "index.html"]);
+}
+
+#[test]
+fn test_invalid_url_regex_is_config_error() {
+    for recursive_config in [
+        RecursiveConfig {
+            accept_regex: Some("files/(".to_string()),
+            ..Default::default()
+        },
+        RecursiveConfig {
+            reject_regex: Some("files/[z-a]".to_string()),
+            ..Default::default()
+        },
+    ] {
+        let result = RecursiveDownloader::new(DownloadConfig::default(), recursive_config);
+        assert!(matches!(result, Err(Error::ConfigError(_))));
+    }
+}
+
+#[tokio::test]
+async fn test_noindex_recorded_and_nofollow_from_header() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<html><head><meta name="robots" content="noindex"></head><body><a href="/a.html">a</a></body></html>"#)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/a.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_header("x-robots-tag", "otherbot: noindex")
+        .with_body(r#"<html><body><a href="/b.html">b</a></body></html>"#)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/b.html")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_header("x-robots-tag", "none")
+        .with_body(r#"<html><body><a href="/c.html">c</a></body></html>"#)
+        .create_async()
+        .await;
+    let unfollowed = server
+        .mock("GET", "/c.html")
+        .with_status(200)
+        .expect(0)
+        .create_async()
+        .await;
+
+    let recursive_config = RecursiveConfig {
+        max_depth: 5,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    // Pages marked noindex are still saved and crawled; none also stops following
+    let base = server.url();
+    assert_eq!(downloader.noindex_pages(), [format!("{base}/"), format!("{base}/b.html")]);
+    assert_eq!(downloader.stats().noindex_pages, 2);
+    let mut noindex: Vec<_> = downloader
+        .download_origins()
+        .values()
+        .map(|origin| (origin.url.clone(), origin.noindex))
+        .collect();
+    noindex.sort();
+    assert_eq!(
+        noindex,
+        [
+            (format!("{base}/"), true),
+            (format!("{base}/a.html"), false),
+            (format!("{base}/b.html"), true),
+        ]
+    );
+    unfollowed.assert_async().await;
+}
+
+/// Crawl a site whose index links to `links`, every other path serving the same body
+///
+/// Returns the downloader and the number of non-index page requests.
+async fn crawl_duplicate_site(
+    links: &[&str],
+    recursive_config: RecursiveConfig,
+) -> (RecursiveDownloader, usize) {
+    let mut server = Server::new_async().await;
+
+    let anchors: String = links
+        .iter()
+        .map(|link| format!(r#"<a href="{link}">link</a>"#))
+        .collect();
+    server
+        .mock("GET", "/")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(format!("<html><body>{anchors}</body></html>"))
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+
+    let hits = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = hits.clone();
+    server
+        .mock("GET", Matcher::Regex(r"^/(page|item)".to_string()))
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .with_body_from_request(move |_| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            b"same content".to_vec()
+        })
+        .expect_at_least(0)
+        .create_async()
+        .await;
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    let count = hits.load(std::sync::atomic::Ordering::SeqCst);
+    (downloader, count)
+}
+
+#[tokio::test]
+async fn test_tracking_params_fetched_once() {
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        strip_query_params: vec!["utm_*".to_string(), "fbclid".to_string()],
+        ..Default::default()
+    };
+    let (downloader, hits) = crawl_duplicate_site(
+        &[
+            "/page?utm_source=news",
+            "/page?utm_source=mail&utm_medium=email",
+            "/page?fbclid=abc#comments",
+            "/page",
+        ],
+        recursive_config,
+    )
+    .await;
+
+    assert_eq!(hits, 1);
+    assert_eq!(downloader.stats().duplicate_fetches_avoided, 3);
+}
+
+#[tokio::test]
+async fn test_tracking_params_kept_in_request_by_default() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<a href="/page?utm_source=news&id=7">x</a>"#)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+    let with_params = server
+        .mock("GET", "/page")
+        .match_query(Matcher::UrlEncoded("utm_source".into(), "news".into()))
+        .with_status(200)
+        .with_body("page")
+        .expect(1)
+        .create_async()
+        .await;
+
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        strip_query_params: vec!["utm_*".to_string()],
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    with_params.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_strip_from_request() {
+    let mut server = Server::new_async().await;
+    server
+        .mock("GET", "/")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(r#"<a href="/page?utm_source=news&id=7">x</a>"#)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+    let stripped = server
+        .mock("GET", "/page")
+        .match_query(Matcher::Exact("id=7".into()))
+        .with_status(200)
+        .with_body("page")
+        .expect(1)
+        .create_async()
+        .await;
+
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        strip_query_params: vec!["utm_*".to_string()],
+        strip_from_request: true,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    stripped.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_session_param_detected_from_identical_bodies() {
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        session_param_detection: true,
+        ..Default::default()
+    };
+    let (downloader, hits) = crawl_duplicate_site(
+        &[
+            "/item?sid=a1&id=1",
+            "/item?sid=b2&id=1",
+            "/item?sid=c3&id=1",
+            "/item?sid=d4&id=1",
+        ],
+        recursive_config,
+    )
+    .await;
+
+    // The second fetch reveals `sid`; the remaining variants are skipped
+    assert_eq!(hits, 2);
+    let stats = downloader.stats();
+    assert_eq!(stats.session_params_detected, 1);
+    assert_eq!(stats.duplicate_fetches_avoided, 2);
+}
+
+/// A three-page listing linked only through `Link: rel="next"` headers
+async fn paginated_listing(server: &mut mockito::ServerGuard) -> Vec<mockito::Mock> {
+    let mut mocks = Vec::new();
+    for page in 1..=3 {
+        let mut mock = server
+            .mock("GET", format!("/list/{page}").as_str())
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(format!("<html><body>Page {page}</body></html>"))
+            .expect(1);
+        if page > 1 {
+            mock = mock.with_header("link", &format!("<{}>; rel=\"prev\"", page - 1));
+        }
+        if page < 3 {
+            mock = mock.with_header("link", &format!("<{}>; rel=\"next\"", page + 1));
+        }
+        mocks.push(mock.create_async().await);
+    }
+    mocks
+}
+
+#[tokio::test]
+async fn test_pagination_followed_from_link_header() {
+    let mut server = Server::new_async().await;
+    let mocks = paginated_listing(&mut server).await;
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), RecursiveConfig::default()).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let files = downloader
+        .download_recursive(&format!("{}/list/1", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    assert_eq!(files.len(), 3);
+    for mock in mocks {
+        mock.assert_async().await;
+    }
+}
+
+#[tokio::test]
+async fn test_pagination_not_followed_when_disabled() {
+    let mut server = Server::new_async().await;
+    let _mocks = paginated_listing(&mut server).await;
+
+    let recursive_config = RecursiveConfig {
+        follow_pagination: false,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let files = downloader
+        .download_recursive(&format!("{}/list/1", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    assert_eq!(files.len(), 1);
+}
+
+#[tokio::test]
+async fn test_crawl_progress_snapshots() {
+    let mut server = Server::new_async().await;
+    let _mocks = paginated_listing(&mut server).await;
+
+    let snapshots: Arc<Mutex<Vec<CrawlProgress>>> = Arc::default();
+    let recorded = snapshots.clone();
+    let config = RecursiveConfig {
+        crawl_progress: Some(CrawlProgressCallback(Arc::new(move |progress| {
+            recorded.lock().unwrap().push(progress);
+        }))),
+        ..RecursiveConfig::default()
+    };
+    let mut downloader = RecursiveDownloader::new(DownloadConfig::default(), config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/list/1", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    let snapshots = snapshots.lock().unwrap();
+    // One snapshot per queue item, including the rel="prev" links back to visited pages
+    assert_eq!(snapshots.len(), 5);
+    assert!(snapshots.windows(2).all(|w| w[0].visited <= w[1].visited));
+    assert!(snapshots
+        .windows(2)
+        .all(|w| w[0].bytes_downloaded <= w[1].bytes_downloaded && w[0].elapsed <= w[1].elapsed));
+    assert_eq!(snapshots[0].queued, 1);
+
+    let last = snapshots.last().unwrap();
+    assert_eq!(last.visited, 3);
+    assert_eq!(last.queued, 0);
+    assert_eq!(last.depths, vec![1, 1, 1]);
+    assert_eq!(last.bytes_downloaded, downloader.stats().bytes_downloaded);
+    assert!(last.bytes_downloaded > 0);
+}
+
+/// A small site served for both GET and HEAD: a page without an extension,
+/// requisites, a PDF, a broken link and pages beyond depth 3
+async fn mirror_site(server: &mut mockito::ServerGuard) {
+    let pages = [
+        (
+            "/",
+            200,
+            "text/html",
+            r#"<html><head><link rel="stylesheet" href="/style.css"></head><body>
+                <a href="/about">About</a> <a href="/missing">Gone</a>
+                <a href="/manual">Manual</a> <img src="/logo.png"></body></html>"#,
+        ),
+        (
+            "/about",
+            200,
+            "text/html",
+            r#"<html><body><a href="/team.html">Team</a></body></html>"#,
+        ),
+        (
+            "/team.html",
+            200,
+            "text/html",
+            r#"<html><body><a href="/history">History</a></body></html>"#,
+        ),
+        ("/history", 200, "text/html", "<html><body>Long ago</body></html>"),
+        ("/manual", 200, "application/pdf", "%PDF-1.4"),
+        ("/style.css", 200, "text/css", "body { color: black }"),
+        ("/logo.png", 200, "image/png", "PNG"),
+        ("/missing", 404, "text/html", "Not found"),
+        ("/robots.txt", 404, "text/plain", ""),
+    ];
+    for (path, status, content_type, body) in pages {
+        for method in ["GET", "HEAD"] {
+            server
+                .mock(method, path)
+                .with_status(status)
+                .with_header("content-type", content_type)
+                .with_body(body)
+                .create_async()
+                .await;
+        }
+    }
+}
+
+/// URLs visited by a crawl of `mirror_site`, in order
+async fn visited_urls(spider: bool) -> Vec<String> {
+    let mut server = Server::new_async().await;
+    mirror_site(&mut server).await;
+
+    let visited: Arc<Mutex<Vec<String>>> = Arc::default();
+    let recorded = visited.clone();
+    let last_count = Mutex::new(0);
+    let config = RecursiveConfig {
+        spider,
+        max_depth: 3,
+        page_requisites: true,
+        adjust_extension: true,
+        crawl_progress: Some(CrawlProgressCallback(Arc::new(move |progress| {
+            let mut last_count = last_count.lock().unwrap();
+            if progress.visited > *last_count {
+                *last_count = progress.visited;
+                let path = progress.current_url.rsplit_once('/').unwrap().1.to_string();
+                recorded.lock().unwrap().push(format!("/{path}"));
+            }
+        }))),
+        ..RecursiveConfig::default()
+    };
+    let mut downloader = RecursiveDownloader::new(DownloadConfig::default(), config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    assert_eq!(downloader.broken_links().len(), 1);
+    let visited = visited.lock().unwrap().clone();
+    visited
+}
+
+#[tokio::test]
+async fn test_spider_visits_same_urls_as_download() {
+    let downloaded = visited_urls(false).await;
+    let spidered = visited_urls(true).await;
+
+    assert_eq!(
+        downloaded,
+        vec![
+            "/",
+            "/about",
+            "/missing",
+            "/manual",
+            "/logo.png",
+            "/style.css",
+            "/team.html"
+        ]
+    );
+    assert_eq!(spidered, downloaded);
+}
+
+/// Sitemap index -> two child sitemaps (one gzipped) -> three pages
+async fn sitemap_site(server: &mut mockito::ServerGuard) -> Vec<mockito::Mock> {
+    use std::io::Write;
+
+    let base = server.url();
+    let index = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>{base}/sitemap-posts.xml</loc></sitemap>
+  <sitemap><loc>{base}/sitemap-pages.xml.gz</loc></sitemap>
+</sitemapindex>"#
+    );
+    let posts = format!(
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>{base}/posts/one.html</loc><lastmod>2024-01-10</lastmod></url>
+  <url><loc>{base}/posts/two.html</loc><lastmod>2024-01-11T08:00:00+00:00</lastmod></url>
+</urlset>"#
+    );
+    let pages = format!(
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>{base}/about.html</loc><lastmod>2024-02-01</lastmod></url>
+  <url><loc>http://other.example/offsite.html</loc><lastmod>2024-02-01</lastmod></url>
+</urlset>"#
+    );
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(pages.as_bytes()).unwrap();
+    let pages_gz = encoder.finish().unwrap();
+
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/sitemap.xml")
+        .with_status(200)
+        .with_header("content-type", "application/xml")
+        .with_body(index)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/sitemap-posts.xml")
+        .with_status(200)
+        .with_header("content-type", "application/xml")
+        .with_body(posts)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/sitemap-pages.xml.gz")
+        .with_status(200)
+        .with_header("content-type", "application/gzip")
+        .with_body(pages_gz)
+        .create_async()
+        .await;
+
+    let mut pages = Vec::new();
+    for path in ["/posts/one.html", "/posts/two.html", "/about.html"] {
+        pages.push(
+            server
+                .mock("GET", path)
+                .with_status(200)
+                .with_header("content-type", "text/html")
+                // Links in the pages themselves are never crawled
+                .with_body(r#"<html><a href="/not-in-sitemap.html">x</a></html>"#)
+                .expect(1)
+                .create_async()
+                .await,
+        );
+    }
+    pages
+}
+
+#[tokio::test]
+async fn test_download_from_sitemap_index() {
+    let mut server = Server::new_async().await;
+    let pages = sitemap_site(&mut server).await;
+    let not_listed = server
+        .mock("GET", "/not-in-sitemap.html")
+        .expect(0)
+        .create_async()
+        .await;
+    let temp_dir = TempDir::new().unwrap();
+    let sitemap_url = format!("{}/sitemap.xml", server.url());
+    let host_dir = temp_dir.path().join(
+        url::Url::parse(&server.url())
+            .unwrap()
+            .host_str()
+            .unwrap()
+            .to_string(),
+    );
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), RecursiveConfig::default()).unwrap();
+    let files = downloader
+        .download_from_sitemap(&sitemap_url, temp_dir.path())
+        .await
+        .unwrap();
+
+    let mut names: Vec<String> = files
+        .iter()
+        .map(|f| f.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["about.html", "one.html", "two.html"]);
+    assert!(files.iter().all(|f| f.starts_with(&host_dir)));
+    assert_eq!(downloader.stats().sitemaps_fetched, 3);
+
+    // Second run: every lastmod matches the local file, so nothing is downloaded again
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), RecursiveConfig::default()).unwrap();
+    let files = downloader
+        .download_from_sitemap(&sitemap_url, temp_dir.path())
+        .await
+        .unwrap();
+    assert!(files.is_empty());
+    assert_eq!(downloader.stats().sitemap_unchanged, 3);
+
+    for page in pages {
+        page.assert_async().await;
+    }
+    not_listed.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_download_from_sitemap_replaces_outdated_file() {
+    let mut server = Server::new_async().await;
+    let _pages = sitemap_site(&mut server).await;
+    let temp_dir = TempDir::new().unwrap();
+    let sitemap_url = format!("{}/sitemap.xml", server.url());
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), RecursiveConfig::default()).unwrap();
+    let files = downloader
+        .download_from_sitemap(&sitemap_url, temp_dir.path())
+        .await
+        .unwrap();
+    let about = files.iter().find(|f| f.ends_with("about.html")).unwrap();
+
+    // A local copy older than its lastmod is downloaded again and replaced
+    let stale = filetime::FileTime::from_unix_time(1_600_000_000, 0);
+    filetime::set_file_mtime(about, stale).unwrap();
+    let refetched = server
+        .mock("GET", "/about.html")
+        .with_status(200)
+        .with_body("new")
+        .expect(1)
+        .create_async()
+        .await;
+
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), RecursiveConfig::default()).unwrap();
+    let files = downloader
+        .download_from_sitemap(&sitemap_url, temp_dir.path())
+        .await
+        .unwrap();
+
+    assert_eq!(files, vec![about.clone()]);
+    assert_eq!(std::fs::read_to_string(about).unwrap(), "new");
+    refetched.assert_async().await;
+}
+
+/// Crawl a site where `/docs` is a page and `/docs/intro.html` lives below it,
+/// linked from the index in the given order, and return the saved files
+async fn crawl_page_and_directory(links: [&str; 2]) -> (TempDir, Vec<std::path::PathBuf>) {
+    let mut server = Server::new_async().await;
+    let index = format!(
+        r#"<html><body><a href="{}">A</a> <a href="{}">B</a></body></html>"#,
+        links[0], links[1]
+    );
+    let pages = [
+        ("/", index.as_str()),
+        ("/docs", "<html><body>Docs overview</body></html>"),
+        ("/docs/intro.html", "<html><body>Introduction</body></html>"),
+    ];
+    for (path, body) in pages {
+        server
+            .mock("GET", path)
+            .with_header("content-type", "text/html")
+            .with_body(body)
+            .create_async()
+            .await;
+    }
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+
+    let recursive_config = RecursiveConfig {
+        max_depth: 3,
+        convert_links: true,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let files = downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+    (temp_dir, files)
+}
+
+#[tokio::test]
+async fn test_page_and_directory_with_same_name() {
+    for links in [["/docs", "/docs/intro.html"], ["/docs/intro.html", "/docs"]] {
+        let (temp_dir, files) = crawl_page_and_directory(links).await;
+
+        // Whichever comes first, the page ends up as docs/index.html and nothing is lost
+        let docs_page = files
+            .iter()
+            .find(|path| path.ends_with("docs/index.html"))
+            .unwrap_or_else(|| panic!("{links:?}: saved {files:?}"));
+        assert_eq!(
+            std::fs::read_to_string(docs_page).unwrap(),
+            "<html><body>Docs overview</body></html>"
+        );
+        let intro = docs_page.with_file_name("intro.html");
+        assert!(files.contains(&intro), "{links:?}: saved {files:?}");
+        assert_eq!(
+            std::fs::read_to_string(&intro).unwrap(),
+            "<html><body>Introduction</body></html>"
+        );
+        assert!(files.iter().all(|path| path.is_file()), "{links:?}: saved {files:?}");
+
+        // -k points the index's link to /docs at the moved page
+        let index_page = docs_page.parent().unwrap().with_file_name("index.html");
+        let index = std::fs::read_to_string(index_page).unwrap();
+        let converted = docs_page.strip_prefix(temp_dir.path()).unwrap();
+        assert!(
+            index.contains(&format!("href=\"{}\"", converted.display())),
+            "{links:?}: {index}"
+        );
+    }
+}
+
+/// Hint headers (`Accept`, `Accept-Language`, `Priority`, `Sec-Fetch-*`) received per path
+type HintHeaders = Arc<Mutex<std::collections::BTreeMap<String, Vec<(String, String)>>>>;
+
+fn record_hint_headers(received: &HintHeaders, request: &mockito::Request) {
+    let hints = request
+        .headers()
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            name == "accept"
+                || name == "accept-language"
+                || name == "priority"
+                || name.starts_with("sec-fetch-")
+        })
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+        .collect::<std::collections::BTreeMap<_, _>>();
+    received
+        .lock()
+        .unwrap()
+        .insert(request.path().to_string(), hints.into_iter().collect());
+}
+
+/// A page with an image and a stylesheet, recording the hint headers of each request
+async fn page_with_requisites(server: &mut mockito::ServerGuard) -> HintHeaders {
+    let received = HintHeaders::default();
+    let pages = [
+        (
+            "/",
+            "text/html",
+            r#"<html><head><link rel="stylesheet" href="/style.css"></head>
+            <body><img src="/logo.png"><a href="/about.html">About</a></body></html>"#,
+        ),
+        ("/about.html", "text/html", "<html><body>About</body></html>"),
+        ("/logo.png", "image/png", "PNG"),
+        ("/style.css", "text/css", "body {}"),
+    ];
+    for (path, content_type, body) in pages {
+        let received = Arc::clone(&received);
+        server
+            .mock("GET", path)
+            .with_header("content-type", content_type)
+            .with_body_from_request(move |request| {
+                record_hint_headers(&received, request);
+                body.as_bytes().to_vec()
+            })
+            .create_async()
+            .await;
+    }
+    received
+}
+
+fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_browser_preset_headers_for_pages_and_requisites() {
+    let mut server = Server::new_async().await;
+    let received = page_with_requisites(&mut server).await;
+    let temp_dir = TempDir::new().unwrap();
+
+    let download_config = DownloadConfig {
+        header_preset: Some(wget_faster_lib::HeaderPreset::Browser),
+        ..DownloadConfig::default()
+    };
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        page_requisites: true,
+        ..RecursiveConfig::default()
+    };
+    let mut downloader = RecursiveDownloader::new(download_config, recursive_config).unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    let received = received.lock().unwrap();
+    let page_accept =
+        "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8";
+    assert_eq!(
+        received["/"],
+        headers(&[
+            ("accept", page_accept),
+            ("accept-language", "en-US,en;q=0.9"),
+            ("priority", "u=1"),
+            ("sec-fetch-dest", "document"),
+            ("sec-fetch-mode", "navigate"),
+            ("sec-fetch-site", "none"),
+        ])
+    );
+    assert_eq!(
+        received["/about.html"],
+        headers(&[
+            ("accept", page_accept),
+            ("accept-language", "en-US,en;q=0.9"),
+            ("priority", "u=1"),
+            ("sec-fetch-dest", "document"),
+            ("sec-fetch-mode", "navigate"),
+            ("sec-fetch-site", "same-origin"),
+        ])
+    );
+    assert_eq!(
+        received["/logo.png"],
+        headers(&[
+            ("accept", "image/avif,image/webp,image/apng,image/svg+xml,image/*,*/*;q=0.8"),
+            ("accept-language", "en-US,en;q=0.9"),
+            ("priority", "u=5"),
+            ("sec-fetch-dest", "image"),
+            ("sec-fetch-mode", "no-cors"),
+            ("sec-fetch-site", "same-origin"),
+        ])
+    );
+    assert_eq!(
+        received["/style.css"],
+        headers(&[
+            ("accept", "text/css,*/*;q=0.1"),
+            ("accept-language", "en-US,en;q=0.9"),
+            ("priority", "u=5"),
+            ("sec-fetch-dest", "style"),
+            ("sec-fetch-mode", "no-cors"),
+            ("sec-fetch-site", "same-origin"),
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_explicit_headers_override_preset_and_priority() {
+    let mut server = Server::new_async().await;
+    let received = page_with_requisites(&mut server).await;
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut download_config = DownloadConfig {
+        header_preset: Some(wget_faster_lib::HeaderPreset::Minimal),
+        default_accept: Some("text/html;q=0.9,*/*;q=0.5".to_string()),
+        ..DownloadConfig::default()
+    };
+    download_config
+        .headers
+        .insert("Priority".to_string(), "u=3".to_string());
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        page_requisites: true,
+        requisite_priority: None,
+        ..RecursiveConfig::default()
+    };
+    let mut downloader = RecursiveDownloader::new(download_config, recursive_config).unwrap();
+    downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    let received = received.lock().unwrap();
+    let expected = headers(&[("accept", "text/html;q=0.9,*/*;q=0.5"), ("priority", "u=3")]);
+    assert_eq!(received["/"], expected);
+    assert_eq!(received["/logo.png"], expected);
+}
+
+#[tokio::test]
+async fn test_download_origins() {
+    let mut server = Server::new_async().await;
+    let base = server.url();
+    let pages = [
+        ("/", r#"<a href="/a.html">A</a> <a href="/b.html">B</a>"#),
+        ("/a.html", r#"<a href="/big.iso">ISO</a>"#),
+        ("/b.html", r#"<a href="/big.iso">ISO</a> <a href="/">Home</a>"#),
+    ];
+    for (path, body) in pages {
+        server
+            .mock("GET", path)
+            .with_header("content-type", "text/html")
+            .with_body(format!("<html><body>{body}</body></html>"))
+            .create_async()
+            .await;
+    }
+    server
+        .mock("GET", "/big.iso")
+        .with_header("content-type", "application/octet-stream")
+        .with_body("iso")
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+
+    let config = DownloadConfig {
+        write_provenance: Some(ProvenanceConfig::sidecar()),
+        ..DownloadConfig::default()
+    };
+    let mut downloader = RecursiveDownloader::new(config, RecursiveConfig::default()).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let files = downloader
+        .download_recursive(&format!("{base}/"), temp_dir.path())
+        .await
+        .unwrap();
+    let origins = downloader.download_origins();
+    assert_eq!(origins.len(), files.len());
+
+    let origin_of = |name: &str| {
+        let path = files.iter().find(|path| path.ends_with(name)).unwrap();
+        origins[path].clone()
+    };
+    let start = origin_of("index.html");
+    assert_eq!((start.referrer, start.depth), (None, 0));
+    assert_eq!(start.referral_count, 1);
+
+    let b = origin_of("b.html");
+    assert_eq!(b.referrer, Some(format!("{base}/")));
+    assert_eq!(b.depth, 1);
+
+    // Reachable from both pages: the first one found wins, both are counted
+    let iso = origin_of("big.iso");
+    assert_eq!(iso.referrer, Some(format!("{base}/a.html")));
+    assert_eq!(iso.depth, 2);
+    assert_eq!(iso.referrers, vec![format!("{base}/a.html"), format!("{base}/b.html")]);
+    assert_eq!(iso.referral_count, 2);
+
+    let iso_path = files.iter().find(|path| path.ends_with("big.iso")).unwrap();
+    let sidecar = format!("{}.provenance.json", iso_path.display());
+    let record: ProvenanceRecord =
+        serde_json::from_str(&std::fs::read_to_string(sidecar).unwrap()).unwrap();
+    assert_eq!(record.referrer, Some(format!("{base}/a.html")));
+    assert_eq!(record.depth, Some(2));
+}
+
+#[tokio::test]
+async fn test_endless_bodies_time_out_or_are_skipped() {
+    use std::time::Duration;
+    use wget_faster_lib::test_server::{route, TestServer};
+
+    let index = r#"<html><body>
+        <a href="/live.log">log</a> <a href="/events">events</a> <a href="/a.html">a</a>
+    </body></html>"#;
+    let server = TestServer::start([
+        route("/").body(index).header("content-type", "text/html"),
+        route("/live.log")
+            .body("tick\n")
+            .header("content-type", "text/plain")
+            .endless()
+            .delay_per_chunk(Duration::from_millis(20)),
+        route("/events")
+            .body("data: x\n\n")
+            .header("content-type", "text/event-stream; charset=utf-8")
+            .endless(),
+        route("/a.html")
+            .body("<html></html>")
+            .header("content-type", "text/html"),
+    ])
+    .await
+    .unwrap();
+
+    let temp_dir = TempDir::new().unwrap();
+    let log = temp_dir.path().join("rejected.csv");
+    let recursive_config = RecursiveConfig {
+        max_requisite_duration: Some(Duration::from_millis(300)),
+        no_host_directories: true,
+        rejected_log: Some(log.clone()),
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let started = std::time::Instant::now();
+    downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(downloader.timed_out_urls(), [server.url_for("/live.log")]);
+    assert_eq!(downloader.stats().timed_out, 1);
+    assert_eq!(downloader.stats().late_rejections, 1);
+    assert!(!temp_dir.path().join("live.log").exists());
+    assert!(!temp_dir.path().join("events").exists());
+    assert!(temp_dir.path().join("a.html").exists());
+    let log = std::fs::read_to_string(log).unwrap();
+    assert!(log.lines().any(|line| line.starts_with("TIMEOUT\t")), "{log}");
+}
+
+/// Two-character shard directory of a URL path
+fn shard(path: &str) -> String {
+    let hash = path
+        .bytes()
+        .fold(0u8, |hash, byte| hash.wrapping_mul(31).wrapping_add(byte));
+    format!("{hash:02x}")
+}
+
+#[tokio::test]
+async fn test_path_mapper_shards_files_and_converts_links() {
+    let mut server = Server::new_async().await;
+    let index = r#"<html><body><a href="/about.html">About</a><img src="/logo.png"><a href="/escape.txt">E</a></body></html>"#;
+    let pages = [
+        ("/", "text/html", index),
+        ("/about.html", "text/html", "<html><body>About</body></html>"),
+        ("/logo.png", "image/png", "png"),
+        ("/escape.txt", "text/plain", "escape"),
+    ];
+    for (path, content_type, body) in pages {
+        server
+            .mock("GET", path)
+            .with_header("content-type", content_type)
+            .with_body(body)
+            .create_async()
+            .await;
+    }
+    server
+        .mock("GET", "/robots.txt")
+        .with_status(404)
+        .create_async()
+        .await;
+
+    let requisites = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&requisites);
+    let mapper: PathMapperFn = Arc::new(move |url, context| {
+        if context.is_requisite {
+            seen.lock().unwrap().push(url.path().to_string());
+        }
+        if url.path() == "/escape.txt" {
+            return Some("../escape.txt".into());
+        }
+        let name = context.default_path.file_name()?;
+        Some(context.output_dir.join(shard(url.path())).join(name))
+    });
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        page_requisites: true,
+        convert_links: true,
+        adjust_extension: true,
+        path_mapper: Some(PathMapper(mapper)),
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let files = downloader
+        .download_recursive(&format!("{}/", server.url()), temp_dir.path())
+        .await
+        .unwrap();
+
+    let index_path = temp_dir.path().join(shard("/")).join("index.html");
+    let about_path = temp_dir
+        .path()
+        .join(shard("/about.html"))
+        .join("about.html");
+    let logo_path = temp_dir.path().join(shard("/logo.png")).join("logo.png");
+    for path in [&index_path, &about_path, &logo_path] {
+        assert!(files.contains(path), "{} not in {files:?}", path.display());
+    }
+    assert_eq!(*requisites.lock().unwrap(), ["/logo.png"]);
+
+    // A path outside the output directory falls back to the default one
+    let host = url::Url::parse(&server.url()).unwrap();
+    let escape_path = temp_dir
+        .path()
+        .join(host.host_str().unwrap())
+        .join("escape.txt");
+    assert!(files.contains(&escape_path), "{files:?}");
+    assert!(!temp_dir
+        .path()
+        .parent()
+        .unwrap()
+        .join("escape.txt")
+        .exists());
+
+    // Converted links name the mapped files (relative to the output directory)
+    let converted = std::fs::read_to_string(&index_path).unwrap();
+    for (attribute, path) in [("href", &about_path), ("src", &logo_path)] {
+        let link = path
+            .strip_prefix(temp_dir.path())
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(converted.contains(&format!(r#"{attribute}="{link}""#)), "{converted}");
+    }
+}
+
+/// Site whose start page links a page, an image and a stylesheet; the
+/// stylesheet references images, a font and another stylesheet
+async fn requisites_site() -> wget_faster_lib::test_server::TestServer {
+    use wget_faster_lib::test_server::{route, TestServer};
+
+    let index = r#"<html><head><link rel="stylesheet" href="/css/site.css"></head>
+        <body><a href="/about.html">About</a><img src="/logo.png"></body></html>"#;
+    let site_css = r#"@import "print.css";
+        body { background: url("../img/bg.png"); }
+        .icon { background: url(data:image/png;base64,iVBORw0KGgo=); }
+        @font-face { font-family: F; src: url('/fonts/f.woff2'); }"#;
+    TestServer::start([
+        route("/").body(index).header("content-type", "text/html"),
+        route("/about.html")
+            .body(r#"<html><body><img src="/deep.png"></body></html>"#)
+            .header("content-type", "text/html"),
+        route("/css/site.css")
+            .body(site_css)
+            .header("content-type", "text/css"),
+        route("/css/print.css")
+            .body("h1 { background: url(h1.png) }")
+            .header("content-type", "text/css"),
+        route("/logo.png").body("logo"),
+        route("/img/bg.png").body("bg"),
+        route("/css/h1.png").body("h1"),
+        route("/fonts/f.woff2").body("font"),
+        route("/deep.png").body("deep"),
+    ])
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_page_requisites_exempt_from_max_depth() {
+    let server = requisites_site().await;
+    let get = |path| server.hits(&http::Method::GET, path);
+
+    // Without -p the last level's requisites are cut off like its pages
+    let temp_dir = TempDir::new().unwrap();
+    let recursive_config = RecursiveConfig {
+        max_depth: 1,
+        ..Default::default()
+    };
+    RecursiveDownloader::new(DownloadConfig::default(), recursive_config)
+        .unwrap()
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+    assert_eq!(get("/logo.png"), 0);
+
+    let temp_dir = TempDir::new().unwrap();
+    let recursive_config = RecursiveConfig {
+        max_depth: 1,
+        page_requisites: true,
+        ..Default::default()
+    };
+    RecursiveDownloader::new(DownloadConfig::default(), recursive_config)
+        .unwrap()
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+    for path in [
+        "/logo.png",
+        "/css/site.css",
+        "/img/bg.png",
+        "/css/print.css",
+    ] {
+        assert_eq!(get(path), 1, "{path}");
+    }
+    // Pages past the limit, and what they link to, are still skipped
+    assert_eq!(get("/about.html"), 0);
+    assert_eq!(get("/deep.png"), 0);
+}
+
+#[tokio::test]
+async fn test_stylesheet_references_are_downloaded() {
+    let server = requisites_site().await;
+    let temp_dir = TempDir::new().unwrap();
+    let recursive_config = RecursiveConfig {
+        max_depth: 5,
+        page_requisites: true,
+        no_host_directories: true,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+
+    for (path, contents) in [
+        ("img/bg.png", "bg"),
+        ("fonts/f.woff2", "font"),
+        ("css/print.css", "h1 { background: url(h1.png) }"),
+        ("css/h1.png", "h1"),
+    ] {
+        let saved = std::fs::read_to_string(temp_dir.path().join(path));
+        assert_eq!(saved.ok().as_deref(), Some(contents), "{path}");
+    }
+    assert_eq!(downloader.stats().skipped_links.data, 1);
+}
+
+#[tokio::test]
+async fn test_quota_stops_crawl() {
+    use wget_faster_lib::CrawlStopReason;
+
+    let server = requisites_site().await;
+    let temp_dir = TempDir::new().unwrap();
+    let download_config = DownloadConfig {
+        quota: Some(10),
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(download_config, RecursiveConfig::default()).unwrap();
+    let files = downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+
+    // The start page finishes; none of its links start
+    assert_eq!(files.len(), 1);
+    assert_eq!(server.hits(&http::Method::GET, "/about.html"), 0);
+    assert_eq!(downloader.stats().stop_reason, Some(CrawlStopReason::QuotaExceeded(10)));
+}
+
+#[tokio::test]
+async fn test_quota_cuts_crawl_midway() {
+    use wget_faster_lib::test_server::{route, TestServer};
+    use wget_faster_lib::CrawlStopReason;
+
+    let index = r#"<a href="a.txt">a</a><a href="b.txt">b</a><a href="c.txt">c</a>"#;
+    let robots = "User-agent: *\nDisallow:\n";
+    let server = TestServer::start([
+        route("/").body(index).header("content-type", "text/html"),
+        route("/robots.txt").body(robots),
+        route("/a.txt").body(vec![b'a'; 100]),
+        route("/b.txt").body(vec![b'b'; 100]),
+        route("/c.txt").body(vec![b'c'; 100]),
+    ])
+    .await
+    .unwrap();
+    // Reached halfway through b.txt, which still finishes
+    let quota = (index.len() + robots.len() + 150) as u64;
+    let temp_dir = TempDir::new().unwrap();
+    let download_config = DownloadConfig {
+        quota: Some(quota),
+        ..Default::default()
+    };
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        ..Default::default()
+    };
+    let mut downloader = RecursiveDownloader::new(download_config, recursive_config).unwrap();
+    downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+
+    assert_eq!(downloader.quota_used(), (index.len() + robots.len() + 200) as u64);
+    assert_eq!(downloader.stats().stop_reason, Some(CrawlStopReason::QuotaExceeded(quota)));
+    let host = temp_dir
+        .path()
+        .join(url::Url::parse(server.url()).unwrap().host_str().unwrap());
+    assert!(host.join("a.txt").exists());
+    assert!(host.join("b.txt").exists());
+    assert!(!host.join("c.txt").exists());
+    assert_eq!(server.hits(&http::Method::GET, "/c.txt"), 0);
+}
+
+#[tokio::test]
+async fn test_saved_headers_crawl_converts_body_only() {
+    use wget_faster_lib::saved_headers;
+    use wget_faster_lib::test_server::{route, TestServer};
+
+    let server = TestServer::start([
+        route("/")
+            .body(r#"<a href="/docs/page.html">page</a>"#)
+            .header("content-type", "text/html"),
+        route("/docs/page.html")
+            .body("<p>page</p>")
+            .header("content-type", "text/html"),
+    ])
+    .await
+    .unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let download_config = DownloadConfig {
+        save_headers: true,
+        ..Default::default()
+    };
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        convert_links: true,
+        ..Default::default()
+    };
+    let mut downloader = RecursiveDownloader::new(download_config, recursive_config).unwrap();
+    let files = downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+
+    assert_eq!(files.len(), 2);
+    let index = files
+        .iter()
+        .find(|path| path.ends_with("index.html"))
+        .unwrap();
+    let saved = std::fs::read(index).unwrap();
+    let (headers, body_start) = saved_headers::split(&saved);
+    assert_eq!(headers.unwrap().headers["content-type"], "text/html");
+    // The link in the body now points to the saved page
+    let page = files
+        .iter()
+        .find(|path| path.ends_with("docs/page.html"))
+        .unwrap();
+    let converted = page.strip_prefix(temp_dir.path()).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&saved[body_start..]),
+        format!(r#"<a href="{}">page</a>"#, converted.display())
+    );
+}
+
+#[tokio::test]
+async fn test_convert_file_only_keeps_links_as_written() {
+    use wget_faster_lib::test_server::{route, TestServer};
+    use wget_faster_lib::ConversionMode;
+
+    let server = TestServer::start([
+        route("/")
+            .body(r#"<a href="/docs/page.php#top">page</a><img src="logo.png">"#)
+            .header("content-type", "text/html"),
+        route("/docs/page.php")
+            .body("<p>page</p>")
+            .header("content-type", "text/html"),
+        route("/logo.png").body("logo"),
+    ])
+    .await
+    .unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        convert_links: true,
+        adjust_extension: true,
+        conversion_mode: ConversionMode::FileOnly,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let files = downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+
+    // Only the file names change, to those of the saved files
+    let index = files
+        .iter()
+        .find(|path| path.ends_with("index.html"))
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(index).unwrap(),
+        r#"<a href="/docs/page.php.html#top">page</a><img src="logo.png">"#
+    );
+}
+
+/// Crawl a page linking two files on a host asking for `Crawl-delay: 1`
+async fn crawl_with_crawl_delay(ignore_robots_delay: bool) -> (std::time::Duration, u64) {
+    use wget_faster_lib::test_server::{route, TestServer};
+
+    let server = TestServer::start([
+        route("/")
+            .body(r#"<a href="a.txt">a</a><a href="b.txt">b</a>"#)
+            .header("content-type", "text/html"),
+        route("/robots.txt").body("User-agent: *\nCrawl-delay: 1\n"),
+        route("/a.txt").body("a"),
+        route("/b.txt").body("b"),
+    ])
+    .await
+    .unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        ignore_robots_delay,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let started = std::time::Instant::now();
+    let files = downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+    assert_eq!(files.len(), 3);
+    (started.elapsed(), downloader.stats().host_waits)
+}
+
+#[tokio::test]
+async fn test_crawl_delay_spaces_requests_to_host() {
+    let (elapsed, host_waits) = crawl_with_crawl_delay(false).await;
+    // robots.txt is only known once the links are checked: a.txt starts the
+    // delay and b.txt waits it out
+    assert!(elapsed >= std::time::Duration::from_secs(1), "{elapsed:?}");
+    assert_eq!(host_waits, 1);
+}
+
+#[tokio::test]
+async fn test_ignore_robots_delay() {
+    let (elapsed, host_waits) = crawl_with_crawl_delay(true).await;
+    assert!(elapsed < std::time::Duration::from_secs(1), "{elapsed:?}");
+    assert_eq!(host_waits, 0);
+}
+
+#[tokio::test]
+async fn test_transient_page_failure_retried_while_crawl_continues() {
+    use std::time::Duration;
+    use wget_faster_lib::test_server::{route, TestServer};
+    use wget_faster_lib::RetryConfig;
+
+    let slow = |path: &str| {
+        route(path)
+            .body("slow")
+            .delay_per_chunk(Duration::from_millis(100))
+    };
+    let server = TestServer::start([
+        route("/")
+            .body(
+                r#"<a href="flaky.txt">f</a><a href="a.txt">a</a><a href="b.txt">b</a><a href="c.txt">c</a>"#,
+            )
+            .header("content-type", "text/html"),
+        route("/flaky.txt")
+            .body("finally")
+            .status_sequence([503, 503, 200]),
+        slow("/a.txt"),
+        slow("/b.txt"),
+        slow("/c.txt"),
+    ])
+    .await
+    .unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let download_config = DownloadConfig {
+        retry: RetryConfig {
+            initial_delay: Duration::from_millis(20),
+            backoff_multiplier: 1.0,
+            ..RetryConfig::default()
+        },
+        ..DownloadConfig::default()
+    };
+    let recursive_config = RecursiveConfig {
+        max_depth: 2,
+        ..Default::default()
+    };
+    let mut downloader = RecursiveDownloader::new(download_config, recursive_config).unwrap();
+    let files = downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+
+    let flaky = files
+        .iter()
+        .find(|path| path.ends_with("flaky.txt"))
+        .unwrap();
+    assert_eq!(std::fs::read_to_string(flaky).unwrap(), "finally");
+    assert!(downloader.broken_links().is_empty());
+    assert_eq!(downloader.download_origins()[flaky].attempts, 3);
+    let stats = downloader.stats();
+    assert_eq!(stats.fetch_retries, 2);
+    assert_eq!(stats.fetch_attempts[&server.url_for("/flaky.txt")], 3);
+
+    // Each retry waits its turn behind another download instead of blocking the crawl
+    let log: Vec<String> = server
+        .requests()
+        .into_iter()
+        .map(|r| r.path)
+        .filter(|path| path != "/robots.txt")
+        .collect();
+    assert_eq!(
+        log,
+        [
+            "/",
+            "/flaky.txt",
+            "/a.txt",
+            "/flaky.txt",
+            "/b.txt",
+            "/flaky.txt",
+            "/c.txt"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_recursive_pages_archived_to_one_warc() {
+    use std::io::Read;
+    use wget_faster_lib::test_server::{route, TestServer};
+    let server = TestServer::start([
+        route("/")
+            .body(r#"<html><a href="/a.html">a</a> <a href="/b.txt">b</a></html>"#)
+            .header("content-type", "text/html"),
+        route("/a.html")
+            .body("<html>page a</html>")
+            .header("content-type", "text/html"),
+        route("/b.txt")
+            .body("file b")
+            .header("content-type", "text/plain"),
+    ])
+    .await
+    .unwrap();
+
+    let temp_dir = TempDir::new().unwrap();
+    let warc = temp_dir.path().join("crawl.warc.gz");
+    let config = DownloadConfig {
+        warc_file: Some(warc.clone()),
+        ..DownloadConfig::default()
+    };
+    let recursive_config = RecursiveConfig {
+        no_host_directories: true,
+        ..Default::default()
+    };
+    let mut downloader = RecursiveDownloader::new(config, recursive_config).unwrap();
+    downloader
+        .download_recursive(&server.url_for("/"), &temp_dir.path().join("site"))
+        .await
+        .unwrap();
+    drop(downloader);
+
+    let mut archive = String::new();
+    flate2::read::MultiGzDecoder::new(std::fs::File::open(&warc).unwrap())
+        .read_to_string(&mut archive)
+        .unwrap();
+    assert_eq!(archive.matches("WARC-Type: warcinfo\r\n").count(), 1);
+    for (path, body) in [("/a.html", "<html>page a</html>"), ("/b.txt", "file b")] {
+        let target = format!("WARC-Target-URI: {}\r\n", server.url_for(path));
+        let response = archive
+            .split("WARC/1.1\r\n")
+            .find(|record| record.contains("WARC-Type: response") && record.contains(&target))
+            .unwrap_or_else(|| panic!("no response record for {path}"));
+        assert!(response.ends_with(&format!("\r\n\r\n{body}\r\n\r\n")), "{response}");
+    }
+}
+
+#[tokio::test]
+async fn test_delete_after_leaves_nothing_behind() {
+    use wget_faster_lib::test_server::{route, TestServer};
+    let server = TestServer::start([
+        route("/")
+            .body(r#"<html><a href="/docs/a.html">a</a></html>"#)
+            .header("content-type", "text/html"),
+        route("/docs/a.html")
+            .body(
+                r#"<html><link rel="stylesheet" href="/css/site.css"><a href="b.txt">b</a></html>"#,
+            )
+            .header("content-type", "text/html"),
+        route("/docs/b.txt").body("file b"),
+        route("/css/site.css")
+            .body("body { background: url(/img/bg.png) }")
+            .header("content-type", "text/css"),
+        route("/img/bg.png").body("png"),
+    ])
+    .await
+    .unwrap();
+
+    let temp_dir = TempDir::new().unwrap();
+    let recursive_config = RecursiveConfig {
+        delete_after: true,
+        convert_links: true,
+        page_requisites: true,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    let files = downloader
+        .download_recursive(&server.url_for("/"), temp_dir.path())
+        .await
+        .unwrap();
+
+    assert!(files.is_empty(), "{files:?}");
+    let left: Vec<_> = std::fs::read_dir(temp_dir.path()).unwrap().collect();
+    assert!(left.is_empty(), "{left:?}");
+    let mut fetched = downloader.fetched_urls().to_vec();
+    fetched.sort();
+    let paths = [
+        "/",
+        "/css/site.css",
+        "/docs/a.html",
+        "/docs/b.txt",
+        "/img/bg.png",
+    ];
+    assert_eq!(fetched, paths.map(|path| server.url_for(path)));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_planted_links_in_output_not_followed() {
+    use std::os::unix::fs::symlink;
+    use wget_faster_lib::test_server::{route, TestServer};
+    let server = TestServer::start([
+        route("/robots.txt").body("User-agent: *\nDisallow:\n"),
+        route("/")
+            .body(r#"<html><a href="/docs/a.html">a</a> <a href="/b.txt">b</a></html>"#)
+            .header("content-type", "text/html"),
+        route("/docs/a.html")
+            .body("<html>a</html>")
+            .header("content-type", "text/html"),
+        route("/b.txt").body("new b"),
+    ])
+    .await
+    .unwrap();
+
+    // A previous mirror with links pointing out of it
+    let temp_dir = TempDir::new().unwrap();
+    let (output, outside) = (temp_dir.path().join("mirror"), temp_dir.path().join("etc"));
+    std::fs::create_dir_all(&output).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    std::fs::write(outside.join("passwd"), "root").unwrap();
+    std::fs::write(outside.join("shadow"), "hash").unwrap();
+    symlink(&outside, output.join("docs")).unwrap();
+    symlink(outside.join("passwd"), output.join("robots.txt")).unwrap();
+    std::fs::hard_link(outside.join("shadow"), output.join("b.txt")).unwrap();
+
+    let recursive_config = RecursiveConfig {
+        no_host_directories: true,
+        ..Default::default()
+    };
+    let mut downloader =
+        RecursiveDownloader::new(DownloadConfig::default(), recursive_config).unwrap();
+    downloader
+        .download_recursive(&server.url_for("/"), &output)
+        .await
+        .unwrap();
+
+    let mut outside_files: Vec<_> = std::fs::read_dir(&outside)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    outside_files.sort();
+    assert_eq!(outside_files, ["passwd", "shadow"]);
+    assert_eq!(std::fs::read_to_string(outside.join("passwd")).unwrap(), "root");
+    assert_eq!(std::fs::read_to_string(outside.join("shadow")).unwrap(), "hash");
+
+    assert_eq!(downloader.stats().unsafe_paths_skipped, 1);
+    assert!(output.join("docs").is_symlink());
+    let robots = output.join("robots.txt");
+    assert!(!robots.is_symlink());
+    assert_eq!(std::fs::read_to_string(robots).unwrap(), "User-agent: *\nDisallow:\n");
+    let b = std::fs::metadata(output.join("b.txt")).unwrap();
+    assert_eq!(std::os::unix::fs::MetadataExt::nlink(&b), 1);
+    assert!(output.join("index.html").is_file());
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use wget_faster_lib::{DownloadConfig, Downloader, Error, TlsPolicy};
+
+/// Always presents the same certificate, whatever key it was issued for
+///
+/// ring can't sign with the 1024-bit key of `fixtures/tls/weak.pem`, so that
+/// certificate is paired with the EC key of `localhost.pem`. The client refuses
+/// the certificate before the handshake signature is checked.
+#[derive(Debug)]
+struct FixedCertificate(Arc<rustls::sign::CertifiedKey>);
+
+impl rustls::server::ResolvesServerCert for FixedCertificate {
+    fn resolve(
+        &self,
+        _client_hello: rustls::server::ClientHello<'_>,
+    ) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        Some(Arc::clone(&self.0))
+    }
+}
+
+/// https server on `localhost` presenting `certificate` (PEM, signed by
+/// `fixtures/tls/ca.pem`), answering every request with a short text
+async fn start_server(certificate: &[u8]) -> String {
+    let certs = CertificateDer::pem_slice_iter(certificate)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let key =
+        PrivateKeyDer::from_pem_slice(include_bytes!("fixtures/tls/localhost-key.pem")).unwrap();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let signing_key = provider.key_provider.load_private_key(key).unwrap();
+    let config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(FixedCertificate(Arc::new(rustls::sign::CertifiedKey::new(
+            certs,
+            signing_key,
+        )))));
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let service = hyper::service::service_fn(|_request| async {
+                    Ok::<_, std::convert::Infallible>(hyper::Response::new(
+                        http_body_util::Full::new(bytes::Bytes::from_static(b"secure")),
+                    ))
+                });
+                let _ = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+    format!("https://localhost:{port}/file.txt")
+}
+
+fn config(policy: TlsPolicy) -> DownloadConfig {
+    DownloadConfig {
+        ca_cert: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/ca.pem").into()),
+        tls_policy: Some(policy),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_strong_certificate_passes_and_reports_tls_details() {
+    let url = start_server(include_bytes!("fixtures/tls/localhost.pem")).await;
+    let downloader = Downloader::new(config(TlsPolicy::default())).unwrap();
+
+    let metadata = downloader.get_client().get_metadata(&url).await.unwrap();
+    let tls = metadata.tls_info.expect("TLS details of the response");
+    assert_eq!(tls.version.as_deref(), Some("TLSv1.3"));
+    assert_eq!(tls.chain[0].subject, "CN=localhost");
+    assert_eq!(tls.chain[0].issuer, "CN=wget-faster test CA");
+    assert_eq!(tls.chain[0].key_type, "EC P-256");
+    assert!(tls.warnings.is_empty(), "{:?}", tls.warnings);
+    assert!(!tls.ocsp_stapled);
+
+    let body = downloader.download_to_memory(&url).await.unwrap();
+    assert_eq!(&body[..], b"secure");
+}
+
+#[tokio::test]
+async fn test_small_key_is_a_tls_error() {
+    let url = start_server(include_bytes!("fixtures/tls/weak.pem")).await;
+    let downloader = Downloader::new(config(TlsPolicy::default())).unwrap();
+
+    let error = downloader.download_to_memory(&url).await.unwrap_err();
+    let Error::TlsError(reason) = error.root() else {
+        panic!("expected a TLS policy violation, got {error:?}");
+    };
+    assert_eq!(
+        reason,
+        "certificate 'CN=localhost' has a 1024-bit RSA key (policy minimum 2048)"
+    );
+    assert_eq!(error.exit_code(), 5);
+}
+
+#[tokio::test]
+async fn test_certificate_expiring_within_window_warns() {
+    let url = start_server(include_bytes!("fixtures/tls/localhost.pem")).await;
+    // The fixture expires in 2126: a window of two centuries covers it
+    let downloader = Downloader::new(config(TlsPolicy {
+        expiry_warning: Some(Duration::from_hours(200 * 365 * 24)),
+        ..TlsPolicy::default()
+    }))
+    .unwrap();
+
+    let metadata = downloader.get_client().get_metadata(&url).await.unwrap();
+    let tls = metadata.tls_info.as_ref().unwrap();
+    assert_eq!(tls.warnings, ["certificate 'CN=localhost' expires soon (2126-09-21T10:40:33Z)"]);
+    assert!(metadata
+        .format_headers()
+        .contains("  Warning: certificate 'CN=localhost' expires soon"));
+}
@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use wget_faster_lib::test_server::{route, TestServer};
+use wget_faster_lib::{
+    verify_local, DownloadConfig, Downloader, ProvenanceConfig, RemoteStatus, VerifyRecords,
+    VerifyStatus,
+};
+
+const FILES: [&str; 4] = ["a.txt", "b.bin", "docs/c.txt", "docs/deep/d.txt"];
+
+/// Serve `FILES` (each with an `ETag`) and download them with provenance sidecars into `dir`
+async fn download_tree(dir: &Path) -> TestServer {
+    let server = TestServer::start(FILES.iter().map(|name| {
+        route(format!("/{name}"))
+            .body(format!("contents of {name}\n").repeat(100))
+            .etag(format!("{name}-v1"))
+    }))
+    .await
+    .unwrap();
+
+    let config = DownloadConfig {
+        write_provenance: Some(ProvenanceConfig::sidecar()),
+        ..Default::default()
+    };
+    let downloader = Downloader::new(config).unwrap();
+    for name in FILES {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        downloader
+            .download_to_file(&server.url_for(&format!("/{name}")), path)
+            .await
+            .unwrap();
+    }
+    server
+}
+
+#[tokio::test]
+async fn test_verify_local_flags_only_the_corrupted_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let _server = download_tree(dir.path()).await;
+
+    // Same size, one byte flipped
+    let corrupted = dir.path().join("docs/c.txt");
+    let mut bytes = std::fs::read(&corrupted).unwrap();
+    bytes[100] ^= 1;
+    std::fs::write(&corrupted, bytes).unwrap();
+
+    let records = VerifyRecords::from_sidecars(dir.path()).await.unwrap();
+    assert_eq!(records.files.len(), FILES.len());
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&progress);
+    let callback = Arc::new(move |hashed, total| seen.lock().unwrap().push((hashed, total)));
+    let report = verify_local(dir.path(), &records, Some(callback))
+        .await
+        .unwrap();
+
+    let problems: Vec<(&Path, &VerifyStatus)> = report
+        .problems()
+        .map(|e| (e.path.as_path(), &e.status))
+        .collect();
+    assert_eq!(problems.len(), 1, "{problems:?}");
+    assert_eq!(problems[0].0, Path::new("docs/c.txt"));
+    assert!(matches!(problems[0].1, VerifyStatus::Corrupted { .. }));
+    assert_eq!((report.ok, report.corrupted, report.extra), (3, 1, 0));
+    assert!(!report.is_ok());
+
+    let progress = progress.lock().unwrap();
+    let total: u64 = FILES
+        .iter()
+        .map(|name| std::fs::metadata(dir.path().join(name)).unwrap().len())
+        .sum();
+    assert_eq!(progress.last(), Some(&(total, total)));
+    assert_eq!(report.bytes, total);
+}
+
+#[tokio::test]
+async fn test_verify_local_reports_missing_and_extra_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let _server = download_tree(dir.path()).await;
+
+    std::fs::remove_file(dir.path().join("a.txt")).unwrap();
+    std::fs::write(dir.path().join("docs/notes.txt"), "not downloaded").unwrap();
+    std::fs::write(dir.path().join("b.bin"), "truncated").unwrap();
+
+    let records = VerifyRecords::from_sidecars(dir.path()).await.unwrap();
+    let report = verify_local(dir.path(), &records, None).await.unwrap();
+
+    let problems: Vec<(PathBuf, &str)> = report
+        .problems()
+        .map(|e| (e.path.clone(), e.status.as_str()))
+        .collect();
+    assert_eq!(
+        problems,
+        [
+            (PathBuf::from("a.txt"), "MISSING"),
+            (PathBuf::from("b.bin"), "CORRUPTED"),
+            (PathBuf::from("docs/notes.txt"), "EXTRA"),
+        ]
+    );
+    assert!(matches!(report.entries[1].status, VerifyStatus::WrongSize { actual: 9, .. }));
+}
+
+#[tokio::test]
+async fn test_verify_local_with_checksum_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let _server = download_tree(dir.path()).await;
+
+    // sha256sum output for the tree, kept inside it
+    let sidecars = VerifyRecords::from_sidecars(dir.path()).await.unwrap();
+    let sums: String = sidecars
+        .files
+        .iter()
+        .map(|r| format!("{}  ./{}\n", r.checksum.hex(), r.path.display()))
+        .collect();
+    for name in FILES {
+        std::fs::remove_file(dir.path().join(format!("{name}.provenance.json"))).unwrap();
+    }
+    let sums_file = dir.path().join("SHA256SUMS");
+    std::fs::write(&sums_file, sums).unwrap();
+    std::fs::write(dir.path().join("b.bin"), "x".repeat(1500)).unwrap();
+
+    let records = VerifyRecords::from_checksum_file(&sums_file, dir.path())
+        .await
+        .unwrap();
+    let report = verify_local(dir.path(), &records, None).await.unwrap();
+
+    let problems: Vec<&Path> = report.problems().map(|e| e.path.as_path()).collect();
+    assert_eq!(problems, [Path::new("b.bin")]);
+    assert_eq!((report.ok, report.corrupted, report.extra), (3, 1, 0));
+}
+
+#[tokio::test]
+async fn test_verify_remote_compares_validators() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = download_tree(dir.path()).await;
+
+    // The record of a.txt says it was downloaded as an older version
+    let sidecar = dir.path().join("a.txt.provenance.json");
+    let text = std::fs::read_to_string(&sidecar).unwrap();
+    std::fs::write(&sidecar, text.replace("a.txt-v1", "a.txt-v0")).unwrap();
+
+    let records = VerifyRecords::from_sidecars(dir.path()).await.unwrap();
+    let downloader = Downloader::new(DownloadConfig::default()).unwrap();
+    let report = downloader.verify_remote(&records, 4).await;
+
+    assert_eq!((report.unchanged, report.changed, report.failed), (3, 1, 0));
+    let changed: Vec<&Path> = report
+        .entries
+        .iter()
+        .filter(|e| matches!(e.status, RemoteStatus::Changed { .. }))
+        .map(|e| e.path.as_path())
+        .collect();
+    assert_eq!(changed, [Path::new("a.txt")]);
+    assert!(!report.is_ok());
+    server.assert_no_failures();
+}